@@ -0,0 +1,328 @@
+//! Interaction with cargo: discovering benchmark targets and building them
+
+use anyhow::{bail, Context, Result};
+use goblin::{mach::Mach, Object};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Name of the symbol exported by all the benchmarks created with `tango_main!()` macro
+const TANGO_SYMBOL: &str = "tango_init";
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_root: PathBuf,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<Target>,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+}
+
+/// Messages emitted by `cargo build --message-format=json`. Only compiled artifacts are of interest.
+#[derive(Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message {
+    CompilerArtifact {
+        package_id: String,
+        target: Target,
+        executable: Option<PathBuf>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Benchmark target of a workspace package depending on `tango-bench`
+#[derive(Clone, Debug)]
+pub struct BenchTarget {
+    pub package: String,
+    pub name: String,
+    package_id: String,
+    package_dir: PathBuf,
+}
+
+impl BenchTarget {
+    /// Directory benchmarks are executed in (the same as `cargo bench` uses)
+    pub fn package_dir(&self) -> &Path {
+        &self.package_dir
+    }
+
+    /// Name of the target qualified with the package name (eg. `my-crate::my-bench`)
+    pub fn qualified_name(&self) -> String {
+        format!("{}::{}", self.package, self.name)
+    }
+}
+
+/// Compiled benchmark target
+#[derive(Clone, Debug)]
+pub struct BenchExecutable {
+    pub target: BenchTarget,
+    pub path: PathBuf,
+}
+
+pub struct Workspace {
+    pub root: PathBuf,
+    pub manifest_path: PathBuf,
+    pub target_dir: PathBuf,
+    benches: Vec<BenchTarget>,
+}
+
+impl Workspace {
+    /// Reads workspace metadata using `cargo metadata`
+    ///
+    /// Manifest in the current directory is used if no manifest path is given.
+    pub fn read(manifest_path: Option<&Path>) -> Result<Self> {
+        let mut cmd = cargo();
+        cmd.args(["metadata", "--format-version=1", "--no-deps"]);
+        if let Some(manifest_path) = manifest_path {
+            cmd.arg("--manifest-path").arg(manifest_path);
+        }
+        let output = cmd
+            .stderr(Stdio::inherit())
+            .output()
+            .context("Unable to run cargo metadata")?;
+        if !output.status.success() {
+            bail!("cargo metadata failed ({})", output.status);
+        }
+        let metadata: Metadata =
+            serde_json::from_slice(&output.stdout).context("Invalid cargo metadata output")?;
+
+        let benches = metadata
+            .packages
+            .iter()
+            .filter(|p| {
+                p.name == "tango-bench" || p.dependencies.iter().any(|d| d.name == "tango-bench")
+            })
+            .flat_map(|p| {
+                let package_dir = p.manifest_path.parent().unwrap_or(Path::new("."));
+                p.targets
+                    .iter()
+                    .filter(|t| t.kind.iter().any(|k| k == "bench"))
+                    .map(|t| BenchTarget {
+                        package: p.name.clone(),
+                        name: t.name.clone(),
+                        package_id: p.id.clone(),
+                        package_dir: package_dir.to_path_buf(),
+                    })
+            })
+            .collect();
+
+        let manifest_path = match manifest_path {
+            Some(path) => path.to_path_buf(),
+            None => metadata.workspace_root.join("Cargo.toml"),
+        };
+        Ok(Self {
+            root: metadata.workspace_root,
+            manifest_path,
+            target_dir: metadata.target_directory,
+            benches,
+        })
+    }
+
+    /// Selects benchmark targets by package and target names
+    ///
+    /// All the benchmark targets of the workspace are selected if no names are given. It is an error if any of
+    /// the requested targets doesn't exist.
+    pub fn select(&self, package: Option<&str>, names: &[String]) -> Result<Vec<BenchTarget>> {
+        let candidates = self
+            .benches
+            .iter()
+            .filter(|b| package.map(|p| b.package == p).unwrap_or(true))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            match package {
+                Some(package) => bail!(
+                    "Package `{}` has no benchmarks depending on tango-bench in workspace {}",
+                    package,
+                    self.root.display()
+                ),
+                None => bail!(
+                    "No benchmarks depending on tango-bench found in workspace {}",
+                    self.root.display()
+                ),
+            }
+        }
+
+        for name in names {
+            if !candidates.iter().any(|b| &b.name == name) {
+                let available = candidates
+                    .iter()
+                    .map(|b| b.name.as_str())
+                    .collect::<Vec<_>>();
+                bail!(
+                    "Benchmark target `{}` not found. Available targets: {}",
+                    name,
+                    available.join(", ")
+                );
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .filter(|b| names.is_empty() || names.contains(&b.name))
+            .cloned()
+            .collect())
+    }
+
+    /// Builds given benchmark targets with `cargo bench --no-run`
+    ///
+    /// Targets which are not tango benchmarks (eg. criterion benchmarks in the same package) are skipped,
+    /// unless `explicit` is set in which case they are reported as an error.
+    pub fn build(
+        &self,
+        benches: &[BenchTarget],
+        target_dir: Option<&Path>,
+        explicit: bool,
+    ) -> Result<Vec<BenchExecutable>> {
+        let result = self.build_targets(benches, target_dir, explicit)?;
+        if result.is_empty() {
+            bail!("No tango benchmarks found among selected targets");
+        }
+        Ok(result)
+    }
+
+    /// Builds given benchmark targets the same way as [`Self::build()`], but a target failing to build doesn't
+    /// prevent building the others
+    ///
+    /// Targets are built one by one only if building all of them at once fails. Returns the executables along
+    /// with the targets failed to build.
+    pub fn build_each(
+        &self,
+        benches: &[BenchTarget],
+        target_dir: Option<&Path>,
+        explicit: bool,
+    ) -> (Vec<BenchExecutable>, Vec<(BenchTarget, anyhow::Error)>) {
+        match self.build_targets(benches, target_dir, explicit) {
+            Ok(executables) => return (executables, vec![]),
+            Err(e) if benches.len() == 1 => return (vec![], vec![(benches[0].clone(), e)]),
+            Err(_) => eprintln!("Building benchmarks one by one to find the failing ones"),
+        }
+        let mut executables = vec![];
+        let mut failed = vec![];
+        for bench in benches {
+            match self.build_targets(std::slice::from_ref(bench), target_dir, explicit) {
+                Ok(built) => executables.extend(built),
+                Err(e) => failed.push((bench.clone(), e)),
+            }
+        }
+        (executables, failed)
+    }
+
+    fn build_targets(
+        &self,
+        benches: &[BenchTarget],
+        target_dir: Option<&Path>,
+        explicit: bool,
+    ) -> Result<Vec<BenchExecutable>> {
+        let mut cmd = cargo();
+        cmd.args([
+            "bench",
+            "--no-run",
+            "--message-format=json-render-diagnostics",
+        ])
+        .arg("--manifest-path")
+        .arg(&self.manifest_path);
+        if let Some(target_dir) = target_dir {
+            cmd.arg("--target-dir").arg(target_dir);
+        }
+        for bench in benches {
+            cmd.args(["--package", &bench.package, "--bench", &bench.name]);
+        }
+
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context("Unable to run cargo bench")?;
+
+        let mut executables = HashMap::new();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        for line in BufReader::new(stdout).lines() {
+            let line = line?;
+            if let Ok(Message::CompilerArtifact {
+                package_id,
+                target,
+                executable: Some(executable),
+            }) = serde_json::from_str(&line)
+            {
+                executables.insert((package_id, target.name), executable);
+            }
+        }
+
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("Unable to build benchmarks (cargo bench {})", status);
+        }
+
+        let mut result = vec![];
+        for bench in benches {
+            let key = (bench.package_id.clone(), bench.name.clone());
+            let Some(path) = executables.remove(&key) else {
+                bail!(
+                    "cargo didn't report executable for benchmark `{}`",
+                    bench.name
+                );
+            };
+            if is_tango_benchmark(&path)? {
+                result.push(BenchExecutable {
+                    target: bench.clone(),
+                    path,
+                });
+            } else if explicit {
+                bail!(
+                    "Benchmark target `{}` is not a tango benchmark (tango_main!() is not used)",
+                    bench.name
+                );
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn cargo() -> Command {
+    Command::new(std::env::var_os("CARGO").unwrap_or("cargo".into()))
+}
+
+/// Checks if the executable exports the symbol tango uses to load benchmarks from the baseline executable
+fn is_tango_benchmark(path: &Path) -> Result<bool> {
+    let binary = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let object = Object::parse(&binary)
+        .with_context(|| format!("Unable to parse executable {}", path.display()))?;
+    Ok(match object {
+        Object::Elf(elf) => elf
+            .dynsyms
+            .iter()
+            .filter(|s| !s.is_import())
+            .any(|s| elf.dynstrtab.get_at(s.st_name) == Some(TANGO_SYMBOL)),
+        Object::Mach(Mach::Binary(macho)) => macho
+            .exports()?
+            .iter()
+            .any(|e| e.name.strip_prefix('_') == Some(TANGO_SYMBOL)),
+        Object::PE(pe) => pe.exports.iter().any(|e| e.name == Some(TANGO_SYMBOL)),
+        // Not able to tell for sure, running executable will fail later if it's not a tango benchmark
+        _ => true,
+    })
+}
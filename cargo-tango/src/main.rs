@@ -0,0 +1,463 @@
+//! Cargo subcommand for running common Tango workflows
+//!
+//! - `cargo tango list` – lists tests of all the tango benchmarks in the workspace;
+//! - `cargo tango compare <git-ref-or-path>` – compares current benchmarks against the ones built from a given
+//!   git reference or against already built executables;
+//! - `cargo tango baseline save <name>` and `cargo tango baseline load <name>` – saves current benchmarks as a
+//!   named baseline and compares against it later.
+//!
+//! Arguments after `--` are passed to the benchmark executables as is (eg. `cargo tango compare main -- -t 1`).
+//!
+//! When several benchmarks are compared, test names are qualified with the package and the target name and the
+//! results of all the benchmarks are merged into a single suite summary.
+
+use anyhow::{bail, Context, Result};
+use cargo::{BenchExecutable, BenchTarget, Workspace};
+use clap::{Args, Parser, Subcommand};
+use std::{
+    env::consts::EXE_SUFFIX,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+use suite::{read_results, Suite};
+
+mod cargo;
+mod suite;
+
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cli {
+    /// Runs tango benchmarks
+    Tango(Opts),
+}
+
+#[derive(Args)]
+#[command(version, about)]
+struct Opts {
+    #[command(flatten)]
+    targets: TargetFlags,
+
+    #[command(subcommand)]
+    command: TangoCommand,
+}
+
+#[derive(Args)]
+struct TargetFlags {
+    /// Path to Cargo.toml
+    #[arg(long, global = true)]
+    manifest_path: Option<PathBuf>,
+
+    /// Package with benchmarks
+    #[arg(short, long, global = true)]
+    package: Option<String>,
+
+    /// Name of the benchmark target (all tango benchmarks are used if not given)
+    #[arg(long = "bench", global = true)]
+    benches: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum TangoCommand {
+    /// Lists tests of the benchmarks
+    List,
+
+    /// Compares current benchmarks against a git reference or already built benchmarks
+    Compare {
+        /// Git reference (branch, tag or commit) or path to the benchmark executable or baseline directory
+        against: String,
+
+        /// Arguments passed to the `compare` command of the benchmarks
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Saves and loads named baselines
+    #[command(subcommand)]
+    Baseline(BaselineCommand),
+}
+
+#[derive(Subcommand)]
+enum BaselineCommand {
+    /// Builds current benchmarks and saves them as a named baseline
+    Save { name: String },
+
+    /// Compares current benchmarks against a named baseline
+    Load {
+        name: String,
+
+        /// Arguments passed to the `compare` command of the benchmarks
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+}
+
+fn main() -> ExitCode {
+    let Cli::Tango(opts) = Cli::parse();
+    match run(opts) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(opts: Opts) -> Result<ExitCode> {
+    let targets = opts.targets;
+    let workspace = Workspace::read(targets.manifest_path.as_deref())?;
+    let benches = workspace.select(targets.package.as_deref(), &targets.benches)?;
+    let explicit = !targets.benches.is_empty();
+
+    match opts.command {
+        TangoCommand::List => {
+            let executables = workspace.build(&benches, None, explicit)?;
+            run_each(&executables, |cmd, _| {
+                cmd.arg("list");
+                Ok(())
+            })
+        }
+        TangoCommand::Compare { against, args } => {
+            let path = Path::new(&against);
+            let commit = if path.exists() {
+                None
+            } else {
+                Some(resolve_git_ref(&workspace.root, &against)?)
+            };
+            let (executables, failed) = workspace.build_each(&benches, None, explicit);
+            let baselines = match commit {
+                None => Baselines::Path(path.to_path_buf()),
+                Some(commit) => build_git_ref(&workspace, &commit, &executables)?,
+            };
+            compare(&workspace, &executables, failed, &baselines, &args)
+        }
+        TangoCommand::Baseline(BaselineCommand::Save { name }) => {
+            let executables = workspace.build(&benches, None, explicit)?;
+            let dir = baseline_dir(&workspace, &name)?;
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Unable to create directory {}", dir.display()))?;
+            for exe in &executables {
+                let dest = dir.join(executable_name(&exe.target));
+                fs::copy(&exe.path, &dest)
+                    .with_context(|| format!("Unable to copy executable to {}", dest.display()))?;
+                eprintln!("Saved {} to {}", exe.target.name, dest.display());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        TangoCommand::Baseline(BaselineCommand::Load { name, args }) => {
+            let dir = baseline_dir(&workspace, &name)?;
+            if !dir.is_dir() {
+                bail!(
+                    "Baseline `{}` not found (use `cargo tango baseline save {}` to create it)",
+                    name,
+                    name
+                );
+            }
+            let (executables, failed) = workspace.build_each(&benches, None, explicit);
+            compare(
+                &workspace,
+                &executables,
+                failed,
+                &Baselines::Path(dir),
+                &args,
+            )
+        }
+    }
+}
+
+/// Location of the benchmarks to compare against
+enum Baselines {
+    /// Executable of a single benchmark or a directory with executables named after benchmark targets
+    Path(PathBuf),
+
+    /// Benchmarks built from a git reference
+    Built(Vec<BenchExecutable>),
+}
+
+impl Baselines {
+    fn find(&self, target: &BenchTarget, total: usize) -> Result<PathBuf> {
+        match self {
+            Baselines::Path(path) if path.is_dir() => {
+                let exe = path.join(executable_name(target));
+                if !exe.is_file() {
+                    bail!(
+                        "Benchmark `{}` not found in {}",
+                        target.name,
+                        path.display()
+                    );
+                }
+                Ok(exe)
+            }
+            Baselines::Path(path) if total == 1 => Ok(path.clone()),
+            Baselines::Path(path) => bail!(
+                "{} is a single executable, but {} benchmarks are selected (use --bench to select one)",
+                path.display(),
+                total
+            ),
+            Baselines::Built(executables) => executables
+                .iter()
+                .find(|e| e.target.package == target.package && e.target.name == target.name)
+                .map(|e| e.path.clone())
+                .with_context(|| format!("Benchmark `{}` not found in baseline", target.name)),
+        }
+    }
+}
+
+/// Runs `compare` command of each benchmark executable against the corresponding baseline
+///
+/// If several benchmarks are selected, test names are qualified with the package and the target name
+/// (`crate::bench::test`) and the results of all the benchmarks are merged into a single suite summary. A benchmark
+/// failing to build or missing in the baseline is reported, but doesn't prevent running the others.
+fn compare(
+    workspace: &Workspace,
+    executables: &[BenchExecutable],
+    mut failed: Vec<(BenchTarget, anyhow::Error)>,
+    baselines: &Baselines,
+    args: &[String],
+) -> Result<ExitCode> {
+    let total = executables.len() + failed.len();
+    if total == 0 {
+        bail!("No tango benchmarks found among selected targets");
+    }
+    if total == 1 {
+        if let Some((_, e)) = failed.pop() {
+            return Err(e);
+        }
+        return run_each(executables, |cmd, exe| {
+            let baseline = find_baseline(baselines, exe, total)?;
+            cmd.arg("compare").arg(baseline).args(args);
+            Ok(())
+        });
+    }
+
+    let results_dir = workspace.target_dir.join("tango/results");
+    fs::create_dir_all(&results_dir)
+        .with_context(|| format!("Unable to create directory {}", results_dir.display()))?;
+
+    let mut suite = Suite::default();
+    for (target, e) in failed {
+        eprintln!("[ERROR] {}: {:#}", target.qualified_name(), e);
+        suite.failed(&target.qualified_name(), "build failed".into());
+    }
+    for exe in executables {
+        let name = exe.target.qualified_name();
+        let baseline = match find_baseline(baselines, exe, total) {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                eprintln!("[ERROR] {}: {:#}", name, e);
+                suite.failed(&name, "no baseline".into());
+                continue;
+            }
+        };
+        let results = results_dir.join(format!("{}-{}.jsonl", exe.target.package, exe.target.name));
+        if results.exists() {
+            fs::remove_file(&results)
+                .with_context(|| format!("Unable to remove {}", results.display()))?;
+        }
+
+        eprintln!("Running {}", name);
+        let mut cmd = Command::new(&exe.path);
+        cmd.current_dir(exe.target.package_dir())
+            .arg("compare")
+            .arg(baseline)
+            .arg("--ndjson")
+            .arg(&results)
+            .arg("--name-prefix")
+            .arg(format!("{}::", name))
+            // Filter might match the tests of some of the benchmarks only
+            .arg("--allow-empty")
+            .args(args);
+        let failure = match cmd.status() {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(status.to_string()),
+            Err(e) => Some(format!("unable to run: {}", e)),
+        };
+        let results = match read_results(&results) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("[ERROR] {}: {:#}", name, e);
+                suite.failed(&name, "invalid results".into());
+                continue;
+            }
+        };
+        suite.add(&name, results, failure);
+    }
+
+    println!();
+    for line in suite.render() {
+        println!("{}", line);
+    }
+    if suite.tests() == 0 {
+        eprintln!("[ERROR] No tests were run in any of the benchmarks");
+    }
+    Ok(if suite.is_success() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Path of the baseline of a benchmark
+///
+/// Benchmarks are run from the package directory, so the path is made absolute.
+fn find_baseline(baselines: &Baselines, exe: &BenchExecutable, total: usize) -> Result<PathBuf> {
+    let baseline = baselines.find(&exe.target, total)?;
+    fs::canonicalize(&baseline)
+        .with_context(|| format!("Unable to resolve path {}", baseline.display()))
+}
+
+/// Runs each benchmark executable from its package directory (the same way `cargo bench` does)
+///
+/// All benchmarks are executed even if some of them are failing.
+fn run_each(
+    executables: &[BenchExecutable],
+    mut configure: impl FnMut(&mut Command, &BenchExecutable) -> Result<()>,
+) -> Result<ExitCode> {
+    let mut exit_code = ExitCode::SUCCESS;
+    for exe in executables {
+        if executables.len() > 1 {
+            eprintln!("Running {} ({})", exe.target.name, exe.target.package);
+        }
+        let mut cmd = Command::new(&exe.path);
+        cmd.current_dir(exe.target.package_dir());
+        configure(&mut cmd, exe)?;
+        let status = cmd
+            .status()
+            .with_context(|| format!("Unable to run {}", exe.path.display()))?;
+        if !status.success() {
+            eprintln!("Benchmark {} failed ({})", exe.target.name, status);
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+    Ok(exit_code)
+}
+
+fn baseline_dir(workspace: &Workspace, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid baseline name: {}", name);
+    }
+    Ok(workspace.target_dir.join("tango/baselines").join(name))
+}
+
+fn executable_name(target: &BenchTarget) -> String {
+    format!("{}{}", target.name, EXE_SUFFIX)
+}
+
+fn resolve_git_ref(root: &Path, reference: &str) -> Result<String> {
+    let output = Command::new("git")
+        .current_dir(root)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{}^{{commit}}", reference))
+        .output()
+        .context("Unable to run git")?;
+    if !output.status.success() {
+        bail!(
+            "`{}` is neither an existing path nor a git reference",
+            reference
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Builds the same benchmarks from a given commit in a temporary git worktree
+///
+/// Each commit is built in its own target directory, so subsequent comparisons against the same commit are fast.
+fn build_git_ref(
+    workspace: &Workspace,
+    commit: &str,
+    executables: &[BenchExecutable],
+) -> Result<Baselines> {
+    let tango_dir = workspace.target_dir.join("tango");
+    let worktree = Worktree::add(&workspace.root, &tango_dir.join("worktree"), commit)?;
+
+    let manifest_path = workspace
+        .manifest_path
+        .strip_prefix(&workspace.root)
+        .map(|p| worktree.path.join(p))
+        .unwrap_or_else(|_| worktree.path.join("Cargo.toml"));
+    let other = Workspace::read(Some(&manifest_path))?;
+
+    // Benchmarks missing at the commit or failing to build are reported when looked up in the baseline
+    let mut benches = vec![];
+    for exe in executables {
+        let target = &exe.target;
+        let names = [target.name.clone()];
+        match other.select(Some(&target.package), &names) {
+            Ok(bench) => benches.extend(bench),
+            Err(e) if executables.len() == 1 => {
+                return Err(e).with_context(|| {
+                    format!("Benchmark `{}` not available at {}", target.name, commit)
+                })
+            }
+            Err(_) => eprintln!(
+                "[WARN] Benchmark `{}` not available at {}",
+                target.qualified_name(),
+                commit
+            ),
+        }
+    }
+
+    let target_dir = tango_dir.join("commits").join(commit);
+    eprintln!("Building benchmarks at {}", commit);
+    let (built, failed) = other.build_each(&benches, Some(&target_dir), true);
+    for (target, e) in failed {
+        if executables.len() == 1 {
+            return Err(e);
+        }
+        eprintln!(
+            "[WARN] Unable to build `{}` at {}: {:#}",
+            target.qualified_name(),
+            commit,
+            e
+        );
+    }
+    Ok(Baselines::Built(built))
+}
+
+/// Git worktree removed on drop
+struct Worktree {
+    root: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    fn add(root: &Path, path: &Path, commit: &str) -> Result<Self> {
+        // Worktree might be left from the previous run if it was interrupted
+        if path.exists() {
+            git(root, |cmd| {
+                cmd.args(["worktree", "remove", "--force"]).arg(path)
+            })?;
+        }
+        git(root, |cmd| {
+            cmd.args(["worktree", "add", "--detach"])
+                .arg(path)
+                .arg(commit)
+        })
+        .context("Unable to create git worktree")?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path = &self.path;
+        if let Err(e) = git(&self.root, |cmd| {
+            cmd.args(["worktree", "remove", "--force"]).arg(path)
+        }) {
+            eprintln!("[WARN] Unable to remove git worktree: {:#}", e);
+        }
+    }
+}
+
+fn git(dir: &Path, configure: impl FnOnce(&mut Command) -> &mut Command) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir);
+    let status = configure(&mut cmd).status().context("Unable to run git")?;
+    if !status.success() {
+        bail!("git failed ({})", status);
+    }
+    Ok(())
+}
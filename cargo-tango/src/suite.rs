@@ -0,0 +1,226 @@
+//! Merging the results of several benchmarks into a single suite summary
+//!
+//! Each benchmark is run with `--ndjson` and writes its results as JSON lines. Only the fields the summary is
+//! built from are read, so the results of newer versions of tango are accepted as well.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{fs, io, path::Path};
+
+/// Result of a single test as written by the benchmark
+#[derive(Debug, Deserialize)]
+pub struct TestResult {
+    pub name: String,
+    pub weight: f64,
+    pub significance: Significance,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Significance {
+    /// Difference between candidate and baseline in percents (positive if candidate is slower)
+    pub pct: f64,
+    pub significant: bool,
+}
+
+/// Reads the results written by a benchmark (no results if the file wasn't created)
+pub fn read_results(path: &Path) -> Result<Vec<TestResult>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid results in {}", path.display()))
+}
+
+/// Outcome of a single benchmark of the suite
+struct Benchmark {
+    name: String,
+    tests: usize,
+
+    /// Reason the benchmark failed (eg. it wasn't built or some of the tests regressed)
+    failure: Option<String>,
+}
+
+/// Results of all the benchmarks of a suite
+#[derive(Default)]
+pub struct Suite {
+    benchmarks: Vec<Benchmark>,
+    results: Vec<TestResult>,
+}
+
+impl Suite {
+    /// Adds the results of a benchmark. Results are kept even if the benchmark failed
+    pub fn add(&mut self, name: &str, results: Vec<TestResult>, failure: Option<String>) {
+        self.benchmarks.push(Benchmark {
+            name: name.to_string(),
+            tests: results.len(),
+            failure,
+        });
+        self.results.extend(results);
+    }
+
+    /// Adds a benchmark which wasn't run at all
+    pub fn failed(&mut self, name: &str, reason: String) {
+        self.add(name, vec![], Some(reason));
+    }
+
+    pub fn tests(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Suite succeeds if none of the benchmarks failed and at least one test was run
+    pub fn is_success(&self) -> bool {
+        self.tests() > 0 && self.benchmarks.iter().all(|b| b.failure.is_none())
+    }
+
+    /// Weighted geometric mean of candidate/baseline ratios of all the tests expressed as a change in percents
+    pub fn geomean_pct(&self) -> f64 {
+        let (total_weight, log_ratios) =
+            self.ratios().fold((0., 0.), |(w, l), (weight, ratio, _)| {
+                (w + weight, l + weight * ratio.ln())
+            });
+        if total_weight == 0. {
+            return 0.;
+        }
+        ((log_ratios / total_weight).exp() - 1.) * 100.
+    }
+
+    /// Weighted mean of the significant regressions of all the tests in percents
+    pub fn weighted_regression(&self) -> f64 {
+        let (total_weight, regressions) =
+            self.ratios().fold((0., 0.), |(w, r), (weight, _, result)| {
+                let estimate = &result.significance;
+                let regression = if estimate.significant && estimate.pct > 0. {
+                    weight * estimate.pct
+                } else {
+                    0.
+                };
+                (w + weight, r + regression)
+            });
+        if total_weight == 0. {
+            return 0.;
+        }
+        regressions / total_weight
+    }
+
+    /// Weights and candidate/baseline ratios of the tests the ratio is defined for
+    fn ratios(&self) -> impl Iterator<Item = (f64, f64, &TestResult)> {
+        self.results
+            .iter()
+            .map(|r| (r.weight, 1. + r.significance.pct / 100., r))
+            .filter(|(_, ratio, _)| *ratio > 0. && ratio.is_finite())
+    }
+
+    /// Lines of the suite summary: a status of each benchmark followed by the aggregate of all the tests
+    pub fn render(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Suite  {} benchmarks, {} tests",
+            self.benchmarks.len(),
+            self.tests()
+        )];
+        for benchmark in &self.benchmarks {
+            lines.push(match &benchmark.failure {
+                None => format!("  ok      {} ({} tests)", benchmark.name, benchmark.tests),
+                Some(failure) => format!("  FAILED  {} ({})", benchmark.name, failure),
+            });
+        }
+        for result in &self.results {
+            let estimate = &result.significance;
+            if estimate.significant && estimate.pct > 0. {
+                lines.push(format!(
+                    "  regressed  {} ({:+.2}%)",
+                    result.name, estimate.pct
+                ));
+            }
+        }
+        if self.tests() > 0 {
+            lines.push(format!(
+                "Geomean  {:+.2}% over {} tests (weighted regression: {:.2}%)",
+                self.geomean_pct(),
+                self.tests(),
+                self.weighted_regression()
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, pct: f64, significant: bool) -> TestResult {
+        TestResult {
+            name: name.to_string(),
+            weight: 1.,
+            significance: Significance { pct, significant },
+        }
+    }
+
+    #[test]
+    fn check_suite_summary() {
+        let mut suite = Suite::default();
+        suite.add(
+            "alpha::bench",
+            vec![
+                result("alpha::bench::a", 10., true),
+                result("alpha::bench::b", -10., true),
+            ],
+            None,
+        );
+        suite.add(
+            "beta::bench",
+            vec![result("beta::bench::c", 4., false)],
+            None,
+        );
+        assert!(suite.is_success());
+        assert_eq!(suite.tests(), 3);
+
+        let geomean = ((1.1f64.ln() + 0.9f64.ln() + 1.04f64.ln()) / 3.).exp() - 1.;
+        assert!((suite.geomean_pct() - geomean * 100.).abs() < 1e-9);
+        // Only the significant regression counts
+        assert!((suite.weighted_regression() - 10. / 3.).abs() < 1e-9);
+
+        suite.failed("gamma::bench", "build failed".into());
+        assert!(!suite.is_success());
+        assert_eq!(
+            suite.render()[..4],
+            [
+                "Suite  3 benchmarks, 3 tests",
+                "  ok      alpha::bench (2 tests)",
+                "  ok      beta::bench (1 tests)",
+                "  FAILED  gamma::bench (build failed)",
+            ]
+        );
+        assert_eq!(suite.render()[4], "  regressed  alpha::bench::a (+10.00%)");
+    }
+
+    #[test]
+    fn check_empty_suite_fails() {
+        let mut suite = Suite::default();
+        suite.add("alpha::bench", vec![], None);
+        assert!(!suite.is_success());
+        assert_eq!(suite.geomean_pct(), 0.);
+    }
+
+    #[test]
+    fn check_read_results() {
+        let path = std::env::temp_dir().join(format!("tango-results-{}.jsonl", std::process::id()));
+        assert!(read_results(&path).unwrap().is_empty());
+
+        let line = r#"{"seq":1,"name":"a::b::sort","weight":2.0,"significance":{"pct":-1.5,"significant":true,"verdict":"Different"},"outliers":0}"#;
+        fs::write(&path, format!("{}\n{}\n", line, line)).unwrap();
+        let results = read_results(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].name, "a::b::sort");
+        assert_eq!(results[0].weight, 2.);
+        assert_eq!(results[0].significance.pct, -1.5);
+    }
+}
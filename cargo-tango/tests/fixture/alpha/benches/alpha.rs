@@ -0,0 +1,12 @@
+use std::hint::black_box;
+use tango_bench::{benchmark_fn, tango_benchmarks, tango_main, IntoBenchmarks};
+
+fn benchmarks() -> impl IntoBenchmarks {
+    [
+        benchmark_fn("sum", || (0..black_box(100u64)).sum::<u64>()),
+        benchmark_fn("product", || (1..black_box(20u64)).product::<u64>()),
+    ]
+}
+
+tango_benchmarks!(benchmarks());
+tango_main!();
@@ -0,0 +1,9 @@
+use std::hint::black_box;
+use tango_bench::{benchmark_fn, tango_benchmarks, tango_main, IntoBenchmarks};
+
+fn benchmarks() -> impl IntoBenchmarks {
+    [benchmark_fn("max", || (0..black_box(100u64)).max())]
+}
+
+tango_benchmarks!(benchmarks());
+tango_main!();
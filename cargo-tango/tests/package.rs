@@ -0,0 +1,112 @@
+//! Drives `cargo tango` against a fixture package with a tango benchmark and a benchmark not using tango
+//!
+//! Benchmarks are built in a separate target directory, so nested cargo invocations are not blocked by the
+//! build directory lock held by `cargo test`.
+
+use std::{
+    env::consts::EXE_SUFFIX,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn cargo_tango(args: &[&str]) -> Output {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/package/Cargo.toml");
+    Command::new(env!("CARGO_BIN_EXE_cargo-tango"))
+        .arg("tango")
+        .arg("--manifest-path")
+        .arg(manifest)
+        .args(args)
+        .env("CARGO_TARGET_DIR", target_dir())
+        .output()
+        .unwrap()
+}
+
+fn target_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("package")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn check_list() {
+    let output = cargo_tango(&["list", "--bench", "sample"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let tests = stdout(&output);
+    assert!(tests.lines().any(|l| l == "factorial"), "{}", tests);
+    assert!(
+        tests.lines().any(|l| l.starts_with("str_length/")),
+        "{}",
+        tests
+    );
+}
+
+#[test]
+fn check_missing_bench_target() {
+    let output = cargo_tango(&["list", "--bench", "no-such-bench"]);
+    assert!(!output.status.success());
+
+    let error = stderr(&output);
+    assert!(error.contains("`no-such-bench` not found"), "{}", error);
+    assert!(error.contains("sample"), "{}", error);
+}
+
+#[test]
+fn check_non_tango_bench_is_rejected() {
+    let output = cargo_tango(&["list", "--bench", "plain"]);
+    assert!(!output.status.success());
+
+    let error = stderr(&output);
+    assert!(error.contains("not a tango benchmark"), "{}", error);
+}
+
+#[test]
+fn check_missing_baseline() {
+    let output = cargo_tango(&["baseline", "load", "no-such-baseline"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Baseline `no-such-baseline` not found"));
+}
+
+#[test]
+fn check_baseline_save_and_compare() {
+    let compare_args = ["--", "-t", "0.01", "-f", "factorial"];
+
+    let output = cargo_tango(&["baseline", "save", "it", "--bench", "sample"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let load = [
+        &["baseline", "load", "it", "--bench", "sample"],
+        &compare_args[..],
+    ]
+    .concat();
+    let output = cargo_tango(&load);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("factorial"), "{}", stdout(&output));
+
+    // Comparing against the executable directly
+    let exe = target_dir().join(format!("tango/baselines/it/sample{}", EXE_SUFFIX));
+    let exe = exe.to_str().unwrap();
+    let compare = [&["compare", exe, "--bench", "sample"], &compare_args[..]].concat();
+    let output = cargo_tango(&compare);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("factorial"), "{}", stdout(&output));
+}
+
+#[test]
+fn check_unknown_git_ref() {
+    let output = cargo_tango(&["compare", "no-such-ref-or-path", "--bench", "sample"]);
+    assert!(!output.status.success());
+
+    let error = stderr(&output);
+    assert!(
+        error.contains("neither an existing path nor a git reference"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,5 @@
+//! Benchmark with its own harness, which `cargo tango` should refuse to run
+
+fn main() {
+    println!("not a tango benchmark");
+}
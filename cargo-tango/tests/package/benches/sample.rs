@@ -0,0 +1,17 @@
+use std::hint::black_box;
+use tango_bench::{benchmark_fn, tango_benchmarks, tango_main, IntoBenchmarks};
+
+fn factorial(n: u64) -> u64 {
+    (1..=n).product()
+}
+
+fn benchmarks() -> impl IntoBenchmarks {
+    [
+        benchmark_fn("factorial", || factorial(black_box(20))),
+        benchmark_fn("str_length/short", || black_box("tango").len()),
+        benchmark_fn("str_length/long", || black_box("tango ".repeat(100)).len()),
+    ]
+}
+
+tango_benchmarks!(benchmarks());
+tango_main!();
@@ -0,0 +1,124 @@
+//! Drives `cargo tango` against a fixture workspace with tango benchmarks in two packages
+//!
+//! Benchmarks are built in a separate target directory, so nested cargo invocations are not blocked by the
+//! build directory lock held by `cargo test`.
+
+use std::{
+    env::consts::EXE_SUFFIX,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn cargo_tango(args: &[&str]) -> Output {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixture/Cargo.toml");
+    Command::new(env!("CARGO_BIN_EXE_cargo-tango"))
+        .arg("tango")
+        .arg("--manifest-path")
+        .arg(manifest)
+        .args(args)
+        .env("CARGO_TARGET_DIR", target_dir())
+        .output()
+        .unwrap()
+}
+
+fn target_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("fixture")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const COMPARE_ARGS: [&str; 3] = ["--", "-t", "0.01"];
+
+#[test]
+fn check_merged_report() {
+    let output = cargo_tango(&["baseline", "save", "merged"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = cargo_tango(&[&["baseline", "load", "merged"], &COMPARE_ARGS[..]].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let report = stdout(&output);
+    for name in [
+        "alpha::alpha::sum",
+        "alpha::alpha::product",
+        "beta::beta::max",
+    ] {
+        assert!(
+            report.contains(name),
+            "{} is not reported\n{}",
+            name,
+            report
+        );
+    }
+    assert!(
+        report.contains("Suite  2 benchmarks, 3 tests"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("  ok      alpha::alpha (2 tests)"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("  ok      beta::beta (1 tests)"),
+        "{}",
+        report
+    );
+    assert!(report.contains("Geomean"), "{}", report);
+}
+
+#[test]
+fn check_missing_counterpart_is_reported() {
+    let output = cargo_tango(&["baseline", "save", "partial"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // Baseline directory with the executable of one of the benchmarks only
+    let dir = target_dir().join("tango/baselines/partial");
+    fs::remove_file(dir.join(format!("beta{}", EXE_SUFFIX))).unwrap();
+
+    let output = cargo_tango(&[&["baseline", "load", "partial"], &COMPARE_ARGS[..]].concat());
+    assert!(!output.status.success());
+
+    let report = stdout(&output);
+    assert!(report.contains("alpha::alpha::sum"), "{}", report);
+    assert!(
+        report.contains("  ok      alpha::alpha (2 tests)"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("  FAILED  beta::beta (no baseline)"),
+        "{}",
+        report
+    );
+
+    let error = stderr(&output);
+    assert!(error.contains("`beta` not found"), "{}", error);
+}
+
+#[test]
+fn check_single_benchmark_is_not_qualified() {
+    let output = cargo_tango(&["baseline", "save", "single", "--bench", "beta"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let load = [
+        &["baseline", "load", "single", "--bench", "beta"],
+        &COMPARE_ARGS[..],
+    ]
+    .concat();
+    let output = cargo_tango(&load);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let report = stdout(&output);
+    assert!(report.contains("max"), "{}", report);
+    assert!(!report.contains("beta::beta::max"), "{}", report);
+    assert!(!report.contains("Suite"), "{}", report);
+}
@@ -0,0 +1,29 @@
+use crate::test_funcs::{factorial, sort_unstable, sum};
+use tango_bench::{benchmark, benchmark_fn, generators::RandomVec, tango_main, IntoBenchmarks};
+
+mod test_funcs;
+
+/// Benchmarks without input are registered under the name of the function
+#[benchmark]
+fn sum_4950() -> usize {
+    sum(4950)
+}
+
+#[benchmark(name = "factorial")]
+fn factorial_495() -> usize {
+    factorial(495)
+}
+
+/// Functions of a haystack and a needle are measured on the inputs of the generator
+#[benchmark(generator = RandomVec::<u64>::new(1_000), name = "sort")]
+fn sort_vec(haystack: &Vec<u64>, needle: &()) -> u64 {
+    sort_unstable(haystack, needle)
+}
+
+/// Functions returning `impl IntoBenchmarks` register all the benchmarks they build
+#[benchmark]
+fn nop_benchmarks() -> impl IntoBenchmarks {
+    [benchmark_fn("nop", || 42)]
+}
+
+tango_main!();
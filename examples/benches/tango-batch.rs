@@ -0,0 +1,76 @@
+//! Compares single lookups with a bulk lookup processing a batch of keys per call
+//!
+//! Both functions are reported per key. Functions processing batches of different sizes are only compared when
+//! [`MeasurementSettings::mixed_batches`] is set (`--mixed-batches` in `compare` mode).
+//!
+//! ```console
+//! $ cargo bench --bench=tango-batch -- -t 1
+//! ```
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::{collections::HashMap, process::ExitCode};
+use tango_bench::{
+    cli, BenchmarkMatrix, Generator, IntoBenchmarks, MeasureTarget, MeasurementSettings,
+    PairedTargets,
+};
+
+/// Number of keys looked up by each call of the bulk lookup
+const BATCH: usize = 32;
+
+/// Map of a given size and random keys present in it
+struct RandomMap {
+    size: u64,
+    rng: SmallRng,
+}
+
+impl Generator for RandomMap {
+    type Haystack = HashMap<u64, u64>;
+    type Needle = u64;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        (0..self.size).map(|k| (k, k * 2)).collect()
+    }
+
+    fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+        self.rng.gen_range(0..self.size)
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+}
+
+fn get(map: &HashMap<u64, u64>, key: &u64) -> Option<u64> {
+    map.get(key).copied()
+}
+
+fn get_many(map: &HashMap<u64, u64>, keys: &[u64]) -> Vec<Option<u64>> {
+    keys.iter().map(|k| map.get(k).copied()).collect()
+}
+
+fn lookup(generator: RandomMap, batch: bool) -> Box<dyn MeasureTarget> {
+    let matrix = BenchmarkMatrix::new(generator);
+    let mut benchmarks = if batch {
+        matrix.add_batch_function("get_many", BATCH, get_many)
+    } else {
+        matrix.add_function("get", get)
+    }
+    .into_benchmarks();
+    benchmarks.remove(0)
+}
+
+fn main() -> cli::Result<ExitCode> {
+    let map = || RandomMap {
+        size: 10_000,
+        rng: SmallRng::seed_from_u64(0),
+    };
+    let pairs = vec![PairedTargets::new(
+        lookup(map(), false),
+        lookup(map(), true),
+    )];
+    let settings = MeasurementSettings {
+        mixed_batches: true,
+        ..Default::default()
+    };
+    cli::run_pairs(settings, pairs)
+}
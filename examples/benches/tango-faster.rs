@@ -2,10 +2,10 @@
 
 use crate::test_funcs::{factorial, sum};
 use tango_bench::{
-    benchmark_fn, generators::RandomVec, tango_benchmarks, tango_main, BenchmarkMatrix,
-    IntoBenchmarks,
+    benchmark_fn, benchmark_fn_with_setup, generators::RandomVec, tango_benchmarks, tango_main,
+    BenchmarkMatrix, IntoBenchmarks, MeasureTargetExt,
 };
-use test_funcs::{sort_unstable, str_count, str_take, RandomSubstring};
+use test_funcs::{popcount_table, sort_unstable, str_count, str_take, LazyTable, RandomSubstring};
 
 mod test_funcs;
 
@@ -24,8 +24,27 @@ fn num_benchmarks() -> impl IntoBenchmarks {
 
 fn vec_benchmarks() -> impl IntoBenchmarks {
     BenchmarkMatrix::with_params([100, 1_000, 10_000, 100_000], RandomVec::<u64>::new)
-        .add_function("sort", sort_unstable)
+        .add_function_verified("sort", sort_unstable)
 }
 
-tango_benchmarks!(str_benchmarks(), num_benchmarks(), vec_benchmarks());
+/// Table lookup is faster in a steady state, but the first call pays for building the table,
+/// so cold and warm verdicts are different when compared against `tango-slower`
+fn popcount_benchmarks() -> impl IntoBenchmarks {
+    [
+        benchmark_fn_with_setup("popcount", LazyTable::default, |t| {
+            popcount_table(t, 0xABCDE)
+        }),
+        benchmark_fn_with_setup("popcount_cold", LazyTable::default, |t| {
+            popcount_table(t, 0xABCDE)
+        })
+        .measure_cold(),
+    ]
+}
+
+tango_benchmarks!(
+    str_benchmarks(),
+    num_benchmarks(),
+    vec_benchmarks(),
+    popcount_benchmarks()
+);
 tango_main!();
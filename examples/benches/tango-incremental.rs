@@ -0,0 +1,97 @@
+//! Compares two lookups in a sorted index growing over the run
+//!
+//! The index is generated once per 100 samples and grows by a batch of inserted keys before each sample (see
+//! `Generator::mutate_haystack()`), so the lookups are measured on an evolving index instead of a static one.
+//! Inserts are made outside of the timed region and both functions observe the same index at every sample.
+//!
+//! ```console
+//! $ cargo bench --bench=tango-incremental -- -t 1
+//! ```
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::process::ExitCode;
+use tango_bench::{
+    cli, BenchmarkMatrix, Generator, IntoBenchmarks, MeasureTarget, MeasurementSettings,
+    PairedTargets,
+};
+
+/// Sorted index of keys growing by `inserts` random keys before each sample
+struct GrowingIndex {
+    rng: SmallRng,
+    initial: usize,
+    inserts: usize,
+}
+
+impl Generator for GrowingIndex {
+    type Haystack = Vec<u64>;
+    type Needle = u64;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        let mut index = (0..self.initial)
+            .map(|_| self.rng.gen::<u64>())
+            .collect::<Vec<_>>();
+        index.sort_unstable();
+        index
+    }
+
+    fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+        self.rng.gen()
+    }
+
+    fn mutate_haystack(&mut self, index: &mut Self::Haystack, _sample_idx: usize) {
+        for _ in 0..self.inserts {
+            let key = self.rng.gen();
+            let position = index.partition_point(|&k| k < key);
+            index.insert(position, key);
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        true
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    fn name(&self) -> &str {
+        "growing"
+    }
+}
+
+#[allow(clippy::ptr_arg)]
+fn binary_search(index: &Vec<u64>, key: &u64) -> bool {
+    index.binary_search(key).is_ok()
+}
+
+#[allow(clippy::ptr_arg)]
+fn partition_point(index: &Vec<u64>, key: &u64) -> bool {
+    let position = index.partition_point(|k| k < key);
+    index.get(position) == Some(key)
+}
+
+type LookupFn = fn(&Vec<u64>, &u64) -> bool;
+
+fn lookup(name: &str, f: LookupFn) -> Box<dyn MeasureTarget> {
+    let index = GrowingIndex {
+        rng: SmallRng::seed_from_u64(0),
+        initial: 1_000,
+        inserts: 100,
+    };
+    BenchmarkMatrix::new(index)
+        .add_function(name, f)
+        .into_benchmarks()
+        .remove(0)
+}
+
+fn main() -> cli::Result<ExitCode> {
+    let settings = MeasurementSettings {
+        samples_per_haystack: 100,
+        ..Default::default()
+    };
+    let pairs = vec![PairedTargets::new(
+        lookup("binary_search", binary_search),
+        lookup("partition_point", partition_point),
+    )];
+    cli::run_pairs(settings, pairs)
+}
@@ -0,0 +1,46 @@
+//! Compares `copy_from_slice()` with a manual loop writing into a reused buffer
+//!
+//! Both functions write their output into a scratch buffer owned by the harness instead of returning it, so nothing
+//! is allocated or retained during the measurement.
+//!
+//! ```console
+//! $ cargo bench --bench=tango-inout -- -t 1
+//! ```
+
+use std::process::ExitCode;
+use tango_bench::{
+    cli, generators::RandomVec, BenchmarkMatrix, IntoBenchmarks, MeasureTarget,
+    MeasurementSettings, PairedTargets, ScratchReset,
+};
+
+#[allow(clippy::ptr_arg)]
+fn copy_from_slice(haystack: &Vec<u32>, _: &(), out: &mut Vec<u32>) {
+    out.resize(haystack.len(), 0);
+    out.copy_from_slice(haystack);
+}
+
+#[allow(clippy::ptr_arg)]
+fn manual_copy(haystack: &Vec<u32>, _: &(), out: &mut Vec<u32>) {
+    out.resize(haystack.len(), 0);
+    for (o, v) in out.iter_mut().zip(haystack) {
+        *o = *v;
+    }
+}
+
+type CopyFn = fn(&Vec<u32>, &(), &mut Vec<u32>);
+
+fn copy(name: &str, f: CopyFn) -> Box<dyn MeasureTarget> {
+    // The buffer is sized by the first call and overwritten afterwards
+    BenchmarkMatrix::new(RandomVec::<u32>::new(10_000))
+        .add_inout_function(name, ScratchReset::Never, f)
+        .into_benchmarks()
+        .remove(0)
+}
+
+fn main() -> cli::Result<ExitCode> {
+    let pairs = vec![PairedTargets::new(
+        copy("copy_from_slice", copy_from_slice),
+        copy("manual_copy", manual_copy),
+    )];
+    cli::run_pairs(MeasurementSettings::default(), pairs)
+}
@@ -0,0 +1,29 @@
+//! Compares two implementations registered in the same executable without building a baseline
+//!
+//! ```console
+//! $ cargo bench --bench=tango-pairs -- -t 1
+//! ```
+
+use std::{ops::Range, process::ExitCode, rc::Rc};
+use tango_bench::{
+    cli, BenchmarkMatrix, IntoBenchmarks, MeasureTarget, MeasurementSettings, PairedTargets,
+};
+use test_funcs::{str_count, str_count_rev, RandomSubstring};
+
+mod test_funcs;
+
+fn str_length(name: &str, f: fn(&Rc<String>, &Range<usize>) -> usize) -> Box<dyn MeasureTarget> {
+    let mut benchmarks = BenchmarkMatrix::new(RandomSubstring::new())
+        .add_function_verified(name, f)
+        .into_benchmarks();
+    benchmarks.remove(0)
+}
+
+fn main() -> cli::Result<ExitCode> {
+    let pairs = vec![PairedTargets::new(
+        str_length("str_count", str_count),
+        str_length("str_count_rev", str_count_rev),
+    )
+    .with_name("str_length")];
+    cli::run_pairs(MeasurementSettings::default(), pairs)
+}
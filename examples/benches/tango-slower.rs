@@ -2,10 +2,12 @@
 
 use crate::test_funcs::{factorial, sum};
 use tango_bench::{
-    benchmark_fn, generators::RandomVec, tango_benchmarks, tango_main, BenchmarkMatrix,
-    IntoBenchmarks,
+    benchmark_fn, benchmark_fn_with_setup, generators::RandomVec, tango_benchmarks, tango_main,
+    BenchmarkMatrix, IntoBenchmarks, MeasureTargetExt,
+};
+use test_funcs::{
+    popcount_naive, sort_stable, str_count_rev, str_take, LazyTable, RandomSubstring,
 };
-use test_funcs::{sort_stable, str_count_rev, str_take, RandomSubstring};
 
 mod test_funcs;
 
@@ -24,8 +26,23 @@ fn num_benchmarks() -> impl IntoBenchmarks {
 
 fn vec_benchmarks() -> impl IntoBenchmarks {
     BenchmarkMatrix::with_params([100, 1_000, 10_000, 100_000], RandomVec::<u64>::new)
-        .add_function("sort", sort_stable)
+        .add_function_verified("sort", sort_stable)
+}
+
+fn popcount_benchmarks() -> impl IntoBenchmarks {
+    [
+        benchmark_fn_with_setup("popcount", LazyTable::default, |_| popcount_naive(0xABCDE)),
+        benchmark_fn_with_setup("popcount_cold", LazyTable::default, |_| {
+            popcount_naive(0xABCDE)
+        })
+        .measure_cold(),
+    ]
 }
 
-tango_benchmarks!(str_benchmarks(), num_benchmarks(), vec_benchmarks());
+tango_benchmarks!(
+    str_benchmarks(),
+    num_benchmarks(),
+    vec_benchmarks(),
+    popcount_benchmarks()
+);
 tango_main!();
@@ -1,6 +1,6 @@
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use std::{hint::black_box, ops::Range, rc::Rc};
-use tango_bench::Generator;
+use tango_bench::{Generator, HaystackSize};
 
 /// HTML page with a lot of chinese text to test UTF8 decoding speed
 const INPUT_TEXT: &str = include_str!("./input.txt");
@@ -44,6 +44,11 @@ impl Generator for RandomSubstring {
         &self.name
     }
 
+    fn haystack_size(&self, _haystack: &Self::Haystack) -> Option<HaystackSize> {
+        // Each call processes a substring of `length` characters
+        Some(HaystackSize::Elements(self.length))
+    }
+
     fn next_needle(&mut self, _haystack: &Self::Haystack) -> Self::Needle {
         let start = self
             .rng
@@ -122,6 +127,37 @@ pub fn str_take(n: usize, s: &String, idx: &Range<usize>) -> usize {
     s[idx.start..idx.end].chars().take(black_box(n)).count()
 }
 
+/// Table of population counts for all 20-bit numbers (1MB) which is built on the first use
+#[derive(Default)]
+pub struct LazyTable(Option<Vec<u8>>);
+
+const TABLE_BITS: usize = 20;
+
+#[cfg_attr(feature = "align", repr(align(32)))]
+#[cfg_attr(feature = "align", inline(never))]
+#[allow(unused)]
+pub fn popcount_table(table: &mut LazyTable, n: usize) -> u8 {
+    let table = table.0.get_or_insert_with(|| {
+        (0..1 << TABLE_BITS)
+            .map(|i: usize| i.count_ones() as u8)
+            .collect()
+    });
+    table[black_box(n) & ((1 << TABLE_BITS) - 1)]
+}
+
+#[cfg_attr(feature = "align", repr(align(32)))]
+#[cfg_attr(feature = "align", inline(never))]
+#[allow(unused)]
+pub fn popcount_naive(mut n: usize) -> u8 {
+    n = black_box(n) & ((1 << TABLE_BITS) - 1);
+    let mut count = 0;
+    while n > 0 {
+        count += (n & 1) as u8;
+        n >>= 1;
+    }
+    count
+}
+
 #[cfg_attr(feature = "align", repr(align(32)))]
 #[cfg_attr(feature = "align", inline(never))]
 #[allow(unused)]
@@ -0,0 +1,328 @@
+//! Attribute macro registering free functions as Tango benchmarks
+//!
+//! This crate is not supposed to be used directly. Enable the `macros` feature of `tango-bench` and use
+//! `#[tango_bench::benchmark]`:
+//!
+//! ```rust,ignore
+//! use tango_bench::{benchmark, tango_main};
+//!
+//! #[benchmark]
+//! fn sum() -> u64 {
+//!     (0..1000u64).sum()
+//! }
+//!
+//! #[benchmark(generator = RandomVec::new(1000), name = "sort")]
+//! fn sort_vec(haystack: &Vec<u64>, _: &()) -> Vec<u64> {
+//!     let mut v = haystack.clone();
+//!     v.sort();
+//!     v
+//! }
+//!
+//! tango_main!();
+//! ```
+//!
+//! The function is kept as is and registered in the harness by `tango_main!()` along with the benchmarks listed in
+//! `tango_benchmarks!()`, if any. Depending on its signature the function is registered as:
+//!
+//! - `fn() -> O` – a benchmark without input (see `benchmark_fn()`);
+//! - `fn(&Haystack, &Needle) -> O` – a benchmark of a matrix built from `generator = <expr>` (see `BenchmarkMatrix`);
+//! - `fn() -> impl IntoBenchmarks` – a function building the benchmarks itself.
+//!
+//! Benchmark is named after the function unless `name = "..."` is given.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{
+    parse::Parser, punctuated::Punctuated, spanned::Spanned, Expr, ExprLit, FnArg, ItemFn, Lit,
+    LitStr, Meta, ReturnType, Token, Type,
+};
+
+/// Registers a free function as a benchmark (see the [crate documentation](crate))
+#[proc_macro_attribute]
+pub fn benchmark(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Arguments of the attribute
+#[derive(Default)]
+struct Args {
+    generator: Option<Expr>,
+    name: Option<LitStr>,
+}
+
+impl Args {
+    fn parse(attr: TokenStream2) -> syn::Result<Self> {
+        let mut args = Self::default();
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)?;
+        for meta in metas {
+            let Meta::NameValue(pair) = &meta else {
+                return Err(syn::Error::new(
+                    meta.span(),
+                    "expected `generator = <expr>` or `name = \"...\"`",
+                ));
+            };
+            let key = pair.path.get_ident().map(ToString::to_string);
+            match key.as_deref() {
+                Some("generator") => {
+                    if args.generator.is_some() {
+                        return Err(syn::Error::new(
+                            pair.path.span(),
+                            "duplicate `generator` argument",
+                        ));
+                    }
+                    args.generator = Some(pair.value.clone());
+                }
+                Some("name") => {
+                    if args.name.is_some() {
+                        return Err(syn::Error::new(
+                            pair.path.span(),
+                            "duplicate `name` argument",
+                        ));
+                    }
+                    let Expr::Lit(ExprLit {
+                        lit: Lit::Str(name),
+                        ..
+                    }) = &pair.value
+                    else {
+                        return Err(syn::Error::new(
+                            pair.value.span(),
+                            "benchmark name should be a string literal",
+                        ));
+                    };
+                    if name.value().is_empty() {
+                        return Err(syn::Error::new(
+                            name.span(),
+                            "benchmark name should not be empty",
+                        ));
+                    }
+                    args.name = Some(name.clone());
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        pair.path.span(),
+                        "unknown argument, expected `generator` or `name`",
+                    ))
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn expand(attr: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    let args = Args::parse(attr)?;
+    let func: ItemFn = syn::parse2(item)?;
+    let sig = &func.sig;
+
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "benchmark function can not be `async`",
+        ));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        return Err(syn::Error::new(
+            unsafety.span(),
+            "benchmark function can not be `unsafe`",
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "benchmark function can not be generic",
+        ));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(syn::Error::new(
+            variadic.span(),
+            "benchmark function can not be variadic",
+        ));
+    }
+    if let Some(FnArg::Receiver(receiver)) = sig.inputs.first() {
+        return Err(syn::Error::new(
+            receiver.span(),
+            "benchmark should be a free function, not a method",
+        ));
+    }
+
+    let ident = &sig.ident;
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+
+    let benchmarks = match (&args.generator, sig.inputs.len()) {
+        (None, 0) if returns_into_benchmarks(&sig.output) => {
+            if let Some(name) = &args.name {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "`name` is not used by a function returning `impl IntoBenchmarks`",
+                ));
+            }
+            quote_spanned! {sig.output.span()=> ::tango_bench::IntoBenchmarks::into_benchmarks(#ident()) }
+        }
+        (None, 0) => quote! { ::std::vec![::tango_bench::benchmark_fn(#name, #ident)] },
+        (Some(generator), 2) => quote! {
+            ::tango_bench::IntoBenchmarks::into_benchmarks(
+                ::tango_bench::BenchmarkMatrix::new(#generator).add_function(#name, #ident)
+            )
+        },
+        (None, _) => {
+            return Err(syn::Error::new(
+                sig.inputs.span(),
+                "benchmark function without a `generator` should take no arguments",
+            ))
+        }
+        (Some(generator), _) => {
+            let span = if sig.inputs.is_empty() {
+                generator.span()
+            } else {
+                sig.inputs.span()
+            };
+            return Err(syn::Error::new(
+                span,
+                "benchmark function with a `generator` should take a haystack and a needle: `fn(&H, &N) -> O`",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        #func
+
+        ::tango_bench::__tango_register! { || #benchmarks }
+    })
+}
+
+/// Returns `true` if the function returns `impl IntoBenchmarks`
+fn returns_into_benchmarks(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::ImplTrait(ty) = ty.as_ref() else {
+        return false;
+    };
+    ty.bounds.iter().any(|bound| {
+        let bound = bound.to_token_stream().to_string();
+        bound.rsplit("::").next().map(str::trim) == Some("IntoBenchmarks")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn tokens(source: &str) -> TokenStream2 {
+        TokenStream2::from_str(source).unwrap()
+    }
+
+    /// Message of the expansion error and the source text the error points to
+    fn error(attr: &str, item: &str) -> (String, String) {
+        let error = expand(tokens(attr), tokens(item)).expect_err("error is expected");
+        let text = error.span().source_text().unwrap_or_default();
+        (error.to_string(), text)
+    }
+
+    #[test]
+    fn check_expansion() {
+        let expanded = expand(tokens(""), tokens("fn sum() -> u64 { 42 }"))
+            .unwrap()
+            .to_string();
+        assert!(expanded.contains("fn sum"), "{}", expanded);
+        assert!(
+            expanded.contains("benchmark_fn (\"sum\" , sum)"),
+            "{}",
+            expanded
+        );
+        assert!(expanded.contains("__tango_register !"), "{}", expanded);
+
+        let expanded = expand(
+            tokens("generator = RandomVec::new(10), name = \"sort\""),
+            tokens("fn sort_vec(h: &Vec<u64>, n: &()) -> Vec<u64> { h.clone() }"),
+        )
+        .unwrap()
+        .to_string();
+        assert!(
+            expanded.contains("BenchmarkMatrix :: new (RandomVec :: new (10))"),
+            "{}",
+            expanded
+        );
+        assert!(
+            expanded.contains("add_function (\"sort\" , sort_vec)"),
+            "{}",
+            expanded
+        );
+
+        let expanded = expand(tokens(""), tokens("fn all() -> impl IntoBenchmarks { [] }"))
+            .unwrap()
+            .to_string();
+        assert!(
+            expanded.contains("into_benchmarks (all ())"),
+            "{}",
+            expanded
+        );
+    }
+
+    #[test]
+    fn check_diagnostics() {
+        let cases = [
+            ("", "async fn f() {}", "can not be `async`", "async"),
+            ("", "unsafe fn f() {}", "can not be `unsafe`", "unsafe"),
+            ("", "fn f<T>() {}", "can not be generic", "<T>"),
+            ("", "fn f(&self) {}", "not a method", "&self"),
+            (
+                "",
+                "fn f(a: &u64) {}",
+                "should take no arguments",
+                "a: &u64",
+            ),
+            (
+                "generator = G",
+                "fn f(a: &u64) {}",
+                "a haystack and a needle",
+                "a: &u64",
+            ),
+            ("generator = G", "fn f() {}", "a haystack and a needle", "G"),
+            ("name = 42", "fn f() {}", "should be a string literal", "42"),
+            ("name = \"\"", "fn f() {}", "should not be empty", "\"\""),
+            (
+                "name = \"a\", name = \"b\"",
+                "fn f() {}",
+                "duplicate `name`",
+                "name",
+            ),
+            (
+                "iterations = 10",
+                "fn f() {}",
+                "unknown argument",
+                "iterations",
+            ),
+            (
+                "generator",
+                "fn f() {}",
+                "expected `generator = <expr>`",
+                "generator",
+            ),
+            (
+                "name = \"a\"",
+                "fn f() -> impl IntoBenchmarks { [] }",
+                "`name` is not used",
+                "\"a\"",
+            ),
+        ];
+        for (attr, item, message, text) in cases {
+            let (actual_message, actual_text) = error(attr, item);
+            assert!(
+                actual_message.contains(message),
+                "#[benchmark({})] {}: {}",
+                attr,
+                item,
+                actual_message
+            );
+            assert_eq!(actual_text, text, "#[benchmark({})] {}", attr, item);
+        }
+    }
+}
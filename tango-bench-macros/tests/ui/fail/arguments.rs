@@ -0,0 +1,13 @@
+use tango_bench::benchmark;
+
+#[benchmark]
+fn sum(haystack: &Vec<u64>) -> u64 {
+    haystack.iter().sum()
+}
+
+#[benchmark(generator = tango_bench::generators::RandomVec::<u64>::new(10))]
+fn first(haystack: &Vec<u64>) -> Option<u64> {
+    haystack.first().copied()
+}
+
+fn main() {}
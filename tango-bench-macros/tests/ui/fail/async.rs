@@ -0,0 +1,8 @@
+use tango_bench::benchmark;
+
+#[benchmark]
+async fn sum() -> u64 {
+    42
+}
+
+fn main() {}
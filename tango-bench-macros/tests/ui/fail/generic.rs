@@ -0,0 +1,8 @@
+use tango_bench::benchmark;
+
+#[benchmark]
+fn sum<T: Default>() -> T {
+    T::default()
+}
+
+fn main() {}
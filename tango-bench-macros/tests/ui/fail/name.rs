@@ -0,0 +1,18 @@
+use tango_bench::benchmark;
+
+#[benchmark(name = 42)]
+fn sum() -> u64 {
+    42
+}
+
+#[benchmark(name = "")]
+fn product() -> u64 {
+    42
+}
+
+#[benchmark(iterations = 10)]
+fn difference() -> u64 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,28 @@
+use tango_bench::{benchmark, generators::RandomVec, registry, IntoBenchmarks};
+
+#[benchmark]
+fn sum() -> u64 {
+    (0..1000u64).sum()
+}
+
+#[benchmark(generator = RandomVec::<u64>::new(10), name = "sort")]
+#[allow(clippy::ptr_arg)]
+fn sort_vec(haystack: &Vec<u64>, _: &()) -> Vec<u64> {
+    let mut v = haystack.clone();
+    v.sort();
+    v
+}
+
+#[benchmark]
+fn all() -> impl IntoBenchmarks {
+    [tango_bench::benchmark_fn("all/len", || "all".len())]
+}
+
+fn main() {
+    let mut names = registry::collected()
+        .iter()
+        .map(|b| b.name().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["all/len", "sort/u64/10", "sum"]);
+}
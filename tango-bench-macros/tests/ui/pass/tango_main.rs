@@ -0,0 +1,9 @@
+// `tango_main!()` alone registers the annotated functions, `tango_benchmarks!()` is not needed
+use tango_bench::{benchmark, tango_main};
+
+#[benchmark]
+fn sum() -> u64 {
+    (0..1000u64).sum()
+}
+
+tango_main!();
@@ -1,5 +1,3 @@
-#![cfg_attr(feature = "align", feature(fn_align))]
-
 use num_traits::ToPrimitive;
 use std::{cell::RefCell, rc::Rc};
 use tango_bench::{
@@ -34,11 +32,9 @@ impl<H: Clone, N: Copy> Generator for StaticValue<H, N> {
     fn sync(&mut self, _: u64) {}
 }
 
-#[cfg_attr(feature = "align", repr(align(32)))]
-#[cfg_attr(feature = "align", inline(never))]
-fn create_summary<T: Copy + Ord + Default, N>(input: &Vec<T>, _: &N) -> Option<Summary<T>>
+fn create_summary<T, N>(input: &Vec<T>, _: &N) -> Option<Summary<T>>
 where
-    T: ToPrimitive,
+    T: Copy + Ord + Default + ToPrimitive,
 {
     Summary::from(input)
 }
@@ -54,9 +50,15 @@ fn iqr_interquartile_range_benchmarks() -> impl IntoBenchmarks {
 }
 
 fn empty_benchmarks() -> impl IntoBenchmarks {
-    [benchmark_fn("measure_empty_function", || {
-        benchmark_fn("_", || 42).measure(1)
-    })]
+    [
+        benchmark_fn("measure_empty_function", || {
+            benchmark_fn("_", || 42).measure(1)
+        }),
+        // Outputs needing drop are retained until the end of the sample
+        benchmark_fn("measure_retained_outputs", || {
+            benchmark_fn("_", String::new).measure(50)
+        }),
+    ]
 }
 
 fn generator_empty_benchmarks() -> impl IntoBenchmarks {
@@ -70,8 +72,20 @@ fn generator_empty_benchmarks() -> impl IntoBenchmarks {
     })
 }
 
+fn registration_benchmarks() -> impl IntoBenchmarks {
+    [benchmark_fn("register_and_list_5000", || {
+        let mut matrix = BenchmarkMatrix::with_params(0..100, RandomVec::<u8>::new);
+        for _ in 0..50 {
+            matrix = matrix.add_function("f", |h, _| h.len());
+        }
+        let benchmarks = matrix.into_benchmarks();
+        benchmarks.iter().map(|b| b.name().len()).sum::<usize>()
+    })]
+}
+
 tango_benchmarks!(
     empty_benchmarks(),
+    registration_benchmarks(),
     generator_empty_benchmarks(),
     summary_benchmarks(),
     iqr_interquartile_range_benchmarks()
@@ -1,4 +1,6 @@
 fn main() {
     println!("cargo:rustc-link-arg-benches=-rdynamic");
+    // Integration tests may load the test binary as the executable to compare against
+    println!("cargo:rustc-link-arg-tests=-rdynamic");
     println!("cargo:rerun-if-changed=build.rs");
 }
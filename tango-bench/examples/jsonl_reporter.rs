@@ -0,0 +1,48 @@
+//! Reporter plugin writing the session and all the results as JSON lines
+//!
+//! Build with `cargo build --example jsonl_reporter` and pass the library to the harness:
+//! `cargo bench -- compare --reporter-plugin target/debug/examples/libjsonl_reporter.so`. Results are written to
+//! the file given in `output` plugin option (`--reporter-plugin-option output=<PATH>`), `TANGO_JSONL_REPORT`
+//! environment variable or `tango-report.jsonl` in this order.
+
+use serde_json::Value;
+use std::{
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+};
+use tango_reporter_plugin::{export_reporter, ReporterPlugin};
+
+struct JsonlReporter {
+    output: BufWriter<File>,
+    results: usize,
+}
+
+impl ReporterPlugin for JsonlReporter {
+    fn init(session: &str) -> Option<Self> {
+        let options = serde_json::from_str::<Value>(session).ok()?["options"].take();
+        let path = match options["output"].as_str() {
+            Some(path) => path.to_string(),
+            None => env::var("TANGO_JSONL_REPORT").unwrap_or_else(|_| "tango-report.jsonl".into()),
+        };
+        let mut output = BufWriter::new(File::create(path).ok()?);
+        writeln!(output, "{{\"session\":{}}}", session).ok()?;
+        Some(Self { output, results: 0 })
+    }
+
+    fn on_result(&mut self, result: &str) {
+        self.results += 1;
+        let _ = writeln!(self.output, "{{\"result\":{}}}", result);
+    }
+
+    fn finish(mut self) {
+        let _ = writeln!(
+            self.output,
+            "{{\"finish\":{{\"results\":{}}}}}",
+            self.results
+        );
+        let _ = self.output.flush();
+    }
+}
+
+export_reporter!(JsonlReporter);
@@ -0,0 +1,373 @@
+//! Locating baseline executables stored in archives (eg. CI build artifacts) or on remote hosts
+//!
+//! Archives (`.zip`, `.tar.gz`, `.tgz`) are supported with `archives` feature, `http(s)://` URLs with `download`
+//! feature. Both are unpacked/downloaded into a temporary directory which is removed when [`Unpacked`] is dropped.
+
+use anyhow::{bail, Context, Result};
+use rand::random;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Maximum size of the downloaded file
+#[cfg(feature = "download")]
+const MAX_DOWNLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+const ARCHIVE_EXTENSIONS: [&str; 3] = [".zip", ".tar.gz", ".tgz"];
+
+/// Executable extracted into a temporary directory
+pub(crate) struct Unpacked {
+    _dir: TempDir,
+    executable: PathBuf,
+}
+
+impl Unpacked {
+    /// Unpacks or downloads the executable if given path is an archive or URL
+    ///
+    /// Returns `None` if the path is a regular file and can be used as is. The executable inside the archive is
+    /// located using `inner_path` if given or by the name of the current executable otherwise.
+    pub(crate) fn open_if_needed(path: &Path, inner_path: Option<&Path>) -> Result<Option<Self>> {
+        let name = path.to_string_lossy();
+        let is_url = name.starts_with("https://") || name.starts_with("http://");
+        if !is_url && !is_archive(&name) {
+            return Ok(None);
+        }
+
+        let dir = TempDir::new()?;
+        let file = if is_url {
+            download(&name, &dir)?
+        } else {
+            path.to_path_buf()
+        };
+
+        let executable = if is_archive(&file.to_string_lossy()) {
+            let content = dir.path().join("content");
+            unpack(&file, &content)
+                .with_context(|| format!("Unable to unpack {}", file.display()))?;
+            match inner_path {
+                Some(inner_path) => {
+                    let executable = content.join(inner_path);
+                    if !executable.is_file() {
+                        bail!("{} not found in {}", inner_path.display(), name);
+                    }
+                    executable
+                }
+                None => find_executable(&content)
+                    .with_context(|| format!("Unable to locate executable in {}", name))?,
+            }
+        } else {
+            file
+        };
+        set_executable(&executable)?;
+
+        Ok(Some(Self {
+            _dir: dir,
+            executable,
+        }))
+    }
+
+    pub(crate) fn executable(&self) -> &Path {
+        &self.executable
+    }
+
+    /// Directory all the files are unpacked to
+    #[cfg(all(test, feature = "archives"))]
+    fn dir(&self) -> &Path {
+        self._dir.path()
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::create_dir(path)
+}
+
+fn is_archive(name: &str) -> bool {
+    ARCHIVE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Temporary directory removed on drop
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a directory with a random name in the system temporary directory
+    ///
+    /// The directory is created by this call (an existing path is never reused) and on unix is accessible only by
+    /// the current user, so other users can't plant files or symlinks in it.
+    fn new() -> Result<Self> {
+        loop {
+            let path = env::temp_dir().join(format!("{}{:016x}", Self::prefix(), random::<u64>()));
+            match create_private_dir(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Unable to create temporary directory {}", path.display())
+                    })
+                }
+            }
+        }
+    }
+
+    /// Prefix of the names of the directories created by the current process
+    fn prefix() -> String {
+        format!("tango-{}-", process::id())
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(feature = "archives")]
+fn unpack(archive: &Path, target: &Path) -> Result<()> {
+    use flate2::read::GzDecoder;
+    use std::{fs::File, io::BufReader};
+
+    let file = BufReader::new(File::open(archive)?);
+    if archive.to_string_lossy().ends_with(".zip") {
+        zip::ZipArchive::new(file)?.extract(target)?;
+    } else {
+        tar::Archive::new(GzDecoder::new(file)).unpack(target)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+fn unpack(_archive: &Path, _target: &Path) -> Result<()> {
+    bail!("Support for archives is not enabled (requires `archives` feature of tango-bench)")
+}
+
+#[cfg(feature = "download")]
+fn download(url: &str, dir: &TempDir) -> Result<PathBuf> {
+    use std::{fs::OpenOptions, io::Read};
+
+    // Keeping the name of the file, so the archive type can be detected by the extension
+    let name = url
+        .rsplit('/')
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or("download");
+    let path = dir.path().join(name);
+
+    let response = ureq::get(url)
+        .call()
+        .with_context(|| format!("Unable to download {}", url))?;
+    let mut reader = response.into_reader().take(MAX_DOWNLOAD_SIZE + 1);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("Unable to create {}", path.display()))?;
+    let size =
+        io::copy(&mut reader, &mut file).with_context(|| format!("Unable to download {}", url))?;
+    if size > MAX_DOWNLOAD_SIZE {
+        bail!("{} is larger than {} bytes", url, MAX_DOWNLOAD_SIZE);
+    }
+    Ok(path)
+}
+
+#[cfg(not(feature = "download"))]
+fn download(_url: &str, _dir: &TempDir) -> Result<PathBuf> {
+    bail!("Support for URLs is not enabled (requires `download` feature of tango-bench)")
+}
+
+/// Finds the executable in a given directory
+///
+/// If the directory contains a single file it is used. Otherwise the file with the same name as the current
+/// executable is looked up (ignoring the hash suffix cargo adds to the names of executables).
+fn find_executable(dir: &Path) -> Result<PathBuf> {
+    let mut files = vec![];
+    list_files(dir, &mut files)?;
+    if let [file] = files.as_slice() {
+        return Ok(file.clone());
+    }
+
+    let current = env::current_exe()?;
+    let current = current.file_name().map(normalize_name);
+    let mut matching = files
+        .iter()
+        .filter(|f| f.file_name().map(normalize_name) == current);
+    match (matching.next(), matching.next()) {
+        (Some(file), None) => Ok(file.clone()),
+        _ => {
+            let files = files
+                .iter()
+                .filter_map(|f| f.strip_prefix(dir).ok())
+                .map(|f| f.display().to_string())
+                .collect::<Vec<_>>();
+            bail!(
+                "Use --inner-path to choose one of the files: {}",
+                files.join(", ")
+            )
+        }
+    }
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Name of the executable without extension and hash suffix (`tango_faster-0123456789abcdef` -> `tango_faster`)
+fn normalize_name(name: &std::ffi::OsStr) -> String {
+    let name = Path::new(name)
+        .file_stem()
+        .unwrap_or(name)
+        .to_string_lossy()
+        .replace('-', "_");
+    match name.rsplit_once('_') {
+        Some((stem, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            stem.to_string()
+        }
+        _ => name,
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, feature = "archives"))]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::{fs::File, io::Write};
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    const FILES: [(&str, &[u8]); 2] = [
+        ("bench/tango_faster-0123456789abcdef", b"faster"),
+        ("bench/README", b"readme"),
+    ];
+
+    fn create_tar_gz(path: &Path) {
+        let gz = GzEncoder::new(File::create(path).unwrap(), Compression::fast());
+        let mut tar = tar::Builder::new(gz);
+        for (name, content) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, content).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn create_zip(path: &Path) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in FILES {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn check_unpack_archives() {
+        let dir = TempDir::new().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(dir.path()).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+        }
+
+        let tar_gz = dir.path().join("bench.tar.gz");
+        let zip = dir.path().join("bench.zip");
+        create_tar_gz(&tar_gz);
+        create_zip(&zip);
+
+        for archive in [tar_gz, zip] {
+            let inner_path = Path::new(FILES[0].0);
+            let unpacked = Unpacked::open_if_needed(&archive, Some(inner_path))
+                .unwrap()
+                .unwrap();
+            assert_eq!(fs::read(unpacked.executable()).unwrap(), FILES[0].1);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let metadata = fs::metadata(unpacked.executable()).unwrap();
+                assert_eq!(metadata.permissions().mode() & 0o111, 0o111);
+            }
+
+            let unpacked_dir = unpacked.dir().to_path_buf();
+            drop(unpacked);
+            assert!(!unpacked_dir.exists());
+
+            // Temporary directory is removed on error as well
+            let before = temp_dirs();
+            let result = Unpacked::open_if_needed(&archive, Some(Path::new("unknown")));
+            assert!(result.is_err());
+
+            // Executable can't be located by the name, because test binary is named differently
+            let error = Unpacked::open_if_needed(&archive, None).err().unwrap();
+            assert!(format!("{:#}", error).contains("--inner-path"));
+
+            assert_eq!(temp_dirs(), before);
+        }
+    }
+
+    /// Temporary directories of the current process
+    fn temp_dirs() -> Vec<PathBuf> {
+        let prefix = TempDir::prefix();
+        let mut dirs = fs::read_dir(env::temp_dir())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| {
+                p.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(&prefix)
+            })
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs
+    }
+
+    #[test]
+    fn check_regular_files_are_used_as_is() {
+        let path = Path::new("target/bench");
+        assert!(Unpacked::open_if_needed(path, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn check_normalize_name() {
+        let name = |n: &str| normalize_name(n.as_ref());
+        assert_eq!(name("tango_faster-0123456789abcdef"), "tango_faster");
+        assert_eq!(name("tango-faster"), "tango_faster");
+        assert_eq!(name("tango-faster.exe"), "tango_faster");
+        assert_eq!(name("search-vec-ord"), "search_vec_ord");
+    }
+}
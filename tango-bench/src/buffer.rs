@@ -0,0 +1,165 @@
+//! Fixed-capacity buffer keeping the outputs of the measured functions on the stack
+//!
+//! Allocating a `Vec` for the outputs costs as much as an empty function call for the typical number of iterations
+//! per sample, so up to `N` outputs are stored inline and the buffer spills to the heap beyond that.
+
+use std::{
+    mem::{self, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr, slice,
+};
+
+/// Number of values [`SmallBuffer`] stores inline in the measurement loops
+pub(crate) const INLINE_CAPACITY: usize = 64;
+
+/// Largest value (in bytes) stored inline
+pub(crate) const MAX_INLINE_SIZE: usize = 64;
+
+pub(crate) enum SmallBuffer<T, const N: usize> {
+    Inline {
+        items: [MaybeUninit<T>; N],
+        len: usize,
+    },
+    Heap(Vec<T>),
+}
+
+impl<T, const N: usize> SmallBuffer<T, N> {
+    /// Buffer is allocated on the heap right away if the capacity exceeds `N`
+    #[inline(always)]
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        if capacity <= N {
+            Self::Inline {
+                items: [const { MaybeUninit::uninit() }; N],
+                len: 0,
+            }
+        } else {
+            Self::Heap(Vec::with_capacity(capacity))
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn push(&mut self, value: T) {
+        match self {
+            Self::Inline { items, len } if *len < N => {
+                items[*len].write(value);
+                *len += 1;
+            }
+            Self::Inline { .. } => self.spill().push(value),
+            Self::Heap(vec) => vec.push(value),
+        }
+    }
+
+    /// Moves the inline values to the heap
+    #[cold]
+    fn spill(&mut self) -> &mut Vec<T> {
+        if let Self::Inline { items, len } = self {
+            let mut vec = Vec::with_capacity(N * 2);
+            unsafe {
+                ptr::copy_nonoverlapping(items.as_ptr() as *const T, vec.as_mut_ptr(), *len);
+                vec.set_len(*len);
+            }
+            // Values are owned by the vec now
+            *len = 0;
+            *self = Self::Heap(vec);
+        }
+        match self {
+            Self::Heap(vec) => vec,
+            Self::Inline { .. } => unreachable!(),
+        }
+    }
+}
+
+impl<T, const N: usize> Deref for SmallBuffer<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Self::Inline { items, len } => unsafe {
+                slice::from_raw_parts(items.as_ptr() as *const T, *len)
+            },
+            Self::Heap(vec) => vec,
+        }
+    }
+}
+
+impl<T, const N: usize> DerefMut for SmallBuffer<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match self {
+            Self::Inline { items, len } => unsafe {
+                slice::from_raw_parts_mut(items.as_mut_ptr() as *mut T, *len)
+            },
+            Self::Heap(vec) => vec,
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for SmallBuffer<T, N> {
+    fn drop(&mut self) {
+        if let Self::Inline { items, len } = self {
+            if mem::needs_drop::<T>() {
+                let initialized = ptr::slice_from_raw_parts_mut(items.as_mut_ptr() as *mut T, *len);
+                unsafe { ptr::drop_in_place(initialized) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn check_small_buffer() {
+        let mut buffer = SmallBuffer::<u32, 4>::with_capacity(3);
+        buffer.extend_from(0..3);
+        assert!(buffer.is_inline());
+        assert_eq!(&*buffer, [0, 1, 2]);
+
+        // Values are moved to the heap once the capacity is exceeded
+        buffer.extend_from(3..6);
+        assert!(!buffer.is_inline());
+        assert_eq!(&*buffer, [0, 1, 2, 3, 4, 5]);
+
+        assert!(!SmallBuffer::<u32, 4>::with_capacity(5).is_inline());
+        let mut buffer = SmallBuffer::<u32, 4>::with_capacity(4);
+        buffer.extend_from([3, 1, 2]);
+        buffer.sort_unstable();
+        assert_eq!(&*buffer, [1, 2, 3]);
+    }
+
+    #[test]
+    fn check_small_buffer_drops_values() {
+        let drops = Rc::new(Cell::new(0));
+        for (capacity, count) in [(4, 3), (4, 4), (4, 7), (8, 8)] {
+            drops.set(0);
+            let mut buffer = SmallBuffer::<Counted, 4>::with_capacity(capacity);
+            for _ in 0..count {
+                buffer.push(Counted(Rc::clone(&drops)));
+            }
+            assert_eq!(drops.get(), 0);
+            drop(buffer);
+            assert_eq!(drops.get(), count);
+        }
+    }
+
+    impl<T, const N: usize> SmallBuffer<T, N> {
+        fn is_inline(&self) -> bool {
+            matches!(self, Self::Inline { .. })
+        }
+
+        fn extend_from(&mut self, values: impl IntoIterator<Item = T>) {
+            for value in values {
+                self.push(value);
+            }
+        }
+    }
+}
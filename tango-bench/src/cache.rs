@@ -0,0 +1,298 @@
+//! Cache of the test results allowing to skip measuring benchmarks unaffected by a change
+//!
+//! Each result is stored under a key made of the hashes of both executables, the name of the test and everything
+//! the measurement depends on: settings, number of samples or time limit and the seed. Only the runs with a seed
+//! given on the command line are cached, a result measured with a random seed is never replayed. A test is
+//! replayed from the cache as long as the key is the same. Rebuilding any of the executables, changing the
+//! settings or command line arguments affecting the measurement invalidates the results.
+
+use crate::RunResult;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Number of bytes at the beginning and at the end of an executable the fast hash is computed from
+const SAMPLED_BYTES: u64 = 1 << 20;
+
+/// Everything the result of a test depends on, except the name of the test
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CacheKey {
+    /// Hash of the executable the baseline is loaded from
+    pub(crate) baseline: u64,
+
+    /// Hash of the executable the candidate is loaded from (the running one)
+    pub(crate) candidate: u64,
+
+    /// [`crate::MeasurementSettings`] the tests are measured with
+    pub(crate) settings: Value,
+
+    /// Number of samples of each test (`-s`)
+    pub(crate) samples: Option<usize>,
+
+    /// Time limit of each test (`-t`)
+    pub(crate) time: Option<Duration>,
+
+    /// Seed given on the command line
+    pub(crate) seed: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<R> {
+    key: CacheKey,
+    name: String,
+    result: R,
+}
+
+/// Results of the tests measured with the same [`CacheKey`]
+pub(crate) struct ResultCache {
+    dir: PathBuf,
+    key: CacheKey,
+}
+
+impl ResultCache {
+    pub(crate) fn new(dir: impl Into<PathBuf>, key: CacheKey) -> Self {
+        Self {
+            dir: dir.into(),
+            key,
+        }
+    }
+
+    /// `tango/cache` in the cargo target directory
+    pub(crate) fn default_dir() -> PathBuf {
+        crate::target_dir().join("tango").join("cache")
+    }
+
+    /// Returns the result of a test if it was measured with the same key
+    ///
+    /// Missing, unreadable and outdated entries are not distinguished, the test has to be measured in any case.
+    pub(crate) fn read(&self, name: &str) -> Option<RunResult> {
+        let file = File::open(self.entry_path(name)).ok()?;
+        let entry: Entry<RunResult> = serde_json::from_reader(BufReader::new(file)).ok()?;
+        if entry.key != self.key || entry.name != name {
+            return None;
+        }
+        let mut result = entry.result;
+        result.cached = true;
+        Some(result)
+    }
+
+    pub(crate) fn write(&self, result: &RunResult) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = Entry {
+            key: self.key.clone(),
+            name: result.name.to_string(),
+            result,
+        };
+        // Entry is written to a temporary file first, so concurrent runs never read a partially written one
+        let path = self.entry_path(&result.name);
+        let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(temp, path)
+    }
+
+    fn entry_path(&self, name: &str) -> PathBuf {
+        let mut hash = Fnv::default();
+        hash.write(name.as_bytes());
+        hash.write(&self.key.baseline.to_le_bytes());
+        hash.write(&self.key.candidate.to_le_bytes());
+        self.dir.join(format!("{:016x}.json", hash.0))
+    }
+}
+
+/// Hash of an executable identifying the build
+///
+/// By default only the length, modification time and the first and the last megabyte of the file are hashed, so
+/// large executables are hashed quickly. `precise` hashes the whole content and ignores the modification time, so
+/// an identical rebuild keeps the cached results.
+pub(crate) fn hash_executable(path: &Path, precise: bool) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    let mut hash = Fnv::default();
+    hash.write(&len.to_le_bytes());
+    if precise {
+        hash.consume(&mut file, len)?;
+    } else {
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        hash.write(&mtime.as_nanos().to_le_bytes());
+        let head = len.min(SAMPLED_BYTES);
+        hash.consume(&mut file, head)?;
+        let tail = len.saturating_sub(SAMPLED_BYTES).max(head);
+        file.seek(SeekFrom::Start(tail))?;
+        hash.consume(&mut file, len - tail)?;
+    }
+    Ok(hash.0)
+}
+
+/// 64-bit FNV-1a hash (std hashers are not guaranteed to be stable between Rust versions)
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hashes given number of bytes read from a file
+    fn consume(&mut self, file: &mut File, len: u64) -> io::Result<()> {
+        let mut reader = file.take(len);
+        let mut buf = [0; 64 * 1024];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(()),
+                n => self.write(&buf[..n]),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{id::PairId, summarized_run_result, MeasurementSettings, Summary};
+    use serde_json::to_value;
+    use std::{env, fs::OpenOptions, io::Write, process};
+
+    fn key(settings: MeasurementSettings) -> CacheKey {
+        CacheKey {
+            baseline: 1,
+            candidate: 2,
+            settings: to_value(settings).unwrap(),
+            samples: None,
+            time: Some(Duration::from_millis(100)),
+            seed: 1,
+        }
+    }
+
+    fn result(name: &str) -> RunResult {
+        let baseline = Summary::from(&[10., 12., 11.]).unwrap();
+        let candidate = Summary::from(&[9., 10., 11.]).unwrap();
+        let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+        let settings = MeasurementSettings::default();
+        summarized_run_result(name, baseline, candidate, diff, None, 0, &settings)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("tango-cache-{}-{}", name, process::id()))
+    }
+
+    #[test]
+    fn check_cached_result_roundtrip() {
+        let dir = temp_dir("roundtrip");
+        let cache = ResultCache::new(&dir, key(MeasurementSettings::default()));
+        assert!(cache.read("sort").is_none());
+
+        cache.write(&result("sort")).unwrap();
+        let cached = cache.read("sort").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(cached.cached);
+        assert_eq!(&*cached.name, "sort");
+        assert_eq!(cached.id, PairId::same("sort"));
+        assert_eq!(cached.diff, result("sort").diff);
+        assert_eq!(cached.diff_estimate.pct, result("sort").diff_estimate.pct);
+    }
+
+    #[test]
+    fn check_cache_is_invalidated() {
+        let dir = temp_dir("invalidated");
+        let settings = MeasurementSettings::default();
+        ResultCache::new(&dir, key(settings))
+            .write(&result("sort"))
+            .unwrap();
+
+        let is_cached =
+            |key: CacheKey, name: &str| ResultCache::new(&dir, key).read(name).is_some();
+        assert!(is_cached(key(settings), "sort"));
+        assert!(!is_cached(key(settings), "search"));
+
+        let min_effect = MeasurementSettings {
+            min_effect: settings.min_effect * 2.,
+            ..settings
+        };
+        assert!(!is_cached(key(min_effect), "sort"));
+
+        let changes: [fn(&mut CacheKey); 5] = [
+            |k| k.baseline += 1,
+            |k| k.candidate += 1,
+            |k| k.samples = Some(100),
+            |k| k.time = Some(Duration::from_secs(1)),
+            |k| k.seed = 42,
+        ];
+        for change in changes {
+            let mut changed = key(settings);
+            change(&mut changed);
+            assert!(!is_cached(changed, "sort"));
+        }
+
+        // The result measured with the new settings replaces the previous one
+        ResultCache::new(&dir, key(min_effect))
+            .write(&result("sort"))
+            .unwrap();
+        assert!(is_cached(key(min_effect), "sort"));
+        assert!(!is_cached(key(settings), "sort"));
+
+        // Corrupted entries are not replayed
+        let cache = ResultCache::new(&dir, key(min_effect));
+        fs::write(cache.entry_path("sort"), "{").unwrap();
+        assert!(cache.read("sort").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_hash_executable() {
+        let dir = temp_dir("hash");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("executable");
+        fs::write(&path, vec![0u8; 3 * SAMPLED_BYTES as usize]).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let hash = |precise| hash_executable(&path, precise).unwrap();
+        let patch = |offset: u64, byte: u8| {
+            let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.seek(SeekFrom::Start(offset)).unwrap();
+            file.write_all(&[byte]).unwrap();
+            file.set_modified(modified).unwrap();
+        };
+
+        let (fast, precise) = (hash(false), hash(true));
+        assert_eq!(hash(false), fast);
+
+        // Fast hash doesn't read the middle of the file
+        patch(SAMPLED_BYTES + 1, 1);
+        assert_eq!(hash(false), fast);
+        assert_ne!(hash(true), precise);
+        patch(SAMPLED_BYTES + 1, 0);
+
+        for offset in [0, 3 * SAMPLED_BYTES - 1] {
+            patch(offset, 1);
+            assert_ne!(hash(false), fast);
+            patch(offset, 0);
+        }
+
+        // Precise hash ignores the modification time
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert_ne!(hash(false), fast);
+        assert_eq!(hash(true), precise);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
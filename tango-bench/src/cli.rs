@@ -1,25 +1,61 @@
 //! Contains functionality of a `cargo bench` harness
 
 use self::{
-    commands::PairedTest,
-    reporting::{ConsoleReporter, VerboseReporter},
+    commands::{PairedTest, TestPair},
+    cooldown::Cooldown,
+    isolated::{IsolatedTest, Isolation},
+    reporting::{
+        ConsoleReporter, CsvReporter, JsonReporter, JunitReporter, MarkdownReporter,
+        NdjsonReporter, Qualified, Silent, SortOrder, Sorted, Suite, SvgScatterReporter,
+        VerboseReporter,
+    },
+};
+use crate::{
+    archive::Unpacked,
+    cache::{self, CacheKey, ResultCache},
+    dump_index::{DumpIndex, DumpRecord, DumpSession},
+    dylib::Spi,
+    format::{Delta, TimeUnit},
+    generators,
+    manifest::{Environment, RunManifest, TestSchedule},
+    noise::{
+        self, BackgroundLoad, GateOutcome, LoadScope, LoadSpec, NoiseAction, NoiseFloor, NoiseGate,
+        SentinelRun, SentinelSchedule, SentinelSlot, SentinelSummary,
+    },
+    plugin::WithPlugins,
+    pooled_run_result,
+    profile::{Profile, ProfileOverride, TestProfile},
+    registry::RegistrySnapshot,
+    resource_limits::{self, ResourceLimits},
+    shard::{self, Shard},
+    stats::{self, Verdict},
+    symbol_map::{Side, SymbolMap},
+    timer,
+    watch::{self, History, Regression, WatchSettings},
+    ClockSource, Confirmation, Error, MeasurementSettings, OutlierDetection, OutlierScope, PairId,
+    PairedTargets, PhaseTimings, PrecisionStop, Reporter, RunResult, SampleObserver, SamplePlanner,
+    SamplerType, SessionInfo, SessionMode, SessionSummary, SlowTestPolicy, SuiteSummary,
+    TimerResolution, Unit, Verification,
 };
-use crate::{dylib::Spi, Error, MeasurementSettings, Reporter, SamplerType};
 use anyhow::{bail, Context};
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use colorz::mode::{self, Mode};
-use core::fmt;
 use glob_match::glob_match;
 use libloading::Library;
-use rand::{rngs::SmallRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{to_value, Value};
 use std::{
-    env::args,
+    collections::HashMap,
+    env,
+    ffi::OsString,
     fmt::Display,
-    io::{stderr, Write},
-    num::NonZeroUsize,
-    path::PathBuf,
-    process::ExitCode,
+    fs::{self, File},
+    io::{self, stderr, stdout, BufRead, BufReader, BufWriter, IsTerminal, Write},
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
     str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
     time::{Duration, Instant},
 };
 
@@ -27,54 +63,77 @@ pub type Result<T> = anyhow::Result<T>;
 pub type StdResult<T, E> = std::result::Result<T, E>;
 
 #[derive(Parser, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum BenchmarkMode {
     List {
         #[command(flatten)]
         bench_flags: CargoBenchFlags,
+
+        /// Filter tests by name (eg. '*/{sorted,unsorted}/[0-9]*')
+        #[arg(short = 'f', long = "filter")]
+        filter: Option<String>,
+
+        /// Compare the registered tests with a registry snapshot file and fail listing added, removed and renamed
+        /// tests
+        #[arg(long = "verify-registry", conflicts_with = "filter")]
+        verify_registry: Option<PathBuf>,
+
+        /// Write the current registry to the `--verify-registry` snapshot file instead of comparing with it
+        #[arg(long = "update-registry-snapshot", requires = "verify_registry")]
+        update_registry_snapshot: bool,
     },
-    Compare {
+    Compare(CompareOpts),
+    /// Compare against a baseline in a loop and run a command when a regression persists
+    Watch {
         #[command(flatten)]
         bench_flags: CargoBenchFlags,
 
-        /// Path to the executable to test agains. Tango will test agains itself if no executable given
-        path: Option<PathBuf>,
+        /// Path to the executable to test against (eg. the previous release)
+        path: PathBuf,
 
-        /// write CSV dumps of all the measurements in a given location
-        #[arg(short = 'd', long = "dump")]
-        path_to_dump: Option<PathBuf>,
+        /// Pause between cycles in seconds
+        #[arg(long = "interval", default_value = "3600", value_parser = parse_seconds)]
+        interval: Duration,
 
-        /// seed for the random number generator or omit to use a random seed
-        #[arg(long = "seed")]
-        seed: Option<u64>,
+        /// Number of consecutive cycles a test should regress in to be reported
+        #[arg(long = "consecutive", default_value = "3")]
+        consecutive: NonZeroUsize,
 
-        /// Number of samples to take for each test
-        #[arg(short = 's', long = "samples")]
-        samples: Option<NonZeroUsize>,
+        /// Minimal significant slowdown in percent considered a regression (eg. `1%`)
+        #[arg(long = "regression-threshold", value_parser = parse_percent)]
+        regression_threshold: Option<f64>,
 
-        /// The strategy to decide the number of iterations to run for each sample (values: flat, linear, random)
-        #[arg(long = "sampler")]
-        sampler: Option<SamplerType>,
+        /// Shell command run when a regression is reported. Receives the regression as JSON on stdin
+        #[arg(long = "on-regression", value_name = "CMD")]
+        on_regression: Option<String>,
 
-        /// Duration of each sample in seconds
-        #[arg(short = 't', long = "time")]
-        time: Option<f64>,
+        /// Append the results of each cycle to a given JSON lines file (`target/tango/history.jsonl` by default)
+        #[arg(long = "history")]
+        history: Option<PathBuf>,
 
-        /// Fail if the difference between the two measurements is greater than the given threshold in percent
-        #[arg(long = "fail-threshold")]
-        fail_threshold: Option<f64>,
+        /// Stop after a given number of cycles instead of running until terminated
+        #[arg(long = "cycles")]
+        cycles: Option<NonZeroUsize>,
+
+        #[command(flatten)]
+        comparison: ComparisonOpts,
 
-        /// Should we terminate early if --fail-threshold is exceed
-        #[arg(long = "fail-fast")]
-        fail_fast: bool,
+        #[command(flatten)]
+        layout: LayoutOpts,
+    },
+    /// Recompute results of the measurements dumped by `compare --dump` without measuring again
+    Analyze {
+        #[command(flatten)]
+        bench_flags: CargoBenchFlags,
 
-        /// Perform a read of a dummy data between samsples to minimize the effect of cache on the performance
-        /// (size in Kbytes)
-        #[arg(long = "cache-firewall")]
-        cache_firewall: Option<usize>,
+        /// CSV dump of a test or a directory with dumps (test names are taken from file names)
+        #[arg(short = 'i', long = "input", required_unless_present = "raw")]
+        inputs: Vec<PathBuf>,
 
-        /// Delegate control back to the OS before each sample
-        #[arg(long = "yield-before-sample")]
-        yield_before_sample: Option<bool>,
+        /// Raw samples of baseline and candidate measured by an external runner (eg. cycle counts collected on a
+        /// device, see `tango_core::raw`)
+        #[arg(long = "raw", num_args = 2, value_names = ["BASELINE", "CANDIDATE"], conflicts_with = "inputs")]
+        raw: Option<Vec<PathBuf>>,
 
         /// Filter tests by name (eg. '*/{sorted,unsorted}/[0-9]*')
         #[arg(short = 'f', long = "filter")]
@@ -84,21 +143,368 @@ enum BenchmarkMode {
         #[arg(short = 'g', long = "significant-only", default_value_t = false)]
         significant_only: bool,
 
-        /// Enable outlier detection
-        #[arg(short = 'o', long = "filter-outliers")]
-        filter_outliers: bool,
+        #[command(flatten)]
+        outliers: OutlierOpts,
+
+        /// Detect outliers over all the samples of a test or within consecutive windows of samples (values: global,
+        /// windowed). By default the windows are used only if the measurements drift during the test
+        #[arg(long = "outlier-scope")]
+        outlier_scope: Option<OutlierScope>,
+
+        #[command(flatten)]
+        stats: StatsOpts,
 
-        /// Quiet mode
-        #[arg(short = 'q')]
-        quiet: bool,
+        /// Minimal difference of interest in percent
+        #[arg(long = "min-effect")]
+        min_effect: Option<f64>,
 
         #[arg(short = 'v', long = "verbose", default_value_t = false)]
         verbose: bool,
+
+        /// Display all time values in a given unit
+        #[arg(long = "units", value_enum, default_value_t = TimeUnit::Auto)]
+        units: TimeUnit,
+
+        #[command(flatten)]
+        layout: LayoutOpts,
     },
 }
 
+/// Arguments of the `compare` subcommand
+#[derive(Parser, Debug)]
+struct CompareOpts {
+    #[command(flatten)]
+    bench_flags: CargoBenchFlags,
+
+    /// Path to the executable to test agains. Tango will test agains itself if no executable given
+    ///
+    /// Archives (.zip, .tar.gz) and http(s) URLs are supported with `archives` and `download` features.
+    path: Option<PathBuf>,
+
+    /// Path of the executable inside the archive (by default looked up by the name of the current executable)
+    #[arg(long = "inner-path", requires = "path")]
+    inner_path: Option<PathBuf>,
+
+    /// write CSV dumps of all the measurements in a given location
+    #[arg(short = 'd', long = "dump")]
+    path_to_dump: Option<PathBuf>,
+
+    /// Label recorded in the index of the dump directory (eg. `--label commit=1a2b3c`, can be given several times)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label, requires = "path_to_dump")]
+    labels: Vec<(String, String)>,
+
+    /// write a run manifest (seeds, settings and schedule of all the tests) to a given file
+    #[arg(long = "manifest")]
+    manifest: Option<PathBuf>,
+
+    /// reproduce a run recorded in a given manifest file
+    #[arg(long = "replay-manifest", conflicts_with_all = ["seed", "samples", "time"])]
+    replay_manifest: Option<PathBuf>,
+
+    /// replay the inputs recorded to a given file by `generators::Recorder` instead of generating them (in the
+    /// recorders of the generator with the same name)
+    #[arg(long = "generator-replay", value_name = "PATH")]
+    generator_replay: Option<PathBuf>,
+
+    /// replay the recordings from the beginning once they are exhausted instead of failing the tests
+    #[arg(long = "loop-replay")]
+    loop_replay: bool,
+
+    #[command(flatten)]
+    comparison: ComparisonOpts,
+
+    /// The strategy to decide the number of iterations to run for each sample (values: flat, linear, random)
+    #[arg(long = "sampler")]
+    sampler: Option<SamplerType>,
+
+    /// Clock source of the measurements (values: instant, monotonic-raw, rdtscp, cntvct), the finest available
+    /// one is chosen if omitted
+    #[arg(long = "timer")]
+    timer: Option<ClockSource>,
+
+    /// How the number of samples of a test is decided with -t (values: deadline, fixed)
+    #[arg(long = "plan")]
+    plan: Option<SamplePlanner>,
+
+    /// Run exactly a given number of iterations in each sample (skips estimation)
+    #[arg(long = "iterations")]
+    iterations: Option<NonZeroUsize>,
+
+    /// Take exactly a given number of samples of each test regardless of time
+    #[arg(long = "exact-samples", conflicts_with_all = ["samples", "time"])]
+    exact_samples: Option<NonZeroUsize>,
+
+    /// Don't retain samples and compute results from streaming statistics (disables --dump and outliers
+    /// filtering)
+    #[arg(long = "streaming")]
+    streaming: bool,
+
+    /// Memory in MiB the samples of a test may take before quantiles and outliers are estimated from a random
+    /// subset of them (64 by default)
+    #[arg(long = "max-sample-memory", value_name = "MIB")]
+    max_sample_memory: Option<NonZeroUsize>,
+
+    /// Fail if a test with a fixed number of samples is projected to run longer than given number of seconds
+    #[arg(long = "max-total-time", value_parser = parse_seconds)]
+    max_total_time: Option<Duration>,
+
+    /// Fail if the difference between the two measurements is greater than the given threshold in percent
+    #[arg(long = "fail-threshold")]
+    fail_threshold: Option<f64>,
+
+    /// Should we terminate early if --fail-threshold is exceed
+    #[arg(long = "fail-fast")]
+    fail_fast: bool,
+
+    /// Re-measure the N most significant results (5 by default) with a fresh seed and a doubled budget and
+    /// report the results pooled from both runs marked as confirmed or unconfirmed. Confirmation rows are
+    /// reported after the results of all the tests
+    #[arg(
+        long = "confirm-top",
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "5",
+        conflicts_with = "replay_manifest"
+    )]
+    confirm_top: Option<usize>,
+
+    /// Also compare each side against its second instance loaded from a copy of the executable and warn if any
+    /// of the A/A comparisons is significant (takes three times longer)
+    #[arg(long = "aa-test", conflicts_with_all = ["confirm_top", "sort"])]
+    aa_test: bool,
+
+    /// Fail if the weighted mean of significant regressions across all the tests exceeds a given budget in
+    /// percent (eg. `0.5%`, see `MeasureTargetExt::with_weight()`)
+    #[arg(long = "max-weighted-regression", value_parser = parse_percent)]
+    max_weighted_regression: Option<f64>,
+
+    /// Fail if there is not enough data to conclude neither the difference nor the equivalence of functions
+    #[arg(long = "fail-on-inconclusive")]
+    fail_on_inconclusive: bool,
+
+    /// Perform a read of a dummy data between samsples to minimize the effect of cache on the performance
+    /// (size in Kbytes)
+    #[arg(long = "cache-firewall")]
+    cache_firewall: Option<usize>,
+
+    /// Delegate control back to the OS before each sample
+    #[arg(long = "yield-before-sample")]
+    yield_before_sample: Option<bool>,
+
+    /// Extend the test duration by the time spent generating haystacks, so -t refers to measurement time only
+    #[arg(long = "exclude-generator-time")]
+    exclude_generator_time: bool,
+
+    /// Reuse haystacks for more samples if generating them dominates the sampling time
+    #[arg(long = "amortize-haystacks")]
+    amortize_haystacks: bool,
+
+    /// Measure per-iteration time at several batch sizes and warn if it depends on the number of iterations
+    #[arg(long = "batch-curve")]
+    batch_curve: bool,
+
+    /// After sampling, measure each call of both functions separately and report tail latencies (p99, p99.9
+    /// and max). Requires a high resolution timer (`hw-timer` feature)
+    #[arg(long = "per-call")]
+    per_call: bool,
+
+    /// Number of calls of each function measured with --per-call
+    #[arg(long = "per-call-samples", requires = "per_call")]
+    per_call_samples: Option<NonZeroUsize>,
+
+    /// Take a pair of samples of an empty function every K samples and report the share of the variance of
+    /// the differences caused by the timer and the sampling loop
+    #[arg(long = "null-every", value_name = "K")]
+    null_every: Option<NonZeroUsize>,
+
+    /// Measure calls with --per-call even if the platform timer is not precise enough for that
+    #[arg(long = "force-per-call", requires = "per_call")]
+    force_per_call: bool,
+
+    /// Compare functions processing batches of different sizes (eg. a loop of single lookups against a bulk
+    /// lookup) per element
+    #[arg(long = "mixed-batches")]
+    mixed_batches: bool,
+
+    /// Sample each test until the confidence interval of the difference is narrower than ± given percentage
+    /// (eg. `0.5%`) instead of using the time limit
+    #[arg(long = "target-precision", value_parser = parse_percent, conflicts_with_all = ["samples", "exact_samples"])]
+    target_precision: Option<f64>,
+
+    /// Time limit in seconds of a test sampled until the target precision is reached
+    #[arg(long = "precision-cap", value_parser = parse_seconds, requires = "target_precision")]
+    precision_cap: Option<Duration>,
+
+    /// Abort if a test makes no progress for a given time in seconds (eg. candidate function hangs)
+    #[arg(long = "call-timeout", value_parser = parse_seconds)]
+    call_timeout: Option<Duration>,
+
+    /// Skip a test making no progress for --call-timeout instead of aborting. Each test is measured in a child
+    /// process, which is killed on timeout (samples are not retained, as with cached results)
+    #[arg(long = "skip-on-timeout", requires = "call_timeout", conflicts_with_all = ["aa_test", "confirm_top"])]
+    skip_on_timeout: bool,
+
+    /// Sample a test with a single iteration if estimating both functions takes longer than given number
+    /// of seconds
+    #[arg(long = "estimate-budget", value_parser = parse_seconds)]
+    estimate_budget: Option<Duration>,
+
+    /// What to do when a single call is slower than a quarter of the test time: extend the test time,
+    /// skip the test or force it within the given time (extend, skip or force)
+    #[arg(long = "slow-test-policy")]
+    slow_test_policy: Option<SlowTestPolicy>,
+
+    /// Upper bound in seconds on how long a slow test may be extended to collect enough samples
+    #[arg(long = "max-auto-extend", value_parser = parse_seconds)]
+    max_auto_extend: Option<Duration>,
+
+    /// Pause between tests in milliseconds or `auto` to wait until CPU temperature returns to the initial value
+    #[arg(long = "cooldown", value_parser = parse_cooldown)]
+    cooldown: Option<CooldownArg>,
+
+    /// Fail if the library was built with measurement settings different from the ones of this executable
+    #[arg(long = "strict-settings")]
+    strict_settings: bool,
+
+    /// Number of inputs outputs of verified functions are compared on before measurement (0 disables)
+    #[arg(long = "verification-samples")]
+    verification_samples: Option<usize>,
+
+    /// Measure and report tests even if baseline and candidate outputs are different
+    #[arg(long = "ignore-verification")]
+    ignore_verification: bool,
+
+    /// Don't warn if the executable is compared against an identical copy of itself (eg. A/A testing)
+    #[arg(long = "allow-self-compare")]
+    allow_self_compare: bool,
+
+    /// Measure each test in a child process with limited resources (no core dumps, --memory-limit and
+    /// --cpu-limit). A test exceeding the limits fails alone, the harness itself is not limited
+    #[arg(long = "resource-limits", conflicts_with_all = ["aa_test", "confirm_top"])]
+    resource_limits: bool,
+
+    /// Maximum address space of the child process measuring a test in MiB
+    #[arg(
+        long = "memory-limit",
+        value_name = "MiB",
+        requires = "resource_limits"
+    )]
+    memory_limit: Option<NonZeroU64>,
+
+    /// Maximum CPU time of the child process measuring a test in seconds
+    #[arg(
+        long = "cpu-limit",
+        value_name = "SECONDS",
+        requires = "resource_limits"
+    )]
+    cpu_limit: Option<NonZeroU64>,
+
+    /// Generate background load while measuring to check the robustness of the results
+    /// (eg. `threads=2,duty=0.3[,period=10][,scope=test]`, period in milliseconds)
+    #[arg(long = "inject-load", value_parser = parse_load)]
+    inject_load: Option<LoadSpec>,
+
+    /// Compare a built-in reference function with itself before the suite and don't trust the verdicts if the
+    /// noise floor (95th percentile of the differences) exceeds a given percentage (eg. `1%`)
+    #[arg(long = "max-noise", value_parser = parse_percent)]
+    max_noise: Option<f64>,
+
+    /// Number of times the reference function is compared with itself to measure the noise floor
+    #[arg(long = "noise-runs", default_value = "5", requires = "max_noise")]
+    noise_runs: NonZeroUsize,
+
+    /// What to do if the noise floor exceeds --max-noise: abort the run or demote the verdicts to
+    /// informational (abort or demote)
+    #[arg(long = "on-noise", requires = "max_noise")]
+    noise_action: Option<NoiseAction>,
+
+    /// Don't compare the built-in sentinel pairs (identical functions) at the start, in the middle and at the
+    /// end of the suite to report the noise floor of the run
+    #[arg(long = "no-sentinels")]
+    no_sentinels: bool,
+
+    /// Order the tests are measured in: each test to completion or in turns of short time slices, so a slow
+    /// drift of the environment (eg. thermal ramp) affects all the tests alike instead of the ones measured
+    /// during it (sequential or round-robin)
+    #[arg(
+        long = "schedule",
+        value_enum,
+        default_value_t = TestOrder::Sequential,
+        conflicts_with_all = ["samples", "replay_manifest", "target_precision", "per_call", "path_to_dump", "cooldown", "aa_test"]
+    )]
+    schedule: TestOrder,
+
+    /// Length of a time slice of --schedule round-robin in milliseconds
+    #[arg(long = "slice", default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    slice: u64,
+
+    #[command(flatten)]
+    cache: CacheOpts,
+
+    #[command(flatten)]
+    plugins: PluginOpts,
+
+    #[command(flatten)]
+    report: ReportOpts,
+
+    /// Write a scatter plot of candidate vs baseline samples of each test as an SVG file to a given directory
+    #[arg(long = "scatter-dir", value_name = "PATH")]
+    scatter_dir: Option<PathBuf>,
+
+    /// Prefix the names of the tests in reports (eg. `crate::bench::`)
+    #[arg(long = "name-prefix", value_name = "PREFIX")]
+    name_prefix: Option<String>,
+
+    /// Write the addresses of the measured code of each test in the format of perf maps (eg.
+    /// `/tmp/perf-{pid}.map`, `{pid}` is replaced with the process id) and as JSON (`<PATH>.json`)
+    #[arg(long = "emit-symbol-map", value_name = "PATH")]
+    emit_symbol_map: Option<PathBuf>,
+
+    /// Run only the tests of a given shard of the suite (eg. `2/4`). Tests are assigned to shards by the hash of
+    /// their names
+    #[arg(long = "shard", value_name = "INDEX/COUNT", value_parser = parse_shard)]
+    shard: Option<Shard>,
+
+    /// Balance the shards by the durations of the tests recorded in a given run manifest (see `--manifest`)
+    /// instead of assigning tests by the hash of their names
+    #[arg(long = "shard-by-cost", value_name = "MANIFEST", requires = "shard")]
+    shard_by_cost: Option<PathBuf>,
+
+    /// Report only statistically significant results
+    #[arg(short = 'g', long = "significant-only", default_value_t = false)]
+    significant_only: bool,
+
+    /// Detect outliers over all the samples of a test or within consecutive windows of samples (values: global,
+    /// windowed). By default the windows are used only if the measurements drift during the test
+    #[arg(long = "outlier-scope")]
+    outlier_scope: Option<OutlierScope>,
+
+    #[command(flatten)]
+    stats: StatsOpts,
+
+    /// Override settings of a tolerance profile for the tests assigned to it (eg. `--profile
+    /// io:min_effect=2%,min_samples=50`). Settings: significance, min_effect, min_samples, outlier (none, iqr or
+    /// trim:LOWER,UPPER)
+    #[arg(long = "profile", value_parser = parse_profile_override)]
+    profiles: Vec<ProfileOverride>,
+
+    /// Quiet mode
+    #[arg(short = 'q')]
+    quiet: bool,
+
+    /// Exit successfully if the filter doesn't match any test
+    #[arg(long = "allow-empty")]
+    allow_empty: bool,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(
+    after_help = "A bare FILTER (eg. `cargo bench -- sort`) runs `compare` for the tests with names containing it. \
+    Baseline is taken from TANGO_AGAINST environment variable (the executable is compared against itself if not set). \
+    Use `-f` to filter tests named after a subcommand."
+)]
 struct Opts {
     #[command(subcommand)]
     subcommand: Option<BenchmarkMode>,
@@ -106,8 +512,140 @@ struct Opts {
     #[command(flatten)]
     bench_flags: CargoBenchFlags,
 
-    #[arg(long = "color", default_value = "detect")]
-    coloring_mode: String,
+    /// When to color the output: `auto` colors it if stdout is a terminal and `NO_COLOR` is not set
+    #[arg(long = "color", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Exit successfully with a warning instead of failing on unknown subcommands and flags (eg. the ones meant
+    /// for other harnesses of `cargo bench`)
+    #[arg(long = "tolerant", global = true)]
+    tolerant: bool,
+}
+
+/// Options of the harness comparing [`PairedTargets`] in-process
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct PairOpts {
+    #[command(flatten)]
+    bench_flags: CargoBenchFlags,
+
+    /// Number of samples to take for each pair
+    #[arg(short = 's', long = "samples")]
+    samples: Option<NonZeroUsize>,
+
+    /// Duration of each pair in seconds
+    #[arg(short = 't', long = "time")]
+    time: Option<f64>,
+
+    /// seed for the random number generator or omit to use a random seed
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+
+    /// Filter pairs by name (eg. '*/{sorted,unsorted}/[0-9]*')
+    #[arg(short = 'f', long = "filter")]
+    filter: Option<String>,
+
+    /// Run only pairs with names containing a given string (the same as `-f '*<FILTER>*'`)
+    #[arg(value_name = "FILTER", conflicts_with = "filter")]
+    substring: Option<String>,
+
+    /// Report only statistically significant results
+    #[arg(short = 'g', long = "significant-only", default_value_t = false)]
+    significant_only: bool,
+
+    #[command(flatten)]
+    outliers: OutlierOpts,
+
+    #[command(flatten)]
+    stats: StatsOpts,
+
+    #[command(flatten)]
+    cache: CacheOpts,
+
+    #[command(flatten)]
+    plugins: PluginOpts,
+
+    #[command(flatten)]
+    report: ReportOpts,
+
+    #[arg(short = 'v', long = "verbose", default_value_t = false)]
+    verbose: bool,
+
+    /// Display all time values in a given unit
+    #[arg(long = "units", value_enum, default_value_t = TimeUnit::Auto)]
+    units: TimeUnit,
+
+    /// Also compare each function of a pair against itself and warn if any of the A/A comparisons is significant
+    /// (takes three times longer)
+    #[arg(long = "aa-test", conflicts_with = "sort")]
+    aa_test: bool,
+
+    /// When to color the output: `auto` colors it if stdout is a terminal and `NO_COLOR` is not set
+    #[arg(long = "color", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+impl FromStr for SamplePlanner {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "deadline" => Ok(SamplePlanner::Deadline),
+            "fixed" => Ok(SamplePlanner::Fixed),
+            _ => Err(Error::UnknownSamplePlanner),
+        }
+    }
+}
+
+impl FromStr for SlowTestPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "extend" => Ok(SlowTestPolicy::Extend),
+            "skip" => Ok(SlowTestPolicy::Skip),
+            "force" => Ok(SlowTestPolicy::Force),
+            _ => Err(Error::UnknownSlowTestPolicy),
+        }
+    }
+}
+
+impl FromStr for ClockSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "instant" => Ok(ClockSource::Instant),
+            "monotonic-raw" => Ok(ClockSource::MonotonicRaw),
+            "rdtscp" => Ok(ClockSource::Rdtscp),
+            "cntvct" => Ok(ClockSource::Cntvct),
+            _ => Err(Error::UnknownClockSource),
+        }
+    }
+}
+
+impl FromStr for NoiseAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "abort" => Ok(NoiseAction::Abort),
+            "demote" => Ok(NoiseAction::Demote),
+            _ => Err(Error::UnknownNoiseAction),
+        }
+    }
+}
+
+impl FromStr for OutlierScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "global" => Ok(OutlierScope::Global),
+            "windowed" => Ok(OutlierScope::Windowed),
+            _ => Err(Error::UnknownOutlierScope),
+        }
+    }
 }
 
 impl FromStr for SamplerType {
@@ -130,535 +668,11087 @@ struct CargoBenchFlags {
     bench: bool,
 }
 
-pub fn run(mut settings: MeasurementSettings) -> Result<ExitCode> {
-    let opts = Opts::parse();
-
-    match Mode::from_str(&opts.coloring_mode) {
-        Ok(coloring_mode) => mode::set_coloring_mode(coloring_mode),
-        Err(_) => eprintln!("[WARN] Invalid coloring mode: {}", opts.coloring_mode),
-    }
+/// Outlier filtering shared by all the subcommands computing verdicts
+#[derive(Parser, Debug, Clone, Copy)]
+struct OutlierOpts {
+    /// Enable outlier detection
+    #[arg(short = 'o', long = "filter-outliers")]
+    filter_outliers: bool,
 
-    let subcommand = opts.subcommand.unwrap_or(BenchmarkMode::List {
-        bench_flags: opts.bench_flags,
-    });
+    /// Trim given percentage of the smallest and the largest differences (eg. `--trim 1,1`)
+    #[arg(long = "trim", value_parser = parse_trim, conflicts_with = "filter_outliers")]
+    trim: Option<OutlierDetection>,
+}
 
-    match subcommand {
-        BenchmarkMode::List { bench_flags: _ } => {
-            let spi = Spi::for_self().ok_or(Error::SpiSelfWasMoved)??;
-            for func in spi.tests() {
-                println!("{}", func.name);
-            }
-            Ok(ExitCode::SUCCESS)
+impl OutlierOpts {
+    /// Outlier detection given on the command line (`None` if neither of the flags is given)
+    fn detection(&self) -> Option<OutlierDetection> {
+        match self.trim {
+            Some(trim) => Some(trim),
+            None if self.filter_outliers => Some(OutlierDetection::Iqr),
+            None => None,
         }
-        BenchmarkMode::Compare {
-            bench_flags: _,
-            path,
-            verbose,
-            filter,
-            samples,
-            time,
-            filter_outliers,
-            path_to_dump,
-            fail_threshold,
-            fail_fast,
-            significant_only,
-            seed,
-            sampler,
-            cache_firewall,
-            yield_before_sample,
-            quiet,
-        } => {
-            let mut reporter: Box<dyn Reporter> = if verbose {
-                Box::<VerboseReporter>::default()
-            } else {
-                Box::<ConsoleReporter>::default()
-            };
-
-            let path = path
-                .or_else(|| args().next().map(PathBuf::from))
-                .expect("No path given");
+    }
+}
 
-            #[cfg(target_os = "linux")]
-            let path = crate::linux::patch_pie_binary_if_needed(&path)?.unwrap_or(path);
+/// Version of the statistics shared by all the subcommands computing verdicts
+#[derive(Parser, Debug, Clone, Copy, Default)]
+struct StatsOpts {
+    /// Version of the statistics the verdicts are computed with (1 reproduces the verdicts of the releases up to
+    /// 0.4, see `MeasurementSettings::stats_version`)
+    #[arg(long = "stats-version", value_parser = parse_stats_version)]
+    stats_version: Option<u32>,
+}
 
-            let spi_self = Spi::for_self().ok_or(Error::SpiSelfWasMoved)??;
-            let lib = unsafe { Library::new(&path) }
-                .with_context(|| format!("Unable to open library: {}", path.display()))?;
-            let spi_lib = Spi::for_library(&lib)?;
+impl StatsOpts {
+    /// Overrides the version of the statistics in given settings and checks it is known to this release
+    fn apply(&self, settings: &mut MeasurementSettings) -> Result<()> {
+        if let Some(version) = self.stats_version {
+            settings.stats_version = version;
+        }
+        check_stats_version(settings)
+    }
+}
 
-            settings.filter_outliers = filter_outliers;
-            settings.cache_firewall = cache_firewall;
+/// Options of a comparison shared by `compare` and `watch` subcommands
+#[derive(Parser, Debug, Clone)]
+struct ComparisonOpts {
+    /// Number of samples to take for each test
+    #[arg(short = 's', long = "samples")]
+    samples: Option<NonZeroUsize>,
 
-            if let Some(yield_before_sample) = yield_before_sample {
-                settings.yield_before_sample = yield_before_sample;
-            }
+    /// Duration of each test in seconds
+    #[arg(short = 't', long = "time")]
+    time: Option<f64>,
 
-            if let Some(sampler) = sampler {
-                settings.sampler_type = sampler;
-            }
+    /// seed for the random number generator or omit to use a random seed
+    #[arg(long = "seed")]
+    seed: Option<u64>,
 
-            let filter = filter.as_deref().unwrap_or("");
+    /// Filter tests by name (eg. '*/{sorted,unsorted}/[0-9]*')
+    #[arg(short = 'f', long = "filter")]
+    filter: Option<String>,
 
-            let loop_mode = create_loop_mode(samples, time)?;
-            let paired_test =
-                PairedTest::new(&spi_lib, &spi_self, settings, seed, loop_mode, path_to_dump);
+    #[command(flatten)]
+    outliers: OutlierOpts,
 
-            let mut exit_code = ExitCode::SUCCESS;
+    #[arg(short = 'v', long = "verbose", default_value_t = false)]
+    verbose: bool,
 
-            for func in spi_self.tests() {
-                if !filter.is_empty() && !glob_match(filter, &func.name) {
-                    continue;
-                }
+    /// Display all time values in a given unit
+    #[arg(long = "units", value_enum, default_value_t = TimeUnit::Auto)]
+    units: TimeUnit,
+}
 
-                if spi_lib.lookup(&func.name).is_none() {
-                    if !quiet {
-                        writeln!(stderr(), "{} skipped...", &func.name)?;
-                    }
-                    continue;
-                }
+impl ComparisonOpts {
+    fn filter(&self) -> &str {
+        self.filter.as_deref().unwrap_or("")
+    }
 
-                let result = paired_test.run(&func.name)?;
+    fn outlier_detection(&self) -> OutlierDetection {
+        self.outliers.detection().unwrap_or(OutlierDetection::None)
+    }
 
-                if result.diff_estimate.significant || !significant_only {
-                    reporter.on_complete(&result);
-                }
+    fn loop_mode(&self) -> Result<LoopMode> {
+        create_loop_mode(self.samples, self.time)
+    }
 
-                if result.diff_estimate.significant {
-                    if let Some(threshold) = fail_threshold {
-                        if result.diff_estimate.pct >= threshold {
-                            eprintln!(
-                                "[ERROR] Performance regressed {:+.1}% >= {:.1}%  -  test: {}",
-                                result.diff_estimate.pct, threshold, func.name
-                            );
-                            exit_code = ExitCode::FAILURE;
-                            if fail_fast {
-                                return Ok(ExitCode::FAILURE);
-                            }
-                        }
-                    }
-                }
-            }
-            Ok(exit_code)
+    /// Reporter printing the results to the console (`weights` adds a column with the weights of the tests)
+    fn console_reporter(&self, layout: LayoutOpts, weights: bool) -> Box<dyn Reporter> {
+        if self.verbose {
+            Box::new(VerboseReporter {
+                time_unit: self.units,
+            })
+        } else {
+            Box::new(ConsoleReporter::new(self.units, layout.name_width).with_weights(weights))
         }
     }
 }
 
-fn create_loop_mode(samples: Option<NonZeroUsize>, time: Option<f64>) -> Result<LoopMode> {
-    let loop_mode = match (samples, time) {
-        (Some(samples), None) => LoopMode::Samples(samples.into()),
-        (None, Some(time)) => LoopMode::Time(Duration::from_millis((time * 1000.) as u64)),
-        (None, None) => LoopMode::Time(Duration::from_millis(100)),
-        (Some(_), Some(_)) => bail!("-t and -s are mutually exclusive"),
-    };
-    Ok(loop_mode)
+/// Files the results are reported to along with the console
+#[derive(Parser, Debug, Clone)]
+struct ReportOpts {
+    /// Write all the results as a JSON array to a given file (the schema is stable, see `reporting::JsonResult`)
+    #[arg(long = "json", value_name = "PATH")]
+    json: Option<PathBuf>,
+
+    /// Write a summary of each result as a row of comma separated values to a given file
+    #[arg(long = "csv", value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Write all the tests as a JUnit XML document to a given file, significant regressions are failures
+    #[arg(long = "junit", value_name = "PATH")]
+    junit: Option<PathBuf>,
+
+    /// Stream each result as a line of JSON to a given file as soon as the test is completed (`-` for stdout,
+    /// which leaves out the console output)
+    #[arg(long = "ndjson", visible_alias = "results", value_name = "PATH")]
+    ndjson: Option<PathBuf>,
+
+    /// Print a Markdown table of all the results once the run is finished (eg. for a pull request description)
+    #[arg(long = "markdown")]
+    markdown: bool,
+
+    #[command(flatten)]
+    layout: LayoutOpts,
 }
 
-#[derive(Clone, Copy)]
-enum LoopMode {
-    Samples(usize),
-    Time(Duration),
+/// Layout of the results in the console (part of [`ReportOpts`], also accepted by `watch` and `analyze` which write
+/// no reports)
+#[derive(Parser, Debug, Clone, Copy)]
+struct LayoutOpts {
+    /// Maximum width of the test name column (longer names are shortened in the middle)
+    #[arg(long = "name-width", default_value_t = reporting::DEFAULT_NAME_WIDTH)]
+    name_width: usize,
+
+    /// Report the results in a given order once all the tests are completed instead of as they complete
+    #[arg(long = "sort", value_enum)]
+    sort: Option<SortOrder>,
 }
 
-impl LoopMode {
-    fn should_continue(&self, iter_no: usize, start_time: Instant) -> bool {
-        match self {
-            LoopMode::Samples(samples) => iter_no < *samples,
-            LoopMode::Time(duration) => {
-                // Trying not to stress benchmarking loop with to much of clock calls and check deadline
-                // approximately each 8 milliseconds based on the number of iterations already performed
-                // (we're assuming each iteration is approximately 1 ms)
-                if (iter_no & 0b111) == 0 {
-                    Instant::now() < (start_time + *duration)
-                } else {
-                    true
-                }
-            }
+impl LayoutOpts {
+    /// Wraps the reporter so the results are reported in the order given by `--sort`
+    fn sorted(&self, reporter: Box<dyn Reporter>) -> Box<dyn Reporter> {
+        match self.sort {
+            Some(order) => Box::new(Sorted::new(reporter, order)),
+            None => reporter,
         }
     }
 }
 
-mod commands {
-    use rand::RngCore;
+impl ReportOpts {
+    /// Returns `true` if the console output is left out for `--ndjson -`
+    fn ndjson_to_stdout(&self) -> Result<bool> {
+        ndjson_to_stdout(self.ndjson.as_deref(), self.markdown)
+    }
 
-    use super::*;
-    use crate::{
-        calculate_run_result, dylib::NamedFunction, CacheFirewall, FlatSampler, LinearSampler,
-        RandomSampler, RunResult, Sampler, SamplerType,
-    };
-    use std::{
-        fs::{self, File},
-        io::{self, BufWriter},
-        mem,
-        path::Path,
-        time::Instant,
+    /// Wraps the reporter with the order of `--sort` and the reporters of all the given files except the Markdown
+    /// table
+    fn wrap(self, reporter: Box<dyn Reporter>) -> Result<Box<dyn Reporter>> {
+        let mut reporter = self.layout.sorted(reporter);
+        if let Some(path) = self.json {
+            reporter = json_reporter(reporter, path)?;
+        }
+        if let Some(path) = self.csv {
+            reporter = csv_reporter(reporter, path)?;
+        }
+        if let Some(path) = self.junit {
+            reporter = junit_reporter(reporter, path)?;
+        }
+        if let Some(path) = self.ndjson {
+            reporter = ndjson_reporter(reporter, path)?;
+        }
+        Ok(reporter)
+    }
+}
+
+/// Reporter plugins loaded from dynamic libraries (see [`WithPlugins`])
+#[derive(Parser, Debug, Clone)]
+struct PluginOpts {
+    /// Also report results to a plugin loaded from a given dynamic library (see `tango-reporter-plugin`)
+    #[arg(long = "reporter-plugin", value_name = "PATH")]
+    reporter_plugins: Vec<PathBuf>,
+
+    /// Option passed to all the reporter plugins in the session JSON (can be given several times)
+    #[arg(long = "reporter-plugin-option", value_name = "KEY=VALUE", value_parser = parse_label, requires = "reporter_plugins")]
+    reporter_plugin_options: Vec<(String, String)>,
+}
+
+impl PluginOpts {
+    /// Wraps the reporter so the results are also reported to the plugins loaded from the given paths
+    fn load(&self, reporter: Box<dyn Reporter>) -> Result<Box<dyn Reporter>> {
+        for path in &self.reporter_plugins {
+            if !path.exists() {
+                bail!("Reporter plugin not found: {}", path.display());
+            }
+        }
+        WithPlugins::load(
+            reporter,
+            &self.reporter_plugins,
+            &self.reporter_plugin_options,
+        )
+        .context("Unable to load reporter plugin")
+    }
+}
+
+/// Caching of the test results between runs (see [`ResultCache`])
+#[derive(Parser, Debug, Clone)]
+struct CacheOpts {
+    /// Measure all the tests instead of replaying the results cached by the runs with the same executables,
+    /// settings and seed. Runs without --seed are never cached
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
+    /// Measure tests matching a given filter even if their results are cached (eg. '*/sorted/*')
+    #[arg(long = "force", value_name = "FILTER")]
+    force: Option<String>,
+
+    /// Hash the whole content of executables instead of their size, modification time, first and last megabyte
+    #[arg(long = "precise-hash")]
+    precise_hash: bool,
+}
+
+impl CacheOpts {
+    /// Opens the cache of the results measured with given executables and settings
+    ///
+    /// Returns `None` if caching is disabled, the seed is random or the cache key can't be computed (with a
+    /// warning).
+    fn open(
+        &self,
+        baseline: &Path,
+        settings: &MeasurementSettings,
+        loop_mode: LoopMode,
+        seed: Option<u64>,
+    ) -> Option<ResultCache> {
+        // Results measured with a random seed are not reproducible, so they are never replayed
+        let seed = seed.filter(|_| !self.no_cache)?;
+        let hash = |path: &Path| {
+            cache::hash_executable(path, self.precise_hash)
+                .with_context(|| format!("Unable to hash {}", path.display()))
+        };
+        let key = || -> Result<CacheKey> {
+            let (samples, time) = match loop_mode {
+                LoopMode::Samples(samples) => (Some(samples), None),
+                LoopMode::Time(time) => (None, Some(time)),
+            };
+            Ok(CacheKey {
+                baseline: hash(baseline)?,
+                candidate: hash(&env::current_exe()?)?,
+                settings: to_value(settings)?,
+                samples,
+                time,
+                seed,
+            })
+        };
+        match key() {
+            Ok(key) => Some(ResultCache::new(ResultCache::default_dir(), key)),
+            Err(e) => {
+                eprintln!("[WARN] Results are not cached: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Test should be measured even if its result is cached
+    fn is_forced(&self, name: &str) -> bool {
+        self.force
+            .as_deref()
+            .is_some_and(|f| matches_filter(f, name))
+    }
+
+    /// Result of a test cached by a previous run, unless the test is forced to be measured
+    fn cached(&self, cache: Option<&ResultCache>, name: &str) -> Option<RunResult> {
+        cache.filter(|_| !self.is_forced(name))?.read(name)
+    }
+
+    /// Tests having no cached result, which should be measured
+    fn uncached<'n>(&self, cache: Option<&ResultCache>, tests: &[&'n str]) -> Vec<&'n str> {
+        tests
+            .iter()
+            .copied()
+            .filter(|name| self.cached(cache, name).is_none())
+            .collect()
+    }
+}
+
+/// Exit code used when the filter doesn't match any test (unless `--allow-empty` is given)
+const EXIT_NO_TESTS: u8 = 3;
+
+/// Exit code used when the noise floor exceeds `--max-noise` and the run is aborted
+const EXIT_TOO_NOISY: u8 = 4;
+
+/// Exit code of a child process measuring a test which made no progress for the call timeout (`--skip-on-timeout`)
+const EXIT_TIMEOUT: u8 = 5;
+
+/// Environment variable enabling [`MainOptions::tolerant`] when set to `1`
+const TOLERANT_VAR: &str = "TANGO_PASSTHROUGH_TOLERANT";
+
+/// Options of the `main()` generated by [`tango_main!`](crate::tango_main)
+///
+/// ```rust
+/// use tango_bench::{cli::{DefaultMode, MainOptions}, tango_benchmarks, tango_main, MeasurementSettings};
+///
+/// tango_benchmarks!([]);
+/// tango_main!(
+///     MeasurementSettings::default(),
+///     options = MainOptions {
+///         tolerant: true,
+///         default_mode: DefaultMode::Compare,
+///         ..MainOptions::default()
+///     }
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MainOptions {
+    /// Unknown subcommands and flags produce a warning and a successful exit instead of an error
+    ///
+    /// `cargo bench -- <args>` passes the same arguments to all the bench targets, so in workspaces mixing tango
+    /// with other harnesses the arguments meant for one harness are given to all of them. Users can enable it with
+    /// `--tolerant` or `TANGO_PASSTHROUGH_TOLERANT=1` as well.
+    pub tolerant: bool,
+
+    /// Subcommand run when none is given (eg. by a plain `cargo bench`)
+    pub default_mode: DefaultMode,
+
+    /// Tolerance profiles assigned to the tests by name (see [`crate::profile`])
+    pub profiles: Vec<(String, Profile)>,
+}
+
+/// Subcommand run by the harness invoked without one (see [`MainOptions::default_mode`])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DefaultMode {
+    /// List the registered tests
+    #[default]
+    List,
+
+    /// Compare all the tests with the baseline from `TANGO_AGAINST` (or with itself if not set)
+    Compare,
+}
+
+pub fn run(settings: MeasurementSettings) -> Result<ExitCode> {
+    run_with_observers(settings, vec![])
+}
+
+/// Runs the harness calling given observers at the boundaries of each sample (see [`SampleObserver`])
+pub fn run_with_observers(
+    settings: MeasurementSettings,
+    observers: Vec<Box<dyn SampleObserver>>,
+) -> Result<ExitCode> {
+    run_with_options(settings, MainOptions::default(), observers)
+}
+
+/// Same as [`run_with_observers()`], but with given [`MainOptions`]
+pub fn run_with_options(
+    settings: MeasurementSettings,
+    options: MainOptions,
+    observers: Vec<Box<dyn SampleObserver>>,
+) -> Result<ExitCode> {
+    let options = MainOptions {
+        tolerant: options.tolerant || env::var_os(TOLERANT_VAR).is_some_and(|v| v == "1"),
+        ..options
+    };
+    run_from(env::args_os(), settings, &options, observers)
+}
+
+fn run_from<I, T>(
+    args: I,
+    mut settings: MeasurementSettings,
+    options: &MainOptions,
+    observers: Vec<Box<dyn SampleObserver>>,
+) -> Result<ExitCode>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    if let Some(test) = IsolatedTest::from_env()? {
+        return test.run(settings, &options.profiles, observers);
+    }
+    let args = with_default_subcommand(args, env::var_os(AGAINST_VAR), options.default_mode);
+    let Some(opts) = parse_opts(args, options.tolerant).unwrap_or_else(|e| e.exit()) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    let built_settings = settings;
+
+    mode::set_coloring_mode(coloring_mode(opts.color, env::var_os("NO_COLOR")));
+
+    let subcommand = opts.subcommand.unwrap_or(BenchmarkMode::List {
+        bench_flags: opts.bench_flags,
+        filter: None,
+        verify_registry: None,
+        update_registry_snapshot: false,
+    });
+
+    match subcommand {
+        BenchmarkMode::List {
+            bench_flags: _,
+            filter,
+            verify_registry,
+            update_registry_snapshot,
+        } => {
+            let spi_self = registered_tests(settings.max_name_len, &options.profiles)?;
+            if let Some(path) = verify_registry {
+                return verify_registry_snapshot(&spi_self, &path, update_registry_snapshot);
+            }
+            let filter = filter.as_deref().unwrap_or("");
+            let mut stdout = BufWriter::new(stdout().lock());
+            let mut matched = 0;
+            for func in spi_self.tests() {
+                if matches_filter(filter, func.name.as_str()) {
+                    match &func.profile.name {
+                        Some(name) => writeln!(
+                            stdout,
+                            "{}  ({})",
+                            func.name,
+                            profile_label(name, &func.profile)
+                        )?,
+                        None => writeln!(stdout, "{}", func.name)?,
+                    }
+                    matched += 1;
+                }
+            }
+            stdout.flush()?;
+            if !filter.is_empty() {
+                let total = spi_self.tests().len();
+                eprintln!("{} of {} tests match filter `{}`", matched, total, filter);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        BenchmarkMode::Compare(opts) => {
+            compare(opts, settings, &built_settings, options, observers)
+        }
+        BenchmarkMode::Watch {
+            bench_flags: _,
+            path,
+            interval,
+            consecutive,
+            regression_threshold,
+            on_regression,
+            history,
+            cycles,
+            comparison,
+            layout,
+        } => {
+            let spi_self = registered_tests(settings.max_name_len, &options.profiles)?;
+            let filter = comparison.filter();
+            let lib = load_baseline(&path)?;
+            let spi_lib = baseline_tests(&lib, &path, settings.max_name_len)?;
+
+            settings.outlier_detection = comparison.outlier_detection();
+            settings.clock = Some(select_clock(settings.clock)?);
+            reconcile_settings(&built_settings, &settings, &spi_lib, false)?;
+            let loop_mode = comparison.loop_mode()?;
+
+            let tests = spi_self
+                .tests()
+                .iter()
+                .map(|f| f.name.as_str())
+                .filter(|name| matches_filter(filter, name) && spi_lib.lookup(name).is_some())
+                .collect::<Vec<_>>();
+            if tests.is_empty() {
+                eprintln!("{}", no_matching_tests(filter, &spi_self));
+                return Ok(ExitCode::from(EXIT_NO_TESTS));
+            }
+
+            let mut reporter =
+                Suite::new(layout.sorted(comparison.console_reporter(layout, false)));
+            let history = History::new(
+                history
+                    .unwrap_or_else(|| ResultCache::default_dir().with_file_name("history.jsonl")),
+            );
+            let watch_settings = WatchSettings {
+                interval,
+                consecutive: consecutive.get(),
+                threshold: regression_threshold.unwrap_or(0.),
+                cycles: cycles.map(NonZeroUsize::get),
+            };
+
+            let run_cycle = |cycle: usize, stop: &AtomicBool| -> Result<Vec<RunResult>> {
+                let paired_test = PairedTest::new(
+                    &spi_lib,
+                    &spi_self,
+                    settings,
+                    comparison.seed,
+                    loop_mode,
+                    None,
+                );
+                eprintln!("Cycle {}", cycle);
+                reporter.on_session_start(&SessionInfo {
+                    settings,
+                    filter,
+                    mode: SessionMode::Compare { baseline: &path },
+                    master_seed: paired_test.master_seed(),
+                    environment: Environment::current(),
+                    tests: tests.clone(),
+                });
+                let started = Instant::now();
+                let mut totals = PhaseTimings::default();
+                let mut results = vec![];
+                for name in &tests {
+                    // Tests measured so far are recorded, the rest of the cycle is dropped
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    reporter.on_test_start(name);
+                    let result = match paired_test.run(name, None) {
+                        Ok(result) => result,
+                        Err(e) => match skip_reason(&e) {
+                            Some(reason) => {
+                                reporter.on_skipped(name, reason);
+                                continue;
+                            }
+                            None => return Err(e),
+                        },
+                    };
+                    totals += result.phases;
+                    reporter.on_complete(&result);
+                    results.push(result);
+                }
+                reporter.on_finish(&totals);
+                reporter.on_session_end(&SessionSummary {
+                    phases: totals,
+                    wall_time: started.elapsed(),
+                    dumps: vec![],
+                    sentinels: None,
+                });
+                Ok(results)
+            };
+            let alert = |regression: &Regression| -> Result<()> {
+                eprintln!(
+                    "[ERROR] Performance regressed {:+.1}% in {} consecutive cycles  -  test: {}",
+                    regression.result.diff_estimate.pct, regression.consecutive, regression.test
+                );
+                if let Some(command) = &on_regression {
+                    match watch::run_hook(command, &serde_json::to_vec(regression)?) {
+                        Ok(status) if status.success() => {}
+                        Ok(status) => eprintln!("[WARN] Regression hook failed: {}", status),
+                        Err(e) => eprintln!("[WARN] Unable to run regression hook: {}", e),
+                    }
+                }
+                Ok(())
+            };
+
+            let stop = watch::stop_on_sigterm();
+            let cycles = watch::watch(&watch_settings, run_cycle, Some(&history), alert, stop)?;
+            eprintln!(
+                "{} cycle(s) recorded in {}",
+                cycles,
+                history.path().display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        BenchmarkMode::Analyze {
+            bench_flags: _,
+            inputs,
+            raw,
+            filter,
+            significant_only,
+            outliers,
+            outlier_scope,
+            stats,
+            min_effect,
+            verbose,
+            units,
+            layout,
+        } => {
+            let reporter: Box<dyn Reporter> = if verbose {
+                Box::new(VerboseReporter { time_unit: units })
+            } else {
+                Box::new(ConsoleReporter::new(units, layout.name_width))
+            };
+            let mut reporter = layout.sorted(reporter);
+            let overrides = AnalyzeOverrides {
+                outlier_detection: outliers.detection(),
+                outlier_scope,
+                stats,
+                min_effect,
+                significant_only,
+            };
+            let filter = filter.as_deref().unwrap_or("");
+            match raw.as_deref() {
+                Some([baseline, candidate]) => analyze_raw(
+                    baseline,
+                    candidate,
+                    filter,
+                    settings,
+                    &overrides,
+                    reporter.as_mut(),
+                )?,
+                _ => analyze_dumps(&inputs, filter, settings, &overrides, reporter.as_mut())?,
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+impl CompareOpts {
+    /// Overrides the settings the harness is built with by the arguments
+    fn override_settings(&self, settings: &mut MeasurementSettings) -> Result<()> {
+        settings.outlier_detection = self.comparison.outlier_detection();
+        if let Some(scope) = self.outlier_scope {
+            settings.outlier_scope = Some(scope);
+        }
+        self.stats.apply(settings)?;
+        settings.cache_firewall = self.cache_firewall;
+        settings.exclude_generator_time |= self.exclude_generator_time;
+        settings.amortize_haystacks |= self.amortize_haystacks;
+        settings.batch_curve |= self.batch_curve;
+        if let Some(timer) = self.timer {
+            settings.clock = Some(timer);
+        }
+        settings.clock = Some(select_clock(settings.clock)?);
+        if self.per_call && settings.clock != Some(ClockSource::Rdtscp) && !self.force_per_call {
+            bail!(
+                "Platform timer is not precise enough to measure single calls, rebuild with `hw-timer` feature or use --force-per-call"
+            );
+        }
+        settings.per_call |= self.per_call;
+        settings.mixed_batches |= self.mixed_batches;
+        if let Some(per_call_samples) = self.per_call_samples {
+            settings.per_call_samples = per_call_samples.into();
+        }
+        if let Some(null_every) = self.null_every {
+            settings.null_interval = Some(null_every.into());
+        }
+        if let Some(target_precision) = self.target_precision {
+            if target_precision == 0. {
+                bail!("Target precision should be greater than zero");
+            }
+            settings.target_precision = Some(target_precision);
+        }
+        if let Some(precision_cap) = self.precision_cap {
+            settings.max_precision_time = precision_cap;
+        }
+        if let Some(call_timeout) = self.call_timeout {
+            settings.call_timeout = Some(call_timeout);
+        }
+        if let Some(estimate_budget) = self.estimate_budget {
+            settings.estimate_budget = Some(estimate_budget);
+        }
+        if let Some(slow_test_policy) = self.slow_test_policy {
+            settings.slow_test_policy = slow_test_policy;
+        }
+        if let Some(max_auto_extend) = self.max_auto_extend {
+            settings.max_auto_extend = max_auto_extend;
+        }
+        match self.cooldown {
+            Some(CooldownArg::Fixed(duration)) => {
+                settings.cooldown = Some(duration);
+                settings.adaptive_cooldown = false;
+            }
+            Some(CooldownArg::Auto) => {
+                settings.cooldown = settings.cooldown.or(Some(MAX_ADAPTIVE_COOLDOWN));
+                settings.adaptive_cooldown = true;
+            }
+            None => {}
+        }
+
+        if let Some(iterations) = self.iterations {
+            settings.fixed_iterations = Some(iterations.into());
+        }
+        if let Some(exact_samples) = self.exact_samples {
+            settings.exact_samples = Some(exact_samples.into());
+        }
+        if let Some(max_total_time) = self.max_total_time {
+            settings.max_total_time = Some(max_total_time);
+        }
+        settings.streaming |= self.streaming;
+        if let Some(max_sample_memory) = self.max_sample_memory {
+            settings.max_sample_memory = max_sample_memory.get() << 20;
+        }
+
+        if let Some(verification_samples) = self.verification_samples {
+            settings.verification_samples = verification_samples;
+        }
+
+        if let Some(yield_before_sample) = self.yield_before_sample {
+            settings.yield_before_sample = yield_before_sample;
+        }
+
+        if let Some(sampler) = self.sampler {
+            settings.sampler_type = sampler;
+        }
+
+        if let Some(plan) = self.plan {
+            settings.sample_planner = plan;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the `compare` subcommand with the settings the harness is built with
+fn compare(
+    opts: CompareOpts,
+    mut settings: MeasurementSettings,
+    built_settings: &MeasurementSettings,
+    options: &MainOptions,
+    observers: Vec<Box<dyn SampleObserver>>,
+) -> Result<ExitCode> {
+    if let Some(path) = &opts.generator_replay {
+        generators::recorded_name(path)
+            .with_context(|| format!("Unable to read recording {}", path.display()))?;
+    }
+    if needs_replay_env(opts.generator_replay.as_deref(), opts.loop_replay) {
+        return rerun_with_replay_env(opts.generator_replay.as_deref(), opts.loop_replay);
+    }
+    // Arguments are checked before anything is loaded or written
+    opts.override_settings(&mut settings)?;
+    let CompareOpts {
+        bench_flags: _,
+        path,
+        inner_path,
+        comparison,
+        profiles,
+        path_to_dump,
+        labels,
+        fail_threshold,
+        fail_fast,
+        confirm_top,
+        aa_test,
+        max_weighted_regression,
+        fail_on_inconclusive,
+        significant_only,
+        manifest,
+        replay_manifest,
+        generator_replay,
+        strict_settings,
+        ignore_verification,
+        allow_self_compare,
+        resource_limits,
+        memory_limit,
+        cpu_limit,
+        inject_load,
+        max_noise,
+        noise_runs,
+        noise_action,
+        no_sentinels,
+        schedule,
+        slice,
+        cache,
+        plugins,
+        report,
+        scatter_dir,
+        name_prefix,
+        emit_symbol_map,
+        shard,
+        shard_by_cost,
+        skip_on_timeout,
+        quiet,
+        allow_empty,
+        ..
+    } = opts;
+
+    let spi_self = registered_tests(settings.max_name_len, &options.profiles)?;
+    let filter = comparison.filter();
+    if let Some(path) = &generator_replay {
+        let replayed = spi_self.tests().iter().any(|f| {
+            matches_filter(filter, f.name.as_str())
+                && f.recording.as_deref() == Some(path.as_path())
+        });
+        if !replayed {
+            eprintln!(
+                "[WARN] None of the tests replays {} (the recording is replayed by the recorders of the generator with the same name)",
+                path.display()
+            );
+        }
+    }
+    if !spi_self
+        .tests()
+        .iter()
+        .any(|f| matches_filter(filter, f.name.as_str()))
+    {
+        eprintln!("{}", no_matching_tests(filter, &spi_self));
+        return Ok(if allow_empty {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(EXIT_NO_TESTS)
+        });
+    }
+
+    let weights = spi_self
+        .tests()
+        .iter()
+        .any(|f| matches_filter(filter, f.name.as_str()) && f.weight != 1.);
+    let mut reporter = compare_reporter(
+        &comparison,
+        report,
+        &plugins,
+        scatter_dir,
+        name_prefix,
+        weights,
+    )?;
+
+    // Executable is compared against itself on purpose if the path is not given
+    let allow_self_compare = allow_self_compare || path.is_none();
+    let path = path
+        .or_else(|| env::args().next().map(PathBuf::from))
+        .expect("No path given");
+    let baseline_path = path.clone();
+
+    // Temporary directory with unpacked executable should outlive the library loaded from it
+    let unpacked = Unpacked::open_if_needed(&path, inner_path.as_deref())
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+    let path = match &unpacked {
+        Some(unpacked) => unpacked.executable().to_path_buf(),
+        None => path,
+    };
+
+    if !allow_self_compare && is_same_binary(&path, &env::current_exe()?)? {
+        eprintln!(
+            "[WARN] Comparing binary against itself, differences are due to noise only (use --allow-self-compare if intended)"
+        );
+    }
+
+    let limits = resource_limits.then(|| ResourceLimits {
+        memory: memory_limit.map(|mib| u64::from(mib) << 20),
+        cpu: cpu_limit.map(|seconds| Duration::from_secs(seconds.into())),
+    });
+    if limits.is_some() {
+        resource_limits::check_supported()?;
+    }
+
+    // Results are cached by the executable as given, not the patched copy of it
+    let baseline_executable = path;
+    let lib = load_baseline(&baseline_executable)?;
+    let spi_lib = baseline_tests(&lib, &baseline_executable, settings.max_name_len)?;
+
+    let replica_libs = if aa_test {
+        Some([
+            load_replica(&baseline_executable, "baseline")?,
+            load_replica(&env::current_exe()?, "candidate")?,
+        ])
+    } else {
+        None
+    };
+    let replicas = match &replica_libs {
+        Some(libs) => {
+            let mut replicas = [
+                Spi::for_library(&libs[0], settings.max_name_len)?,
+                Spi::for_library(&libs[1], settings.max_name_len)?,
+            ];
+            for replica in &mut replicas {
+                replica.resolve_profiles(&options.profiles);
+            }
+            Some(replicas)
+        }
+        None => None,
+    };
+
+    let replay = replay_manifest
+        .map(|path| {
+            RunManifest::read(&path)
+                .with_context(|| format!("Unable to read manifest: {}", path.display()))
+        })
+        .transpose()?;
+    let seed = match &replay {
+        Some(replay) => {
+            settings = replay.settings;
+            Some(replay.master_seed)
+        }
+        None => comparison.seed,
+    };
+
+    reconcile_settings(built_settings, &settings, &spi_lib, strict_settings)?;
+
+    if let Some(path) = emit_symbol_map {
+        write_symbol_map(&path, &spi_lib, &spi_self, filter)?;
+    }
+
+    if settings.streaming {
+        if path_to_dump.is_some() {
+            eprintln!("[WARN] Samples are not retained in streaming mode, --dump is ignored");
+        }
+        if reporter.wants_samples() {
+            eprintln!(
+                "[WARN] Samples are not retained in streaming mode, --scatter-dir is ignored"
+            );
+        }
+        if settings.outlier_detection != OutlierDetection::None {
+            eprintln!("[WARN] Outliers are not filtered in streaming mode");
+        }
+    }
+
+    let loop_mode = comparison.loop_mode()?;
+    #[cfg(all(target_os = "linux", feature = "rapl"))]
+    let observers = {
+        let mut observers = observers;
+        if let Some(rapl) = crate::observers::RaplObserver::new() {
+            if seed.is_some() && !cache.no_cache {
+                eprintln!("[WARN] Energy is measured with RAPL, results are not cached");
+            }
+            observers.push(Box::new(rapl));
+        }
+        observers
+    };
+    // Cached results have neither dumps, samples nor observed values, replayed runs are measured on
+    // purpose and the cache is not keyed by the overrides of the profiles or the recording replayed by
+    // --generator-replay. Results measured under injected load should never be mistaken for clean ones
+    let result_cache = if replay.is_some()
+        || generator_replay.is_some()
+        || !profiles.is_empty()
+        || path_to_dump.is_some()
+        || reporter.wants_samples()
+        || !observers.is_empty()
+        || inject_load.is_some()
+    {
+        None
+    } else {
+        cache.open(&baseline_executable, &settings, loop_mode, seed)
+    };
+    let mut paired_test = PairedTest::new(
+        &spi_lib,
+        &spi_self,
+        settings,
+        seed,
+        loop_mode,
+        path_to_dump.clone(),
+    );
+    for observer in observers {
+        paired_test.add_observer(observer);
+    }
+    let isolation = (skip_on_timeout || limits.is_some()).then(|| Isolation {
+        baseline: baseline_executable.clone(),
+        settings,
+        seed: paired_test.master_seed(),
+        loop_mode,
+        dump: path_to_dump.clone(),
+        profiles: profiles.clone(),
+        limits,
+        args: env::args_os().skip(1).collect(),
+    });
+    warn_unassigned_profiles(&profiles, &spi_self);
+    paired_test.override_profiles(profiles.clone());
+    paired_test.keep_pairs(reporter.wants_samples());
+    paired_test.show_progress(!quiet && stderr().is_terminal());
+    // Each side is compared against its replica with the seed of the pair (`--aa-test`)
+    let aa_tests = replicas.as_ref().map(|[baseline, candidate]| {
+        let seed = Some(paired_test.master_seed());
+        [(&spi_lib, baseline), (candidate, &spi_self)].map(|(a, b)| {
+            let mut aa_test = PairedTest::new(a, b, settings, seed, loop_mode, None);
+            aa_test.override_profiles(profiles.clone());
+            aa_test.show_progress(!quiet && stderr().is_terminal());
+            aa_test
+        })
+    });
+    let mut run_manifest = RunManifest::new(paired_test.master_seed(), settings);
+    run_manifest.environment.injected_load = inject_load;
+    run_manifest.shard = shard;
+    if let Some(load) = inject_load {
+        eprintln!(
+            "[WARN] Injecting background load ({}), results are noisy on purpose",
+            load
+        );
+    }
+
+    let gate = max_noise.map(|max| NoiseGate {
+        max,
+        runs: noise_runs.into(),
+        action: noise_action.unwrap_or_default(),
+    });
+    let mut gate_outcome = GateOutcome::Trusted;
+    if let Some(gate) = gate {
+        let floor;
+        (floor, gate_outcome) = check_noise_floor(&gate, settings, loop_mode, seed, quiet)?;
+        run_manifest.environment.noise_floor = Some(floor);
+        if gate_outcome == GateOutcome::Abort {
+            return Ok(ExitCode::from(EXIT_TOO_NOISY));
+        }
+    }
+
+    // Dumps are not written in streaming mode
+    let mut dump_index = path_to_dump.filter(|_| !settings.streaming).map(|dir| {
+        let mut session = DumpSession::new(
+            settings,
+            paired_test.master_seed(),
+            run_manifest.environment.clone(),
+        );
+        session.labels = labels.into_iter().collect();
+        (dir, DumpIndex::new(session))
+    });
+    if let Some((dir, index)) = &dump_index {
+        if let Err(e) = index.write(dir) {
+            eprintln!("[WARN] Dump index is not written: {}", e);
+        }
+    }
+
+    let sharded = match shard {
+        Some(shard) => Some(shard_tests(
+            &spi_self,
+            filter,
+            shard,
+            shard_by_cost.as_deref(),
+        )?),
+        None => None,
+    };
+    let in_shard = |name: &str| match &sharded {
+        Some(sharded) => sharded.contains(&name),
+        None => true,
+    };
+
+    let tests = spi_self
+        .tests()
+        .iter()
+        .map(|f| f.name.as_str())
+        .filter(|name| matches_filter(filter, name) && spi_lib.lookup(name).is_some())
+        .filter(|name| in_shard(name))
+        .filter(|name| match &replay {
+            Some(replay) => replay.test(name).is_some(),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    // Tests measured in turns have no position in the run to place sentinels at
+    let sequential = schedule == TestOrder::Sequential;
+    if skip_on_timeout && !sequential {
+        bail!("--skip-on-timeout requires --schedule sequential");
+    }
+    if limits.is_some() && !sequential {
+        bail!("--resource-limits requires --schedule sequential");
+    }
+    let mut sentinels = (!no_sentinels && sequential && !tests.is_empty())
+        .then(|| Sentinels::new(tests.len(), settings, loop_mode, seed))
+        .and_then(|sentinels| sentinels_or_warn(reporter.as_mut(), sentinels));
+    reporter.on_session_start(&SessionInfo {
+        settings,
+        filter,
+        mode: SessionMode::Compare {
+            baseline: &baseline_path,
+        },
+        master_seed: paired_test.master_seed(),
+        environment: run_manifest.environment.clone(),
+        tests: tests.clone(),
+    });
+    let started = Instant::now();
+
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut totals = PhaseTimings::default();
+    let mut dumps = vec![];
+    let mut summary = SuiteSummary::default();
+    // Reference sensor values are taken before the first test
+    let mut cooldown = Cooldown::new(&settings);
+    let mut first_test = true;
+    let session_load = inject_load
+        .filter(|load| load.scope == LoadScope::Session)
+        .map(BackgroundLoad::start);
+    let test_load = inject_load.filter(|load| load.scope == LoadScope::Test);
+
+    // With --confirm-top results are kept for the confirmation runs
+    let mut deferred = vec![];
+    // Position of the test in the execution order (see noise::SentinelSchedule)
+    let mut position = 0;
+
+    let failures = |result: &RunResult| {
+        let test_settings = match spi_self.lookup(&result.name) {
+            Some(func) => func.profile.settings(&settings, &profiles),
+            None => settings,
+        };
+        let checks = VerdictChecks {
+            fail_threshold,
+            fail_on_inconclusive,
+            min_effect: test_settings.min_effect,
+        };
+        checks.failures(result, gate_outcome)
+    };
+    let mut finish = |reporter: &mut dyn Reporter, result: &RunResult| {
+        run_manifest.tests.push(result.schedule.clone());
+        summary.add(result);
+        report_diagnostics(reporter, result, &settings, run_manifest.environment.timer);
+        collect_dump(reporter, result, &mut dumps);
+        index_dump(reporter, dump_index.as_mut(), result);
+
+        if result.diff_estimate.significant || !significant_only {
+            reporter.on_complete(result);
+        }
+
+        let failures = failures(result);
+        for failure in &failures {
+            reporter.on_failure(&result.name, failure);
+        }
+        !failures.is_empty()
     };
 
-    struct TestedFunction<'a> {
-        spi: &'a Spi<'a>,
-        func: &'a NamedFunction,
-        samples: Vec<u64>,
+    // Stops at the first error, the results reported so far are finished like in a complete run
+    let mut run_tests = || -> Result<()> {
+        // Tests measured in turns, the cached ones are not measured
+        let mut sliced = if sequential {
+            None
+        } else {
+            let uncached = cache.uncached(result_cache.as_ref(), &tests);
+            let slice = Duration::from_millis(slice);
+            let on_start = |name: &str| reporter.on_test_start(name);
+            Some(RoundRobin::start(
+                &paired_test,
+                &uncached,
+                slice,
+                test_load,
+                on_start,
+            )?)
+        };
+        for func in spi_self.tests() {
+            if !matches_filter(filter, func.name.as_str()) || !in_shard(func.name.as_str()) {
+                continue;
+            }
+
+            if spi_lib.lookup(func.name.as_str()).is_none() {
+                if !quiet {
+                    writeln!(stderr(), "{} skipped...", &func.name)?;
+                }
+                continue;
+            }
+
+            let schedule = replay.as_ref().and_then(|r| r.test(func.name.as_str()));
+            if replay.is_some() && schedule.is_none() {
+                if !quiet {
+                    writeln!(
+                        stderr(),
+                        "{} is not in the manifest, skipped...",
+                        &func.name
+                    )?;
+                }
+                continue;
+            }
+
+            if let Some(due) = &mut sentinels {
+                if let Err(e) = due.run_due(position) {
+                    sentinels = sentinels_or_warn(reporter.as_mut(), Err(e));
+                }
+            }
+            position += 1;
+            // Tests measured in turns are started along with the first round
+            if !sliced
+                .as_ref()
+                .is_some_and(|s| s.contains(func.name.as_str()))
+            {
+                reporter.on_test_start(func.name.as_str());
+            }
+
+            if func.profile.undefined {
+                if let Some(name) = &func.profile.name {
+                    let message = format!("profile `{}` is not defined", name);
+                    reporter.on_warning(func.name.as_str(), &message);
+                }
+            }
+
+            match paired_test.verify(func.name.as_str())? {
+                Verification::Mismatch(mismatch) => {
+                    reporter.on_error(func.name.as_str(), &mismatch.to_string());
+                    if !ignore_verification {
+                        exit_code = ExitCode::FAILURE;
+                        if fail_fast {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                }
+                Verification::Skipped(reason) => {
+                    let message = format!("outputs are not verified: {}", reason);
+                    reporter.on_warning(func.name.as_str(), &message);
+                }
+                Verification::NotApplicable | Verification::Passed => {}
+            }
+
+            let cached = cache.cached(result_cache.as_ref(), func.name.as_str());
+            let mut result = match cached {
+                Some(result) => result,
+                None => {
+                    let cooldown_time = match &mut cooldown {
+                        Some(cooldown) if !first_test => cooldown.wait(),
+                        _ => Duration::ZERO,
+                    };
+                    first_test = false;
+
+                    let result = measure_test(
+                        &paired_test,
+                        sliced.as_mut(),
+                        isolation.as_ref(),
+                        func.name.as_str(),
+                        schedule,
+                        test_load,
+                    )?;
+                    let mut result = match result {
+                        Ok(result) => result,
+                        Err(e) => match skip_reason(&e) {
+                            Some(reason) => {
+                                reporter.on_skipped(func.name.as_str(), reason);
+                                continue;
+                            }
+                            None if isolated::is_contained(&e) => {
+                                reporter.on_error(func.name.as_str(), &e.to_string());
+                                exit_code = ExitCode::FAILURE;
+                                if fail_fast {
+                                    return Ok(());
+                                }
+                                continue;
+                            }
+                            None => return Err(e),
+                        },
+                    };
+                    result.phases.cooldown = cooldown_time;
+                    totals += result.phases;
+                    store_result(reporter.as_mut(), result_cache.as_ref(), &result);
+                    result
+                }
+            };
+            result.shard = shard;
+            let aa_results = match &aa_tests {
+                Some(aa_tests) => {
+                    run_aa_tests(aa_tests, reporter.as_mut(), func.name.as_str(), &result)?
+                }
+                None => vec![],
+            };
+            for aa_result in &aa_results {
+                totals += aa_result.phases;
+            }
+            report_aa_test(reporter.as_mut(), &result, &aa_results);
+            let failed = finish(reporter.as_mut(), &result);
+            // Self-comparisons are reported right after the test they belong to
+            for aa_result in &aa_results {
+                if aa_result.diff_estimate.significant || !significant_only {
+                    reporter.on_complete(aa_result);
+                }
+            }
+            if confirm_top.is_some() {
+                deferred.push(result);
+            }
+            if failed {
+                exit_code = ExitCode::FAILURE;
+                if fail_fast {
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(top) = confirm_top {
+            paired_test.confirmation();
+            confirm_results(&mut deferred, top, &settings, |result| {
+                let load = test_load.map(BackgroundLoad::start);
+                let second = paired_test.run(&result.name, None);
+                drop(load);
+                match second {
+                    Ok(second) => {
+                        totals += second.phases;
+                        Ok(Some(second))
+                    }
+                    Err(e) => match skip_reason(&e) {
+                        Some(reason) => {
+                            let message = format!("result is not confirmed: {}", reason);
+                            reporter.on_warning(&result.name, &message);
+                            Ok(None)
+                        }
+                        None => Err(e),
+                    },
+                }
+            })?;
+            // Confirmation rows are reported after all the tests, the first results are already counted
+            for result in deferred.iter().filter(|r| r.confirmation.is_some()) {
+                reporter.on_complete(result);
+                let failures = failures(result);
+                for failure in &failures {
+                    reporter.on_failure(&result.name, failure);
+                }
+                if !failures.is_empty() {
+                    exit_code = ExitCode::FAILURE;
+                    if fail_fast {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    };
+    let outcome = run_tests();
+
+    let sentinels = match &outcome {
+        Ok(()) => sentinels
+            .map(Sentinels::finish)
+            .and_then(|summary| sentinels_or_warn(reporter.as_mut(), summary)),
+        Err(_) => None,
+    };
+    drop(session_load);
+    if let Some((dir, index)) = &mut dump_index {
+        index.session.finish();
+        if let Err(e) = index.write(dir) {
+            eprintln!("[WARN] Dump index is not written: {}", e);
+        }
+    }
+    reporter.on_finish(&totals);
+    reporter.on_summary(&summary);
+    reporter.on_session_end(&SessionSummary {
+        phases: totals,
+        wall_time: started.elapsed(),
+        dumps,
+        sentinels,
+    });
+    outcome?;
+
+    if let (Some(budget), true) = (max_weighted_regression, gate_outcome.enforces_verdicts()) {
+        if summary.weighted_regression() > budget {
+            eprintln!(
+                "[ERROR] Weighted regression {:.2}% exceeds the budget of {:.2}%",
+                summary.weighted_regression(),
+                budget
+            );
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(path) = manifest {
+        run_manifest
+            .write(&path)
+            .with_context(|| format!("Unable to write manifest: {}", path.display()))?;
+    }
+    Ok(exit_code)
+}
+
+/// Writes the entry points of the tests matching a filter (`--emit-symbol-map`)
+fn write_symbol_map(path: &Path, baseline: &Spi, candidate: &Spi, filter: &str) -> Result<()> {
+    let mut symbol_map = SymbolMap::default();
+    symbol_map.add_tests(baseline, Side::Baseline, |name| {
+        matches_filter(filter, name)
+    });
+    symbol_map.add_tests(candidate, Side::Candidate, |name| {
+        matches_filter(filter, name)
+    });
+    let path = symbol_map
+        .write(path)
+        .with_context(|| format!("Unable to write symbol map: {}", path.display()))?;
+    eprintln!(
+        "Symbol map of {} entry point(s) written to {}",
+        symbol_map.entries().len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Warns about the profiles overridden from the command line no test is assigned to
+fn warn_unassigned_profiles(profiles: &[ProfileOverride], spi: &Spi) {
+    for o in profiles {
+        if !spi
+            .tests()
+            .iter()
+            .any(|f| f.profile.name.as_ref() == Some(&o.profile))
+        {
+            eprintln!("[WARN] No test is assigned to profile `{}`", o.profile);
+        }
+    }
+}
+
+/// Tests matching a filter assigned to a given shard (`--shard`), balanced by the costs recorded in a manifest if
+/// given (`--shard-by-cost`)
+fn shard_tests<'s>(
+    spi: &'s Spi,
+    filter: &str,
+    shard: Shard,
+    costs: Option<&Path>,
+) -> Result<Vec<&'s str>> {
+    let costs = costs
+        .map(|path| {
+            RunManifest::read(path)
+                .with_context(|| format!("Unable to read manifest: {}", path.display()))
+        })
+        .transpose()?;
+    let names = spi
+        .tests()
+        .iter()
+        .map(|f| f.name.as_str())
+        .filter(|name| matches_filter(filter, name))
+        .collect::<Vec<_>>();
+    Ok(shard::select(&names, shard, costs.as_ref()))
+}
+
+/// Reporters of the `compare` subcommand: console (unless NDJSON is written to stdout), plugins, Markdown, the
+/// reports written to files, scatter plots and the name prefix
+fn compare_reporter(
+    comparison: &ComparisonOpts,
+    report: ReportOpts,
+    plugins: &PluginOpts,
+    scatter_dir: Option<PathBuf>,
+    name_prefix: Option<String>,
+    weights: bool,
+) -> Result<Box<dyn Reporter>> {
+    let reporter: Box<dyn Reporter> = if report.ndjson_to_stdout()? {
+        Box::new(Silent)
+    } else {
+        comparison.console_reporter(report.layout, weights)
+    };
+    let mut reporter = plugins.load(reporter)?;
+    if report.markdown {
+        reporter = Box::new(MarkdownReporter::new(reporter, stdout(), comparison.units));
+    }
+    reporter = report.wrap(reporter)?;
+    if let Some(dir) = scatter_dir {
+        reporter = Box::new(SvgScatterReporter { reporter, dir });
+    }
+    if let Some(prefix) = name_prefix {
+        reporter = Box::new(Qualified { reporter, prefix });
+    }
+    Ok(Box::new(Suite::new(reporter)))
+}
+
+/// Arguments of `analyze` changing the way dumps are analyzed
+#[derive(Default)]
+struct AnalyzeOverrides {
+    outlier_detection: Option<OutlierDetection>,
+    outlier_scope: Option<OutlierScope>,
+    stats: StatsOpts,
+    min_effect: Option<f64>,
+    significant_only: bool,
+}
+
+impl AnalyzeOverrides {
+    fn apply(&self, settings: &mut MeasurementSettings) -> Result<()> {
+        if let Some(outlier_detection) = self.outlier_detection {
+            settings.outlier_detection = outlier_detection;
+        }
+        if let Some(scope) = self.outlier_scope {
+            settings.outlier_scope = Some(scope);
+        }
+        if let Some(min_effect) = self.min_effect {
+            settings.min_effect = min_effect;
+        }
+        self.stats.apply(settings)
+    }
+}
+
+/// Recomputes the results of the measurements dumped in given files and directories and reports them
+///
+/// If a directory has an index (see [`DumpIndex`]), the results are computed with the settings of the run the dumps
+/// are written by and reported in the order the tests were run, so the report of the run is reproduced without any
+/// other inputs. Settings are overridden by the arguments given explicitly.
+fn analyze_dumps(
+    inputs: &[PathBuf],
+    filter: &str,
+    mut settings: MeasurementSettings,
+    overrides: &AnalyzeOverrides,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    let mut index = None;
+    for dir in inputs.iter().filter(|i| i.is_dir()) {
+        index = DumpIndex::read(dir)
+            .with_context(|| format!("Unable to read dump index: {}", dir.display()))?;
+        if index.is_some() {
+            break;
+        }
+    }
+    match &index {
+        Some(index) => {
+            settings = index.session.settings;
+            eprintln!("{}", describe_dump_session(&index.session));
+        }
+        None => settings.outlier_detection = OutlierDetection::None,
+    }
+    overrides.apply(&mut settings)?;
+
+    let mut summary = SuiteSummary::default();
+    for (name, path) in commands::find_dumps(inputs)? {
+        if !matches_filter(filter, &name) {
+            continue;
+        }
+        let measurements = commands::read_raw_measurements(&path)
+            .with_context(|| format!("Unable to read dump: {}", path.display()))?;
+        let Some(result) = measurements.analyze(name.as_str(), &settings) else {
+            reporter.on_warning(&name, "dump contains no measurements");
+            continue;
+        };
+        summary.add(&result);
+        if result.diff_estimate.significant || !overrides.significant_only {
+            reporter.on_complete(&result);
+        }
+    }
+    reporter.on_finish(&PhaseTimings::default());
+    reporter.on_summary(&summary);
+    Ok(())
+}
+
+/// Computes the result of the samples of both functions measured by an external runner and reports it
+///
+/// Values are reported as counts (eg. cycles) and, like for the dumps without an index, outliers are not filtered
+/// unless requested.
+fn analyze_raw(
+    baseline: &Path,
+    candidate: &Path,
+    filter: &str,
+    mut settings: MeasurementSettings,
+    overrides: &AnalyzeOverrides,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    settings.outlier_detection = OutlierDetection::None;
+    overrides.apply(&mut settings)?;
+
+    let (id, measurements) = commands::read_device_measurements(baseline, candidate)?;
+    let name = id.to_string();
+    let mut summary = SuiteSummary::default();
+    if matches_filter(filter, &name) {
+        match measurements.analyze(&name, &settings) {
+            Some(mut result) => {
+                result.id = id;
+                result.unit = Unit::Count;
+                summary.add(&result);
+                if result.diff_estimate.significant || !overrides.significant_only {
+                    reporter.on_complete(&result);
+                }
+            }
+            None => reporter.on_warning(&name, "raw samples contain no measurements"),
+        }
+    }
+    reporter.on_finish(&PhaseTimings::default());
+    reporter.on_summary(&summary);
+    Ok(())
+}
+
+/// One line description of the run dumps are written by
+fn describe_dump_session(session: &DumpSession) -> String {
+    let mut line = format!(
+        "Dumps of the run started at {} (seed: {}",
+        session.started, session.master_seed
+    );
+    for (key, value) in &session.labels {
+        line.push_str(&format!(", {}: {}", key, value));
+    }
+    line.push(')');
+    if session.finished.is_none() {
+        line.push_str(", the run was not finished");
+    }
+    line
+}
+
+/// Compares each pair of targets in-process
+///
+/// This is an alternative entrypoint of the harness for A/B experiments within a single executable. It accepts
+/// a subset of the `compare` command arguments (eg. `-t`, `-s`, `-f`, `-v`).
+pub fn run_pairs(settings: MeasurementSettings, pairs: Vec<PairedTargets>) -> Result<ExitCode> {
+    run_pairs_from(env::args_os(), settings, pairs)
+}
+
+fn run_pairs_from<I, T>(
+    args: I,
+    mut settings: MeasurementSettings,
+    pairs: Vec<PairedTargets>,
+) -> Result<ExitCode>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let opts = PairOpts::parse_from(args);
+
+    mode::set_coloring_mode(coloring_mode(opts.color, env::var_os("NO_COLOR")));
+
+    settings.outlier_detection = opts.outliers.detection().unwrap_or(OutlierDetection::None);
+    opts.stats.apply(&mut settings)?;
+    settings.clock = Some(select_clock(settings.clock)?);
+
+    let filter = opts
+        .filter
+        .or(opts.substring.map(|s| substring_filter(&s)))
+        .unwrap_or_default();
+    let filter = filter.as_str();
+
+    let weights = pairs
+        .iter()
+        .any(|p| matches_filter(filter, &p.name) && p.candidate.weight() != 1.);
+    let reporter: Box<dyn Reporter> = if opts.report.ndjson_to_stdout()? {
+        Box::new(Silent)
+    } else if opts.verbose {
+        Box::new(VerboseReporter {
+            time_unit: opts.units,
+        })
+    } else {
+        Box::new(
+            ConsoleReporter::new(opts.units, opts.report.layout.name_width).with_weights(weights),
+        )
+    };
+    let mut reporter = opts.plugins.load(reporter)?;
+    if opts.report.markdown {
+        reporter = Box::new(MarkdownReporter::new(reporter, stdout(), opts.units));
+    }
+    reporter = opts.report.wrap(reporter)?;
+    let mut reporter: Box<dyn Reporter> = Box::new(Suite::new(reporter));
+
+    let loop_mode = create_loop_mode(opts.samples, opts.time)?;
+    // Both functions of each pair are compiled into the running executable
+    let result_cache = opts
+        .cache
+        .open(&env::current_exe()?, &settings, loop_mode, opts.seed);
+    // All the pairs are measured with the same master seed, the same way tests of `compare` are
+    let seed = opts.seed.unwrap_or_else(rand::random);
+    let mut totals = PhaseTimings::default();
+    let mut summary = SuiteSummary::default();
+    let mut exit_code = ExitCode::SUCCESS;
+
+    let environment = Environment::current();
+    let tests = pairs
+        .iter()
+        .map(|p| p.name.as_str())
+        .filter(|name| matches_filter(filter, name))
+        .collect();
+    reporter.on_session_start(&SessionInfo {
+        settings,
+        filter,
+        mode: SessionMode::Pairs,
+        master_seed: seed,
+        environment: environment.clone(),
+        tests,
+    });
+    let started = Instant::now();
+
+    for pair in pairs {
+        if !matches_filter(filter, &pair.name) {
+            continue;
+        }
+        let id = pair.id();
+        let fingerprints = (
+            pair.baseline.code_fingerprint(),
+            pair.candidate.code_fingerprint(),
+        );
+        if let (Some(baseline), Some(candidate)) = fingerprints {
+            if baseline == candidate {
+                reporter.on_warning(&pair.name, "baseline and candidate run the same code");
+            }
+        }
+        let replicas = match pair.replicas().filter(|_| opts.aa_test) {
+            Some([baseline, candidate]) => Some([
+                Spi::for_benchmarks(vec![baseline])?,
+                Spi::for_benchmarks(vec![candidate])?,
+            ]),
+            None if opts.aa_test => {
+                let message = "A/A comparisons are skipped, the targets are not created by PairedTargets::from_fn()";
+                reporter.on_warning(&pair.name, message);
+                None
+            }
+            None => None,
+        };
+        let baseline = Spi::for_benchmarks(vec![pair.baseline])?;
+        let candidate = Spi::for_benchmarks(vec![pair.candidate])?;
+
+        // Each side is compared against its second instance, so the comparison has its own state and input stream
+        // on both sides like the pair. Self-comparisons go first, so the verdict of the pair can be flagged
+        let mut runs = vec![];
+        if let Some([baseline_replica, candidate_replica]) = &replicas {
+            let (baseline_id, candidate_id) = (&id.baseline, &id.candidate);
+            let name = aa_test_name(&pair.name, "baseline");
+            runs.push((
+                name,
+                PairId::same(baseline_id.clone()),
+                &baseline,
+                baseline_replica,
+                true,
+            ));
+            let name = aa_test_name(&pair.name, "candidate");
+            runs.push((
+                name,
+                PairId::same(candidate_id.clone()),
+                candidate_replica,
+                &candidate,
+                true,
+            ));
+        }
+        runs.push((pair.name.clone(), id, &baseline, &candidate, false));
+
+        reporter.on_test_start(&pair.name);
+        let mut aa_results = vec![];
+        for (name, id, baseline, candidate, self_comparison) in runs {
+            let test = TestPair { name: &name, id };
+            let mut paired_test =
+                PairedTest::new(baseline, candidate, settings, Some(seed), loop_mode, None);
+            paired_test.show_progress(stderr().is_terminal());
+
+            // Outputs of a function compared against itself always match
+            if !self_comparison {
+                match paired_test.verify_pair(&test)? {
+                    Verification::Mismatch(mismatch) => {
+                        reporter.on_error(test.name, &mismatch.to_string());
+                        exit_code = ExitCode::FAILURE;
+                        continue;
+                    }
+                    Verification::Skipped(reason) => {
+                        let message = format!("outputs are not verified: {}", reason);
+                        reporter.on_warning(test.name, &message);
+                    }
+                    Verification::NotApplicable | Verification::Passed => {}
+                }
+            }
+
+            let cached = match &result_cache {
+                Some(result_cache) if !opts.cache.is_forced(test.name) => {
+                    result_cache.read(test.name)
+                }
+                _ => None,
+            };
+            let mut result = match cached {
+                Some(result) => result,
+                None => {
+                    let result = match paired_test.run_pair(&test, None) {
+                        Ok(result) => result,
+                        Err(e) => match skip_reason(&e) {
+                            Some(reason) => {
+                                reporter.on_skipped(test.name, reason);
+                                continue;
+                            }
+                            None => return Err(e),
+                        },
+                    };
+                    totals += result.phases;
+                    store_result(reporter.as_mut(), result_cache.as_ref(), &result);
+                    result
+                }
+            };
+            if self_comparison {
+                result.aa_of = Some(pair.name.as_str().into());
+            } else {
+                // Self-comparisons would skew the suite summary towards no change
+                summary.add(&result);
+                report_aa_test(reporter.as_mut(), &result, &aa_results);
+            }
+            report_estimate_overrun(reporter.as_mut(), &result, &settings);
+            report_order_bias(reporter.as_mut(), &result);
+            report_batch_curve(reporter.as_mut(), &result, &settings);
+            report_precision(reporter.as_mut(), &result, &settings);
+            report_timer_resolution(reporter.as_mut(), &result, environment.timer);
+            report_reservoir(reporter.as_mut(), &result, &settings);
+            report_overhead_variance(reporter.as_mut(), &result, &settings);
+            if self_comparison {
+                aa_results.push(result);
+            } else if result.diff_estimate.significant || !opts.significant_only {
+                reporter.on_complete(&result);
+            }
+        }
+        // Self-comparisons are reported right after the pair they belong to
+        for result in &aa_results {
+            if result.diff_estimate.significant || !opts.significant_only {
+                reporter.on_complete(result);
+            }
+        }
+    }
+
+    reporter.on_finish(&totals);
+    reporter.on_summary(&summary);
+    reporter.on_session_end(&SessionSummary {
+        phases: totals,
+        wall_time: started.elapsed(),
+        dumps: vec![],
+        sentinels: None,
+    });
+    Ok(exit_code)
+}
+
+/// Conditions a result fails the run on (`--fail-threshold` and `--fail-on-inconclusive`)
+struct VerdictChecks {
+    fail_threshold: Option<f64>,
+    fail_on_inconclusive: bool,
+    min_effect: f64,
+}
+
+impl VerdictChecks {
+    /// Descriptions of the conditions a result fails
+    ///
+    /// Verdicts made in a too noisy environment (see [`NoiseGate`]) are reported, but never fail the run.
+    fn failures(&self, result: &RunResult, gate: GateOutcome) -> Vec<String> {
+        let mut failures = vec![];
+        if !gate.enforces_verdicts() {
+            return failures;
+        }
+        let estimate = &result.diff_estimate;
+        if self.fail_on_inconclusive && estimate.verdict == Verdict::Inconclusive {
+            let delta = Delta {
+                pct: estimate.pct,
+                ci: estimate.ci,
+            };
+            failures.push(format!(
+                "Inconclusive result {} is wider than ±{:.1}%",
+                delta, self.min_effect
+            ));
+        }
+        if let (true, Some(threshold)) = (estimate.significant, self.fail_threshold) {
+            if estimate.pct >= threshold {
+                failures.push(format!(
+                    "Performance regressed {:+.1}% >= {:.1}%",
+                    estimate.pct, threshold
+                ));
+            }
+        }
+        failures
+    }
+}
+
+/// Measures the noise floor by comparing the reference function with itself (see [`NoiseGate`])
+///
+/// Each comparison is made with the same settings and the time limit as the tests of the suite.
+fn measure_noise_floor(
+    gate: &NoiseGate,
+    settings: MeasurementSettings,
+    loop_mode: LoopMode,
+    seed: Option<u64>,
+) -> Result<NoiseFloor> {
+    let baseline = Spi::for_benchmarks(vec![noise::reference_benchmark()])?;
+    let candidate = Spi::for_benchmarks(vec![noise::reference_benchmark()])?;
+    let diffs = (0..gate.runs as u64)
+        .map(|run| {
+            // Each run is measured on its own seed, so the runs are independent
+            let seed = seed.map(|s| s.wrapping_add(run));
+            let test = PairedTest::new(&baseline, &candidate, settings, seed, loop_mode, None);
+            test.run(noise::REFERENCE_NAME, None)
+                .map(|r| r.diff_estimate.pct)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    NoiseFloor::from_diffs(&diffs, gate.max).ok_or_else(|| Error::NoMeasurements.into())
+}
+
+/// Measures the noise floor and checks it against the gate (`--max-noise`), reporting a noisy environment
+fn check_noise_floor(
+    gate: &NoiseGate,
+    settings: MeasurementSettings,
+    loop_mode: LoopMode,
+    seed: Option<u64>,
+    quiet: bool,
+) -> Result<(NoiseFloor, GateOutcome)> {
+    let floor = measure_noise_floor(gate, settings, loop_mode, seed)?;
+    let outcome = gate.check(&floor);
+    if !quiet {
+        eprintln!("Noise floor {}", floor);
+    }
+    match outcome {
+        GateOutcome::Trusted => {}
+        GateOutcome::Abort => eprintln!(
+            "[ERROR] Environment is too noisy: noise floor of ±{:.2}% exceeds ±{:.2}%, skipping verdicts",
+            floor.pct, floor.max
+        ),
+        GateOutcome::Demoted => eprintln!(
+            "[WARN] Environment is too noisy: noise floor of ±{:.2}% exceeds ±{:.2}%, verdicts are informational",
+            floor.pct, floor.max
+        ),
+    }
+    Ok((floor, outcome))
+}
+
+/// Maximal time a sentinel pair is compared for, so the sentinels add at most a couple of seconds to the run
+const SENTINEL_TIME: Duration = Duration::from_millis(100);
+
+/// Sentinels only qualify the run, so if they fail the warning is issued and the run goes on without them
+fn sentinels_or_warn<T>(reporter: &mut dyn Reporter, sentinels: Result<T>) -> Option<T> {
+    sentinels
+        .map_err(|e| {
+            reporter.on_warning(
+                "sentinels",
+                &format!("noise floor is not measured: {:#}", e),
+            )
+        })
+        .ok()
+}
+
+/// Sentinel pairs compared in the slots of a [`SentinelSchedule`]
+///
+/// Sentinels are measured on their own functions and never reach the reporters, exports, cache or failure checks
+/// of the suite. Only their summary is reported at the end of the session.
+struct Sentinels {
+    baseline: Spi<'static>,
+    candidate: Spi<'static>,
+    schedule: SentinelSchedule,
+    settings: MeasurementSettings,
+    loop_mode: LoopMode,
+    seed: Option<u64>,
+    runs: Vec<SentinelRun>,
+}
+
+impl Sentinels {
+    fn new(
+        tests: usize,
+        settings: MeasurementSettings,
+        loop_mode: LoopMode,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        // Spin count is shared by both functions of a pair
+        let spins = noise::calibrate_spins(noise::SENTINEL_CALL_TIME);
+        // Options extending the sampling of the tests would exceed the time of the sentinels
+        let settings = MeasurementSettings {
+            exact_samples: None,
+            target_precision: None,
+            slow_test_policy: SlowTestPolicy::Force,
+            ..settings
+        };
+        let loop_mode = match loop_mode {
+            LoopMode::Time(duration) => LoopMode::Time(duration.min(SENTINEL_TIME)),
+            LoopMode::Samples(_) => LoopMode::Time(SENTINEL_TIME),
+        };
+        Ok(Self {
+            baseline: Spi::for_benchmarks(noise::sentinel_benchmarks(spins))?,
+            candidate: Spi::for_benchmarks(noise::sentinel_benchmarks(spins))?,
+            schedule: SentinelSchedule::new(tests),
+            settings,
+            loop_mode,
+            seed,
+            runs: vec![],
+        })
+    }
+
+    /// Compares the sentinel pairs of all the slots due before the test at a given position
+    fn run_due(&mut self, position: usize) -> Result<()> {
+        for slot in self.schedule.due(position) {
+            self.run_slot(slot)?;
+        }
+        Ok(())
+    }
+
+    fn run_slot(&mut self, slot: SentinelSlot) -> Result<()> {
+        for (name, _) in noise::SENTINELS {
+            // Each run is measured on its own seed, so the runs are independent
+            let seed = self.seed.map(|s| s.wrapping_add(self.runs.len() as u64));
+            let test = PairedTest::new(
+                &self.baseline,
+                &self.candidate,
+                self.settings,
+                seed,
+                self.loop_mode,
+                None,
+            );
+            let result = test.run(name, None)?;
+            self.runs.push(SentinelRun {
+                name,
+                slot,
+                pct: result.diff_estimate.pct,
+                significant: result.diff_estimate.significant,
+            });
+        }
+        Ok(())
+    }
+
+    /// Compares the sentinel pairs of the slots left (the end of the suite)
+    fn finish(mut self) -> Result<SentinelSummary> {
+        for slot in self.schedule.rest() {
+            self.run_slot(slot)?;
+        }
+        Ok(SentinelSummary { runs: self.runs })
+    }
+}
+
+/// Reports the warnings about the way a test was measured, which don't affect its verdict
+fn report_diagnostics(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+    timer: Option<TimerResolution>,
+) {
+    report_estimate_overrun(reporter, result, settings);
+    report_order_bias(reporter, result);
+    report_batch_curve(reporter, result, settings);
+    report_precision(reporter, result, settings);
+    report_timer_resolution(reporter, result, timer);
+    report_reservoir(reporter, result, settings);
+    report_overhead_variance(reporter, result, settings);
+
+    if result.generator_overhead() > settings.generator_overhead_threshold {
+        let message = format!(
+            "{:.0}% of the time was spent generating haystacks. Consider increasing samples_per_haystack",
+            result.generator_overhead() * 100.
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Warns if iterations of a test weren't estimated within the budget and a single iteration was sampled instead
+fn report_estimate_overrun(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+) {
+    if let (Some(elapsed), Some(budget)) = (result.estimate_overrun, settings.estimate_budget) {
+        let message = format!(
+            "estimation took {:.2}s exceeding the budget of {:.2}s, sampled with 1 iteration per sample",
+            elapsed.as_secs_f64(),
+            budget.as_secs_f64()
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Notes that quantiles and outliers of a test are estimated from a reservoir, because its samples didn't fit in
+/// memory
+fn report_reservoir(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+) {
+    if let Some(reservoir) = result.reservoir {
+        let message = format!(
+            "samples exceed the memory cap of {} MiB, quantiles and outliers are estimated from {} of {} samples (outliers are not filtered)",
+            settings.max_sample_memory >> 20,
+            reservoir,
+            result.schedule.samples
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Warns if the target precision of a test wasn't reached within the time cap
+fn report_precision(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+) {
+    if let (Some(precision), Some(target)) = (result.precision, settings.target_precision) {
+        if precision.stop == PrecisionStop::TimeCap {
+            let message = format!(
+                "target precision of ±{:.2}% is not reached in {:.1}s (achieved ±{:.2}% after {} samples)",
+                target,
+                settings.max_precision_time.as_secs_f64(),
+                precision.half_width,
+                result.schedule.samples
+            );
+            reporter.on_warning(&result.name, &message);
+        }
+    }
+}
+
+/// Per-iteration times closer than this factor to the timer resolution are reported
+const TIMER_RESOLUTION_FACTOR: f64 = 3.;
+
+/// Warns if per-iteration time of any function is comparable to the resolution of the timer, so the difference is
+/// dominated by quantization
+fn report_timer_resolution(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    timer: Option<TimerResolution>,
+) {
+    let Some(timer) = timer else {
+        return;
+    };
+    let fastest = result.baseline.mean.min(result.candidate.mean);
+    if result.unit == Unit::Nanoseconds
+        && fastest < timer.resolution as f64 * TIMER_RESOLUTION_FACTOR
+    {
+        let message = format!(
+            "per-iteration time of {:.1} ns is within {}× of the timer resolution of {} ns. Consider increasing the number of iterations per sample",
+            fastest, TIMER_RESOLUTION_FACTOR, timer.resolution
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Warns if per-iteration value of any function depends on the number of iterations in a sample
+fn report_batch_curve(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+) {
+    let Some(curves) = &result.schedule.batch_curve else {
+        return;
+    };
+    for (side, curve) in ["baseline", "candidate"].iter().zip(curves) {
+        let change = curve.change_pct();
+        if change.abs() > settings.batch_curve_threshold {
+            let message = format!(
+                "per-iteration time of the {} changes by {:+.1}% with the number of iterations per sample, consider fixing it with --iterations",
+                side, change
+            );
+            reporter.on_warning(&result.name, &message);
+        }
+    }
+}
+
+/// Warns if any of the self-comparisons of a pair came out significant (`--aa-test`), so the verdict of the pair
+/// can't be trusted
+fn report_aa_test(reporter: &mut dyn Reporter, result: &RunResult, aa_results: &[RunResult]) {
+    let significant = aa_results
+        .iter()
+        .filter(|r| r.diff_estimate.significant)
+        .map(|r| r.name.as_ref())
+        .collect::<Vec<_>>();
+    if !significant.is_empty() {
+        let message = format!(
+            "A/A comparison is significant ({}), differences of the same function are detected and the verdict is not trustworthy",
+            significant.join(", ")
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Loads a copy of an executable (`--aa-test`)
+///
+/// Loading the same path twice gives the same instance of the library, so the copy is what gives the tests their
+/// own state apart from the ones loaded from the original path. The copy is removed once it is loaded.
+fn load_replica(path: &Path, side: &str) -> Result<Library> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let replica = path.with_file_name(format!("{}-aa-{}", file_name, side));
+    fs::copy(path, &replica)
+        .with_context(|| format!("Unable to copy {} to {}", path.display(), replica.display()))?;
+    #[cfg(target_os = "linux")]
+    let patched = crate::linux::patch_pie_binary_if_needed(&replica)?;
+    #[cfg(not(target_os = "linux"))]
+    let patched = None::<PathBuf>;
+    let loaded = patched.as_deref().unwrap_or(&replica);
+    let library = unsafe { Library::new(loaded) };
+    for path in [Some(&replica), patched.as_ref()].into_iter().flatten() {
+        let _ = fs::remove_file(path);
+    }
+    library.with_context(|| format!("Unable to open library: {}", loaded.display()))
+}
+
+/// Name of the self-comparison of a given side of a pair (`--aa-test`)
+fn aa_test_name(name: &str, side: &str) -> String {
+    format!("{} (A/A {})", name, side)
+}
+
+/// Warns if the variance of the differences of a test is dominated by the overhead of the measurement, so the
+/// comparison is not trustworthy, or if the overhead wasn't measured although requested
+fn report_overhead_variance(
+    reporter: &mut dyn Reporter,
+    result: &RunResult,
+    settings: &MeasurementSettings,
+) {
+    if let Some(reason) = result.null_skipped {
+        let message = format!("--null-every is ignored, {}", reason);
+        reporter.on_warning(&result.name, &message);
+    }
+    let Some(overhead) = result.overhead_variance else {
+        return;
+    };
+    if overhead.overhead_fraction > settings.overhead_variance_threshold {
+        let message = format!(
+            "{:.0}% of the variance of the differences is the overhead of the timer and the sampling loop ({} samples of an empty function), the comparison is not trustworthy. Consider increasing the number of iterations per sample",
+            overhead.overhead_fraction * 100.,
+            overhead.samples
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Warns if the verdict of a test was downgraded because of the order bias
+fn report_order_bias(reporter: &mut dyn Reporter, result: &RunResult) {
+    if let (true, Some(bias)) = (result.diff_estimate.order_biased, &result.order_bias) {
+        let message = format!(
+            "result is inconclusive, the order of calls shifts the difference by {:.2}% which exceeds the measured effect of {:.2}%",
+            bias.pct.abs() / 2.,
+            result.diff_estimate.pct.abs()
+        );
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Caches the result of a test, warning if it couldn't be written
+fn store_result(reporter: &mut dyn Reporter, cache: Option<&ResultCache>, result: &RunResult) {
+    if let Some(cache) = cache {
+        if let Err(e) = cache.write(result) {
+            let message = format!("result is not cached: {}", e);
+            reporter.on_warning(&result.name, &message);
+        }
+    }
+}
+
+/// Measures a test in its own process (`--skip-on-timeout`, `--resource-limits`) or in turns with the other tests
+/// if it's scheduled so, with the background load of the test if any
+///
+/// The outer error stops the run, the inner one fails the test.
+fn measure_test(
+    paired_test: &PairedTest,
+    sliced: Option<&mut RoundRobin>,
+    isolation: Option<&Isolation>,
+    name: &str,
+    schedule: Option<&TestSchedule>,
+    load: Option<LoadSpec>,
+) -> Result<Result<RunResult>> {
+    let measured = match sliced {
+        Some(sliced) => sliced.take(name)?,
+        None => None,
+    };
+    Ok(measured.unwrap_or_else(|| {
+        let load = load.map(BackgroundLoad::start);
+        let result = match isolation {
+            Some(isolation) => isolation.run(name, schedule),
+            None => paired_test.run(name, schedule),
+        };
+        drop(load);
+        result
+    }))
+}
+
+/// Compares each side of a test with its replica (`--aa-test`), reporting the skipped comparisons
+fn run_aa_tests(
+    aa_tests: &[PairedTest; 2],
+    reporter: &mut dyn Reporter,
+    test_name: &str,
+    result: &RunResult,
+) -> Result<Vec<RunResult>> {
+    let mut aa_results = vec![];
+    for (aa_test, side) in aa_tests.iter().zip(["baseline", "candidate"]) {
+        let name = aa_test_name(&result.name, side);
+        match aa_test.run(test_name, None) {
+            Ok(mut aa_result) => {
+                aa_result.name = name.into();
+                aa_result.aa_of = Some(result.name.clone());
+                aa_results.push(aa_result);
+            }
+            Err(e) => match skip_reason(&e) {
+                Some(reason) => reporter.on_skipped(&name, reason),
+                None => return Err(e),
+            },
+        }
+    }
+    Ok(aa_results)
+}
+
+/// Returns the reason a test was skipped if the error is [`Error::TestSkipped`]
+fn skip_reason(error: &anyhow::Error) -> Option<&str> {
+    match error.downcast_ref::<Error>() {
+        Some(Error::TestSkipped(reason)) => Some(reason),
+        _ => None,
+    }
+}
+
+/// Measures the tests in turns of short time slices (`--schedule round-robin`)
+///
+/// Each round runs the next slice of every test having slices left, so a drift of the environment during the run is
+/// spread over all the tests instead of being absorbed by the ones measured during it. Results of the slices of a
+/// test are pooled. Haystacks and fixtures are created anew in each slice. Tests skipped in any of the slices are
+/// returned with the error and not measured further.
+///
+/// The first slices of all the tests are run upfront, the following rounds are run only until the requested test
+/// has no slices left, so the results can be reported as they are completed.
+struct RoundRobin<'t> {
+    paired_test: &'t PairedTest<'t>,
+    slice: Duration,
+    load: Option<LoadSpec>,
+    running: Vec<Sliced>,
+    finished: HashMap<String, Result<RunResult>>,
+    /// Index of the slice run by the next round
+    index: usize,
+}
+
+struct Sliced {
+    name: String,
+    first: RunResult,
+    pooled: RunResult,
+    slices: usize,
+}
+
+impl<'t> RoundRobin<'t> {
+    /// Runs the first slices of `tests`, calling `on_start` before each of them
+    fn start(
+        paired_test: &'t PairedTest<'t>,
+        tests: &[&str],
+        slice: Duration,
+        load: Option<LoadSpec>,
+        mut on_start: impl FnMut(&str),
+    ) -> Result<Self> {
+        let mut round_robin = Self {
+            paired_test,
+            slice,
+            load,
+            running: vec![],
+            finished: HashMap::new(),
+            index: 1,
+        };
+        for &name in tests {
+            on_start(name);
+            match round_robin.run(name, None, 0) {
+                Ok(first) => round_robin.running.push(Sliced {
+                    name: name.to_string(),
+                    slices: paired_test.slices(&first, slice),
+                    pooled: first.clone(),
+                    first,
+                }),
+                Err(e) if skip_reason(&e).is_some() => {
+                    round_robin.finished.insert(name.to_string(), Err(e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(round_robin)
+    }
+
+    /// Whether the test is measured in turns and is not taken yet
+    fn contains(&self, name: &str) -> bool {
+        self.finished.contains_key(name) || self.running.iter().any(|t| t.name == name)
+    }
+
+    /// Runs the rounds until the test has no slices left and takes its result
+    ///
+    /// Returns `None` if the test is not measured in turns.
+    fn take(&mut self, name: &str) -> Result<Option<Result<RunResult>>> {
+        while !self.finished.contains_key(name) && self.running.iter().any(|t| t.name == name) {
+            self.round()?;
+        }
+        Ok(self.finished.remove(name))
+    }
+
+    fn round(&mut self) -> Result<()> {
+        let mut i = 0;
+        while i < self.running.len() {
+            if self.index >= self.running[i].slices {
+                let test = self.running.remove(i);
+                self.finished.insert(test.name, Ok(test.pooled));
+                continue;
+            }
+            let test = &self.running[i];
+            match self.run(&test.name, Some(&test.first), self.index) {
+                Ok(result) => {
+                    let test = &mut self.running[i];
+                    test.pooled = self.paired_test.pool_slices(&test.pooled, &result);
+                }
+                Err(e) if skip_reason(&e).is_some() => {
+                    let test = self.running.remove(i);
+                    self.finished.insert(test.name, Err(e));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            i += 1;
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    fn run(&self, name: &str, first: Option<&RunResult>, index: usize) -> Result<RunResult> {
+        let load = self.load.map(BackgroundLoad::start);
+        let result = self.paired_test.run_slice(name, self.slice, first, index);
+        drop(load);
+        result
+    }
+}
+
+/// Name the master seed of the confirmation runs is derived from (see [`PairedTest::confirmation()`])
+const CONFIRMATION_SEED: &str = "confirmation";
+
+/// Indices of at most `top` significant results, most significant first
+///
+/// Results are ranked by the difference relative to the half-width of its confidence interval, so a small but
+/// precisely measured difference outranks a large but noisy one.
+fn most_significant(results: &[RunResult], top: usize) -> Vec<usize> {
+    let score = |result: &RunResult| {
+        let estimate = &result.diff_estimate;
+        let half_width = (estimate.ci.upper - estimate.ci.lower) / 2.;
+        estimate.pct.abs() / half_width.max(f64::EPSILON)
+    };
+    let mut significant = (0..results.len())
+        .filter(|&i| results[i].diff_estimate.significant)
+        .collect::<Vec<_>>();
+    significant.sort_by(|&a, &b| score(&results[b]).total_cmp(&score(&results[a])));
+    significant.truncate(top);
+    significant
+}
+
+/// Re-measures `top` most significant results and replaces them with the results pooled from both runs
+///
+/// A result is confirmed if the second run finds a significant difference in the same direction. `rerun` returns
+/// `None` if the test is skipped on the second run, such results are kept as they are and marked unconfirmed.
+fn confirm_results(
+    results: &mut [RunResult],
+    top: usize,
+    settings: &MeasurementSettings,
+    mut rerun: impl FnMut(&RunResult) -> Result<Option<RunResult>>,
+) -> Result<()> {
+    for i in most_significant(results, top) {
+        let first = &results[i];
+        let Some(second) = rerun(first)? else {
+            results[i].confirmation = Some(Confirmation::Unconfirmed);
+            continue;
+        };
+        let (a, b) = (&first.diff_estimate, &second.diff_estimate);
+        let confirmation = if b.significant && (a.pct > 0.) == (b.pct > 0.) {
+            Confirmation::Confirmed
+        } else {
+            Confirmation::Unconfirmed
+        };
+        let mut pooled = pooled_run_result(first, &second, settings);
+        pooled.confirmation = Some(confirmation);
+        results[i] = pooled;
+    }
+    Ok(())
+}
+
+/// Collects the dump of a test measurements, warning if it couldn't be written
+fn collect_dump(reporter: &mut dyn Reporter, result: &RunResult, dumps: &mut Vec<PathBuf>) {
+    match &result.dump {
+        Some(Ok(path)) => dumps.push(path.clone()),
+        Some(Err(e)) => {
+            let message = format!("measurements are not dumped: {}", e);
+            reporter.on_warning(&result.name, &message);
+        }
+        None => {}
+    }
+}
+
+/// Records the dump of a test in the index of the dump directory, warning if the index couldn't be written
+fn index_dump(
+    reporter: &mut dyn Reporter,
+    index: Option<&mut (PathBuf, DumpIndex)>,
+    result: &RunResult,
+) {
+    let (Some((dir, index)), Some(Ok(path))) = (index, &result.dump) else {
+        return;
+    };
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let record = DumpRecord {
+        file: file.into_owned(),
+        test: result.name.to_string(),
+        display_name: reporting::display_name(result).into_owned(),
+        samples: result.diff.n + result.outliers,
+        verdict: result.diff_estimate.verdict,
+        outliers: result.outliers,
+    };
+    if let Err(e) = index.append(dir, record) {
+        let message = format!("dump index is not written: {}", e);
+        reporter.on_warning(&result.name, &message);
+    }
+}
+
+/// Environment variable with the path to the baseline used when the harness is invoked with a bare filter
+const AGAINST_VAR: &str = "TANGO_AGAINST";
+
+/// Parses the arguments of the harness
+///
+/// Returns `None` if the arguments are not supported by the harness and it's `tolerant` (see
+/// [`MainOptions::tolerant`]) or `--tolerant` is given. Only unknown subcommands and flags are tolerated, invalid
+/// values of the known flags are still reported.
+fn parse_opts(args: Vec<OsString>, tolerant: bool) -> StdResult<Option<Opts>, clap::Error> {
+    let tolerant = tolerant || args.iter().any(|a| a == "--tolerant");
+    match Opts::try_parse_from(args) {
+        Ok(opts) => Ok(Some(opts)),
+        Err(e)
+            if tolerant
+                && matches!(
+                    e.kind(),
+                    ErrorKind::UnknownArgument | ErrorKind::InvalidSubcommand
+                ) =>
+        {
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            let message = message.strip_prefix("error: ").unwrap_or(message);
+            eprintln!(
+                "[WARN] Arguments are not supported by tango, nothing is run: {}",
+                message
+            );
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Emulates `compare` being the default subcommand when the first positional argument is not a subcommand
+///
+/// `cargo bench -- sort -t 1` is rewritten as `cargo bench -- compare [$TANGO_AGAINST] -f '*sort*' -t 1`. Without
+/// any positional arguments `compare [$TANGO_AGAINST]` is inserted if `default_mode` is [`DefaultMode::Compare`].
+fn with_default_subcommand<I, T>(
+    args: I,
+    against: Option<OsString>,
+    default_mode: DefaultMode,
+) -> Vec<OsString>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+    let command = Opts::command();
+
+    // Skipping executable name and top-level options
+    let mut i = 1;
+    while let Some(arg) = args.get(i).and_then(|a| a.to_str()) {
+        if arg == "--color" {
+            i += 2;
+        } else if arg.starts_with('-') {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    let Some(positional) = args.get(i) else {
+        if default_mode == DefaultMode::Compare {
+            let mut replacement = vec![OsString::from("compare")];
+            replacement.extend(against);
+            args.splice(i..i, replacement);
+        }
+        return args;
+    };
+    if command.find_subcommand(positional).is_some() {
+        return args;
+    }
+
+    let filter = substring_filter(&positional.to_string_lossy());
+    let mut replacement = vec![OsString::from("compare")];
+    replacement.extend(against);
+    replacement.extend(["-f".into(), filter.into()]);
+    args.splice(i..=i, replacement);
+    args
+}
+
+/// Glob matching names containing a given string. Glob patterns are used as is
+fn substring_filter(filter: &str) -> String {
+    if filter.contains(['*', '?', '[', '{']) {
+        filter.to_string()
+    } else {
+        format!("*{}*", filter)
+    }
+}
+
+/// Checks if both paths refer to the same file or to the files with the same content
+fn is_same_binary(a: &Path, b: &Path) -> io::Result<bool> {
+    if a.canonicalize()? == b.canonicalize()? {
+        return Ok(true);
+    }
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (
+        BufReader::new(File::open(a)?),
+        BufReader::new(File::open(b)?),
+    );
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(true);
+        }
+        let len = chunk.len().min(b.fill_buf()?.len());
+        if len == 0 || chunk[..len] != b.buffer()[..len] {
+            return Ok(false);
+        }
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+/// The harness is not run with the environment `--generator-replay` and `--loop-replay` are passed in
+fn needs_replay_env(replay: Option<&Path>, looping: bool) -> bool {
+    let replay_set = env::var_os(generators::REPLAY_VAR).as_deref() == replay.map(Path::as_os_str);
+    let looping_set = env::var_os(generators::LOOP_REPLAY_VAR).is_some() == looping;
+    !(replay_set && looping_set)
+}
+
+/// Runs the harness again in a child process with `--generator-replay` and `--loop-replay` passed in the
+/// environment
+///
+/// Generators are created when the benchmarks are built and the benchmarks of this executable are built before the
+/// command line is parsed (see [`crate::tango_main!`]), so only a new process can replay the recording in both
+/// executables compared.
+fn rerun_with_replay_env(replay: Option<&Path>, looping: bool) -> Result<ExitCode> {
+    let mut command = Command::new(env::current_exe()?);
+    command.args(env::args_os().skip(1));
+    match replay {
+        Some(path) => command.env(generators::REPLAY_VAR, path),
+        None => command.env_remove(generators::REPLAY_VAR),
+    };
+    if looping {
+        command.env(generators::LOOP_REPLAY_VAR, "1");
+    } else {
+        command.env_remove(generators::LOOP_REPLAY_VAR);
+    }
+    let status = command.status().context("Unable to start child process")?;
+    Ok(match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::FAILURE,
+    })
+}
+
+/// Benchmarks registered in this executable with their profiles resolved against given definitions
+fn registered_tests(max_name_len: usize, profiles: &[(String, Profile)]) -> Result<Spi<'static>> {
+    let mut spi_self = Spi::for_self(max_name_len).ok_or(Error::SpiSelfWasMoved)??;
+    spi_self.resolve_profiles(profiles);
+    if spi_self.tests().is_empty() {
+        return Err(Error::NoBenchmarksRegistered.into());
+    }
+    Ok(spi_self)
+}
+
+/// Checks that the library was built with the same measurement settings and pushes effective host settings to it
+///
+/// Settings given to `tango_main!()` are compared (`built`), so command line overrides are not reported. The harness
+/// measures both functions using the host settings, so a mismatch is only reported. In strict mode it is an error.
+fn reconcile_settings(
+    built: &MeasurementSettings,
+    effective: &MeasurementSettings,
+    spi: &Spi,
+    strict: bool,
+) -> Result<()> {
+    let library_settings = match spi.settings() {
+        Some(Ok(settings)) => Some(settings),
+        Some(Err(e)) => {
+            eprintln!(
+                "[WARN] measurement settings of the library can't be read, the settings of the host are used: {}",
+                e
+            );
+            None
+        }
+        None => None,
+    };
+    if let Some(library_settings) = library_settings {
+        let diff = settings_diff(built, &library_settings);
+        if !diff.is_empty() {
+            let mut message =
+                "library was built with different measurement settings (host / library):"
+                    .to_string();
+            for (field, host, library) in diff {
+                message += &format!("\n    {}: {} / {}", field, host, library);
+            }
+            if strict {
+                bail!(message);
+            }
+            eprintln!("[WARN] {}", message);
+        }
+    }
+    spi.push_settings(effective);
+    Ok(())
+}
+
+/// Makes the measurements of this process use a given clock source returning the selected one
+///
+/// The finest available source is chosen if none is given (see [`crate::probe_clocks()`]). Fails if the source is
+/// not available on this machine.
+fn select_clock(clock: Option<ClockSource>) -> Result<ClockSource> {
+    match clock {
+        Some(clock) if !timer::select(clock) => {
+            bail!("Clock source {} is not available on this machine", clock)
+        }
+        Some(clock) => Ok(clock),
+        None => Ok(timer::selected()),
+    }
+}
+
+/// Lists measurement settings which differ as `(field, a value, b value)`
+fn settings_diff(
+    a: &MeasurementSettings,
+    b: &MeasurementSettings,
+) -> Vec<(String, String, String)> {
+    let (Ok(Value::Object(a)), Ok(Value::Object(b))) = (to_value(a), to_value(b)) else {
+        return vec![];
+    };
+    a.into_iter()
+        .filter_map(|(field, a_value)| {
+            let b_value = b.get(&field)?;
+            (a_value != *b_value).then(|| (field, a_value.to_string(), b_value.to_string()))
+        })
+        .collect()
+}
+
+/// Checks if the test name matches the filter (glob pattern). Empty filter matches all the tests
+fn matches_filter(filter: &str, name: &str) -> bool {
+    filter.is_empty() || glob_match(filter, name)
+}
+
+/// Compares the registered tests with a registry snapshot file or rewrites the file if `update` is set
+fn verify_registry_snapshot(spi: &Spi, path: &Path, update: bool) -> Result<ExitCode> {
+    let current = RegistrySnapshot::of_spi(spi);
+    if update {
+        current
+            .write(path)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+        println!(
+            "Registry snapshot of {} tests written to {}",
+            current.entries().len(),
+            path.display()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let expected = RegistrySnapshot::read(path)
+        .with_context(|| format!("Unable to read registry snapshot {}", path.display()))?;
+    let diff = expected.diff(&current);
+    if diff.is_empty() {
+        println!(
+            "Registry matches {} ({} tests)",
+            path.display(),
+            current.entries().len()
+        );
+        Ok(ExitCode::SUCCESS)
+    } else {
+        eprint!(
+            "[ERROR] Registry doesn't match {} (use --update-registry-snapshot to accept the changes):\n{}",
+            path.display(),
+            diff
+        );
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Explains the empty selection: the filter, number of registered tests and a few of their names
+fn no_matching_tests(filter: &str, spi: &Spi) -> String {
+    const EXAMPLES: usize = 3;
+
+    let tests = spi.tests();
+    let examples = tests
+        .iter()
+        .take(EXAMPLES)
+        .map(|f| f.name.as_ref())
+        .collect::<Vec<_>>();
+    let ellipsis = if tests.len() > EXAMPLES { ", ..." } else { "" };
+    format!(
+        "[ERROR] No tests match filter `{}` ({} tests registered: {}{})",
+        filter,
+        tests.len(),
+        examples.join(", "),
+        ellipsis
+    )
+}
+
+/// Value of `--cooldown` argument
+#[derive(Clone, Copy, Debug)]
+enum CooldownArg {
+    Fixed(Duration),
+    Auto,
+}
+
+/// Maximum pause of `--cooldown auto` unless the limit is given in the settings
+const MAX_ADAPTIVE_COOLDOWN: Duration = Duration::from_secs(30);
+
+fn parse_cooldown(value: &str) -> std::result::Result<CooldownArg, String> {
+    if value == "auto" {
+        return Ok(CooldownArg::Auto);
+    }
+    value
+        .parse()
+        .map(|ms| CooldownArg::Fixed(Duration::from_millis(ms)))
+        .map_err(|_| format!("expected milliseconds or `auto`: {}", value))
+}
+
+/// Loads the executable to compare against, patching it first if it can't be loaded as is
+fn load_baseline(path: &Path) -> Result<Library> {
+    #[cfg(target_os = "linux")]
+    crate::linux::check_loadable(path)
+        .with_context(|| format!("Unable to load baseline: {}", path.display()))?;
+    #[cfg(target_os = "linux")]
+    let patched = crate::linux::patch_pie_binary_if_needed(path)
+        .with_context(|| format!("Unable to patch baseline: {}", path.display()))?;
+    #[cfg(not(target_os = "linux"))]
+    let patched = None::<PathBuf>;
+    let path = patched.as_deref().unwrap_or(path);
+    unsafe { Library::new(path) }
+        .with_context(|| format!("Unable to open library: {}", path.display()))
+}
+
+/// Tests registered in the baseline loaded by [`load_baseline()`]
+fn baseline_tests<'l>(lib: &'l Library, path: &Path, max_name_len: usize) -> Result<Spi<'l>> {
+    Spi::for_library(lib, max_name_len)
+        .with_context(|| format!("Unable to read tests of baseline: {}", path.display()))
+}
+
+/// Wraps the reporter so all the results are also written as a JSON array to a given file (`--json`)
+fn json_reporter(reporter: Box<dyn Reporter>, path: PathBuf) -> Result<Box<dyn Reporter>> {
+    let json = JsonReporter::create(reporter, path.clone())
+        .with_context(|| format!("Unable to create {}", path.display()))?;
+    Ok(Box::new(json))
+}
+
+/// Wraps the reporter so a summary of each result is also written as a CSV row to a given file (`--csv`)
+fn csv_reporter(reporter: Box<dyn Reporter>, path: PathBuf) -> Result<Box<dyn Reporter>> {
+    let csv = File::create(&path)
+        .and_then(|file| CsvReporter::new(reporter, BufWriter::new(file)))
+        .with_context(|| format!("Unable to create {}", path.display()))?;
+    Ok(Box::new(csv))
+}
+
+/// Wraps the reporter so all the tests are also written as a JUnit XML document to a given file (`--junit`)
+fn junit_reporter(reporter: Box<dyn Reporter>, path: PathBuf) -> Result<Box<dyn Reporter>> {
+    let file =
+        File::create(&path).with_context(|| format!("Unable to create {}", path.display()))?;
+    Ok(Box::new(JunitReporter::new(reporter, BufWriter::new(file))))
+}
+
+/// Whether `--ndjson` streams to stdout, in which case the console output is left out, so the stream can be parsed
+fn ndjson_to_stdout(ndjson: Option<&Path>, markdown: bool) -> Result<bool> {
+    let to_stdout = ndjson.is_some_and(|path| path.as_os_str() == "-");
+    if to_stdout && markdown {
+        bail!("--markdown can't be combined with --ndjson -, both are written to stdout");
+    }
+    Ok(to_stdout)
+}
+
+/// Wraps the reporter so each result is also streamed as a line of JSON to a given file or stdout (`--ndjson`)
+fn ndjson_reporter(reporter: Box<dyn Reporter>, path: PathBuf) -> Result<Box<dyn Reporter>> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(NdjsonReporter::new(reporter, stdout())));
+    }
+    let file =
+        File::create(&path).with_context(|| format!("Unable to create {}", path.display()))?;
+    Ok(Box::new(NdjsonReporter::new(
+        reporter,
+        BufWriter::new(file),
+    )))
+}
+
+/// Accepts the versions of the statistics known to this release (see [`MeasurementSettings::stats_version`])
+fn parse_stats_version(value: &str) -> std::result::Result<u32, String> {
+    let version = value.parse::<u32>().map_err(|e| e.to_string())?;
+    if (1..=stats::STATS_VERSION).contains(&version) {
+        Ok(version)
+    } else {
+        Err(format!("expected 1..={}", stats::STATS_VERSION))
+    }
+}
+
+/// Fails if the statistics of a given version are unknown to this release (eg. the version is written by a newer
+/// release)
+fn check_stats_version(settings: &MeasurementSettings) -> Result<()> {
+    if !(1..=stats::STATS_VERSION).contains(&settings.stats_version) {
+        bail!(
+            "Statistics version {} is not supported (expected 1..={})",
+            settings.stats_version,
+            stats::STATS_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Parses a non-negative number of seconds (eg. `0.5`)
+fn parse_seconds(value: &str) -> std::result::Result<Duration, String> {
+    let seconds = value
+        .parse::<f64>()
+        .map_err(|_| format!("expected number of seconds: {}", value))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration {}: {}", value, e))
+}
+
+/// Parses a `key=value` pair (label of the run or an option of the reporter plugins)
+fn parse_label(value: &str) -> std::result::Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value: {}", value)),
+    }
+}
+
+/// Parses background load given as comma separated `key=value` pairs (eg. `threads=2,duty=0.3`)
+fn parse_load(value: &str) -> std::result::Result<LoadSpec, String> {
+    let mut threads = None;
+    let mut duty = None;
+    let mut load = LoadSpec::new(0, 0.);
+    for pair in value.split(',') {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value: {}", pair))?;
+        let invalid = || format!("invalid {}: {}", key.trim(), value);
+        match key.trim() {
+            "threads" => threads = Some(value.trim().parse().map_err(|_| invalid())?),
+            "duty" => match value.trim().parse::<f64>() {
+                Ok(v) if (0. ..=1.).contains(&v) => duty = Some(v),
+                _ => return Err(format!("invalid duty: {} (expected 0-1)", value)),
+            },
+            "period" => match value.trim().parse::<u64>() {
+                Ok(ms) if ms > 0 => load.period = Duration::from_millis(ms),
+                _ => return Err(invalid()),
+            },
+            "scope" => {
+                load.scope = match value.trim() {
+                    "session" => LoadScope::Session,
+                    "test" => LoadScope::Test,
+                    _ => {
+                        return Err(format!(
+                            "invalid scope: {} (expected session or test)",
+                            value
+                        ))
+                    }
+                }
+            }
+            key => return Err(format!("unknown key: {}", key)),
+        }
+    }
+    match (threads, duty) {
+        (Some(threads), Some(duty)) => Ok(LoadSpec {
+            threads,
+            duty,
+            ..load
+        }),
+        _ => Err("threads and duty are required (eg. threads=2,duty=0.3)".to_string()),
+    }
+}
+
+/// Parses non-negative percentage with an optional `%` sign (eg. `0.5%`)
+fn parse_percent(value: &str) -> std::result::Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(pct) if pct >= 0. && pct.is_finite() => Ok(pct),
+        _ => Err(format!("invalid percentage: {}", value)),
+    }
+}
+
+fn parse_shard(value: &str) -> std::result::Result<Shard, String> {
+    value
+        .split_once('/')
+        .and_then(|(index, count)| Shard::new(index.parse().ok()?, count.parse().ok()?))
+        .ok_or_else(|| format!("invalid shard: {} (expected INDEX/COUNT, eg. 2/4)", value))
+}
+
+fn parse_trim(value: &str) -> std::result::Result<OutlierDetection, String> {
+    let parse = |pct: &str| match pct.trim().parse::<f64>() {
+        Ok(pct) if (0. ..50.).contains(&pct) => Ok(pct),
+        _ => Err(format!("invalid percentage: {} (expected 0-50)", pct)),
+    };
+    let (lower, upper) = value
+        .split_once(',')
+        .ok_or("expected lower and upper percentages (eg. 1,1)")?;
+    Ok(OutlierDetection::Trim {
+        lower_pct: parse(lower)?,
+        upper_pct: parse(upper)?,
+    })
+}
+
+/// Profile of a test as shown by `list` (eg. `io: min_effect=5%`)
+fn profile_label(name: &str, profile: &TestProfile) -> String {
+    if profile.undefined {
+        format!("{}: undefined", name)
+    } else if profile.tolerance.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}: {}", name, profile.tolerance)
+    }
+}
+
+fn parse_profile_override(value: &str) -> std::result::Result<ProfileOverride, String> {
+    let (profile, settings) = value
+        .split_once(':')
+        .ok_or("expected profile name and settings (eg. io:min_effect=2%)")?;
+    if profile.is_empty() {
+        return Err("profile name is empty".into());
+    }
+    let percent = |value: &str| {
+        let value = value.trim();
+        match value.strip_suffix('%') {
+            Some(pct) => pct.parse::<f64>().map(|pct| pct / 100.),
+            None => value.parse::<f64>(),
+        }
+        .ok()
+    };
+    let mut overrides = Profile::EMPTY;
+    // Values of `trim` contain a comma, so settings are split at the commas followed by a key
+    let mut pairs: Vec<String> = vec![];
+    for part in settings.split(',') {
+        match pairs.last_mut() {
+            Some(last) if !part.contains('=') => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => pairs.push(part.to_string()),
+        }
+    }
+    for pair in &pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value: {}", pair))?;
+        let invalid = || format!("invalid value of {}: {}", key, value);
+        match key.trim() {
+            "significance" => {
+                let significance = percent(value).filter(|s| *s > 0. && *s < 1.);
+                overrides.significance = Some(significance.ok_or_else(invalid)?);
+            }
+            "min_effect" => {
+                let min_effect = value.trim().trim_end_matches('%').parse::<f64>();
+                let min_effect = min_effect.ok().filter(|e| *e >= 0.);
+                overrides.min_effect = Some(min_effect.ok_or_else(invalid)?);
+            }
+            "min_samples" => {
+                overrides.min_samples = Some(value.trim().parse().map_err(|_| invalid())?);
+            }
+            "outlier" => {
+                overrides.outlier = Some(match value.trim() {
+                    "none" => OutlierDetection::None,
+                    "iqr" => OutlierDetection::Iqr,
+                    trim => match trim.strip_prefix("trim:") {
+                        Some(pcts) => parse_trim(pcts)?,
+                        None => return Err(invalid()),
+                    },
+                });
+            }
+            key => return Err(format!("unknown profile setting: {}", key)),
+        }
+    }
+    Ok(ProfileOverride {
+        profile: profile.to_string(),
+        settings: overrides,
+    })
+}
+
+fn create_loop_mode(samples: Option<NonZeroUsize>, time: Option<f64>) -> Result<LoopMode> {
+    let loop_mode = match (samples, time) {
+        (Some(samples), None) => LoopMode::Samples(samples.into()),
+        (None, Some(time)) => LoopMode::Time(Duration::from_millis((time * 1000.) as u64)),
+        (None, None) => LoopMode::Time(Duration::from_millis(100)),
+        (Some(_), Some(_)) => bail!("-t and -s are mutually exclusive"),
+    };
+    Ok(loop_mode)
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum LoopMode {
+    Samples(usize),
+    Time(Duration),
+}
+
+/// When the output is colored (`--color`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum ColorChoice {
+    /// Colored if stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    #[value(alias = "detect")]
+    Auto,
+
+    Always,
+
+    Never,
+}
+
+/// Coloring mode of the output for a given choice and the value of `NO_COLOR` environment variable
+///
+/// Any non-empty value of `NO_COLOR` disables colors unless they are forced with `--color always`
+/// (see <https://no-color.org>).
+fn coloring_mode(choice: ColorChoice, no_color: Option<OsString>) -> Mode {
+    match choice {
+        ColorChoice::Auto if no_color.is_some_and(|v| !v.is_empty()) => Mode::Never,
+        ColorChoice::Auto => Mode::Detect,
+        ColorChoice::Always => Mode::Always,
+        ColorChoice::Never => Mode::Never,
+    }
+}
+
+/// Order the tests of a suite are measured in (`--schedule`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum TestOrder {
+    /// Each test is measured to completion before the next one
+    #[default]
+    Sequential,
+
+    /// Tests are measured in turns of short time slices (see [`RoundRobin`])
+    RoundRobin,
+}
+
+impl LoopMode {
+    /// Loop taking twice as many samples or running twice as long
+    fn doubled(self) -> Self {
+        match self {
+            LoopMode::Samples(samples) => LoopMode::Samples(samples * 2),
+            LoopMode::Time(duration) => LoopMode::Time(duration * 2),
+        }
+    }
+
+    /// Loop running no longer than a given time (loops with the fixed number of samples are not changed)
+    fn capped(self, max: Duration) -> Self {
+        match self {
+            LoopMode::Samples(samples) => LoopMode::Samples(samples),
+            LoopMode::Time(duration) => LoopMode::Time(duration.min(max)),
+        }
+    }
+
+    fn should_continue(&self, iter_no: usize, start_time: Instant) -> bool {
+        match self {
+            LoopMode::Samples(samples) => iter_no < *samples,
+            LoopMode::Time(duration) => {
+                // Trying not to stress benchmarking loop with to much of clock calls and check deadline
+                // approximately each 8 milliseconds based on the number of iterations already performed
+                // (we're assuming each iteration is approximately 1 ms)
+                if (iter_no & 0b111) == 0 {
+                    Instant::now() < (start_time + *duration)
+                } else {
+                    true
+                }
+            }
+        }
+    }
+}
+
+mod commands {
+    use super::{status, watchdog::Watchdog, *};
+    use crate::{
+        calculate_downsampled_run_result, calculate_run_result_raw, calculate_streaming_run_result,
+        dylib::NamedFunction, id::PairId, manifest::TestSchedule, overhead_variance,
+        profile::ProfileOverride, stats, AchievedPrecision, CacheFirewall, CostHints, FlatSampler,
+        LinearSampler, ObservedSummary, ObservedValue, PairedSamples, PhaseTimings, PrecisionStop,
+        RandomSampler, RngFactory, RunResult, SampleOrder, Sampler, SamplerType, Side,
+        StreamingSamples, TailLatencies, Throughput, ThroughputRates, Unit,
+    };
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        fs::{self, File, OpenOptions},
+        io::{self, BufWriter},
+        mem,
+        path::Path,
+        process,
+        time::Instant,
+    };
+    use tango_core::{planner, raw::RawFile};
+
+    /// Functions of a paired test with the resources acquired (see [`MeasureTarget::prepare()`])
+    ///
+    /// Resources are released when dropped, so the fixtures don't outlive a test aborted by an error.
+    struct PreparedPair<'a>([(&'a Spi<'a>, &'a NamedFunction); 2]);
+
+    impl<'a> PreparedPair<'a> {
+        fn new(sides: [(&'a Spi<'a>, &'a NamedFunction); 2]) -> Self {
+            for (spi, func) in sides {
+                spi.prepare(func);
+            }
+            Self(sides)
+        }
+    }
+
+    impl Drop for PreparedPair<'_> {
+        fn drop(&mut self) {
+            for (spi, func) in self.0 {
+                spi.teardown(func);
+            }
+        }
+    }
+
+    struct TestedFunction<'a> {
+        spi: &'a Spi<'a>,
+        func: &'a NamedFunction,
+        side: Side,
+        samples: Vec<u64>,
+
+        /// Wall time spent taking samples of the function
+        measure_time: Duration,
+    }
+
+    impl<'a> TestedFunction<'a> {
+        fn new(spi: &'a Spi<'a>, func: &'a NamedFunction, side: Side) -> Self {
+            TestedFunction {
+                spi,
+                func,
+                side,
+                samples: Vec::new(),
+                measure_time: Duration::ZERO,
+            }
+        }
+
+        fn run(&mut self, iterations: usize) {
+            let sample = self.spi.run(self.func, iterations);
+            self.samples.push(sample);
+        }
+
+        /// Removes the last sample taken
+        fn take_sample(&mut self) -> u64 {
+            self.samples.pop().expect("No samples taken")
+        }
+
+        /// Runs the function notifying observers before and after the sample
+        ///
+        /// Values attached by observers are appended to `observed` along with the number of the sample.
+        fn run_observed(
+            &mut self,
+            sample_no: usize,
+            iterations: usize,
+            observers: &mut [Box<dyn SampleObserver>],
+            observed: &mut Vec<(usize, Side, ObservedValue)>,
+        ) {
+            let test = self.func.name.as_str();
+            for observer in observers.iter_mut() {
+                observer.before_sample(test, self.side, iterations);
+            }
+            self.run(iterations);
+            let sample = *self.samples.last().unwrap();
+            for observer in observers.iter_mut() {
+                observer.after_sample(test, self.side, sample);
+                let values = observer.sample_values();
+                observed.extend(values.into_iter().map(|v| (sample_no, self.side, v)));
+            }
+        }
+
+        /// Runs the function without recording a sample
+        fn warm_up(&mut self, iterations: usize) {
+            self.spi.run(self.func, iterations);
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            self.spi.next_haystack(self.func)
+        }
+
+        fn mutate_haystack(&mut self) {
+            self.spi.mutate_haystack(self.func)
+        }
+
+        /// Fails if the inputs of the function failed since the last check (see [`Spi::take_error()`])
+        fn check_inputs(&self) -> Result<()> {
+            match self.spi.take_error(self.func) {
+                Some(error) => bail!("Inputs of {} failed: {}", self.func.name, error),
+                None => Ok(()),
+            }
+        }
+
+        fn estimate_iterations(&mut self, iterations: u32) -> usize {
+            self.spi.estimate_iterations(self.func, iterations)
+        }
+
+        fn cost_hints(&self) -> Option<CostHints> {
+            self.spi.cost_hints(self.func)
+        }
+
+        fn throughput(&self) -> Option<Throughput> {
+            self.spi.throughput(self.func)
+        }
+    }
+
+    /// Empty function sampled in pairs between the samples of a test (see [`MeasurementSettings::null_interval`])
+    ///
+    /// Both sides are [`TestedFunction`]s, so the samples are taken and stored the same way the samples of the test
+    /// are.
+    struct NullSampler<'a> {
+        pair: [TestedFunction<'a>; 2],
+        interval: usize,
+
+        /// Number of iterations and the order of the calls of each pair of samples
+        plan: Vec<(usize, SampleOrder)>,
+    }
+
+    impl<'a> NullSampler<'a> {
+        fn new(spi: &'a [Spi<'a>; 2], interval: usize) -> Self {
+            let [baseline, candidate] = spi;
+            NullSampler {
+                pair: [
+                    TestedFunction::new(baseline, &baseline.tests()[0], Side::Baseline),
+                    TestedFunction::new(candidate, &candidate.tests()[0], Side::Candidate),
+                ],
+                interval: interval.max(1),
+                plan: vec![],
+            }
+        }
+
+        /// Takes a pair of samples after every `interval` samples of the test returning the time spent
+        ///
+        /// Sides are called in the same order the test was sampled in and the clock is read between them the same
+        /// way the sampling loop does.
+        fn sample(
+            &mut self,
+            i: usize,
+            iterations: usize,
+            first: Side,
+            clock: &mut PhaseClock,
+        ) -> Duration {
+            if !i.is_multiple_of(self.interval) {
+                return Duration::ZERO;
+            }
+            let [baseline, candidate] = &mut self.pair;
+            let (first, second, order) = match first {
+                Side::Baseline => (baseline, candidate, SampleOrder::BaselineFirst),
+                Side::Candidate => (candidate, baseline, SampleOrder::CandidateFirst),
+            };
+            first.run(iterations);
+            let elapsed = clock.lap();
+            second.run(iterations);
+            self.plan.push((iterations, order));
+            elapsed + clock.lap()
+        }
+
+        /// Baseline and candidate samples along with the number of iterations and the order of the calls
+        fn pairs(&self) -> Vec<(u64, u64, usize, SampleOrder)> {
+            let [baseline, candidate] = &self.pair;
+            baseline
+                .samples
+                .iter()
+                .zip(&candidate.samples)
+                .zip(&self.plan)
+                .map(|((&b, &c), &(iterations, order))| (b, c, iterations, order))
+                .collect()
+        }
+    }
+
+    /// Time window the number of iterations of both functions is estimated for
+    const ESTIMATE_MS: u32 = 50;
+
+    /// Number of measurements at each batch size of a batch curve (see [`stats::batch_curve()`])
+    pub(crate) const BATCH_CURVE_REPEATS: usize = 5;
+
+    /// Number of calls of a function measured at once in per-call mode before switching to the other function
+    const PER_CALL_BLOCK: usize = 100;
+
+    /// Number of iterations and haystack reuse both functions of a paired test are measured with
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) struct SamplingPlan {
+        /// Estimated number of iterations per sample the sampler is built with
+        pub(crate) iterations: usize,
+
+        /// Number of samples taken on each haystack
+        pub(crate) samples_per_haystack: usize,
+    }
+
+    impl SamplingPlan {
+        /// Plans sampling from the iterations estimates and generator costs of both functions
+        ///
+        /// If any of the functions doesn't report its costs, estimates are averaged and haystacks are reused as
+        /// configured. Otherwise both functions are assumed to have the more expensive generator of the two and the
+        /// number of iterations is chosen for the slower function.
+        pub(crate) fn new(
+            settings: &MeasurementSettings,
+            estimates: (usize, usize),
+            hints: (Option<CostHints>, Option<CostHints>),
+        ) -> Self {
+            let configured = Self {
+                iterations: estimates.0 / 2 + estimates.1 / 2,
+                samples_per_haystack: settings.samples_per_haystack,
+            };
+            let (Some(a_hints), Some(b_hints)) = hints else {
+                return configured;
+            };
+            let hints = a_hints.max(b_hints);
+
+            // Estimates include needle generation, so the cost of the cheaper generator is replaced with
+            // the cost of the more expensive one
+            let window = ESTIMATE_MS as f64 * 1e6;
+            let iteration_ns = |estimate: usize, own: CostHints| {
+                let ns = window / estimate.max(1) as f64;
+                ns + hints
+                    .needle_ns_estimate
+                    .saturating_sub(own.needle_ns_estimate) as f64
+            };
+            let iteration_ns =
+                iteration_ns(estimates.0, a_hints).max(iteration_ns(estimates.1, b_hints));
+            let iterations = (window / iteration_ns) as usize;
+
+            let sampler_iterations = iterations.clamp(
+                settings.min_iterations_per_sample.max(1),
+                settings.max_iterations_per_sample.max(1),
+            );
+            // Linear and random samplers take half of the iterations on average
+            let sample_ns = match settings.sampler_type {
+                SamplerType::Flat => sampler_iterations as f64 * iteration_ns,
+                SamplerType::Linear | SamplerType::Random => {
+                    sampler_iterations as f64 * iteration_ns / 2.
+                }
+            };
+            let threshold = settings.generator_overhead_threshold;
+            let samples_per_haystack =
+                if settings.amortize_haystacks && threshold > 0. && threshold < 1. {
+                    let haystack_ns = hints.haystack_ns_estimate as f64;
+                    let amortized = haystack_ns * (1. - threshold) / (threshold * sample_ns);
+                    settings.samples_per_haystack.max(amortized.ceil() as usize)
+                } else {
+                    settings.samples_per_haystack
+                };
+            Self {
+                iterations,
+                samples_per_haystack,
+            }
+        }
+
+        /// Number of samples fitting in a given time according to the estimates of both functions
+        ///
+        /// Always even, so each function is measured first the same number of times (see [`SamplePlanner::Fixed`]).
+        pub(crate) fn fixed_samples(
+            &self,
+            settings: &MeasurementSettings,
+            estimates: (usize, usize),
+            duration: Duration,
+        ) -> usize {
+            let window = ESTIMATE_MS as f64 * 1e6;
+            let pair_ns = window / estimates.0.max(1) as f64 + window / estimates.1.max(1) as f64;
+            let iterations = self.iterations.clamp(
+                settings.min_iterations_per_sample.max(1),
+                settings.max_iterations_per_sample.max(1),
+            ) as f64;
+            // Linear and random samplers take half of the iterations on average
+            let iterations = match settings.sampler_type {
+                SamplerType::Flat => iterations,
+                SamplerType::Linear | SamplerType::Random => iterations / 2.,
+            };
+            planner::even_samples(duration.as_nanos() as f64, pair_ns, iterations)
+        }
+    }
+
+    /// Parameters of a test shared by the phases of [`PairedTest::run_pair_sliced`]
+    #[derive(Clone, Copy)]
+    struct SliceParams<'s> {
+        test_name: &'s str,
+        settings: &'s MeasurementSettings,
+        replay: Option<&'s TestSchedule>,
+        slice: Option<Duration>,
+        seed: u64,
+        cold: bool,
+        unit: Unit,
+    }
+
+    /// Sampling of a test planned by [`PairedTest::plan_sampling`]
+    #[derive(Clone, Copy)]
+    struct SliceSchedule {
+        plan: SamplingPlan,
+
+        /// When the sampling of the slice stops
+        loop_mode: LoopMode,
+
+        /// Time limit of the test, extended if a single call is too slow for the configured one
+        time_mode: LoopMode,
+
+        planned_samples: Option<usize>,
+        target_precision: Option<f64>,
+        estimate_overrun: Option<Duration>,
+        slow_call: Option<Duration>,
+        extended_duration: Option<Duration>,
+    }
+
+    impl SliceSchedule {
+        /// Aborts the test if it runs for too long after taking a given number of samples
+        fn check_time_limits(
+            &self,
+            params: &SliceParams,
+            start_time: Instant,
+            taken: usize,
+        ) -> Result<()> {
+            let &SliceParams {
+                test_name,
+                settings,
+                ..
+            } = params;
+            if let (Some(planned), LoopMode::Time(duration)) =
+                (self.planned_samples, self.time_mode)
+            {
+                if start_time.elapsed() > duration * 2 {
+                    bail!(
+                        "Test {} took more than twice its time limit of {:.1}s after {} of {} planned samples",
+                        test_name,
+                        duration.as_secs_f64(),
+                        taken,
+                        planned,
+                    );
+                }
+            }
+
+            if let (Some(max_time), LoopMode::Samples(samples)) =
+                (settings.max_total_time, self.loop_mode)
+            {
+                let projected = start_time.elapsed().mul_f64(samples as f64 / taken as f64);
+                if projected > max_time {
+                    bail!(
+                        "Test {} is projected to take {:.1}s for {} samples, which exceeds maximum total time of {:.1}s",
+                        test_name,
+                        projected.as_secs_f64(),
+                        samples,
+                        max_time.as_secs_f64()
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Checks that both functions of a paired test can be compared with each other
+    fn check_comparable(
+        test_name: &str,
+        a_func: &NamedFunction,
+        b_func: &NamedFunction,
+        settings: &MeasurementSettings,
+    ) -> Result<()> {
+        if a_func.unit != b_func.unit {
+            bail!(
+                "Test {} measures different units: {:?} (baseline) and {:?} (candidate)",
+                test_name,
+                a_func.unit,
+                b_func.unit
+            );
+        }
+
+        if a_func.batch != b_func.batch && !settings.mixed_batches {
+            bail!(
+                "Test {} processes batches of different sizes: {} (baseline) and {} (candidate). Use --mixed-batches to compare them per element",
+                test_name,
+                a_func.batch,
+                b_func.batch
+            );
+        }
+
+        // Mutating the haystack of only one function would compare them on different inputs
+        if a_func.mutates != b_func.mutates {
+            let side = if b_func.mutates {
+                "candidate"
+            } else {
+                "baseline"
+            };
+            bail!(
+                "Test {} mutates haystacks only in the {} executable. Both executables should be built with the same generator",
+                test_name,
+                side
+            );
+        }
+        Ok(())
+    }
+
+    /// Reason the overhead of a test can't be measured with [`MeasurementSettings::null_interval`]
+    ///
+    /// Overhead is only comparable to the differences of the tests measuring time, elements of both functions should
+    /// match as well.
+    fn null_skipped(
+        settings: &MeasurementSettings,
+        unit: Unit,
+        batch: [usize; 2],
+    ) -> Option<&'static str> {
+        match settings.null_interval {
+            Some(_) if unit != Unit::Nanoseconds => Some("the test doesn't measure time"),
+            Some(_) if batch[0] != batch[1] => {
+                Some("baseline and candidate process batches of different sizes")
+            }
+            _ => None,
+        }
+    }
+
+    /// Clock attributing the time elapsed since the previous phase boundary to the next phase
+    struct PhaseClock(Instant);
+
+    impl PhaseClock {
+        fn start() -> Self {
+            Self(Instant::now())
+        }
+
+        /// Time elapsed since the previous boundary
+        fn lap(&mut self) -> Duration {
+            let now = Instant::now();
+            let elapsed = now - self.0;
+            self.0 = now;
+            elapsed
+        }
+    }
+
+    /// Measure the difference in performance of two functions
+    ///
+    /// Provides a way to save a raw dump of measurements into directory
+    ///
+    /// The format is as follows
+    /// ```txt
+    /// b_1,c_1
+    /// b_2,c_2
+    /// ...
+    /// b_n,c_n
+    /// ```
+    /// where `b_1..b_n` are baseline absolute time (in nanoseconds) measurements
+    /// and `c_1..c_n` are candidate time measurements
+    ///
+    /// Returns a percentage difference in performance of two functions if this change is
+    /// statistically significant
+    pub(crate) struct PairedTest<'a> {
+        baseline: &'a Spi<'a>,
+        candidate: &'a Spi<'a>,
+        rng: RngFactory,
+        settings: MeasurementSettings,
+        loop_mode: LoopMode,
+        samples_dump_path: Option<PathBuf>,
+        firewall: Option<CacheFirewall>,
+        watchdog: Option<Watchdog>,
+        observers: RefCell<Vec<Box<dyn SampleObserver>>>,
+
+        /// Retain all the samples in [`RunResult::pairs`] (see [`Reporter::wants_samples()`])
+        keep_pairs: bool,
+
+        /// Show the phase of the running test on a status line
+        progress: bool,
+
+        /// Overrides of the settings of the profiles from the command line
+        profiles: Vec<ProfileOverride>,
+    }
+
+    impl<'a> PairedTest<'a> {
+        pub fn new(
+            baseline: &'a Spi<'a>,
+            candidate: &'a Spi<'a>,
+            settings: MeasurementSettings,
+            seed: Option<u64>,
+            loop_mode: LoopMode,
+            samples_dump_path: Option<PathBuf>,
+        ) -> Self {
+            let rng = RngFactory::new(seed.unwrap_or_else(rand::random));
+            let firewall = settings
+                .cache_firewall
+                .map(|s| s * 1024)
+                .map(CacheFirewall::new);
+            let watchdog = settings
+                .call_timeout
+                .map(|timeout| Watchdog::start(timeout, abort_on_timeout));
+            Self {
+                baseline,
+                candidate,
+                rng,
+                settings,
+                loop_mode,
+                samples_dump_path,
+                firewall,
+                watchdog,
+                observers: RefCell::new(vec![]),
+                keep_pairs: false,
+                progress: false,
+                profiles: vec![],
+            }
+        }
+
+        /// Overrides the settings of the profiles (`--profile`)
+        pub fn override_profiles(&mut self, overrides: Vec<ProfileOverride>) {
+            self.profiles = overrides;
+        }
+
+        /// Effective settings of a function given its tolerance profile (see [`crate::profile`])
+        pub fn settings_of(&self, func: &NamedFunction) -> MeasurementSettings {
+            func.profile.settings(&self.settings, &self.profiles)
+        }
+
+        /// Retains all the samples of each test in the results, including the outliers
+        pub fn keep_pairs(&mut self, keep: bool) {
+            self.keep_pairs = keep;
+        }
+
+        /// Enables the status line showing the phase of the running test on stderr
+        pub fn show_progress(&mut self, progress: bool) {
+            self.progress = progress;
+        }
+
+        /// Registers observer notified at the boundaries of each recorded sample
+        pub fn add_observer(&mut self, observer: Box<dyn SampleObserver>) {
+            self.observers.get_mut().push(observer);
+        }
+
+        pub fn master_seed(&self) -> u64 {
+            self.rng.seed()
+        }
+
+        /// Switches to the confirmation runs of `--confirm-top`
+        ///
+        /// Tests are run again with the seeds derived from a different master seed, so they are measured on fresh
+        /// inputs, and with twice the time limit or the number of samples. Samples of the confirmation runs are
+        /// not dumped.
+        pub fn confirmation(&mut self) {
+            self.rng = RngFactory::new(self.rng.seed_for(CONFIRMATION_SEED));
+            self.loop_mode = self.loop_mode.doubled();
+            self.settings.exact_samples = self.settings.exact_samples.map(|samples| samples * 2);
+            self.samples_dump_path = None;
+        }
+
+        fn lookup(&self, pair: &TestPair) -> (&'a NamedFunction, &'a NamedFunction) {
+            let a_func = self
+                .baseline
+                .lookup(pair.id.baseline.as_str())
+                .expect("Invalid test name given");
+            let b_func = self
+                .candidate
+                .lookup(pair.id.candidate.as_str())
+                .expect("Invalid test name given");
+            (a_func, b_func)
+        }
+
+        /// Compares outputs of both functions on the same inputs
+        ///
+        /// Calls are not timed and don't affect the measurement, because [`Self::run()`] syncs generators again.
+        pub fn verify(&self, test_name: &str) -> Result<Verification> {
+            self.verify_pair(&TestPair::same(test_name))
+        }
+
+        /// Same as [`Self::verify()`], but for the functions registered under different names
+        pub fn verify_pair(&self, pair: &TestPair) -> Result<Verification> {
+            let samples = self.settings.verification_samples;
+            if samples == 0 {
+                return Ok(Verification::NotApplicable);
+            }
+            let (a_func, b_func) = self.lookup(pair);
+
+            let seed = self.rng.seed_for(pair.name);
+            self.baseline.sync(a_func, seed);
+            self.candidate.sync(b_func, seed);
+            let baseline = self.baseline.verify(a_func, samples).transpose()?;
+            let candidate = self.candidate.verify(b_func, samples).transpose()?;
+            Ok(Verification::new(baseline.as_deref(), candidate.as_deref()))
+        }
+
+        /// Runs the test
+        ///
+        /// If `replay` schedule is given, the seed, iterations estimate and the number of samples are taken from it
+        /// instead of being derived, so the test is executed exactly the same way as recorded.
+        pub fn run(&self, test_name: &str, replay: Option<&TestSchedule>) -> Result<RunResult> {
+            self.run_pair(&TestPair::same(test_name), replay)
+        }
+
+        /// Same as [`Self::run()`], but for the functions registered under different names
+        ///
+        /// Both functions get the same seed derived from the name of the pair, so they are measured on the same
+        /// inputs. This allows to compare different functions of the same executable.
+        pub fn run_pair(
+            &self,
+            pair: &TestPair,
+            replay: Option<&TestSchedule>,
+        ) -> Result<RunResult> {
+            self.run_pair_sliced(pair, replay, None)
+        }
+
+        /// Runs a slice of a test measured in turns with the other tests (`--schedule round-robin`)
+        ///
+        /// The first slice (`first` is `None`) plans the sampling of the test and is sampled for the duration of the
+        /// slice. The following slices replay the plan and the number of samples of the first one, each on the
+        /// inputs of its own seed derived from the name of the test and the index of the slice, so the slices take
+        /// about the same time and are reproducible.
+        pub fn run_slice(
+            &self,
+            test_name: &str,
+            slice: Duration,
+            first: Option<&RunResult>,
+            index: usize,
+        ) -> Result<RunResult> {
+            let pair = TestPair::same(test_name);
+            let Some(first) = first else {
+                return self.run_pair_sliced(&pair, None, Some(slice));
+            };
+            let seed = RngFactory::new(self.rng.seed_for(test_name)).seed_for(&index.to_string());
+            let replay = TestSchedule {
+                seed,
+                first: SampleOrder::first_for_seed(seed),
+                ..first.schedule.clone()
+            };
+            self.run_pair_sliced(&pair, Some(&replay), Some(slice))
+        }
+
+        /// Number of slices a test is measured in given its first slice
+        ///
+        /// Tests are given the same total time as when measured sequentially (including the extension of a slow
+        /// test). Tests with a fixed number of samples are measured in a single slice.
+        pub fn slices(&self, first: &RunResult, slice: Duration) -> usize {
+            match (self.loop_mode, self.settings.exact_samples) {
+                (LoopMode::Time(duration), None) => {
+                    let duration = first.schedule.extended_duration.unwrap_or(duration);
+                    (duration.as_secs_f64() / slice.as_secs_f64())
+                        .ceil()
+                        .max(1.) as usize
+                }
+                _ => 1,
+            }
+        }
+
+        /// Result of a test pooled from the results of its slices (see [`Self::run_slice()`])
+        pub fn pool_slices(&self, pooled: &RunResult, slice: &RunResult) -> RunResult {
+            let settings = match self.candidate.lookup(pooled.id.candidate.as_str()) {
+                Some(func) => self.settings_of(func),
+                None => self.settings,
+            };
+            let mut result = pooled_run_result(pooled, slice, &settings);
+            // The first slice is replayed by the following ones, so the pooled schedule replays all of them at once
+            result.schedule.samples += slice.schedule.samples;
+            result.schedule.total_iterations += slice.schedule.total_iterations;
+            result.schedule.duration = Some(result.phases.total());
+            result
+        }
+
+        fn run_pair_sliced(
+            &self,
+            pair: &TestPair,
+            replay: Option<&TestSchedule>,
+            slice: Option<Duration>,
+        ) -> Result<RunResult> {
+            let test_name = pair.name;
+            let mut clock = PhaseClock::start();
+            let mut phases = PhaseTimings::default();
+
+            let (a_func, b_func) = self.lookup(pair);
+            // Results are judged by the tolerance profile of the current version of the code
+            let settings = &self.settings_of(b_func);
+
+            let watchdog = self.watchdog.as_ref();
+            let _watch = watchdog.map(|w| w.watch(a_func.name.clone()));
+
+            let seed = replay.map_or_else(|| self.rng.seed_for(test_name), |r| r.seed);
+            self.baseline.sync(a_func, seed);
+            self.candidate.sync(b_func, seed);
+            // Fixtures are created before anything is measured and are released on any exit from the test
+            let _fixtures = PreparedPair::new([(self.baseline, a_func), (self.candidate, b_func)]);
+
+            // Cold-start mode is propagated to both functions, so they are measured the same way
+            // even if only one of the binaries has the test registered as cold
+            let cold = a_func.cold || b_func.cold;
+            // The same goes for including setup in the measurement
+            let e2e = a_func.e2e || b_func.e2e;
+            if e2e {
+                self.include_setup(test_name, a_func, b_func)?;
+            }
+            // Importance of the test is defined by the current version of the code
+            let weight = b_func.weight;
+            let profile = b_func.profile.name.clone();
+            let generator = b_func.generator.clone();
+            let recording = b_func.recording.clone();
+
+            check_comparable(test_name, a_func, b_func, settings)?;
+            let unit = a_func.unit;
+            // Functions processing batches are compared per element
+            let batch = [a_func.batch, b_func.batch];
+            let mutates = a_func.mutates;
+
+            let mut a_func = TestedFunction::new(self.baseline, a_func, Side::Baseline);
+            let mut b_func = TestedFunction::new(self.candidate, b_func, Side::Candidate);
+
+            let params = SliceParams {
+                test_name,
+                settings,
+                replay,
+                slice,
+                seed,
+                cold,
+                unit,
+            };
+            // Batch curve is measured once per test
+            let first_slice = slice.is_none() || replay.is_none();
+            let batch_curve = if settings.batch_curve && !cold && first_slice {
+                self.measure_batch_curve(&params, &a_func, &b_func)
+            } else {
+                None
+            };
+            let mut observers = self.observers.borrow_mut();
+            let mut observed = vec![];
+
+            let schedule = self.plan_sampling(&params, &mut a_func, &mut b_func)?;
+            let SliceSchedule {
+                plan,
+                loop_mode,
+                target_precision,
+                estimate_overrun,
+                slow_call,
+                extended_duration,
+                ..
+            } = schedule;
+            if self.progress {
+                status::show(test_name, "measuring");
+            }
+            let iterations_per_sample = plan.iterations;
+            let mut sampler = create_sampler(settings, iterations_per_sample, seed);
+            let mut switch_counter = 0;
+            let first = replay.map_or_else(|| SampleOrder::first_for_seed(seed), |r| r.first);
+            // Functions are swapped before each sample, so they start in the order opposite to the first one
+            if first == SampleOrder::BaselineFirst {
+                mem::swap(&mut a_func, &mut b_func);
+                switch_counter += 1;
+            }
+
+            let mut i = 0;
+            let mut discarded = 0;
+
+            let mut sample_iterations = vec![];
+            let mut total_iterations = 0;
+            // Samples exceeding the memory cap are accumulated into a reservoir instead
+            let capacity = settings.sample_capacity();
+            let reservoir = || StreamingSamples::with_reservoir(first, batch, capacity, seed);
+            let mut streaming = match loop_mode {
+                _ if settings.streaming => Some(StreamingSamples::new(first, batch)),
+                LoopMode::Samples(samples) if samples > capacity => Some(reservoir()),
+                _ => None,
+            };
+            let mut precision_reached = false;
+
+            let null_skipped = null_skipped(settings, unit, batch);
+            let null_spi = match settings.null_interval {
+                Some(_) if null_skipped.is_none() => Some([
+                    Spi::for_benchmarks(vec![noise::null_benchmark()])?,
+                    Spi::for_benchmarks(vec![noise::null_benchmark()])?,
+                ]),
+                _ => None,
+            };
+            let mut null = null_spi
+                .as_ref()
+                .zip(settings.null_interval)
+                .map(|(spi, interval)| NullSampler::new(spi, interval));
+
+            phases.estimate = clock.lap();
+            let start_time = Instant::now();
+            loop {
+                // When generator time is excluded the deadline is shifted by the time spent in generators,
+                // so the configured duration refers to measurement time only
+                let deadline_start = if settings.exclude_generator_time {
+                    start_time + phases.generate
+                } else {
+                    start_time
+                };
+                if !loop_mode.should_continue(i, deadline_start) {
+                    break;
+                }
+
+                // In cold mode each sample is a single call on a freshly reset target
+                let iterations = if cold {
+                    1
+                } else if let Some(iterations) = settings.fixed_iterations {
+                    iterations
+                } else {
+                    sampler.next_sample_iterations(i)
+                };
+                i += 1;
+
+                // !!! IMPORTANT !!!
+                // Algorithms should be called in different order on each new iteration.
+                // This equalize the probability of facing unfortunate circumstances like cache misses or page faults
+                // for both functions. Although both algorithms are from distinct shared objects and therefore
+                // must be fully self-contained in terms of virtual address space (each shared object has its own
+                // generator instances, static variables, memory mappings, etc.) it might be the case that
+                // on the level of physical memory both of them rely on the same memory-mapped test data, for example.
+                // In that case first function will experience the larger amount of major page faults.
+                {
+                    mem::swap(&mut a_func, &mut b_func);
+                    switch_counter += 1;
+                }
+                // Order bias is calculated assuming the order of a sample is known from its position
+                debug_assert_eq!(
+                    a_func.side == Side::Candidate,
+                    SampleOrder::of_sample(i - 1, first) == SampleOrder::CandidateFirst
+                );
+
+                if cold || i % plan.samples_per_haystack == 0 {
+                    // both functions should be called, so no short-circuiting here
+                    let haystack_changed = a_func.next_haystack() | b_func.next_haystack();
+                    phases.generate += clock.lap();
+
+                    if haystack_changed
+                        && !cold
+                        && settings.discard_first_sample_after_haystack_change
+                    {
+                        a_func.warm_up(iterations);
+                        b_func.warm_up(iterations);
+                        phases.warmup += clock.lap();
+                        discarded += 1;
+                    }
+                } else if mutates {
+                    // Haystack evolves between the samples, the same way for both functions
+                    a_func.mutate_haystack();
+                    b_func.mutate_haystack();
+                    phases.generate += clock.lap();
+                }
+
+                if let Some(watchdog) = watchdog {
+                    watchdog.beat();
+                }
+
+                if settings.yield_before_sample {
+                    std::thread::yield_now();
+                }
+
+                if let Some(firewall) = &self.firewall {
+                    firewall.issue_read();
+                }
+
+                // Time spent in observers is attributed to the measurement phase of the function
+                a_func.run_observed(i, iterations, &mut observers, &mut observed);
+                a_func.measure_time += clock.lap();
+                b_func.run_observed(i, iterations, &mut observers, &mut observed);
+                b_func.measure_time += clock.lap();
+                a_func.check_inputs()?;
+                b_func.check_inputs()?;
+                if let Some(null) = &mut null {
+                    phases.null += null.sample(i, iterations, a_func.side, &mut clock);
+                }
+                total_iterations += iterations as u64;
+                if let Some(streaming) = &mut streaming {
+                    let (a, b) = (a_func.take_sample(), b_func.take_sample());
+                    match a_func.side {
+                        Side::Baseline => streaming.add(a, b, iterations),
+                        Side::Candidate => streaming.add(b, a, iterations),
+                    }
+                } else {
+                    sample_iterations.push(iterations);
+                    if sample_iterations.len() > capacity {
+                        let (base, candidate) = match a_func.side {
+                            Side::Baseline => (&mut a_func, &mut b_func),
+                            Side::Candidate => (&mut b_func, &mut a_func),
+                        };
+                        let mut downsampled = reservoir();
+                        let samples = base.samples.iter().zip(&candidate.samples);
+                        for ((&b, &c), &iterations) in samples.zip(&sample_iterations) {
+                            downsampled.add(b, c, iterations);
+                        }
+                        base.samples = vec![];
+                        candidate.samples = vec![];
+                        sample_iterations = vec![];
+                        streaming = Some(downsampled);
+                    }
+                }
+
+                if let Some(target) = target_precision {
+                    let interval = settings.precision_check_interval.max(1);
+                    if i >= settings.min_samples && i % interval == 0 {
+                        let (base, candidate) = match a_func.side {
+                            Side::Baseline => (&a_func, &b_func),
+                            Side::Candidate => (&b_func, &a_func),
+                        };
+                        let result = match &streaming {
+                            Some(streaming) => {
+                                calculate_streaming_run_result(test_name, streaming, settings)
+                            }
+                            None => calculate_run_result_raw(
+                                test_name,
+                                &base.samples,
+                                &candidate.samples,
+                                &sample_iterations,
+                                batch,
+                                first,
+                                settings,
+                            ),
+                        };
+                        phases.analyze += clock.lap();
+                        if result.is_some_and(|r| r.diff_estimate.ci.half_width() <= target) {
+                            precision_reached = true;
+                            break;
+                        }
+                    }
+                }
+
+                schedule.check_time_limits(&params, start_time, i)?;
+            }
+
+            // If we switched functions odd number of times then we need to swap them back so that
+            // the first function is always the baseline.
+            if switch_counter % 2 != 0 {
+                mem::swap(&mut a_func, &mut b_func);
+            }
+            // Calls are measured after sampling, so they don't affect the samples
+            let tail = if settings.per_call && !cold && unit == Unit::Nanoseconds {
+                if self.progress {
+                    status::show(test_name, "measuring calls");
+                }
+                self.measure_calls(&mut a_func, &mut b_func, &mut clock)
+            } else {
+                None
+            };
+            phases.measure_base = a_func.measure_time;
+            phases.measure_candidate = b_func.measure_time;
+
+            let run_result = match &streaming {
+                Some(streaming) => calculate_downsampled_run_result(test_name, streaming, settings),
+                None => calculate_run_result_raw(
+                    test_name,
+                    &a_func.samples,
+                    &b_func.samples,
+                    &sample_iterations,
+                    batch,
+                    first,
+                    settings,
+                ),
+            };
+            let mut run_result = run_result.ok_or(Error::NoMeasurements)?;
+            run_result.id = pair.id.clone();
+            run_result.cold = cold;
+            run_result.e2e = e2e;
+            run_result.weight = weight;
+            run_result.generator = generator;
+            run_result.recording = recording;
+            // Both functions process the same inputs, the baseline may be built with a version of tango not
+            // reporting the sizes
+            if unit == Unit::Nanoseconds {
+                let time = [run_result.baseline.mean, run_result.candidate.mean];
+                run_result.throughput = b_func
+                    .throughput()
+                    .or_else(|| a_func.throughput())
+                    .and_then(|throughput| ThroughputRates::new(throughput, time));
+            }
+            run_result.profile = profile;
+            run_result.discarded = discarded;
+            run_result.unit = unit;
+            run_result.observed = ObservedSummary::summarize(&observed, settings);
+            run_result.estimate_overrun = estimate_overrun;
+            run_result.tail = tail;
+            run_result.overhead_variance = null
+                .as_ref()
+                .and_then(|null| overhead_variance(&run_result, &null.pairs(), batch[0], settings));
+            run_result.null_skipped = null_skipped;
+            run_result.precision = target_precision.map(|_| AchievedPrecision {
+                half_width: run_result.diff_estimate.ci.half_width(),
+                stop: if precision_reached {
+                    PrecisionStop::Reached
+                } else {
+                    PrecisionStop::TimeCap
+                },
+            });
+            run_result.schedule = TestSchedule {
+                name: test_name.to_string(),
+                seed,
+                estimate: iterations_per_sample,
+                samples: i,
+                samples_per_haystack: plan.samples_per_haystack,
+                first,
+                total_iterations,
+                slow_call,
+                extended_duration,
+                batch_curve,
+                duration: None,
+            };
+
+            if self.keep_pairs && streaming.is_none() {
+                run_result.pairs = Some(PairedSamples::new(
+                    &a_func.samples,
+                    &b_func.samples,
+                    &sample_iterations,
+                    batch,
+                    settings,
+                ));
+            }
+
+            // Failing to dump measurements doesn't fail the test, the results are still reported
+            run_result.dump = self.dump_samples(
+                test_name,
+                first,
+                batch,
+                [&a_func.samples, &b_func.samples],
+                &sample_iterations,
+                streaming.as_ref(),
+            );
+
+            phases.analyze += clock.lap();
+            run_result.phases = phases;
+            run_result.schedule.duration = Some(phases.total());
+            if self.progress {
+                status::clear();
+            }
+            Ok(run_result)
+        }
+
+        /// Makes both functions include setup in the measurement if any of them does
+        fn include_setup(
+            &self,
+            test_name: &str,
+            a_func: &NamedFunction,
+            b_func: &NamedFunction,
+        ) -> Result<()> {
+            for (spi, func, side) in [
+                (self.baseline, a_func, "baseline"),
+                (self.candidate, b_func, "candidate"),
+            ] {
+                if !spi.set_includes_setup(func, true) {
+                    bail!(
+                        "Test {} includes setup, but the {} executable doesn't support it. Rebuild it with newer version of tango",
+                        test_name,
+                        side
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        /// Measures the batch curves of both functions (see [`MeasurementSettings::batch_curve`])
+        ///
+        /// Generators of both functions are synchronized afterwards, so the measurement doesn't affect the inputs of
+        /// the samples.
+        fn measure_batch_curve(
+            &self,
+            params: &SliceParams,
+            a_func: &TestedFunction,
+            b_func: &TestedFunction,
+        ) -> Option<[stats::BatchCurve; 2]> {
+            if self.progress {
+                status::show(params.test_name, "measuring batch curve");
+            }
+            let curve = |f: &TestedFunction| {
+                stats::batch_curve(|n| f.spi.run(f.func, n), BATCH_CURVE_REPEATS)
+            };
+            let curves = curve(a_func).zip(curve(b_func)).map(|(a, b)| [a, b]);
+            // Measuring consumes inputs of both generators
+            self.baseline.sync(a_func.func, params.seed);
+            self.candidate.sync(b_func.func, params.seed);
+            curves
+        }
+
+        /// Chooses the number of iterations per sample and when the sampling of a slice stops
+        ///
+        /// The plan is replayed from the schedule if given, otherwise estimated unless the test is cold or the number
+        /// of iterations is fixed. Fails with [`Error::TestSkipped`] if a single call of a test is too slow for its
+        /// time limit and [`SlowTestPolicy::Skip`] is used.
+        fn plan_sampling(
+            &self,
+            params: &SliceParams,
+            a_func: &mut TestedFunction,
+            b_func: &mut TestedFunction,
+        ) -> Result<SliceSchedule> {
+            let &SliceParams {
+                test_name,
+                settings,
+                replay,
+                slice,
+                seed,
+                cold,
+                unit,
+            } = params;
+            let configured = SamplingPlan {
+                iterations: 1,
+                samples_per_haystack: settings.samples_per_haystack,
+            };
+            let mut estimate_overrun = None;
+            let mut planned_samples = None;
+            let mut slow_call = None;
+            let mut extended_duration = None;
+            // Time limit of this test, may be extended if the test is too slow for it
+            let mut time_mode = self.loop_mode;
+            let target_precision = settings.target_precision.filter(|_| {
+                replay.is_none()
+                    && settings.exact_samples.is_none()
+                    && matches!(self.loop_mode, LoopMode::Time(_))
+            });
+            let plan = if let Some(replay) = replay {
+                SamplingPlan {
+                    iterations: replay.estimate,
+                    samples_per_haystack: match replay.samples_per_haystack {
+                        0 => settings.samples_per_haystack,
+                        samples => samples,
+                    },
+                }
+            } else if cold {
+                configured
+            } else if let Some(iterations) = settings.fixed_iterations {
+                SamplingPlan {
+                    iterations,
+                    ..configured
+                }
+            } else {
+                if self.progress {
+                    status::show(test_name, "estimating");
+                }
+                let plan = self.estimate(a_func, b_func);
+                if let (Ok((_, estimates)), LoopMode::Time(duration), None, Unit::Nanoseconds) =
+                    (&plan, self.loop_mode, settings.exact_samples, unit)
+                {
+                    slow_call = self.slow_call(a_func, b_func, *estimates, duration);
+                }
+                // Estimation consumes inputs of both generators, possibly not in the same way
+                self.baseline.sync(a_func.func, seed);
+                self.candidate.sync(b_func.func, seed);
+                match plan {
+                    Ok((plan, estimates)) => {
+                        if let (Some(call), LoopMode::Time(duration)) = (slow_call, self.loop_mode)
+                        {
+                            match settings.slow_test_policy {
+                                SlowTestPolicy::Force => {}
+                                SlowTestPolicy::Skip => {
+                                    let reason = format!(
+                                        "a single call takes {:.1}ms, more than {:.0}% of the time limit of {:.2}s",
+                                        call.as_secs_f64() * 1e3,
+                                        settings.slow_call_fraction * 100.,
+                                        duration.as_secs_f64()
+                                    );
+                                    if self.progress {
+                                        status::clear();
+                                    }
+                                    return Err(Error::TestSkipped(reason).into());
+                                }
+                                SlowTestPolicy::Extend => {
+                                    // Each sample calls both functions
+                                    let sample = call * 2 * plan.iterations.max(1) as u32;
+                                    let needed = sample * settings.min_samples as u32;
+                                    let extended = needed.min(settings.max_auto_extend);
+                                    if extended > duration {
+                                        extended_duration = Some(extended);
+                                        time_mode = LoopMode::Time(extended);
+                                    }
+                                }
+                            }
+                        }
+                        if let (SamplePlanner::Fixed, LoopMode::Time(duration), None) =
+                            (settings.sample_planner, time_mode, target_precision)
+                        {
+                            let duration = slice.map_or(duration, |slice| slice.min(duration));
+                            planned_samples =
+                                Some(plan.fixed_samples(settings, estimates, duration));
+                        }
+                        plan
+                    }
+                    Err(elapsed) => {
+                        estimate_overrun = Some(elapsed);
+                        configured
+                    }
+                }
+            };
+            let loop_mode = match (replay, settings.exact_samples, planned_samples) {
+                (Some(replay), _, _) => LoopMode::Samples(replay.samples),
+                (None, Some(samples), _) => LoopMode::Samples(samples),
+                (None, None, Some(samples)) => LoopMode::Samples(samples),
+                (None, None, None) if target_precision.is_some() => {
+                    LoopMode::Time(settings.max_precision_time)
+                }
+                (None, None, None) => slice.map_or(time_mode, |slice| time_mode.capped(slice)),
+            };
+            Ok(SliceSchedule {
+                plan,
+                loop_mode,
+                time_mode,
+                planned_samples,
+                target_precision,
+                estimate_overrun,
+                slow_call,
+                extended_duration,
+            })
+        }
+
+        /// Dumps the samples of a test to the configured directory if any
+        ///
+        /// Only the reservoir is dumped if the samples didn't fit in memory.
+        fn dump_samples(
+            &self,
+            test_name: &str,
+            first: SampleOrder,
+            batch: [usize; 2],
+            [a_samples, b_samples]: [&[u64]; 2],
+            sample_iterations: &[usize],
+            streaming: Option<&StreamingSamples>,
+        ) -> Option<StdResult<PathBuf, String>> {
+            let path = self.samples_dump_path.as_ref()?;
+            let written = match streaming.map(StreamingSamples::reservoir_samples) {
+                None => {
+                    let values = a_samples
+                        .iter()
+                        .copied()
+                        .zip(b_samples.iter().copied())
+                        .zip(sample_iterations.iter().copied())
+                        .map(|((a, b), c)| (a, b, c));
+                    write_dump(path, test_name, first, batch, None, values)
+                }
+                Some(Some((samples, seen))) => {
+                    let note = Some((samples.len(), seen));
+                    write_dump(path, test_name, first, batch, note, samples)
+                }
+                Some(None) => return None,
+            };
+            Some(written.map_err(|e| e.to_string()))
+        }
+
+        /// Measures calls of both functions one by one (see [`MeasurementSettings::per_call`])
+        ///
+        /// Functions are measured in alternating blocks, each block starting with the function measured second in
+        /// the previous one. Returns `None` if any of the functions doesn't support per-call measurement.
+        fn measure_calls(
+            &self,
+            a_func: &mut TestedFunction,
+            b_func: &mut TestedFunction,
+            clock: &mut PhaseClock,
+        ) -> Option<TailLatencies> {
+            let calls = self.settings.per_call_samples;
+            let mut baseline = Vec::with_capacity(calls);
+            let mut candidate = Vec::with_capacity(calls);
+            let mut measure = |func: &mut TestedFunction, times: &mut Vec<u64>, block| {
+                let measured = func.spi.measure_detailed(func.func, block);
+                func.measure_time += clock.lap();
+                times.extend(measured?);
+                Some(())
+            };
+            while baseline.len() < calls {
+                let block = PER_CALL_BLOCK.min(calls - baseline.len());
+                if (baseline.len() / PER_CALL_BLOCK).is_multiple_of(2) {
+                    measure(a_func, &mut baseline, block)?;
+                    measure(b_func, &mut candidate, block)?;
+                } else {
+                    measure(b_func, &mut candidate, block)?;
+                    measure(a_func, &mut baseline, block)?;
+                }
+            }
+            TailLatencies::new(&mut baseline, &mut candidate, crate::timer_overhead())
+        }
+
+        /// Time of a single call of the slower function if it exceeds [`MeasurementSettings::slow_call_fraction`]
+        /// of a given time limit
+        ///
+        /// Call time is derived from the estimates. Functions not fitting the estimation window even once are called
+        /// to measure it.
+        fn slow_call(
+            &self,
+            a_func: &mut TestedFunction,
+            b_func: &mut TestedFunction,
+            estimates: (usize, usize),
+            duration: Duration,
+        ) -> Option<Duration> {
+            let call_time = |func: &mut TestedFunction, estimate: usize| {
+                if estimate > 0 {
+                    Duration::from_nanos(ESTIMATE_MS as u64 * 1_000_000 / estimate as u64)
+                } else {
+                    Duration::from_nanos(func.spi.run(func.func, 1))
+                }
+            };
+            let call = call_time(a_func, estimates.0).max(call_time(b_func, estimates.1));
+            (call > duration.mul_f64(self.settings.slow_call_fraction)).then_some(call)
+        }
+
+        /// Plans sampling of both functions within [`MeasurementSettings::estimate_budget`]
+        ///
+        /// Functions are estimated one after another: the libraries are independent, but [`Spi`] is not `Send`
+        /// (in-process targets are not required to be), so the estimation can't be moved to another thread.
+        /// Returns the time spent if the budget is exceeded.
+        fn estimate(
+            &self,
+            a_func: &mut TestedFunction,
+            b_func: &mut TestedFunction,
+        ) -> StdResult<(SamplingPlan, (usize, usize)), Duration> {
+            let start = Instant::now();
+            let check_budget = || {
+                let elapsed = start.elapsed();
+                match self.settings.estimate_budget {
+                    Some(budget) if elapsed > budget => Err(elapsed),
+                    _ => Ok(()),
+                }
+            };
+            let a_estimate = a_func.estimate_iterations(ESTIMATE_MS);
+            check_budget()?;
+            let b_estimate = b_func.estimate_iterations(ESTIMATE_MS);
+            check_budget()?;
+            let estimates = (a_estimate, b_estimate);
+            let hints = (a_func.cost_hints(), b_func.cost_hints());
+            Ok((
+                SamplingPlan::new(&self.settings, estimates, hints),
+                estimates,
+            ))
+        }
+    }
+
+    /// Names of the functions compared by a test
+    pub(crate) struct TestPair<'n> {
+        /// Name of the test the seed and the results are derived from
+        pub name: &'n str,
+
+        /// Functions compared by the test
+        pub id: PairId,
+    }
+
+    impl<'n> TestPair<'n> {
+        /// Test comparing functions with the same name
+        pub fn same(name: &'n str) -> Self {
+            Self {
+                name,
+                id: PairId::same(name),
+            }
+        }
+    }
+
+    fn abort_on_timeout(test_name: &str, elapsed: Duration) {
+        // Test measured in a child process is reported by the parent (`--skip-on-timeout`)
+        if super::isolated::is_child() {
+            process::exit(EXIT_TIMEOUT.into());
+        }
+        eprintln!(
+            "[ERROR] Test {} made no progress for {:.1}s (call timeout exceeded), aborting",
+            test_name,
+            elapsed.as_secs_f64()
+        );
+        process::exit(1);
+    }
+
+    fn create_sampler(
+        settings: &MeasurementSettings,
+        estimate: usize,
+        seed: u64,
+    ) -> Box<dyn Sampler> {
+        match settings.sampler_type {
+            SamplerType::Flat => Box::new(FlatSampler::new(settings, estimate)),
+            SamplerType::Linear => Box::new(LinearSampler::new(settings, estimate)),
+            SamplerType::Random => Box::new(RandomSampler::new(settings, estimate, seed)),
+        }
+    }
+
+    /// Raw measurements of a test as written by [`PairedTest::run()`]
+    ///
+    /// Dumps start with a comment line stating the order of the first sample (see [`DUMP_FIRST_ORDER`]) or, if only
+    /// a reservoir of the samples is dumped, the number of the dumped samples (see [`DUMP_RESERVOIR`]). Dumps
+    /// without the order are assumed to start with the candidate. Dumps of the functions processing batches also
+    /// state the batch sizes (see [`DUMP_BATCH`]).
+    pub(crate) struct RawMeasurements {
+        baseline: Vec<u64>,
+        candidate: Vec<u64>,
+        iterations: Vec<usize>,
+        first: SampleOrder,
+        batch: [usize; 2],
+    }
+
+    impl RawMeasurements {
+        /// Calculates results the same way a live run does, but with given settings
+        pub fn analyze(&self, name: &str, settings: &MeasurementSettings) -> Option<RunResult> {
+            calculate_run_result_raw(
+                name,
+                &self.baseline,
+                &self.candidate,
+                &self.iterations,
+                self.batch,
+                self.first,
+                settings,
+            )
+        }
+    }
+
+    /// Prefix of the dump line stating the order of the first sample
+    const DUMP_FIRST_ORDER: &str = "# first:";
+
+    /// Prefix of the dump line stating the batch sizes of baseline and candidate
+    const DUMP_BATCH: &str = "# batch:";
+
+    /// Prefix of the dump line stating that only a random subset of the samples is dumped (eg. `# reservoir: 1000
+    /// of 5000000`)
+    ///
+    /// Such a dump skips samples, so the order of the calls in each sample is not known.
+    const DUMP_RESERVOIR: &str = "# reservoir:";
+
+    /// File of a dump directory mapping dump file names to test names
+    ///
+    /// Each line is a file name and a test name separated by a tab. Appended for every dump written, so the last
+    /// line for a file wins.
+    pub(crate) const DUMP_NAMES_FILE: &str = "names.tsv";
+
+    /// Escapes a test name to be used as a dump file name
+    ///
+    /// Characters not allowed in file names on some platforms (`/\:*?"<>|`), control characters and `%` itself are
+    /// replaced with `%XX`, where `XX` is a hex code of the byte. Escaping is reversed by [`unescape_dump_name()`].
+    pub(crate) fn escape_dump_name(name: &str) -> String {
+        let mut escaped = String::with_capacity(name.len());
+        for c in name.chars() {
+            if c.is_ascii_control() || "/\\:*?\"<>|%".contains(c) {
+                escaped.push_str(&format!("%{:02X}", c as u8));
+            } else {
+                escaped.push(c);
+            }
+        }
+        escaped
+    }
+
+    /// Restores a test name from a dump file name (see [`escape_dump_name()`])
+    ///
+    /// Invalid escape sequences are kept as is.
+    pub(crate) fn unescape_dump_name(escaped: &str) -> String {
+        let mut name = String::with_capacity(escaped.len());
+        let mut rest = escaped;
+        while let Some(idx) = rest.find('%') {
+            name.push_str(&rest[..idx]);
+            rest = &rest[idx..];
+            let code = rest
+                .get(1..3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match code {
+                Some(code) if code.is_ascii() => {
+                    name.push(code as char);
+                    rest = &rest[3..];
+                }
+                _ => {
+                    name.push('%');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        name.push_str(rest);
+        name
+    }
+
+    /// Writes measurements of a test to a given directory, returning the path of the dump
+    ///
+    /// The directory is created if it doesn't exist and the test name is recorded in [`DUMP_NAMES_FILE`].
+    /// `reservoir` is the number of the dumped and all the samples if only a reservoir of them is dumped.
+    fn write_dump<A: Display, B: Display, C: Display>(
+        dir: &Path,
+        test_name: &str,
+        first: SampleOrder,
+        batch: [usize; 2],
+        reservoir: Option<(usize, usize)>,
+        values: impl IntoIterator<Item = (A, B, C)>,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let file_name = format!("{}.csv", escape_dump_name(test_name));
+        let path = dir.join(&file_name);
+        write_raw_measurements(&path, first, batch, reservoir, values)?;
+
+        let mut names = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(DUMP_NAMES_FILE))?;
+        writeln!(names, "{}\t{}", file_name, test_name)?;
+        Ok(path)
+    }
+
+    /// Reads [`DUMP_NAMES_FILE`] of a dump directory, missing file is treated as empty
+    fn read_dump_names(dir: &Path) -> Result<HashMap<String, String>> {
+        let content = match fs::read_to_string(dir.join(DUMP_NAMES_FILE)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e).context("Unable to read dump names"),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(file, name)| (file.to_string(), name.to_string()))
+            .collect())
+    }
+
+    /// Finds dumps in given files and directories, returning them along with the test names
+    ///
+    /// Test names are taken from the index of the directory, [`DUMP_NAMES_FILE`] or restored from the file names
+    /// if both are missing. Dumps recorded in the index come first in the order the tests were run.
+    pub(crate) fn find_dumps(inputs: &[PathBuf]) -> Result<Vec<(String, PathBuf)>> {
+        let mut dumps = vec![];
+        let mut names = HashMap::new();
+        for input in inputs {
+            if input.is_dir() {
+                for (file, name) in read_dump_names(input)? {
+                    names.insert(input.join(file), name);
+                }
+                let index = DumpIndex::read(input)
+                    .with_context(|| format!("Unable to read dump index: {}", input.display()))?
+                    .map(|index| index.dumps)
+                    .unwrap_or_default();
+                for record in &index {
+                    names.insert(input.join(&record.file), record.test.clone());
+                }
+                let mut files = vec![];
+                for entry in fs::read_dir(input)
+                    .with_context(|| format!("Unable to read directory: {}", input.display()))?
+                {
+                    let path = entry?.path();
+                    if path.extension().is_some_and(|e| e == "csv") {
+                        files.push(path);
+                    }
+                }
+                files.sort_by_cached_key(|path| {
+                    let file = path.file_name().unwrap_or_default();
+                    let position = index.iter().position(|d| file == d.file.as_str());
+                    (position.unwrap_or(usize::MAX), path.clone())
+                });
+                dumps.extend(files);
+            } else {
+                dumps.push(input.clone());
+            }
+        }
+        Ok(dumps
+            .into_iter()
+            .map(|path| {
+                let name = names.remove(&path).unwrap_or_else(|| {
+                    let stem = path.file_stem().unwrap_or_default();
+                    unescape_dump_name(&stem.to_string_lossy())
+                });
+                (name, path)
+            })
+            .collect())
+    }
+
+    pub(crate) fn read_raw_measurements(path: impl AsRef<Path>) -> Result<RawMeasurements> {
+        let mut measurements = RawMeasurements {
+            baseline: vec![],
+            candidate: vec![],
+            iterations: vec![],
+            first: SampleOrder::CandidateFirst,
+            batch: [1, 1],
+        };
+        for (no, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if let Some(batch) = line.strip_prefix(DUMP_BATCH) {
+                let mut sizes = batch.split_whitespace().map(str::parse::<usize>);
+                measurements.batch = match (sizes.next(), sizes.next(), sizes.next()) {
+                    (Some(Ok(a)), Some(Ok(b)), None) if a > 0 && b > 0 => [a, b],
+                    _ => bail!("Invalid batch sizes at line {}: {}", no + 1, line),
+                };
+                continue;
+            }
+            if line.starts_with(DUMP_RESERVOIR) {
+                continue;
+            }
+            if let Some(order) = line.strip_prefix(DUMP_FIRST_ORDER) {
+                measurements.first = match order.trim() {
+                    "baseline" => SampleOrder::BaselineFirst,
+                    "candidate" => SampleOrder::CandidateFirst,
+                    _ => bail!(
+                        "Invalid order of the first sample at line {}: {}",
+                        no + 1,
+                        line
+                    ),
+                };
+                continue;
+            }
+            let parse = || -> Option<(u64, u64, usize)> {
+                let mut fields = line.split(',').map(str::trim);
+                let values = (
+                    fields.next()?.parse().ok()?,
+                    fields.next()?.parse().ok()?,
+                    fields.next()?.parse().ok()?,
+                );
+                fields.next().is_none().then_some(values)
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((baseline, candidate, iterations)) = parse() else {
+                bail!("Invalid measurement at line {}: {}", no + 1, line);
+            };
+            if iterations == 0 {
+                bail!("Zero iterations at line {}", no + 1);
+            }
+            measurements.baseline.push(baseline);
+            measurements.candidate.push(candidate);
+            measurements.iterations.push(iterations);
+        }
+        Ok(measurements)
+    }
+
+    /// Reads the samples of both functions measured by an external runner (see [`tango_core::raw`])
+    ///
+    /// Samples are paired by their position, so both files should have the same number of samples taken with the
+    /// same number of iterations.
+    pub(crate) fn read_device_measurements(
+        baseline: &Path,
+        candidate: &Path,
+    ) -> Result<(PairId, RawMeasurements)> {
+        let read = |path: &Path| {
+            fs::read(path)
+                .with_context(|| format!("Unable to read raw samples: {}", path.display()))
+        };
+        let (baseline_bytes, candidate_bytes) = (read(baseline)?, read(candidate)?);
+        let parse = |bytes, path: &Path| {
+            RawFile::parse(bytes)
+                .with_context(|| format!("Invalid raw samples: {}", path.display()))
+        };
+        let baseline = parse(&baseline_bytes, baseline)?;
+        let candidate = parse(&candidate_bytes, candidate)?;
+
+        let (a, b) = (baseline.header, candidate.header);
+        if a.iterations != b.iterations {
+            bail!(
+                "Samples are taken with different number of iterations: {} (baseline), {} (candidate)",
+                a.iterations,
+                b.iterations
+            );
+        }
+        if a.candidate_first != b.candidate_first {
+            bail!("Baseline and candidate state different order of the first sample");
+        }
+        let n = baseline.samples().len();
+        if n != candidate.samples().len() {
+            bail!(
+                "Baseline has {} samples, while candidate has {}",
+                n,
+                candidate.samples().len()
+            );
+        }
+        let measurements = RawMeasurements {
+            baseline: baseline.samples().collect(),
+            candidate: candidate.samples().collect(),
+            iterations: vec![a.iterations as usize; n],
+            first: if a.candidate_first {
+                SampleOrder::CandidateFirst
+            } else {
+                SampleOrder::BaselineFirst
+            },
+            batch: [1, 1],
+        };
+        Ok((PairId::new(a.name, b.name), measurements))
+    }
+
+    fn write_raw_measurements<A: Display, B: Display, C: Display>(
+        path: impl AsRef<Path>,
+        first: SampleOrder,
+        batch: [usize; 2],
+        reservoir: Option<(usize, usize)>,
+        values: impl IntoIterator<Item = (A, B, C)>,
+    ) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        // Reservoir skips samples, so the order of the calls can't be derived from the position of a sample
+        if let Some((dumped, total)) = reservoir {
+            writeln!(&mut file, "{} {} of {}", DUMP_RESERVOIR, dumped, total)?;
+        } else {
+            let first = match first {
+                SampleOrder::BaselineFirst => "baseline",
+                SampleOrder::CandidateFirst => "candidate",
+            };
+            writeln!(&mut file, "{} {}", DUMP_FIRST_ORDER, first)?;
+        }
+        if batch != [1, 1] {
+            writeln!(&mut file, "{} {} {}", DUMP_BATCH, batch[0], batch[1])?;
+        }
+        for (a, b, c) in values {
+            writeln!(&mut file, "{},{},{}", a, b, c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Watchdog detecting tests which stopped making progress
+///
+/// The sampling loop reports a heartbeat between samples. Monitor thread checks the heartbeat periodically and
+/// calls timeout handler if there was no heartbeat for a given time.
+mod watchdog {
+    use crate::id::TestId;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    type TimeoutHandler = Box<dyn Fn(&str, Duration) + Send>;
+
+    pub(crate) struct Watchdog {
+        state: Arc<State>,
+        monitor: Option<JoinHandle<()>>,
+    }
+
+    struct State {
+        /// Time of the watchdog creation heartbeats are counted from
+        epoch: Instant,
+
+        /// Time of the last heartbeat (in nanoseconds since `epoch`)
+        heartbeat: AtomicU64,
+
+        /// Name of the test being watched, `None` when watchdog is idle
+        test_name: Mutex<Option<TestId>>,
+
+        stop: AtomicBool,
+    }
+
+    impl State {
+        fn elapsed_since_heartbeat(&self) -> Duration {
+            let heartbeat = Duration::from_nanos(self.heartbeat.load(Ordering::Acquire));
+            self.epoch.elapsed().saturating_sub(heartbeat)
+        }
+    }
+
+    impl Watchdog {
+        /// Starts monitor thread which calls `on_timeout` with the test name if no heartbeat
+        /// happened within `timeout`
+        pub(crate) fn start(
+            timeout: Duration,
+            on_timeout: impl Fn(&str, Duration) + Send + 'static,
+        ) -> Self {
+            let state = Arc::new(State {
+                epoch: Instant::now(),
+                heartbeat: AtomicU64::new(0),
+                test_name: Mutex::new(None),
+                stop: AtomicBool::new(false),
+            });
+            let on_timeout: TimeoutHandler = Box::new(on_timeout);
+            let monitor = {
+                let state = Arc::clone(&state);
+                thread::spawn(move || monitor(&state, timeout, on_timeout))
+            };
+            Self {
+                state,
+                monitor: Some(monitor),
+            }
+        }
+
+        /// Starts watching a test. Watching continues until returned guard is dropped
+        pub(crate) fn watch(&self, test_name: TestId) -> WatchGuard<'_> {
+            self.beat();
+            *self.state.test_name.lock().unwrap() = Some(test_name);
+            WatchGuard(self)
+        }
+
+        pub(crate) fn beat(&self) {
+            let now = self.state.epoch.elapsed().as_nanos() as u64;
+            self.state.heartbeat.store(now, Ordering::Release);
+        }
+    }
+
+    impl Drop for Watchdog {
+        fn drop(&mut self) {
+            self.state.stop.store(true, Ordering::Release);
+            if let Some(monitor) = self.monitor.take() {
+                monitor.thread().unpark();
+                let _ = monitor.join();
+            }
+        }
+    }
+
+    pub(crate) struct WatchGuard<'a>(&'a Watchdog);
+
+    impl Drop for WatchGuard<'_> {
+        fn drop(&mut self) {
+            *self.0.state.test_name.lock().unwrap() = None;
+        }
+    }
+
+    fn monitor(state: &State, timeout: Duration, on_timeout: TimeoutHandler) {
+        let check_interval = (timeout / 4).max(Duration::from_millis(1));
+        while !state.stop.load(Ordering::Acquire) {
+            thread::park_timeout(check_interval);
+            // Heartbeat should be read under the lock, otherwise the heartbeat of the previous test
+            // might be attributed to the test which has just started
+            let mut test_name = state.test_name.lock().unwrap();
+            let elapsed = state.elapsed_since_heartbeat();
+            if elapsed > timeout {
+                if let Some(test_name) = test_name.take() {
+                    on_timeout(test_name.as_str(), elapsed);
+                }
+            }
+        }
+    }
+}
+
+/// Measuring tests in child processes, so a hanging test can be skipped instead of aborting the run
+/// (`--skip-on-timeout`)
+///
+/// A function which doesn't return can't be interrupted within the process. The harness runs its own executable
+/// with the description of the test in [`ISOLATED_VAR`]. The child measures the test with the same settings and seed
+/// and writes the result to a file. If the watchdog of the child detects a timeout, the child exits with
+/// [`EXIT_TIMEOUT`] and the test is reported as failed. With `--resource-limits` the child limits its resources
+/// before loading the baseline, and a child failing for any reason fails only its test.
+mod isolated {
+    use super::{
+        baseline_tests, commands::PairedTest, load_baseline, registered_tests, select_clock,
+        skip_reason, LoopMode, Result, EXIT_TIMEOUT,
+    };
+    use crate::{
+        dylib::Spi,
+        manifest::TestSchedule,
+        profile::{Profile, ProfileOverride},
+        resource_limits::{self, ResourceLimits},
+        Error, MeasurementSettings, RunResult, SampleObserver,
+    };
+    use anyhow::{bail, Context};
+    use rand::random;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        env,
+        ffi::OsString,
+        fs::{self, File},
+        io::BufWriter,
+        path::PathBuf,
+        process::{self, Command, ExitCode, Stdio},
+    };
+
+    /// Environment variable with the JSON of [`IsolatedTest`] given to the child process
+    const ISOLATED_VAR: &str = "TANGO_ISOLATED_TEST";
+
+    /// Runs each test in a child process (the parent side)
+    pub(crate) struct Isolation {
+        /// Executable the baseline functions are loaded from
+        pub(crate) baseline: PathBuf,
+        pub(crate) settings: MeasurementSettings,
+        pub(crate) seed: u64,
+        pub(crate) loop_mode: LoopMode,
+        pub(crate) dump: Option<PathBuf>,
+        pub(crate) profiles: Vec<ProfileOverride>,
+
+        /// Resources of the child process (`--resource-limits`)
+        pub(crate) limits: Option<ResourceLimits>,
+
+        /// Arguments of the child process (the ones the harness is started with)
+        pub(crate) args: Vec<OsString>,
+    }
+
+    impl Isolation {
+        /// Measures a test in a child process
+        ///
+        /// Returns [`Error::CallTimeout`] if the test made no progress for the call timeout and [`Error::ChildFailed`]
+        /// if the child with limited resources failed.
+        pub(crate) fn run(&self, name: &str, schedule: Option<&TestSchedule>) -> Result<RunResult> {
+            let output = env::temp_dir().join(format!(
+                "tango-isolated-{}-{:016x}.json",
+                process::id(),
+                random::<u64>()
+            ));
+            let test = IsolatedTest {
+                name: name.to_string(),
+                baseline: self.baseline.clone(),
+                settings: self.settings,
+                seed: self.seed,
+                loop_mode: self.loop_mode,
+                dump: self.dump.clone(),
+                profiles: self.profiles.clone(),
+                limits: self.limits,
+                schedule: schedule.cloned(),
+                output: output.clone(),
+            };
+            let child = Command::new(env::current_exe()?)
+                .args(&self.args)
+                .env(ISOLATED_VAR, serde_json::to_string(&test)?)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()
+                .context("Unable to start child process")?;
+            let outcome = fs::read(&output);
+            let _ = fs::remove_file(&output);
+            match child.status.code() {
+                Some(0) => {}
+                Some(code) if code == i32::from(EXIT_TIMEOUT) => {
+                    let timeout = self.settings.call_timeout.unwrap_or_default();
+                    return Err(Error::CallTimeout(timeout).into());
+                }
+                _ if self.limits.is_some() => {
+                    let stderr = String::from_utf8_lossy(&child.stderr);
+                    let message = format!("{}: {}", child.status, stderr.trim());
+                    return Err(Error::ChildFailed(message).into());
+                }
+                _ => bail!(
+                    "Child process measuring {} failed ({}): {}",
+                    name,
+                    child.status,
+                    String::from_utf8_lossy(&child.stderr).trim()
+                ),
+            }
+            let outcome = outcome.context("Child process didn't write the result")?;
+            match serde_json::from_slice(&outcome)? {
+                Outcome::Completed(result) => Ok(*result),
+                Outcome::Skipped(reason) => Err(Error::TestSkipped(reason).into()),
+            }
+        }
+    }
+
+    /// Test measured by the child process
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct IsolatedTest {
+        name: String,
+        baseline: PathBuf,
+        settings: MeasurementSettings,
+        seed: u64,
+        loop_mode: LoopMode,
+        dump: Option<PathBuf>,
+        profiles: Vec<ProfileOverride>,
+        limits: Option<ResourceLimits>,
+        schedule: Option<TestSchedule>,
+
+        /// File the [`Outcome`] is written to
+        output: PathBuf,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Outcome {
+        Completed(Box<RunResult>),
+        Skipped(String),
+    }
+
+    impl IsolatedTest {
+        /// Test the process should measure if it is a child process
+        pub(crate) fn from_env() -> Result<Option<Self>> {
+            let Some(json) = env::var_os(ISOLATED_VAR) else {
+                return Ok(None);
+            };
+            let json = json.to_string_lossy();
+            Ok(Some(
+                serde_json::from_str(&json).context("Invalid isolated test")?,
+            ))
+        }
+
+        /// Measures the test against the functions of the baseline executable (`built` are the settings of this
+        /// executable)
+        pub(crate) fn run(
+            self,
+            built: MeasurementSettings,
+            profiles: &[(String, Profile)],
+            observers: Vec<Box<dyn SampleObserver>>,
+        ) -> Result<ExitCode> {
+            self.apply_limits()?;
+            let spi_self = registered_tests(built.max_name_len, profiles)?;
+            let lib = load_baseline(&self.baseline)?;
+            let spi_lib = baseline_tests(&lib, &self.baseline, built.max_name_len)?;
+            select_clock(self.settings.clock)?;
+            spi_lib.push_settings(&self.settings);
+            self.run_with(&spi_lib, &spi_self, observers)
+        }
+
+        /// Limits the resources of this process if requested, before any of the measured code is loaded
+        pub(crate) fn apply_limits(&self) -> Result<()> {
+            if let Some(limits) = self.limits {
+                resource_limits::apply(limits)?;
+            }
+            Ok(())
+        }
+
+        /// Measures the test with given functions and writes the outcome
+        pub(crate) fn run_with(
+            self,
+            baseline: &Spi,
+            candidate: &Spi,
+            observers: Vec<Box<dyn SampleObserver>>,
+        ) -> Result<ExitCode> {
+            let mut paired_test = PairedTest::new(
+                baseline,
+                candidate,
+                self.settings,
+                Some(self.seed),
+                self.loop_mode,
+                self.dump,
+            );
+            for observer in observers {
+                paired_test.add_observer(observer);
+            }
+            paired_test.override_profiles(self.profiles);
+            let outcome = match paired_test.run(&self.name, self.schedule.as_ref()) {
+                Ok(result) => Outcome::Completed(Box::new(result)),
+                Err(e) => match skip_reason(&e) {
+                    Some(reason) => Outcome::Skipped(reason.to_string()),
+                    None => return Err(e),
+                },
+            };
+            let file = File::create(&self.output)
+                .with_context(|| format!("Unable to create {}", self.output.display()))?;
+            serde_json::to_writer(BufWriter::new(file), &outcome)?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+
+    /// Whether this process measures a test for its parent
+    pub(crate) fn is_child() -> bool {
+        env::var_os(ISOLATED_VAR).is_some()
+    }
+
+    /// Whether the test failed in its child process without affecting the run (timeout or a failure under limits)
+    pub(crate) fn is_contained(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::CallTimeout(_) | Error::ChildFailed(_))
+        )
+    }
+}
+
+/// Transient status line on stderr showing which phase of a test is running
+///
+/// The line is overwritten by the next status and erased before the results are reported.
+mod status {
+    use std::io::{stderr, Write};
+
+    /// ANSI sequence returning the cursor to the line start and erasing the line
+    const ERASE_LINE: &str = "\r\x1b[2K";
+
+    pub(super) fn show(test_name: &str, status: &str) {
+        let mut stderr = stderr().lock();
+        let _ = write!(stderr, "{}{} {}…", ERASE_LINE, test_name, status);
+        let _ = stderr.flush();
+    }
+
+    pub(super) fn clear() {
+        let mut stderr = stderr().lock();
+        let _ = write!(stderr, "{}", ERASE_LINE);
+        let _ = stderr.flush();
+    }
+}
+
+/// Pauses between tests letting the machine to cool down
+///
+/// Passively cooled machines are throttled after running for a while, so tests executed later in the suite are
+/// slower. Adaptive cooldown records the sensor value at the start of the run and waits until the value returns to
+/// it (within a band) before the next test.
+mod cooldown {
+    use crate::MeasurementSettings;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Adaptive cooldown waits for the temperature to be at most this much higher than at the start (°C)
+    const TEMPERATURE_BAND: f64 = 2.;
+
+    /// Adaptive cooldown waits for the frequency to be at most this fraction lower than at the start
+    const FREQUENCY_BAND: f64 = 0.05;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub(crate) struct Cooldown {
+        max: Duration,
+        adaptive: Option<Adaptive>,
+    }
+
+    struct Adaptive {
+        sensor: Sensor,
+        reference: f64,
+        poll_interval: Duration,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub(crate) enum SensorKind {
+        /// Temperature in °C
+        Temperature,
+
+        /// CPU frequency in kHz
+        Frequency,
+    }
+
+    pub(crate) struct Sensor {
+        kind: SensorKind,
+        read: Box<dyn FnMut() -> Option<f64>>,
+    }
+
+    impl Sensor {
+        pub(crate) fn new(kind: SensorKind, read: impl FnMut() -> Option<f64> + 'static) -> Self {
+            Self {
+                kind,
+                read: Box::new(read),
+            }
+        }
+
+        /// Checks if the current value is close enough to the reference one
+        fn recovered(&self, reference: f64, value: f64) -> bool {
+            match self.kind {
+                SensorKind::Temperature => value <= reference + TEMPERATURE_BAND,
+                SensorKind::Frequency => value >= reference * (1. - FREQUENCY_BAND),
+            }
+        }
+    }
+
+    impl Cooldown {
+        /// Creates cooldown according to the settings (`None` if cooldown is not configured or not available)
+        pub(crate) fn new(settings: &MeasurementSettings) -> Option<Self> {
+            let max = settings.cooldown?;
+            if !settings.adaptive_cooldown {
+                return Some(Self {
+                    max,
+                    adaptive: None,
+                });
+            }
+            let cooldown = sensor().and_then(|sensor| Self::adaptive(max, sensor, POLL_INTERVAL));
+            if cooldown.is_none() {
+                eprintln!("[WARN] Neither temperature nor CPU frequency sensors are available, cooldown is disabled");
+            }
+            cooldown
+        }
+
+        /// Creates adaptive cooldown using the current value of the sensor as a reference
+        pub(crate) fn adaptive(
+            max: Duration,
+            mut sensor: Sensor,
+            poll_interval: Duration,
+        ) -> Option<Self> {
+            let reference = (sensor.read)()?;
+            let adaptive = Adaptive {
+                sensor,
+                reference,
+                poll_interval,
+            };
+            Some(Self {
+                max,
+                adaptive: Some(adaptive),
+            })
+        }
+
+        /// Pauses the current thread returning the time spent
+        pub(crate) fn wait(&mut self) -> Duration {
+            let start = Instant::now();
+            match &mut self.adaptive {
+                None => thread::sleep(self.max),
+                Some(adaptive) => loop {
+                    let remaining = self.max.saturating_sub(start.elapsed());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    // Unreadable sensor is not going to get better, so the pause is finished
+                    let value = (adaptive.sensor.read)().unwrap_or(adaptive.reference);
+                    if adaptive.sensor.recovered(adaptive.reference, value) {
+                        break;
+                    }
+                    thread::sleep(adaptive.poll_interval.min(remaining));
+                },
+            }
+            start.elapsed()
+        }
+    }
+
+    /// Finds CPU temperature sensor or CPU frequency if there are no temperature sensors
+    #[cfg(target_os = "linux")]
+    fn sensor() -> Option<Sensor> {
+        use std::{fs, path::Path};
+
+        /// hwmon drivers of CPU temperature sensors (`temp1` is package or die temperature for all of them)
+        const CPU_SENSORS: [&str; 4] = ["coretemp", "k10temp", "zenpower", "cpu_thermal"];
+        const CPU_FREQUENCY: &str = "/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq";
+
+        let read =
+            |path: &Path| -> Option<f64> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+
+        let hwmon = fs::read_dir("/sys/class/hwmon")
+            .ok()?
+            .flatten()
+            .map(|e| e.path());
+        for dir in hwmon {
+            let name = fs::read_to_string(dir.join("name")).unwrap_or_default();
+            let input = dir.join("temp1_input");
+            if CPU_SENSORS.contains(&name.trim()) && read(&input).is_some() {
+                // Temperature is reported in millidegrees
+                let sensor = move || read(&input).map(|t| t / 1000.);
+                return Some(Sensor::new(SensorKind::Temperature, sensor));
+            }
+        }
+
+        let frequency = Path::new(CPU_FREQUENCY);
+        read(frequency)?;
+        Some(Sensor::new(SensorKind::Frequency, move || read(frequency)))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn sensor() -> Option<Sensor> {
+        None
+    }
+}
+
+pub mod reporting {
+    use crate::cli::{colorize, commands::escape_dump_name, verdict_marker};
+    use crate::format::{
+        escape_xml, Delta, Histogram, HumanCount, HumanThroughput, HumanTime, HumanValue,
+        PairedHistogram, ScatterPlot, SignedPercent, TimeUnit,
+    };
+    use crate::{
+        id::PairId,
+        noise::SentinelSummary,
+        registry::unit_name,
+        stats::{ConfidenceInterval, OverheadVariance, Verdict},
+        Confirmation, ObservedSummary, PhaseTimings, PrecisionStop, Reporter, RunResult,
+        SessionInfo, SessionMode, SessionSummary, SuiteSummary, Summary, Unit,
+    };
+    use colorz::{mode::Stream, Colorize};
+    use serde::{Deserialize, Serialize};
+    use std::{
+        borrow::Cow,
+        cmp::Ordering,
+        fs::{self, File},
+        io::{self, BufWriter, Write},
+        mem,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    /// Implements the given [`Reporter`] callbacks of a decorator by passing them to `self.reporter` as is
+    macro_rules! forward {
+        ($($callback:ident),+) => {
+            $(forward!(@ $callback);)+
+        };
+        (@ on_session_start) => {
+            fn on_session_start(&mut self, session: &SessionInfo) {
+                self.reporter.on_session_start(session);
+            }
+        };
+        (@ on_suite_start) => {
+            fn on_suite_start(&mut self, names: &[&str]) {
+                self.reporter.on_suite_start(names);
+            }
+        };
+        (@ on_test_start) => {
+            fn on_test_start(&mut self, name: &str) {
+                self.reporter.on_test_start(name);
+            }
+        };
+        (@ wants_samples) => {
+            fn wants_samples(&self) -> bool {
+                self.reporter.wants_samples()
+            }
+        };
+        (@ on_complete) => {
+            fn on_complete(&mut self, results: &RunResult) {
+                self.reporter.on_complete(results);
+            }
+        };
+        (@ on_finish) => {
+            fn on_finish(&mut self, totals: &PhaseTimings) {
+                self.reporter.on_finish(totals);
+            }
+        };
+        (@ on_suite_finish) => {
+            fn on_suite_finish(&mut self, results: &[RunResult]) {
+                self.reporter.on_suite_finish(results);
+            }
+        };
+        (@ on_summary) => {
+            fn on_summary(&mut self, summary: &SuiteSummary) {
+                self.reporter.on_summary(summary);
+            }
+        };
+        (@ on_warning) => {
+            fn on_warning(&mut self, name: &str, message: &str) {
+                self.reporter.on_warning(name, message);
+            }
+        };
+        (@ on_error) => {
+            fn on_error(&mut self, name: &str, message: &str) {
+                self.reporter.on_error(name, message);
+            }
+        };
+        (@ on_failure) => {
+            fn on_failure(&mut self, name: &str, message: &str) {
+                self.reporter.on_failure(name, message);
+            }
+        };
+        (@ on_skipped) => {
+            fn on_skipped(&mut self, name: &str, reason: &str) {
+                self.reporter.on_skipped(name, reason);
+            }
+        };
+        (@ on_session_end) => {
+            fn on_session_end(&mut self, summary: &SessionSummary) {
+                self.reporter.on_session_end(summary);
+            }
+        };
+    }
+
+    /// Number of the buckets of sample distributions in verbose output
+    const HISTOGRAM_BUCKETS: usize = 20;
+
+    /// Height of the histograms in verbose output (in rows)
+    const HISTOGRAM_HEIGHT: usize = 2;
+
+    /// Width of the bars of the histogram of the differences in verbose output (in columns)
+    const DIFF_HISTOGRAM_WIDTH: usize = 40;
+
+    /// Test name annotated with the measurement mode and whether the result is replayed from the cache
+    pub(crate) fn display_name(results: &RunResult) -> Cow<'_, str> {
+        let tags = [
+            (results.cold, "cold"),
+            (results.e2e, "e2e"),
+            (results.per_element, "per element"),
+            (results.cached, "cached"),
+            (
+                results.confirmation == Some(Confirmation::Confirmed),
+                "confirmed",
+            ),
+            (
+                results.confirmation == Some(Confirmation::Unconfirmed),
+                "unconfirmed",
+            ),
+        ]
+        .iter()
+        .filter(|(tagged, _)| *tagged)
+        .map(|(_, tag)| *tag)
+        .collect::<Vec<_>>();
+        if tags.is_empty() {
+            Cow::Borrowed(&results.name)
+        } else {
+            Cow::Owned(format!("{} ({})", results.name, tags.join(", ")))
+        }
+    }
+
+    /// Reports nothing but the warnings and errors (to stderr), so stdout is left to `--ndjson -`
+    pub(super) struct Silent;
+
+    impl Reporter for Silent {
+        fn on_complete(&mut self, _results: &RunResult) {}
+    }
+
+    #[derive(Default)]
+    pub(super) struct VerboseReporter {
+        pub(super) time_unit: TimeUnit,
+    }
+
+    impl Reporter for VerboseReporter {
+        fn on_session_start(&mut self, session: &SessionInfo) {
+            let source = match session.mode {
+                SessionMode::Compare { baseline } => format!("against {}", baseline.display()),
+                SessionMode::Pairs => "pairs".to_string(),
+            };
+            let filter = if session.filter.is_empty() {
+                String::new()
+            } else {
+                format!(", filter: {}", session.filter)
+            };
+            println!(
+                "{} {}  (tests: {}, seed: {}{})",
+                "Comparing".bold().stream(Stream::Stdout),
+                source,
+                session.tests.len(),
+                session.master_seed,
+                filter
+            );
+            let env = &session.environment;
+            println!(
+                "    tango {}, {}/{}, sampler: {:?}, outliers: {:?}",
+                env.tango_version,
+                env.os,
+                env.arch,
+                session.settings.sampler_type,
+                session.settings.outlier_detection
+            );
+            if let Some(load) = env.injected_load {
+                println!(
+                    "    {} {}",
+                    "injected load:".red().stream(Stream::Stdout),
+                    load
+                );
+            }
+            if let Some(floor) = env.noise_floor {
+                let label = "noise floor:";
+                let label = if floor.exceeded() {
+                    label.red().stream(Stream::Stdout).to_string()
+                } else {
+                    label.to_string()
+                };
+                println!("    {} {}", label, floor);
+            }
+            if let Some(timer) = env.timer {
+                println!("    timer: {}", timer);
+            }
+            println!();
+        }
+
+        fn on_complete(&mut self, results: &RunResult) {
+            let base = results.baseline;
+            let candidate = results.candidate;
+            let value = |v| HumanValue::new(results.unit, v).with_time_unit(self.time_unit);
+
+            let significant = results.diff_estimate.significant;
+
+            print!(
+                "{}  (n: {}, outliers: {}",
+                display_name(results).bold().stream(Stream::Stdout),
+                HumanCount::from(results.diff.n),
+                HumanCount::from(results.outliers)
+            );
+            if results.discarded > 0 {
+                print!(", discarded: {}", HumanCount::from(results.discarded));
+            }
+            if results.weight != 1. {
+                print!(", weight: {}", results.weight);
+            }
+            if let Some(profile) = &results.profile {
+                print!(", profile: {}", profile);
+            }
+            println!(")");
+
+            println!(
+                "    {:12}   {:>15} {:>15} {:>15}",
+                "",
+                "baseline".bold().stream(Stream::Stdout),
+                "candidate".bold().stream(Stream::Stdout),
+                "∆".bold().stream(Stream::Stdout),
+            );
+            println!(
+                "    {:12} ╭────────────────────────────────────────────────",
+                ""
+            );
+            println!(
+                "    {:12} │ {:>15} {:>15} {:>15}  {}{}",
+                "mean",
+                value(base.mean),
+                value(candidate.mean),
+                colorize(
+                    value(results.diff.mean),
+                    significant,
+                    results.diff.mean < 0.
+                ),
+                colorize(
+                    SignedPercent(results.diff_estimate.pct),
+                    significant,
+                    results.diff.mean < 0.
+                ),
+                verdict_marker(results.diff_estimate.verdict),
+            );
+            println!(
+                "    {:12} │ {:49}{}",
+                "99% CI",
+                "",
+                Delta {
+                    pct: results.diff_estimate.pct,
+                    ci: results.diff_estimate.ci
+                },
+            );
+            println!(
+                "    {:12} │ {:>15} {:>15} {:>15}",
+                "min",
+                value(base.min),
+                value(candidate.min),
+                value(candidate.min - base.min)
+            );
+            println!(
+                "    {:12} │ {:>15} {:>15} {:>15}",
+                "max",
+                value(base.max),
+                value(candidate.max),
+                value(candidate.max - base.max),
+            );
+            println!(
+                "    {:12} │ {:>15} {:>15} {:>15}",
+                "std. dev.",
+                value(base.variance.sqrt()),
+                value(candidate.variance.sqrt()),
+                value(results.diff.variance.sqrt()),
+            );
+            if let Some(p) = results.percentiles {
+                let (b, c, d) = (p.baseline, p.candidate, p.diff);
+                for (name, b, c, d) in [
+                    ("p50", b.p50, c.p50, d.p50),
+                    ("p95", b.p95, c.p95, d.p95),
+                    ("p99", b.p99, c.p99, d.p99),
+                ] {
+                    println!(
+                        "    {:12} │ {:>15} {:>15} {:>15}",
+                        name,
+                        value(b),
+                        value(c),
+                        value(d)
+                    );
+                }
+            }
+            if let Some(rates) = results.throughput {
+                let rate = |per_second| HumanThroughput {
+                    unit: rates.unit,
+                    per_second,
+                };
+                println!(
+                    "    {:12} │ {:>15} {:>15}",
+                    "throughput",
+                    rate(rates.baseline),
+                    rate(rates.candidate),
+                );
+            }
+            if results.outliers > 0 {
+                println!(
+                    "    {:12} │ {:>15} {:>15} {:>15}",
+                    "outliers",
+                    HumanCount::from(results.outliers_baseline),
+                    HumanCount::from(results.outliers_candidate),
+                    format!("{} unattr.", results.outliers_unattributed),
+                );
+            }
+            if let (Some([candidate_first, baseline_first]), Some(bias)) =
+                (results.diff_by_ordering, results.order_bias)
+            {
+                for (name, summary) in [
+                    ("cand. first", candidate_first),
+                    ("base. first", baseline_first),
+                ] {
+                    println!(
+                        "    {:12} │ {:>15} {:>15} {:>15}",
+                        name,
+                        "",
+                        "",
+                        value(summary.mean)
+                    );
+                }
+                println!(
+                    "    {:12} │ {:>15} {:>15} {:>15}  {}{}",
+                    "order bias",
+                    "",
+                    "",
+                    value(bias.bias),
+                    SignedPercent(bias.pct),
+                    if bias.significant { "*" } else { "" },
+                );
+                if results.diff_estimate.order_biased {
+                    println!(
+                        "    {:12} │ inconclusive: order bias exceeds the measured effect",
+                        ""
+                    );
+                }
+            }
+            if let Some(curves) = &results.schedule.batch_curve {
+                for (name, curve) in [("base. batch", &curves[0]), ("cand. batch", &curves[1])] {
+                    let points = curve
+                        .points
+                        .iter()
+                        .map(|(size, v)| format!("{}: {}", size, value(*v)))
+                        .collect::<Vec<_>>();
+                    println!(
+                        "    {:12} │ {}  ({})",
+                        name,
+                        points.join(", "),
+                        SignedPercent(curve.change_pct())
+                    );
+                }
+            }
+            if let Some(tail) = results.tail {
+                let (b, c) = (tail.baseline, tail.candidate);
+                let deltas = tail.deltas();
+                for (name, b, c, delta) in [
+                    ("call p99", b.p99, c.p99, deltas[0]),
+                    ("call p99.9", b.p999, c.p999, deltas[1]),
+                    ("call max", b.max, c.max, deltas[2]),
+                ] {
+                    println!(
+                        "    {:12} │ {:>15} {:>15} {:>15}  {}",
+                        name,
+                        value(b),
+                        value(c),
+                        value(c - b),
+                        SignedPercent(delta)
+                    );
+                }
+                println!(
+                    "    {:12} │ {} calls of each function, timer overhead {} per call",
+                    "",
+                    HumanCount::from(tail.calls),
+                    value(tail.timer_overhead as f64)
+                );
+            }
+            if let Some(overhead) = results.overhead_variance {
+                println!(
+                    "    {:12} │ {:.0}% of the variance (null σ {}, workload σ {}), empty function takes {} ({} samples)",
+                    "overhead",
+                    overhead.overhead_fraction * 100.,
+                    value(overhead.null_variance.sqrt()),
+                    value(overhead.excess_variance.sqrt()),
+                    value(overhead.overhead),
+                    HumanCount::from(overhead.samples)
+                );
+            }
+            if let Some(slow_call) = results.schedule.slow_call {
+                let note = match results.schedule.extended_duration {
+                    Some(extended) => format!("time limit extended to {}", self.time(extended)),
+                    None => "time limit is not extended, results may be unreliable".to_string(),
+                };
+                println!(
+                    "    {:12} │ single call takes {}, {}",
+                    "slow call",
+                    self.time(slow_call),
+                    note
+                );
+            }
+            if let Some(precision) = results.precision {
+                let stop = match precision.stop {
+                    PrecisionStop::Reached => "target reached",
+                    PrecisionStop::TimeCap => "time cap reached",
+                };
+                println!(
+                    "    {:12} │ ±{:.2}%  ({} after {} samples)",
+                    "precision",
+                    precision.half_width,
+                    stop,
+                    HumanCount::from(results.schedule.samples)
+                );
+            }
+            for observed in &results.observed {
+                let mean = |s: Option<Summary<f64>>| s.map(|s| s.mean);
+                let value = |v: Option<f64>| {
+                    v.map(|v| HumanValue::new(observed.unit, v).with_time_unit(self.time_unit))
+                        .map(|v| v.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                };
+                let (base, candidate) = (mean(observed.baseline), mean(observed.candidate));
+                let diff = base.zip(candidate).map(|(b, c)| c - b);
+                let change = observed
+                    .diff_estimate
+                    .as_ref()
+                    .map(|e| format!("  {}{}", SignedPercent(e.pct), verdict_marker(e.verdict)))
+                    .unwrap_or_default();
+                println!(
+                    "    {:12} │ {:>15} {:>15} {:>15}{}",
+                    observed.name,
+                    value(base),
+                    value(candidate),
+                    value(diff),
+                    change,
+                );
+            }
+            if results.unit == Unit::Nanoseconds {
+                let time = [results.baseline.mean, results.candidate.mean];
+                if let Some([base, candidate]) =
+                    ObservedSummary::time_per_energy(&results.observed, time)
+                {
+                    let ratio = |v: Option<f64>| {
+                        v.map(|v| format!("{:.1} ns/uJ", v))
+                            .unwrap_or_else(|| "-".to_string())
+                    };
+                    println!(
+                        "    {:12} │ {:>15} {:>15}",
+                        "time/energy",
+                        ratio(base),
+                        ratio(candidate),
+                    );
+                }
+            }
+            if let Some([base, candidate]) = ObservedSummary::system_share(&results.observed) {
+                let share = |v: Option<f64>| {
+                    v.map(|v| format!("{:.1}%", v))
+                        .unwrap_or_else(|| "-".to_string())
+                };
+                let diff = base
+                    .zip(candidate)
+                    .map(|(b, c)| format!("{:+.1} pp", c - b))
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "    {:12} │ {:>15} {:>15} {:>15}",
+                    "sys_share",
+                    share(base),
+                    share(candidate),
+                    diff,
+                );
+            }
+            if let Some(reason) = missing_histogram_reason(results) {
+                println!("    {:12} │ not shown, {}", "histogram", reason);
+            }
+            if let Some(histogram) = results.samples.as_ref().and_then(|[base, candidate]| {
+                PairedHistogram::new(base, candidate, HISTOGRAM_BUCKETS)
+            }) {
+                for (label, line) in histogram.lines(HISTOGRAM_HEIGHT) {
+                    println!("    {:12} │ {}", label, line);
+                }
+                let (min, max) = histogram.range();
+                let width = HISTOGRAM_BUCKETS / 2;
+                println!(
+                    "    {:12} │ {:<width$}{:>width$}  (^ mean, m median)",
+                    "",
+                    value(min),
+                    value(max),
+                    width = width
+                );
+            }
+            // Paired samples are retained in the same order, so their differences are the filtered differences
+            if let Some(histogram) = results.samples.as_ref().and_then(|[base, candidate]| {
+                let diffs = base.iter().zip(candidate).map(|(b, c)| c - b);
+                Histogram::spanning(&diffs.collect::<Vec<_>>(), HISTOGRAM_BUCKETS)
+            }) {
+                let bars = histogram.horizontal_bars(DIFF_HISTOGRAM_WIDTH);
+                for (bucket, bar) in bars.into_iter().enumerate() {
+                    let (from, to) = histogram.bucket_range(bucket);
+                    let line = format!(
+                        "    {:12} │ {:>10} .. {:>10} {:>7} {}",
+                        if bucket == 0 { "diff" } else { "" },
+                        value(from),
+                        value(to),
+                        HumanCount::from(histogram.counts()[bucket]),
+                        bar
+                    );
+                    println!("{}", line.trim_end());
+                }
+            }
+            let phases = shown_phases(&results.phases)
+                .iter()
+                .map(|(name, d)| format!("{}: {}", name, self.time(*d)))
+                .collect::<Vec<_>>();
+            println!("    {:12}   {}", "time", phases.join(", "));
+            println!();
+        }
+
+        fn on_finish(&mut self, totals: &PhaseTimings) {
+            if totals.total().is_zero() {
+                return;
+            }
+            let phases = shown_phases(totals)
+                .iter()
+                .map(|(name, d)| {
+                    let pct = totals.fraction(*d) * 100.;
+                    format!("{}: {} ({:.0}%)", name, self.time(*d), pct)
+                })
+                .collect::<Vec<_>>();
+            println!(
+                "{}  {}  ({})",
+                "Total time".bold().stream(Stream::Stdout),
+                self.time(totals.total()),
+                phases.join(", ")
+            );
+        }
+
+        fn on_summary(&mut self, summary: &SuiteSummary) {
+            if summary.tests() > 1 {
+                println!("{}", summary_line(summary));
+            }
+        }
+
+        fn on_session_end(&mut self, summary: &SessionSummary) {
+            let between_tests = summary.wall_time.saturating_sub(summary.phases.total());
+            println!(
+                "{}  {}  ({} between tests)",
+                "Wall time".bold().stream(Stream::Stdout),
+                self.time(summary.wall_time),
+                self.time(between_tests)
+            );
+            if let Some(dir) = summary.dumps.first().and_then(|p| p.parent()) {
+                println!(
+                    "{}  {} tests to {}",
+                    "Dumped".bold().stream(Stream::Stdout),
+                    summary.dumps.len(),
+                    dir.display()
+                );
+            }
+            if let Some(sentinels) = &summary.sentinels {
+                report_sentinels(sentinels, true);
+            }
+        }
+    }
+
+    /// Prints the noise floor measured by the sentinels and warns if the session is flagged
+    fn report_sentinels(sentinels: &SentinelSummary, detailed: bool) {
+        for line in sentinel_lines(sentinels, detailed) {
+            println!("{}", line);
+        }
+        if let Some(warning) = sentinel_warning(sentinels) {
+            eprintln!("[WARN] {}", warning);
+        }
+    }
+
+    /// Noise floor section of the summary, `detailed` adds a line per sentinel run
+    fn sentinel_lines(sentinels: &SentinelSummary, detailed: bool) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{}  {}",
+            "Noise floor".bold().stream(Stream::Stdout),
+            sentinels
+        )];
+        if detailed {
+            lines.extend(sentinels.runs.iter().map(|run| {
+                format!(
+                    "  {:<6}  {:<20}  {:>8}{}",
+                    run.slot.to_string(),
+                    run.name,
+                    SignedPercent(run.pct).to_string(),
+                    if run.significant { "  significant" } else { "" }
+                )
+            }));
+        }
+        lines
+    }
+
+    /// Warning flagging the whole session if some of the sentinels show a significant difference
+    fn sentinel_warning(sentinels: &SentinelSummary) -> Option<String> {
+        if !sentinels.is_flagged() {
+            return None;
+        }
+        let flagged = sentinels
+            .flagged()
+            .map(|run| {
+                format!(
+                    "{} at the {} ({})",
+                    run.name,
+                    run.slot,
+                    SignedPercent(run.pct)
+                )
+            })
+            .collect::<Vec<_>>();
+        Some(format!(
+            "Session is flagged: identical functions show a significant difference: {}. Differences of this size may be caused by the environment",
+            flagged.join(", ")
+        ))
+    }
+
+    /// Geometric mean of all the tests along with the weighted regression
+    fn summary_line(summary: &SuiteSummary) -> String {
+        format!(
+            "{}  {} over {} tests (weighted regression: {:.2}%)",
+            "Geomean".bold().stream(Stream::Stdout),
+            SignedPercent(summary.geomean_pct()),
+            summary.tests(),
+            summary.weighted_regression()
+        )
+    }
+
+    /// Phases worth reporting: cooldown and null sampling are omitted if they are not configured
+    fn shown_phases(phases: &PhaseTimings) -> Vec<(&'static str, Duration)> {
+        phases
+            .phases()
+            .into_iter()
+            .filter(|(name, d)| !matches!(*name, "cooldown" | "null") || !d.is_zero())
+            .collect()
+    }
+
+    /// Why the histograms of a result can't be drawn (`None` if its samples are retained)
+    fn missing_histogram_reason(results: &RunResult) -> Option<&'static str> {
+        match (&results.samples, results.cached) {
+            (Some(_), _) => None,
+            (None, true) => Some("samples of cached results are not stored"),
+            (None, false) => {
+                Some("samples are not retained in streaming mode or for pooled results")
+            }
+        }
+    }
+
+    impl VerboseReporter {
+        fn time(&self, duration: Duration) -> HumanTime {
+            HumanTime::new(duration.as_nanos() as f64).with_unit(self.time_unit)
+        }
+    }
+
+    /// Test names longer than this are shortened unless configured otherwise
+    pub(super) const DEFAULT_NAME_WIDTH: usize = 60;
+
+    /// Minimal width of the name column, so short names of different groups are aligned
+    const MIN_NAME_WIDTH: usize = 20;
+
+    /// Reports a line per test
+    ///
+    /// Results are buffered until all the tests of a group (tests with the same name prefix before the first `/`,
+    /// eg. a function of a [`crate::BenchmarkMatrix`] with different generators) are completed, so the columns of
+    /// a group are aligned by the longest name in the group.
+    pub(super) struct ConsoleReporter {
+        time_unit: TimeUnit,
+        max_name_width: usize,
+
+        /// Show the weight column (only if some of the tests have non-default weight)
+        weights: bool,
+        rows: Vec<ConsoleRow>,
+
+        /// First segment of the name of the tests the buffered rows belong to
+        group: Option<String>,
+    }
+
+    struct ConsoleRow {
+        name: String,
+        weight: f64,
+        baseline: String,
+        candidate: String,
+        change: SignedPercent,
+        verdict: Verdict,
+        significant: bool,
+        candidate_faster: bool,
+
+        /// Data processed per second by the candidate (see [`Generator::haystack_size()`])
+        ///
+        /// [`Generator::haystack_size()`]: crate::Generator::haystack_size()
+        throughput: Option<HumanThroughput>,
+    }
+
+    impl ConsoleReporter {
+        pub(super) fn new(time_unit: TimeUnit, max_name_width: usize) -> Self {
+            Self {
+                time_unit,
+                max_name_width: max_name_width.max(MIN_NAME_WIDTH),
+                weights: false,
+                rows: vec![],
+                group: None,
+            }
+        }
+
+        /// Enables the weight column
+        pub(super) fn with_weights(self, weights: bool) -> Self {
+            Self { weights, ..self }
+        }
+
+        fn flush(&mut self) {
+            for line in self.render() {
+                println!("{}", line);
+            }
+            self.rows.clear();
+        }
+
+        fn render(&self) -> Vec<String> {
+            let names = fit_names(
+                self.rows.iter().map(|r| r.name.as_str()),
+                self.max_name_width,
+            );
+            let width = names
+                .iter()
+                .map(|n| n.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(MIN_NAME_WIDTH);
+            self.rows
+                .iter()
+                .zip(names)
+                .map(|(row, name)| {
+                    let (significant, faster) = (row.significant, row.candidate_faster);
+                    let weight = if self.weights {
+                        format!(" {:>6}", format!("×{}", row.weight))
+                    } else {
+                        String::new()
+                    };
+                    let throughput = match row.throughput {
+                        Some(throughput) => format!("  {:>12}", throughput),
+                        None => String::new(),
+                    };
+                    format!(
+                        "{:width$}{} [ {:>8} ... {:>8} ]    {:>8}{}{}",
+                        colorize(name, significant, faster),
+                        weight,
+                        row.baseline,
+                        colorize(&row.candidate, significant, faster),
+                        colorize(row.change, significant, faster),
+                        verdict_marker(row.verdict),
+                        throughput,
+                        width = width,
+                    )
+                })
+                .collect()
+        }
+    }
+
+    impl Reporter for ConsoleReporter {
+        fn on_complete(&mut self, results: &RunResult) {
+            // A/A comparisons are grouped with the pair they belong to
+            let test = results.aa_of.as_ref().unwrap_or(&results.name);
+            let group = test.split('/').next().map(str::to_string);
+            if !self.rows.is_empty() && self.group != group {
+                self.flush();
+            }
+            self.group = group;
+
+            let value = |v| {
+                HumanValue::new(results.unit, v)
+                    .with_time_unit(self.time_unit)
+                    .to_string()
+            };
+            self.rows.push(ConsoleRow {
+                name: display_name(results).into_owned(),
+                weight: results.weight,
+                baseline: value(results.baseline.mean),
+                candidate: value(results.candidate.mean),
+                change: SignedPercent(results.diff_estimate.pct),
+                verdict: results.diff_estimate.verdict,
+                significant: results.diff_estimate.significant,
+                candidate_faster: results.diff.mean < 0.,
+                throughput: results.throughput.map(|rates| HumanThroughput {
+                    unit: rates.unit,
+                    per_second: rates.candidate,
+                }),
+            });
+        }
+
+        fn on_finish(&mut self, _totals: &PhaseTimings) {
+            self.flush();
+        }
+
+        fn on_summary(&mut self, summary: &SuiteSummary) {
+            if summary.tests() > 1 {
+                println!("{}", summary_line(summary));
+            }
+        }
+
+        fn on_session_end(&mut self, summary: &SessionSummary) {
+            if let Some(sentinels) = &summary.sentinels {
+                report_sentinels(sentinels, false);
+            }
+        }
+    }
+
+    /// Shortens names longer than `max_width` characters by replacing their middle part with `…`
+    ///
+    /// If several names are shortened to the same string, a hash of the full name is appended to each of them,
+    /// so the names stay distinguishable.
+    fn fit_names<'a>(names: impl Iterator<Item = &'a str>, max_width: usize) -> Vec<String> {
+        let names = names.collect::<Vec<_>>();
+        let shortened = names
+            .iter()
+            .map(|n| shorten(n, max_width, ""))
+            .collect::<Vec<_>>();
+        names
+            .iter()
+            .zip(&shortened)
+            .map(|(name, short)| {
+                let collides = shortened.iter().filter(|s| *s == short).count() > 1;
+                if collides && short != name {
+                    shorten(name, max_width, &format!("~{:06x}", name_hash(name)))
+                } else {
+                    short.clone()
+                }
+            })
+            .collect()
+    }
+
+    fn shorten(name: &str, max_width: usize, suffix: &str) -> String {
+        let chars = name.chars().count();
+        if chars <= max_width {
+            return name.to_string();
+        }
+        let keep = max_width.saturating_sub(1 + suffix.chars().count());
+        let head = keep.div_ceil(2);
+        let tail = keep - head;
+        let mut result = name.chars().take(head).collect::<String>();
+        result.push('…');
+        result.extend(name.chars().skip(chars - tail));
+        result.push_str(suffix);
+        result
+    }
+
+    /// 24-bit FNV-1a hash of a name
+    fn name_hash(name: &str) -> u32 {
+        let mut hash = 0xcbf29ce484222325_u64;
+        for byte in name.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        (hash & 0xFF_FFFF) as u32
+    }
+
+    /// Order the results are reported in with `--sort`
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+    pub(crate) enum SortOrder {
+        /// By the name split into `/`-separated segments, numeric segments are compared as numbers (eg. `sort/100`
+        /// goes before `sort/1000`)
+        Name,
+
+        /// Regressions first, from the largest change to the largest improvement
+        Change,
+
+        /// Significant changes first, each from the largest absolute change
+        Significance,
+
+        /// Slowest candidate first
+        Time,
+    }
+
+    impl SortOrder {
+        /// Compares results in this order. Ties are broken by the names, so the order of the same set of results
+        /// is always the same
+        pub(crate) fn compare(self, a: &RunResult, b: &RunResult) -> Ordering {
+            let (a_pct, b_pct) = (a.diff_estimate.pct, b.diff_estimate.pct);
+            let order = match self {
+                SortOrder::Name => Ordering::Equal,
+                SortOrder::Change => b_pct.total_cmp(&a_pct),
+                SortOrder::Significance => b
+                    .diff_estimate
+                    .significant
+                    .cmp(&a.diff_estimate.significant)
+                    .then(b_pct.abs().total_cmp(&a_pct.abs())),
+                SortOrder::Time => b.candidate.mean.total_cmp(&a.candidate.mean),
+            };
+            order.then_with(|| compare_names(&a.name, &b.name))
+        }
+    }
+
+    /// Compares test names segment by segment, numeric segments are compared as numbers and go before the others
+    pub(crate) fn compare_names(a: &str, b: &str) -> Ordering {
+        let segment = |s: &str| match s.parse::<u64>() {
+            Ok(n) => (0, n, s.to_string()),
+            Err(_) => (1, 0, s.to_string()),
+        };
+        a.split('/')
+            .map(segment)
+            .cmp(b.split('/').map(segment))
+            .then_with(|| a.cmp(b))
+    }
+
+    /// Buffers the results until the run is finished or stopped and reports them in a given order (`--sort`)
+    ///
+    /// Warnings and errors are reported immediately.
+    pub(crate) struct Sorted {
+        reporter: Box<dyn Reporter>,
+        order: SortOrder,
+        results: Vec<RunResult>,
+    }
+
+    impl Sorted {
+        pub(crate) fn new(reporter: Box<dyn Reporter>, order: SortOrder) -> Self {
+            Self {
+                reporter,
+                order,
+                results: vec![],
+            }
+        }
+    }
+
+    impl Reporter for Sorted {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped,
+            on_session_end
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.results.push(results.clone());
+        }
+
+        fn on_finish(&mut self, totals: &PhaseTimings) {
+            let order = self.order;
+            self.results.sort_by(|a, b| order.compare(a, b));
+            for result in self.results.drain(..) {
+                self.reporter.on_complete(&result);
+            }
+            self.reporter.on_finish(totals);
+        }
+    }
+
+    /// Calls the suite callbacks of a reporter: [`Reporter::on_suite_start()`] with the tests planned when the session
+    /// starts and [`Reporter::on_suite_finish()`] with all the reported results when the tests are finished
+    ///
+    /// Wraps the whole chain of the reporters, so the results are collected in the order the runner reports them in.
+    pub(crate) struct Suite {
+        reporter: Box<dyn Reporter>,
+        results: Vec<RunResult>,
+    }
+
+    impl Suite {
+        pub(crate) fn new(reporter: Box<dyn Reporter>) -> Self {
+            Self {
+                reporter,
+                results: vec![],
+            }
+        }
+    }
+
+    impl Reporter for Suite {
+        forward!(
+            on_test_start,
+            wants_samples,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped,
+            on_session_end
+        );
+
+        fn on_session_start(&mut self, session: &SessionInfo) {
+            self.results.clear();
+            self.reporter.on_session_start(session);
+            self.reporter.on_suite_start(&session.tests);
+        }
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.results.push(results.clone());
+            self.reporter.on_complete(results);
+        }
+
+        fn on_finish(&mut self, totals: &PhaseTimings) {
+            self.reporter.on_finish(totals);
+            self.reporter.on_suite_finish(&self.results);
+            self.results.clear();
+        }
+    }
+
+    /// Prefixes the names of all the reported tests (`--name-prefix`)
+    ///
+    /// Used by `cargo tango` to qualify the tests of several benchmarks reported together (eg. `crate::bench::`).
+    pub(crate) struct Qualified {
+        pub(crate) reporter: Box<dyn Reporter>,
+        pub(crate) prefix: String,
+    }
+
+    impl Qualified {
+        fn qualify(&self, name: &str) -> String {
+            format!("{}{}", self.prefix, name)
+        }
+    }
+
+    impl Reporter for Qualified {
+        forward!(
+            on_session_start,
+            wants_samples,
+            on_finish,
+            on_summary,
+            on_session_end
+        );
+
+        fn on_suite_start(&mut self, names: &[&str]) {
+            let names = names.iter().map(|n| self.qualify(n)).collect::<Vec<_>>();
+            let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+            self.reporter.on_suite_start(&names);
+        }
+
+        fn on_test_start(&mut self, name: &str) {
+            self.reporter.on_test_start(&self.qualify(name));
+        }
+
+        fn on_complete(&mut self, results: &RunResult) {
+            let mut results = results.clone();
+            results.name = self.qualify(&results.name).into();
+            self.reporter.on_complete(&results);
+        }
+
+        fn on_suite_finish(&mut self, results: &[RunResult]) {
+            let mut results = results.to_vec();
+            for result in &mut results {
+                result.name = self.qualify(&result.name).into();
+            }
+            self.reporter.on_suite_finish(&results);
+        }
+
+        fn on_warning(&mut self, name: &str, message: &str) {
+            self.reporter.on_warning(&self.qualify(name), message);
+        }
+
+        fn on_error(&mut self, name: &str, message: &str) {
+            self.reporter.on_error(&self.qualify(name), message);
+        }
+
+        fn on_failure(&mut self, name: &str, message: &str) {
+            self.reporter.on_failure(&self.qualify(name), message);
+        }
+
+        fn on_skipped(&mut self, name: &str, reason: &str) {
+            self.reporter.on_skipped(&self.qualify(name), reason);
+        }
+    }
+
+    /// Result of a test as written by [`JsonReporter`]
+    ///
+    /// This is the stable schema of `--json` files: fields are only added in the future, never renamed or removed.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct JsonResult {
+        /// Display name of the test (the name of the test or of the pair)
+        pub name: String,
+
+        /// Names of the compared functions
+        pub id: PairId,
+
+        /// Unit of all the values
+        pub unit: Unit,
+
+        /// Per-iteration values of baseline samples left after filtering outliers
+        pub baseline: Summary<f64>,
+
+        /// Per-iteration values of candidate samples left after filtering outliers
+        pub candidate: Summary<f64>,
+
+        /// Per-iteration differences (candidate - baseline) left after filtering outliers
+        pub diff: Summary<f64>,
+
+        pub significance: JsonSignificance,
+
+        /// Number of the samples filtered as outliers
+        pub outliers: usize,
+
+        /// Importance of the test in the suite summary (1 for the results written before it was added)
+        #[serde(default = "default_weight")]
+        pub weight: f64,
+
+        /// Name of the generator producing the inputs of the candidate (absent if it doesn't use a generator)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub generator: Option<String>,
+
+        /// File the inputs of the candidate are recorded to or replayed from (see [`crate::generators::Recorder`])
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub recording: Option<PathBuf>,
+
+        /// Name of the test the result is an A/A comparison of (`--aa-test`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub aa_of: Option<String>,
+
+        /// Variance of the differences split into the measurement overhead and the workload (only present with
+        /// `--null-every`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub overhead_variance: Option<OverheadVariance>,
+
+        /// Values attached to the samples by observers (absent if the test has no observers or the result is
+        /// cached)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub observed: Vec<JsonObserved>,
+    }
+
+    /// Summary of the values attached to the samples of a test by an observer (see [`JsonResult`])
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct JsonObserved {
+        /// Name of the value given by the observer
+        pub name: String,
+
+        /// Unit of all the values
+        pub unit: Unit,
+
+        /// Values observed in the baseline samples (absent if the observer doesn't report them for baseline)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub baseline: Option<Summary<f64>>,
+
+        /// Values observed in the candidate samples (absent if the observer doesn't report them for candidate)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub candidate: Option<Summary<f64>>,
+
+        /// Paired differences (candidate - baseline) of the values observed in the same samples
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub diff: Option<Summary<f64>>,
+    }
+
+    impl From<&ObservedSummary> for JsonObserved {
+        fn from(observed: &ObservedSummary) -> Self {
+            Self {
+                name: observed.name.to_string(),
+                unit: observed.unit,
+                baseline: observed.baseline,
+                candidate: observed.candidate,
+                diff: observed.diff,
+            }
+        }
+    }
+
+    /// Decision on the difference between candidate and baseline (see [`JsonResult`])
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct JsonSignificance {
+        /// Relative difference of candidate from baseline (in percents)
+        pub pct: f64,
+
+        /// Confidence interval of the relative difference (in percents)
+        pub ci: ConfidenceInterval,
+
+        pub verdict: Verdict,
+
+        /// Difference is statistically significant (the same as the verdict being `Different`)
+        pub significant: bool,
+
+        /// Version of the statistics the verdict is computed with (see `MeasurementSettings::stats_version`)
+        #[serde(default = "crate::stats::legacy_stats_version")]
+        pub stats_version: u32,
+    }
+
+    fn default_weight() -> f64 {
+        1.
+    }
+
+    impl From<&RunResult> for JsonResult {
+        fn from(result: &RunResult) -> Self {
+            let estimate = &result.diff_estimate;
+            Self {
+                name: result.name.to_string(),
+                id: result.id.clone(),
+                unit: result.unit,
+                baseline: result.baseline,
+                candidate: result.candidate,
+                diff: result.diff,
+                significance: JsonSignificance {
+                    pct: estimate.pct,
+                    ci: estimate.ci,
+                    verdict: estimate.verdict,
+                    significant: estimate.significant,
+                    stats_version: result.stats_version,
+                },
+                outliers: result.outliers,
+                weight: result.weight,
+                generator: result.generator.clone(),
+                recording: result.recording.clone(),
+                aa_of: result.aa_of.as_deref().map(str::to_string),
+                overhead_variance: result.overhead_variance,
+                observed: result.observed.iter().map(JsonObserved::from).collect(),
+            }
+        }
+    }
+
+    /// Line written by [`NdjsonReporter`]: a [`JsonResult`] numbered in the order the tests are completed
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct NdjsonLine {
+        /// Number of the line starting from 1. A gap means the stream is truncated
+        pub seq: u64,
+
+        #[serde(flatten)]
+        pub result: JsonResult,
+    }
+
+    /// Writes each completed result as a line of newline delimited JSON (`--ndjson`)
+    ///
+    /// Lines follow the stable schema of [`JsonResult`]. Each line is flushed as soon as the test is completed, so the
+    /// results can be consumed while the run is in progress.
+    pub(crate) struct NdjsonReporter<W: Write> {
+        reporter: Box<dyn Reporter>,
+        writer: W,
+        seq: u64,
+    }
+
+    impl<W: Write> NdjsonReporter<W> {
+        pub(crate) fn new(reporter: Box<dyn Reporter>, writer: W) -> Self {
+            Self {
+                reporter,
+                writer,
+                seq: 0,
+            }
+        }
+
+        fn write(&mut self, results: &RunResult) -> io::Result<()> {
+            self.seq += 1;
+            let line = NdjsonLine {
+                seq: self.seq,
+                result: JsonResult::from(results),
+            };
+            serde_json::to_writer(&mut self.writer, &line)?;
+            self.writer.write_all(b"\n")?;
+            self.writer.flush()
+        }
+    }
+
+    impl<W: Write> Reporter for NdjsonReporter<W> {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_finish,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped,
+            on_session_end
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            if let Err(e) = self.write(results) {
+                let message = format!("result is not streamed: {}", e);
+                self.reporter.on_warning(&results.name, &message);
+            }
+        }
+    }
+
+    /// Writes all the completed results as a JSON array of [`JsonResult`] to a given file (`--json`)
+    ///
+    /// The array is written once when the session ends, or when the reporter is dropped if the run is stopped by an
+    /// error. Until then the file contains an empty array.
+    pub(crate) struct JsonReporter {
+        reporter: Box<dyn Reporter>,
+        path: PathBuf,
+        results: Option<Vec<JsonResult>>,
+    }
+
+    impl JsonReporter {
+        /// Creates the reporter writing an empty array to a given file, so it is writable before the run starts
+        pub(crate) fn create(reporter: Box<dyn Reporter>, path: PathBuf) -> io::Result<Self> {
+            fs::write(&path, "[]")?;
+            Ok(Self {
+                reporter,
+                path,
+                results: Some(vec![]),
+            })
+        }
+
+        fn write(&mut self) {
+            let Some(results) = self.results.take() else {
+                return;
+            };
+            let written = File::create(&self.path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                serde_json::to_writer_pretty(&mut writer, &results)?;
+                writer.flush()
+            });
+            if let Err(e) = written {
+                let message = format!("results are not written to {}: {}", self.path.display(), e);
+                self.reporter.on_warning("json", &message);
+            }
+        }
+    }
+
+    impl Drop for JsonReporter {
+        fn drop(&mut self) {
+            self.write();
+        }
+    }
+
+    impl Reporter for JsonReporter {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_finish,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            if let Some(json) = &mut self.results {
+                json.push(JsonResult::from(results));
+            }
+        }
+
+        fn on_session_end(&mut self, summary: &SessionSummary) {
+            self.reporter.on_session_end(summary);
+            self.write();
+        }
+    }
+
+    /// Columns of the files written by [`CsvReporter`]
+    const CSV_HEADER: [&str; 17] = [
+        "name",
+        "n",
+        "unit",
+        "baseline_mean",
+        "baseline_min",
+        "baseline_max",
+        "baseline_std_dev",
+        "candidate_mean",
+        "candidate_min",
+        "candidate_max",
+        "candidate_std_dev",
+        "diff_mean",
+        "change_pct",
+        "significant",
+        "outliers",
+        "stats_version",
+        "recording",
+    ];
+
+    /// Writes a row of comma separated values summarizing each completed result (`--csv`)
+    ///
+    /// The header is written when the reporter is created. Rows are buffered and flushed when the reporter is
+    /// dropped, so the results of the tests completed before an error are still written.
+    pub(crate) struct CsvReporter<W: Write> {
+        reporter: Box<dyn Reporter>,
+        writer: W,
+    }
+
+    impl<W: Write> CsvReporter<W> {
+        pub(crate) fn new(reporter: Box<dyn Reporter>, mut writer: W) -> io::Result<Self> {
+            writeln!(writer, "{}", CSV_HEADER.join(","))?;
+            Ok(Self { reporter, writer })
+        }
+
+        fn write(&mut self, results: &RunResult) -> io::Result<()> {
+            let recording = results
+                .recording
+                .as_deref()
+                .map(Path::to_string_lossy)
+                .unwrap_or_default();
+            let summary = |s: &Summary<f64>| {
+                let values = [s.mean, s.min, s.max, s.variance.sqrt()];
+                values.map(|v| v.to_string()).join(",")
+            };
+            writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                csv_field(&results.name),
+                results.diff.n,
+                unit_name(results.unit),
+                summary(&results.baseline),
+                summary(&results.candidate),
+                results.diff.mean,
+                results.diff_estimate.pct,
+                results.diff_estimate.significant,
+                results.outliers,
+                results.stats_version,
+                csv_field(&recording)
+            )
+        }
+    }
+
+    impl<W: Write> Drop for CsvReporter<W> {
+        fn drop(&mut self) {
+            // Errors can't be reported from drop, rows written so far are already handed to the writer
+            let _ = self.writer.flush();
+        }
+    }
+
+    impl<W: Write> Reporter for CsvReporter<W> {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_finish,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped,
+            on_session_end
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            if let Err(e) = self.write(results) {
+                let message = format!("result is not written to CSV: {}", e);
+                self.reporter.on_warning(&results.name, &message);
+            }
+        }
+    }
+
+    /// Quotes a CSV field if it contains a separator, a quote or a line break (quotes are doubled)
+    pub(crate) fn csv_field(value: &str) -> Cow<'_, str> {
+        if value.contains([',', '"', '\n', '\r']) {
+            Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    /// Writes a Markdown table of all the completed results once the run is finished (`--markdown`)
+    ///
+    /// The table is meant to be pasted into a pull request description, so it is written at once instead of a row
+    /// per result when the session ends, or when the reporter is dropped if the run is stopped by an error. Rows are
+    /// kept in the order the results are reported in.
+    pub(crate) struct MarkdownReporter<W: Write> {
+        reporter: Box<dyn Reporter>,
+        writer: W,
+        time_unit: TimeUnit,
+        rows: Vec<[String; 5]>,
+    }
+
+    impl<W: Write> MarkdownReporter<W> {
+        pub(crate) fn new(reporter: Box<dyn Reporter>, writer: W, time_unit: TimeUnit) -> Self {
+            Self {
+                reporter,
+                writer,
+                time_unit,
+                rows: vec![],
+            }
+        }
+
+        fn write_table(&mut self) -> io::Result<()> {
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+            writeln!(
+                self.writer,
+                "| Test | Baseline | Candidate | Δ% | Significant |"
+            )?;
+            writeln!(
+                self.writer,
+                "|:-----|---------:|----------:|---:|:-----------:|"
+            )?;
+            for row in self.rows.drain(..) {
+                writeln!(self.writer, "| {} |", row.join(" | "))?;
+            }
+            self.writer.flush()
+        }
+    }
+
+    impl<W: Write> Drop for MarkdownReporter<W> {
+        fn drop(&mut self) {
+            // Table is still written if the run is stopped by an error before the session ends
+            if let Err(e) = self.write_table() {
+                let message = format!("table is not written: {}", e);
+                self.reporter.on_warning("markdown", &message);
+            }
+        }
+    }
+
+    impl<W: Write> Reporter for MarkdownReporter<W> {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_finish,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            let value = |v| {
+                HumanValue::new(results.unit, v)
+                    .with_time_unit(self.time_unit)
+                    .to_string()
+            };
+            let significant = if results.diff_estimate.significant {
+                "✓"
+            } else {
+                "✗"
+            };
+            self.rows.push([
+                markdown_cell(&display_name(results)),
+                value(results.baseline.mean),
+                value(results.candidate.mean),
+                SignedPercent(results.diff_estimate.pct).to_string(),
+                significant.to_string(),
+            ]);
+        }
+
+        fn on_session_end(&mut self, summary: &SessionSummary) {
+            self.reporter.on_session_end(summary);
+            if let Err(e) = self.write_table() {
+                let message = format!("table is not written: {}", e);
+                self.reporter.on_warning("markdown", &message);
+            }
+        }
+    }
+
+    /// Escapes the characters breaking a Markdown table cell (backslashes, pipes and line breaks)
+    pub(crate) fn markdown_cell(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace(['\n', '\r'], " ")
+    }
+
+    /// Writes all the tests as a JUnit XML document for CI test reports (`--junit`)
+    ///
+    /// Each result is a `<testcase>`, significant regressions (candidate being slower) are failures, so they show
+    /// up in the test report of a CI job. Errors and skipped tests are reported as such. The document is written
+    /// at once when the run is finished, or when the reporter is dropped if the run is stopped by an error.
+    pub(crate) struct JunitReporter<W: Write> {
+        reporter: Box<dyn Reporter>,
+        writer: Option<W>,
+        cases: Vec<JunitCase>,
+        time: Duration,
+    }
+
+    struct JunitCase {
+        name: String,
+        time: Duration,
+        outcome: JunitOutcome,
+    }
+
+    enum JunitOutcome {
+        /// `details` describe the result and are kept for the case it fails the run later
+        Passed {
+            details: String,
+        },
+        Failure {
+            message: String,
+            details: String,
+        },
+        Error(String),
+        Skipped(String),
+    }
+
+    impl<W: Write> JunitReporter<W> {
+        pub(crate) fn new(reporter: Box<dyn Reporter>, writer: W) -> Self {
+            Self {
+                reporter,
+                writer: Some(writer),
+                cases: vec![],
+                time: Duration::ZERO,
+            }
+        }
+
+        fn add(&mut self, name: &str, time: Duration, outcome: JunitOutcome) {
+            self.cases.push(JunitCase {
+                name: name.to_string(),
+                time,
+                outcome,
+            });
+        }
+
+        fn write(&mut self) -> io::Result<()> {
+            let Some(mut writer) = self.writer.take() else {
+                return Ok(());
+            };
+            let count =
+                |f: fn(&JunitOutcome) -> bool| self.cases.iter().filter(|c| f(&c.outcome)).count();
+            writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                writer,
+                r#"<testsuite name="tango" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+                self.cases.len(),
+                count(|o| matches!(o, JunitOutcome::Failure { .. })),
+                count(|o| matches!(o, JunitOutcome::Error(_))),
+                count(|o| matches!(o, JunitOutcome::Skipped(_))),
+                self.time.as_secs_f64()
+            )?;
+            for case in &self.cases {
+                // Tests are grouped the same way the console groups them
+                let class = case.name.split('/').next().unwrap_or_default();
+                write!(
+                    writer,
+                    r#"  <testcase classname="{}" name="{}" time="{:.3}""#,
+                    escape_xml(class),
+                    escape_xml(&case.name),
+                    case.time.as_secs_f64()
+                )?;
+                match &case.outcome {
+                    JunitOutcome::Passed { .. } => writeln!(writer, "/>")?,
+                    JunitOutcome::Failure { message, details } => writeln!(
+                        writer,
+                        ">\n    <failure type=\"verdict\" message=\"{}\">{}</failure>\n  </testcase>",
+                        escape_xml(message),
+                        escape_xml(details)
+                    )?,
+                    JunitOutcome::Error(message) => writeln!(
+                        writer,
+                        ">\n    <error message=\"{}\"/>\n  </testcase>",
+                        escape_xml(message)
+                    )?,
+                    JunitOutcome::Skipped(reason) => writeln!(
+                        writer,
+                        ">\n    <skipped message=\"{}\"/>\n  </testcase>",
+                        escape_xml(reason)
+                    )?,
+                }
+            }
+            writeln!(writer, "</testsuite>")?;
+            writer.flush()
+        }
+    }
+
+    impl<W: Write> Drop for JunitReporter<W> {
+        fn drop(&mut self) {
+            // Errors can't be reported from drop, the document is incomplete anyway if the run is stopped
+            let _ = self.write();
+        }
+    }
+
+    impl<W: Write> Reporter for JunitReporter<W> {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            wants_samples,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_session_end
+        );
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            let value = |v| HumanValue::new(results.unit, v).to_string();
+            let details = format!(
+                "baseline: {}, candidate: {}, change: {}",
+                value(results.baseline.mean),
+                value(results.candidate.mean),
+                SignedPercent(results.diff_estimate.pct)
+            );
+            self.add(
+                &results.name,
+                results.phases.total(),
+                JunitOutcome::Passed { details },
+            );
+        }
+
+        /// Fails the case of the test the same way the run is failed, so the report agrees with the exit code
+        fn on_failure(&mut self, name: &str, message: &str) {
+            self.reporter.on_failure(name, message);
+            match self.cases.iter_mut().rev().find(|c| c.name == name) {
+                Some(case) => match &mut case.outcome {
+                    JunitOutcome::Passed { details } => {
+                        let details = mem::take(details);
+                        case.outcome = JunitOutcome::Failure {
+                            message: message.to_string(),
+                            details,
+                        };
+                    }
+                    JunitOutcome::Failure { message: first, .. } => {
+                        *first += "; ";
+                        *first += message;
+                    }
+                    JunitOutcome::Error(_) | JunitOutcome::Skipped(_) => {}
+                },
+                None => self.add(
+                    name,
+                    Duration::ZERO,
+                    JunitOutcome::Failure {
+                        message: message.to_string(),
+                        details: String::new(),
+                    },
+                ),
+            }
+        }
+
+        fn on_finish(&mut self, totals: &PhaseTimings) {
+            self.reporter.on_finish(totals);
+            self.time = totals.total();
+            if let Err(e) = self.write() {
+                let message = format!("report is not written: {}", e);
+                self.reporter.on_warning("junit", &message);
+            }
+        }
+
+        fn on_error(&mut self, name: &str, message: &str) {
+            self.reporter.on_error(name, message);
+            self.add(
+                name,
+                Duration::ZERO,
+                JunitOutcome::Error(message.to_string()),
+            );
+        }
+
+        fn on_skipped(&mut self, name: &str, reason: &str) {
+            self.reporter.on_skipped(name, reason);
+            self.add(
+                name,
+                Duration::ZERO,
+                JunitOutcome::Skipped(reason.to_string()),
+            );
+        }
+    }
+
+    /// Writes a scatter plot of the samples of each completed test to a given directory (`--scatter-dir`)
+    ///
+    /// File names are escaped test names (see [`escape_dump_name()`]) with `.svg` extension. Tests without samples
+    /// (replayed from the cache or measured in streaming mode) are not plotted.
+    pub(crate) struct SvgScatterReporter {
+        pub(crate) reporter: Box<dyn Reporter>,
+        pub(crate) dir: PathBuf,
+    }
+
+    impl SvgScatterReporter {
+        fn write(&self, results: &RunResult) -> io::Result<()> {
+            let Some(pairs) = &results.pairs else {
+                return Ok(());
+            };
+            let Some(plot) = ScatterPlot::new(&pairs.baseline, &pairs.candidate, &pairs.outliers)
+            else {
+                return Ok(());
+            };
+            fs::create_dir_all(&self.dir)?;
+            let path = self
+                .dir
+                .join(format!("{}.svg", escape_dump_name(&results.name)));
+            fs::write(path, plot.svg(&display_name(results), results.unit))
+        }
+    }
+
+    impl Reporter for SvgScatterReporter {
+        forward!(
+            on_session_start,
+            on_suite_start,
+            on_test_start,
+            on_finish,
+            on_suite_finish,
+            on_summary,
+            on_warning,
+            on_error,
+            on_failure,
+            on_skipped,
+            on_session_end
+        );
+
+        fn wants_samples(&self) -> bool {
+            true
+        }
+
+        fn on_complete(&mut self, results: &RunResult) {
+            self.reporter.on_complete(results);
+            if let Err(e) = self.write(results) {
+                let message = format!("scatter plot is not written: {}", e);
+                self.reporter.on_warning(&results.name, &message);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{
+            manifest::Environment,
+            noise::{SentinelRun, SentinelSlot},
+            summarized_run_result, MeasurementSettings, PairedSamples, ThroughputUnit,
+        };
+        use colorz::mode::{set_coloring_mode, Mode};
+        use std::{cell::RefCell, env, process, rc::Rc};
+
+        fn row(name: &str, baseline: &str, candidate: &str, change: f64) -> ConsoleRow {
+            ConsoleRow {
+                name: name.to_string(),
+                weight: 1.,
+                baseline: baseline.to_string(),
+                candidate: candidate.to_string(),
+                change: SignedPercent(change),
+                verdict: Verdict::Different,
+                significant: false,
+                candidate_faster: change < 0.,
+                throughput: None,
+            }
+        }
+
+        #[test]
+        fn check_console_without_colors() {
+            set_coloring_mode(Mode::Never);
+
+            let mut reporter = ConsoleReporter::new(TimeUnit::Auto, 40);
+            let mut faster = row("sort", "1.2 us", "1.1 us", -8.3);
+            faster.significant = true;
+            let mut slower = row("search", "1.2 us", "1.5 us", 25.);
+            slower.significant = true;
+            let mut equivalent = row("parse", "1.2 us", "1.2 us", 0.1);
+            equivalent.verdict = Verdict::Equivalent;
+            reporter.rows = vec![faster, slower, equivalent];
+            // Significant changes and verdict markers are colored otherwise
+            for line in reporter.render() {
+                assert!(!line.contains("\x1B["), "{:?}", line);
+            }
+        }
+
+        #[test]
+        fn check_console_layout() {
+            set_coloring_mode(Mode::Never);
+
+            let mut reporter = ConsoleReporter::new(TimeUnit::Auto, 40);
+            reporter.rows = vec![
+                row("search/sorted", "1.2 us", "1.1 us", -8.3),
+                row(
+                    "search/RandomMap{n=1048576,hit=50%}",
+                    "12.5 ms",
+                    "13.0 ms",
+                    4.,
+                ),
+                row(
+                    "search/RandomMap{n=1048576,hit=50%,distribution=zipf}",
+                    "210.4 ms",
+                    "209.9 ms",
+                    -0.24,
+                ),
+            ];
+            let expected = [
+                "search/sorted                            [   1.2 us ...   1.1 us ]      -8.30%*",
+                "search/RandomMap{n=1048576,hit=50%}      [  12.5 ms ...  13.0 ms ]      +4.00%*",
+                "search/RandomMap{n=1…,distribution=zipf} [ 210.4 ms ... 209.9 ms ]      -0.24%*",
+            ];
+            assert_eq!(reporter.render(), expected);
+
+            reporter.rows = vec![row("sum", "10.0 ns", "10.0 ns", 0.)];
+            let expected = ["sum                  [  10.0 ns ...  10.0 ns ]      +0.00%*"];
+            assert_eq!(reporter.render(), expected);
+
+            let mut measured = row("sum", "10.0 ns", "10.0 ns", 0.);
+            measured.throughput = Some(HumanThroughput {
+                unit: ThroughputUnit::Elements,
+                per_second: 1.5e9,
+            });
+            reporter.rows = vec![measured];
+            let expected =
+                ["sum                  [  10.0 ns ...  10.0 ns ]      +0.00%*   1.5 Gelem/s"];
+            assert_eq!(reporter.render(), expected);
+
+            let mut reporter = ConsoleReporter::new(TimeUnit::Auto, 40).with_weights(true);
+            let mut weighted = row("parse", "1.2 us", "1.1 us", -8.3);
+            weighted.weight = 5.;
+            reporter.rows = vec![weighted, row("sum", "10.0 ns", "10.0 ns", 0.)];
+            let expected = [
+                "parse                    ×5 [   1.2 us ...   1.1 us ]      -8.30%*",
+                "sum                      ×1 [  10.0 ns ...  10.0 ns ]      +0.00%*",
+            ];
+            assert_eq!(reporter.render(), expected);
+        }
+
+        #[test]
+        fn check_shortened_names_are_unique() {
+            let a = "search/RandomMap{n=1048576,hit=50%,distribution=zipf}";
+            let b = "search/RandomMap{n=1048576,hit=90%,distribution=zipf}";
+            let names = fit_names([a, b, "sum"].into_iter(), 30);
+
+            assert_eq!(names[2], "sum");
+            assert_ne!(names[0], names[1]);
+            for name in &names[..2] {
+                assert_eq!(name.chars().count(), 30);
+                assert!(name.starts_with("search/Ra"), "{}", name);
+                assert!(name.contains('~'), "{}", name);
+            }
+            assert_eq!(shorten(a, 30, ""), shorten(b, 30, ""));
+        }
+
+        /// Records the names of the completed tests and the tests with warnings
+        struct Names(Rc<RefCell<Vec<String>>>);
+
+        impl Reporter for Names {
+            fn on_complete(&mut self, results: &RunResult) {
+                self.0.borrow_mut().push(results.name.to_string());
+            }
+
+            fn on_warning(&mut self, name: &str, _message: &str) {
+                self.0.borrow_mut().push(name.to_string());
+            }
+        }
+
+        /// Records the lifecycle of the tests as `start <name>` and `complete <name>` events
+        struct Lifecycle(Rc<RefCell<Vec<String>>>);
+
+        impl Reporter for Lifecycle {
+            fn on_suite_start(&mut self, names: &[&str]) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("suite start {}", names.join(", ")));
+            }
+
+            fn on_test_start(&mut self, name: &str) {
+                self.0.borrow_mut().push(format!("start {}", name));
+            }
+
+            fn on_complete(&mut self, results: &RunResult) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("complete {}", results.name));
+            }
+
+            fn on_suite_finish(&mut self, results: &[RunResult]) {
+                let names = results.iter().map(|r| &*r.name).collect::<Vec<_>>();
+                self.0
+                    .borrow_mut()
+                    .push(format!("suite finish {}", names.join(", ")));
+            }
+        }
+
+        #[test]
+        fn check_lifecycle_forwarded() {
+            let events = Rc::new(RefCell::new(vec![]));
+            let sorted = Sorted::new(Box::new(Lifecycle(Rc::clone(&events))), SortOrder::Name);
+            let qualified = Qualified {
+                reporter: Box::new(sorted),
+                prefix: "app::".into(),
+            };
+            let ndjson = NdjsonReporter::new(Box::new(qualified), vec![]);
+            let mut reporter = Suite::new(Box::new(ndjson));
+
+            let settings = MeasurementSettings::default();
+            let names = ["sort", "search"];
+            reporter.on_session_start(&SessionInfo {
+                settings,
+                filter: "",
+                mode: SessionMode::Pairs,
+                master_seed: 42,
+                environment: Environment::current(),
+                tests: names.to_vec(),
+            });
+            let summary = Summary::from(&[10., 12., 11.]).unwrap();
+            let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+            for name in names {
+                reporter.on_test_start(name);
+                let result =
+                    summarized_run_result(name, summary, summary, diff, None, 0, &settings);
+                reporter.on_complete(&result);
+            }
+            // Starts are forwarded immediately even if the results are buffered
+            assert_eq!(
+                *events.borrow(),
+                [
+                    "suite start app::sort, app::search",
+                    "start app::sort",
+                    "start app::search"
+                ]
+            );
+            reporter.on_finish(&PhaseTimings::default());
+            // Suite results are kept in the order they are reported in
+            assert_eq!(
+                events.take()[3..],
+                [
+                    "complete app::search",
+                    "complete app::sort",
+                    "suite finish app::sort, app::search"
+                ]
+            );
+        }
+
+        #[test]
+        fn check_qualified_result_lines() {
+            let names = Rc::new(RefCell::new(vec![]));
+            let qualified = Qualified {
+                reporter: Box::new(Names(Rc::clone(&names))),
+                prefix: "app::bench::".into(),
+            };
+            let mut reporter = NdjsonReporter::new(Box::new(qualified), vec![]);
+
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[10., 12., 11.]).unwrap();
+            let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+            for name in ["sort", "search"] {
+                let result =
+                    summarized_run_result(name, summary, summary, diff, None, 0, &settings);
+                reporter.on_complete(&result);
+            }
+            reporter.on_warning("sort", "noisy");
+
+            assert_eq!(
+                *names.borrow(),
+                ["app::bench::sort", "app::bench::search", "app::bench::sort"]
+            );
+            // Names are qualified only in the reports, not in the results themselves
+            let lines = String::from_utf8(reporter.writer).unwrap();
+            let lines = lines
+                .lines()
+                .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0]["name"], "sort");
+            assert_eq!(lines[1]["name"], "search");
+            assert!(lines[1]["significance"]["pct"].is_f64());
+        }
+
+        #[test]
+        fn check_markdown_table() {
+            let names = Rc::new(RefCell::new(vec![]));
+            let mut table = vec![];
+            let mut reporter = MarkdownReporter::new(
+                Box::new(Names(Rc::clone(&names))),
+                &mut table,
+                TimeUnit::Auto,
+            );
+
+            let settings = MeasurementSettings::default();
+            let baseline = Summary::from(&[1000., 1200., 1100.]).unwrap();
+            let candidate = Summary::from(&[500., 600., 550.]).unwrap();
+            let slower = Summary::from(&[-500., -600., -550.]).unwrap();
+            let same = Summary::from(&[1., -1., 0.]).unwrap();
+            let results = [("sort", candidate, slower), ("a\\|b", baseline, same)].map(
+                |(name, candidate, diff)| {
+                    summarized_run_result(name, baseline, candidate, diff, None, 0, &settings)
+                },
+            );
+            for result in &results {
+                reporter.on_complete(result);
+                reporter.on_finish(&PhaseTimings::default());
+            }
+            // Rows are forwarded as they complete, the table is written at the end of the session only
+            assert_eq!(*names.borrow(), ["sort", "a\\|b"]);
+            assert!(reporter.writer.is_empty());
+            reporter.on_session_end(&SessionSummary {
+                phases: PhaseTimings::default(),
+                wall_time: Duration::ZERO,
+                dumps: vec![],
+                sentinels: None,
+            });
+            drop(reporter);
+
+            let table = String::from_utf8(table).unwrap();
+            let lines = table.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 4);
+            assert_eq!(
+                lines[0],
+                "| Test | Baseline | Candidate | Δ% | Significant |"
+            );
+            assert_eq!(
+                lines[2],
+                format!(
+                    "| sort | 1.1 us | 550.0 ns | {} | ✓ |",
+                    SignedPercent(results[0].diff_estimate.pct)
+                )
+            );
+            assert!(lines[3].starts_with("| a\\\\\\|b | 1.1 us | 1.1 us | "));
+            assert!(lines[3].ends_with(" | ✗ |"));
+        }
+
+        #[test]
+        fn check_missing_histogram_reason() {
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[1., 2., 3.]).unwrap();
+            let mut result =
+                summarized_run_result("sort", summary, summary, summary, None, 0, &settings);
+            let streaming = missing_histogram_reason(&result).unwrap();
+            assert!(streaming.contains("streaming"), "{}", streaming);
+            result.cached = true;
+            let cached = missing_histogram_reason(&result).unwrap();
+            assert!(cached.contains("cached"), "{}", cached);
+            result.samples = Some([vec![1.], vec![2.]]);
+            assert_eq!(missing_histogram_reason(&result), None);
+        }
+
+        #[test]
+        fn check_json_written_once() {
+            let dir = env::temp_dir().join(format!("tango-json-once-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("results.json");
+            let mut reporter = JsonReporter::create(Box::new(Silent), path.clone()).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[1., 2., 3.]).unwrap();
+            let result =
+                summarized_run_result("sort", summary, summary, summary, None, 0, &settings);
+            reporter.on_complete(&result);
+            reporter.on_finish(&PhaseTimings::default());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+
+            // Run stopped by an error never reaches the end of the session
+            drop(reporter);
+            let json = fs::read_to_string(&path).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            let results: Vec<JsonResult> = serde_json::from_str(&json).unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].name, "sort");
+        }
+
+        #[test]
+        fn check_markdown_table_written_on_drop() {
+            struct Closed;
+
+            impl Write for Closed {
+                fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                    Err(io::ErrorKind::BrokenPipe.into())
+                }
+
+                fn flush(&mut self) -> io::Result<()> {
+                    Ok(())
+                }
+            }
+
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[1., 2., 3.]).unwrap();
+            let result =
+                summarized_run_result("sort", summary, summary, summary, None, 0, &settings);
+
+            // Run stopped by an error never reaches the end of the session
+            let mut table = vec![];
+            let mut reporter = MarkdownReporter::new(Box::new(Silent), &mut table, TimeUnit::Auto);
+            reporter.on_complete(&result);
+            drop(reporter);
+            assert_eq!(String::from_utf8(table).unwrap().lines().count(), 3);
+
+            let names = Rc::new(RefCell::new(vec![]));
+            let mut reporter =
+                MarkdownReporter::new(Box::new(Names(Rc::clone(&names))), Closed, TimeUnit::Auto);
+            reporter.on_complete(&result);
+            drop(reporter);
+            assert_eq!(*names.borrow(), ["sort", "markdown"]);
+        }
+
+        #[test]
+        fn check_junit_report() {
+            let names = Rc::new(RefCell::new(vec![]));
+            let mut xml = vec![];
+            let mut reporter = JunitReporter::new(Box::new(Names(Rc::clone(&names))), &mut xml);
+
+            let settings = MeasurementSettings::default();
+            let baseline = Summary::from(&[1000., 1200., 1100.]).unwrap();
+            let slower = Summary::from(&[2000., 2200., 2100.]).unwrap();
+            let faster = Summary::from(&[500., 600., 550.]).unwrap();
+            let diff = |candidate: Summary<f64>| {
+                let mean = candidate.mean - baseline.mean;
+                Summary::from(&[mean - 1., mean, mean + 1.]).unwrap()
+            };
+            for (name, candidate) in [("sort/<T>&mut", slower), ("sort/fast", faster)] {
+                let result = summarized_run_result(
+                    name,
+                    baseline,
+                    candidate,
+                    diff(candidate),
+                    None,
+                    0,
+                    &settings,
+                );
+                assert!(result.diff_estimate.significant);
+                reporter.on_complete(&result);
+            }
+            // Only the conditions failing the run fail the cases, a significant difference alone doesn't
+            reporter.on_failure("sort/<T>&mut", "Performance regressed +90.9% >= 5.0%");
+            reporter.on_failure("hidden", "Inconclusive result\n");
+            reporter.on_skipped("search", "not in \"baseline\"");
+            reporter.on_error("parse", "outputs differ");
+            reporter.on_finish(&PhaseTimings::default());
+            drop(reporter);
+
+            let xml = String::from_utf8(xml).unwrap();
+            let lines = xml.lines().collect::<Vec<_>>();
+            assert_eq!(lines[0], r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            assert_eq!(
+                lines[1],
+                r#"<testsuite name="tango" tests="5" failures="2" errors="1" skipped="1" time="0.000">"#
+            );
+            assert_eq!(
+                lines[2],
+                r#"  <testcase classname="sort" name="sort/&lt;T&gt;&amp;mut" time="0.000">"#
+            );
+            assert!(lines[3].starts_with(
+                r#"    <failure type="verdict" message="Performance regressed +90.9% &gt;= 5.0%">baseline: 1.1 us, candidate: 2.1 us"#
+            ));
+            assert_eq!(
+                lines[5],
+                r#"  <testcase classname="sort" name="sort/fast" time="0.000"/>"#
+            );
+            assert_eq!(
+                lines[7],
+                r#"    <failure type="verdict" message="Inconclusive result&#10;"></failure>"#
+            );
+            assert_eq!(
+                lines[10],
+                r#"    <skipped message="not in &quot;baseline&quot;"/>"#
+            );
+            assert_eq!(lines[13], r#"    <error message="outputs differ"/>"#);
+            assert_eq!(lines.last(), Some(&"</testsuite>"));
+            assert_eq!(lines.len(), 16);
+            // Reports are only written once
+            assert_eq!(xml.matches("<testsuite").count(), 1);
+
+            // Tests completed before an error stopping the run are still written
+            let mut xml = vec![];
+            let mut reporter = JunitReporter::new(Box::new(Names(Rc::clone(&names))), &mut xml);
+            reporter.on_skipped("search", "filtered");
+            drop(reporter);
+            let xml = String::from_utf8(xml).unwrap();
+            assert!(xml.contains(r#"tests="1" failures="0" errors="0" skipped="1""#));
+            assert!(xml.ends_with("</testsuite>\n"));
+        }
+
+        #[test]
+        fn check_pair_identity_in_reports() {
+            set_coloring_mode(Mode::Never);
+
+            let name = "iter_sum vs fold_sum";
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[1000., 1200., 1100.]).unwrap();
+            let mut result =
+                summarized_run_result(name, summary, summary, summary, None, 0, &settings);
+            result.id = PairId::new("iter_sum", "fold_sum");
+            assert_eq!(result.id.to_string(), name);
+
+            let mut console = ConsoleReporter::new(TimeUnit::Auto, 40);
+            console.on_complete(&result);
+            assert!(console.render()[0].starts_with("iter_sum vs fold_sum "));
+
+            let mut csv = CsvReporter::new(Box::new(Silent), vec![]).unwrap();
+            csv.on_complete(&result);
+            let csv = String::from_utf8(csv.writer.clone()).unwrap();
+            assert!(csv
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("iter_sum vs fold_sum,"));
+
+            let mut markdown = MarkdownReporter::new(Box::new(Silent), vec![], TimeUnit::Auto);
+            markdown.on_complete(&result);
+            markdown.on_session_end(&SessionSummary {
+                phases: PhaseTimings::default(),
+                wall_time: Duration::ZERO,
+                dumps: vec![],
+                sentinels: None,
+            });
+            let markdown = String::from_utf8(mem::take(&mut markdown.writer)).unwrap();
+            assert!(markdown
+                .lines()
+                .nth(2)
+                .unwrap()
+                .starts_with("| iter_sum vs fold_sum |"));
+
+            let mut xml = vec![];
+            let mut junit = JunitReporter::new(Box::new(Silent), &mut xml);
+            junit.on_complete(&result);
+            junit.on_finish(&PhaseTimings::default());
+            drop(junit);
+            let xml = String::from_utf8(xml).unwrap();
+            assert!(xml.contains(
+                r#"<testcase classname="iter_sum vs fold_sum" name="iter_sum vs fold_sum""#
+            ));
+
+            // JSON outputs keep the structured identity next to the display name
+            let mut ndjson = NdjsonReporter::new(Box::new(Silent), vec![]);
+            ndjson.on_complete(&result);
+            let line: NdjsonLine = serde_json::from_slice(&ndjson.writer).unwrap();
+            assert_eq!(line.result.name, name);
+            assert_eq!(line.result.id, result.id);
+            let json: serde_json::Value = serde_json::from_slice(&ndjson.writer).unwrap();
+            assert_eq!(json["id"]["baseline"], "iter_sum");
+            assert_eq!(json["id"]["candidate"], "fold_sum");
+        }
+
+        #[test]
+        fn check_svg_scatter_reporter() {
+            let dir = env::temp_dir().join(format!("tango-scatter-{}", process::id()));
+            let names = Rc::new(RefCell::new(vec![]));
+            let scatter = SvgScatterReporter {
+                reporter: Box::new(Names(Rc::clone(&names))),
+                dir: dir.clone(),
+            };
+            let mut reporter = Sorted::new(Box::new(scatter), SortOrder::Name);
+            assert!(reporter.wants_samples());
+            assert!(!Names(Rc::clone(&names)).wants_samples());
+
+            let settings = MeasurementSettings::default();
+            let summary = Summary::from(&[10., 12., 11.]).unwrap();
+            let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+            let mut plotted =
+                summarized_run_result("sort/u32", summary, summary, diff, None, 0, &settings);
+            plotted.pairs = Some(PairedSamples {
+                baseline: vec![10., 12., 11.],
+                candidate: vec![9., 10., 20.],
+                outliers: vec![false, false, true],
+            });
+            let cached =
+                summarized_run_result("search", summary, summary, diff, None, 0, &settings);
+            reporter.on_complete(&plotted);
+            reporter.on_complete(&cached);
+            reporter.on_finish(&PhaseTimings::default());
+
+            let mut files = fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            files.sort();
+            let svg = fs::read_to_string(dir.join("sort%2Fu32.svg")).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            assert_eq!(files, ["sort%2Fu32.svg"]);
+            assert!(svg.starts_with("<svg "), "{}", svg);
+            assert_eq!(svg.matches("<circle ").count(), 3);
+            assert_eq!(*names.borrow(), ["search", "sort/u32"]);
+        }
+
+        #[test]
+        fn check_sentinel_lines() {
+            set_coloring_mode(Mode::Never);
+
+            let run = |slot, pct, significant| SentinelRun {
+                name: "tango/sentinel-short",
+                slot,
+                pct,
+                significant,
+            };
+            let mut sentinels = SentinelSummary {
+                runs: vec![
+                    run(SentinelSlot::Start, 0.12, false),
+                    run(SentinelSlot::End, -0.5, false),
+                ],
+            };
+            assert_eq!(
+                sentinel_lines(&sentinels, false),
+                ["Noise floor  ±0.50% over 2 sentinel run(s)"]
+            );
+            assert_eq!(
+                sentinel_lines(&sentinels, true)[1..],
+                [
+                    "  start   tango/sentinel-short    +0.12%",
+                    "  end     tango/sentinel-short    -0.50%"
+                ]
+            );
+            assert_eq!(sentinel_warning(&sentinels), None);
+
+            sentinels.runs.push(run(SentinelSlot::Middle, 3., true));
+            assert!(sentinel_lines(&sentinels, true)[3].ends_with("+3.00%  significant"));
+            let warning = sentinel_warning(&sentinels).unwrap();
+            assert!(
+                warning.contains("tango/sentinel-short at the middle (+3.00%)"),
+                "{}",
+                warning
+            );
+        }
+
+        #[test]
+        fn check_sort_orders() {
+            let settings = MeasurementSettings::default();
+            let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+            // Name, change, significance and candidate time
+            let results = [
+                ("sort/1000", 5., true, 300.),
+                ("sort/100", -10., true, 30.),
+                ("search/20", 1., false, 50.),
+                ("search/3", 5., true, 300.),
+                ("search/sorted", -1., false, 10.),
+            ]
+            .map(|(name, pct, significant, time)| {
+                let candidate = Summary::from(&[time]).unwrap();
+                let mut result =
+                    summarized_run_result(name, candidate, candidate, diff, None, 0, &settings);
+                result.diff_estimate.pct = pct;
+                result.diff_estimate.significant = significant;
+                result
+            });
+
+            let sorted = |order| {
+                let names = Rc::new(RefCell::new(vec![]));
+                let mut reporter = Sorted::new(Box::new(Names(Rc::clone(&names))), order);
+                for result in &results {
+                    reporter.on_complete(result);
+                }
+                reporter.on_warning("sort/100", "noisy");
+                // Warnings are reported immediately, results once all the tests are completed
+                assert_eq!(*names.borrow(), ["sort/100"]);
+                reporter.on_finish(&PhaseTimings::default());
+                names.take()[1..].to_vec()
+            };
+            assert_eq!(
+                sorted(SortOrder::Name),
+                [
+                    "search/3",
+                    "search/20",
+                    "search/sorted",
+                    "sort/100",
+                    "sort/1000"
+                ]
+            );
+            assert_eq!(
+                sorted(SortOrder::Change),
+                [
+                    "search/3",
+                    "sort/1000",
+                    "search/20",
+                    "search/sorted",
+                    "sort/100"
+                ]
+            );
+            assert_eq!(
+                sorted(SortOrder::Significance),
+                [
+                    "sort/100",
+                    "search/3",
+                    "sort/1000",
+                    "search/20",
+                    "search/sorted"
+                ]
+            );
+            assert_eq!(
+                sorted(SortOrder::Time),
+                [
+                    "search/3",
+                    "sort/1000",
+                    "search/20",
+                    "sort/100",
+                    "search/sorted"
+                ]
+            );
+        }
+    }
+}
+
+fn colorize<T: Display>(value: T, do_paint: bool, is_improved: bool) -> impl Display {
+    use colorz::{ansi, mode::Stream::Stdout, Colorize, Style};
+
+    const RED: Style = Style::new().fg(ansi::Red).const_into_runtime_style();
+    const GREEN: Style = Style::new().fg(ansi::Green).const_into_runtime_style();
+    const DEFAULT: Style = Style::new().const_into_runtime_style();
+
+    if do_paint {
+        if is_improved {
+            value.into_style_with(GREEN).stream(Stdout)
+        } else {
+            value.into_style_with(RED).stream(Stdout)
+        }
+    } else {
+        value.into_style_with(DEFAULT).stream(Stdout)
+    }
+}
+
+/// Marker of the test verdict printed after the relative difference
+///
+/// `*` – functions are different, `≈` – functions are equivalent, `?` – not enough data to conclude either
+fn verdict_marker(verdict: Verdict) -> impl Display {
+    use colorz::{ansi, mode::Stream::Stdout, Colorize, Style};
+
+    const GRAY: Style = Style::new()
+        .fg(ansi::BrightBlack)
+        .const_into_runtime_style();
+    const YELLOW: Style = Style::new().fg(ansi::Yellow).const_into_runtime_style();
+    const DEFAULT: Style = Style::new().const_into_runtime_style();
+
+    match verdict {
+        Verdict::Different => "*".into_style_with(DEFAULT).stream(Stdout),
+        Verdict::Equivalent => "≈".into_style_with(GRAY).stream(Stdout),
+        Verdict::Inconclusive => "?".into_style_with(YELLOW).stream(Stdout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        commands::{SamplingPlan, BATCH_CURVE_REPEATS},
+        reporting::{JsonResult, NdjsonLine},
+        watchdog::Watchdog,
+        *,
+    };
+    use crate::{
+        benchmark_fn, benchmark_fn_with_setup,
+        fixture::TempDirFixture,
+        generators::{RandomVec, Recorder, Replayer},
+        id::PairId,
+        registry::InvalidName,
+        stats::BATCH_SIZES,
+        BenchmarkBuilder, BenchmarkMatrix, CostHints, GenFunc, Generator, HaystackSize,
+        IntoBenchmarks, MeasureTarget, MeasureTargetExt, ObservedValue, Side, ThroughputUnit, Unit,
+    };
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+    use std::{
+        cell::{Cell, RefCell},
+        env, fs,
+        hint::black_box,
+        ops::Range,
+        process::{self, Command},
+        rc::Rc,
+        sync::{atomic::AtomicU64, mpsc, Arc, Mutex},
+        thread,
+    };
+    use tango_core::raw::{self, RawHeader};
+
+    const FIRST_ACCESS_DELAY: Duration = Duration::from_millis(5);
+
+    /// Haystack which is slow on the first access
+    struct LazyHaystack(Cell<bool>);
+
+    struct LazyHaystackGenerator;
+
+    impl Generator for LazyHaystackGenerator {
+        type Haystack = LazyHaystack;
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            LazyHaystack(Cell::new(false))
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn sync(&mut self, _: u64) {}
+    }
+
+    fn touch(haystack: &LazyHaystack, _: &()) {
+        if !haystack.0.replace(true) {
+            thread::sleep(FIRST_ACCESS_DELAY);
+        }
+    }
+
+    fn lazy_haystack_spi() -> Spi<'static> {
+        let target: Box<dyn MeasureTarget> =
+            Box::new(GenFunc::new("touch", touch, LazyHaystackGenerator));
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
+
+    #[test]
+    fn check_first_sample_after_haystack_change_is_discarded() {
+        let baseline = lazy_haystack_spi();
+        let candidate = lazy_haystack_spi();
+        let delay = FIRST_ACCESS_DELAY.as_nanos() as f64;
+
+        let settings = MeasurementSettings {
+            max_iterations_per_sample: 1,
+            sampler_type: SamplerType::Flat,
+            ..Default::default()
+        };
+        let test_name = "touch/LazyHaystackGenerator";
+        let samples = LoopMode::Samples(10);
+
+        let paired_test = PairedTest::new(&baseline, &candidate, settings, Some(0), samples, None);
+        let result = paired_test.run(test_name, None).unwrap();
+        assert_eq!(result.discarded, 10);
+        assert!(result.baseline.max < delay);
+        assert!(result.candidate.max < delay);
+
+        let settings = MeasurementSettings {
+            discard_first_sample_after_haystack_change: false,
+            ..settings
+        };
+        let paired_test = PairedTest::new(&baseline, &candidate, settings, Some(0), samples, None);
+        let result = paired_test.run(test_name, None).unwrap();
+        assert_eq!(result.discarded, 0);
+        assert!(result.baseline.min >= delay);
+        assert!(result.candidate.min >= delay);
+    }
+
+    /// Test `busy` summing `n` numbers
+    fn busy_spi(n: u64) -> Spi<'static> {
+        let target = benchmark_fn("busy", move || (0..n).map(black_box).sum::<u64>());
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
+
+    #[test]
+    fn check_pairs_are_kept_on_request() {
+        let (baseline, candidate) = (busy_spi(100), busy_spi(100));
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(50);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        assert!(test.run("busy", None).unwrap().pairs.is_none());
+
+        let mut test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        test.keep_pairs(true);
+        let result = test.run("busy", None).unwrap();
+        let pairs = result.pairs.unwrap();
+        assert_eq!(pairs.baseline.len(), 50);
+        assert_eq!(pairs.candidate.len(), 50);
+        let outliers = pairs.outliers.iter().filter(|o| **o).count();
+        assert_eq!(outliers, result.outliers);
+    }
+
+    #[test]
+    fn check_manifest_replay() {
+        let baseline = busy_spi(1000);
+        let candidate = busy_spi(1000);
+        let settings = MeasurementSettings::default();
+
+        let loop_mode = LoopMode::Time(Duration::from_millis(50));
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let original = test.run("busy", None).unwrap();
+
+        // Loop mode of the replaying test is ignored, the number of samples is taken from the schedule
+        let seed = Some(test.master_seed());
+        let loop_mode = LoopMode::Samples(1);
+        let test = PairedTest::new(&baseline, &candidate, settings, seed, loop_mode, None);
+        let replayed = test.run("busy", Some(&original.schedule)).unwrap();
+
+        // Duration of the test is recorded, but not replayed
+        let schedule = |result: &RunResult| crate::manifest::TestSchedule {
+            duration: None,
+            ..result.schedule.clone()
+        };
+        assert_eq!(schedule(&replayed), schedule(&original));
+        assert!(original.schedule.duration.is_some());
+        assert_eq!(replayed.diff.n, original.diff.n);
+        // Tests are running in parallel, so timings are only roughly comparable
+        let ratio = replayed.baseline.mean / original.baseline.mean;
+        assert!((0.25..4.).contains(&ratio), "ratio: {}", ratio);
+    }
+
+    #[test]
+    fn check_random_seed_runs_are_not_cached() {
+        let opts = CacheOpts {
+            no_cache: false,
+            force: None,
+            precise_hash: false,
+        };
+        let exe = env::current_exe().unwrap();
+        let settings = MeasurementSettings::default();
+        let open = |opts: &CacheOpts, seed| opts.open(&exe, &settings, LoopMode::Samples(10), seed);
+        assert!(open(&opts, None).is_none());
+        assert!(open(&opts, Some(42)).is_some());
+
+        let disabled = CacheOpts {
+            no_cache: true,
+            ..opts
+        };
+        assert!(open(&disabled, Some(42)).is_none());
+    }
+
+    #[test]
+    fn check_settings_mismatch() {
+        let host = MeasurementSettings::default();
+        let library_settings = MeasurementSettings {
+            max_iterations_per_sample: 100,
+            outlier_detection: OutlierDetection::Iqr,
+            ..host
+        };
+        let library = || {
+            let benchmarks = vec![benchmark_fn("noop", || {})];
+            Spi::for_benchmarks_with_settings(benchmarks, library_settings).unwrap()
+        };
+
+        let diff = settings_diff(&host, &library_settings);
+        let fields = diff.iter().map(|(f, _, _)| f.as_str()).collect::<Vec<_>>();
+        assert_eq!(fields, ["max_iterations_per_sample", "outlier_detection"]);
+        assert_eq!(
+            diff[0],
+            (
+                "max_iterations_per_sample".into(),
+                "5000".into(),
+                "100".into()
+            )
+        );
+
+        assert!(reconcile_settings(&host, &host, &library(), true).is_err());
+        reconcile_settings(&library_settings, &host, &library(), true).unwrap();
+
+        // After reconciliation library reports host settings as effective
+        let library = library();
+        reconcile_settings(&host, &host, &library, false).unwrap();
+        let effective = library.settings().unwrap().unwrap();
+        assert!(settings_diff(&host, &effective).is_empty());
+
+        // Settings of the libraries built before a field was added are read with the default value of the field
+        let mut older = to_value(host).unwrap();
+        older.as_object_mut().unwrap().remove("streaming");
+        let older = serde_json::from_value::<MeasurementSettings>(older).unwrap();
+        assert!(settings_diff(&host, &older).is_empty());
+
+        // Executables not exporting settings are not checked
+        let library = Spi::for_benchmarks(vec![benchmark_fn("noop", || {})]).unwrap();
+        assert!(library.settings().is_none());
+        reconcile_settings(&host, &host, &library, true).unwrap();
+    }
+
+    #[test]
+    fn check_watchdog() {
+        let (tx, rx) = mpsc::channel();
+        let on_timeout = move |name: &str, _| tx.send(name.to_string()).unwrap();
+        let watchdog = Watchdog::start(Duration::from_millis(50), on_timeout);
+
+        // Test making progress
+        {
+            let _watch = watchdog.watch("progressing".into());
+            for _ in 0..20 {
+                thread::sleep(Duration::from_millis(10));
+                watchdog.beat();
+            }
+        }
+
+        // Idle watchdog
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(rx.try_recv().ok(), None);
+
+        // Test without progress
+        let _watch = watchdog.watch("hanging".into());
+        let name = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(name, "hanging");
+    }
+
+    fn parse_mode(
+        args: &[&str],
+        against: Option<&str>,
+        default_mode: DefaultMode,
+    ) -> Option<BenchmarkMode> {
+        let args = [&["bench"], args].concat();
+        let args = with_default_subcommand(args, against.map(OsString::from), default_mode);
+        Opts::try_parse_from(args).unwrap().subcommand
+    }
+
+    fn parse(args: &[&str], against: Option<&str>) -> BenchmarkMode {
+        parse_mode(args, against, DefaultMode::List).unwrap()
+    }
+
+    #[test]
+    fn check_positional_filter() {
+        let compare = |args, against| match parse(args, against) {
+            BenchmarkMode::Compare(CompareOpts {
+                path, comparison, ..
+            }) => (path, comparison.filter, comparison.time),
+            mode => panic!("Unexpected mode: {:?}", mode),
+        };
+
+        // cargo passes --bench after user arguments
+        let (path, filter, _) = compare(&["substring", "--bench"], None);
+        assert_eq!(path, None);
+        assert_eq!(filter.as_deref(), Some("*substring*"));
+
+        let (path, filter, time) = compare(&["--color", "never", "sort", "-t", "1"], None);
+        assert_eq!(path, None);
+        assert_eq!(filter.as_deref(), Some("*sort*"));
+        assert_eq!(time, Some(1.));
+
+        let (path, _, _) = compare(&["--bench", "sort"], Some("target/baseline"));
+        assert_eq!(path, Some(PathBuf::from("target/baseline")));
+
+        // Glob patterns are used as is
+        let (_, filter, _) = compare(&["sort/*/100", "--bench"], None);
+        assert_eq!(filter.as_deref(), Some("sort/*/100"));
+
+        // Explicit subcommands are still working
+        let (path, filter, _) = compare(&["compare", "target/baseline", "-f", "sort"], None);
+        assert_eq!(path, Some(PathBuf::from("target/baseline")));
+        assert_eq!(filter.as_deref(), Some("sort"));
+
+        // Subcommand name is never treated as a filter
+        let mode = parse(&["list", "--bench"], Some("target/baseline"));
+        assert!(matches!(mode, BenchmarkMode::List { filter: None, .. }));
+
+        assert!(parse_mode(&["--bench"], None, DefaultMode::List).is_none());
+
+        // Default mode applies only without positional arguments
+        assert!(matches!(
+            parse_mode(&["--bench"], None, DefaultMode::Compare),
+            Some(BenchmarkMode::Compare(CompareOpts { path: None, .. }))
+        ));
+        let mode = parse_mode(&["--bench"], Some("target/baseline"), DefaultMode::Compare);
+        let Some(BenchmarkMode::Compare(CompareOpts { path, .. })) = mode else {
+            panic!("compare is the default mode");
+        };
+        assert_eq!(path, Some(PathBuf::from("target/baseline")));
+        let mode = parse(&["list"], None);
+        assert!(matches!(mode, BenchmarkMode::List { .. }));
+    }
+
+    #[test]
+    fn check_tolerant_parsing() {
+        let parse = |args: &[&str], tolerant| {
+            let args = [&["bench"], args].concat();
+            let args = with_default_subcommand(args, None, DefaultMode::List);
+            parse_opts(args, tolerant).map(|opts| opts.map(|o| o.subcommand.is_some()))
+        };
+
+        // Arguments of other harnesses are errors by default
+        let criterion = ["--save-baseline", "main", "--bench"];
+        let e = parse(&criterion, false).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnknownArgument);
+        assert!(parse(&["--nocapture"], false).is_err());
+
+        // ...and a no-op in tolerant mode
+        assert!(parse(&criterion, true).unwrap().is_none());
+        assert!(parse(&["--nocapture"], true).unwrap().is_none());
+        assert!(parse(&["--nocapture", "--tolerant"], false)
+            .unwrap()
+            .is_none());
+        assert!(parse(&["compare", "--tolerant", "--nocapture"], false)
+            .unwrap()
+            .is_none());
+
+        // Supported arguments are parsed as usual and invalid values are still errors
+        assert_eq!(parse(&["compare", "-t", "1"], true).unwrap(), Some(true));
+        assert_eq!(
+            parse(&["--tolerant", "--bench"], false).unwrap(),
+            Some(false)
+        );
+        let e = parse(&["compare", "-t", "one"], true).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn check_test_names_are_validated_on_load() {
+        let spi = |names: Vec<&'static str>| {
+            Spi::for_benchmarks(names.into_iter().map(|n| benchmark_fn(n, || 1)).collect())
+        };
+
+        let spi_ok = spi(vec!["sort  vec\u{a0}100", "sum"]).unwrap();
+        let names = spi_ok
+            .tests()
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["sort vec 100", "sum"]);
+        assert!(spi_ok.lookup("sort vec 100").is_some());
+
+        let Err(e) = spi(vec!["sum", "sort\n100"]) else {
+            panic!("name with a newline is accepted");
+        };
+        assert_eq!(
+            e.to_string(),
+            "Invalid name of test #1 \"sort\\n100\": name contains control character '\\n'"
+        );
+
+        // Huge names are reported by a prefix cut at a character boundary
+        let huge = String::leak("日".repeat(100));
+        let Err(Error::InvalidTestName(idx, name, reason)) = spi(vec![huge]) else {
+            panic!("huge name is accepted");
+        };
+        assert_eq!(idx, 0);
+        assert_eq!(name, format!("{}…", "日".repeat(21)));
+        assert_eq!(reason, InvalidName::TooLong { len: 300, max: 256 });
+    }
+
+    #[test]
+    fn check_verify_registry() {
+        let path = env::temp_dir().join(format!("tango-cli-registry-{}.snap", process::id()));
+        let spi = |names: [&'static str; 2]| {
+            Spi::for_benchmarks(names.map(|n| benchmark_fn(n, || 1 + 1)).into()).unwrap()
+        };
+
+        let registry = spi(["sort/100", "sum"]);
+        assert!(verify_registry_snapshot(&registry, &path, false).is_err());
+        let updated = verify_registry_snapshot(&registry, &path, true).unwrap();
+        let matches = verify_registry_snapshot(&registry, &path, false).unwrap();
+        let renamed = verify_registry_snapshot(&spi(["sort/1000", "sum"]), &path, false).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(updated, ExitCode::SUCCESS);
+        assert_eq!(matches, ExitCode::SUCCESS);
+        assert_eq!(renamed, ExitCode::FAILURE);
+
+        let args = ["bench", "list", "--update-registry-snapshot"];
+        assert!(
+            Opts::try_parse_from(args).is_err(),
+            "snapshot path is required"
+        );
+    }
+
+    #[test]
+    fn check_fixed_cooldown() {
+        let settings = MeasurementSettings {
+            cooldown: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let mut cooldown = Cooldown::new(&settings).unwrap();
+        assert!(cooldown.wait() >= Duration::from_millis(20));
+
+        assert!(Cooldown::new(&MeasurementSettings::default()).is_none());
+    }
+
+    #[test]
+    fn check_adaptive_cooldown() {
+        let sensor = |kind, values: Vec<f64>| {
+            let reads = Rc::new(Cell::new(0));
+            let mut values = values.into_iter();
+            let counter = Rc::clone(&reads);
+            let sensor = cooldown::Sensor::new(kind, move || {
+                counter.set(counter.get() + 1);
+                values.next()
+            });
+            (sensor, reads)
+        };
+        let max = Duration::from_secs(10);
+        let poll_interval = Duration::from_millis(1);
+
+        // Waits until temperature returns within the band
+        let (temperature, reads) = sensor(
+            cooldown::SensorKind::Temperature,
+            vec![50., 60., 55., 51.5, 70.],
+        );
+        let mut cooldown = Cooldown::adaptive(max, temperature, poll_interval).unwrap();
+        assert!(cooldown.wait() < max);
+        assert_eq!(reads.get(), 4);
+
+        // Lower frequency means throttling
+        let (frequency, reads) = sensor(
+            cooldown::SensorKind::Frequency,
+            vec![3_000_000., 2_000_000., 2_900_000.],
+        );
+        let mut cooldown = Cooldown::adaptive(max, frequency, poll_interval).unwrap();
+        cooldown.wait();
+        assert_eq!(reads.get(), 3);
+
+        // Pause is limited if the machine doesn't cool down
+        let max = Duration::from_millis(20);
+        let hot = cooldown::Sensor::new(cooldown::SensorKind::Temperature, {
+            let mut first = true;
+            move || Some(if std::mem::take(&mut first) { 50. } else { 90. })
+        });
+        let mut cooldown = Cooldown::adaptive(max, hot, poll_interval).unwrap();
+        let waited = cooldown.wait();
+        assert!(waited >= max && waited < max * 10, "{:?}", waited);
+
+        // Sensor which is not readable from the start can't be used
+        let (broken, _) = sensor(cooldown::SensorKind::Temperature, vec![]);
+        assert!(Cooldown::adaptive(max, broken, poll_interval).is_none());
+    }
+
+    #[test]
+    fn check_parse_cooldown() {
+        assert!(matches!(parse_cooldown("auto"), Ok(CooldownArg::Auto)));
+        assert!(matches!(
+            parse_cooldown("250"),
+            Ok(CooldownArg::Fixed(d)) if d == Duration::from_millis(250)
+        ));
+        assert!(parse_cooldown("1s").is_err());
+    }
+
+    #[test]
+    fn check_noise_gate_and_fail_threshold() {
+        // Candidate is clearly 10% slower
+        let baseline = (0..100).map(|i| 100. + (i % 3) as f64).collect::<Vec<_>>();
+        let candidate = baseline.iter().map(|b| b * 1.1).collect::<Vec<_>>();
+        let result = crate::calculate_run_result(
+            "test",
+            &baseline,
+            &candidate,
+            &[1; 100],
+            crate::SampleOrder::CandidateFirst,
+            &MeasurementSettings::default(),
+        )
+        .unwrap();
+        assert!(result.diff_estimate.significant);
+
+        let checks = |fail_threshold| VerdictChecks {
+            fail_threshold,
+            fail_on_inconclusive: true,
+            min_effect: 1.,
+        };
+        let failures = checks(Some(5.)).failures(&result, GateOutcome::Trusted);
+        assert_eq!(failures.len(), 1, "{:?}", failures);
+        assert!(failures[0].contains("regressed +10.0%"), "{:?}", failures);
+        assert!(checks(Some(20.))
+            .failures(&result, GateOutcome::Trusted)
+            .is_empty());
+
+        // Noise floor exceeding the maximum demotes the verdicts, so the regression doesn't fail the run
+        let gate = NoiseGate {
+            max: 1.,
+            runs: 5,
+            action: NoiseAction::Demote,
+        };
+        let noisy = NoiseFloor {
+            pct: 3.,
+            runs: 5,
+            max: 1.,
+        };
+        let outcome = gate.check(&noisy);
+        assert_eq!(outcome, GateOutcome::Demoted);
+        assert!(checks(Some(5.)).failures(&result, outcome).is_empty());
+
+        let quiet = NoiseFloor { pct: 0.5, ..noisy };
+        let outcome = gate.check(&quiet);
+        assert_eq!(checks(Some(5.)).failures(&result, outcome).len(), 1);
+
+        assert_eq!(
+            NoiseAction::from_str("demote").unwrap(),
+            NoiseAction::Demote
+        );
+        assert!(NoiseAction::from_str("ignore").is_err());
+    }
+
+    #[test]
+    fn check_sentinels() {
+        use noise::SentinelSlot::*;
+
+        // Options extending the sampling of the tests are not applied to the sentinels
+        let settings = MeasurementSettings {
+            samples_per_haystack: 1,
+            exact_samples: Some(usize::MAX),
+            target_precision: Some(0.001),
+            ..MeasurementSettings::default()
+        };
+        let capped = Sentinels::new(4, settings, LoopMode::Samples(usize::MAX), None).unwrap();
+        assert!(matches!(capped.loop_mode, LoopMode::Time(SENTINEL_TIME)));
+        assert_eq!(capped.settings.exact_samples, None);
+        assert_eq!(capped.settings.target_precision, None);
+
+        let loop_mode = LoopMode::Time(Duration::from_millis(5));
+        let mut sentinels = Sentinels::new(4, settings, loop_mode, Some(1)).unwrap();
+        for position in 0..4 {
+            sentinels.run_due(position).unwrap();
+        }
+        let summary = sentinels.finish().unwrap();
+
+        // Both pairs are compared in each slot
+        let runs = summary
+            .runs
+            .iter()
+            .map(|r| (r.slot, r.name))
+            .collect::<Vec<_>>();
+        let [short, long] = noise::SENTINELS.map(|(name, _)| name);
+        assert_eq!(
+            runs,
+            [
+                (Start, short),
+                (Start, long),
+                (Middle, short),
+                (Middle, long),
+                (End, short),
+                (End, long)
+            ]
+        );
+        assert!(summary.runs.iter().all(|r| r.pct.is_finite()));
+    }
+
+    #[test]
+    fn check_confirm_top() {
+        let settings = MeasurementSettings::default();
+        // Candidate is slower by a given factor, differences are shifted by a deterministic noise
+        let measure = |name: &str, factor: f64| {
+            let baseline = (0..100).map(|i| 100. + (i % 3) as f64).collect::<Vec<_>>();
+            let candidate = baseline
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b * factor + ((i * 7) % 5) as f64 - 2.)
+                .collect::<Vec<_>>();
+            crate::calculate_run_result(
+                name,
+                &baseline,
+                &candidate,
+                &[1; 100],
+                crate::SampleOrder::CandidateFirst,
+                &settings,
+            )
+            .unwrap()
+        };
+
+        let mut results = vec![
+            measure("equal", 1.),
+            measure("small", 1.02),
+            measure("large", 1.1),
+            measure("flaky", 1.05),
+        ];
+        assert!(!results[0].diff_estimate.significant);
+        assert!(results[1].diff_estimate.significant);
+
+        let mut rerun = vec![];
+        confirm_results(&mut results, 2, &settings, |result| {
+            rerun.push(result.name.to_string());
+            Ok(match &*result.name {
+                "large" => Some(measure("large", 1.1)),
+                // Difference disappears on the second run
+                _ => Some(measure("flaky", 1.)),
+            })
+        })
+        .unwrap();
+        assert_eq!(rerun, ["large", "flaky"]);
+
+        let [equal, small, large, flaky] = &results[..] else {
+            unreachable!()
+        };
+        assert_eq!(equal.confirmation, None);
+        assert_eq!(small.confirmation, None);
+        assert_eq!(large.confirmation, Some(Confirmation::Confirmed));
+        assert_eq!(large.diff.n, 200);
+        assert_eq!(large.diff_estimate.verdict, Verdict::Different);
+        assert_eq!(flaky.confirmation, Some(Confirmation::Unconfirmed));
+        assert!(
+            (flaky.diff_estimate.pct - 2.5).abs() < 0.5,
+            "{}",
+            flaky.diff_estimate.pct
+        );
+        assert_eq!(reporting::display_name(large), "large (confirmed)");
+        assert_eq!(reporting::display_name(flaky), "flaky (unconfirmed)");
+
+        // Results skipped on the second run are kept as measured
+        let mut results = vec![measure("large", 1.1)];
+        confirm_results(&mut results, 5, &settings, |_| Ok(None)).unwrap();
+        assert_eq!(results[0].confirmation, Some(Confirmation::Unconfirmed));
+        assert_eq!(results[0].diff.n, 100);
+    }
+
+    #[test]
+    fn check_parse_shard() {
+        assert_eq!(parse_shard("2/4"), Ok(Shard { index: 2, count: 4 }));
+        assert!(parse_shard("0/4").is_err());
+        assert!(parse_shard("5/4").is_err());
+        assert!(parse_shard("2").is_err());
+    }
+
+    #[test]
+    fn check_parse_profile_override() {
+        let parsed = parse_profile_override("io:min_effect=2%,min_samples=50").unwrap();
+        assert_eq!(parsed.profile, "io");
+        assert_eq!(parsed.settings.min_effect, Some(2.));
+        assert_eq!(parsed.settings.min_samples, Some(50));
+        assert_eq!(parsed.settings.significance, None);
+
+        let parsed = parse_profile_override("io:significance=5%,outlier=trim:1,2").unwrap();
+        assert_eq!(parsed.settings.significance, Some(0.05));
+        assert_eq!(
+            parsed.settings.outlier,
+            Some(OutlierDetection::Trim {
+                lower_pct: 1.,
+                upper_pct: 2.
+            })
+        );
+        let parsed = parse_profile_override("cpu:significance=0.001,outlier=iqr").unwrap();
+        assert_eq!(parsed.settings.significance, Some(0.001));
+        assert_eq!(parsed.settings.outlier, Some(OutlierDetection::Iqr));
+
+        assert!(parse_profile_override("io").is_err());
+        assert!(parse_profile_override(":min_effect=2").is_err());
+        assert!(parse_profile_override("io:min_effect").is_err());
+        assert!(parse_profile_override("io:min_effect=-1").is_err());
+        assert!(parse_profile_override("io:significance=1.5").is_err());
+        assert!(parse_profile_override("io:outlier=tukey").is_err());
+        assert!(parse_profile_override("io:weight=2").is_err());
+    }
+
+    #[test]
+    fn check_test_profiles() {
+        let definitions = [(
+            "cli-test-io".to_string(),
+            Profile {
+                min_effect: Some(50.),
+                min_samples: Some(20),
+                ..Profile::EMPTY
+            },
+        )];
+        let benchmarks = || {
+            let tolerance = Profile {
+                min_samples: Some(7),
+                ..Profile::EMPTY
+            };
+            vec![
+                benchmark_fn("io", || 1)
+                    .with_profile("cli-test-io")
+                    .with_tolerance(tolerance),
+                benchmark_fn("cpu", || 2),
+                benchmark_fn("misspelled", || 3).with_profile("cli-test-oi"),
+            ]
+        };
+        let baseline = Spi::for_benchmarks(benchmarks()).unwrap();
+        let mut candidate = Spi::for_benchmarks(benchmarks()).unwrap();
+        candidate.resolve_profiles(&definitions);
+
+        let io = candidate.lookup("io").unwrap();
+        assert_eq!(io.profile.name.as_deref(), Some("cli-test-io"));
+        assert_eq!(io.profile.tolerance.min_effect, Some(50.));
+        assert_eq!(io.profile.tolerance.min_samples, Some(7));
+        assert_eq!(
+            profile_label("cli-test-io", &io.profile),
+            "cli-test-io: min_effect=50% min_samples=7"
+        );
+        let cpu = candidate.lookup("cpu").unwrap();
+        assert_eq!(cpu.profile, TestProfile::default());
+        let misspelled = &candidate.lookup("misspelled").unwrap().profile;
+        assert!(misspelled.undefined);
+        assert_eq!(
+            profile_label("cli-test-oi", misspelled),
+            "cli-test-oi: undefined"
+        );
+
+        let settings = MeasurementSettings::default();
+        let mut paired_test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            Some(0),
+            LoopMode::Samples(20),
+            None,
+        );
+        assert_eq!(paired_test.settings_of(io).min_effect, 50.);
+        paired_test.override_profiles(vec![
+            parse_profile_override("cli-test-io:min_effect=60%").unwrap()
+        ]);
+        let io_settings = paired_test.settings_of(io);
+        assert_eq!(io_settings.min_effect, 60.);
+        assert_eq!(io_settings.min_samples, 7);
+        assert_eq!(paired_test.settings_of(cpu).min_effect, settings.min_effect);
+
+        let result = paired_test.run("io", None).unwrap();
+        assert_eq!(result.profile.as_deref(), Some("cli-test-io"));
+        let result = paired_test.run("cpu", None).unwrap();
+        assert_eq!(result.profile, None);
+    }
+
+    #[test]
+    fn check_parse_percent() {
+        assert_eq!(parse_percent("0.5%"), Ok(0.5));
+        assert_eq!(parse_percent("2"), Ok(2.));
+        assert!(parse_percent("-1%").is_err());
+        assert!(parse_percent("%").is_err());
+    }
+
+    #[test]
+    fn check_parse_seconds() {
+        assert_eq!(parse_seconds("0.5"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_seconds("0"), Ok(Duration::ZERO));
+        for invalid in ["-1", "NaN", "inf", "1e300", "1s"] {
+            assert!(parse_seconds(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn check_parse_load() {
+        assert_eq!(parse_load("threads=2,duty=0.3"), Ok(LoadSpec::new(2, 0.3)));
+        let load = parse_load("duty=0.5, threads=1, period=20, scope=test").unwrap();
+        assert_eq!(load.period, Duration::from_millis(20));
+        assert_eq!(load.scope, LoadScope::Test);
+
+        assert!(parse_load("threads=2").is_err());
+        assert!(parse_load("threads=2,duty=1.5").is_err());
+        assert!(parse_load("threads=2,duty=0.3,period=0").is_err());
+        assert!(parse_load("threads=2,duty=0.3,load=1").is_err());
+    }
+
+    /// Runs the hanging test in a child process (the test binary itself) and checks that the watchdog aborts it
+    #[test]
+    fn check_call_timeout_aborts_hanging_test() {
+        const TEST_NAME: &str = "cli::tests::check_call_timeout_aborts_hanging_test";
+        const ENV_VAR: &str = "TANGO_RUN_HANGING_TEST";
+
+        if env::var_os(ENV_VAR).is_some() {
+            let hanging = || {
+                let f = || {
+                    while black_box(true) {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                };
+                Spi::for_benchmarks(vec![benchmark_fn("hanging", f)]).unwrap()
+            };
+            let (baseline, candidate) = (hanging(), hanging());
+            let settings = MeasurementSettings {
+                call_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            };
+            let loop_mode = LoopMode::Samples(10);
+            let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+            let _ = test.run("hanging", None);
+            unreachable!("Hanging test should be aborted");
+        }
+
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", TEST_NAME, "--nocapture"])
+            .env(ENV_VAR, "1")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("Test hanging made no progress"),
+            "{}",
+            stderr
+        );
+    }
+
+    /// Measures the tests in child processes (the test binary itself) and checks that only the hanging one fails
+    #[test]
+    fn check_skip_on_timeout_isolates_hanging_test() {
+        const TEST_NAME: &str = "cli::tests::check_skip_on_timeout_isolates_hanging_test";
+
+        if let Some(test) = IsolatedTest::from_env().unwrap() {
+            let spi = || {
+                let hanging = || {
+                    while black_box(true) {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                };
+                let benchmarks = vec![benchmark_fn("hanging", hanging), benchmark_fn("fast", || 0)];
+                Spi::for_benchmarks(benchmarks).unwrap()
+            };
+            test.run_with(&spi(), &spi(), vec![]).unwrap();
+            process::exit(0);
+        }
+
+        let isolation = Isolation {
+            baseline: PathBuf::new(),
+            settings: MeasurementSettings {
+                call_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+            seed: 0,
+            loop_mode: LoopMode::Samples(10),
+            dump: None,
+            profiles: vec![],
+            limits: None,
+            args: ["--exact", TEST_NAME, "--nocapture"]
+                .map(OsString::from)
+                .to_vec(),
+        };
+        let Err(error) = isolation.run("hanging", None) else {
+            panic!("Hanging test should time out");
+        };
+        assert!(isolated::is_contained(&error), "{:#}", error);
+        assert!(
+            error.to_string().contains("no progress for 0.1s"),
+            "{}",
+            error
+        );
+
+        let result = isolation.run("fast", None).unwrap();
+        assert_eq!(result.name.as_ref(), "fast");
+        assert_eq!(result.baseline.n, 10);
+    }
+
+    /// Measures the tests in child processes with limited memory and checks that only the allocating one fails
+    #[cfg(unix)]
+    #[test]
+    fn check_resource_limits_contain_allocating_test() {
+        const TEST_NAME: &str = "cli::tests::check_resource_limits_contain_allocating_test";
+
+        if let Some(test) = IsolatedTest::from_env().unwrap() {
+            test.apply_limits().unwrap();
+            let spi = || {
+                let allocating = || black_box(vec![1u8; 10 << 30]).len();
+                let benchmarks = vec![
+                    benchmark_fn("allocating", allocating),
+                    benchmark_fn("fast", || 0),
+                ];
+                Spi::for_benchmarks(benchmarks).unwrap()
+            };
+            test.run_with(&spi(), &spi(), vec![]).unwrap();
+            process::exit(0);
+        }
+
+        let isolation = Isolation {
+            baseline: PathBuf::new(),
+            settings: MeasurementSettings::default(),
+            seed: 0,
+            loop_mode: LoopMode::Samples(10),
+            dump: None,
+            profiles: vec![],
+            limits: Some(ResourceLimits {
+                memory: Some(1 << 30),
+                cpu: None,
+            }),
+            args: ["--exact", TEST_NAME, "--nocapture"]
+                .map(OsString::from)
+                .to_vec(),
+        };
+        let Err(error) = isolation.run("allocating", None) else {
+            panic!("Allocating test should exceed the memory limit");
+        };
+        assert!(isolated::is_contained(&error), "{:#}", error);
+        assert!(error.to_string().contains("memory allocation"), "{}", error);
+
+        let result = isolation.run("fast", None).unwrap();
+        assert_eq!(result.baseline.n, 10);
+    }
+
+    /// Runs the CLI in a child process (the test binary itself) with a given set of registered benchmarks
+    ///
+    /// Child process is needed because the benchmarks are registered in a global state, which can be initialized
+    /// only once.
+    fn run_cli(test_name: &str, benchmarks: &[&str], args: &[&str]) -> (i32, String, String) {
+        const ARGS_VAR: &str = "TANGO_CLI_ARGS";
+        const BENCHMARKS_VAR: &str = "TANGO_CLI_BENCHMARKS";
+
+        if let Some(args) = env::var_os(ARGS_VAR) {
+            // Benchmark names must be static, leaking is fine as the process exits right after
+            let names = env::var(BENCHMARKS_VAR).unwrap().leak();
+            let benchmarks = names
+                .split_whitespace()
+                .map(|name| benchmark_fn(name, || 0))
+                .collect();
+            unsafe { crate::dylib::__tango_init(benchmarks) };
+
+            let args = args.to_string_lossy();
+            let args = ["bench"].into_iter().chain(args.split_whitespace());
+            let code = match run_from(
+                args,
+                MeasurementSettings::default(),
+                &MainOptions::default(),
+                vec![],
+            ) {
+                Ok(code) if code == ExitCode::SUCCESS => 0,
+                Ok(code) if code == ExitCode::from(EXIT_NO_TESTS) => EXIT_NO_TESTS as i32,
+                Ok(_) => 1,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    1
+                }
+            };
+            process::exit(code);
+        }
+
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", test_name, "--nocapture"])
+            .env(ARGS_VAR, args.join(" "))
+            .env(BENCHMARKS_VAR, benchmarks.join(" "))
+            .output()
+            .unwrap();
+        (
+            output.status.code().unwrap(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    #[test]
+    fn check_no_benchmarks_registered() {
+        const TEST_NAME: &str = "cli::tests::check_no_benchmarks_registered";
+
+        for args in [&["list"][..], &["compare", "baseline"]] {
+            let (code, _, stderr) = run_cli(TEST_NAME, &[], args);
+            assert_eq!(code, 1, "{}", stderr);
+            assert!(stderr.contains("No benchmarks registered"), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn check_filter_matching_no_tests() {
+        const TEST_NAME: &str = "cli::tests::check_filter_matching_no_tests";
+        let benchmarks = ["sort/first", "sort/second", "search/first", "search/second"];
+
+        // Baseline is not loaded if there are no tests to run, so it doesn't need to exist
+        let (code, _, stderr) = run_cli(TEST_NAME, &benchmarks, &["compare", "none", "-f", "x*"]);
+        assert_eq!(code, EXIT_NO_TESTS as i32, "{}", stderr);
+        assert!(stderr.contains("No tests match filter `x*`"), "{}", stderr);
+        assert!(stderr.contains("4 tests registered"), "{}", stderr);
+        assert!(
+            stderr.contains("sort/first, sort/second, search/first, ..."),
+            "{}",
+            stderr
+        );
+
+        let args = ["compare", "none", "-f", "x*", "--allow-empty"];
+        let (code, _, stderr) = run_cli(TEST_NAME, &benchmarks, &args);
+        assert_eq!(code, 0, "{}", stderr);
+
+        let (code, stdout, stderr) = run_cli(TEST_NAME, &benchmarks, &["list", "-f", "*/first"]);
+        assert_eq!(code, 0, "{}", stderr);
+        // Test harness output precedes the list of tests
+        assert!(
+            stdout.ends_with(" sort/first\nsearch/first\n"),
+            "{}",
+            stdout
+        );
+        assert!(stderr.contains("2 of 4 tests match filter"), "{}", stderr);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn check_baseline_load_errors_name_baseline() {
+        const TEST_NAME: &str = "cli::tests::check_baseline_load_errors_name_baseline";
+
+        // Header of the test executable with e_type changed to ET_EXEC
+        let mut header = fs::read(env::current_exe().unwrap()).unwrap()[..64].to_vec();
+        header[16..18].copy_from_slice(&2u16.to_le_bytes());
+        let path = env::temp_dir().join(format!("tango-load-error-{}", process::id()));
+        fs::write(&path, header).unwrap();
+
+        let path_str = path.to_str().unwrap();
+        for mode in ["compare", "watch"] {
+            let (code, _, stderr) = run_cli(TEST_NAME, &["sum"], &[mode, path_str]);
+            assert_eq!(code, 1, "{}", stderr);
+            let message = format!("Unable to load baseline: {}: ", path.display());
+            assert!(stderr.contains(&message), "{}", stderr);
+            assert!(stderr.contains("position-dependent"), "{}", stderr);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    fn verified_spi(f: fn(&Vec<u32>, &()) -> u32) -> Spi<'static> {
+        let benchmarks = BenchmarkMatrix::new(RandomVec::<u32>::new(10))
+            .add_function_verified("max", f)
+            .add_function("max_unverified", f)
+            .into_benchmarks();
+        Spi::for_benchmarks(benchmarks).unwrap()
+    }
+
+    #[allow(clippy::ptr_arg)]
+    fn max(haystack: &Vec<u32>, _: &()) -> u32 {
+        haystack.iter().copied().max().unwrap()
+    }
+
+    /// Differs from [`max()`] only when the maximum is the last element
+    #[allow(clippy::ptr_arg)]
+    fn max_but_last(haystack: &Vec<u32>, _: &()) -> u32 {
+        haystack[..haystack.len() - 1]
+            .iter()
+            .copied()
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn check_output_verification() {
+        let baseline = verified_spi(max);
+        let candidate = verified_spi(max_but_last);
+        let same_candidate = verified_spi(max);
+        let settings = MeasurementSettings {
+            verification_samples: 100,
+            ..Default::default()
+        };
+        let samples = LoopMode::Samples(10);
+        let test_name = "max/u32/10";
+
+        let paired_test = PairedTest::new(&baseline, &candidate, settings, Some(0), samples, None);
+        let Verification::Mismatch(mismatch) = paired_test.verify(test_name).unwrap() else {
+            panic!("Outputs mismatch is not detected");
+        };
+        let message = mismatch.to_string();
+        assert!(message.contains(" of 100 inputs"), "{}", message);
+        assert!(message.contains("First mismatch (input #"), "{}", message);
+
+        let paired_test =
+            PairedTest::new(&baseline, &same_candidate, settings, Some(0), samples, None);
+        assert_eq!(paired_test.verify(test_name).unwrap(), Verification::Passed);
+
+        let unverified = "max_unverified/u32/10";
+        let result = paired_test.verify(unverified).unwrap();
+        assert_eq!(result, Verification::NotApplicable);
+
+        let settings = MeasurementSettings {
+            verification_samples: 0,
+            ..settings
+        };
+        let paired_test = PairedTest::new(&baseline, &candidate, settings, Some(0), samples, None);
+        let result = paired_test.verify(test_name).unwrap();
+        assert_eq!(result, Verification::NotApplicable);
+    }
+
+    /// Generates a fixed text and random substrings of it (ranges on char boundaries)
+    struct RandomSubstring {
+        text: Rc<String>,
+        rng: SmallRng,
+    }
+
+    impl RandomSubstring {
+        fn new() -> Self {
+            Self {
+                text: Rc::new("Tango – быстрый 🕺 benchmark ".repeat(20)),
+                rng: SmallRng::seed_from_u64(0),
+            }
+        }
+    }
+
+    impl Generator for RandomSubstring {
+        type Haystack = Rc<String>;
+        type Needle = Range<usize>;
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            Rc::clone(&self.text)
+        }
+
+        fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+            let boundaries = haystack.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+            let start = self.rng.gen_range(0..boundaries.len() / 2);
+            boundaries[start]..boundaries[start + boundaries.len() / 2]
+        }
+
+        fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+            Some(HaystackSize::Elements(haystack.chars().count() / 2))
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.rng = SmallRng::seed_from_u64(seed);
+        }
+    }
+
+    fn str_count(s: &Rc<String>, idx: &Range<usize>) -> usize {
+        s[idx.clone()].chars().count()
+    }
+
+    fn str_count_rev(s: &Rc<String>, idx: &Range<usize>) -> usize {
+        s[idx.clone()].chars().rev().count()
+    }
+
+    /// Counts bytes instead of chars, so the output is different for non-ASCII substrings
+    fn str_len(s: &Rc<String>, idx: &Range<usize>) -> usize {
+        s[idx.clone()].len()
+    }
+
+    fn str_target(
+        name: &str,
+        f: fn(&Rc<String>, &Range<usize>) -> usize,
+    ) -> Box<dyn MeasureTarget> {
+        let mut targets = BenchmarkMatrix::new(RandomSubstring::new())
+            .add_function_verified(name, f)
+            .into_benchmarks();
+        targets.pop().unwrap()
+    }
+
+    /// Pairs `str_length` and `rev_name` comparing both orders of the same two functions
+    fn str_length_pairs(rev_name: &str) -> Vec<PairedTargets> {
+        vec![
+            PairedTargets::new(
+                str_target("str_count", str_count),
+                str_target("str_count_rev", str_count_rev),
+            )
+            .with_name("str_length"),
+            PairedTargets::new(
+                str_target("str_count_rev", str_count_rev),
+                str_target("str_count", str_count),
+            )
+            .with_name(rev_name),
+        ]
+    }
+
+    /// Runs the pairs with the path of a temporary file appended to the arguments and returns the contents of the
+    /// file written to it
+    fn run_pairs_to_file(
+        name: &str,
+        args: &[&str],
+        settings: MeasurementSettings,
+        pairs: Vec<PairedTargets>,
+    ) -> (ExitCode, String) {
+        let dir = env::temp_dir().join(format!("tango-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results");
+        let args = args.iter().copied().chain([path.to_str().unwrap()]);
+        let exit_code = run_pairs_from(args, settings, pairs).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (exit_code, contents)
+    }
+
+    /// Checks that both targets of a pair are given the same inputs and measures them (10 iterations per sample)
+    fn run_pair(pair: PairedTargets, samples: usize) -> RunResult {
+        let name = pair.name().to_string();
+        let test = TestPair {
+            name: &name,
+            id: pair.id(),
+        };
+        let baseline = Spi::for_benchmarks(vec![pair.baseline]).unwrap();
+        let candidate = Spi::for_benchmarks(vec![pair.candidate]).unwrap();
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(samples);
+        let paired_test =
+            PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        assert_eq!(
+            paired_test.verify_pair(&test).unwrap(),
+            Verification::Passed
+        );
+        paired_test.run_pair(&test, None).unwrap()
+    }
+
+    #[test]
+    fn check_paired_targets() {
+        let pair = PairedTargets::new(
+            str_target("str_count", str_count),
+            str_target("str_count_rev", str_count_rev).with_weight(3.),
+        );
+        assert_eq!(
+            pair.name(),
+            "str_count/RandomSubstring vs str_count_rev/RandomSubstring"
+        );
+        let pair = pair.with_name("str_length");
+
+        let result = run_pair(pair, 20);
+        assert_eq!(&*result.name, "str_length");
+        assert_eq!(result.schedule.name, "str_length");
+        assert_eq!(result.schedule.samples, 20);
+        // Weight is taken from the candidate
+        assert_eq!(result.weight, 3.);
+    }
+
+    #[test]
+    fn check_pair_identity_in_outputs() {
+        let pair = PairedTargets::new(
+            str_target("str_count", str_count),
+            str_target("str_count_rev", str_count_rev),
+        );
+        let id = pair.id();
+        assert_eq!(id.baseline, "str_count/RandomSubstring");
+        assert_eq!(id.candidate, "str_count_rev/RandomSubstring");
+        // Default name of a pair is the rendered identity
+        assert_eq!(pair.name(), id.to_string());
+
+        let name = pair.name().to_string();
+        let result = run_pair(pair, 10);
+        assert_eq!(result.id, id);
+        assert_eq!(&*result.name, name);
+
+        assert!(TestPair::same(&name).id.is_same());
+
+        // Exported results keep the display name as a string next to the structured identity
+        let line = serde_json::to_value(JsonResult::from(&result)).unwrap();
+        assert_eq!(line["name"], name.as_str());
+        assert_eq!(line["id"]["baseline"], "str_count/RandomSubstring");
+        assert_eq!(line["id"]["candidate"], "str_count_rev/RandomSubstring");
+        let parsed: JsonResult = serde_json::from_value(line).unwrap();
+        assert_eq!(parsed.id, id);
+    }
+
+    #[test]
+    fn check_throughput_reported() {
+        let pair = PairedTargets::new(
+            str_target("str_count", str_count),
+            str_target("str_count_rev", str_count_rev),
+        );
+        let result = run_pair(pair, 10);
+
+        // RandomSubstring reports the number of characters searched in each haystack
+        let rates = result.throughput.unwrap();
+        assert_eq!(rates.unit, ThroughputUnit::Elements);
+        assert!(rates.baseline > 0. && rates.candidate > 0.);
+        let ratio = rates.baseline / rates.candidate;
+        let expected = result.candidate.mean / result.baseline.mean;
+        assert!((ratio - expected).abs() < 1e-6 * expected);
+    }
+
+    #[test]
+    fn check_run_pairs() {
+        let args = [
+            "bench",
+            "-s",
+            "20",
+            "--color",
+            "never",
+            "--no-cache",
+            "str_",
+        ];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let run = |pairs| run_pairs_from(args, settings, pairs).unwrap();
+
+        let pairs = vec![
+            PairedTargets::new(
+                str_target("str_count", str_count),
+                str_target("str_count_rev", str_count_rev),
+            )
+            .with_name("str_length"),
+            // Filtered out, otherwise the run would fail because of different outputs
+            PairedTargets::new(
+                str_target("str_count", str_count),
+                str_target("str_len", str_len),
+            )
+            .with_name("bytes_vs_chars"),
+        ];
+        assert_eq!(run(pairs), ExitCode::SUCCESS);
+
+        let pairs = vec![PairedTargets::new(
+            str_target("str_count", str_count),
+            str_target("str_len", str_len),
+        )
+        .with_name("str_bytes")];
+        assert_eq!(run(pairs), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn check_json_results() {
+        let args = ["bench", "-s", "20", "--no-cache", "str_", "--json"];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let pairs = str_length_pairs("str_length_rev");
+        let (exit_code, json) = run_pairs_to_file("json", &args, settings, pairs);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+
+        let value: Value = serde_json::from_str(&json).unwrap();
+        let results: Vec<JsonResult> = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(to_value(&results).unwrap(), value);
+
+        let names = results.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["str_length", "str_length_rev"]);
+        let result = &results[0];
+        assert_eq!(
+            result.id,
+            PairId::new("str_count/RandomSubstring", "str_count_rev/RandomSubstring")
+        );
+        assert_eq!(result.unit, Unit::Nanoseconds);
+        assert_eq!(result.generator.as_deref(), Some("RandomSubstring"));
+        assert_eq!(result.diff.n + result.outliers, 20);
+        let significance = &result.significance;
+        assert_eq!(
+            significance.significant,
+            significance.verdict == Verdict::Different
+        );
+
+        // Field names are the documented schema
+        let keys = value[0].as_object().unwrap().keys().collect::<Vec<_>>();
+        let schema = [
+            "baseline",
+            "candidate",
+            "diff",
+            "generator",
+            "id",
+            "name",
+            "outliers",
+            "significance",
+            "unit",
+            "weight",
+        ];
+        assert_eq!(keys, schema);
+        let keys = value[0]["significance"].as_object().unwrap().keys();
+        assert!(keys.eq(["ci", "pct", "significant", "stats_version", "verdict"]));
+        assert_eq!(significance.stats_version, stats::STATS_VERSION);
+        let keys = value[0]["baseline"].as_object().unwrap().keys();
+        assert!(keys.eq(["max", "mean", "min", "n", "variance"]));
+    }
+
+    #[test]
+    fn check_csv_results() {
+        let args = ["bench", "-s", "20", "--no-cache", "str_", "--csv"];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let pairs = str_length_pairs("str_length, \"rev\"");
+        let (exit_code, csv) = run_pairs_to_file("csv", &args, settings, pairs);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "name,n,unit,baseline_mean,baseline_min,baseline_max,baseline_std_dev,candidate_mean,candidate_min,candidate_max,candidate_std_dev,diff_mean,change_pct,significant,outliers,stats_version,recording"
+        );
+        assert_eq!(lines.len(), 3);
+        let row = lines[1].split(',').collect::<Vec<_>>();
+        assert_eq!(row.len(), 17);
+        assert_eq!(row[15], stats::STATS_VERSION.to_string());
+        assert_eq!(row[16], "");
+        assert_eq!(row[0], "str_length");
+        let n = row[1].parse::<usize>().unwrap();
+        let outliers = row[14].parse::<usize>().unwrap();
+        assert_eq!(n + outliers, 20);
+        assert_eq!(row[2], "ns");
+        let [mean, min, max] = [row[3], row[4], row[5]].map(|v| v.parse::<f64>().unwrap());
+        assert!(min <= mean && mean <= max);
+        assert!(["true", "false"].contains(&row[13]));
+
+        // Separators and quotes in the name are escaped
+        assert!(lines[2].starts_with("\"str_length, \"\"rev\"\"\",20,ns,"));
+        assert_eq!(reporting::csv_field("a\nb"), "\"a\nb\"");
+    }
+
+    #[test]
+    fn check_aa_test() {
+        let args = ["bench", "-s", "300", "--no-cache", "--aa-test", "--json"];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            min_effect: 2.,
+            ..Default::default()
+        };
+        let sum =
+            |name, n: u64| move || benchmark_fn(name, move || (0..n).map(black_box).sum::<u64>());
+        // Each A/A comparison gets its own instance of the target
+        let instances = Rc::new(Cell::new(0));
+        let counted = |n| {
+            let instances = Rc::clone(&instances);
+            move || {
+                instances.set(instances.get() + 1);
+                sum("sum_2000", n)()
+            }
+        };
+        let pairs = vec![
+            PairedTargets::from_fn(sum("sum_1000", 1000), counted(2000)).with_name("sum"),
+            PairedTargets::new(sum("sum_1000", 1000)(), sum("sum_3000", 3000)()).with_name("new"),
+        ];
+        let (exit_code, json) = run_pairs_to_file("aa-test", &args, settings, pairs);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        assert_eq!(instances.get(), 2);
+
+        // A/A comparisons follow the pair, pairs created without factories have none
+        let results: Vec<JsonResult> = serde_json::from_str(&json).unwrap();
+        let names = results.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["sum", "sum (A/A baseline)", "sum (A/A candidate)", "new"]
+        );
+        assert_eq!(results[0].id, PairId::new("sum_1000", "sum_2000"));
+        assert_eq!(results[1].id, PairId::same("sum_1000"));
+        assert_eq!(results[2].id, PairId::same("sum_2000"));
+        for result in &results[1..3] {
+            assert!(!result.significance.significant, "{}", result.name);
+            assert_eq!(result.aa_of.as_deref(), Some("sum"));
+        }
+        assert_eq!(results[0].aa_of, None);
+        assert!(results[0].significance.significant);
+        assert!(results[0].significance.pct > 50.);
+    }
+
+    #[test]
+    fn check_ndjson_to_stdout() {
+        assert!(ndjson_to_stdout(Some(Path::new("-")), false).unwrap());
+        assert!(!ndjson_to_stdout(Some(Path::new("out.ndjson")), true).unwrap());
+        assert!(!ndjson_to_stdout(None, true).unwrap());
+        // Markdown table would be interleaved with the stream
+        assert!(ndjson_to_stdout(Some(Path::new("-")), true).is_err());
+    }
+
+    #[test]
+    fn check_ndjson_stream() {
+        let args = ["bench", "-s", "10", "--no-cache", "--ndjson"];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let pairs = vec![
+            PairedTargets::new(
+                str_target("str_count", str_count),
+                str_target("str_count_rev", str_count_rev),
+            ),
+            PairedTargets::new(
+                benchmark_fn("sum", || (0..100u64).map(black_box).sum::<u64>()),
+                benchmark_fn("sum_rev", || (0..100u64).rev().map(black_box).sum::<u64>()),
+            ),
+        ];
+        let (exit_code, ndjson) = run_pairs_to_file("ndjson", &args, settings, pairs);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+
+        let lines = ndjson
+            .lines()
+            .map(|l| serde_json::from_str::<NdjsonLine>(l).unwrap())
+            .collect::<Vec<_>>();
+        let seq = lines.iter().map(|l| l.seq).collect::<Vec<_>>();
+        assert_eq!(seq, [1, 2]);
+        assert_eq!(
+            lines[0].result.generator.as_deref(),
+            Some("RandomSubstring")
+        );
+        assert_eq!(lines[1].result.name, "sum vs sum_rev");
+        assert_eq!(lines[1].result.generator, None);
+    }
+
+    #[test]
+    fn check_replayed_inputs() {
+        let dir = TempDirFixture::create_in(&env::temp_dir(), "tango-replay").unwrap();
+        let recording = dir.path().join("inputs.jsonl");
+        // Needles are not recorded, so the replayed ones can't get out of sync with the haystacks
+        let mut recorder = Recorder::wrap(RandomVec::<u32>::new(10), &recording).unwrap();
+        for _ in 0..3 {
+            recorder.next_haystack();
+        }
+        drop(recorder);
+
+        let path = dir.path().join("results.ndjson");
+        let args = [
+            "bench",
+            "-s",
+            "10",
+            "--no-cache",
+            "--ndjson",
+            path.to_str().unwrap(),
+        ];
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            samples_per_haystack: 1,
+            ..Default::default()
+        };
+        let replayed = |name, f: fn(&Vec<u32>, &()) -> Option<u32>, looping| {
+            let replayer = Replayer::<Vec<u32>, ()>::open(&recording).unwrap();
+            let replayer = if looping {
+                replayer.looping()
+            } else {
+                replayer
+            };
+            let mut targets = BenchmarkMatrix::new(replayer)
+                .add_function(name, f)
+                .into_benchmarks();
+            targets.pop().unwrap()
+        };
+        let min = |h: &Vec<u32>, _: &()| h.iter().copied().min();
+        let max = |h: &Vec<u32>, _: &()| h.iter().copied().max();
+
+        let pairs = vec![PairedTargets::new(
+            replayed("min", min, true),
+            replayed("max", max, true),
+        )];
+        let exit_code = run_pairs_from(args, settings, pairs).unwrap();
+        let ndjson = fs::read_to_string(&path).unwrap();
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        let line = serde_json::from_str::<NdjsonLine>(ndjson.trim_end()).unwrap();
+        assert_eq!(line.result.recording, Some(recording.clone()));
+
+        // Exhausted recording fails the test
+        let pairs = vec![PairedTargets::new(
+            replayed("min", min, false),
+            replayed("max", max, false),
+        )];
+        let error = run_pairs_from(args, settings, pairs)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("is exhausted while reading haystack"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn check_coloring_mode() {
+        use clap::ValueEnum;
+
+        let detect = ColorChoice::from_str("detect", false).unwrap();
+        assert_eq!(detect, ColorChoice::Auto);
+        assert_eq!(coloring_mode(ColorChoice::Auto, None), Mode::Detect);
+        let no_color = Some(OsString::from("1"));
+        assert_eq!(
+            coloring_mode(ColorChoice::Auto, no_color.clone()),
+            Mode::Never
+        );
+        // Empty NO_COLOR is the same as not set
+        let empty = Some(OsString::new());
+        assert_eq!(coloring_mode(ColorChoice::Auto, empty), Mode::Detect);
+        // Explicit choice takes precedence
+        assert_eq!(coloring_mode(ColorChoice::Always, no_color), Mode::Always);
+        assert_eq!(coloring_mode(ColorChoice::Never, None), Mode::Never);
+    }
+
+    /// Benchmarks with different haystack types registered the same way `tango_benchmarks!()` does
+    fn mixed_haystacks_spi() -> Spi<'static> {
+        let mut benchmarks = vec![];
+        benchmarks.extend(
+            BenchmarkMatrix::new(RandomSubstring::new())
+                .add_function_verified("str_count", str_count)
+                .into_benchmarks(),
+        );
+        benchmarks.extend(
+            BenchmarkMatrix::new(RandomVec::<u32>::new(10))
+                .add_function_verified("max", |v: &Vec<u32>, _: &()| v.iter().max().copied())
+                .into_benchmarks(),
+        );
+        Spi::for_benchmarks(benchmarks).unwrap()
+    }
+
+    #[test]
+    fn check_mixed_haystack_types() {
+        let (baseline, candidate) = (mixed_haystacks_spi(), mixed_haystacks_spi());
+        let names = candidate
+            .tests()
+            .iter()
+            .map(|t| t.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["str_count/RandomSubstring", "max/u32/10"]);
+
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        for name in &names {
+            assert_eq!(test.verify(name).unwrap(), Verification::Passed);
+            assert_eq!(test.run(name, None).unwrap().schedule.samples, 10);
+        }
+    }
+
+    /// Target measuring the time drifting by 1ns per each measurement of any of the targets sharing `clock`
+    struct DriftingTarget {
+        name: &'static str,
+        clock: Arc<AtomicU64>,
+    }
+
+    impl MeasureTarget for DriftingTarget {
+        fn measure(&mut self, iterations: usize) -> u64 {
+            thread::sleep(Duration::from_micros(50));
+            (1000 + self.clock.fetch_add(1, Ordering::Relaxed)) * iterations as u64
+        }
+
+        fn estimate_iterations(&mut self, _: u32) -> usize {
+            1
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            false
+        }
+
+        fn sync(&mut self, _: u64) {}
+
+        fn name(&self) -> &str {
+            self.name
+        }
+    }
+
+    #[test]
+    fn check_round_robin_spreads_drift() {
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Time(Duration::from_millis(100));
+        let tests = ["a", "b"];
+
+        // Difference of the baseline means of two identical tests measured while the environment drifts
+        let skew = |schedule: TestOrder| {
+            let clock = Arc::new(AtomicU64::new(0));
+            let spi = || {
+                let targets = tests.map(|name| {
+                    let clock = Arc::clone(&clock);
+                    Box::new(DriftingTarget { name, clock }) as Box<dyn MeasureTarget>
+                });
+                Spi::for_benchmarks(targets.into()).unwrap()
+            };
+            let (baseline, candidate) = (spi(), spi());
+            let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+            let results = match schedule {
+                TestOrder::Sequential => tests
+                    .iter()
+                    .map(|name| test.run(name, None).unwrap())
+                    .collect::<Vec<_>>(),
+                TestOrder::RoundRobin => {
+                    let slice = Duration::from_millis(10);
+                    let mut round_robin =
+                        RoundRobin::start(&test, &tests, slice, None, |_| {}).unwrap();
+                    tests
+                        .iter()
+                        .map(|name| round_robin.take(name).unwrap().unwrap().unwrap())
+                        .collect()
+                }
+            };
+            (results[1].baseline.mean - results[0].baseline.mean).abs()
+        };
+
+        let sequential = skew(TestOrder::Sequential);
+        let round_robin = skew(TestOrder::RoundRobin);
+        assert!(
+            round_robin < sequential / 3.,
+            "round-robin: {}, sequential: {}",
+            round_robin,
+            sequential
+        );
+    }
+
+    #[test]
+    fn check_round_robin_slices() {
+        let (baseline, baseline_calls) = counting_spi(Duration::from_millis(1));
+        let (candidate, _) = counting_spi(Duration::from_millis(1));
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Time(Duration::from_millis(50));
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+
+        let slice = Duration::from_millis(10);
+        let mut started = vec![];
+        let mut round_robin = RoundRobin::start(&test, &["counting"], slice, None, |name| {
+            started.push(name.to_string())
+        })
+        .unwrap();
+        assert!(round_robin.contains("counting"));
+        assert_eq!(started, ["counting"]);
+        let result = round_robin.take("counting").unwrap().unwrap().unwrap();
+        assert!(!round_robin.contains("counting"));
+        assert!(round_robin.take("other").unwrap().is_none());
+        // Slices after the first one replay its number of samples and the pooled result accounts all of them
+        let samples = baseline_calls.borrow().len();
+        assert_eq!(result.schedule.samples, samples);
+        assert_eq!(result.baseline.n, samples);
+        assert_eq!(test.slices(&result, slice), 5);
+    }
+
+    /// Records the number of iterations of each call. Estimation is not supported
+    struct CountingTarget(Rc<RefCell<Vec<usize>>>, Duration);
+
+    impl MeasureTarget for CountingTarget {
+        fn measure(&mut self, iterations: usize) -> u64 {
+            self.0.borrow_mut().push(iterations);
+            thread::sleep(self.1);
+            1
+        }
+
+        fn estimate_iterations(&mut self, _: u32) -> usize {
+            panic!("Estimation should not be used");
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            false
+        }
+
+        fn sync(&mut self, _: u64) {}
+
+        fn name(&self) -> &str {
+            "counting"
+        }
+    }
+
+    fn counting_spi(delay: Duration) -> (Spi<'static>, Rc<RefCell<Vec<usize>>>) {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let target = Box::new(CountingTarget(Rc::clone(&calls), delay));
+        (Spi::for_benchmarks(vec![target]).unwrap(), calls)
+    }
+
+    #[test]
+    fn check_fixed_schedule() {
+        let (baseline, baseline_calls) = counting_spi(Duration::ZERO);
+        let (candidate, candidate_calls) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(7),
+            exact_samples: Some(13),
+            ..Default::default()
+        };
+        // Time limit is ignored if the number of samples is exact
+        let loop_mode = LoopMode::Time(Duration::ZERO);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("counting", None).unwrap();
+        assert_eq!(*baseline_calls.borrow(), vec![7; 13]);
+        assert_eq!(*candidate_calls.borrow(), vec![7; 13]);
+        assert_eq!(result.schedule.estimate, 7);
+        assert_eq!(result.schedule.samples, 13);
+        assert_eq!(result.schedule.total_iterations, 7 * 13);
+    }
+
+    fn setup_counting_spi(include_setup: bool) -> (Spi<'static>, Rc<Cell<usize>>) {
+        let setups = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&setups);
+        let setup = move || counter.set(counter.get() + 1);
+        let target = benchmark_fn_with_setup("setup", setup, |_| {}).include_setup(include_setup);
+        (Spi::for_benchmarks(vec![target]).unwrap(), setups)
+    }
+
+    #[test]
+    fn check_setup_inclusion_is_propagated() {
+        let (baseline, baseline_setups) = setup_counting_spi(false);
+        let (candidate, candidate_setups) = setup_counting_spi(true);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(3),
+            exact_samples: Some(4),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(4);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("setup", None).unwrap();
+        assert!(result.e2e);
+        // Both functions create fresh state for each iteration
+        assert_eq!(baseline_setups.get(), 3 * 4);
+        assert_eq!(candidate_setups.get(), 3 * 4);
+    }
+
+    #[test]
+    fn check_streaming_mode() {
+        let (baseline, baseline_calls) = counting_spi(Duration::ZERO);
+        let (candidate, _) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(3),
+            exact_samples: Some(100),
+            streaming: true,
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(100);
+        let dump = env::temp_dir().join(format!("tango-streaming-dump-{}", process::id()));
+
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            None,
+            loop_mode,
+            Some(dump.clone()),
+        );
+        let result = test.run("counting", None).unwrap();
+        assert_eq!(baseline_calls.borrow().len(), 100);
+        assert_eq!(result.diff.n, 100);
+        assert_eq!(result.schedule.total_iterations, 300);
+        // Each sample of 3 iterations measures 1
+        assert!((result.baseline.mean - 1. / 3.).abs() < 1e-9);
+        assert_eq!(result.diff.mean, 0.);
+        // Samples are not retained, so nothing is dumped
+        assert!(!dump.exists());
+    }
+
+    #[test]
+    fn check_samples_over_memory_cap_are_downsampled() {
+        let (baseline, _) = counting_spi(Duration::ZERO);
+        let (candidate, _) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(3),
+            outlier_detection: OutlierDetection::Iqr,
+            max_sample_memory: 2000,
+            ..Default::default()
+        };
+        let capacity = settings.sample_capacity();
+        let dump = env::temp_dir().join(format!("tango-reservoir-dump-{}", process::id()));
+
+        // Planned samples don't fit from the start
+        let loop_mode = LoopMode::Samples(100);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            Some(0),
+            loop_mode,
+            Some(dump.clone()),
+        );
+        let result = test.run("counting", None).unwrap();
+        assert_eq!(result.reservoir, Some(capacity));
+        // Summaries are computed from all the samples
+        assert_eq!(result.diff.n, 100);
+        assert!((result.baseline.mean - 1. / 3.).abs() < 1e-9);
+        let [base, _] = result.samples.unwrap();
+        assert_eq!(base.len(), capacity);
+
+        let path = result.dump.unwrap().unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let measurements = commands::read_raw_measurements(&path).unwrap();
+        fs::remove_dir_all(&dump).unwrap();
+        assert!(content.starts_with(&format!("# reservoir: {} of 100\n", capacity)));
+        assert!(!content.contains("# first:"));
+        let reanalyzed = measurements.analyze("counting", &settings).unwrap();
+        assert_eq!(reanalyzed.diff.n, capacity);
+
+        // Samples of a test with a time limit are switched once they exceed the cap
+        let loop_mode = LoopMode::Time(Duration::from_millis(20));
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let result = test.run("counting", None).unwrap();
+        assert!(result.schedule.samples > capacity);
+        assert_eq!(result.diff.n, result.schedule.samples);
+        assert_eq!(result.reservoir, Some(capacity));
+
+        // Samples within the cap are retained as is
+        let loop_mode = LoopMode::Samples(capacity);
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        assert_eq!(test.run("counting", None).unwrap().reservoir, None);
+    }
+
+    #[test]
+    fn check_max_total_time() {
+        let (baseline, _) = counting_spi(Duration::from_millis(1));
+        let (candidate, candidate_calls) = counting_spi(Duration::from_millis(1));
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            exact_samples: Some(1000),
+            max_total_time: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let error = test.run("counting", None).err().unwrap().to_string();
+        assert!(error.contains("exceeds maximum total time"), "{}", error);
+        // Test is stopped as soon as the projection exceeds the limit
+        assert!(candidate_calls.borrow().len() < 50);
+    }
+
+    /// Calls of [`SlowEstimateTarget`]
+    #[derive(Default)]
+    struct EstimatedCalls {
+        estimations: usize,
+        iterations: Vec<usize>,
+    }
+
+    /// Records the number of iterations of each call. Estimation takes a given time
+    struct SlowEstimateTarget {
+        calls: Rc<RefCell<EstimatedCalls>>,
+        estimate_time: Duration,
+
+        /// Time each sample takes regardless of the number of iterations
+        sample_time: Duration,
+    }
+
+    impl MeasureTarget for SlowEstimateTarget {
+        fn measure(&mut self, iterations: usize) -> u64 {
+            self.calls.borrow_mut().iterations.push(iterations);
+            thread::sleep(self.sample_time);
+            1
+        }
+
+        /// Reports 100 iterations in 50 ms (0.5 ms per iteration)
+        fn estimate_iterations(&mut self, _: u32) -> usize {
+            self.calls.borrow_mut().estimations += 1;
+            thread::sleep(self.estimate_time);
+            100
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            false
+        }
+
+        fn sync(&mut self, _: u64) {}
+
+        fn name(&self) -> &str {
+            "slow_estimate"
+        }
+    }
+
+    fn slow_estimate_spi(estimate_time: Duration) -> (Spi<'static>, Rc<RefCell<EstimatedCalls>>) {
+        estimated_spi(estimate_time, Duration::ZERO)
+    }
+
+    fn estimated_spi(
+        estimate_time: Duration,
+        sample_time: Duration,
+    ) -> (Spi<'static>, Rc<RefCell<EstimatedCalls>>) {
+        let calls = Rc::new(RefCell::new(EstimatedCalls::default()));
+        let target = Box::new(SlowEstimateTarget {
+            calls: Rc::clone(&calls),
+            estimate_time,
+            sample_time,
+        });
+        (Spi::for_benchmarks(vec![target]).unwrap(), calls)
+    }
+
+    #[test]
+    fn check_estimate_budget() {
+        let estimate_time = Duration::from_millis(100);
+        let (baseline, baseline_calls) = slow_estimate_spi(estimate_time);
+        let (candidate, candidate_calls) = slow_estimate_spi(estimate_time);
+        let settings = MeasurementSettings {
+            estimate_budget: Some(Duration::from_millis(50)),
+            exact_samples: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("slow_estimate", None).unwrap();
+        let overrun = result.estimate_overrun.unwrap();
+        // Candidate is not estimated once the baseline exhausted the budget
+        assert_eq!(baseline_calls.borrow().estimations, 1);
+        assert_eq!(candidate_calls.borrow().estimations, 0);
+        assert!(overrun >= estimate_time && overrun < estimate_time * 2);
+        assert!(result.phases.estimate < estimate_time * 2);
+        assert_eq!(baseline_calls.borrow().iterations, vec![1; 10]);
+        assert_eq!(candidate_calls.borrow().iterations, vec![1; 10]);
+        assert_eq!(result.schedule.estimate, 1);
+
+        let (baseline, _) = slow_estimate_spi(Duration::ZERO);
+        let (candidate, candidate_calls) = slow_estimate_spi(Duration::ZERO);
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("slow_estimate", None).unwrap();
+        assert_eq!(result.estimate_overrun, None);
+        assert_eq!(candidate_calls.borrow().estimations, 1);
+        assert_eq!(result.schedule.estimate, 100);
+    }
+
+    #[test]
+    fn check_batch_curve() {
+        let (baseline, baseline_calls) = slow_estimate_spi(Duration::ZERO);
+        let (candidate, _) = slow_estimate_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            batch_curve: true,
+            exact_samples: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("slow_estimate", None).unwrap();
+
+        // Batch sizes are measured before the samples are taken
+        let iterations = &baseline_calls.borrow().iterations;
+        let curve_calls = BATCH_SIZES.len() * BATCH_CURVE_REPEATS;
+        assert_eq!(iterations.len(), curve_calls + 10);
+        assert_eq!(iterations[..BATCH_SIZES.len()], BATCH_SIZES);
+
+        // Each sample reports the same value regardless of the number of iterations
+        let [baseline_curve, _] = result.schedule.batch_curve.unwrap();
+        assert_eq!(baseline_curve.points.first(), Some(&(1, 1.)));
+        assert!(baseline_curve.change_pct() < -settings.batch_curve_threshold);
+    }
+
+    /// Function with a slow path taken on every `period`-th call (never if `period` is 0)
+    fn rare_slow_path_spi(period: u64, slow: Duration) -> Spi<'static> {
+        let calls = Cell::new(0u64);
+        let target = benchmark_fn("rare_slow_path", move || {
+            let n = calls.get() + 1;
+            calls.set(n);
+            if period > 0 && n.is_multiple_of(period) {
+                let start = Instant::now();
+                while start.elapsed() < slow {
+                    std::hint::spin_loop();
+                }
+            }
+            (0..black_box(10u64)).sum::<u64>()
+        });
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
+
+    #[test]
+    fn check_per_call_tail_latencies() {
+        let slow = Duration::from_micros(200);
+        let baseline = rare_slow_path_spi(0, slow);
+        // 0.2% of the calls take the slow path: visible in p99.9, but not in p99
+        let candidate = rare_slow_path_spi(500, slow);
+        let settings = MeasurementSettings {
+            per_call: true,
+            per_call_samples: 5000,
+            exact_samples: Some(10),
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("rare_slow_path", None).unwrap();
+
+        // Usual mean comparison is still made
+        assert_eq!(result.diff.n, 10);
+        let tail = result.tail.unwrap();
+        assert_eq!(tail.calls, 5000);
+        let slow_ns = slow.as_nanos() as f64;
+        assert!(tail.candidate.p999 >= slow_ns, "{:?}", tail);
+        assert!(tail.candidate.max >= slow_ns, "{:?}", tail);
+        assert!(tail.candidate.p99 < slow_ns, "{:?}", tail);
+        assert!(tail.baseline.p999 < slow_ns, "{:?}", tail);
+        assert!(tail.deltas()[1] > 100., "{:?}", tail);
+
+        // Calls are not measured unless requested
+        let settings = MeasurementSettings {
+            per_call: false,
+            ..settings
+        };
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        assert!(test.run("rare_slow_path", None).unwrap().tail.is_none());
+    }
+
+    /// Spins for 5-15µs (10µs on average) varying pseudo-randomly from call to call
+    fn varying_workload_spi(seed: u64) -> Spi<'static> {
+        let state = Cell::new(seed);
+        let target = benchmark_fn("workload", move || {
+            // xorshift64
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+            let spin = Duration::from_nanos(5_000 + x % 10_000);
+            let start = Instant::now();
+            while start.elapsed() < spin {
+                std::hint::spin_loop();
+            }
+        });
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
+
+    #[test]
+    fn check_overhead_variance() {
+        let settings = MeasurementSettings {
+            null_interval: Some(1),
+            outlier_detection: OutlierDetection::Iqr,
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(2000);
+
+        // Differences of a near-empty function are the noise of the measurement
+        let empty = || {
+            let target = benchmark_fn("empty", || black_box(1u64));
+            Spi::for_benchmarks(vec![target]).unwrap()
+        };
+        let (baseline, candidate) = (empty(), empty());
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("empty", None).unwrap();
+        let overhead = result.overhead_variance.unwrap();
+        // Null pairs are filtered the same way as the samples of the test. The split itself is checked on fixed
+        // values by the tests of `overhead_variance()`, the variances of timer readings fluctuate from run to run
+        assert!(overhead.samples > 1000 && overhead.samples <= 2000);
+        assert!(
+            (0. ..=1.).contains(&overhead.overhead_fraction),
+            "{:?}",
+            overhead
+        );
+        assert!(result.phases.null > Duration::ZERO);
+        assert!(result.null_skipped.is_none());
+
+        // Overhead of a varying workload
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            ..settings
+        };
+        let (baseline, candidate) = (varying_workload_spi(1), varying_workload_spi(2));
+        let loop_mode = LoopMode::Samples(500);
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("workload", None).unwrap();
+        let overhead = result.overhead_variance.unwrap();
+        assert!(
+            (0. ..=1.).contains(&overhead.overhead_fraction),
+            "{:?}",
+            overhead
+        );
+
+        // Empty function is not sampled unless requested
+        let settings = MeasurementSettings {
+            null_interval: None,
+            ..settings
+        };
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("workload", None).unwrap();
+        assert!(result.overhead_variance.is_none());
+        assert_eq!(result.phases.null, Duration::ZERO);
+
+        // Overhead is not comparable to the differences of the values other than time
+        let settings = MeasurementSettings {
+            null_interval: Some(1),
+            ..settings
+        };
+        let metric = || {
+            let target = crate::benchmark_fn_metric("size", || 1u8, |_| 1, Unit::Bytes);
+            Spi::for_benchmarks(vec![target]).unwrap()
+        };
+        let (baseline, candidate) = (metric(), metric());
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let result = test.run("size", None).unwrap();
+        assert!(result.overhead_variance.is_none());
+        assert_eq!(result.null_skipped, Some("the test doesn't measure time"));
+    }
+
+    /// Processes `batch` elements per iteration in `element_ns` nanoseconds each
+    struct BatchTarget {
+        batch: usize,
+        element_ns: u64,
+    }
+
+    impl MeasureTarget for BatchTarget {
+        fn measure(&mut self, iterations: usize) -> u64 {
+            self.element_ns * (self.batch * iterations) as u64
+        }
+
+        fn estimate_iterations(&mut self, _: u32) -> usize {
+            1
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            false
+        }
+
+        fn sync(&mut self, _: u64) {}
+
+        fn name(&self) -> &str {
+            "lookup"
+        }
+
+        fn batch_len(&self) -> usize {
+            self.batch
+        }
+    }
+
+    #[test]
+    fn check_mixed_batches() {
+        let target = |batch, element_ns| {
+            Spi::for_benchmarks(vec![Box::new(BatchTarget { batch, element_ns })]).unwrap()
+        };
+        let single = target(1, 100);
+        let batched = target(8, 50);
+        let loop_mode = LoopMode::Samples(10);
+
+        let test = PairedTest::new(&single, &batched, Default::default(), None, loop_mode, None);
+        let Err(error) = test.run("lookup", None) else {
+            panic!("batches of different sizes are compared");
+        };
+        assert!(error.to_string().contains("--mixed-batches"), "{}", error);
+
+        let settings = MeasurementSettings {
+            mixed_batches: true,
+            ..Default::default()
+        };
+        let dir = env::temp_dir().join(format!("tango-batch-dump-{}", process::id()));
+        let test = PairedTest::new(
+            &single,
+            &batched,
+            settings,
+            None,
+            loop_mode,
+            Some(dir.clone()),
+        );
+        let result = test.run("lookup", None).unwrap();
+        assert!(result.per_element);
+        assert_eq!(reporting::display_name(&result), "lookup (per element)");
+        assert!((result.baseline.mean - 100.).abs() < 1e-9);
+        assert!((result.candidate.mean - 50.).abs() < 1e-9);
+
+        // Dumps keep raw values, but are re-analyzed per element as well
+        let measurements = commands::read_raw_measurements(dir.join("lookup.csv")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let reanalyzed = measurements.analyze("lookup", &settings).unwrap();
+        assert!(reanalyzed.per_element);
+        assert_eq!(reanalyzed.candidate, result.candidate);
+
+        // Batches of the same size are compared without opting in
+        let other = target(8, 50);
+        let test = PairedTest::new(&batched, &other, Default::default(), None, loop_mode, None);
+        let result = test.run("lookup", None).unwrap();
+        assert!(result.per_element);
+        assert!((result.diff.mean).abs() < 1e-9);
+    }
+
+    /// Reports per-iteration value of 1000 with a uniformly distributed noise of a given spread
+    struct NoisyTarget {
+        rng: SmallRng,
+        spread: u64,
+    }
+
+    impl MeasureTarget for NoisyTarget {
+        fn measure(&mut self, iterations: usize) -> u64 {
+            (1000 + self.rng.gen_range(0..=self.spread)) * iterations as u64
+        }
+
+        fn estimate_iterations(&mut self, _: u32) -> usize {
+            1
+        }
+
+        fn next_haystack(&mut self) -> bool {
+            false
+        }
+
+        /// Noise of baseline and candidate is independent regardless of the seed
+        fn sync(&mut self, _: u64) {}
+
+        fn name(&self) -> &str {
+            "noisy"
+        }
+    }
+
+    #[test]
+    fn check_target_precision() {
+        let run = |spread, outlier_detection| {
+            let noisy = |seed| {
+                let rng = SmallRng::seed_from_u64(seed);
+                let target = Box::new(NoisyTarget { rng, spread });
+                Spi::for_benchmarks(vec![target]).unwrap()
+            };
+            let (baseline, candidate) = (noisy(1), noisy(2));
+            let settings = MeasurementSettings {
+                target_precision: Some(1.),
+                max_precision_time: Duration::from_millis(100),
+                precision_check_interval: 50,
+                outlier_detection,
+                fixed_iterations: Some(1),
+                ..Default::default()
+            };
+            let loop_mode = LoopMode::Time(Duration::from_millis(1));
+            let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+            test.run("noisy", None).unwrap()
+        };
+
+        // Precision is checked each 50 samples, and is reached long before the time limit is
+        let result = run(10, OutlierDetection::None);
+        let precision = result.precision.unwrap();
+        assert_eq!(precision.stop, PrecisionStop::Reached);
+        assert!(precision.half_width <= 1.);
+        assert_eq!(result.schedule.samples, 50);
+
+        // Noise of up to 100 times the value can't be averaged to ±1% in 100ms
+        for outlier_detection in [OutlierDetection::None, OutlierDetection::Iqr] {
+            let result = run(100_000, outlier_detection);
+            let precision = result.precision.unwrap();
+            assert_eq!(precision.stop, PrecisionStop::TimeCap);
+            assert!(precision.half_width > 1.);
+            assert!(result.schedule.samples > 50);
+        }
+    }
+
+    #[test]
+    fn check_slow_test_policy() {
+        // A call takes 0.5 ms (see `SlowEstimateTarget`), more than a quarter of the time limit
+        let loop_mode = LoopMode::Time(Duration::from_millis(1));
+        let run = |slow_test_policy, max_auto_extend| {
+            let (baseline, _) = estimated_spi(Duration::ZERO, Duration::from_millis(10));
+            let (candidate, _) = estimated_spi(Duration::ZERO, Duration::from_millis(10));
+            let settings = MeasurementSettings {
+                slow_test_policy,
+                max_auto_extend,
+                min_samples: 3,
+                ..Default::default()
+            };
+            let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+            test.run("slow_estimate", None)
+        };
+
+        // A pair of samples of 100 iterations is estimated to take 100 ms
+        let result = run(SlowTestPolicy::Extend, Duration::from_secs(10)).unwrap();
+        assert_eq!(result.schedule.slow_call, Some(Duration::from_micros(500)));
+        assert_eq!(
+            result.schedule.extended_duration,
+            Some(Duration::from_millis(300))
+        );
+        assert!(result.schedule.samples >= 3, "{}", result.schedule.samples);
+
+        let result = run(SlowTestPolicy::Extend, Duration::from_millis(50)).unwrap();
+        assert_eq!(
+            result.schedule.extended_duration,
+            Some(Duration::from_millis(50))
+        );
+
+        let result = run(SlowTestPolicy::Force, Duration::from_secs(10)).unwrap();
+        assert_eq!(result.schedule.slow_call, Some(Duration::from_micros(500)));
+        assert_eq!(result.schedule.extended_duration, None);
+
+        let error = run(SlowTestPolicy::Skip, Duration::from_secs(10))
+            .err()
+            .unwrap();
+        let reason = skip_reason(&error).unwrap();
+        assert!(reason.contains("a single call takes 0.5ms"), "{}", reason);
+    }
+
+    #[test]
+    fn check_sample_planners_are_balanced() {
+        let run = |planner, duration| {
+            let (baseline, baseline_calls) = slow_estimate_spi(Duration::ZERO);
+            let (candidate, candidate_calls) = slow_estimate_spi(Duration::ZERO);
+            let settings = MeasurementSettings {
+                sample_planner: planner,
+                ..Default::default()
+            };
+            let loop_mode = LoopMode::Time(duration);
+            let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+            let result = test.run("slow_estimate", None).unwrap();
+            let baseline_samples = baseline_calls.borrow().iterations.len();
+            assert_eq!(baseline_samples, candidate_calls.borrow().iterations.len());
+            assert_eq!(baseline_samples, result.schedule.samples);
+            baseline_samples
+        };
+
+        // Functions are measured first alternately, so both orderings are balanced if the number of samples is even
+        let samples = run(SamplePlanner::Deadline, Duration::from_millis(20));
+        assert!(samples > 0 && samples % 2 == 0, "{}", samples);
+
+        // A pair of samples of 50 iterations on average (random sampler) is estimated to take 50 ms
+        assert_eq!(run(SamplePlanner::Fixed, Duration::from_millis(200)), 4);
+        assert_eq!(run(SamplePlanner::Fixed, Duration::from_millis(250)), 6);
+        assert_eq!(run(SamplePlanner::Fixed, Duration::from_millis(10)), 2);
+    }
+
+    #[test]
+    fn check_fixed_plan_overrun_is_aborted() {
+        // Each sample takes 20 ms instead of estimated 0.5 ms
+        let (baseline, _) = estimated_spi(Duration::ZERO, Duration::from_millis(20));
+        let (candidate, candidate_calls) = estimated_spi(Duration::ZERO, Duration::from_millis(20));
+        let settings = MeasurementSettings {
+            sample_planner: SamplePlanner::Fixed,
+            sampler_type: SamplerType::Flat,
+            max_iterations_per_sample: 1,
+            ..Default::default()
+        };
+        // 0.5 ms per iteration of each function, so 100 samples are planned
+        let loop_mode = LoopMode::Time(Duration::from_millis(100));
+        let test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        let error = test.run("slow_estimate", None).err().unwrap().to_string();
+        assert!(error.contains("twice its time limit"), "{}", error);
+        assert!(candidate_calls.borrow().iterations.len() < 10);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum SampleEvent {
+        Before(Side, usize),
+        After(Side),
+    }
+
+    /// Records sample boundaries and reports number of iterations as a value
+    struct RecordingObserver {
+        events: Rc<RefCell<Vec<SampleEvent>>>,
+        iterations: usize,
+    }
+
+    impl SampleObserver for RecordingObserver {
+        fn before_sample(&mut self, test: &str, side: Side, iterations: usize) {
+            assert_eq!(test, "counting");
+            self.iterations = iterations;
+            self.events
+                .borrow_mut()
+                .push(SampleEvent::Before(side, iterations));
+        }
+
+        fn after_sample(&mut self, _test: &str, side: Side, _value: u64) {
+            self.events.borrow_mut().push(SampleEvent::After(side));
+        }
+
+        fn sample_values(&mut self) -> Vec<ObservedValue> {
+            let value = ObservedValue {
+                name: "iterations",
+                unit: Unit::Count,
+                value: self.iterations as f64,
+            };
+            vec![value]
+        }
+    }
+
+    #[test]
+    fn check_sample_observers() {
+        let (baseline, _) = counting_spi(Duration::ZERO);
+        let (candidate, _) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(7),
+            exact_samples: Some(13),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(13);
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut test = PairedTest::new(&baseline, &candidate, settings, None, loop_mode, None);
+        test.add_observer(Box::new(RecordingObserver {
+            events: Rc::clone(&events),
+            iterations: 0,
+        }));
+        let result = test.run("counting", None).unwrap();
+
+        // Each recorded sample of both functions is wrapped in a pair of calls, warm-ups are not observed
+        let events = events.borrow();
+        assert_eq!(events.len(), 13 * 4);
+        for sample in events.chunks(4) {
+            let [SampleEvent::Before(a, 7), SampleEvent::After(a_after), SampleEvent::Before(b, 7), SampleEvent::After(b_after)] =
+                sample
+            else {
+                panic!("Unexpected events: {:?}", sample);
+            };
+            assert_eq!(a, a_after);
+            assert_eq!(b, b_after);
+            assert_ne!(a, b);
+        }
+
+        let [observed] = result.observed.as_slice() else {
+            panic!("Unexpected observed values: {:?}", result.observed);
+        };
+        assert_eq!(observed.name, "iterations");
+        for summary in [observed.baseline, observed.candidate] {
+            let summary = summary.unwrap();
+            assert_eq!(summary.n, 13);
+            assert_eq!(summary.mean, 7.);
+        }
+    }
+
+    /// Generator of a vector growing by an element before each sample if the mutation is enabled
+    struct GrowingVec(bool);
+
+    impl Generator for GrowingVec {
+        type Haystack = Vec<u64>;
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            vec![]
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, sample_idx: usize) {
+            haystack.push(sample_idx as u64);
+        }
+
+        fn mutates_haystack(&self) -> bool {
+            self.0
+        }
+
+        fn sync(&mut self, _: u64) {}
+    }
+
+    /// Spi of a function logging the lengths of the [`GrowingVec`] haystacks it is called with
+    fn growing_spi(mutates: bool) -> (Spi<'static>, Arc<Mutex<Vec<usize>>>) {
+        let lengths = Arc::new(Mutex::new(vec![]));
+        let log = Arc::clone(&lengths);
+        let f = move |h: &Vec<u64>, _: &()| log.lock().unwrap().push(h.len());
+        let target = GenFunc::new("growing", f, GrowingVec(mutates));
+        (
+            Spi::for_benchmarks(vec![Box::new(target)]).unwrap(),
+            lengths,
+        )
+    }
+
+    /// Settings taking 12 samples with a new haystack every 4 samples
+    fn growing_settings() -> MeasurementSettings {
+        MeasurementSettings {
+            fixed_iterations: Some(1),
+            exact_samples: Some(12),
+            samples_per_haystack: 4,
+            discard_first_sample_after_haystack_change: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn check_haystack_mutation() {
+        let (baseline, baseline_lengths) = growing_spi(true);
+        let (candidate, candidate_lengths) = growing_spi(true);
+        let settings = growing_settings();
+        let loop_mode = LoopMode::Samples(12);
+
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let result = test.run("growing/GrowingVec", None).unwrap();
+
+        // Both functions observe the same length of the haystack at every sample
+        // (haystack is changed every 4 samples, the first one is generated by the first sample)
+        let baseline_lengths = baseline_lengths.lock().unwrap();
+        assert_eq!(*baseline_lengths, [0, 1, 2, 0, 1, 2, 3, 0, 1, 2, 3, 0]);
+        assert_eq!(*baseline_lengths, *candidate_lengths.lock().unwrap());
+        assert!(!result.phases.generate.is_zero());
+    }
+
+    #[test]
+    fn check_haystack_mutation_is_opt_in() {
+        let loop_mode = LoopMode::Samples(12);
+        let (baseline, baseline_lengths) = growing_spi(false);
+        let (candidate, _) = growing_spi(false);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            growing_settings(),
+            Some(0),
+            loop_mode,
+            None,
+        );
+        test.run("growing/GrowingVec", None).unwrap();
+        assert!(baseline_lengths.lock().unwrap().iter().all(|&l| l == 0));
+
+        // Baseline built with a generator which doesn't mutate haystacks can't be paired with one which does
+        let (candidate, _) = growing_spi(true);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            growing_settings(),
+            Some(0),
+            loop_mode,
+            None,
+        );
+        let Err(error) = test.run("growing/GrowingVec", None) else {
+            panic!("functions mutating haystacks differently are compared");
+        };
+        assert!(
+            error.to_string().contains("only in the candidate"),
+            "{}",
+            error
+        );
+    }
+
+    /// Generator of random haystacks and needles shared by all the functions of a matrix
+    struct RandomPair(SmallRng);
+
+    impl Generator for RandomPair {
+        type Haystack = u64;
+        type Needle = u64;
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            self.0.gen()
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+            self.0.gen()
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.0 = SmallRng::seed_from_u64(seed);
+        }
+    }
+
+    /// Inputs observed by the functions of a [`RandomPair`] matrix along with the name of the function
+    type InputLog = Arc<Mutex<Vec<(&'static str, u64, u64)>>>;
+
+    fn logging_spi(names: &[&'static str], log: &InputLog) -> Spi<'static> {
+        let mut matrix = BenchmarkMatrix::new(RandomPair(SmallRng::seed_from_u64(0)));
+        for &name in names {
+            let log = Arc::clone(log);
+            matrix = matrix.add_function(name, move |h: &u64, n: &u64| {
+                log.lock().unwrap().push((name, *h, *n));
+            });
+        }
+        Spi::for_benchmarks(matrix.into_benchmarks()).unwrap()
+    }
+
+    #[test]
+    fn check_test_inputs_dont_depend_on_test_set() {
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(4),
+            sampler_type: SamplerType::Random,
+            samples_per_haystack: 3,
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(20);
+
+        // Inputs of the tests `a` and `b` and the number of samples of each test run in a given order
+        let run = |registered: &[&'static str], order: &[&'static str]| {
+            let baseline_log = InputLog::default();
+            let candidate_log = InputLog::default();
+            let baseline = logging_spi(registered, &baseline_log);
+            let candidate = logging_spi(registered, &candidate_log);
+            let test = PairedTest::new(&baseline, &candidate, settings, Some(7), loop_mode, None);
+            let samples = order
+                .iter()
+                .map(|name| {
+                    let result = test.run(&format!("{}/RandomPair", name), None).unwrap();
+                    (*name, result.baseline.n)
+                })
+                .collect::<Vec<_>>();
+            let inputs = |log: &InputLog, name| {
+                let log = log.lock().unwrap();
+                log.iter()
+                    .filter(|(n, ..)| *n == name)
+                    .map(|&(_, h, n)| (h, n))
+                    .collect::<Vec<_>>()
+            };
+            let inputs = ["a", "b"].map(|name| {
+                let baseline = inputs(&baseline_log, name);
+                assert_eq!(baseline, inputs(&candidate_log, name));
+                baseline
+            });
+            (inputs, samples)
+        };
+
+        let (inputs, samples) = run(&["a", "b"], &["a", "b"]);
+        // Tests registered before the other ones, filtered out or run between them don't change their inputs or
+        // schedules
+        let (extended_inputs, extended_samples) = run(&["c", "a", "z", "b"], &["a", "z", "b"]);
+        assert!(!inputs[0].is_empty() && inputs[0] != inputs[1]);
+        assert_eq!(inputs, extended_inputs);
+        assert_eq!(samples, [extended_samples[0], extended_samples[2]]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum FixtureEvent {
+        Create(PathBuf),
+        Call(PathBuf),
+        Drop(PathBuf),
+    }
+
+    type FixtureLog = Arc<Mutex<Vec<FixtureEvent>>>;
+
+    struct LoggedFixture {
+        dir: TempDirFixture,
+        log: FixtureLog,
+    }
+
+    impl Drop for LoggedFixture {
+        fn drop(&mut self) {
+            let path = self.dir.path().to_path_buf();
+            self.log.lock().unwrap().push(FixtureEvent::Drop(path));
+        }
+    }
+
+    fn fixture_spi(parent: &Path, log: &FixtureLog) -> Spi<'static> {
+        let (parent, log) = (parent.to_path_buf(), Arc::clone(log));
+        let target = BenchmarkBuilder::new("io")
+            .with_fixture(move || {
+                let dir = TempDirFixture::create_in(&parent, "io").unwrap();
+                let path = dir.path().to_path_buf();
+                log.lock().unwrap().push(FixtureEvent::Create(path));
+                LoggedFixture {
+                    dir,
+                    log: Arc::clone(&log),
+                }
+            })
+            .with_setup(|fixture| fixture.dir.path().join("data"))
+            .build(|fixture, file| {
+                fs::write(file, b"data").unwrap();
+                let path = fixture.dir.path().to_path_buf();
+                fixture.log.lock().unwrap().push(FixtureEvent::Call(path));
+            });
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
+
+    #[test]
+    fn check_fixtures_wrap_measurement() {
+        let parent = env::temp_dir().join(format!("tango-fixtures-{}", process::id()));
+        let baseline_log = FixtureLog::default();
+        let candidate_log = FixtureLog::default();
+        let baseline = fixture_spi(&parent, &baseline_log);
+        let candidate = fixture_spi(&parent, &candidate_log);
+        let settings = MeasurementSettings::default();
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            None,
+            LoopMode::Samples(10),
+            None,
+        );
+        test.run("io", None).unwrap();
+
+        let dirs = [&baseline_log, &candidate_log].map(|log| {
+            let log = log.lock().unwrap();
+            let (first, calls, last) = (&log[0], &log[1..log.len() - 1], &log[log.len() - 1]);
+            // Fixture is created once before any call and dropped once after all of them
+            let FixtureEvent::Create(dir) = first else {
+                panic!("Fixture is not created first: {:?}", first)
+            };
+            assert!(!calls.is_empty());
+            assert!(calls.iter().all(|e| *e == FixtureEvent::Call(dir.clone())));
+            assert_eq!(*last, FixtureEvent::Drop(dir.clone()));
+            assert!(!dir.exists());
+            dir.clone()
+        });
+        // Baseline and candidate don't share the fixture even though they are run in the same process
+        assert_ne!(dirs[0], dirs[1]);
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    #[test]
+    fn check_reanalysis_of_dumps() {
+        let baseline = busy_spi(1000);
+        let candidate = busy_spi(1100);
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let dir = env::temp_dir().join(format!("tango-dump-{}", process::id()));
+        let loop_mode = LoopMode::Samples(200);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            Some(0),
+            loop_mode,
+            Some(dir.clone()),
+        );
+        let live = test.run("busy", None).unwrap();
+
+        let dumps = commands::find_dumps(std::slice::from_ref(&dir)).unwrap();
+        let [(name, path)] = dumps.as_slice() else {
+            panic!("Unexpected dumps: {:?}", dumps);
+        };
+        assert_eq!(name, "busy");
+        let measurements = commands::read_raw_measurements(path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Same settings give exactly the same decision
+        let result = measurements.analyze(name, &settings).unwrap();
+        assert_eq!(result.baseline, live.baseline);
+        assert_eq!(result.candidate, live.candidate);
+        assert_eq!(result.diff, live.diff);
+        assert_eq!(result.outliers, live.outliers);
+        assert_eq!(result.diff_estimate.pct, live.diff_estimate.pct);
+        assert_eq!(result.diff_estimate.ci, live.diff_estimate.ci);
+        assert_eq!(result.diff_estimate.verdict, live.diff_estimate.verdict);
+
+        // Decision is recomputed under different settings
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::None,
+            min_effect: f64::INFINITY,
+            ..settings
+        };
+        let result = measurements.analyze(name, &settings).unwrap();
+        assert_eq!(result.outliers, 0);
+        assert_eq!(result.diff.n, 200);
+        assert_ne!(result.diff_estimate.verdict, Verdict::Different);
     }
 
-    impl<'a> TestedFunction<'a> {
-        fn new(spi: &'a Spi<'a>, func: &'a NamedFunction) -> Self {
-            TestedFunction {
-                spi,
-                func,
-                samples: Vec::new(),
-            }
+    #[test]
+    fn check_reference_pair_under_load() {
+        // Candidate sums 1% more numbers than baseline
+        let baseline = busy_spi(1000);
+        let candidate = busy_spi(1010);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(200);
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+
+        let clean = test.run("busy", None).unwrap();
+        let load = BackgroundLoad::start(LoadSpec::new(2, 0.3));
+        let loaded = test.run("busy", None).unwrap();
+        assert!(load.stop() > 0.);
+
+        // Load doesn't cost samples, whether the difference is still detected under it depends on the machine
+        for result in [clean, loaded] {
+            assert_eq!(result.schedule.samples, 200);
+            assert_eq!(result.diff.n + result.outliers, 200);
+            assert!(result.diff_estimate.pct.is_finite());
         }
+    }
 
-        fn run(&mut self, iterations: usize) {
-            let sample = self.spi.run(self.func, iterations);
-            self.samples.push(sample);
+    /// Keeps all the completed results
+    #[derive(Default)]
+    struct Collecting(Vec<RunResult>);
+
+    impl Reporter for Collecting {
+        fn on_complete(&mut self, results: &RunResult) {
+            self.0.push(results.clone());
         }
+    }
 
-        fn next_haystack(&mut self) {
-            self.spi.next_haystack(self.func);
+    #[test]
+    fn check_dump_index_rerenders_report() {
+        let busy_spi = |n: u64| {
+            let targets = ["b", "a"]
+                .map(|name| benchmark_fn(name, move || (0..n).map(black_box).sum::<u64>()));
+            Spi::for_benchmarks(targets.into()).unwrap()
+        };
+        let baseline = busy_spi(1000);
+        let candidate = busy_spi(1100);
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            min_effect: 2.,
+            fixed_iterations: Some(10),
+            ..Default::default()
+        };
+        let dir = env::temp_dir().join(format!("tango-dump-index-run-{}", process::id()));
+        let loop_mode = LoopMode::Samples(200);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            Some(0),
+            loop_mode,
+            Some(dir.clone()),
+        );
+        let mut session = DumpSession::new(settings, test.master_seed(), Environment::current());
+        session.labels.insert("commit".into(), "1a2b3c".into());
+        let mut index = Some((dir.clone(), DumpIndex::new(session)));
+        let mut live = Collecting::default();
+        for name in ["b", "a"] {
+            let result = test.run(name, None).unwrap();
+            index_dump(&mut live, index.as_mut(), &result);
+            live.on_complete(&result);
         }
+        let (_, index) = index.as_mut().unwrap();
+        index.session.finish();
+        index.write(&dir).unwrap();
 
-        fn estimate_iterations(&mut self, iterations: u32) -> usize {
-            self.spi.estimate_iterations(self.func, iterations)
+        // Nothing but the dump directory is given, settings of the run are taken from the index
+        let mut rerendered = Collecting::default();
+        let overrides = AnalyzeOverrides::default();
+        let defaults = MeasurementSettings::default();
+        let dirs = std::slice::from_ref(&dir);
+        analyze_dumps(dirs, "", defaults, &overrides, &mut rerendered).unwrap();
+
+        let mut unfiltered = Collecting::default();
+        let overrides = AnalyzeOverrides {
+            outlier_detection: Some(OutlierDetection::None),
+            ..Default::default()
+        };
+        analyze_dumps(dirs, "a", defaults, &overrides, &mut unfiltered).unwrap();
+
+        let index = DumpIndex::read(&dir).unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(index.session.labels["commit"], "1a2b3c");
+        assert_eq!(index.session.settings.min_effect, 2.);
+        let tests = index
+            .dumps
+            .iter()
+            .map(|d| d.test.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(tests, ["b", "a"]);
+
+        assert_eq!(rerendered.0.len(), live.0.len());
+        for ((live, result), record) in live.0.iter().zip(&rerendered.0).zip(&index.dumps) {
+            assert_eq!(result.name, live.name);
+            assert_eq!(result.baseline, live.baseline);
+            assert_eq!(result.candidate, live.candidate);
+            assert_eq!(result.outliers, live.outliers);
+            assert_eq!(result.diff_estimate.pct, live.diff_estimate.pct);
+            assert_eq!(result.diff_estimate.verdict, live.diff_estimate.verdict);
+
+            assert_eq!(record.file, format!("{}.csv", live.name));
+            assert_eq!(record.samples, 200);
+            assert_eq!(record.outliers, live.outliers);
+            assert_eq!(record.verdict, live.diff_estimate.verdict);
         }
-    }
 
-    /// Measure the difference in performance of two functions
-    ///
-    /// Provides a way to save a raw dump of measurements into directory
-    ///
-    /// The format is as follows
-    /// ```txt
-    /// b_1,c_1
-    /// b_2,c_2
-    /// ...
-    /// b_n,c_n
-    /// ```
-    /// where `b_1..b_n` are baseline absolute time (in nanoseconds) measurements
-    /// and `c_1..c_n` are candidate time measurements
-    ///
-    /// Returns a percentage difference in performance of two functions if this change is
-    /// statistically significant
-    pub(crate) struct PairedTest<'a> {
-        baseline: &'a Spi<'a>,
-        candidate: &'a Spi<'a>,
-        seed: u64,
-        settings: MeasurementSettings,
-        loop_mode: LoopMode,
-        samples_dump_path: Option<PathBuf>,
-        firewall: Option<CacheFirewall>,
+        let [result] = unfiltered.0.as_slice() else {
+            panic!("Filter is not applied");
+        };
+        assert_eq!(result.outliers, 0);
+        assert_eq!(result.diff.n, 200);
     }
 
-    impl<'a> PairedTest<'a> {
-        pub fn new(
-            baseline: &'a Spi<'a>,
-            candidate: &'a Spi<'a>,
-            settings: MeasurementSettings,
-            seed: Option<u64>,
-            loop_mode: LoopMode,
-            samples_dump_path: Option<PathBuf>,
-        ) -> Self {
-            let seed = seed.unwrap_or_else(rand::random);
-            let firewall = settings
-                .cache_firewall
-                .map(|s| s * 1024)
-                .map(CacheFirewall::new);
-            Self {
-                baseline,
-                candidate,
-                seed,
-                settings,
-                loop_mode,
-                samples_dump_path,
-                firewall,
-            }
+    #[test]
+    fn check_raw_samples_analyzed_as_pair() {
+        // Cycle counts of 50 iterations per sample as collected on a device: candidate is 5% slower, both functions
+        // share the slow drift of the device clock and a few interrupts hit the baseline
+        let mut rng = SmallRng::seed_from_u64(0);
+        let (mut baseline, mut candidate) = (vec![], vec![]);
+        for i in 0..400_u64 {
+            let drift = i / 4;
+            let interrupt = if i % 97 == 0 { 20_000 } else { 0 };
+            baseline.push(50 * (2000 + drift + rng.gen_range(0..40)) + interrupt);
+            candidate.push(50 * (2100 + drift + rng.gen_range(0..40)));
         }
+        let header = |name| RawHeader {
+            name,
+            iterations: 50,
+            candidate_first: false,
+        };
+        let dir = env::temp_dir().join(format!("tango-raw-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (baseline_path, candidate_path) = (dir.join("base.bin"), dir.join("candidate.bin"));
+        fs::write(
+            &baseline_path,
+            raw::encode(&header("crc32/table"), &baseline),
+        )
+        .unwrap();
+        fs::write(
+            &candidate_path,
+            raw::encode(&header("crc32/bitwise"), &candidate),
+        )
+        .unwrap();
 
-        pub fn run(&self, test_name: &str) -> Result<RunResult> {
-            let a_func = self
-                .baseline
-                .lookup(test_name)
-                .expect("Invalid test name given");
-            let b_func = self
-                .candidate
-                .lookup(test_name)
-                .expect("Invalid test name given");
+        let mut reporter = Collecting::default();
+        let overrides = AnalyzeOverrides {
+            outlier_detection: Some(OutlierDetection::Iqr),
+            ..Default::default()
+        };
+        let settings = MeasurementSettings::default();
+        let analyze = |filter, reporter: &mut Collecting| {
+            analyze_raw(
+                &baseline_path,
+                &candidate_path,
+                filter,
+                settings,
+                &overrides,
+                reporter,
+            )
+        };
+        analyze("", &mut reporter).unwrap();
+        analyze("crc32/table", &mut reporter).unwrap();
 
-            let mut rng = SmallRng::seed_from_u64(self.seed);
+        // Files of different lengths can't be paired
+        fs::write(
+            &candidate_path,
+            raw::encode(&header("crc32/bitwise"), &candidate[1..]),
+        )
+        .unwrap();
+        let err = analyze("", &mut Collecting::default()).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
 
-            let seed = rng.next_u64();
-            self.baseline.sync(a_func, seed);
-            self.candidate.sync(b_func, seed);
+        let [result] = reporter.0.as_slice() else {
+            panic!("Filter is not applied");
+        };
+        assert_eq!(result.id, PairId::new("crc32/table", "crc32/bitwise"));
+        assert_eq!(&*result.name, "crc32/table vs crc32/bitwise");
+        assert_eq!(result.unit, Unit::Count);
+        assert_eq!(result.diff.n + result.outliers, 400);
+        // Interrupts are filtered along with the same number of the smallest differences
+        assert_eq!(result.outliers, 10);
+        assert!(
+            (result.baseline.mean - 2070.).abs() < 3.,
+            "{}",
+            result.baseline.mean
+        );
+        assert_eq!(result.diff_estimate.verdict, Verdict::Different);
+        assert!((result.diff_estimate.pct - 4.8).abs() < 0.1);
+        assert!(err.to_string().contains("400 samples"), "{}", err);
 
-            let mut a_func = TestedFunction::new(self.baseline, a_func);
-            let mut b_func = TestedFunction::new(self.candidate, b_func);
+        let mode = parse(&["analyze", "--raw", "a.bin", "b.bin"], None);
+        let BenchmarkMode::Analyze { raw: Some(raw), .. } = mode else {
+            panic!("Raw samples are not parsed");
+        };
+        assert_eq!(raw, [PathBuf::from("a.bin"), PathBuf::from("b.bin")]);
+        assert!(Opts::try_parse_from(["bench", "analyze", "--raw", "a.bin"]).is_err());
+    }
 
-            // Estimating the number of iterations achievable in 1 ms
-            let iterations_per_sample =
-                b_func.estimate_iterations(50) / 2 + a_func.estimate_iterations(50) / 2;
-            let mut sampler = create_sampler(&self.settings, iterations_per_sample, seed);
+    #[test]
+    fn check_dump_names_are_escaped() {
+        let name = "search/RandomMap{n=1,hit=50%}";
+        let escaped = commands::escape_dump_name(name);
+        assert_eq!(escaped, "search%2FRandomMap{n=1,hit=50%25}");
+        assert_eq!(commands::unescape_dump_name(&escaped), name);
+        assert_eq!(commands::unescape_dump_name("100%-%zz"), "100%-%zz");
 
-            let mut i = 0;
-            let mut switch_counter = 0;
+        let (baseline, _) = counting_spi(Duration::ZERO);
+        let (candidate, _) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            ..Default::default()
+        };
+        let dir = env::temp_dir().join(format!("tango-dump-names-{}", process::id()));
+        let loop_mode = LoopMode::Samples(10);
+        let test = PairedTest::new(
+            &baseline,
+            &candidate,
+            settings,
+            Some(0),
+            loop_mode,
+            Some(dir.join("nested")),
+        );
+        let pair = TestPair {
+            name,
+            id: PairId::same("counting"),
+        };
+        let result = test.run_pair(&pair, None).unwrap();
+        let Some(Ok(path)) = &result.dump else {
+            panic!("Dump is not written: {:?}", result.dump);
+        };
+        assert_eq!(path.parent(), Some(dir.join("nested").as_path()));
 
-            let mut sample_iterations = vec![];
+        let dumps = commands::find_dumps(&[dir.join("nested")]).unwrap();
+        // Names are restored from the file names if the names file is missing
+        fs::remove_file(dir.join("nested").join(commands::DUMP_NAMES_FILE)).unwrap();
+        let restored = commands::find_dumps(&[dir.join("nested")]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dumps, vec![(name.to_string(), path.clone())]);
+        assert_eq!(restored, dumps);
+    }
 
-            let start_time = Instant::now();
-            while self.loop_mode.should_continue(i, start_time) {
-                let iterations = sampler.next_sample_iterations(i);
-                i += 1;
+    #[test]
+    fn check_unwritable_dump_is_reported() {
+        let (baseline, _) = counting_spi(Duration::ZERO);
+        let (candidate, _) = counting_spi(Duration::ZERO);
+        let settings = MeasurementSettings {
+            fixed_iterations: Some(1),
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Samples(10);
+        let run = |dir: PathBuf| {
+            let test = PairedTest::new(
+                &baseline,
+                &candidate,
+                settings,
+                Some(0),
+                loop_mode,
+                Some(dir),
+            );
+            let result = test.run("counting", None).unwrap();
+            assert_eq!(result.diff.n, 10);
+            result.dump.unwrap().unwrap_err()
+        };
 
-                // !!! IMPORTANT !!!
-                // Algorithms should be called in different order on each new iteration.
-                // This equalize the probability of facing unfortunate circumstances like cache misses or page faults
-                // for both functions. Although both algorithms are from distinct shared objects and therefore
-                // must be fully self-contained in terms of virtual address space (each shared object has its own
-                // generator instances, static variables, memory mappings, etc.) it might be the case that
-                // on the level of physical memory both of them rely on the same memory-mapped test data, for example.
-                // In that case first function will experience the larger amount of major page faults.
-                {
-                    mem::swap(&mut a_func, &mut b_func);
-                    switch_counter += 1;
-                }
+        // Dump directory can't be created inside a file
+        let file = env::temp_dir().join(format!("tango-dump-file-{}", process::id()));
+        fs::write(&file, "").unwrap();
+        run(file.join("dump"));
+        fs::remove_file(&file).unwrap();
 
-                if i % self.settings.samples_per_haystack == 0 {
-                    a_func.next_haystack();
-                    b_func.next_haystack();
-                }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
 
-                if self.settings.yield_before_sample {
-                    std::thread::yield_now();
-                }
+            let dir = env::temp_dir().join(format!("tango-dump-readonly-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+            // Permissions are not enforced for privileged users
+            let enforced = File::create(dir.join("probe")).is_err();
+            if enforced {
+                let error = run(dir.clone());
+                assert!(
+                    error.to_lowercase().contains("permission denied"),
+                    "{}",
+                    error
+                );
+            }
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
 
-                if let Some(firewall) = &self.firewall {
-                    firewall.issue_read();
-                }
+    #[test]
+    fn check_invalid_dumps() {
+        let path = env::temp_dir().join(format!("tango-invalid-dump-{}.csv", process::id()));
+        let read = |content: &str| {
+            fs::write(&path, content).unwrap();
+            commands::read_raw_measurements(&path).map(|m| m.analyze("test", &Default::default()))
+        };
 
-                a_func.run(iterations);
-                b_func.run(iterations);
-                sample_iterations.push(iterations);
-            }
+        assert!(read("10,12,1\n\n20,22,2\n").unwrap().is_some());
+        assert!(read("").unwrap().is_none());
+        let error = read("10,12,1\n10,12\n").err().unwrap().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(read("10,12,0\n").is_err());
+        assert!(read("10,12,1,4\n").is_err());
+        assert!(read("# first: baseline\n10,12,1\n").unwrap().is_some());
+        assert!(read("# first: both\n10,12,1\n").is_err());
+        fs::remove_file(&path).unwrap();
+    }
 
-            // If we switched functions odd number of times then we need to swap them back so that
-            // the first function is always the baseline.
-            if switch_counter % 2 != 0 {
-                mem::swap(&mut a_func, &mut b_func);
-            }
+    #[test]
+    fn check_same_binary_detection() {
+        let exe = env::current_exe().unwrap();
+        let dir = env::temp_dir().join(format!("tango-self-compare-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let copy = dir.join("copy");
+        fs::copy(&exe, &copy).unwrap();
+        let patched = dir.join("patched");
+        let mut content = fs::read(&exe).unwrap();
+        *content.last_mut().unwrap() ^= 1;
+        fs::write(&patched, content).unwrap();
 
-            let run_result = calculate_run_result(
-                test_name,
-                &a_func.samples,
-                &b_func.samples,
-                &sample_iterations,
-                self.settings.filter_outliers,
-            )
-            .ok_or(Error::NoMeasurements)?;
+        assert!(is_same_binary(&exe, &exe).unwrap());
+        assert!(is_same_binary(&copy, &exe).unwrap());
+        assert!(!is_same_binary(&patched, &exe).unwrap());
+        assert!(is_same_binary(&dir.join("missing"), &exe).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-            if let Some(path) = &self.samples_dump_path {
-                if !path.exists() {
-                    fs::create_dir_all(path)?;
-                }
-                let file_name = format!("{}.csv", test_name.replace('/', "-"));
-                let file_path = path.join(file_name);
-                let values = a_func
-                    .samples
-                    .iter()
-                    .copied()
-                    .zip(b_func.samples.iter().copied())
-                    .zip(sample_iterations.iter().copied())
-                    .map(|((a, b), c)| (a, b, c));
-                write_raw_measurements(file_path, values)
-                    .context("Unable to write raw measurements")?;
-            }
+    /// Generator which is expensive compared to the benchmarked function
+    struct SlowGenerator;
 
-            Ok(run_result)
+    impl Generator for SlowGenerator {
+        type Haystack = ();
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            thread::sleep(Duration::from_millis(2));
         }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn sync(&mut self, _: u64) {}
     }
 
-    fn create_sampler(
-        settings: &MeasurementSettings,
-        estimate: usize,
-        seed: u64,
-    ) -> Box<dyn Sampler> {
-        match settings.sampler_type {
-            SamplerType::Flat => Box::new(FlatSampler::new(settings, estimate)),
-            SamplerType::Linear => Box::new(LinearSampler::new(settings, estimate)),
-            SamplerType::Random => Box::new(RandomSampler::new(settings, estimate, seed)),
-        }
+    fn slow_generator_spi() -> Spi<'static> {
+        let func = |_: &(), _: &()| thread::sleep(Duration::from_millis(1));
+        let target: Box<dyn MeasureTarget> = Box::new(GenFunc::new("sleep", func, SlowGenerator));
+        Spi::for_benchmarks(vec![target]).unwrap()
     }
 
-    fn write_raw_measurements<A: Display, B: Display, C: Display>(
-        path: impl AsRef<Path>,
-        values: impl IntoIterator<Item = (A, B, C)>,
-    ) -> io::Result<()> {
-        let mut file = BufWriter::new(File::create(path)?);
+    #[test]
+    fn check_generator_time_accounting() {
+        let baseline = slow_generator_spi();
+        let candidate = slow_generator_spi();
+        let duration = Duration::from_millis(50);
 
-        for (a, b, c) in values {
-            writeln!(&mut file, "{},{},{}", a, b, c)?;
-        }
-        Ok(())
+        let settings = MeasurementSettings {
+            max_iterations_per_sample: 1,
+            discard_first_sample_after_haystack_change: false,
+            ..Default::default()
+        };
+        let loop_mode = LoopMode::Time(duration);
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let result = test.run("sleep/SlowGenerator", None).unwrap();
+        assert!(result.phases.measure_base + result.phases.measure_candidate < duration);
+        assert!(result.generator_overhead() > settings.generator_overhead_threshold);
+
+        let settings = MeasurementSettings {
+            exclude_generator_time: true,
+            ..settings
+        };
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let result = test.run("sleep/SlowGenerator", None).unwrap();
+        // Deadline is checked before the time of the last check is attributed to a phase
+        let measure_time = result.phases.measure_base + result.phases.measure_candidate;
+        assert!(measure_time + Duration::from_millis(1) >= duration);
+        assert!(result.phases.generate >= duration);
     }
-}
 
-pub mod reporting {
-    use crate::cli::{colorize, HumanTime};
-    use crate::{Reporter, RunResult};
-    use colorz::{mode::Stream, Colorize};
+    #[test]
+    fn check_sampling_plan() {
+        let settings = MeasurementSettings {
+            amortize_haystacks: true,
+            ..Default::default()
+        };
+        let hints = |haystack_ns, needle_ns| {
+            Some(CostHints {
+                haystack_ns_estimate: haystack_ns,
+                needle_ns_estimate: needle_ns,
+            })
+        };
+        let plan = |estimates, hints| SamplingPlan::new(&settings, estimates, hints);
 
-    #[derive(Default)]
-    pub(super) struct VerboseReporter;
+        // Libraries not reporting costs are planned the same way as before
+        let averaged = SamplingPlan {
+            iterations: 950,
+            samples_per_haystack: 1,
+        };
+        assert_eq!(plan((1000, 900), (None, None)), averaged);
+        assert_eq!(plan((1000, 900), (hints(0, 0), None)), averaged);
 
-    impl Reporter for VerboseReporter {
-        fn on_complete(&mut self, results: &RunResult) {
-            let base = results.baseline;
-            let candidate = results.candidate;
+        // Baseline is faster, but only because its needles are cheaper: 50 us + 30 us of candidate needles
+        let cheap = hints(0, 0);
+        let expensive = hints(100_000_000, 30_000);
+        let conservative = SamplingPlan {
+            iterations: 625,
+            samples_per_haystack: 1,
+        };
+        let no_amortization = |estimates, hints| {
+            let settings = MeasurementSettings {
+                amortize_haystacks: false,
+                ..settings
+            };
+            SamplingPlan::new(&settings, estimates, hints)
+        };
+        assert_eq!(
+            no_amortization((1000, 900), (cheap, expensive)),
+            conservative
+        );
 
-            let significant = results.diff_estimate.significant;
+        // 100 ms haystack is amortized over 4 samples of 25 ms (625 * 80 us on average for random sampler)
+        let amortized = SamplingPlan {
+            samples_per_haystack: 4,
+            ..conservative
+        };
+        assert_eq!(plan((1000, 900), (cheap, expensive)), amortized);
+        // The same plan is used regardless of the side the expensive generator is on
+        assert_eq!(plan((900, 1000), (expensive, cheap)), amortized);
+    }
 
-            println!(
-                "{}  (n: {}, outliers: {})",
-                results.name.bold().stream(Stream::Stdout),
-                results.diff.n,
-                results.outliers
-            );
+    /// Generator taking a given time to generate a haystack
+    struct CostlyGenerator(Duration);
 
-            println!(
-                "    {:12}   {:>15} {:>15} {:>15}",
-                "",
-                "baseline".bold().stream(Stream::Stdout),
-                "candidate".bold().stream(Stream::Stdout),
-                "∆".bold().stream(Stream::Stdout),
-            );
-            println!(
-                "    {:12} ╭────────────────────────────────────────────────",
-                ""
-            );
-            println!(
-                "    {:12} │ {:>15} {:>15} {:>15}  {:+4.2}{}{}",
-                "mean",
-                HumanTime(base.mean),
-                HumanTime(candidate.mean),
-                colorize(
-                    HumanTime(results.diff.mean),
-                    significant,
-                    results.diff.mean < 0.
-                ),
-                colorize(
-                    results.diff_estimate.pct,
-                    significant,
-                    results.diff.mean < 0.
-                ),
-                colorize("%", significant, results.diff.mean < 0.),
-                if significant { "*" } else { "" },
-            );
-            println!(
-                "    {:12} │ {:>15} {:>15} {:>15}",
-                "min",
-                HumanTime(base.min),
-                HumanTime(candidate.min),
-                HumanTime(candidate.min - base.min)
-            );
-            println!(
-                "    {:12} │ {:>15} {:>15} {:>15}",
-                "max",
-                HumanTime(base.max),
-                HumanTime(candidate.max),
-                HumanTime(candidate.max - base.max),
-            );
-            println!(
-                "    {:12} │ {:>15} {:>15} {:>15}",
-                "std. dev.",
-                HumanTime(base.variance.sqrt()),
-                HumanTime(candidate.variance.sqrt()),
-                HumanTime(results.diff.variance.sqrt()),
-            );
-            println!();
+    impl Generator for CostlyGenerator {
+        type Haystack = ();
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            thread::sleep(self.0);
         }
-    }
 
-    #[derive(Default)]
-    pub(super) struct ConsoleReporter;
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
 
-    impl Reporter for ConsoleReporter {
-        fn on_complete(&mut self, results: &RunResult) {
-            let base = results.baseline;
-            let candidate = results.candidate;
-            let diff = results.diff;
+        fn name(&self) -> &str {
+            "Costly"
+        }
 
-            let significant = results.diff_estimate.significant;
+        fn sync(&mut self, _: u64) {}
+    }
 
-            let speedup = results.diff_estimate.pct;
-            let candidate_faster = diff.mean < 0.;
-            println!(
-                "{:50} [ {:>8} ... {:>8} ]    {:>+7.2}{}{}",
-                colorize(&results.name, significant, candidate_faster),
-                HumanTime(base.mean),
-                colorize(HumanTime(candidate.mean), significant, candidate_faster),
-                colorize(speedup, significant, candidate_faster),
-                colorize("%", significant, candidate_faster),
-                if significant { "*" } else { "" },
-            )
-        }
+    fn costly_generator_spi(haystack_time: Duration) -> Spi<'static> {
+        let func = |_: &(), _: &()| black_box(42);
+        let target = GenFunc::new("noop", func, CostlyGenerator(haystack_time));
+        Spi::for_benchmarks(vec![Box::new(target)]).unwrap()
     }
-}
 
-fn colorize<T: Display>(value: T, do_paint: bool, is_improved: bool) -> impl Display {
-    use colorz::{ansi, mode::Stream::Stdout, Colorize, Style};
+    #[test]
+    fn check_haystacks_amortized_for_the_most_expensive_generator() {
+        let cheap = costly_generator_spi(Duration::ZERO);
+        let expensive = costly_generator_spi(Duration::from_millis(5));
+        let simple = Spi::for_benchmarks(vec![benchmark_fn("noop/Costly", || 42)]).unwrap();
 
-    const RED: Style = Style::new().fg(ansi::Red).const_into_runtime_style();
-    const GREEN: Style = Style::new().fg(ansi::Green).const_into_runtime_style();
-    const DEFAULT: Style = Style::new().const_into_runtime_style();
+        let settings = MeasurementSettings {
+            amortize_haystacks: true,
+            ..Default::default()
+        };
+        let samples_per_haystack = |baseline, candidate| {
+            let loop_mode = LoopMode::Samples(4);
+            let test = PairedTest::new(baseline, candidate, settings, Some(0), loop_mode, None);
+            let result = test.run("noop/Costly", None).unwrap();
+            result.schedule.samples_per_haystack
+        };
 
-    if do_paint {
-        if is_improved {
-            value.into_style_with(GREEN).stream(Stdout)
-        } else {
-            value.into_style_with(RED).stream(Stdout)
-        }
-    } else {
-        value.into_style_with(DEFAULT).stream(Stdout)
+        assert_eq!(samples_per_haystack(&cheap, &cheap), 1);
+        assert!(samples_per_haystack(&cheap, &expensive) > 1);
+        assert!(samples_per_haystack(&expensive, &cheap) > 1);
+        // Target without a generator doesn't report costs, so the configured value is used
+        assert_eq!(samples_per_haystack(&simple, &expensive), 1);
     }
-}
 
-struct HumanTime(f64);
+    #[test]
+    fn check_phase_timings() {
+        let baseline = busy_spi(1000);
+        let candidate = busy_spi(1000);
+        let settings = MeasurementSettings::default();
 
-impl fmt::Display for HumanTime {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const USEC: f64 = 1_000.;
-        const MSEC: f64 = USEC * 1_000.;
-        const SEC: f64 = MSEC * 1_000.;
+        let loop_mode = LoopMode::Time(Duration::from_millis(50));
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let start = Instant::now();
+        let result = test.run("busy", None).unwrap();
+        let wall_time = start.elapsed();
 
-        if self.0.abs() > SEC {
-            f.pad(&format!("{:.1} s", self.0 / SEC))
-        } else if self.0.abs() > MSEC {
-            f.pad(&format!("{:.1} ms", self.0 / MSEC))
-        } else if self.0.abs() > USEC {
-            f.pad(&format!("{:.1} us", self.0 / USEC))
-        } else if self.0 == 0. {
-            f.pad("0 ns")
-        } else {
-            f.pad(&format!("{:.1} ns", self.0))
-        }
-    }
-}
+        let phases = result.phases;
+        assert!(!phases.estimate.is_zero());
+        assert!(!phases.generate.is_zero());
+        assert!(!phases.measure_base.is_zero());
+        assert!(!phases.measure_candidate.is_zero());
+        assert!(!phases.analyze.is_zero());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let total = phases.total();
+        assert!(total <= wall_time);
+        assert!(
+            wall_time - total < wall_time / 20,
+            "phases: {:?}, wall time: {:?}",
+            phases,
+            wall_time
+        );
 
-    #[test]
-    fn check_human_time() {
-        assert_eq!(format!("{}", HumanTime(0.1)), "0.1 ns");
-        assert_eq!(format!("{:>5}", HumanTime(0.)), " 0 ns");
+        let mut totals = PhaseTimings::default();
+        totals += phases;
+        totals += phases;
+        assert_eq!(totals.total(), total * 2);
+    }
 
-        assert_eq!(format!("{}", HumanTime(120.)), "120.0 ns");
+    /// Run-length encoding of the input
+    fn rle(input: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        for chunk in input.chunk_by(|a, b| a == b) {
+            for part in chunk.chunks(u8::MAX as usize) {
+                output.extend([part.len() as u8, part[0]]);
+            }
+        }
+        output
+    }
 
-        assert_eq!(format!("{}", HumanTime(1200.)), "1.2 us");
+    fn compressor_spi(compress: fn(&[u8]) -> Vec<u8>) -> Spi<'static> {
+        let input = [[0u8; 100], [1u8; 100], [2u8; 100]].concat();
+        let target = crate::benchmark_fn_metric(
+            "compressed_size",
+            move || compress(&input),
+            |output| output.len() as u64,
+            Unit::Bytes,
+        );
+        Spi::for_benchmarks(vec![target]).unwrap()
+    }
 
-        assert_eq!(format!("{}", HumanTime(1200000.)), "1.2 ms");
+    #[test]
+    fn check_compressors_compared_by_size() {
+        let baseline = compressor_spi(|input| input.to_vec());
+        let candidate = compressor_spi(rle);
 
-        assert_eq!(format!("{}", HumanTime(1200000000.)), "1.2 s");
+        let settings = MeasurementSettings::default();
+        let loop_mode = LoopMode::Samples(10);
+        let test = PairedTest::new(&baseline, &candidate, settings, Some(0), loop_mode, None);
+        let result = test.run("compressed_size", None).unwrap();
 
-        assert_eq!(format!("{}", HumanTime(-1200000.)), "-1.2 ms");
+        assert_eq!(result.unit, Unit::Bytes);
+        assert_eq!(result.baseline.mean, 300.);
+        assert_eq!(result.candidate.mean, 6.);
+        assert_eq!(result.diff.variance, 0.);
+        assert_eq!(result.diff_estimate.pct, -98.);
+        assert!(result.diff_estimate.significant);
     }
 
     // Sane checking some simple patterns
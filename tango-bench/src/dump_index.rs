@@ -0,0 +1,285 @@
+//! Index of a dump directory describing the run the dumps come from
+//!
+//! `compare --dump` writes [`INDEX_FILE`] next to the dumps: the session (settings, seed, environment, labels,
+//! start and end time) and a record of each dump (test name, number of samples, verdict and number of outliers).
+//! The index is written through a temporary file at the start and at the end of the run. Records of the completed
+//! tests are appended to [`JOURNAL_FILE`] in between and merged into the index by the final write, so an interrupted
+//! run leaves a readable index of the tests completed so far. `analyze` takes the settings and the test names from
+//! the index, so reports are re-rendered from a dump directory without any other inputs.
+//!
+//! [`VERSION`] is bumped on incompatible changes only. Fields added later have defaults, so indices written by other
+//! releases of the same version are readable.
+
+use crate::{manifest::Environment, stats::Verdict, Error, MeasurementSettings};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the index file in a dump directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// Name of the file the records of the dumps are appended to until the index is finished
+pub const JOURNAL_FILE: &str = "index.journal.jsonl";
+
+/// Version of the index format
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DumpIndex {
+    pub version: u32,
+
+    pub session: DumpSession,
+
+    /// Dumps in the order the tests were run
+    pub dumps: Vec<DumpRecord>,
+}
+
+/// Run the dumps are written by
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DumpSession {
+    pub settings: MeasurementSettings,
+
+    /// Seed all the test seeds are derived from (see [`crate::RngFactory`])
+    pub master_seed: u64,
+
+    pub environment: Environment,
+
+    /// Labels given to the run (`--label key=value`)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// Unix timestamp of the start of the run (in seconds)
+    #[serde(default)]
+    pub started: u64,
+
+    /// Unix timestamp of the end of the run (in seconds), `None` if the run is in progress or was interrupted
+    #[serde(default)]
+    pub finished: Option<u64>,
+}
+
+/// Dump of a single test
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DumpRecord {
+    /// Name of the dump file in the directory
+    pub file: String,
+
+    /// Name of the test
+    pub test: String,
+
+    /// Name the test is reported with (eg. `sort (cold)`)
+    #[serde(default)]
+    pub display_name: String,
+
+    /// Number of the samples in the dump
+    #[serde(default)]
+    pub samples: usize,
+
+    /// Verdict of the run the dump is written by
+    pub verdict: Verdict,
+
+    /// Number of the samples filtered as outliers
+    #[serde(default)]
+    pub outliers: usize,
+}
+
+impl DumpSession {
+    pub fn new(settings: MeasurementSettings, master_seed: u64, environment: Environment) -> Self {
+        Self {
+            settings,
+            master_seed,
+            environment,
+            labels: BTreeMap::new(),
+            started: now(),
+            finished: None,
+        }
+    }
+
+    /// Marks the run as finished
+    pub fn finish(&mut self) {
+        self.finished = Some(now());
+    }
+}
+
+impl DumpIndex {
+    pub fn new(session: DumpSession) -> Self {
+        Self {
+            version: VERSION,
+            session,
+            dumps: vec![],
+        }
+    }
+
+    /// Adds a record of a dump, replacing the previous record of the same file if any
+    pub fn add(&mut self, record: DumpRecord) {
+        self.dumps.retain(|d| d.file != record.file);
+        self.dumps.push(record);
+    }
+
+    /// Record of a dump file with a given name
+    pub fn dump(&self, file: &str) -> Option<&DumpRecord> {
+        self.dumps.iter().find(|d| d.file == file)
+    }
+
+    /// Adds a record of a dump and appends it to the journal of a given dump directory
+    ///
+    /// The record is in the index of the directory once it is written (see [`Self::write()`]) or read back.
+    pub fn append(&mut self, dir: impl AsRef<Path>, record: DumpRecord) -> Result<(), Error> {
+        let path = dir.as_ref().join(JOURNAL_FILE);
+        let mut line = serde_json::to_vec(&record).map_err(Error::InvalidDumpIndex)?;
+        line.push(b'\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)?;
+        self.add(record);
+        Ok(())
+    }
+
+    /// Reads the index of a given dump directory, `None` if the directory has no index
+    ///
+    /// Records left in the journal by an interrupted run are added to the index. A partially written last record is
+    /// ignored.
+    pub fn read(dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let dir = dir.as_ref();
+        let file = match File::open(dir.join(INDEX_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut index: Self =
+            serde_json::from_reader(BufReader::new(file)).map_err(Error::InvalidDumpIndex)?;
+        if index.version > VERSION {
+            return Err(Error::UnsupportedDumpIndexVersion(index.version));
+        }
+        let journal = match File::open(dir.join(JOURNAL_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Some(index)),
+            Err(e) => return Err(e.into()),
+        };
+        for line in BufReader::new(journal).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(record) => index.add(record),
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(Error::InvalidDumpIndex(e)),
+            }
+        }
+        Ok(Some(index))
+    }
+
+    /// Writes the index to a given dump directory, removing the journal merged into it
+    ///
+    /// The index is written to a temporary file first and renamed, so the index in the directory is always
+    /// complete.
+    pub fn write(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!(".{}.tmp", INDEX_FILE));
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(Error::InvalidDumpIndex)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp, dir.join(INDEX_FILE))?;
+        match fs::remove_file(dir.join(JOURNAL_FILE)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn check_dump_index_roundtrip() {
+        let dir = env::temp_dir().join(format!("tango-dump-index-{}", process::id()));
+        assert!(DumpIndex::read(&dir).unwrap().is_none());
+
+        let mut session =
+            DumpSession::new(MeasurementSettings::default(), 42, Environment::current());
+        session.labels.insert("commit".into(), "1a2b3c".into());
+        let mut index = DumpIndex::new(session);
+        let record = DumpRecord {
+            file: "sort.csv".into(),
+            test: "sort".into(),
+            display_name: "sort (cold)".into(),
+            samples: 100,
+            verdict: Verdict::Inconclusive,
+            outliers: 3,
+        };
+        index.write(&dir).unwrap();
+        index.append(&dir, record.clone()).unwrap();
+
+        // Partial index of an interrupted run is readable
+        let read = DumpIndex::read(&dir).unwrap().unwrap();
+        assert_eq!(read.session.finished, None);
+        assert_eq!(read.dumps, std::slice::from_ref(&record));
+
+        // Rewritten dump replaces the previous record
+        let rewritten = DumpRecord {
+            verdict: Verdict::Different,
+            ..record
+        };
+        index.append(&dir, rewritten.clone()).unwrap();
+        let mut journal = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))
+            .unwrap();
+        journal.write_all(b"{\"file\":\"trunc").unwrap();
+        let read = DumpIndex::read(&dir).unwrap().unwrap();
+        assert_eq!(read.dumps, std::slice::from_ref(&rewritten));
+        index.session.finish();
+        index.write(&dir).unwrap();
+
+        let read = DumpIndex::read(&dir).unwrap().unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, 1, "temporary file or journal is left");
+        assert_eq!(read.session.master_seed, 42);
+        assert_eq!(read.session.labels["commit"], "1a2b3c");
+        assert!(read.session.finished.is_some());
+        assert_eq!(read.dumps, std::slice::from_ref(&rewritten));
+        assert_eq!(read.dump("sort.csv"), Some(&rewritten));
+    }
+
+    #[test]
+    fn check_dump_index_defaults() {
+        let json = r#"{
+            "version": 1,
+            "session": {
+                "settings": SETTINGS,
+                "master_seed": 1,
+                "environment": ENVIRONMENT,
+                "added_later": true
+            },
+            "dumps": [{ "file": "sort.csv", "test": "sort", "verdict": "Different" }]
+        }"#;
+        let json = json
+            .replace(
+                "SETTINGS",
+                &serde_json::to_string(&MeasurementSettings::default()).unwrap(),
+            )
+            .replace(
+                "ENVIRONMENT",
+                &serde_json::to_string(&Environment::current()).unwrap(),
+            );
+        let index: DumpIndex = serde_json::from_str(&json).unwrap();
+        assert_eq!(index.session.started, 0);
+        assert_eq!(index.dumps[0].display_name, "");
+        assert_eq!(index.dumps[0].samples, 0);
+    }
+}
@@ -1,37 +1,173 @@
 //! Loading and resolving symbols from .dylib/.so libraries
 
 use self::ffi::VTable;
-use crate::{Error, MeasureTarget};
+use crate::{
+    id::TestId,
+    profile::{Profile, TestProfile},
+    registry::{normalize_name, truncate_name},
+    timer, CostHints, Error, MeasureTarget, MeasurementSettings, Throughput, Unit,
+    VerificationSample, DEFAULT_SETTINGS,
+};
 use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     ffi::c_char,
-    ptr::{addr_of, null},
+    path::PathBuf,
+    ptr::{addr_of, addr_of_mut, null},
     slice, str,
 };
 
+/// Length of the prefix of an invalid test name reported in the error
+const MAX_REPORTED_NAME_LEN: usize = 64;
+
+/// Version of [`Metadata`] reported by this version of tango
+const METADATA_VERSION: u32 = 1;
+
+/// Properties of the selected function reported by a library in a single call (`tango_metadata()`)
+///
+/// Properties added later are optional, so the metadata of the libraries built with older versions of tango is
+/// still readable. The version is bumped only if the meaning of an existing property changes.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Metadata {
+    version: u32,
+    cold: bool,
+    unit: Unit,
+    weight: f64,
+    includes_setup: bool,
+    batch_len: usize,
+    profile: TestProfile,
+    generator: Option<String>,
+    recording: Option<PathBuf>,
+    mutates_haystack: bool,
+    entry_point: Option<usize>,
+    cost_hints: Option<CostHints>,
+    throughput: Option<Throughput>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            version: METADATA_VERSION,
+            cold: false,
+            unit: Unit::Nanoseconds,
+            weight: 1.,
+            includes_setup: false,
+            batch_len: 1,
+            profile: TestProfile::default(),
+            generator: None,
+            recording: None,
+            mutates_haystack: false,
+            entry_point: None,
+            cost_hints: None,
+            throughput: None,
+        }
+    }
+}
+
+impl Metadata {
+    /// Metadata of a target with the overrides of its profile (see [`TestProfile::resolve()`])
+    fn of(target: &dyn MeasureTarget) -> Self {
+        Self {
+            version: METADATA_VERSION,
+            cold: target.is_cold(),
+            unit: target.unit(),
+            weight: target.weight(),
+            includes_setup: target.includes_setup(),
+            batch_len: target.batch_len(),
+            profile: TestProfile::new(target.profile(), &target.tolerance()),
+            generator: target.generator().map(str::to_string),
+            recording: target.recording(),
+            mutates_haystack: target.mutates_haystack(),
+            entry_point: target.entry_point(),
+            cost_hints: target.cost_hints(),
+            throughput: target.throughput(),
+        }
+    }
+}
+
 pub struct Spi<'l> {
     tests: Vec<NamedFunction>,
+
+    /// Index of tests by name
+    index: HashMap<TestId, usize>,
     vt: Box<dyn VTable + 'l>,
 }
 
 pub struct NamedFunction {
-    pub name: String,
+    /// Name of the test shared with all the results reported for it
+    pub name: TestId,
+
+    /// Function should be measured in cold-start mode (see [`crate::MeasureTargetExt::measure_cold()`])
+    pub cold: bool,
+
+    /// Unit of the values measured by the function
+    pub unit: Unit,
+
+    /// Importance of the function in the suite summary (see [`crate::MeasureTargetExt::with_weight()`])
+    pub weight: f64,
+
+    /// Setup of the function state is included in the measurement (see [`crate::benchmark_fn_including_setup()`])
+    pub e2e: bool,
+
+    /// Number of needles processed by each call (see [`crate::BenchmarkMatrix::add_batch_function()`])
+    pub batch: usize,
+
+    /// Tolerance profile of the function (see [`crate::MeasureTargetExt::with_profile()`])
+    pub profile: TestProfile,
+
+    /// Name of the generator producing the inputs of the function (see [`crate::MeasureTarget::generator()`])
+    pub generator: Option<String>,
+
+    /// File the inputs of the function are recorded to or replayed from (see [`crate::Generator::recording()`])
+    pub recording: Option<PathBuf>,
+
+    /// Haystack of the function is mutated between the samples (see [`crate::Generator::mutates_haystack()`])
+    pub mutates: bool,
 
     ///  Function index in FFI API
     idx: usize,
 }
 
 impl<'l> Spi<'l> {
-    pub(crate) fn for_library(library: &'l Library) -> Result<Self, Error> {
-        Self::for_vtable(ffi::LibraryVTable::new(library)?)
+    /// Loads the tests of a library rejecting the ones with invalid names (see [`normalize_name()`])
+    pub(crate) fn for_library(library: &'l Library, max_name_len: usize) -> Result<Self, Error> {
+        Self::for_vtable(ffi::LibraryVTable::new(library)?, max_name_len)
     }
 
     /// TODO: should be singleton
-    pub(crate) fn for_self() -> Option<Result<Self, Error>> {
-        unsafe { ffi::SELF_SPI.take().map(Self::for_vtable) }
+    pub(crate) fn for_self(max_name_len: usize) -> Option<Result<Self, Error>> {
+        unsafe {
+            (*addr_of_mut!(ffi::SELF_SPI))
+                .take()
+                .map(|vt| Self::for_vtable(vt, max_name_len))
+        }
     }
 
-    fn for_vtable<T: VTable + 'l>(vt: T) -> Result<Self, Error> {
+    /// Creates Spi for the benchmarks owned by the caller bypassing the global FFI state
+    pub(crate) fn for_benchmarks(benchmarks: Vec<Box<dyn MeasureTarget>>) -> Result<Self, Error> {
+        Self::for_vtable(
+            ffi::LocalVTable::new(benchmarks, None),
+            DEFAULT_SETTINGS.max_name_len,
+        )
+    }
+
+    /// Same as [`Self::for_benchmarks()`], but exports given measurement settings like a library built with
+    /// `tango_main!(settings)` does
+    #[cfg(test)]
+    pub(crate) fn for_benchmarks_with_settings(
+        benchmarks: Vec<Box<dyn MeasureTarget>>,
+        settings: MeasurementSettings,
+    ) -> Result<Self, Error> {
+        let max_name_len = settings.max_name_len;
+        Self::for_vtable(
+            ffi::LocalVTable::new(benchmarks, Some(settings)),
+            max_name_len,
+        )
+    }
+
+    fn for_vtable<T: VTable + 'l>(vt: T, max_name_len: usize) -> Result<Self, Error> {
         let vt = Box::new(vt);
         vt.init();
 
@@ -40,19 +176,39 @@ impl<'l> Spi<'l> {
             vt.select(idx);
 
             let mut length = 0usize;
-            let name_ptr: *const c_char = null();
-            vt.get_test_name(addr_of!(name_ptr) as _, &mut length);
+            let mut name_ptr: *const c_char = null();
+            vt.get_test_name(&mut name_ptr, &mut length);
             if length == 0 {
                 continue;
             }
             let slice = unsafe { slice::from_raw_parts(name_ptr as *const u8, length) };
-            let name = str::from_utf8(slice)
-                .map_err(Error::InvalidFFIString)?
-                .to_string();
-            tests.push(NamedFunction { name, idx });
+            let name = str::from_utf8(slice).map_err(Error::InvalidFFIString)?;
+            let name = normalize_name(name, max_name_len).map_err(|reason| {
+                Error::InvalidTestName(idx, truncate_name(name, MAX_REPORTED_NAME_LEN), reason)
+            })?;
+            let name = TestId::new(name);
+            let metadata = read_metadata(vt.as_ref())?;
+            tests.push(NamedFunction {
+                name,
+                idx,
+                cold: metadata.cold,
+                unit: metadata.unit,
+                weight: metadata.weight,
+                e2e: metadata.includes_setup,
+                batch: metadata.batch_len.max(1),
+                profile: metadata.profile,
+                generator: metadata.generator,
+                recording: metadata.recording,
+                mutates: metadata.mutates_haystack,
+            });
         }
 
-        Ok(Spi { vt, tests })
+        let index = tests
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.name.clone(), i))
+            .collect();
+        Ok(Spi { vt, tests, index })
     }
 
     pub(crate) fn tests(&self) -> &[NamedFunction] {
@@ -60,7 +216,14 @@ impl<'l> Spi<'l> {
     }
 
     pub(crate) fn lookup(&self, name: &str) -> Option<&NamedFunction> {
-        self.tests.iter().find(|f| f.name == name)
+        self.index.get(name).map(|&i| &self.tests[i])
+    }
+
+    /// Merges the profiles of the tests with their definitions (see [`crate::cli::MainOptions::profiles`])
+    pub(crate) fn resolve_profiles(&mut self, definitions: &[(String, Profile)]) {
+        for test in &mut self.tests {
+            test.profile.resolve(definitions);
+        }
     }
 
     pub(crate) fn run(&self, func: &NamedFunction, iterations: usize) -> u64 {
@@ -68,11 +231,51 @@ impl<'l> Spi<'l> {
         self.vt.run(iterations)
     }
 
+    /// Measures each of `iterations` calls of the function separately (see [`MeasureTarget::measure_detailed()`])
+    ///
+    /// Returns `None` if the function or the library doesn't support it.
+    pub(crate) fn measure_detailed(
+        &self,
+        func: &NamedFunction,
+        iterations: usize,
+    ) -> Option<Vec<u64>> {
+        self.vt.select(func.idx);
+        let mut times = vec![0; iterations];
+        self.vt.measure_detailed(&mut times).then_some(times)
+    }
+
     pub(crate) fn estimate_iterations(&self, func: &NamedFunction, time_ms: u32) -> usize {
         self.vt.select(func.idx);
         self.vt.estimate_iterations(time_ms)
     }
 
+    /// Generator costs reported by the function during the last iterations estimation
+    ///
+    /// Returns `None` if the function or the library doesn't report costs (see [`MeasureTarget::cost_hints()`]).
+    pub(crate) fn cost_hints(&self, func: &NamedFunction) -> Option<CostHints> {
+        self.metadata(func)?.cost_hints
+    }
+
+    /// Amount of data processed by each iteration of the function on the haystacks generated so far
+    ///
+    /// Returns `None` if the function or the library doesn't report it (see [`MeasureTarget::throughput()`]).
+    pub(crate) fn throughput(&self, func: &NamedFunction) -> Option<Throughput> {
+        self.metadata(func)?.throughput
+    }
+
+    /// Address of the code measuring the function (see [`MeasureTarget::entry_point()`])
+    ///
+    /// Returns `None` if the function or the library doesn't export it.
+    pub(crate) fn entry_point(&self, func: &NamedFunction) -> Option<usize> {
+        self.metadata(func)?.entry_point
+    }
+
+    /// Current metadata of the function, `None` if it can't be read (it was read successfully when loading)
+    fn metadata(&self, func: &NamedFunction) -> Option<Metadata> {
+        self.vt.select(func.idx);
+        read_metadata(self.vt.as_ref()).ok()
+    }
+
     pub(crate) fn sync(&self, func: &NamedFunction, seed: u64) {
         self.vt.select(func.idx);
         self.vt.sync(seed)
@@ -82,6 +285,105 @@ impl<'l> Spi<'l> {
         self.vt.select(func.idx);
         self.vt.next_haystack()
     }
+
+    /// Applies incremental changes to the haystack of the function (see [`MeasureTarget::mutate_haystack()`])
+    pub(crate) fn mutate_haystack(&self, func: &NamedFunction) {
+        self.vt.select(func.idx);
+        self.vt.mutate_haystack()
+    }
+
+    /// Takes the error the inputs of the function failed with (see [`MeasureTarget::take_error()`])
+    ///
+    /// Returns `None` if there is no error or the library doesn't report them.
+    pub(crate) fn take_error(&self, func: &NamedFunction) -> Option<String> {
+        self.vt.select(func.idx);
+        let mut length = 0usize;
+        let mut ptr: *const c_char = null();
+        if !self.vt.take_error(&mut ptr, &mut length) {
+            return None;
+        }
+        let error = unsafe { slice::from_raw_parts(ptr as *const u8, length) };
+        Some(String::from_utf8_lossy(error).into_owned())
+    }
+
+    /// Acquires the resources of the function before the test (see [`MeasureTarget::prepare()`])
+    pub(crate) fn prepare(&self, func: &NamedFunction) {
+        self.vt.select(func.idx);
+        self.vt.prepare()
+    }
+
+    /// Releases the resources of the function after the test (see [`MeasureTarget::teardown()`])
+    pub(crate) fn teardown(&self, func: &NamedFunction) {
+        self.vt.select(func.idx);
+        self.vt.teardown()
+    }
+
+    /// Includes or excludes the setup of the function state from the measurement
+    ///
+    /// Returns `false` if the library doesn't support it.
+    pub(crate) fn set_includes_setup(&self, func: &NamedFunction, include: bool) -> bool {
+        self.vt.select(func.idx);
+        self.vt.set_includes_setup(include)
+    }
+
+    /// Runs the function on a given number of inputs to verify its outputs (see [`MeasureTarget::verify()`])
+    ///
+    /// Returns `None` if the function or the library doesn't support output verification.
+    pub(crate) fn verify(
+        &self,
+        func: &NamedFunction,
+        samples: usize,
+    ) -> Option<Result<Vec<VerificationSample>, Error>> {
+        self.vt.select(func.idx);
+        let mut length = 0usize;
+        let mut json_ptr: *const c_char = null();
+        if !self.vt.verify(samples, &mut json_ptr, &mut length) {
+            return None;
+        }
+        let json = unsafe { slice::from_raw_parts(json_ptr as *const u8, length) };
+        Some(serde_json::from_slice(json).map_err(Error::InvalidVerification))
+    }
+
+    /// Effective measurement settings of the library
+    ///
+    /// Those are the settings given to `tango_main!()` unless the host has [pushed][Self::push_settings()] its own.
+    /// Returns `None` if the library doesn't export its settings.
+    pub(crate) fn settings(&self) -> Option<Result<MeasurementSettings, Error>> {
+        let mut length = 0usize;
+        let mut json_ptr: *const c_char = null();
+        if !self.vt.settings_json(&mut json_ptr, &mut length) || length == 0 {
+            return None;
+        }
+        let json = unsafe { slice::from_raw_parts(json_ptr as *const u8, length) };
+        Some(serde_json::from_slice(json).map_err(Error::InvalidSettings))
+    }
+
+    /// Makes the library use host measurement settings
+    ///
+    /// Returns `false` if the library doesn't support it.
+    pub(crate) fn push_settings(&self, settings: &MeasurementSettings) -> bool {
+        match serde_json::to_string(settings) {
+            Ok(json) => self.vt.set_settings_json(&json),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Metadata of the selected function, the default one if the library doesn't report metadata
+fn read_metadata(vt: &dyn VTable) -> Result<Metadata, Error> {
+    let mut length = 0usize;
+    let mut json_ptr: *const c_char = null();
+    if !vt.metadata_json(&mut json_ptr, &mut length) || length == 0 {
+        return Ok(Metadata::default());
+    }
+    let json = unsafe { slice::from_raw_parts(json_ptr as *const u8, length) };
+    // Invalid UTF-8 (eg. in the name of a generator) is replaced instead of failing the whole library
+    let json = String::from_utf8_lossy(json);
+    let metadata: Metadata = serde_json::from_str(&json).map_err(Error::InvalidMetadata)?;
+    if metadata.version > METADATA_VERSION {
+        return Err(Error::UnsupportedMetadataVersion(metadata.version));
+    }
+    Ok(metadata)
 }
 
 /// State which holds the information about list of benchmarks and which one is selected.
@@ -89,9 +391,89 @@ impl<'l> Spi<'l> {
 pub struct State {
     pub benchmarks: Vec<Box<dyn MeasureTarget>>,
     pub selected_function: usize,
+
+    /// Measurement settings pushed by the host (see [`Spi::push_settings()`])
+    pub settings: Option<MeasurementSettings>,
+
+    /// Keeps serialized settings alive while the host is reading them
+    settings_json: String,
+
+    /// Keeps serialized verification samples alive while the host is reading them
+    verification_json: String,
+
+    /// Keeps serialized metadata alive while the host is reading it
+    metadata_json: String,
+
+    /// Keeps the last error taken from the selected function alive while the host is reading it
+    error: String,
 }
 
 impl State {
+    fn new(benchmarks: Vec<Box<dyn MeasureTarget>>) -> Self {
+        Self {
+            benchmarks,
+            selected_function: 0,
+            settings: None,
+            settings_json: String::new(),
+            verification_json: String::new(),
+            metadata_json: String::new(),
+            error: String::new(),
+        }
+    }
+
+    /// Serializes effective settings (pushed by the host or `default` ones)
+    fn settings_json(&mut self, default: &MeasurementSettings) -> &str {
+        let settings = self.settings.as_ref().unwrap_or(default);
+        self.settings_json = serde_json::to_string(settings).unwrap_or_default();
+        &self.settings_json
+    }
+
+    fn set_settings_json(&mut self, json: &[u8]) -> bool {
+        match serde_json::from_slice::<MeasurementSettings>(json) {
+            Ok(settings) => {
+                // Measurements of the library are taken with the same clock as the ones of the host
+                if let Some(clock) = settings.clock {
+                    timer::select(clock);
+                }
+                self.settings = Some(settings);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Serializes verification samples of the selected function. Returns `None` if it doesn't support verification
+    fn verification_json(&mut self, samples: usize) -> Option<&str> {
+        let samples = self.selected_mut().verify(samples)?;
+        self.verification_json = serde_json::to_string(&samples).ok()?;
+        Some(&self.verification_json)
+    }
+
+    /// Takes the error of the selected function. Returns `None` if there is none
+    fn take_error(&mut self) -> Option<&str> {
+        self.error = self.selected_mut().take_error()?;
+        Some(&self.error)
+    }
+
+    /// Serializes the metadata of the selected function
+    fn metadata_json(&mut self) -> &str {
+        let metadata = Metadata::of(self.selected());
+        self.metadata_json = serde_json::to_string(&metadata).unwrap_or_default();
+        &self.metadata_json
+    }
+
+    /// Fills `times` with the times of the calls of the selected function. Returns `false` if it doesn't support
+    /// per-call measurement
+    fn measure_detailed(&mut self, times: &mut [u64]) -> bool {
+        match self.selected_mut().measure_detailed(times.len()) {
+            Some(measured) if measured.len() == times.len() => {
+                times.copy_from_slice(&measured);
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn selected(&self) -> &dyn MeasureTarget {
         self.benchmarks[self.selected_function].as_ref()
     }
@@ -104,16 +486,45 @@ impl State {
 /// Global state of the benchmarking library
 static mut STATE: Option<State> = None;
 
+unsafe fn state() -> Option<&'static State> {
+    (*addr_of!(STATE)).as_ref()
+}
+
+unsafe fn state_mut() -> Option<&'static mut State> {
+    (*addr_of_mut!(STATE)).as_mut()
+}
+
 /// `tango_init()` implementation
 ///
-/// This function is not exported from the library, but is used by the `tango_init()` functions
-/// generated by the `tango_benchmark!()` macro.
+/// This function is not exported from the library, but is used by the exported [`ffi::tango_init()`].
+///
+/// # Safety
+/// Should be called once before any other `tango_*` function and never concurrently with them
 pub unsafe fn __tango_init(benchmarks: Vec<Box<dyn MeasureTarget>>) {
-    if STATE.is_none() {
-        STATE = Some(State {
-            benchmarks,
-            selected_function: 0,
-        });
+    if state().is_none() {
+        STATE = Some(State::new(benchmarks));
+    }
+}
+
+/// `tango_settings_json()` implementation
+///
+/// This function is not exported from the library, but is used by the `tango_settings_json()` functions
+/// generated by the `tango_main!()` macro.
+///
+/// # Safety
+/// `json` and `length` should be valid pointers. Returned string is valid until the next call of this function
+pub unsafe fn __tango_settings_json(
+    settings: MeasurementSettings,
+    json: *mut *const c_char,
+    length: *mut usize,
+) {
+    if let Some(s) = state_mut() {
+        let j = s.settings_json(&settings);
+        *json = j.as_ptr() as _;
+        *length = j.len();
+    } else {
+        *json = null();
+        *length = 0;
     }
 }
 
@@ -125,7 +536,7 @@ pub unsafe fn __tango_init(benchmarks: Vec<Box<dyn MeasureTarget>>) {
 /// way two executables can coexist in the single process at the same time.
 pub mod ffi {
     use super::*;
-    use std::{os::raw::c_char, ptr::null, usize};
+    use std::{cell::RefCell, os::raw::c_char, ptr::null};
 
     /// Signature types of all FFI API functions
     pub type InitFn = unsafe extern "C" fn();
@@ -133,9 +544,19 @@ pub mod ffi {
     type GetTestNameFn = unsafe extern "C" fn(*mut *const c_char, *mut usize);
     type SelectFn = unsafe extern "C" fn(usize);
     type RunFn = unsafe extern "C" fn(usize) -> u64;
+    type MeasureDetailedFn = unsafe extern "C" fn(usize, *mut u64) -> bool;
     type EstimateIterationsFn = unsafe extern "C" fn(u32) -> usize;
     type NextHaystackFn = unsafe extern "C" fn() -> bool;
+    type MutateHaystackFn = unsafe extern "C" fn();
+    type TakeErrorFn = unsafe extern "C" fn(*mut *const c_char, *mut usize) -> bool;
+    type PrepareFn = unsafe extern "C" fn();
+    type TeardownFn = unsafe extern "C" fn();
     type SyncFn = unsafe extern "C" fn(u64);
+    type SetIncludesSetupFn = unsafe extern "C" fn(bool);
+    pub type SettingsJsonFn = unsafe extern "C" fn(*mut *const c_char, *mut usize);
+    type SetSettingsJsonFn = unsafe extern "C" fn(*const c_char, usize) -> bool;
+    type VerifyFn = unsafe extern "C" fn(usize, *mut *const c_char, *mut usize) -> bool;
+    type MetadataJsonFn = unsafe extern "C" fn(*mut *const c_char, *mut usize) -> bool;
     type FreeFn = unsafe extern "C" fn();
 
     /// This block of constants is checking that all exported tango functions are of valid type according to the API.
@@ -144,30 +565,49 @@ pub mod ffi {
     mod type_check {
         use super::*;
 
+        const TANGO_INIT: InitFn = tango_init;
         const TANGO_COUNT: CountFn = tango_count;
         const TANGO_SELECT: SelectFn = tango_select;
         const TANGO_GET_TEST_NAME: GetTestNameFn = tango_get_test_name;
         const TANGO_RUN: RunFn = tango_run;
+        const TANGO_MEASURE_DETAILED: MeasureDetailedFn = tango_measure_detailed;
         const TANGO_ESTIMATE_ITERATIONS: EstimateIterationsFn = tango_estimate_iterations;
         const TANGO_SYNC: SyncFn = tango_sync;
+        const TANGO_MUTATE_HAYSTACK: MutateHaystackFn = tango_mutate_haystack;
+        const TANGO_TAKE_ERROR: TakeErrorFn = tango_take_error;
+        const TANGO_PREPARE: PrepareFn = tango_prepare;
+        const TANGO_TEARDOWN: TeardownFn = tango_teardown;
+        const TANGO_SET_INCLUDES_SETUP: SetIncludesSetupFn = tango_set_includes_setup;
+        const TANGO_SET_SETTINGS_JSON: SetSettingsJsonFn = tango_set_settings_json;
+        const TANGO_VERIFY: VerifyFn = tango_verify;
+        const TANGO_METADATA_JSON: MetadataJsonFn = tango_metadata_json;
         const TANGO_FREE: FreeFn = tango_free;
     }
 
+    /// Initializes the harness with all the [`collected()`](crate::registry::collected) benchmarks
+    ///
+    /// # Safety
+    /// Should be called once before any other `tango_*` function and never concurrently with them
+    #[no_mangle]
+    pub unsafe extern "C" fn tango_init() {
+        __tango_init(crate::registry::collected())
+    }
+
     #[no_mangle]
     unsafe extern "C" fn tango_count() -> usize {
-        STATE.as_ref().map(|s| s.benchmarks.len()).unwrap_or(0)
+        state().map(|s| s.benchmarks.len()).unwrap_or(0)
     }
 
     #[no_mangle]
     unsafe extern "C" fn tango_select(idx: usize) {
-        if let Some(s) = STATE.as_mut() {
+        if let Some(s) = state_mut() {
             s.selected_function = idx.min(s.benchmarks.len() - 1);
         }
     }
 
     #[no_mangle]
     unsafe extern "C" fn tango_get_test_name(name: *mut *const c_char, length: *mut usize) {
-        if let Some(s) = STATE.as_ref() {
+        if let Some(s) = state() {
             let n = s.selected().name();
             *name = n.as_ptr() as _;
             *length = n.len();
@@ -179,16 +619,27 @@ pub mod ffi {
 
     #[no_mangle]
     unsafe extern "C" fn tango_run(iterations: usize) -> u64 {
-        if let Some(s) = STATE.as_mut() {
-            s.selected_mut().measure(iterations)
+        if let Some(s) = state_mut() {
+            s.selected_mut().measure_value(iterations).value
         } else {
             0
         }
     }
 
+    /// Writes the times of `iterations` calls of the selected function to `times` (should have room for all of
+    /// them). Returns `false` if the function doesn't support per-call measurement
+    #[no_mangle]
+    unsafe extern "C" fn tango_measure_detailed(iterations: usize, times: *mut u64) -> bool {
+        if let Some(s) = state_mut() {
+            s.measure_detailed(slice::from_raw_parts_mut(times, iterations))
+        } else {
+            false
+        }
+    }
+
     #[no_mangle]
     unsafe extern "C" fn tango_estimate_iterations(time_ms: u32) -> usize {
-        if let Some(s) = STATE.as_mut() {
+        if let Some(s) = state_mut() {
             s.selected_mut().estimate_iterations(time_ms)
         } else {
             0
@@ -197,23 +648,96 @@ pub mod ffi {
 
     #[no_mangle]
     unsafe extern "C" fn tango_next_haystack() -> bool {
-        if let Some(s) = STATE.as_mut() {
+        if let Some(s) = state_mut() {
             s.selected_mut().next_haystack()
         } else {
             false
         }
     }
 
+    #[no_mangle]
+    unsafe extern "C" fn tango_mutate_haystack() {
+        if let Some(s) = state_mut() {
+            s.selected_mut().mutate_haystack()
+        }
+    }
+
+    /// Writes the error the inputs of the selected function failed with. Returns `false` if there is none
+    #[no_mangle]
+    unsafe extern "C" fn tango_take_error(error: *mut *const c_char, length: *mut usize) -> bool {
+        let Some(e) = state_mut().and_then(|s| s.take_error()) else {
+            return false;
+        };
+        *error = e.as_ptr() as _;
+        *length = e.len();
+        true
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn tango_prepare() {
+        if let Some(s) = state_mut() {
+            s.selected_mut().prepare()
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn tango_teardown() {
+        if let Some(s) = state_mut() {
+            s.selected_mut().teardown()
+        }
+    }
+
     #[no_mangle]
     unsafe extern "C" fn tango_sync(seed: u64) {
-        if let Some(s) = STATE.as_mut() {
+        if let Some(s) = state_mut() {
             s.selected_mut().sync(seed)
         }
     }
 
+    #[no_mangle]
+    unsafe extern "C" fn tango_set_includes_setup(include: bool) {
+        if let Some(s) = state_mut() {
+            s.selected_mut().set_includes_setup(include)
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn tango_set_settings_json(json: *const c_char, length: usize) -> bool {
+        if let Some(s) = state_mut() {
+            s.set_settings_json(slice::from_raw_parts(json as *const u8, length))
+        } else {
+            false
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn tango_verify(
+        samples: usize,
+        json: *mut *const c_char,
+        length: *mut usize,
+    ) -> bool {
+        let Some(j) = state_mut().and_then(|s| s.verification_json(samples)) else {
+            return false;
+        };
+        *json = j.as_ptr() as _;
+        *length = j.len();
+        true
+    }
+
+    /// Writes JSON of the metadata of the selected function (see [`Metadata`])
+    #[no_mangle]
+    unsafe extern "C" fn tango_metadata_json(json: *mut *const c_char, length: *mut usize) -> bool {
+        let Some(j) = state_mut().map(|s| s.metadata_json()) else {
+            return false;
+        };
+        *json = j.as_ptr() as _;
+        *length = j.len();
+        true
+    }
+
     #[no_mangle]
     unsafe extern "C" fn tango_free() {
-        STATE.take();
+        (*addr_of_mut!(STATE)).take();
     }
 
     pub(super) trait VTable {
@@ -222,9 +746,37 @@ pub mod ffi {
         fn select(&self, func_idx: usize);
         fn get_test_name(&self, ptr: *mut *const c_char, len: *mut usize);
         fn run(&self, iterations: usize) -> u64;
+
+        /// Returns `false` if the selected function doesn't support per-call measurement
+        fn measure_detailed(&self, times: &mut [u64]) -> bool;
         fn estimate_iterations(&self, time_ms: u32) -> usize;
         fn next_haystack(&self) -> bool;
+
+        /// Does nothing if the library doesn't support haystack mutation
+        fn mutate_haystack(&self);
+
+        /// Returns `false` if there is no error or the library doesn't report them
+        fn take_error(&self, ptr: *mut *const c_char, len: *mut usize) -> bool;
+
+        /// Does nothing if the library doesn't support test fixtures
+        fn prepare(&self);
+
+        /// Does nothing if the library doesn't support test fixtures
+        fn teardown(&self);
         fn sync(&self, seed: u64);
+
+        /// Returns `false` if including setup in the measurement is not supported
+        fn set_includes_setup(&self, include: bool) -> bool;
+
+        /// Returns `false` if measurement settings are not exported
+        fn settings_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool;
+        fn set_settings_json(&self, json: &str) -> bool;
+
+        /// Returns `false` if the selected function doesn't support output verification
+        fn verify(&self, samples: usize, ptr: *mut *const c_char, len: *mut usize) -> bool;
+
+        /// Returns `false` if metadata is not reported
+        fn metadata_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool;
     }
 
     pub(super) static mut SELF_SPI: Option<SelfVTable> = Some(SelfVTable);
@@ -257,6 +809,10 @@ pub mod ffi {
             unsafe { tango_run(iterations) }
         }
 
+        fn measure_detailed(&self, times: &mut [u64]) -> bool {
+            unsafe { tango_measure_detailed(times.len(), times.as_mut_ptr()) }
+        }
+
         fn estimate_iterations(&self, time_ms: u32) -> usize {
             unsafe { tango_estimate_iterations(time_ms) }
         }
@@ -265,9 +821,47 @@ pub mod ffi {
             unsafe { tango_next_haystack() }
         }
 
+        fn mutate_haystack(&self) {
+            unsafe { tango_mutate_haystack() }
+        }
+
+        fn take_error(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            unsafe { tango_take_error(ptr, len) }
+        }
+
+        fn prepare(&self) {
+            unsafe { tango_prepare() }
+        }
+
+        fn teardown(&self) {
+            unsafe { tango_teardown() }
+        }
+
         fn sync(&self, seed: u64) {
             unsafe { tango_sync(seed) }
         }
+
+        fn set_includes_setup(&self, include: bool) -> bool {
+            unsafe { tango_set_includes_setup(include) };
+            true
+        }
+
+        fn settings_json(&self, _ptr: *mut *const c_char, _len: *mut usize) -> bool {
+            // Settings of the executable are known to the harness directly
+            false
+        }
+
+        fn set_settings_json(&self, json: &str) -> bool {
+            unsafe { tango_set_settings_json(json.as_ptr() as _, json.len()) }
+        }
+
+        fn verify(&self, samples: usize, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            unsafe { tango_verify(samples, ptr, len) }
+        }
+
+        fn metadata_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            unsafe { tango_metadata_json(ptr, len) }
+        }
     }
 
     impl Drop for SelfVTable {
@@ -278,6 +872,140 @@ pub mod ffi {
         }
     }
 
+    /// FFI implementation for the list of benchmarks owned by the caller
+    ///
+    /// Unlike [`SelfVTable`] is not using global state, so several instances can coexist in the same process.
+    pub(super) struct LocalVTable(RefCell<State>, Option<MeasurementSettings>);
+
+    impl LocalVTable {
+        pub(super) fn new(
+            benchmarks: Vec<Box<dyn MeasureTarget>>,
+            settings: Option<MeasurementSettings>,
+        ) -> Self {
+            Self(RefCell::new(State::new(benchmarks)), settings)
+        }
+    }
+
+    impl VTable for LocalVTable {
+        fn init(&self) {}
+
+        fn count(&self) -> usize {
+            self.0.borrow().benchmarks.len()
+        }
+
+        fn select(&self, func_idx: usize) {
+            let mut state = self.0.borrow_mut();
+            state.selected_function = func_idx.min(state.benchmarks.len() - 1);
+        }
+
+        fn get_test_name(&self, ptr: *mut *const c_char, len: *mut usize) {
+            let state = self.0.borrow();
+            let n = state.selected().name();
+            unsafe {
+                *ptr = n.as_ptr() as _;
+                *len = n.len();
+            }
+        }
+
+        fn run(&self, iterations: usize) -> u64 {
+            self.0
+                .borrow_mut()
+                .selected_mut()
+                .measure_value(iterations)
+                .value
+        }
+
+        fn measure_detailed(&self, times: &mut [u64]) -> bool {
+            self.0.borrow_mut().measure_detailed(times)
+        }
+
+        fn estimate_iterations(&self, time_ms: u32) -> usize {
+            self.0
+                .borrow_mut()
+                .selected_mut()
+                .estimate_iterations(time_ms)
+        }
+
+        fn next_haystack(&self) -> bool {
+            self.0.borrow_mut().selected_mut().next_haystack()
+        }
+
+        fn mutate_haystack(&self) {
+            self.0.borrow_mut().selected_mut().mutate_haystack()
+        }
+
+        fn take_error(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            let mut state = self.0.borrow_mut();
+            let Some(error) = state.take_error() else {
+                return false;
+            };
+            unsafe {
+                *ptr = error.as_ptr() as _;
+                *len = error.len();
+            }
+            true
+        }
+
+        fn prepare(&self) {
+            self.0.borrow_mut().selected_mut().prepare()
+        }
+
+        fn teardown(&self) {
+            self.0.borrow_mut().selected_mut().teardown()
+        }
+
+        fn sync(&self, seed: u64) {
+            self.0.borrow_mut().selected_mut().sync(seed)
+        }
+
+        fn set_includes_setup(&self, include: bool) -> bool {
+            self.0
+                .borrow_mut()
+                .selected_mut()
+                .set_includes_setup(include);
+            true
+        }
+
+        fn settings_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            let Some(settings) = &self.1 else {
+                return false;
+            };
+            let mut state = self.0.borrow_mut();
+            let json = state.settings_json(settings);
+            unsafe {
+                *ptr = json.as_ptr() as _;
+                *len = json.len();
+            }
+            true
+        }
+
+        fn set_settings_json(&self, json: &str) -> bool {
+            self.0.borrow_mut().set_settings_json(json.as_bytes())
+        }
+
+        fn verify(&self, samples: usize, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            let mut state = self.0.borrow_mut();
+            let Some(json) = state.verification_json(samples) else {
+                return false;
+            };
+            unsafe {
+                *ptr = json.as_ptr() as _;
+                *len = json.len();
+            }
+            true
+        }
+
+        fn metadata_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            let mut state = self.0.borrow_mut();
+            let json = state.metadata_json();
+            unsafe {
+                *ptr = json.as_ptr() as _;
+                *len = json.len();
+            }
+            true
+        }
+    }
+
     pub(super) struct LibraryVTable<'l> {
         init_fn: Symbol<'l, InitFn>,
         count_fn: Symbol<'l, CountFn>,
@@ -288,21 +1016,61 @@ pub mod ffi {
         next_haystack_fn: Symbol<'l, NextHaystackFn>,
         sync_fn: Symbol<'l, SyncFn>,
         free_fn: Symbol<'l, FreeFn>,
+
+        /// Optional, exported only by the executables using `tango_main!()`
+        settings_json_fn: Option<Symbol<'l, SettingsJsonFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        set_settings_json_fn: Option<Symbol<'l, SetSettingsJsonFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        verify_fn: Option<Symbol<'l, VerifyFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        set_includes_setup_fn: Option<Symbol<'l, SetIncludesSetupFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        measure_detailed_fn: Option<Symbol<'l, MeasureDetailedFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        metadata_json_fn: Option<Symbol<'l, MetadataJsonFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        mutate_haystack_fn: Option<Symbol<'l, MutateHaystackFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        take_error_fn: Option<Symbol<'l, TakeErrorFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        prepare_fn: Option<Symbol<'l, PrepareFn>>,
+
+        /// Optional, not exported by the libraries built with older versions of tango
+        teardown_fn: Option<Symbol<'l, TeardownFn>>,
     }
 
     impl<'l> LibraryVTable<'l> {
         pub(super) fn new(library: &'l Library) -> Result<Self, Error> {
             unsafe {
                 Ok(Self {
-                    init_fn: lookup_symbol(library, "tango_init")?,
-                    count_fn: lookup_symbol(library, "tango_count")?,
-                    select_fn: lookup_symbol(library, "tango_select")?,
-                    get_test_name_fn: lookup_symbol(library, "tango_get_test_name")?,
-                    run_fn: lookup_symbol(library, "tango_run")?,
-                    estimate_iterations_fn: lookup_symbol(library, "tango_estimate_iterations")?,
-                    next_haystack_fn: lookup_symbol(library, "tango_next_haystack")?,
-                    sync_fn: lookup_symbol(library, "tango_sync")?,
-                    free_fn: lookup_symbol(library, "tango_free")?,
+                    init_fn: lookup_entry_point(library, "tango_init")?,
+                    count_fn: lookup_required(library, "tango_count")?,
+                    select_fn: lookup_required(library, "tango_select")?,
+                    get_test_name_fn: lookup_required(library, "tango_get_test_name")?,
+                    run_fn: lookup_required(library, "tango_run")?,
+                    estimate_iterations_fn: lookup_required(library, "tango_estimate_iterations")?,
+                    next_haystack_fn: lookup_required(library, "tango_next_haystack")?,
+                    sync_fn: lookup_required(library, "tango_sync")?,
+                    free_fn: lookup_required(library, "tango_free")?,
+                    settings_json_fn: lookup_symbol(library, "tango_settings_json").ok(),
+                    set_settings_json_fn: lookup_symbol(library, "tango_set_settings_json").ok(),
+                    verify_fn: lookup_symbol(library, "tango_verify").ok(),
+                    set_includes_setup_fn: lookup_symbol(library, "tango_set_includes_setup").ok(),
+                    measure_detailed_fn: lookup_symbol(library, "tango_measure_detailed").ok(),
+                    metadata_json_fn: lookup_symbol(library, "tango_metadata_json").ok(),
+                    mutate_haystack_fn: lookup_symbol(library, "tango_mutate_haystack").ok(),
+                    take_error_fn: lookup_symbol(library, "tango_take_error").ok(),
+                    prepare_fn: lookup_symbol(library, "tango_prepare").ok(),
+                    teardown_fn: lookup_symbol(library, "tango_teardown").ok(),
                 })
             }
         }
@@ -329,6 +1097,15 @@ pub mod ffi {
             unsafe { (self.run_fn)(iterations) }
         }
 
+        fn measure_detailed(&self, times: &mut [u64]) -> bool {
+            match &self.measure_detailed_fn {
+                Some(measure_detailed_fn) => unsafe {
+                    measure_detailed_fn(times.len(), times.as_mut_ptr())
+                },
+                None => false,
+            }
+        }
+
         fn estimate_iterations(&self, time_ms: u32) -> usize {
             unsafe { (self.estimate_iterations_fn)(time_ms) }
         }
@@ -337,9 +1114,78 @@ pub mod ffi {
             unsafe { (self.next_haystack_fn)() }
         }
 
+        fn mutate_haystack(&self) {
+            if let Some(mutate_haystack_fn) = &self.mutate_haystack_fn {
+                unsafe { mutate_haystack_fn() }
+            }
+        }
+
+        fn take_error(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            if let Some(take_error_fn) = &self.take_error_fn {
+                unsafe { take_error_fn(ptr, len) }
+            } else {
+                false
+            }
+        }
+
+        fn prepare(&self) {
+            if let Some(prepare_fn) = &self.prepare_fn {
+                unsafe { prepare_fn() }
+            }
+        }
+
+        fn teardown(&self) {
+            if let Some(teardown_fn) = &self.teardown_fn {
+                unsafe { teardown_fn() }
+            }
+        }
+
         fn sync(&self, seed: u64) {
             unsafe { (self.sync_fn)(seed) }
         }
+
+        fn set_includes_setup(&self, include: bool) -> bool {
+            match &self.set_includes_setup_fn {
+                Some(set_includes_setup_fn) => {
+                    unsafe { set_includes_setup_fn(include) };
+                    true
+                }
+                None => false,
+            }
+        }
+
+        fn settings_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            if let Some(settings_json_fn) = &self.settings_json_fn {
+                unsafe { settings_json_fn(ptr, len) };
+                true
+            } else {
+                false
+            }
+        }
+
+        fn set_settings_json(&self, json: &str) -> bool {
+            if let Some(set_settings_json_fn) = &self.set_settings_json_fn {
+                unsafe { set_settings_json_fn(json.as_ptr() as _, json.len()) }
+            } else {
+                false
+            }
+        }
+
+        fn verify(&self, samples: usize, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            if let Some(verify_fn) = &self.verify_fn {
+                unsafe { verify_fn(samples, ptr, len) }
+            } else {
+                false
+            }
+        }
+
+        fn metadata_json(&self, ptr: *mut *const c_char, len: *mut usize) -> bool {
+            if let Some(metadata_json_fn) = &self.metadata_json_fn {
+                unsafe { metadata_json_fn(ptr, len) }
+            } else {
+                false
+            }
+        }
     }
 
     impl<'l> Drop for LibraryVTable<'l> {
@@ -348,6 +1194,26 @@ pub mod ffi {
         }
     }
 
+    /// Same as [`lookup_symbol()`], but for the symbol telling a tango library from any other one
+    unsafe fn lookup_entry_point<'l, T>(
+        library: &'l Library,
+        name: &'static str,
+    ) -> Result<Symbol<'l, T>, Error> {
+        library
+            .get(name.as_bytes())
+            .map_err(|e| Error::MissingEntryPoint(name, e))
+    }
+
+    /// Same as [`lookup_symbol()`], but for the symbols every supported version of tango exports
+    unsafe fn lookup_required<'l, T>(
+        library: &'l Library,
+        name: &'static str,
+    ) -> Result<Symbol<'l, T>, Error> {
+        library
+            .get(name.as_bytes())
+            .map_err(|e| Error::IncompatibleLibrary(name, e))
+    }
+
     unsafe fn lookup_symbol<'l, T>(
         library: &'l Library,
         name: &'static str,
@@ -0,0 +1,127 @@
+//! Resources shared by the iterations of IO benchmarks
+//!
+//! Fixtures are registered using [`BenchmarkBuilder::with_fixture()`][crate::BenchmarkBuilder::with_fixture()]. The harness
+//! creates a fixture before the test starts and drops it after the test finishes, so cleanup is done by the
+//! [`Drop`] implementation of the fixture. When comparing, baseline and candidate are loaded in the same process,
+//! so the fixtures should not depend on any fixed path: [`TempDirFixture`] appends a random suffix to its name.
+
+use rand::random;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Temporary directory removed together with all its contents when dropped
+///
+/// Drop is also performed while unwinding, so the directory doesn't outlive a panicking benchmark.
+#[derive(Debug)]
+pub struct TempDirFixture {
+    path: PathBuf,
+}
+
+impl TempDirFixture {
+    /// Creates a unique directory in `tango/fixtures` of the cargo target directory
+    ///
+    /// # Panics
+    ///
+    /// Panics if the directory can not be created.
+    pub fn create(prefix: &str) -> Self {
+        let parent = crate::target_dir().join("tango").join("fixtures");
+        Self::create_in(&parent, prefix).unwrap_or_else(|e| {
+            panic!(
+                "Unable to create fixture directory in {}: {}",
+                parent.display(),
+                e
+            )
+        })
+    }
+
+    /// Creates a unique directory in a given directory
+    ///
+    /// Name of the directory is made of the prefix, the id of the process and a random number.
+    pub fn create_in(parent: &Path, prefix: &str) -> io::Result<Self> {
+        fs::create_dir_all(parent)?;
+        loop {
+            let path = parent.join(format!(
+                "{}-{}-{:016x}",
+                prefix,
+                process::id(),
+                random::<u64>()
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDirFixture {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDirFixture {
+    fn drop(&mut self) {
+        // Errors can't be reported from drop and a leftover directory is not worth a panic
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, panic};
+
+    fn parent(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("tango-fixture-{}-{}", name, process::id()))
+    }
+
+    #[test]
+    fn check_temp_dir_removed_on_drop() {
+        let parent = parent("drop");
+        let fixture = TempDirFixture::create_in(&parent, "io").unwrap();
+        let path = fixture.path().to_path_buf();
+        fs::write(path.join("data"), b"data").unwrap();
+        assert!(path.is_dir());
+
+        drop(fixture);
+        assert!(!path.exists());
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    #[test]
+    fn check_temp_dir_removed_on_panic() {
+        let parent = parent("panic");
+        let mut path = None;
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let fixture = TempDirFixture::create_in(&parent, "io").unwrap();
+            path = Some(fixture.path().to_path_buf());
+            fs::write(fixture.path().join("data"), b"data").unwrap();
+            panic!("benchmark failed");
+        }));
+
+        assert!(result.is_err());
+        assert!(!path.unwrap().exists());
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    #[test]
+    fn check_temp_dirs_are_unique() {
+        let parent = parent("unique");
+        let a = TempDirFixture::create_in(&parent, "io").unwrap();
+        let b = TempDirFixture::create_in(&parent, "io").unwrap();
+        assert_ne!(a.path(), b.path());
+        assert!(a.path().starts_with(&parent));
+
+        drop((a, b));
+        fs::remove_dir_all(parent).unwrap();
+    }
+}
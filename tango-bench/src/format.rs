@@ -0,0 +1,779 @@
+//! Human readable formatting of the values used in reports
+//!
+//! Output doesn't depend on the system locale: `.` is always used as a decimal separator and `,` as a thousands
+//! separator. All formatters respect width and alignment flags (eg. `{:>10}`).
+
+use crate::{stats::ConfidenceInterval, ThroughputUnit, Unit};
+use std::fmt;
+
+/// Unit time values are displayed in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TimeUnit {
+    /// The most appropriate unit is chosen for each value
+    #[default]
+    Auto,
+    Ns,
+    Us,
+    Ms,
+}
+
+/// Formats time given in nanoseconds
+#[derive(Clone, Copy, Debug)]
+pub struct HumanTime {
+    ns: f64,
+    unit: TimeUnit,
+}
+
+impl HumanTime {
+    pub fn new(ns: f64) -> Self {
+        Self {
+            ns,
+            unit: TimeUnit::Auto,
+        }
+    }
+
+    /// Displays the value in a given unit instead of choosing one automatically
+    pub fn with_unit(self, unit: TimeUnit) -> Self {
+        Self { unit, ..self }
+    }
+}
+
+impl fmt::Display for HumanTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const USEC: f64 = 1_000.;
+        const MSEC: f64 = USEC * 1_000.;
+        const SEC: f64 = MSEC * 1_000.;
+
+        let ns = self.ns;
+        match self.unit {
+            TimeUnit::Ns => f.pad(&format!("{:.1} ns", ns)),
+            TimeUnit::Us => f.pad(&format!("{:.1} us", ns / USEC)),
+            TimeUnit::Ms => f.pad(&format!("{:.1} ms", ns / MSEC)),
+            TimeUnit::Auto if ns.abs() > SEC => f.pad(&format!("{:.1} s", ns / SEC)),
+            TimeUnit::Auto if ns.abs() > MSEC => f.pad(&format!("{:.1} ms", ns / MSEC)),
+            TimeUnit::Auto if ns.abs() > USEC => f.pad(&format!("{:.1} us", ns / USEC)),
+            TimeUnit::Auto if ns == 0. => f.pad("0 ns"),
+            TimeUnit::Auto => f.pad(&format!("{:.1} ns", ns)),
+        }
+    }
+}
+
+/// Formats size given in bytes using binary prefixes
+#[derive(Clone, Copy, Debug)]
+pub struct HumanBytes(pub f64);
+
+impl fmt::Display for HumanBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const KB: f64 = 1024.;
+        const MB: f64 = KB * 1024.;
+        const GB: f64 = MB * 1024.;
+
+        if self.0.abs() > GB {
+            f.pad(&format!("{:.1} GiB", self.0 / GB))
+        } else if self.0.abs() > MB {
+            f.pad(&format!("{:.1} MiB", self.0 / MB))
+        } else if self.0.abs() > KB {
+            f.pad(&format!("{:.1} KiB", self.0 / KB))
+        } else {
+            f.pad(&format!("{:.1} B", self.0))
+        }
+    }
+}
+
+/// Formats energy given in microjoules using SI prefixes
+#[derive(Clone, Copy, Debug)]
+pub struct HumanEnergy(pub f64);
+
+impl fmt::Display for HumanEnergy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uj = self.0.abs();
+        if uj >= 1e6 {
+            f.pad(&format!("{:.1} J", self.0 / 1e6))
+        } else if uj >= 1e3 {
+            f.pad(&format!("{:.1} mJ", self.0 / 1e3))
+        } else if uj >= 1. || uj == 0. {
+            f.pad(&format!("{:.1} uJ", self.0))
+        } else {
+            f.pad(&format!("{:.1} nJ", self.0 * 1e3))
+        }
+    }
+}
+
+/// Formats the amount of data processed per second: bytes with binary prefixes, elements with SI prefixes
+#[derive(Clone, Copy, Debug)]
+pub struct HumanThroughput {
+    pub unit: ThroughputUnit,
+    pub per_second: f64,
+}
+
+impl fmt::Display for HumanThroughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const PREFIXES: [(f64, &str); 3] = [(1e9, "G"), (1e6, "M"), (1e3, "K")];
+
+        let value = self.per_second;
+        match self.unit {
+            ThroughputUnit::Bytes => f.pad(&format!("{}/s", HumanBytes(value))),
+            ThroughputUnit::Elements => {
+                for (scale, prefix) in PREFIXES {
+                    if value.abs() >= scale {
+                        return f.pad(&format!("{:.1} {}elem/s", value / scale, prefix));
+                    }
+                }
+                f.pad(&format!("{:.1} elem/s", value))
+            }
+        }
+    }
+}
+
+/// Formats value according to the unit it is measured in
+#[derive(Clone, Copy, Debug)]
+pub struct HumanValue {
+    unit: Unit,
+    value: f64,
+    time_unit: TimeUnit,
+}
+
+impl HumanValue {
+    pub fn new(unit: Unit, value: f64) -> Self {
+        Self {
+            unit,
+            value,
+            time_unit: TimeUnit::Auto,
+        }
+    }
+
+    /// Unit used for time values (see [`HumanTime::with_unit()`])
+    pub fn with_time_unit(self, time_unit: TimeUnit) -> Self {
+        Self { time_unit, ..self }
+    }
+}
+
+impl fmt::Display for HumanValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            Unit::Nanoseconds => HumanTime::new(self.value).with_unit(self.time_unit).fmt(f),
+            Unit::Bytes => HumanBytes(self.value).fmt(f),
+            Unit::Count => f.pad(&format!("{:.1}", self.value)),
+            Unit::Microjoules => HumanEnergy(self.value).fmt(f),
+        }
+    }
+}
+
+/// Formats integer counts either with thousands separators (`12,345`) or in a short form (`12.3k`)
+#[derive(Clone, Copy, Debug)]
+pub struct HumanCount {
+    value: u64,
+    short: bool,
+}
+
+impl HumanCount {
+    pub fn new(value: impl Into<u64>) -> Self {
+        Self {
+            value: value.into(),
+            short: false,
+        }
+    }
+
+    pub fn short(self) -> Self {
+        Self {
+            short: true,
+            ..self
+        }
+    }
+}
+
+impl From<usize> for HumanCount {
+    fn from(value: usize) -> Self {
+        Self::new(value as u64)
+    }
+}
+
+impl fmt::Display for HumanCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SUFFIXES: [(u64, &str); 3] = [(1_000_000_000, "G"), (1_000_000, "M"), (1_000, "k")];
+
+        if self.short {
+            for (scale, suffix) in SUFFIXES {
+                if self.value >= scale {
+                    return f.pad(&format!(
+                        "{:.1}{}",
+                        self.value as f64 / scale as f64,
+                        suffix
+                    ));
+                }
+            }
+            f.pad(&self.value.to_string())
+        } else {
+            let digits = self.value.to_string();
+            let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                    result.push(',');
+                }
+                result.push(digit);
+            }
+            f.pad(&result)
+        }
+    }
+}
+
+/// Formats percentage with an explicit sign
+///
+/// Precision adapts to the magnitude, so the number of significant digits stays approximately the same:
+/// `+1.23%`, `-12.3%`, `+123%`.
+#[derive(Clone, Copy, Debug)]
+pub struct SignedPercent(pub f64);
+
+impl fmt::Display for SignedPercent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = match self.0.abs() {
+            v if v >= 100. => 0,
+            v if v >= 10. => 1,
+            _ => 2,
+        };
+        f.pad(&format!("{:+.*}%", precision, self.0))
+    }
+}
+
+/// Formats relative difference along with its confidence interval: `+1.23% [+0.50%, +1.96%]`
+#[derive(Clone, Copy, Debug)]
+pub struct Delta {
+    pub pct: f64,
+    pub ci: ConfidenceInterval,
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!(
+            "{} [{}, {}]",
+            SignedPercent(self.pct),
+            SignedPercent(self.ci.lower),
+            SignedPercent(self.ci.upper)
+        ))
+    }
+}
+
+/// Distribution of the values over equal-width buckets of a given range
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    counts: Vec<usize>,
+    min: f64,
+    max: f64,
+    mean: f64,
+    median: f64,
+}
+
+impl Histogram {
+    /// Block characters of the bars in eighths of a row
+    const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    /// Block characters of the horizontal bars in eighths of a column
+    const HORIZONTAL_BLOCKS: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+    /// Values outside of `[min, max]` are counted in the first or the last bucket. Returns `None` if there are
+    /// no values
+    pub fn new(values: &[f64], min: f64, max: f64, buckets: usize) -> Option<Self> {
+        if values.is_empty() || buckets == 0 {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.
+        } else {
+            sorted[mid]
+        };
+        let mut histogram = Self {
+            counts: vec![0; buckets],
+            min,
+            max,
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median,
+        };
+        for &value in values {
+            let bucket = histogram.bucket_of(value);
+            histogram.counts[bucket] += 1;
+        }
+        Some(histogram)
+    }
+
+    /// Histogram over the range of the values. Returns `None` if there are no values
+    pub fn spanning(values: &[f64], buckets: usize) -> Option<Self> {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Self::new(values, min, max, buckets)
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Lower and upper edges of a given bucket
+    pub fn bucket_range(&self, bucket: usize) -> (f64, f64) {
+        let width = (self.max - self.min) / self.counts.len() as f64;
+        let edge = |i: usize| self.min + width * i as f64;
+        (edge(bucket), edge(bucket + 1))
+    }
+
+    /// Bucket a given value falls into
+    pub fn bucket_of(&self, value: f64) -> usize {
+        let buckets = self.counts.len();
+        if self.max <= self.min {
+            return 0;
+        }
+        let position = (value - self.min) / (self.max - self.min) * buckets as f64;
+        (position.max(0.) as usize).min(buckets - 1)
+    }
+
+    /// Bars of the histogram as rows of block characters (from the top to the bottom)
+    ///
+    /// Bars are scaled so the bucket with `max_count` values takes all the `height` rows.
+    pub fn bars(&self, height: usize, max_count: usize) -> Vec<String> {
+        let eighths = |count: usize| {
+            let eighths = count as f64 / max_count.max(1) as f64 * (height * 8) as f64;
+            // Non-empty buckets are always visible
+            (eighths.round() as usize).max((count > 0) as usize)
+        };
+        (0..height)
+            .rev()
+            .map(|row| {
+                self.counts
+                    .iter()
+                    .map(|&count| Self::BLOCKS[eighths(count).saturating_sub(row * 8).min(8)])
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Bars of the histogram laid horizontally, one per bucket
+    ///
+    /// Bars are scaled so the largest bucket takes all the `width` columns.
+    pub fn horizontal_bars(&self, width: usize) -> Vec<String> {
+        let max_count = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        self.counts
+            .iter()
+            .map(|&count| {
+                let eighths = count as f64 / max_count as f64 * (width * 8) as f64;
+                // Non-empty buckets are always visible
+                let eighths = (eighths.round() as usize).max((count > 0) as usize);
+                let mut bar = Self::BLOCKS[8].to_string().repeat(eighths / 8);
+                if !eighths.is_multiple_of(8) {
+                    bar.push(Self::HORIZONTAL_BLOCKS[eighths % 8]);
+                }
+                bar
+            })
+            .collect()
+    }
+
+    /// Positions of the mean (`^`) and the median (`m`) under the bars (`*` if they are in the same bucket)
+    pub fn markers(&self) -> String {
+        let (mean, median) = (self.bucket_of(self.mean), self.bucket_of(self.median));
+        let line = (0..self.counts.len()).map(|bucket| match (bucket == mean, bucket == median) {
+            (true, true) => '*',
+            (true, false) => '^',
+            (false, true) => 'm',
+            (false, false) => ' ',
+        });
+        line.collect::<String>().trim_end().to_string()
+    }
+}
+
+/// Histograms of baseline and candidate values over their combined range
+///
+/// Both histograms share the scale of x and y axes, so the bars of the same bucket are directly comparable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairedHistogram {
+    pub baseline: Histogram,
+    pub candidate: Histogram,
+}
+
+impl PairedHistogram {
+    /// Returns `None` if there are no values
+    pub fn new(baseline: &[f64], candidate: &[f64], buckets: usize) -> Option<Self> {
+        let values = baseline.iter().chain(candidate);
+        let min = values.clone().copied().fold(f64::INFINITY, f64::min);
+        let max = values.copied().fold(f64::NEG_INFINITY, f64::max);
+        Some(Self {
+            baseline: Histogram::new(baseline, min, max, buckets)?,
+            candidate: Histogram::new(candidate, min, max, buckets)?,
+        })
+    }
+
+    /// Range of the x axis
+    pub fn range(&self) -> (f64, f64) {
+        (self.baseline.min, self.baseline.max)
+    }
+
+    /// Bars and markers of both histograms one under another, each line is preceded by its label
+    pub fn lines(&self, height: usize) -> Vec<(&'static str, String)> {
+        let max_count = self
+            .baseline
+            .counts
+            .iter()
+            .chain(&self.candidate.counts)
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let mut lines = vec![];
+        for (label, histogram) in [("baseline", &self.baseline), ("candidate", &self.candidate)] {
+            for (i, row) in histogram.bars(height, max_count).into_iter().enumerate() {
+                lines.push((if i == 0 { label } else { "" }, row));
+            }
+            lines.push(("", histogram.markers()));
+        }
+        lines
+    }
+}
+
+/// Scatter plot of paired samples rendered as a self-contained SVG image
+///
+/// Each sample is a dot with the baseline value on the x axis and the candidate value on the y axis. Both axes
+/// share the scale, so the dots above the dashed identity line are the samples where the candidate was slower.
+/// Samples filtered as outliers are drawn in red.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScatterPlot<'a> {
+    baseline: &'a [f64],
+    candidate: &'a [f64],
+    outliers: &'a [bool],
+    min: f64,
+    max: f64,
+}
+
+impl<'a> ScatterPlot<'a> {
+    /// Side of the image (in pixels)
+    const SIZE: f64 = 320.;
+
+    /// Space around the plot area taken by the title and the axis labels (in pixels)
+    const MARGIN: f64 = 40.;
+
+    /// Returns `None` if there are no values
+    ///
+    /// `outliers` marks the samples filtered as outliers and should be as long as the values.
+    pub fn new(baseline: &'a [f64], candidate: &'a [f64], outliers: &'a [bool]) -> Option<Self> {
+        let values = baseline.iter().chain(candidate).copied();
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.fold(f64::NEG_INFINITY, f64::max);
+        if !min.is_finite() || !max.is_finite() {
+            return None;
+        }
+        Some(Self {
+            baseline,
+            candidate,
+            outliers,
+            min,
+            max,
+        })
+    }
+
+    /// Renders the plot titled with a given test name, axis labels are formatted in a given unit
+    pub fn svg(&self, title: &str, unit: Unit) -> String {
+        let (size, margin) = (Self::SIZE, Self::MARGIN);
+        let (low, high) = (margin, size - margin);
+        let span = self.max - self.min;
+        let scale = |v: f64| {
+            if span > 0. {
+                (v - self.min) / span * (high - low)
+            } else {
+                (high - low) / 2.
+            }
+        };
+        let label = |v: f64| escape_xml(&HumanValue::new(unit, v).to_string());
+
+        let mut svg = String::new();
+        svg.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" \
+             viewBox=\"0 0 {size} {size}\" font-family=\"sans-serif\" font-size=\"10\">\n"
+        ));
+        svg.push_str(&format!("<title>{}</title>\n", escape_xml(title)));
+        svg.push_str(&format!(
+            "<rect x=\"{low}\" y=\"{low}\" width=\"{0}\" height=\"{0}\" fill=\"none\" stroke=\"#888\"/>\n",
+            high - low
+        ));
+        svg.push_str(&format!(
+            "<line x1=\"{low}\" y1=\"{high}\" x2=\"{high}\" y2=\"{low}\" stroke=\"#888\" stroke-dasharray=\"4 3\"/>\n"
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            size / 2.,
+            margin / 2.,
+            escape_xml(title)
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">baseline</text>\n",
+            size / 2.,
+            size - margin / 4.
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{0}\" y=\"{1}\" text-anchor=\"middle\" transform=\"rotate(-90 {0} {1})\">candidate</text>\n",
+            margin / 4. + 5.,
+            size / 2.
+        ));
+        let (min, max) = (label(self.min), label(self.max));
+        svg.push_str(&format!(
+            "<text x=\"{low}\" y=\"{0}\">{min}</text>\n<text x=\"{high}\" y=\"{0}\" text-anchor=\"end\">{max}</text>\n",
+            high + 12.
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{0}\" y=\"{high}\" text-anchor=\"end\">{min}</text>\n<text x=\"{0}\" y=\"{1}\" text-anchor=\"end\">{max}</text>\n",
+            low - 3.,
+            low + 8.
+        ));
+        for (outliers, fill) in [(false, "#1f77b4"), (true, "#d62728")] {
+            svg.push_str(&format!("<g fill=\"{}\" fill-opacity=\"0.7\">\n", fill));
+            let samples = self.baseline.iter().zip(self.candidate).zip(self.outliers);
+            for ((&b, &c), _) in samples.filter(|(_, &outlier)| outlier == outliers) {
+                svg.push_str(&format!(
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\"/>\n",
+                    low + scale(b),
+                    high - scale(c)
+                ));
+            }
+            svg.push_str("</g>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Escapes the characters having special meaning in XML text and attributes
+///
+/// Whitespace control characters are kept as character references, so they survive attribute normalization. Other
+/// control characters are not allowed in XML documents at all and are replaced with U+FFFD.
+pub(crate) fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push_str(&format!("&#{};", c as u32)),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_escape_xml() {
+        assert_eq!(escape_xml("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+        assert_eq!(escape_xml("a\tb\nc\u{1b}[0m"), "a&#9;b&#10;c\u{fffd}[0m");
+    }
+
+    #[test]
+    fn check_human_time() {
+        assert_eq!(format!("{}", HumanTime::new(0.1)), "0.1 ns");
+        assert_eq!(format!("{:>5}", HumanTime::new(0.)), " 0 ns");
+        assert_eq!(format!("{}", HumanTime::new(120.)), "120.0 ns");
+        assert_eq!(format!("{}", HumanTime::new(1200.)), "1.2 us");
+        assert_eq!(format!("{}", HumanTime::new(1200000.)), "1.2 ms");
+        assert_eq!(format!("{}", HumanTime::new(1200000000.)), "1.2 s");
+        assert_eq!(format!("{}", HumanTime::new(-1200000.)), "-1.2 ms");
+    }
+
+    #[test]
+    fn check_human_time_fixed_unit() {
+        let time = |ns: f64, unit| format!("{}", HumanTime::new(ns).with_unit(unit));
+        assert_eq!(time(1200000., TimeUnit::Ns), "1200000.0 ns");
+        assert_eq!(time(1200000., TimeUnit::Us), "1200.0 us");
+        assert_eq!(time(120., TimeUnit::Us), "0.1 us");
+        assert_eq!(time(1200000000., TimeUnit::Ms), "1200.0 ms");
+        assert_eq!(time(0., TimeUnit::Ms), "0.0 ms");
+    }
+
+    #[test]
+    fn check_human_value() {
+        let value = |unit, v| format!("{}", HumanValue::new(unit, v));
+        assert_eq!(value(Unit::Nanoseconds, 1200.), "1.2 us");
+        assert_eq!(value(Unit::Bytes, 100.), "100.0 B");
+        assert_eq!(value(Unit::Bytes, 1536.), "1.5 KiB");
+        assert_eq!(value(Unit::Bytes, -3145728.), "-3.0 MiB");
+        assert_eq!(format!("{:>6}", HumanValue::new(Unit::Count, 2.)), "   2.0");
+        assert_eq!(value(Unit::Microjoules, 0.25), "250.0 nJ");
+        assert_eq!(value(Unit::Microjoules, 12.), "12.0 uJ");
+        assert_eq!(value(Unit::Microjoules, -1500.), "-1.5 mJ");
+        assert_eq!(value(Unit::Microjoules, 2.5e6), "2.5 J");
+
+        // Fixed time unit is not applied to other units
+        let value = HumanValue::new(Unit::Bytes, 1536.).with_time_unit(TimeUnit::Ns);
+        assert_eq!(format!("{}", value), "1.5 KiB");
+        let value = HumanValue::new(Unit::Nanoseconds, 1536.).with_time_unit(TimeUnit::Ns);
+        assert_eq!(format!("{}", value), "1536.0 ns");
+    }
+
+    #[test]
+    fn check_human_throughput() {
+        let rate = |unit, per_second| format!("{}", HumanThroughput { unit, per_second });
+        assert_eq!(rate(ThroughputUnit::Bytes, 512.), "512.0 B/s");
+        assert_eq!(
+            rate(ThroughputUnit::Bytes, 1.5 * 1024. * 1024.),
+            "1.5 MiB/s"
+        );
+        assert_eq!(
+            rate(ThroughputUnit::Bytes, 3. * 1024. * 1024. * 1024.),
+            "3.0 GiB/s"
+        );
+        assert_eq!(rate(ThroughputUnit::Elements, 12.), "12.0 elem/s");
+        assert_eq!(rate(ThroughputUnit::Elements, 2_500.), "2.5 Kelem/s");
+        assert_eq!(rate(ThroughputUnit::Elements, 123e6), "123.0 Melem/s");
+        let padded = HumanThroughput {
+            unit: ThroughputUnit::Elements,
+            per_second: 1e9,
+        };
+        assert_eq!(format!("{:>12}", padded), " 1.0 Gelem/s");
+    }
+
+    #[test]
+    fn check_human_count() {
+        let count = |v: u64| format!("{}", HumanCount::new(v));
+        assert_eq!(count(0), "0");
+        assert_eq!(count(999), "999");
+        assert_eq!(count(1_000), "1,000");
+        assert_eq!(count(12_345), "12,345");
+        assert_eq!(count(123_456_789), "123,456,789");
+
+        let short = |v: u64| format!("{}", HumanCount::new(v).short());
+        assert_eq!(short(999), "999");
+        assert_eq!(short(12_345), "12.3k");
+        assert_eq!(short(2_500_000), "2.5M");
+        assert_eq!(short(7_000_000_000), "7.0G");
+
+        assert_eq!(format!("{:>7}", HumanCount::from(1234_usize)), "  1,234");
+    }
+
+    #[test]
+    fn check_signed_percent() {
+        let pct = |v| format!("{}", SignedPercent(v));
+        assert_eq!(pct(0.), "+0.00%");
+        assert_eq!(pct(1.234), "+1.23%");
+        assert_eq!(pct(-12.345), "-12.3%");
+        assert_eq!(pct(123.45), "+123%");
+        assert_eq!(pct(-1860516.68), "-1860517%");
+        assert_eq!(format!("{:>8}", SignedPercent(1.)), "  +1.00%");
+    }
+
+    #[test]
+    fn check_delta() {
+        let delta = Delta {
+            pct: 1.234,
+            ci: ConfidenceInterval {
+                lower: 0.5,
+                upper: 1.96,
+            },
+        };
+        assert_eq!(format!("{}", delta), "+1.23% [+0.50%, +1.96%]");
+    }
+
+    #[test]
+    fn check_histogram_buckets() {
+        let histogram =
+            Histogram::new(&[0., 1., 2., 2.5, 9.99, 10., 12., -1.], 0., 10., 5).unwrap();
+        // Maximum and values outside of the range are counted in the edge buckets
+        assert_eq!(histogram.counts(), &[3, 2, 0, 0, 3]);
+        assert_eq!(Histogram::new(&[], 0., 10., 5), None);
+
+        // All the values are in the first bucket if the range is empty
+        let histogram = Histogram::new(&[3., 3.], 3., 3., 4).unwrap();
+        assert_eq!(histogram.counts(), &[2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn check_histogram_rendering() {
+        let values = [
+            1., 2., 2., 3., 3., 3., 3., 3., 3., 3., 3., 4., 4., 4., 4., 5., 9.,
+        ];
+        let histogram = Histogram::new(&values, 0., 10., 10).unwrap();
+        assert_eq!(histogram.bars(1, 8), vec![" ▁▂█▄▁   ▁"]);
+        assert_eq!(histogram.bars(2, 8), vec!["   █      ", " ▂▄██▂   ▂"]);
+        // Mean is 3.47, median is 3
+        assert_eq!(histogram.markers(), "   *");
+    }
+
+    #[test]
+    fn check_horizontal_histogram() {
+        // Two modes of the differences with nothing in between
+        let diffs = [-10., -10., -9., -9., -9., -8., 8., 9., 9., 10.];
+        let histogram = Histogram::spanning(&diffs, 5).unwrap();
+        assert_eq!(histogram.counts(), &[6, 0, 0, 0, 4]);
+        assert_eq!(histogram.bucket_range(0), (-10., -6.));
+        assert_eq!(histogram.bucket_range(4), (6., 10.));
+        assert_eq!(histogram.horizontal_bars(3), vec!["███", "", "", "", "██"]);
+
+        let histogram = Histogram::spanning(&[1., 2., 2., 2., 2., 2., 2., 2., 2., 3.], 3).unwrap();
+        assert_eq!(histogram.horizontal_bars(2), vec!["▎", "██", "▎"]);
+        assert_eq!(Histogram::spanning(&[], 5), None);
+    }
+
+    #[test]
+    fn check_paired_histogram() {
+        let baseline = [10., 10., 11., 11., 11., 12., 12., 13.];
+        let candidate = [10., 11., 15., 16., 16., 16., 17., 20.];
+        let histogram = PairedHistogram::new(&baseline, &candidate, 10).unwrap();
+        assert_eq!(histogram.range(), (10., 20.));
+        let art = histogram
+            .lines(2)
+            .into_iter()
+            .map(|(label, line)| format!("{:>9} │{}", label, line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let expected = [
+            " baseline │▃█▃       ",
+            "          │███▅      ",
+            "          │ *",
+            "candidate │      █   ",
+            "          │▅▅   ▅█▅ ▅",
+            "          │     ^m",
+        ];
+        assert_eq!(art, expected.join("\n"));
+        assert_eq!(PairedHistogram::new(&[], &[1.], 10), None);
+    }
+
+    #[test]
+    fn check_scatter_plot() {
+        let baseline = [10., 20., 30.];
+        let candidate = [12., 18., 50.];
+        let outliers = [false, false, true];
+        let plot = ScatterPlot::new(&baseline, &candidate, &outliers).unwrap();
+        let expected = [
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="320" height="320" viewBox="0 0 320 320" font-family="sans-serif" font-size="10">"##,
+            r##"<title>sort/&lt;u32&gt; &amp; co</title>"##,
+            r##"<rect x="40" y="40" width="240" height="240" fill="none" stroke="#888"/>"##,
+            r##"<line x1="40" y1="280" x2="280" y2="40" stroke="#888" stroke-dasharray="4 3"/>"##,
+            r##"<text x="160" y="20" text-anchor="middle">sort/&lt;u32&gt; &amp; co</text>"##,
+            r##"<text x="160" y="310" text-anchor="middle">baseline</text>"##,
+            r##"<text x="15" y="160" text-anchor="middle" transform="rotate(-90 15 160)">candidate</text>"##,
+            r##"<text x="40" y="292">10.0 ns</text>"##,
+            r##"<text x="280" y="292" text-anchor="end">50.0 ns</text>"##,
+            r##"<text x="37" y="280" text-anchor="end">10.0 ns</text>"##,
+            r##"<text x="37" y="48" text-anchor="end">50.0 ns</text>"##,
+            r##"<g fill="#1f77b4" fill-opacity="0.7">"##,
+            r##"<circle cx="40.0" cy="268.0" r="2"/>"##,
+            r##"<circle cx="100.0" cy="232.0" r="2"/>"##,
+            r##"</g>"##,
+            r##"<g fill="#d62728" fill-opacity="0.7">"##,
+            r##"<circle cx="160.0" cy="40.0" r="2"/>"##,
+            r##"</g>"##,
+            r##"</svg>"##,
+            "",
+        ];
+        assert_eq!(
+            plot.svg("sort/<u32> & co", Unit::Nanoseconds),
+            expected.join("\n")
+        );
+
+        // All the dots are in the middle if the values are the same
+        let plot = ScatterPlot::new(&[5.], &[5.], &[false]).unwrap();
+        assert!(plot
+            .svg("same", Unit::Nanoseconds)
+            .contains(r#"<circle cx="160.0" cy="160.0" r="2"/>"#));
+        assert_eq!(ScatterPlot::new(&[], &[], &[]), None);
+    }
+}
@@ -1,7 +1,25 @@
-use std::{any::type_name, marker::PhantomData};
+use std::{
+    any::type_name,
+    collections::{HashMap, HashSet},
+    env, fmt,
+    fs::File,
+    hash::Hash,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    mem,
+    path::{Path, PathBuf},
+    sync::mpsc::{sync_channel, Receiver},
+    thread::{self, JoinHandle},
+};
 
-use crate::Generator;
-use rand::{rngs::SmallRng, Fill, Rng, SeedableRng};
+use crate::{Error, Generator, HaystackSize};
+use rand::{
+    distributions::{Distribution, Standard},
+    rngs::SmallRng,
+    seq::SliceRandom,
+    Fill, Rng, SeedableRng,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct RandomVec<T>(SmallRng, usize, PhantomData<T>, String);
@@ -34,6 +52,10 @@ where
 
     fn next_needle(&mut self, _haystack: &Self::Haystack) -> Self::Needle {}
 
+    fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+        Some(HaystackSize::Bytes(mem::size_of_val(haystack.as_slice())))
+    }
+
     fn name(&self) -> &str {
         &self.3
     }
@@ -41,4 +63,1572 @@ where
     fn sync(&mut self, seed: u64) {
         self.0 = SmallRng::seed_from_u64(seed);
     }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.0 = rng;
+    }
+}
+
+/// Ring of shuffled indices into a haystack
+///
+/// Picking a random valid position in a haystack for every needle is either wasteful (when positions have to be
+/// enumerated first, like keys of a map) or produces repeated needles. `SampledIndex` shuffles the positions once
+/// per haystack, so [`SampledIndex::next()`] is O(1) and doesn't repeat a position until all the positions are
+/// returned. After that the ring is reshuffled, so the cost of shuffling is amortized over the needles.
+///
+/// The ring is supposed to be rebuilt in [`Generator::next_haystack()`] and used in [`Generator::next_needle()`]:
+///
+/// ```rust
+/// use rand::{rngs::SmallRng, Rng, SeedableRng};
+/// use tango_bench::{generators::SampledIndex, Generator};
+///
+/// struct RandomElement(SmallRng, SampledIndex);
+///
+/// impl Generator for RandomElement {
+///     type Haystack = Vec<u32>;
+///     type Needle = u32;
+///
+///     fn next_haystack(&mut self) -> Self::Haystack {
+///         let haystack = (0..1000).map(|_| self.0.gen()).collect::<Vec<_>>();
+///         self.1.rebuild(haystack.len(), &mut self.0);
+///         haystack
+///     }
+///
+///     fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+///         haystack[self.1.next(&mut self.0).unwrap()]
+///     }
+///
+///     fn sync(&mut self, seed: u64) {
+///         self.0 = SmallRng::seed_from_u64(seed);
+///     }
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct SampledIndex {
+    ring: Vec<usize>,
+    position: usize,
+}
+
+impl SampledIndex {
+    /// Shuffles positions of a haystack of a given length
+    pub fn rebuild(&mut self, len: usize, rng: &mut impl Rng) {
+        self.ring.clear();
+        self.ring.extend(0..len);
+        self.ring.shuffle(rng);
+        self.position = 0;
+    }
+
+    /// Next position in the ring or `None` if haystack is empty
+    pub fn next(&mut self, rng: &mut impl Rng) -> Option<usize> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        if self.position == self.ring.len() {
+            self.ring.shuffle(rng);
+            self.position = 0;
+        }
+        let idx = self.ring[self.position];
+        self.position += 1;
+        Some(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+/// Samples keys of map-like haystacks without repetition
+///
+/// Keys are copied from the haystack only once, when [`KeySampler::sample()`] is given a haystack different from
+/// the previous one, and then sampled using [`SampledIndex`]. Haystacks are told apart by the number of keys, the
+/// address and the value of the first one. Memory of a dropped haystack can be reused by the next one, so the
+/// address alone is not enough. Because the sampler tracks the haystack itself it works the same way
+/// when haystacks are generated by another instance of the generator (see [`PrefetchingGenerator`]).
+///
+/// ```rust
+/// use rand::{rngs::SmallRng, SeedableRng};
+/// use std::collections::HashMap;
+/// use tango_bench::generators::KeySampler;
+///
+/// let mut rng = SmallRng::seed_from_u64(0);
+/// let haystack = HashMap::from([(1, "a"), (2, "b")]);
+/// let mut sampler = KeySampler::default();
+/// let key = *sampler.sample(haystack.keys(), &mut rng).unwrap();
+/// assert!(haystack.contains_key(&key));
+/// ```
+#[derive(Clone)]
+pub struct KeySampler<K> {
+    keys: Vec<K>,
+    index: SampledIndex,
+    source: Option<(usize, usize)>,
+}
+
+impl<K> Default for KeySampler<K> {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            index: SampledIndex::default(),
+            source: None,
+        }
+    }
+}
+
+impl<K: Clone + PartialEq> KeySampler<K> {
+    /// Returns a random key of the haystack given its keys
+    ///
+    /// Only the first key is read if keys belong to the same haystack as on the previous call.
+    pub fn sample<'a>(
+        &mut self,
+        mut keys: impl ExactSizeIterator<Item = &'a K>,
+        rng: &mut impl Rng,
+    ) -> Option<&K>
+    where
+        K: 'a,
+    {
+        let len = keys.len();
+        let first = keys.next()?;
+        let source = (len, first as *const K as usize);
+        if self.source != Some(source) || self.keys.first() != Some(first) {
+            self.keys.clear();
+            self.keys.push(first.clone());
+            self.keys.extend(keys.cloned());
+            self.index.rebuild(self.keys.len(), rng);
+            self.source = Some(source);
+        }
+        self.index.next(rng).map(|idx| &self.keys[idx])
+    }
+
+    /// Number of keys in the last sampled haystack
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Number of consecutive draws of the keys already present after which [`RandomMap`] and [`RandomSet`] give up
+///
+/// It happens if the size is larger than the number of the distinct values of the key type (eg. 300 of `u8`).
+const MAX_DUPLICATE_DRAWS: usize = 10_000;
+
+/// Generates hash maps of a given size and needles for lookup
+///
+/// Needles are keys present in the map with a given probability (hit ratio) or random keys otherwise. Present keys
+/// are sampled using [`KeySampler`], so each key is looked up once before any of them repeats. If the key type
+/// doesn't have enough distinct values, maps are smaller than requested and the test fails (see
+/// [`Generator::take_error()`]).
+///
+/// Each call looks up a single needle, so the throughput is reported in lookups per second rather than in the size
+/// of the map.
+///
+/// ```rust
+/// use tango_bench::{generators::RandomMap, Generator};
+///
+/// let generator = RandomMap::<u64, u64>::new(1 << 20, 0.5);
+/// assert_eq!(generator.name(), "RandomMap{n=1048576,hit=50%}");
+/// ```
+#[derive(Clone)]
+pub struct RandomMap<K, V> {
+    rng: SmallRng,
+    size: usize,
+    hit_ratio: f64,
+    keys: KeySampler<K>,
+    name: String,
+    error: Option<String>,
+    _value: PhantomData<V>,
+}
+
+impl<K, V> RandomMap<K, V> {
+    /// Creates generator of maps with `size` entries, `hit_ratio` is a share of needles present in the map
+    pub fn new(size: usize, hit_ratio: f64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(42),
+            size,
+            hit_ratio: hit_ratio.clamp(0., 1.),
+            keys: KeySampler::default(),
+            name: collection_name("RandomMap", size, hit_ratio),
+            error: None,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Generator for RandomMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    Standard: Distribution<K> + Distribution<V>,
+{
+    type Haystack = HashMap<K, V>;
+    type Needle = K;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        let mut map = HashMap::with_capacity(self.size);
+        let mut duplicates = 0;
+        while map.len() < self.size && duplicates < MAX_DUPLICATE_DRAWS {
+            match map.insert(self.rng.gen(), self.rng.gen()) {
+                Some(_) => duplicates += 1,
+                None => duplicates = 0,
+            }
+        }
+        if map.len() < self.size {
+            self.error = Some(too_few_keys::<K>(self.size, map.len()));
+        }
+        map
+    }
+
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+        if self.rng.gen_bool(self.hit_ratio) {
+            if let Some(key) = self.keys.sample(haystack.keys(), &mut self.rng) {
+                return key.clone();
+            }
+        }
+        self.rng.gen()
+    }
+
+    fn haystack_size(&self, _haystack: &Self::Haystack) -> Option<HaystackSize> {
+        Some(HaystackSize::Elements(1))
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.rng = rng;
+    }
+}
+
+/// Generates hash sets of a given size and needles for lookup
+///
+/// The same as [`RandomMap`], but for sets (including the throughput reported in lookups per second).
+///
+/// ```rust
+/// use tango_bench::{generators::RandomSet, Generator};
+///
+/// let generator = RandomSet::<u32>::new(1000, 0.9);
+/// assert_eq!(generator.name(), "RandomSet{n=1000,hit=90%}");
+/// ```
+#[derive(Clone)]
+pub struct RandomSet<T> {
+    rng: SmallRng,
+    size: usize,
+    hit_ratio: f64,
+    values: KeySampler<T>,
+    name: String,
+    error: Option<String>,
+}
+
+impl<T> RandomSet<T> {
+    /// Creates generator of sets with `size` values, `hit_ratio` is a share of needles present in the set
+    pub fn new(size: usize, hit_ratio: f64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(42),
+            size,
+            hit_ratio: hit_ratio.clamp(0., 1.),
+            values: KeySampler::default(),
+            name: collection_name("RandomSet", size, hit_ratio),
+            error: None,
+        }
+    }
+}
+
+impl<T> Generator for RandomSet<T>
+where
+    T: Eq + Hash + Clone,
+    Standard: Distribution<T>,
+{
+    type Haystack = HashSet<T>;
+    type Needle = T;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        let mut set = HashSet::with_capacity(self.size);
+        let mut duplicates = 0;
+        while set.len() < self.size && duplicates < MAX_DUPLICATE_DRAWS {
+            if set.insert(self.rng.gen()) {
+                duplicates = 0;
+            } else {
+                duplicates += 1;
+            }
+        }
+        if set.len() < self.size {
+            self.error = Some(too_few_keys::<T>(self.size, set.len()));
+        }
+        set
+    }
+
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+        if self.rng.gen_bool(self.hit_ratio) {
+            if let Some(value) = self.values.sample(haystack.iter(), &mut self.rng) {
+                return value.clone();
+            }
+        }
+        self.rng.gen()
+    }
+
+    fn haystack_size(&self, _haystack: &Self::Haystack) -> Option<HaystackSize> {
+        Some(HaystackSize::Elements(1))
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.rng = rng;
+    }
+}
+
+fn too_few_keys<K>(size: usize, generated: usize) -> String {
+    format!(
+        "Unable to generate {} distinct values of {}, only {} are generated",
+        size,
+        type_name::<K>(),
+        generated
+    )
+}
+
+fn collection_name(kind: &str, size: usize, hit_ratio: f64) -> String {
+    format!(
+        "{}{{n={},hit={}%}}",
+        kind,
+        size,
+        (hit_ratio.clamp(0., 1.) * 100.).round()
+    )
+}
+
+/// Single entry of a generator recording
+///
+/// Recording is a file with one JSON-encoded entry per line. The first entry is always the name of the recorded
+/// generator followed by haystacks and needles in the order they were produced. Recordings of the generators
+/// mutating haystacks (see [`Generator::mutates_haystack()`]) have `Mutating` entry after the name and the mutated
+/// haystack after each mutation.
+#[derive(Serialize, Deserialize)]
+enum Record<S, H, N> {
+    Name(S),
+    Mutating,
+    Haystack(H),
+    Needle(N),
+    Mutation(H),
+}
+
+/// Environment variable with the recording given to `--generator-replay`
+///
+/// Benchmarks are built by the executables being compared, so the harness passes the recording to both of them
+/// through the environment.
+pub(crate) const REPLAY_VAR: &str = "TANGO_GENERATOR_REPLAY";
+
+/// Environment variable set by `--loop-replay` (see [`Replayer::looping()`])
+pub(crate) const LOOP_REPLAY_VAR: &str = "TANGO_LOOP_REPLAY";
+
+/// Generator recording every haystack and needle produced by the wrapped generator to a file
+///
+/// Recorded inputs can be replayed later using [`Replayer`]. The path of the recording is reported in `--csv`
+/// and `--ndjson` results. Errors writing the recording fail the test (see [`Generator::take_error()`]), the
+/// inputs are still produced, but not recorded anymore.
+///
+/// If the harness is run with `--generator-replay <PATH>` and the recording given was made by a generator with the
+/// same name, the recording is replayed instead (see [`Replayer`]) and nothing is written.
+///
+/// ```rust,no_run
+/// use tango_bench::generators::{RandomVec, Recorder};
+///
+/// let generator = Recorder::wrap(RandomVec::<u32>::new(100), "inputs.jsonl").unwrap();
+/// ```
+pub struct Recorder<G: Generator> {
+    inner: G,
+
+    /// Recording is created once the first input is produced, so the harness process only parsing the command
+    /// line doesn't truncate the recording given to `--generator-replay`
+    writer: Option<BufWriter<File>>,
+    path: PathBuf,
+
+    /// Recording given to `--generator-replay` replayed instead of the inputs of the wrapped generator
+    replay: Option<Replayer<G::Haystack, G::Needle>>,
+
+    /// Error writing the recording not taken by the harness yet
+    error: Option<io::Error>,
+
+    /// Recording is incomplete after an error, so nothing is written anymore
+    failed: bool,
+}
+
+impl<G> Recorder<G>
+where
+    G: Generator,
+    G::Haystack: DeserializeOwned,
+    G::Needle: DeserializeOwned,
+{
+    /// Records the inputs of the generator to a given file or replays the recording given to `--generator-replay`
+    pub fn wrap(generator: G, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let replay = env::var_os(REPLAY_VAR).map(PathBuf::from);
+        Self::with_replay(generator, path, replay.as_deref())
+    }
+
+    fn with_replay(
+        generator: G,
+        path: impl AsRef<Path>,
+        replay: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(replay) = replay {
+            if recorded_name(replay)? == generator.name() {
+                let replayer = Replayer::open(replay)?;
+                return Ok(Self {
+                    inner: generator,
+                    writer: None,
+                    path: replay.to_path_buf(),
+                    replay: Some(replayer),
+                    error: None,
+                    failed: false,
+                });
+            }
+        }
+        Ok(Self {
+            inner: generator,
+            writer: None,
+            path,
+            replay: None,
+            error: None,
+            failed: false,
+        })
+    }
+}
+
+impl<G: Generator> Recorder<G> {
+    /// Path of the file inputs are recorded to (or replayed from)
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_inner(self) -> G {
+        self.inner
+    }
+
+    fn write<H: Serialize, N: Serialize>(&mut self, record: &Record<&str, H, N>) {
+        let written = self
+            .writer()
+            .and_then(|writer| write_record(writer, record));
+        self.check(written);
+    }
+
+    /// Creates the recording starting with the name of the generator if it is not created yet
+    fn writer(&mut self) -> io::Result<&mut BufWriter<File>> {
+        if self.writer.is_none() {
+            let mut writer = BufWriter::new(File::create(&self.path)?);
+            write_record(&mut writer, &Record::<_, (), ()>::Name(self.inner.name()))?;
+            if self.inner.mutates_haystack() {
+                write_record(&mut writer, &Record::<&str, (), ()>::Mutating)?;
+            }
+            self.writer = Some(writer);
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
+
+    fn flush(&mut self) {
+        if let Some(writer) = &mut self.writer {
+            let flushed = writer.flush();
+            self.check(flushed);
+        }
+    }
+
+    fn check(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            if !self.failed {
+                self.error = Some(e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+fn write_record<H: Serialize, N: Serialize>(
+    writer: &mut impl Write,
+    record: &Record<&str, H, N>,
+) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")
+}
+
+impl<G> Generator for Recorder<G>
+where
+    G: Generator,
+    G::Haystack: Serialize + DeserializeOwned,
+    G::Needle: Serialize + DeserializeOwned,
+{
+    type Haystack = G::Haystack;
+    type Needle = G::Needle;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        if let Some(replay) = &mut self.replay {
+            return replay.next_haystack();
+        }
+        // Needles of the previous haystack are complete at this point
+        self.flush();
+        let haystack = self.inner.next_haystack();
+        if !self.failed {
+            self.write(&Record::<_, _, ()>::Haystack(&haystack));
+        }
+        haystack
+    }
+
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+        if let Some(replay) = &mut self.replay {
+            return replay.next_needle(haystack);
+        }
+        let needle = self.inner.next_needle(haystack);
+        if !self.failed {
+            self.write(&Record::<_, (), _>::Needle(&needle));
+        }
+        needle
+    }
+
+    fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, sample_idx: usize) {
+        if let Some(replay) = &mut self.replay {
+            replay.mutate_haystack(haystack, sample_idx);
+            return;
+        }
+        self.inner.mutate_haystack(haystack, sample_idx);
+        if !self.failed {
+            self.write(&Record::<_, _, ()>::Mutation(&*haystack));
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        match &self.replay {
+            Some(replay) => replay.mutates_haystack(),
+            None => self.inner.mutates_haystack(),
+        }
+    }
+
+    fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+        self.inner.haystack_size(haystack)
+    }
+
+    fn recording(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        if let Some(error) = self.replay.as_mut().and_then(|r| r.take_error()) {
+            return Some(error);
+        }
+        let path = self.path.display();
+        self.error
+            .take()
+            .map(|e| format!("Unable to write recording {}: {}", path, e))
+            .or_else(|| self.inner.take_error())
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.flush();
+        self.inner.sync(seed)
+    }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.flush();
+        self.inner.sync_with(rng)
+    }
+}
+
+/// Kind of an input stored in a recording
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InputKind {
+    Haystack,
+    Needle,
+    Mutation,
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputKind::Haystack => "haystack",
+            InputKind::Needle => "needle",
+            InputKind::Mutation => "mutation",
+        };
+        f.write_str(name)
+    }
+}
+
+fn invalid_recording(reason: &str) -> Error {
+    Error::InvalidRecording(serde::de::Error::custom(reason))
+}
+
+/// Reads the name of the generator from the first line of a recording
+fn read_name(lines: &mut impl Iterator<Item = io::Result<String>>) -> Result<String, Error> {
+    let Some(line) = lines.next().transpose()? else {
+        return Err(invalid_recording("recording is empty"));
+    };
+    match serde_json::from_str(&line).map_err(Error::InvalidRecording)? {
+        Record::<String, (), ()>::Name(name) => Ok(name),
+        _ => Err(invalid_recording(
+            "recording doesn't start with the name of the generator",
+        )),
+    }
+}
+
+/// Name of the generator a recording was made by
+///
+/// Only the first line of the recording is read, so it can be checked before the inputs are (see
+/// [`Replayer::open()`]).
+pub(crate) fn recorded_name(path: &Path) -> Result<String, Error> {
+    read_name(&mut BufReader::new(File::open(path)?).lines())
+}
+
+/// Needle replayed from a recording without needles (eg. a recording of a generator with `()` needles which were
+/// never drawn)
+const NULL_NEEDLE: &str = r#"{"Needle":null}"#;
+
+/// Generator replaying inputs recorded by [`Recorder`] in the same order
+///
+/// Replayed generator has the same name as the recorded one. Syncing has no effect, inputs are always replayed in
+/// the order they were recorded. The whole recording is read and checked against the types of the inputs when
+/// it is opened, so the recording can't fail to be read while replaying.
+///
+/// If the recording is exhausted (unless [`Replayer::looping()`] is set) or the inputs are requested in the order
+/// different from the recorded one, the next input of the requested kind is replayed instead and the test fails
+/// (see [`Generator::take_error()`]).
+///
+/// Sizes of the haystacks are not recorded, so the throughput of the replayed tests is reported only if the size
+/// is given with [`Replayer::with_haystack_size()`].
+pub struct Replayer<H, N> {
+    path: PathBuf,
+    name: String,
+
+    /// Lines of the recording along with the kind of the input they hold
+    inputs: Vec<(InputKind, String)>,
+
+    /// Index of the next input to replay
+    next: usize,
+    looping: bool,
+
+    /// Recorded generator mutates haystacks
+    mutating: bool,
+
+    /// Recording has needles, otherwise `null` needles are replayed
+    needles: bool,
+
+    /// Error replaying the recording not taken by the harness yet
+    error: Option<String>,
+    haystack_size: Option<fn(&H) -> HaystackSize>,
+    _types: PhantomData<fn() -> (H, N)>,
+}
+
+impl<H: DeserializeOwned, N: DeserializeOwned> Replayer<H, N> {
+    /// Reads the recording
+    ///
+    /// Fails if the recording can't be read or any of its inputs is not of the expected type.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+        let name = read_name(&mut lines)?;
+
+        let mut mutating = false;
+        let mut inputs = vec![];
+        for (idx, line) in lines.enumerate() {
+            let line = line?;
+            let record: Record<String, H, N> =
+                serde_json::from_str(&line).map_err(Error::InvalidRecording)?;
+            let kind = match record {
+                Record::Mutating if idx == 0 => {
+                    mutating = true;
+                    continue;
+                }
+                Record::Haystack(_) => InputKind::Haystack,
+                Record::Needle(_) => InputKind::Needle,
+                Record::Mutation(_) if mutating => InputKind::Mutation,
+                _ => return Err(invalid_recording("unexpected entry in the recording")),
+            };
+            inputs.push((kind, line));
+        }
+
+        let has = |kind| inputs.iter().any(|(k, _)| *k == kind);
+        if !has(InputKind::Haystack) {
+            return Err(invalid_recording("recording has no haystacks"));
+        }
+        let needles = has(InputKind::Needle);
+        if !needles {
+            serde_json::from_str::<Record<String, H, N>>(NULL_NEEDLE)
+                .map_err(|_| invalid_recording("recording has no needles"))?;
+        }
+
+        Ok(Self {
+            path,
+            name,
+            inputs,
+            next: 0,
+            looping: env::var_os(LOOP_REPLAY_VAR).is_some(),
+            mutating,
+            needles,
+            error: None,
+            haystack_size: None,
+            _types: PhantomData,
+        })
+    }
+
+    /// Starts replaying the recording from the beginning once it is exhausted instead of failing the test
+    ///
+    /// Set for all the replayed recordings if the harness is run with `--loop-replay`.
+    pub fn looping(self) -> Self {
+        Self {
+            looping: true,
+            ..self
+        }
+    }
+
+    /// Reports the throughput of the replayed tests with the size of each haystack (see
+    /// [`Generator::haystack_size()`])
+    pub fn with_haystack_size(self, size: fn(&H) -> HaystackSize) -> Self {
+        Self {
+            haystack_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Finds the next input of a given kind reporting the inputs skipped on the way
+    ///
+    /// Returns `None` if the recording has no inputs of the kind.
+    fn next_input(&mut self, kind: InputKind) -> Option<Record<String, H, N>> {
+        let len = self.inputs.len();
+        let mut idx = self.next;
+        for _ in 0..=len {
+            if idx == len {
+                if !self.looping {
+                    self.fail(format!("exhausted while reading {}", kind));
+                }
+                idx = 0;
+            }
+            let (found, line) = &self.inputs[idx];
+            if *found == kind {
+                self.next = idx + 1;
+                // Inputs are checked to be of the expected types when the recording is opened
+                return serde_json::from_str(line).ok();
+            }
+            self.fail(format!("out of sync: expected {}, found {}", kind, found));
+            idx += 1;
+        }
+        None
+    }
+
+    fn fail(&mut self, reason: String) {
+        if self.error.is_none() {
+            self.error = Some(format!("Recording {} is {}", self.path.display(), reason));
+        }
+    }
+}
+
+impl<H, N> Generator for Replayer<H, N>
+where
+    H: DeserializeOwned,
+    N: DeserializeOwned,
+{
+    type Haystack = H;
+    type Needle = N;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        match self.next_input(InputKind::Haystack) {
+            Some(Record::Haystack(haystack)) => haystack,
+            _ => unreachable!("recording has haystacks of the expected type"),
+        }
+    }
+
+    fn next_needle(&mut self, _haystack: &Self::Haystack) -> Self::Needle {
+        let record = if self.needles {
+            self.next_input(InputKind::Needle)
+        } else {
+            serde_json::from_str(NULL_NEEDLE).ok()
+        };
+        match record {
+            Some(Record::Needle(needle)) => needle,
+            _ => unreachable!("recording has needles or they can be omitted"),
+        }
+    }
+
+    fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, _sample_idx: usize) {
+        // Haystack is left as is if the recording has no mutations, the test fails anyway
+        if let Some(Record::Mutation(mutated)) = self.next_input(InputKind::Mutation) {
+            *haystack = mutated;
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.mutating
+    }
+
+    fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+        self.haystack_size.map(|size| size(haystack))
+    }
+
+    fn recording(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sync(&mut self, _seed: u64) {}
+
+    fn sync_with(&mut self, _rng: SmallRng) {}
+}
+
+/// Generator producing haystacks of the wrapped generator on a background thread
+///
+/// Useful for generators spending a lot of time in [`Generator::next_haystack()`] (reading files, building
+/// indexes). Haystacks are generated ahead of time into a queue of a given depth, so the measuring thread doesn't
+/// wait for them. Needles are still generated on the measuring thread, because they are generated for a given
+/// haystack. Along with each haystack the background thread queues a clone of the wrapped generator taken right
+/// after the haystack is generated, and the needles are generated by that clone. So the generators keeping the
+/// state of the current haystack (eg. [`SampledIndex`]) work the same way they do without prefetching.
+///
+/// Syncing stops the background thread discarding all the prefetched haystacks, so haystacks produced after
+/// syncing depend only on the seed. The background thread is stopped when the generator is dropped. If it panics,
+/// the haystacks are generated on the measuring thread and the test fails (see [`Generator::take_error()`]).
+///
+/// ```rust
+/// use tango_bench::generators::{PrefetchingGenerator, RandomVec};
+///
+/// let generator = PrefetchingGenerator::new(RandomVec::<u32>::new(1_000_000), 4);
+/// ```
+pub struct PrefetchingGenerator<G: Generator> {
+    /// `None` only while switching the states
+    worker: Option<Prefetcher<G>>,
+
+    /// Generator of the needles of the last haystack
+    needles: G,
+    depth: usize,
+
+    /// Failure of the background thread not taken by the harness yet
+    error: Option<String>,
+}
+
+enum Prefetcher<G: Generator> {
+    Idle(G),
+    Running {
+        haystacks: Receiver<(G::Haystack, G)>,
+        thread: JoinHandle<G>,
+    },
+}
+
+impl<G> PrefetchingGenerator<G>
+where
+    G: Generator + Clone + Send + 'static,
+    G::Haystack: Send + 'static,
+{
+    /// Wraps a generator with a queue of `depth` prefetched haystacks
+    pub fn new(generator: G, depth: usize) -> Self {
+        assert!(depth > 0, "Depth should be positive");
+        Self {
+            needles: generator.clone(),
+            worker: Some(Prefetcher::Idle(generator)),
+            depth,
+            error: None,
+        }
+    }
+
+    /// Starts generating haystacks on a background thread if it's not started yet
+    fn start(&mut self) {
+        self.worker = match self.worker.take() {
+            Some(Prefetcher::Idle(mut generator)) => {
+                let (tx, rx) = sync_channel(self.depth);
+                let spawned = thread::Builder::new()
+                    .name(format!("tango-prefetch-{}", generator.name()))
+                    .spawn(move || {
+                        loop {
+                            let haystack = generator.next_haystack();
+                            let needles = generator.clone();
+                            // Errors are passed along with the clone
+                            generator.take_error();
+                            if tx.send((haystack, needles)).is_err() {
+                                break;
+                            }
+                        }
+                        generator
+                    });
+                match spawned {
+                    Ok(thread) => Some(Prefetcher::Running {
+                        haystacks: rx,
+                        thread,
+                    }),
+                    Err(e) => {
+                        self.fail(format!("Unable to start haystack generator thread: {}", e));
+                        Some(Prefetcher::Idle(self.needles.clone()))
+                    }
+                }
+            }
+            worker => worker,
+        }
+    }
+
+    /// Stops the background thread discarding prefetched haystacks
+    ///
+    /// If the background thread panicked, it is restarted with the generator of the needles.
+    fn stop(&mut self) {
+        self.worker = match self.worker.take() {
+            Some(Prefetcher::Running { haystacks, thread }) => {
+                // Background thread is either generating a haystack or blocked on a full queue. In both cases
+                // it exits once the receiver is dropped
+                drop(haystacks);
+                match thread.join() {
+                    Ok(generator) => Some(Prefetcher::Idle(generator)),
+                    Err(panic) => {
+                        let message = panic
+                            .downcast_ref::<&str>()
+                            .copied()
+                            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                            .unwrap_or("unknown error");
+                        self.fail(format!("Haystack generator panicked: {}", message));
+                        Some(Prefetcher::Idle(self.needles.clone()))
+                    }
+                }
+            }
+            worker => worker,
+        };
+    }
+
+    fn restart(&mut self, sync: impl Fn(&mut G)) {
+        self.stop();
+        if let Some(Prefetcher::Idle(generator)) = &mut self.worker {
+            sync(generator);
+        }
+        sync(&mut self.needles);
+    }
+
+    fn fail(&mut self, error: String) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl<G> Generator for PrefetchingGenerator<G>
+where
+    G: Generator + Clone + Send + 'static,
+    G::Haystack: Send + 'static,
+{
+    type Haystack = G::Haystack;
+    type Needle = G::Needle;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        self.start();
+        if let Some(Prefetcher::Running { haystacks, .. }) = &self.worker {
+            if let Ok((haystack, needles)) = haystacks.recv() {
+                self.needles = needles;
+                return haystack;
+            }
+            // Sender is dropped only if the background thread panicked
+            self.stop();
+        }
+        // The test fails anyway, the haystack is only needed to finish the sample
+        self.needles.next_haystack()
+    }
+
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+        self.needles.next_needle(haystack)
+    }
+
+    fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, sample_idx: usize) {
+        self.needles.mutate_haystack(haystack, sample_idx)
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.needles.mutates_haystack()
+    }
+
+    fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+        self.needles.haystack_size(haystack)
+    }
+
+    fn recording(&self) -> Option<&Path> {
+        self.needles.recording()
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take().or_else(|| self.needles.take_error())
+    }
+
+    fn name(&self) -> &str {
+        self.needles.name()
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.restart(|g| g.sync(seed));
+    }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.restart(|g| g.sync_with(rng.clone()));
+    }
+}
+
+impl<G: Generator> Drop for PrefetchingGenerator<G> {
+    fn drop(&mut self) {
+        if let Some(Prefetcher::Running { haystacks, thread }) = self.worker.take() {
+            drop(haystacks);
+            // Panic of the background thread is already reported by the panic hook
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use std::{cell::Cell, fs, ops::Range, process, sync::Arc};
+
+    /// Simplified version of substring generator from the examples
+    struct RandomSubstring(SmallRng, String);
+
+    impl Generator for RandomSubstring {
+        type Haystack = String;
+        type Needle = Range<usize>;
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            self.1.clone()
+        }
+
+        fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+            let start = self.0.gen_range(0..haystack.len() / 2);
+            start..start + haystack.len() / 2
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.0 = SmallRng::seed_from_u64(seed);
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tango-{}-{}.jsonl", name, process::id()))
+    }
+
+    /// Records 3 haystacks with 2 needles each and returns produced inputs
+    fn record<G>(generator: G, path: &Path) -> Vec<(G::Haystack, Vec<G::Needle>)>
+    where
+        G: Generator,
+        G::Haystack: Serialize + DeserializeOwned,
+        G::Needle: Serialize + DeserializeOwned,
+    {
+        let mut recorder = Recorder::wrap(generator, path).unwrap();
+        recorder.sync(1);
+        (0..3)
+            .map(|_| {
+                let haystack = recorder.next_haystack();
+                let needles = (0..2).map(|_| recorder.next_needle(&haystack)).collect();
+                (haystack, needles)
+            })
+            .collect()
+    }
+
+    fn replay<H, N>(replayer: &mut Replayer<H, N>) -> Vec<(H, Vec<N>)>
+    where
+        H: DeserializeOwned,
+        N: DeserializeOwned,
+    {
+        (0..3)
+            .map(|_| {
+                let haystack = replayer.next_haystack();
+                let needles = (0..2).map(|_| replayer.next_needle(&haystack)).collect();
+                (haystack, needles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_random_vec_roundtrip() {
+        let path = temp_path("random-vec");
+        let generator = RandomVec::<u32>::new(10);
+        let name = generator.name().to_string();
+        let recorded = record(generator, &path);
+
+        let mut replayer = Replayer::<Vec<u32>, ()>::open(&path).unwrap();
+        replayer.sync(42);
+        let replayed = replay(&mut replayer);
+        assert_eq!(replayer.haystack_size(&replayed[0].0), None);
+        let replayer = Replayer::<Vec<u32>, ()>::open(&path)
+            .unwrap()
+            .with_haystack_size(|h| HaystackSize::Elements(h.len()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayer.name(), name);
+        assert!(!replayer.mutates_haystack());
+        assert_eq!(replayed, recorded);
+        let size = replayer.haystack_size(&replayed[0].0);
+        assert_eq!(size, Some(HaystackSize::Elements(10)));
+    }
+
+    #[test]
+    fn check_substring_roundtrip() {
+        let path = temp_path("substring");
+        let generator = RandomSubstring(SmallRng::seed_from_u64(0), "Hello, world!".into());
+        let recorded = record(generator, &path);
+
+        let mut replayer = Replayer::<String, Range<usize>>::open(&path).unwrap();
+        let replayed = replay(&mut replayer);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayer.name(), "RandomSubstring");
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn check_recorder_replays_recording_of_same_generator() {
+        let path = temp_path("recorder-replay");
+        let unused = temp_path("recorder-replay-unused");
+        let recorded = record(RandomVec::<u8>::new(4), &path);
+
+        // Generator is synced with another seed, but the recorded inputs are replayed
+        let mut recorder =
+            Recorder::with_replay(RandomVec::<u8>::new(4), &unused, Some(&path)).unwrap();
+        recorder.sync(2);
+        let replayed = (0..3).map(|_| {
+            let haystack = recorder.next_haystack();
+            let needles = (0..2).map(|_| recorder.next_needle(&haystack)).collect();
+            (haystack, needles)
+        });
+        assert_eq!(replayed.collect::<Vec<_>>(), recorded);
+        assert_eq!(recorder.recording(), Some(path.as_path()));
+        assert_eq!(recorder.take_error(), None);
+        assert!(!unused.exists());
+
+        // Recording of another generator is not replayed
+        let mut recorder =
+            Recorder::with_replay(RandomVec::<u8>::new(8), &unused, Some(&path)).unwrap();
+        assert_eq!(recorder.recording(), Some(unused.as_path()));
+        assert_eq!(recorder.next_haystack().len(), 8);
+        drop(recorder);
+        fs::remove_file(&unused).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_looping_replay() {
+        let path = temp_path("looping");
+        let recorded = record(RandomVec::<u8>::new(4), &path);
+
+        let mut replayer = Replayer::<Vec<u8>, ()>::open(&path).unwrap().looping();
+        let first = replay(&mut replayer);
+        let second = replay(&mut replayer);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, recorded);
+        assert_eq!(second, recorded);
+    }
+
+    #[test]
+    fn check_exhausted_replay() {
+        let path = temp_path("exhausted");
+        record(RandomVec::<u8>::new(4), &path);
+
+        let mut replayer = Replayer::<Vec<u8>, ()>::open(&path).unwrap();
+        let recorded = replay(&mut replayer);
+        assert_eq!(replayer.take_error(), None);
+
+        // Replaying starts over, but the error is reported once
+        let replayed = replay(&mut replayer);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, recorded);
+        let error = replayer.take_error().unwrap();
+        assert!(error.contains("exhausted"), "{}", error);
+        assert_eq!(replayer.take_error(), None);
+    }
+
+    #[test]
+    fn check_replay_out_of_sync() {
+        let path = temp_path("out-of-sync");
+        let recorded = record(RandomVec::<u8>::new(4), &path);
+
+        let mut replayer = Replayer::<Vec<u8>, ()>::open(&path).unwrap();
+        replayer.next_haystack();
+        // Needles of the first haystack are skipped
+        let haystack = replayer.next_haystack();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(haystack, recorded[1].0);
+        let error = replayer.take_error().unwrap();
+        assert!(
+            error.contains("expected haystack, found needle"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn check_replay_type_mismatch() {
+        let path = temp_path("mismatch");
+        record(RandomVec::<u8>::new(4), &path);
+
+        let result = Replayer::<String, ()>::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Error::InvalidRecording(_))));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn check_recording_write_error() {
+        let mut recorder = Recorder::wrap(RandomVec::<u8>::new(4), "/dev/full").unwrap();
+        let haystack = recorder.next_haystack();
+        recorder.next_needle(&haystack);
+        recorder.next_haystack();
+
+        let error = recorder.take_error().unwrap();
+        assert!(error.contains("/dev/full"), "{}", error);
+        assert_eq!(recorder.take_error(), None);
+        assert_eq!(recorder.recording(), Some(Path::new("/dev/full")));
+    }
+
+    /// Generator appending a random element to the haystack on each mutation
+    #[derive(Clone)]
+    struct Appending(SmallRng);
+
+    impl Generator for Appending {
+        type Haystack = Vec<u8>;
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            vec![self.0.gen()]
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, _sample_idx: usize) {
+            haystack.push(self.0.gen());
+        }
+
+        fn mutates_haystack(&self) -> bool {
+            true
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.0 = SmallRng::seed_from_u64(seed);
+        }
+    }
+
+    #[test]
+    fn check_mutations_roundtrip() {
+        let path = temp_path("mutations");
+        let mut recorder = Recorder::wrap(Appending(SmallRng::seed_from_u64(0)), &path).unwrap();
+        assert!(recorder.mutates_haystack());
+        let mut haystack = recorder.next_haystack();
+        for sample_idx in 1..3 {
+            recorder.mutate_haystack(&mut haystack, sample_idx);
+        }
+        drop(recorder);
+
+        let mut replayer = Replayer::<Vec<u8>, ()>::open(&path).unwrap();
+        assert!(replayer.mutates_haystack());
+        let mut replayed = replayer.next_haystack();
+        for sample_idx in 1..3 {
+            replayer.mutate_haystack(&mut replayed, sample_idx);
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, haystack);
+
+        let prefetching = PrefetchingGenerator::new(Appending(SmallRng::seed_from_u64(0)), 1);
+        assert!(prefetching.mutates_haystack());
+        assert!(!PrefetchingGenerator::new(RandomVec::<u8>::new(1), 1).mutates_haystack());
+    }
+
+    /// Counts haystacks produced. Clones share the reference counter, so it's possible to check that all of them
+    /// are dropped
+    #[derive(Clone)]
+    struct CountingGenerator {
+        value: u64,
+        _refs: Arc<()>,
+    }
+
+    impl Generator for CountingGenerator {
+        type Haystack = u64;
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            self.value += 1;
+            assert!(self.value != u64::MAX, "Counter overflow");
+            self.value
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn sync(&mut self, seed: u64) {
+            self.value = seed;
+        }
+    }
+
+    #[test]
+    fn check_prefetching_is_deterministic() {
+        let mut generator = RandomVec::<u32>::new(16);
+        generator.sync(7);
+        let expected = (0..5)
+            .map(|_| generator.next_haystack())
+            .collect::<Vec<_>>();
+
+        let mut prefetching = PrefetchingGenerator::new(RandomVec::<u32>::new(16), 2);
+        assert_eq!(prefetching.name(), generator.name());
+        prefetching.sync(7);
+        let haystacks = (0..3)
+            .map(|_| prefetching.next_haystack())
+            .collect::<Vec<_>>();
+        assert_eq!(haystacks, expected[..3]);
+
+        // Prefetched haystacks are discarded on sync
+        prefetching.sync(7);
+        let haystacks = (0..5)
+            .map(|_| prefetching.next_haystack())
+            .collect::<Vec<_>>();
+        assert_eq!(haystacks, expected);
+    }
+
+    #[test]
+    fn check_prefetching_thread_is_stopped_on_drop() {
+        let counter = Arc::new(());
+        let mut prefetching = PrefetchingGenerator::new(
+            CountingGenerator {
+                value: 0,
+                _refs: Arc::clone(&counter),
+            },
+            3,
+        );
+        prefetching.sync(10);
+        assert_eq!(prefetching.next_haystack(), 11);
+        prefetching.next_needle(&11);
+        assert!(Arc::strong_count(&counter) > 2);
+
+        // Generator is returned by the background thread only when it's finished
+        drop(prefetching);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    /// Generator panicking on the background thread of [`PrefetchingGenerator`] after a given number of haystacks
+    #[derive(Clone)]
+    struct PanickingInBackground(u64);
+
+    impl Generator for PanickingInBackground {
+        type Haystack = u64;
+        type Needle = u64;
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            let background = thread::current()
+                .name()
+                .is_some_and(|n| n.starts_with("tango-prefetch"));
+            assert!(
+                !background || self.0 < 2,
+                "Haystack {} is too large",
+                self.0
+            );
+            self.0 += 1;
+            self.0
+        }
+
+        /// Needles are the haystacks they are generated after
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+            self.0
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.0 = seed;
+        }
+    }
+
+    #[test]
+    fn check_prefetching_needles_follow_haystacks() {
+        let mut prefetching = PrefetchingGenerator::new(PanickingInBackground(0), 3);
+        prefetching.sync(0);
+        for expected in 1..=2 {
+            let haystack = prefetching.next_haystack();
+            assert_eq!(haystack, expected);
+            assert_eq!(prefetching.next_needle(&haystack), expected);
+        }
+        assert_eq!(prefetching.take_error(), None);
+    }
+
+    #[test]
+    fn check_prefetching_reports_panics() {
+        let mut prefetching = PrefetchingGenerator::new(PanickingInBackground(0), 1);
+        prefetching.sync(0);
+        assert_eq!(prefetching.next_haystack(), 1);
+        assert_eq!(prefetching.next_haystack(), 2);
+
+        // Haystack is generated on the measuring thread instead
+        assert_eq!(prefetching.next_haystack(), 3);
+        let error = prefetching.take_error().unwrap();
+        assert!(error.contains("Haystack 2 is too large"), "{}", error);
+        assert_eq!(prefetching.take_error(), None);
+
+        // Syncing restarts the background thread
+        prefetching.sync(0);
+        assert_eq!(prefetching.next_haystack(), 1);
+        assert_eq!(prefetching.take_error(), None);
+    }
+
+    /// RNG counting the number of values requested from it
+    struct CountingRng(SmallRng, usize);
+
+    impl RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            self.1 += 1;
+            self.0.next_u32()
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.1 += 1;
+            self.0.next_u64()
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.1 += 1;
+            self.0.fill_bytes(dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.1 += 1;
+            self.0.try_fill_bytes(dest)
+        }
+    }
+
+    #[test]
+    fn check_sampled_index_covers_haystack_before_repeating() {
+        let mut rng = CountingRng(SmallRng::seed_from_u64(0), 0);
+        let mut index = SampledIndex::default();
+        assert_eq!(index.next(&mut rng), None);
+
+        let len = 100;
+        index.rebuild(len, &mut rng);
+        for _ in 0..3 {
+            let mut positions = (0..len)
+                .map(|_| index.next(&mut rng).unwrap())
+                .collect::<Vec<_>>();
+            positions.sort();
+            assert_eq!(positions, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn check_sampled_index_is_amortized_constant() {
+        let mut rng = CountingRng(SmallRng::seed_from_u64(0), 0);
+        let mut index = SampledIndex::default();
+        let len = 1000;
+        index.rebuild(len, &mut rng);
+        let shuffle_calls = rng.1;
+        assert!(shuffle_calls <= 2 * len, "{}", shuffle_calls);
+
+        // No randomness is needed until the ring wraps
+        for _ in 0..len {
+            index.next(&mut rng);
+        }
+        assert_eq!(rng.1, shuffle_calls);
+
+        // Only reshuffles on wrap, so the cost per needle is constant
+        for _ in 0..10 * len {
+            index.next(&mut rng);
+        }
+        assert!(rng.1 <= 11 * 2 * len, "{}", rng.1);
+    }
+
+    #[test]
+    fn check_key_sampler_snapshots_keys_once_per_haystack() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut sampler = KeySampler::default();
+        let reads = Cell::new(0);
+        let len = 1000;
+
+        for haystack in 0..2 {
+            let map = (0..len)
+                .map(|i| (i + haystack * len, ()))
+                .collect::<HashMap<_, _>>();
+            reads.set(0);
+            let mut keys = (0..len)
+                .map(|_| {
+                    let keys = map.keys().inspect(|_| reads.set(reads.get() + 1));
+                    *sampler.sample(keys, &mut rng).unwrap()
+                })
+                .collect::<Vec<_>>();
+            keys.sort();
+
+            let mut expected = map.keys().copied().collect::<Vec<_>>();
+            expected.sort();
+            assert_eq!(keys, expected);
+            assert_eq!(sampler.len(), len);
+            // All the keys are read on the first call, after that only the first key is read
+            assert_eq!(reads.get(), len + (len - 1));
+        }
+
+        let empty = HashMap::<usize, ()>::new();
+        assert_eq!(sampler.sample(empty.keys(), &mut rng), None);
+    }
+
+    #[test]
+    fn check_random_map_needles() {
+        let mut generator = RandomMap::<u64, u64>::new(100, 1.);
+        assert_eq!(generator.name(), "RandomMap{n=100,hit=100%}");
+        let map = generator.next_haystack();
+        assert_eq!(map.len(), 100);
+        let needles = (0..100)
+            .map(|_| generator.next_needle(&map))
+            .collect::<HashSet<_>>();
+        assert_eq!(needles, map.keys().copied().collect());
+
+        let mut generator = RandomMap::<u64, u64>::new(100, 0.);
+        let map = generator.next_haystack();
+        assert!((0..100).all(|_| !map.contains_key(&generator.next_needle(&map))));
+        assert_eq!(generator.take_error(), None);
+        // Throughput is the rate of lookups
+        assert_eq!(
+            generator.haystack_size(&map),
+            Some(HaystackSize::Elements(1))
+        );
+    }
+
+    #[test]
+    fn check_size_larger_than_key_space() {
+        let mut generator = RandomMap::<bool, u8>::new(3, 1.);
+        assert_eq!(generator.next_haystack().len(), 2);
+        let error = generator.take_error().unwrap();
+        assert!(error.contains("3 distinct values of bool"), "{}", error);
+
+        let mut generator = RandomSet::<u8>::new(300, 1.);
+        assert_eq!(generator.next_haystack().len(), 256);
+        assert!(generator.take_error().is_some());
+        assert_eq!(generator.take_error(), None);
+    }
+
+    #[test]
+    fn check_random_set_needles_with_prefetching() {
+        // Haystacks and needles are generated by different instances of the generator
+        let mut generator = PrefetchingGenerator::new(RandomSet::<u32>::new(50, 1.), 2);
+        assert_eq!(generator.name(), "RandomSet{n=50,hit=100%}");
+        for _ in 0..3 {
+            let set = generator.next_haystack();
+            let needles = (0..50)
+                .map(|_| generator.next_needle(&set))
+                .collect::<HashSet<_>>();
+            assert_eq!(needles, set);
+        }
+    }
 }
@@ -0,0 +1,154 @@
+//! Identity of the tests shared by the harness, the libraries and the exported results
+//!
+//! A test is identified by its name ([`TestId`]) and a result of a comparison by the names of both functions
+//! ([`PairId`]). In `compare` both functions have the same name, while the pairs of [`cli::run_pairs()`] compare
+//! the functions registered under different names. Results keep a display name next to the identity, which is the
+//! name of the test or a custom name of a pair (see [`PairedTargets::with_name()`]).
+//!
+//! Both types are serialized as strings (or a map of strings), and [`Display`](fmt::Display) renders them the
+//! same way all the reports do: `name` or `baseline vs candidate`.
+//!
+//! [`cli::run_pairs()`]: crate::cli::run_pairs()
+//! [`PairedTargets::with_name()`]: crate::PairedTargets::with_name()
+
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, fmt, sync::Arc};
+
+/// Name of a registered test
+///
+/// Cheap to clone, so the same name is shared by all the results of the test.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TestId(Arc<str>);
+
+impl TestId {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for TestId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Allows to look the tests up by `&str` in the maps keyed by [`TestId`]
+impl Borrow<str> for TestId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for TestId {
+    fn from(name: &str) -> Self {
+        Self(name.into())
+    }
+}
+
+impl From<String> for TestId {
+    fn from(name: String) -> Self {
+        Self(name.into())
+    }
+}
+
+impl From<Arc<str>> for TestId {
+    fn from(name: Arc<str>) -> Self {
+        Self(name)
+    }
+}
+
+impl PartialEq<str> for TestId {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for TestId {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+/// Functions compared by a test
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PairId {
+    pub baseline: TestId,
+    pub candidate: TestId,
+}
+
+impl PairId {
+    pub fn new(baseline: impl Into<TestId>, candidate: impl Into<TestId>) -> Self {
+        Self {
+            baseline: baseline.into(),
+            candidate: candidate.into(),
+        }
+    }
+
+    /// Pair comparing the functions with the same name (eg. two versions of the same test in `compare`)
+    pub fn same(id: impl Into<TestId>) -> Self {
+        let id = id.into();
+        Self {
+            baseline: id.clone(),
+            candidate: id,
+        }
+    }
+
+    pub fn is_same(&self) -> bool {
+        self.baseline == self.candidate
+    }
+}
+
+/// `name` if both functions have the same name, `baseline vs candidate` otherwise
+impl fmt::Display for PairId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_same() {
+            write!(f, "{}", self.baseline)
+        } else {
+            write!(f, "{} vs {}", self.baseline, self.candidate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn check_pair_display() {
+        assert_eq!(PairId::same("sort/u64/100").to_string(), "sort/u64/100");
+        assert_eq!(PairId::new("a", "b").to_string(), "a vs b");
+        assert!(PairId::new("a", "a").is_same());
+    }
+
+    #[test]
+    fn check_serialized_as_strings() {
+        let id = PairId::new("iter_sum", "fold_sum");
+        let value = serde_json::to_value(&id).unwrap();
+        assert_eq!(
+            value,
+            json!({"baseline": "iter_sum", "candidate": "fold_sum"})
+        );
+        assert_eq!(serde_json::from_value::<PairId>(value).unwrap(), id);
+        assert_eq!(serde_json::to_value(TestId::from("a")).unwrap(), json!("a"));
+    }
+
+    #[test]
+    fn check_lookup_by_str() {
+        let index = HashMap::from([(TestId::from("a"), 1)]);
+        assert_eq!(index.get("a"), Some(&1));
+        assert_eq!(TestId::from("a"), "a");
+    }
+}
@@ -1,24 +1,79 @@
+// Without the `cli` feature the sampling loop driving the measurement core is left to the user of the library
+#![cfg_attr(not(feature = "cli"), allow(dead_code))]
+
+use buffer::SmallBuffer;
 use core::ptr;
-use num_traits::ToPrimitive;
+use id::PairId;
+use manifest::{Environment, TestSchedule};
+use profile::Profile;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use stats::{
+    ConfidenceInterval, OrderBias, OverheadVariance, Percentiles, Reservoir, SamplePercentiles,
+    SignificanceOutcome, StreamingStats, TailLatencies, Verdict,
+};
 use std::{
     any::type_name,
-    cell::RefCell,
-    cmp::Ordering,
+    collections::HashMap,
+    fmt::{self, Debug, Write},
     hint::black_box,
-    io, mem,
-    ops::{Add, Div, RangeInclusive},
-    rc::Rc,
+    io, iter, mem,
+    ops::{Add, AddAssign, Deref, DerefMut, Div},
+    path::{Path, PathBuf},
     str::Utf8Error,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
 };
+use tango_core::{outliers::tukey_fences, RunningSummary};
 use thiserror::Error;
 use timer::{ActiveTimer, Timer};
 
+#[cfg(feature = "cli")]
+mod archive;
+mod buffer;
+#[cfg(feature = "cli")]
+mod cache;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod dump_index;
+#[cfg(feature = "dylib")]
 pub mod dylib;
+pub mod fixture;
+pub mod format;
 pub mod generators;
-#[cfg(target_os = "linux")]
+pub mod id;
+#[cfg(all(target_os = "linux", feature = "dylib"))]
 pub mod linux;
+pub mod manifest;
+pub mod noise;
+pub mod observers;
+#[cfg(feature = "cli")]
+mod plugin;
+pub mod profile;
+pub mod quick;
+pub mod registry;
+#[cfg(feature = "cli")]
+mod resource_limits;
+#[cfg(feature = "send")]
+pub mod send;
+pub mod shard;
+pub mod stats;
+#[cfg(feature = "cli")]
+mod symbol_map;
+#[cfg(feature = "dylib")]
+pub mod testing;
+#[cfg(feature = "cli")]
+mod watch;
+
+#[cfg(feature = "macros")]
+pub use tango_bench_macros::benchmark;
+pub use tango_core::{iqr_variance_thresholds, trim_mask, Summary};
+
+#[doc(hidden)]
+pub use inventory;
+
+#[cfg(test)]
+extern crate self as tango_bench;
 
 const NS_TO_MS: usize = 1_000_000;
 
@@ -30,15 +85,118 @@ pub enum Error {
     #[error("Invalid string pointer from FFI")]
     InvalidFFIString(Utf8Error),
 
+    #[error(
+        "No benchmarks registered. Make sure tango_benchmarks!() is given at least one benchmark or a function is annotated with #[tango_bench::benchmark]"
+    )]
+    NoBenchmarksRegistered,
+
+    #[error("Invalid test metadata reported by the library")]
+    InvalidMetadata(#[source] serde_json::Error),
+
+    #[error("Unsupported test metadata version: {0}. The library is built with a newer version of tango")]
+    UnsupportedMetadataVersion(u32),
+
     #[error("Spi::self() was already called")]
     SpiSelfWasMoved,
 
+    #[cfg(feature = "dylib")]
     #[error("Unable to load library symbol")]
     UnableToLoadSymbol(#[source] libloading::Error),
 
+    #[cfg(feature = "dylib")]
+    /// Required symbol is not exported by the library, the executable is not a tango benchmark or its symbols are
+    /// stripped
+    #[error(
+        "Library doesn't export {0}. Make sure the benchmark is built with tango_main!(), linked with -rdynamic and the exported symbols are not stripped (eg. `strip = true` in [profile.bench])"
+    )]
+    MissingEntryPoint(&'static str, #[source] libloading::Error),
+
+    #[cfg(feature = "dylib")]
+    /// Library is a tango benchmark, but lacks one of the symbols every supported version exports
+    #[error(
+        "Library is a tango benchmark, but doesn't export {0}. It is built with a version of tango incompatible with the harness, rebuild it with the same version"
+    )]
+    IncompatibleLibrary(&'static str, #[source] libloading::Error),
+
+    #[error(
+        "{} is a position-dependent executable and can't be loaded as a library. Rebuild it as a position-independent executable (eg. with RUSTFLAGS=\"-C relocation-model=pic -C link-args=-pie\")",
+        .0.display()
+    )]
+    PositionDependentExecutable(PathBuf),
+
+    #[error("{} is built for {1}, but the harness is built for {2}", .0.display())]
+    ArchitectureMismatch(PathBuf, &'static str, &'static str),
+
     #[error("Unknown sampler type. Available options are: flat and linear")]
     UnknownSamplerType,
 
+    #[error("Unknown sample planner. Available options are: deadline and fixed")]
+    UnknownSamplePlanner,
+
+    #[error("Unknown slow test policy. Available options are: extend, skip and force")]
+    UnknownSlowTestPolicy,
+
+    #[error("Unknown noise action. Available options are: abort and demote")]
+    UnknownNoiseAction,
+
+    #[error("Unknown outlier scope. Available options are: global and windowed")]
+    UnknownOutlierScope,
+
+    #[error(
+        "Unknown clock source. Available options are: instant, monotonic-raw, rdtscp and cntvct"
+    )]
+    UnknownClockSource,
+
+    /// Test made no progress for [`MeasurementSettings::call_timeout`] and is not measured further
+    #[error("Test made no progress for {:.1}s (call timeout exceeded)", .0.as_secs_f64())]
+    CallTimeout(Duration),
+
+    /// Child process measuring the test under `--resource-limits` failed (eg. it exceeded one of the limits)
+    #[error("Child process measuring the test failed ({0})")]
+    ChildFailed(String),
+
+    /// Test is not run for a given reason (see [`SlowTestPolicy::Skip`])
+    #[error("{0}")]
+    TestSkipped(String),
+
+    #[error("Invalid measurement settings exported by the library")]
+    InvalidSettings(#[source] serde_json::Error),
+
+    #[error("Invalid run manifest")]
+    InvalidManifest(#[source] serde_json::Error),
+
+    #[error("Unsupported run manifest version: {0}. It is written by a newer version of tango")]
+    UnsupportedManifestVersion(u32),
+
+    #[error("Invalid dump index")]
+    InvalidDumpIndex(#[source] serde_json::Error),
+
+    #[error("Unsupported dump index version: {0}. It is written by a newer version of tango")]
+    UnsupportedDumpIndexVersion(u32),
+
+    #[error("Invalid name of test #{0} {1:?}: {2}")]
+    InvalidTestName(usize, String, registry::InvalidName),
+
+    #[error("Invalid registry snapshot at line {0}: {1}")]
+    InvalidRegistrySnapshot(usize, &'static str),
+
+    #[error("Invalid generator recording")]
+    InvalidRecording(#[source] serde_json::Error),
+
+    #[error("Invalid output verification samples exported by the library")]
+    InvalidVerification(#[source] serde_json::Error),
+
+    #[cfg(feature = "cli")]
+    #[error(
+        "Reporter plugin ABI version {0} is not supported (expected {})",
+        tango_reporter_plugin::ABI_VERSION
+    )]
+    IncompatiblePlugin(u32),
+
+    #[cfg(feature = "cli")]
+    #[error("Unable to load reporter plugin")]
+    UnableToLoadPlugin(#[source] libloading::Error),
+
     #[error("IO Error")]
     IOError(#[from] io::Error),
 }
@@ -46,7 +204,9 @@ pub enum Error {
 /// Registers benchmark in the system
 ///
 /// Macros accepts a list of functions that produce any [`IntoBenchmarks`] type. All of the benchmarks
-/// created by those functions are registered in the harness.
+/// created by those functions are registered in the harness along with the functions annotated with
+/// `#[tango_bench::benchmark]` (see [`registry::collected()`]). Benches using only annotated functions don't
+/// need this macro.
 ///
 /// ## Example
 /// ```rust
@@ -59,20 +219,37 @@ pub enum Error {
 ///
 /// tango_benchmarks!(time_benchmarks());
 /// ```
+#[cfg(feature = "dylib")]
 #[macro_export]
 macro_rules! tango_benchmarks {
-    ($($func_expr:expr),+) => {
-        /// Type checking tango_init() function
-        const TANGO_INIT: $crate::dylib::ffi::InitFn = tango_init;
-
-        /// Exported function for initializing the benchmark harness
-        #[no_mangle]
-        unsafe extern "C" fn tango_init() {
+    ($($func_expr:expr),* $(,)?) => {
+        $crate::__tango_register!(listed, || {
             let mut benchmarks = vec![];
             $(benchmarks.extend($crate::IntoBenchmarks::into_benchmarks($func_expr));)*
-            $crate::dylib::__tango_init(benchmarks)
+            benchmarks
+        });
+
+        /// Initializes the benchmark harness for the benches calling `tango_init()` directly
+        #[allow(dead_code)]
+        unsafe fn tango_init() {
+            $crate::dylib::ffi::tango_init()
         }
+    };
+}
 
+/// Submits a factory of benchmarks to the [`registry`] (used by `#[tango_bench::benchmark]`)
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tango_register {
+    (listed, $factory:expr) => {
+        $crate::inventory::submit! {
+            $crate::registry::Registration { factory: $factory, listed: true }
+        }
+    };
+    ($factory:expr) => {
+        $crate::inventory::submit! {
+            $crate::registry::Registration { factory: $factory, listed: false }
+        }
     };
 }
 
@@ -93,15 +270,48 @@ macro_rules! tango_benchmarks {
 ///     ..Default::default()
 /// });
 /// ```
+///
+/// [`SampleObserver`]s are registered after the settings:
+/// ```rust,ignore
+/// tango_main!(MeasurementSettings::default(), observers = [RusageObserver::new()]);
+/// ```
+///
+/// Behavior of the command line is configured with [`cli::MainOptions`] given before the observers:
+/// ```rust,ignore
+/// tango_main!(MeasurementSettings::default(), options = MainOptions { tolerant: true, ..Default::default() });
+/// ```
+#[cfg(feature = "cli")]
 #[macro_export]
 macro_rules! tango_main {
-    ($settings:expr) => {
+    ($settings:expr, options = $options:expr, observers = [$($observer:expr),* $(,)?]) => {
         fn main() -> $crate::cli::Result<std::process::ExitCode> {
             // Initialize Tango for SelfVTable usage
-            unsafe { tango_init() };
-            $crate::cli::run($settings)
+            unsafe { $crate::dylib::ffi::tango_init() };
+            let observers = vec![$(Box::new($observer) as Box<dyn $crate::SampleObserver>),*];
+            $crate::cli::run_with_options($settings, $options, observers)
+        }
+
+        /// Type checking tango_settings_json() function
+        const TANGO_SETTINGS_JSON: $crate::dylib::ffi::SettingsJsonFn = tango_settings_json;
+
+        /// Exported function for reading measurement settings the benchmark was built with
+        #[no_mangle]
+        unsafe extern "C" fn tango_settings_json(
+            json: *mut *const std::os::raw::c_char,
+            length: *mut usize,
+        ) {
+            $crate::dylib::__tango_settings_json($settings, json, length)
         }
     };
+    ($settings:expr, options = $options:expr) => {
+        tango_main! {$settings, options = $options, observers = []}
+    };
+    ($settings:expr, observers = [$($observer:expr),* $(,)?]) => {
+        tango_main! {$settings, options = $crate::cli::MainOptions::default(), observers = [$($observer),*]}
+    };
+    ($settings:expr) => {
+        tango_main! {$settings, observers = []}
+    };
     () => {
         tango_main! {$crate::MeasurementSettings::default()}
     };
@@ -115,6 +325,282 @@ pub fn benchmark_fn<O, F: Fn() -> O + 'static>(
     Box::new(SimpleFunc { name, func })
 }
 
+/// Creates a benchmark comparing a custom metric of the function output instead of execution time
+///
+/// `metric` extracts the measured quantity from the function output (eg. compressed size). Values are summed
+/// over all iterations of a sample and compared by the same statistical machinery as time measurements.
+///
+/// ```rust
+/// use tango_bench::{benchmark_fn_metric, IntoBenchmarks, Unit};
+///
+/// fn size_benchmarks() -> impl IntoBenchmarks {
+///     [benchmark_fn_metric("encoded_size", || 42u32.to_string(), |s| s.len() as u64, Unit::Bytes)]
+/// }
+/// ```
+pub fn benchmark_fn_metric<O, F, M>(
+    name: &'static str,
+    func: F,
+    metric: M,
+    unit: Unit,
+) -> Box<dyn MeasureTarget>
+where
+    F: Fn() -> O + 'static,
+    M: Fn(&O) -> u64 + 'static,
+{
+    assert!(!name.is_empty());
+    Box::new(MetricFunc {
+        name,
+        func,
+        metric,
+        unit,
+    })
+}
+
+/// Creates a benchmark for a function which requires some state to operate on
+///
+/// State is created using `setup` function once and then reused for all the iterations. Creation of the state
+/// is not included in the measurement. State is created anew only when target is [reset][MeasureTarget::reset()]
+/// which allows to measure the cost of the first call using [`MeasureTargetExt::measure_cold()`].
+pub fn benchmark_fn_with_setup<S, O, I, F>(
+    name: &'static str,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget>
+where
+    S: 'static,
+    I: Fn() -> S + 'static,
+    F: Fn(&mut S) -> O + 'static,
+{
+    assert!(!name.is_empty());
+    Box::new(SetupFunc {
+        name,
+        setup,
+        func,
+        state: None,
+        include_setup: false,
+    })
+}
+
+/// Creates a benchmark for a function together with the setup of its state
+///
+/// Same as [`benchmark_fn_with_setup()`], but for the targets where the setup is the workload itself (eg. building
+/// an index). Each iteration creates fresh state, so the cost of the setup is not amortized, and the measured time
+/// includes both the setup and the call. Reports label such tests `(e2e)`. Existing registrations can be
+/// converted using [`MeasureTargetExt::include_setup()`].
+///
+/// ```rust
+/// use tango_bench::{benchmark_fn_including_setup, IntoBenchmarks};
+///
+/// fn index_benchmarks() -> impl IntoBenchmarks {
+///     [benchmark_fn_including_setup("index", || vec![3, 1, 2], |v| v.sort())]
+/// }
+/// ```
+pub fn benchmark_fn_including_setup<S, O, I, F>(
+    name: &'static str,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget>
+where
+    S: 'static,
+    I: Fn() -> S + 'static,
+    F: Fn(&mut S) -> O + 'static,
+{
+    benchmark_fn_with_setup(name, setup, func).include_setup(true)
+}
+
+/// Creates a benchmark for a function operating on an external resource (a directory, a file, a socket, etc.)
+///
+/// Shorthand for `BenchmarkBuilder::new(name).with_fixture(fixture).with_setup(setup).build(func)` (see
+/// [`BenchmarkBuilder::with_fixture()`]).
+///
+/// ```rust
+/// use std::fs;
+/// use tango_bench::{benchmark_fn_with_fixture, fixture::TempDirFixture, IntoBenchmarks};
+///
+/// fn io_benchmarks() -> impl IntoBenchmarks {
+///     [benchmark_fn_with_fixture(
+///         "write",
+///         || TempDirFixture::create("write"),
+///         |dir| dir.path().join("data"),
+///         |_, file| fs::write(file, [0u8; 512]).unwrap(),
+///     )]
+/// }
+/// ```
+pub fn benchmark_fn_with_fixture<X, S, O, C, I, F>(
+    name: &'static str,
+    fixture: C,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget>
+where
+    X: 'static,
+    S: 'static,
+    C: Fn() -> X + 'static,
+    I: Fn(&X) -> S + 'static,
+    F: Fn(&X, &mut S) -> O + 'static,
+{
+    BenchmarkBuilder::new(name)
+        .with_fixture(fixture)
+        .with_setup(setup)
+        .build(func)
+}
+
+/// Builder of the benchmarks operating on an external resource
+///
+/// ```rust
+/// use std::fs;
+/// use tango_bench::{fixture::TempDirFixture, BenchmarkBuilder, IntoBenchmarks};
+///
+/// fn io_benchmarks() -> impl IntoBenchmarks {
+///     [
+///         BenchmarkBuilder::new("read_dir")
+///             .with_fixture(|| TempDirFixture::create("read_dir"))
+///             .build(|dir| fs::read_dir(dir.path()).unwrap().count()),
+///         BenchmarkBuilder::new("write")
+///             .with_fixture(|| TempDirFixture::create("write"))
+///             .with_setup(|dir| dir.path().join("data"))
+///             .build(|_, file| fs::write(file, [0u8; 512]).unwrap()),
+///     ]
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct BenchmarkBuilder {
+    name: &'static str,
+}
+
+impl BenchmarkBuilder {
+    pub fn new(name: &'static str) -> Self {
+        assert!(!name.is_empty());
+        Self { name }
+    }
+
+    /// Passes a fixture created by `fixture` to the function
+    ///
+    /// The fixture is created once per test before the number of iterations is estimated and is dropped after the
+    /// test finishes, so neither is part of the measurement. Cleanup is expected to be done by the [`Drop`] of the
+    /// fixture (see [`fixture::TempDirFixture`]). When comparing, baseline and candidate create their own fixtures.
+    pub fn with_fixture<X, C: Fn() -> X + 'static>(self, fixture: C) -> FixtureBuilder<C> {
+        FixtureBuilder {
+            name: self.name,
+            fixture,
+        }
+    }
+}
+
+/// Benchmark with a fixture (see [`BenchmarkBuilder::with_fixture()`])
+pub struct FixtureBuilder<C> {
+    name: &'static str,
+    fixture: C,
+}
+
+impl<X: 'static, C: Fn() -> X + 'static> FixtureBuilder<C> {
+    /// Creates the state of the function from the fixture the same way as [`benchmark_fn_with_setup()`]
+    pub fn with_setup<S, I: Fn(&X) -> S + 'static>(self, setup: I) -> FixtureSetupBuilder<C, I> {
+        FixtureSetupBuilder {
+            name: self.name,
+            fixture: self.fixture,
+            setup,
+        }
+    }
+
+    /// Creates a benchmark of a function taking the fixture only
+    pub fn build<O, F: Fn(&X) -> O + 'static>(self, func: F) -> Box<dyn MeasureTarget> {
+        self.with_setup(|_| ())
+            .build(move |fixture, _| func(fixture))
+    }
+}
+
+/// Benchmark with a fixture and a state (see [`FixtureBuilder::with_setup()`])
+pub struct FixtureSetupBuilder<C, I> {
+    name: &'static str,
+    fixture: C,
+    setup: I,
+}
+
+impl<X, S, C, I> FixtureSetupBuilder<C, I>
+where
+    X: 'static,
+    S: 'static,
+    C: Fn() -> X + 'static,
+    I: Fn(&X) -> S + 'static,
+{
+    /// Creates a benchmark of a function taking the fixture and the state
+    pub fn build<O, F: Fn(&X, &mut S) -> O + 'static>(self, func: F) -> Box<dyn MeasureTarget> {
+        Box::new(FixtureFunc {
+            name: self.name,
+            fixture_fn: self.fixture,
+            setup: self.setup,
+            func,
+            fixture: None,
+            state: None,
+            include_setup: false,
+        })
+    }
+}
+
+/// Creates a benchmark for a function processing `batch` needles of a [`Generator`] at once
+///
+/// Results are reported per element (see [`BenchmarkMatrix::add_batch_function()`]).
+///
+/// ```rust
+/// use tango_bench::{benchmark_fn_batch, generators::RandomVec, IntoBenchmarks};
+///
+/// fn sum_benchmarks() -> impl IntoBenchmarks {
+///     [benchmark_fn_batch("sum", RandomVec::<u32>::new(100), 16, |v, needles: &[()]| {
+///         needles.iter().map(|_| v.iter().sum::<u32>()).sum::<u32>()
+///     })]
+/// }
+/// ```
+pub fn benchmark_fn_batch<G, F, O>(
+    name: &str,
+    generator: G,
+    batch: usize,
+    f: F,
+) -> Box<dyn MeasureTarget>
+where
+    G: Generator + 'static,
+    F: Fn(&G::Haystack, &[G::Needle]) -> O + 'static,
+{
+    Box::new(BatchGenFunc::new(
+        name,
+        Shared::new(f),
+        InputCursor::exclusive(generator),
+        batch,
+    ))
+}
+
+/// Creates a benchmark for a function writing its output into a scratch buffer owned by the target
+///
+/// See [`BenchmarkMatrix::add_inout_function()`].
+///
+/// ```rust
+/// use tango_bench::{benchmark_fn_inout, generators::RandomVec, IntoBenchmarks, ScratchReset};
+///
+/// fn copy_benchmarks() -> impl IntoBenchmarks {
+///     [benchmark_fn_inout("copy", RandomVec::<u32>::new(100), ScratchReset::Iteration, |v, _, out: &mut Vec<u32>| {
+///         out.extend_from_slice(v)
+///     })]
+/// }
+/// ```
+pub fn benchmark_fn_inout<G, F, S>(
+    name: &str,
+    generator: G,
+    reset: ScratchReset,
+    f: F,
+) -> Box<dyn MeasureTarget>
+where
+    G: Generator + 'static,
+    S: Default + Resettable + 'static,
+    F: Fn(&G::Haystack, &G::Needle, &mut S) + 'static,
+{
+    Box::new(InOutGenFunc::new(
+        name,
+        Shared::new(f),
+        InputCursor::exclusive(generator),
+        reset,
+    ))
+}
+
 pub trait MeasureTarget {
     /// Measures the performance if the function
     ///
@@ -130,6 +616,31 @@ pub trait MeasureTarget {
     /// [`next_haystack()`]: Self::next_haystack()
     fn measure(&mut self, iterations: usize) -> u64;
 
+    /// Measures the quantity being compared for given number of iterations
+    ///
+    /// By default it is execution time as reported by [`Self::measure()`]. Targets comparing other quantities
+    /// (see [`benchmark_fn_metric()`]) should override this method along with [`Self::unit()`].
+    fn measure_value(&mut self, iterations: usize) -> MeasuredValue {
+        MeasuredValue {
+            value: self.measure(iterations),
+            unit: self.unit(),
+        }
+    }
+
+    /// Measures each of `iterations` calls separately and returns the time of each call (in nanoseconds)
+    ///
+    /// Inputs are prepared outside of the measurement. Each value includes the overhead of reading the timer
+    /// (see [`timer_overhead()`]), so it is only meaningful with a high resolution timer (`hw-timer` feature).
+    /// Returns `None` if the target doesn't support per-call measurement.
+    fn measure_detailed(&mut self, _iterations: usize) -> Option<Vec<u64>> {
+        None
+    }
+
+    /// Unit of the values returned by [`Self::measure_value()`]
+    fn unit(&self) -> Unit {
+        Unit::Nanoseconds
+    }
+
     /// Estimates the number of iterations achievable within given time.
     ///
     /// Time span is given in milliseconds (`time_ms`). Estimate can be an approximation and it is important
@@ -145,878 +656,5402 @@ pub trait MeasureTarget {
     /// Haystack/Needle distinction is described in [`Generator`] trait.
     fn next_haystack(&mut self) -> bool;
 
+    /// Applies incremental changes to the current haystack before the next sample
+    ///
+    /// Called by the harness outside of the timed region between the samples taken on the same haystack, the time
+    /// is accounted as generator time. Both functions of a pair are called the same number of times, so they observe
+    /// the same sequence of changes. Targets using a [`Generator`] delegate to [`Generator::mutate_haystack()`].
+    fn mutate_haystack(&mut self) {}
+
+    /// Returns `true` if the harness should call [`Self::mutate_haystack()`] between the samples
+    fn mutates_haystack(&self) -> bool {
+        false
+    }
+
+    /// Acquires the resources of the target before the test starts
+    ///
+    /// Called by the harness once per test, before the number of iterations is estimated, so the time is not part
+    /// of any measurement. Targets with fixtures (see [`benchmark_fn_with_fixture()`]) create them here.
+    fn prepare(&mut self) {}
+
+    /// Releases the resources acquired by [`Self::prepare()`]
+    ///
+    /// Called by the harness after the test finishes, including the case when it was aborted by an error.
+    fn teardown(&mut self) {}
+
     /// Synchronize RNG state
     ///
     /// If this implementation has linked generator with RNG state, this method should delegate to
     /// [`Generator::sync()`]
     fn sync(&mut self, seed: u64);
 
+    /// Takes the error the inputs of the target failed with since the last call
+    ///
+    /// Targets using a [`Generator`] delegate to [`Generator::take_error()`]. Returns `None` by default.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+
+    /// File the inputs of the target are recorded to or replayed from (see [`Generator::recording()`])
+    fn recording(&self) -> Option<PathBuf> {
+        None
+    }
+
     /// Name of the benchmark
     fn name(&self) -> &str;
-}
-
-struct SimpleFunc<F> {
-    name: &'static str,
-    func: F,
-}
 
-impl<O, F: Fn() -> O> MeasureTarget for SimpleFunc<F> {
-    fn measure(&mut self, iterations: usize) -> u64 {
-        if mem::needs_drop::<O>() {
-            let mut result = Vec::with_capacity(iterations);
-            let start = ActiveTimer::start();
-            for _ in 0..iterations {
-                result.push(black_box((self.func)()));
-            }
-            let time = ActiveTimer::stop(start);
-            drop(result);
-            time
-        } else {
-            let start = ActiveTimer::start();
-            for _ in 0..iterations {
-                black_box((self.func)());
-            }
-            ActiveTimer::stop(start)
-        }
+    /// Name of the generator producing the inputs of the target (see [`Generator::name()`])
+    ///
+    /// Returns `None` if the target doesn't use a generator.
+    fn generator(&self) -> Option<&str> {
+        None
     }
 
-    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
-        let median = median_execution_time(self, 11) as usize;
-        time_ms as usize * NS_TO_MS / median
+    /// Discards all the state accumulated by the target, so the next call is performed as if it was the first one
+    ///
+    /// By default generates a new haystack. Used by cold-start measurements (see [`MeasureTargetExt::measure_cold()`]).
+    fn reset(&mut self) {
+        self.next_haystack();
     }
 
-    fn next_haystack(&mut self) -> bool {
+    /// Returns `true` if the target should be measured in cold-start mode
+    ///
+    /// In this mode each sample consists of exactly one iteration and the target is [reset][Self::reset()]
+    /// before each sample.
+    fn is_cold(&self) -> bool {
         false
     }
 
-    fn name(&self) -> &str {
-        self.name
+    /// Importance of the target relative to the other targets of the suite (see [`MeasureTargetExt::with_weight()`])
+    fn weight(&self) -> f64 {
+        1.
     }
 
-    fn sync(&mut self, _: u64) {}
-}
-
-/// Implementation of a [`MeasureTarget`] which uses [`Generator`] to generates a new payload for a function
-/// each new sample.
-pub struct GenFunc<F, G: Generator> {
-    f: Rc<RefCell<F>>,
-    g: Rc<RefCell<G>>,
-    haystack: Option<G::Haystack>,
-    name: String,
-}
+    /// Name of the tolerance profile the results of the target are judged by (see
+    /// [`MeasureTargetExt::with_profile()`])
+    fn profile(&self) -> Option<&str> {
+        None
+    }
 
-impl<F, O, G> GenFunc<F, G>
-where
-    G: Generator,
-    F: Fn(&G::Haystack, &G::Needle) -> O,
-{
-    pub fn new(name: &str, f: F, g: G) -> Self {
-        let f = Rc::new(RefCell::new(f));
-        let g = Rc::new(RefCell::new(g));
-        Self::from_ref_cell(name, f, g)
+    /// Settings of the profile overridden for the target (see [`MeasureTargetExt::with_tolerance()`])
+    fn tolerance(&self) -> Profile {
+        Profile::EMPTY
     }
 
-    fn from_ref_cell(name: &str, f: Rc<RefCell<F>>, g: Rc<RefCell<G>>) -> Self {
-        Self {
-            name: format!("{}/{}", name, g.borrow().name()),
-            haystack: None,
-            f,
-            g,
-        }
+    /// Cost of generating inputs for the target
+    ///
+    /// Should be populated during [`Self::estimate_iterations()`]. The largest costs reported by both functions of a
+    /// paired test are used to plan the sampling. Returns `None` if not known.
+    fn cost_hints(&self) -> Option<CostHints> {
+        None
     }
-}
 
-impl<F, O, G> MeasureTarget for GenFunc<F, G>
-where
-    G: Generator,
-    F: Fn(&G::Haystack, &G::Needle) -> O,
-{
-    fn measure(&mut self, iterations: usize) -> u64 {
-        let mut g = self.g.borrow_mut();
-        let haystack = &*self.haystack.get_or_insert_with(|| g.next_haystack());
-        let f = self.f.borrow_mut();
+    /// Amount of data processed by each iteration, the mean over the haystacks generated so far
+    ///
+    /// Reported by the targets using a [`Generator`] which knows the sizes of its haystacks (see
+    /// [`Generator::haystack_size()`]). Returns `None` if not known.
+    fn throughput(&self) -> Option<Throughput> {
+        None
+    }
 
-        if mem::needs_drop::<O>() {
-            let mut result = Vec::with_capacity(iterations);
-            let start = ActiveTimer::start();
-            for _ in 0..iterations {
-                let needle = g.next_needle(haystack);
-                result.push(black_box((f)(haystack, &needle)));
-            }
-            let time = ActiveTimer::stop(start);
-            drop(result);
-            time
-        } else {
-            let start = ActiveTimer::start();
-            for _ in 0..iterations {
-                let needle = g.next_needle(haystack);
-                black_box((f)(haystack, &needle));
-            }
-            ActiveTimer::stop(start)
-        }
+    /// Runs the function on `samples` freshly generated inputs and returns debug representations of inputs and outputs
+    ///
+    /// Calls are not measured. Returns `None` if the target doesn't support output verification (see
+    /// [`BenchmarkMatrix::add_function_verified()`]).
+    fn verify(&mut self, _samples: usize) -> Option<Vec<VerificationSample>> {
+        None
     }
 
-    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
-        // Here we relying on the fact that measure() is not generating a new haystack
-        // without a call to next_haystack()
-        let median = median_execution_time(self, 10);
-        (time_ms as usize * NS_TO_MS) / median as usize
+    /// Identity of the code measured by the target
+    ///
+    /// Targets with the same fingerprint run exactly the same code, so comparing them is pointless (eg. the same
+    /// function is registered as both baseline and candidate by mistake). Returns `None` if the identity can't be
+    /// established.
+    fn code_fingerprint(&self) -> Option<u64> {
+        None
     }
 
-    fn next_haystack(&mut self) -> bool {
-        self.haystack = Some(self.g.borrow_mut().next_haystack());
-        true
+    /// Address of the code taking the samples of the target
+    ///
+    /// Allows to attribute the samples of a profiler to the tests (see `--emit-symbol-map`). Returns `None` if
+    /// not known.
+    fn entry_point(&self) -> Option<usize> {
+        None
     }
 
-    fn name(&self) -> &str {
-        &self.name
+    /// Returns `true` if the setup of the target is included in the measurement (see
+    /// [`benchmark_fn_including_setup()`])
+    fn includes_setup(&self) -> bool {
+        false
     }
 
-    fn sync(&mut self, seed: u64) {
-        self.g.borrow_mut().sync(seed)
+    /// Includes or excludes the setup of the target from the measurement
+    ///
+    /// Targets without separate setup stage measure everything they do anyway, so by default it is a no-op.
+    fn set_includes_setup(&mut self, _include: bool) {}
+
+    /// Number of needles processed by each iteration (see [`BenchmarkMatrix::add_batch_function()`])
+    ///
+    /// The harness divides the measured values by this number, so targets processing batches are reported per
+    /// element.
+    fn batch_len(&self) -> usize {
+        1
     }
 }
 
-/// Matrix of functions is used to perform benchmark with different generator strategies.
-///
-/// It is a common task to benchmark function with different payload size and/or different structure of the payload.
-/// `BenchmarkMatrix` creates a new [`MeasureTarget`] for each unique combination of [`Generator`]
-/// and tested function.
-///
-/// # Example
-/// ```rust
-/// use tango_bench::{generators::RandomVec, BenchmarkMatrix, IntoBenchmarks};
-///
-/// fn sum_positive(haystack: &Vec<u32>, _: &()) -> u32 {
-///     haystack.iter().copied().filter(|v| *v > 0).sum()
-/// }
-///
-/// fn sum_benchmarks() -> impl IntoBenchmarks {
-///     BenchmarkMatrix::with_params([100, 1_000, 10_000], RandomVec::new)
-///         .add_function("sum_positive", sum_positive)
-/// }
-/// ```
-pub struct BenchmarkMatrix<G> {
-    generators: Vec<Rc<RefCell<G>>>,
-    functions: Vec<Box<dyn MeasureTarget>>,
+/// Unit of the quantity measured by a [`MeasureTarget`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Unit {
+    Nanoseconds,
+    Bytes,
+    Count,
+    Microjoules,
 }
 
-impl<G: Generator> BenchmarkMatrix<G> {
-    pub fn new(generator: G) -> Self {
-        let generator = Rc::new(RefCell::new(generator));
-        Self {
-            generators: vec![generator],
-            functions: vec![],
-        }
-    }
+/// Cost of the generator of a target (see [`MeasureTarget::cost_hints()`])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CostHints {
+    /// Time to generate a haystack in nanoseconds
+    pub haystack_ns_estimate: u64,
 
-    /// New matrix with generator created for a given set of parameters
-    pub fn with_params<P>(params: impl IntoIterator<Item = P>, generator: impl Fn(P) -> G) -> Self {
-        let generators: Vec<_> = params
-            .into_iter()
-            .map(generator)
-            .map(RefCell::new)
-            .map(Rc::new)
-            .collect();
+    /// Time to generate a needle in nanoseconds (included in the measured time)
+    pub needle_ns_estimate: u64,
+}
+
+impl CostHints {
+    /// The most expensive of both hints
+    pub fn max(self, other: Self) -> Self {
         Self {
-            generators,
-            functions: vec![],
+            haystack_ns_estimate: self.haystack_ns_estimate.max(other.haystack_ns_estimate),
+            needle_ns_estimate: self.needle_ns_estimate.max(other.needle_ns_estimate),
         }
     }
+}
 
-    /// Add a new generator to the matrix for each parameter in the given iterator.
-    pub fn add_generators_with_params<P>(
-        mut self,
-        params: impl IntoIterator<Item = P>,
-        generator: impl Fn(P) -> G,
-    ) -> Self {
-        let generators = params
-            .into_iter()
-            .map(generator)
-            .map(RefCell::new)
-            .map(Rc::new);
-        self.generators.extend(generators);
-        self
-    }
+/// Size of the input processed by a single call of a function (see [`Generator::haystack_size()`])
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HaystackSize {
+    Bytes(usize),
+    Elements(usize),
+}
 
-    pub fn add_function<F, O>(mut self, name: &str, f: F) -> Self
-    where
-        G: 'static,
-        F: Fn(&G::Haystack, &G::Needle) -> O + 'static,
-    {
-        let f = Rc::new(RefCell::new(f));
-        self.generators
-            .iter()
-            .map(Rc::clone)
-            .map(|g| GenFunc::from_ref_cell(name, Rc::clone(&f), g))
-            .map(Box::new)
-            .for_each(|f| self.functions.push(f));
-        self
-    }
+/// Unit of the data processed by a target
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ThroughputUnit {
+    Bytes,
+    Elements,
 }
 
-impl<G> IntoBenchmarks for BenchmarkMatrix<G> {
-    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
-        assert!(!self.functions.is_empty(), "No functions was given");
-        self.functions
-    }
+/// Amount of data processed by each iteration of a target (see [`MeasureTarget::throughput()`])
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Throughput {
+    pub unit: ThroughputUnit,
+
+    /// Mean size of the input processed by an iteration
+    pub per_iteration: f64,
 }
 
-pub trait IntoBenchmarks {
-    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>>;
+/// Sizes of the haystacks generated by a target, reported as [`MeasureTarget::throughput()`]
+#[derive(Clone, Copy, Debug, Default)]
+struct HaystackSizes {
+    unit: Option<ThroughputUnit>,
+    total: f64,
+    count: usize,
 }
 
-impl<const N: usize> IntoBenchmarks for [Box<dyn MeasureTarget>; N] {
-    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
-        self.into_iter().collect()
+impl HaystackSizes {
+    fn add(&mut self, size: Option<HaystackSize>) {
+        let (unit, size) = match size {
+            Some(HaystackSize::Bytes(size)) => (ThroughputUnit::Bytes, size),
+            Some(HaystackSize::Elements(size)) => (ThroughputUnit::Elements, size),
+            None => return,
+        };
+        self.unit = Some(unit);
+        self.total += size as f64;
+        self.count += 1;
     }
-}
 
-impl IntoBenchmarks for Vec<Box<dyn MeasureTarget>> {
-    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
-        self
+    fn mean(&self) -> Option<Throughput> {
+        Some(Throughput {
+            unit: self.unit?,
+            per_iteration: self.total / self.count as f64,
+        })
     }
 }
 
-/// Generates the payload for the benchmarking functions
-///
-/// One of the most important parts of the benchmarking process is generating the payload to test the algorithm. This /// is what this trait is doing. Test function registered in the system can accepts two arguments:
-/// - *haystack* - usually the data structure we're testing the algorithm on
-/// - *needle* - the supplementary used to test the algorithm.
-///
-/// ## Haystack
-/// Haystack is typically some sort of a collection that is used in benchmarking. It can be quite large and
-/// expensive to generate, because it is generated once per sample or less. The frequency of haystack generation
-/// is controlled by [`MeasurementSettings::samples_per_haystack`].
-///
-/// ## Needle
-/// Needle is usually some type of query that is presented to the algorithm. In case of searching algorithm it
-/// can be value we search in the collection.
-///
-/// Important distinction between haystack and needle is that haystack generation is not included in timing while
-/// needle generation is a part of measurement loop. Therefore needle generation should be relativley lightweight.
-///
-/// Sometimes haystack generation might be so expensive that it makes sense to leave haystack fixed and provide
-/// randomness by generating different needles. For example, instead of generating new random `Vec<T>` for each sample
-/// it might be more practical to generate a single `Vec` and a new `Range<usize>` as a haystack at each iteration.
-///
-/// It might be the case that the algorithm being tested is not using both type of values.
-/// In this case corresponding value type should unit type – `()`.
-/// Depending on the type of algorithm you might not need to generate both of them. Here are some examples:
-///
-/// | Algorithm | Haystack | Needle |
-/// |----------|----------|--------|
-/// | Searching in a string | String | substrung to search for and/or range to search over |
-/// | Searching in a collection | Collection | Value to search for and/or range to search over |
-/// | Soring | Collection | – |
-/// | Numerical computation: factorial, DP problems, etc. | – | Input parameters |
-///
-/// Tango orchestrates the generating of haystack and needle and guarantees that both benchmarking
-/// functions are called with the same input parameters. Therefore performance difference is predictable.
-pub trait Generator {
-    type Haystack;
-    type Needle;
+/// Cumulative value of a measured quantity for all iterations of a sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeasuredValue {
+    pub value: u64,
+    pub unit: Unit,
+}
 
-    /// Generates next random haystack for the benchmark
+/// Additional combinators for boxed [`MeasureTarget`]s
+pub trait MeasureTargetExt {
+    /// Measures first-call (cold-start) performance of a target
     ///
-    /// All iterations within sample are using the same haystack. Haystack are changed only between samples
-    /// (see. [`MeasureTarget::next_haystack()`]).
-    fn next_haystack(&mut self) -> Self::Haystack;
+    /// Some functions trade steady-state speed for an expensive lazy initialization on the first call, which
+    /// is hidden when averaging over many iterations. In cold mode each sample runs exactly one iteration on
+    /// a freshly reset target, so the distribution reflects first-call latency. Reports label such tests `(cold)`.
+    ///
+    /// ```rust
+    /// use tango_bench::{benchmark_fn_with_setup, IntoBenchmarks, MeasureTargetExt};
+    ///
+    /// fn table_benchmarks() -> impl IntoBenchmarks {
+    ///     [benchmark_fn_with_setup("table", Vec::<u8>::new, |t| t.len()).measure_cold()]
+    /// }
+    /// ```
+    fn measure_cold(self) -> Box<dyn MeasureTarget>;
 
-    /// Generates next random needle for the benchmark
+    /// Assigns the importance of a target in the suite-level summary (`1.0` by default)
     ///
-    /// This method should be relatively lightweight, because the execution time of this method is included
-    /// in reported by the benchmark time. Implementation are given haystack generated by
-    /// [`Self::next_haystack()`] which will be used for benchmark execution.
-    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle;
+    /// Not all the tests matter equally: a small regression on a hot path may outweigh a large one on an error
+    /// path. The suite geometric mean and the weighted regression checked by `--max-weighted-regression` take
+    /// weights into account. Weights of the candidate executable are used.
+    ///
+    /// ```rust
+    /// use tango_bench::{benchmark_fn, IntoBenchmarks, MeasureTargetExt};
+    ///
+    /// fn parser_benchmarks() -> impl IntoBenchmarks {
+    ///     [benchmark_fn("parse", || "42".parse::<u32>()).with_weight(5.0)]
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the weight is not a finite positive number.
+    fn with_weight(self, weight: f64) -> Box<dyn MeasureTarget>;
 
-    /// Syncs internal RNG-state of this generator with given seed
+    /// Includes the setup of the state in the measurement (see [`benchmark_fn_including_setup()`])
     ///
-    /// For benchmarks to be predictable the harness periodically synchronize the RNG state of all the generators.
-    /// If applicable, implementations should set internal RNG state with the value derived from given `seed`.
-    /// Implementation are free to transform seed value in any meaningfull way (like taking only lower 32 bits)
-    /// as long as this transformation is deterministic.
-    fn sync(&mut self, seed: u64);
+    /// Has no effect on the targets without separate setup stage.
+    ///
+    /// ```rust
+    /// use tango_bench::{benchmark_fn_with_setup, IntoBenchmarks, MeasureTargetExt};
+    ///
+    /// fn index_benchmarks() -> impl IntoBenchmarks {
+    ///     [benchmark_fn_with_setup("index", || vec![3, 1, 2], |v| v.sort()).include_setup(true)]
+    /// }
+    /// ```
+    fn include_setup(self, include: bool) -> Box<dyn MeasureTarget>;
 
-    /// Name of generator
-    fn name(&self) -> &str {
-        let name = type_name::<Self>();
-        if let Some(idx) = name.rfind("::") {
-            // it's safe to operate on byte offsets here because ':' symbols is 1-byte ascii
-            &name[idx + 2..]
-        } else {
-            name
-        }
+    /// Assigns a tolerance profile the results of a target are judged by (see [`profile`])
+    ///
+    /// Profile is defined in [`cli::MainOptions::profiles`]. Profile of the candidate executable is used.
+    ///
+    /// ```rust
+    /// use tango_bench::{benchmark_fn, IntoBenchmarks, MeasureTargetExt};
+    ///
+    /// fn io_benchmarks() -> impl IntoBenchmarks {
+    ///     [benchmark_fn("temp_dir", std::env::temp_dir).with_profile("io")]
+    /// }
+    /// ```
+    fn with_profile(self, name: &str) -> Box<dyn MeasureTarget>;
+
+    /// Overrides some of the settings of the profile of a target (see [`profile`])
+    ///
+    /// ```rust
+    /// use tango_bench::{benchmark_fn, profile::Profile, IntoBenchmarks, MeasureTargetExt};
+    ///
+    /// fn io_benchmarks() -> impl IntoBenchmarks {
+    ///     let tolerance = Profile { min_samples: Some(100), ..Profile::EMPTY };
+    ///     [benchmark_fn("temp_dir", std::env::temp_dir).with_profile("io").with_tolerance(tolerance)]
+    /// }
+    /// ```
+    fn with_tolerance(self, tolerance: Profile) -> Box<dyn MeasureTarget>;
+}
+
+impl MeasureTargetExt for Box<dyn MeasureTarget> {
+    fn measure_cold(self) -> Box<dyn MeasureTarget> {
+        Decorated::boxed(self, Cold)
+    }
+
+    fn with_weight(self, weight: f64) -> Box<dyn MeasureTarget> {
+        assert!(
+            weight.is_finite() && weight > 0.,
+            "Weight should be a finite positive number: {}",
+            weight
+        );
+        Decorated::boxed(self, Weight(weight))
+    }
+
+    fn include_setup(mut self, include: bool) -> Box<dyn MeasureTarget> {
+        self.set_includes_setup(include);
+        self
+    }
+
+    fn with_profile(self, name: &str) -> Box<dyn MeasureTarget> {
+        let name = Some(name.to_string());
+        Decorated::boxed(
+            self,
+            Profiled {
+                name,
+                tolerance: Profile::EMPTY,
+            },
+        )
+    }
+
+    fn with_tolerance(self, tolerance: Profile) -> Box<dyn MeasureTarget> {
+        Decorated::boxed(
+            self,
+            Profiled {
+                name: None,
+                tolerance,
+            },
+        )
     }
 }
 
-pub(crate) trait Reporter {
-    fn on_complete(&mut self, results: &RunResult);
+struct SimpleFunc<F> {
+    name: &'static str,
+    func: F,
 }
 
-/// Describes basic settings for the benchmarking process
-///
-/// This structure is passed to [`cli::run()`].
-///
-/// Should be created only with overriding needed properties, like so:
-/// ```rust
-/// use tango_bench::MeasurementSettings;
-///
-/// let settings = MeasurementSettings {
-///     min_iterations_per_sample: 1000,
-///     ..Default::default()
-/// };
-/// ```
-#[derive(Clone, Copy, Debug)]
-pub struct MeasurementSettings {
-    pub filter_outliers: bool,
+impl<O, F: Fn() -> O> MeasureTarget for SimpleFunc<F> {
+    fn measure(&mut self, iterations: usize) -> u64 {
+        if mem::needs_drop::<O>() {
+            measure_retaining(iterations, || black_box((self.func)()))
+        } else {
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                black_box((self.func)());
+            }
+            ActiveTimer::stop(start)
+        }
+    }
 
-    /// The number of samples per one generated haystack
-    pub samples_per_haystack: usize,
+    fn measure_detailed(&mut self, iterations: usize) -> Option<Vec<u64>> {
+        Some(measure_calls(iterations, || (), |_| (self.func)()))
+    }
 
-    /// Minimum number of iterations in a sample for each of 2 tested functions
-    pub min_iterations_per_sample: usize,
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        let median = median_execution_time(self, 11) as usize;
+        time_ms as usize * NS_TO_MS / median
+    }
 
-    /// The number of iterations in a sample for each of 2 tested functions
-    pub max_iterations_per_sample: usize,
+    fn next_haystack(&mut self) -> bool {
+        false
+    }
 
-    pub sampler_type: SamplerType,
+    fn name(&self) -> &str {
+        self.name
+    }
 
-    /// Size of a CPU cache firewall in KBytes
-    ///
-    /// If set, the scheduler will perform a dummy data read between samples generation to spoil the CPU cache
-    ///
-    /// Cache firewall is a way to reduce the impact of the CPU cache on the benchmarking process. It tries
-    /// to minimize discrepancies in performance between two algorithms due to the CPU cache state.
-    pub cache_firewall: Option<usize>,
+    fn sync(&mut self, _: u64) {}
 
-    /// If true, scheduler will perform a yield of control back to the OS before taking each sample
-    ///
-    /// Yielding control to the OS is a way to reduce the impact of OS scheduler on the benchmarking process.
-    pub yield_before_sample: bool,
+    fn code_fingerprint(&self) -> Option<u64> {
+        fn call<F: Fn() -> O, O>(f: &F) -> O {
+            f()
+        }
+        code_fingerprint::<F>(call::<F, O> as fn(&F) -> O as usize)
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum SamplerType {
-    Flat,
-    Linear,
-    Random,
+/// Address of [`MeasureTarget::measure()`] of a given target type (see [`MeasureTarget::entry_point()`])
+fn entry_point<T: MeasureTarget>() -> usize {
+    <T as MeasureTarget>::measure as fn(&mut T, usize) -> u64 as usize
 }
 
-/// Performs a dummy reads from memory to spoil given amount of CPU cache
+/// Cargo target directory of the running benchmark
 ///
-/// Uses cache aligned data arrays to perform minimum amount of reads possible to spoil the cache
-struct CacheFirewall {
-    cache_lines: Vec<CacheLine>,
+/// Taken from `CARGO_TARGET_DIR` or derived from the location of the running executable
+/// (`target/<profile>/deps/<bench>`). Falls back to `target` in the working directory.
+pub(crate) fn target_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            let exe = std::env::current_exe().ok()?;
+            let deps = exe.parent().filter(|d| d.ends_with("deps"))?;
+            deps.parent()?.parent().map(Path::to_path_buf)
+        })
+        .unwrap_or_else(|| PathBuf::from("target"))
 }
 
-impl CacheFirewall {
-    fn new(bytes: usize) -> Self {
-        let n = bytes / mem::size_of::<CacheLine>();
+/// Fingerprint of the code of a function type (see [`MeasureTarget::code_fingerprint()`])
+///
+/// `instance` is the address of an instance of a generic function calling the function. Each function type gets its
+/// own instance, so the address identifies the code. Only zero-sized functions (function items and closures without
+/// captures) are fingerprinted: behavior of a capturing closure also depends on the captured values.
+fn code_fingerprint<F>(instance: usize) -> Option<u64> {
+    (mem::size_of::<F>() == 0).then_some(instance as u64)
+}
+
+struct MetricFunc<F, M> {
+    name: &'static str,
+    func: F,
+    metric: M,
+    unit: Unit,
+}
+
+impl<O, F: Fn() -> O, M: Fn(&O) -> u64> MeasureTarget for MetricFunc<F, M> {
+    fn measure(&mut self, iterations: usize) -> u64 {
+        self.measure_value(iterations).value
+    }
+
+    fn measure_value(&mut self, iterations: usize) -> MeasuredValue {
+        let value = (0..iterations)
+            .map(|_| (self.metric)(&black_box((self.func)())))
+            .sum();
+        MeasuredValue {
+            value,
+            unit: self.unit,
+        }
+    }
+
+    fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        // measure() is not returning time for this target, so timing calls here
+        const CALLS: u64 = 11;
+        let start = ActiveTimer::start();
+        self.measure_value(CALLS as usize);
+        let time = (ActiveTimer::stop(start) / CALLS).max(1);
+        time_ms as usize * NS_TO_MS / time as usize
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn sync(&mut self, _: u64) {}
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+struct SetupFunc<S, I, F> {
+    name: &'static str,
+    setup: I,
+    func: F,
+    state: Option<S>,
+
+    /// Each iteration creates fresh state and the setup is measured along with the call
+    include_setup: bool,
+}
+
+impl<S, O, I, F> MeasureTarget for SetupFunc<S, I, F>
+where
+    I: Fn() -> S,
+    F: Fn(&mut S) -> O,
+{
+    fn measure(&mut self, iterations: usize) -> u64 {
+        if self.include_setup {
+            // States and outputs are dropped outside of the measurement
+            return measure_retaining(iterations, || {
+                let mut state = (self.setup)();
+                let output = black_box((self.func)(&mut state));
+                (state, output)
+            });
+        }
+
+        let state = self.state.get_or_insert_with(&self.setup);
+
+        if mem::needs_drop::<O>() {
+            measure_retaining(iterations, || black_box((self.func)(state)))
+        } else {
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                black_box((self.func)(state));
+            }
+            ActiveTimer::stop(start)
+        }
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        let median = median_execution_time(self, 11) as usize;
+        time_ms as usize * NS_TO_MS / median
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn sync(&mut self, _: u64) {}
+
+    fn reset(&mut self) {
+        self.state = Some((self.setup)());
+    }
+
+    fn includes_setup(&self) -> bool {
+        self.include_setup
+    }
+
+    fn set_includes_setup(&mut self, include: bool) {
+        self.include_setup = include;
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+struct FixtureFunc<X, S, C, I, F> {
+    name: &'static str,
+    fixture_fn: C,
+    setup: I,
+    func: F,
+    fixture: Option<X>,
+    state: Option<S>,
+
+    /// Each iteration creates fresh state and the setup is measured along with the call
+    include_setup: bool,
+}
+
+impl<X, S, O, C, I, F> MeasureTarget for FixtureFunc<X, S, C, I, F>
+where
+    C: Fn() -> X,
+    I: Fn(&X) -> S,
+    F: Fn(&X, &mut S) -> O,
+{
+    fn measure(&mut self, iterations: usize) -> u64 {
+        // Targets measured without the harness (eg. in tests) are not prepared
+        let fixture = self.fixture.get_or_insert_with(&self.fixture_fn);
+
+        if self.include_setup {
+            // States and outputs are dropped outside of the measurement
+            return measure_retaining(iterations, || {
+                let mut state = (self.setup)(fixture);
+                let output = black_box((self.func)(fixture, &mut state));
+                (state, output)
+            });
+        }
+
+        let state = self.state.get_or_insert_with(|| (self.setup)(fixture));
+
+        if mem::needs_drop::<O>() {
+            measure_retaining(iterations, || black_box((self.func)(fixture, state)))
+        } else {
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                black_box((self.func)(fixture, state));
+            }
+            ActiveTimer::stop(start)
+        }
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        let median = median_execution_time(self, 11) as usize;
+        time_ms as usize * NS_TO_MS / median
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        false
+    }
+
+    fn prepare(&mut self) {
+        self.teardown();
+        self.fixture = Some((self.fixture_fn)());
+    }
+
+    fn teardown(&mut self) {
+        // State may refer to the resources of the fixture, so it goes first
+        self.state = None;
+        self.fixture = None;
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn sync(&mut self, _: u64) {}
+
+    fn reset(&mut self) {
+        let fixture = self.fixture.get_or_insert_with(&self.fixture_fn);
+        self.state = Some((self.setup)(fixture));
+    }
+
+    fn includes_setup(&self) -> bool {
+        self.include_setup
+    }
+
+    fn set_includes_setup(&mut self, include: bool) {
+        self.include_setup = include;
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+/// Properties of a target changed by a [`Decorated`] wrapper, the rest of the target is used as is
+trait Decoration {
+    fn next_haystack(&mut self, target: &mut dyn MeasureTarget) -> bool {
+        target.next_haystack()
+    }
+
+    fn is_cold(&self, target: &dyn MeasureTarget) -> bool {
+        target.is_cold()
+    }
+
+    fn weight(&self, target: &dyn MeasureTarget) -> f64 {
+        target.weight()
+    }
+
+    fn profile<'a>(&'a self, target: &'a dyn MeasureTarget) -> Option<&'a str> {
+        target.profile()
+    }
+
+    fn tolerance(&self, target: &dyn MeasureTarget) -> Profile {
+        target.tolerance()
+    }
+}
+
+/// Wrapper created by the combinators of [`MeasureTargetExt`]
+///
+/// Forwards everything to the wrapped target except for the properties changed by the decoration.
+struct Decorated<D> {
+    target: Box<dyn MeasureTarget>,
+    decoration: D,
+}
+
+impl<D: Decoration + 'static> Decorated<D> {
+    fn boxed(target: Box<dyn MeasureTarget>, decoration: D) -> Box<dyn MeasureTarget> {
+        Box::new(Self { target, decoration })
+    }
+}
+
+/// Cold-start decoration (see [`MeasureTargetExt::measure_cold()`])
+///
+/// Each new haystack requested by the harness resets the underlying target
+struct Cold;
+
+impl Decoration for Cold {
+    fn next_haystack(&mut self, target: &mut dyn MeasureTarget) -> bool {
+        target.reset();
+        true
+    }
+
+    fn is_cold(&self, _: &dyn MeasureTarget) -> bool {
+        true
+    }
+}
+
+/// Decoration created by [`MeasureTargetExt::with_weight()`]
+struct Weight(f64);
+
+impl Decoration for Weight {
+    fn weight(&self, _: &dyn MeasureTarget) -> f64 {
+        self.0
+    }
+}
+
+/// Tolerance profile and the overrides of its settings (see [`MeasureTargetExt::with_profile()`])
+struct Profiled {
+    name: Option<String>,
+    tolerance: Profile,
+}
+
+impl Decoration for Profiled {
+    fn profile<'a>(&'a self, target: &'a dyn MeasureTarget) -> Option<&'a str> {
+        self.name.as_deref().or_else(|| target.profile())
+    }
+
+    fn tolerance(&self, target: &dyn MeasureTarget) -> Profile {
+        target.tolerance().merged(&self.tolerance)
+    }
+}
+
+impl<D: Decoration> MeasureTarget for Decorated<D> {
+    fn measure(&mut self, iterations: usize) -> u64 {
+        self.target.measure(iterations)
+    }
+
+    fn measure_value(&mut self, iterations: usize) -> MeasuredValue {
+        self.target.measure_value(iterations)
+    }
+
+    fn measure_detailed(&mut self, iterations: usize) -> Option<Vec<u64>> {
+        self.target.measure_detailed(iterations)
+    }
+
+    fn unit(&self) -> Unit {
+        self.target.unit()
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        self.target.estimate_iterations(time_ms)
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        self.decoration.next_haystack(self.target.as_mut())
+    }
+
+    fn mutate_haystack(&mut self) {
+        self.target.mutate_haystack()
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.target.mutates_haystack()
+    }
+
+    fn prepare(&mut self) {
+        self.target.prepare()
+    }
+
+    fn teardown(&mut self) {
+        self.target.teardown()
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.target.sync(seed)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.target.take_error()
+    }
+
+    fn recording(&self) -> Option<PathBuf> {
+        self.target.recording()
+    }
+
+    fn name(&self) -> &str {
+        self.target.name()
+    }
+
+    fn generator(&self) -> Option<&str> {
+        self.target.generator()
+    }
+
+    fn reset(&mut self) {
+        self.target.reset()
+    }
+
+    fn is_cold(&self) -> bool {
+        self.decoration.is_cold(self.target.as_ref())
+    }
+
+    fn weight(&self) -> f64 {
+        self.decoration.weight(self.target.as_ref())
+    }
+
+    fn profile(&self) -> Option<&str> {
+        self.decoration.profile(self.target.as_ref())
+    }
+
+    fn tolerance(&self) -> Profile {
+        self.decoration.tolerance(self.target.as_ref())
+    }
+
+    fn cost_hints(&self) -> Option<CostHints> {
+        self.target.cost_hints()
+    }
+
+    fn throughput(&self) -> Option<Throughput> {
+        self.target.throughput()
+    }
+
+    fn verify(&mut self, samples: usize) -> Option<Vec<VerificationSample>> {
+        self.target.verify(samples)
+    }
+
+    fn code_fingerprint(&self) -> Option<u64> {
+        self.target.code_fingerprint()
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        self.target.entry_point()
+    }
+
+    fn includes_setup(&self) -> bool {
+        self.target.includes_setup()
+    }
+
+    fn set_includes_setup(&mut self, include: bool) {
+        self.target.set_includes_setup(include)
+    }
+
+    fn batch_len(&self) -> usize {
+        self.target.batch_len()
+    }
+}
+
+/// Controls whether benchmarked functions may observe each other's modifications of a haystack
+///
+/// Functions receive haystack by a shared reference, but nothing prevents them from mutating interior state
+/// (`Cell`/`RefCell`, memoization caches) or from changing the conditions of the next call in a less obvious way
+/// (eg. faulting in pages of a lazily allocated buffer).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HaystackIsolation {
+    /// The same haystack instance is used for all the samples until the next haystack is generated
+    ///
+    /// Appropriate for the vast majority of the functions which treat haystack as read-only. Has no overhead.
+    #[default]
+    Shared,
+
+    /// Each sample is measured on a fresh clone of the haystack
+    ///
+    /// Appropriate when the function mutates interior state of a haystack and the measurement should not depend
+    /// on the modifications made by the previous samples. The clone is made outside of the timed region,
+    /// but cloning large haystacks adds to the test wall time. In Compare mode baseline and candidate never share
+    /// haystacks, because each binary generates its own.
+    ClonedPerFunction,
+}
+
+/// Buffer an in-out function writes its output into (see [`BenchmarkMatrix::add_inout_function()`])
+pub trait Resettable {
+    /// Brings the buffer back to its initial state, preferably keeping the allocated memory
+    fn reset(&mut self);
+}
+
+impl<T> Resettable for Vec<T> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+}
+
+impl Resettable for String {
+    fn reset(&mut self) {
+        self.clear()
+    }
+}
+
+/// Controls when the scratch buffer of an in-out function is reset (see [`BenchmarkMatrix::add_inout_function()`])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScratchReset {
+    /// Buffer is reset before each sample, so the iterations of a sample write into the same buffer
+    #[default]
+    Sample,
+
+    /// Buffer is reset before each iteration
+    ///
+    /// Each iteration is timed separately to keep the reset out of the measurement, so the overhead of the timer
+    /// is included once per iteration (see [`timer_overhead()`]). Use for the functions which are only
+    /// meaningful on a fresh buffer (eg. appending to a `Vec`).
+    Iteration,
+
+    /// Buffer is never reset, the function writes over whatever the previous iterations left
+    Never,
+}
+
+type CloneFn<T> = fn(&T) -> T;
+
+type VerifyFn<F, G> = fn(&F, &mut G, usize) -> Vec<VerificationSample>;
+
+/// Maximum length of the input debug representation reported for mismatched outputs
+const MAX_INPUT_DEBUG_LEN: usize = 200;
+
+/// Input and output of a single verification call
+///
+/// Baseline and candidate are compiled into different executables, so the outputs are compared by their
+/// [`Debug`] representation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationSample {
+    /// Haystack and needle the function was called with (truncated)
+    pub input: String,
+
+    /// Output of the function
+    pub output: String,
+}
+
+fn verify_outputs<F, O, G>(f: &F, g: &mut G, samples: usize) -> Vec<VerificationSample>
+where
+    G: Generator,
+    G::Haystack: Debug,
+    G::Needle: Debug,
+    F: Fn(&G::Haystack, &G::Needle) -> O,
+    O: Debug,
+{
+    (0..samples)
+        .map(|_| {
+            let haystack = g.next_haystack();
+            let needle = g.next_needle(&haystack);
+            let output = f(&haystack, &needle);
+            let input = (&haystack, &needle);
+            VerificationSample {
+                input: debug_truncated(&input, MAX_INPUT_DEBUG_LEN),
+                output: format!("{:?}", output),
+            }
+        })
+        .collect()
+}
+
+/// Result of comparing baseline and candidate outputs on the same inputs
+#[derive(Debug, PartialEq)]
+pub(crate) enum Verification {
+    /// Neither of the functions support output verification
+    NotApplicable,
+
+    /// Verification is not possible for a given reason
+    Skipped(&'static str),
+
+    Passed,
+    Mismatch(OutputMismatch),
+}
+
+impl Verification {
+    pub(crate) fn new(
+        baseline: Option<&[VerificationSample]>,
+        candidate: Option<&[VerificationSample]>,
+    ) -> Self {
+        let (baseline, candidate) = match (baseline, candidate) {
+            (Some(baseline), Some(candidate)) => (baseline, candidate),
+            (None, None) => return Verification::NotApplicable,
+            (None, Some(_)) => return Verification::Skipped("baseline doesn't support it"),
+            (Some(_), None) => return Verification::Skipped("candidate doesn't support it"),
+        };
+
+        // Different inputs mean generators are not the same in both versions, so outputs are not comparable
+        let same_inputs = baseline.len() == candidate.len()
+            && baseline
+                .iter()
+                .zip(candidate)
+                .all(|(b, c)| b.input == c.input);
+        if !same_inputs {
+            return Verification::Skipped("baseline and candidate inputs are different");
+        }
+
+        let mut mismatched = baseline
+            .iter()
+            .zip(candidate)
+            .enumerate()
+            .filter(|(_, (b, c))| b.output != c.output);
+        let Some((sample, (b, c))) = mismatched.next() else {
+            return Verification::Passed;
+        };
+        Verification::Mismatch(OutputMismatch {
+            sample,
+            mismatched: 1 + mismatched.count(),
+            total: baseline.len(),
+            input: b.input.clone(),
+            baseline: b.output.clone(),
+            candidate: c.output.clone(),
+        })
+    }
+}
+
+/// Baseline and candidate produced different outputs (details of the first mismatched sample)
+#[derive(Debug, PartialEq)]
+pub(crate) struct OutputMismatch {
+    sample: usize,
+    mismatched: usize,
+    total: usize,
+    input: String,
+    baseline: String,
+    candidate: String,
+}
+
+impl fmt::Display for OutputMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MAX_OUTPUT_LEN: usize = 100;
+        write!(
+            f,
+            "outputs differ on {} of {} inputs. First mismatch (input #{}): {}\n    baseline: {}\n    candidate: {}",
+            self.mismatched,
+            self.total,
+            self.sample,
+            self.input,
+            truncated(&self.baseline, MAX_OUTPUT_LEN),
+            truncated(&self.candidate, MAX_OUTPUT_LEN)
+        )
+    }
+}
+
+/// Debug representation of a value limited to `max_len` bytes
+///
+/// Formatting stops as soon as the limit is reached, so it is cheap even for large values.
+fn debug_truncated(value: &impl Debug, max_len: usize) -> String {
+    let mut w = LimitedWriter::new(max_len);
+    if write!(w, "{:?}", value).is_err() {
+        w.0.push_str("...");
+    }
+    w.0
+}
+
+fn truncated(value: &str, max_len: usize) -> String {
+    let mut w = LimitedWriter::new(max_len);
+    if w.write_str(value).is_err() {
+        w.0.push_str("...");
+    }
+    w.0
+}
+
+/// Writer failing once given number of bytes is written
+struct LimitedWriter(String, usize);
+
+impl LimitedWriter {
+    fn new(max_len: usize) -> Self {
+        Self(String::new(), max_len)
+    }
+}
+
+impl Write for LimitedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = self.1 - self.0.len();
+        if s.len() <= available {
+            self.0.push_str(s);
+            return Ok(());
+        }
+        let end = (0..=available)
+            .rev()
+            .find(|i| s.is_char_boundary(*i))
+            .unwrap_or(0);
+        self.0.push_str(&s[..end]);
+        Err(fmt::Error)
+    }
+}
+
+/// Generator or function shared by the targets of a [`BenchmarkMatrix`]
+///
+/// Targets are used by a single thread at a time, so the lock is never contended. It only allows the targets to be
+/// `Send` if the shared value is (see `send` module).
+struct Shared<T>(Arc<Mutex<T>>);
+
+impl<T> Shared<T> {
+    fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    /// Locks the value. Panics of the benchmarks are not invalidating the value, so the poisoning is ignored
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+/// Generator shared by all the functions registered against the same parameter of a [`BenchmarkMatrix`]
+///
+/// Each function reads the inputs through its own [`InputCursor`]. The cursor reseeds the generator with a seed
+/// derived from its position in the input sequence whenever it takes over the generator from another cursor, so
+/// any two functions at the same position observe the same haystack and needles regardless of the order they are
+/// measured in.
+struct SharedGenerator<G> {
+    g: G,
+
+    /// Cursor the state of the generator belongs to, `None` if the next cursor has to reseed the generator
+    owner: Option<usize>,
+
+    /// Number of the cursors created, used as the id of the next one
+    cursors: usize,
+}
+
+impl<G> SharedGenerator<G> {
+    fn new(g: G) -> Self {
+        Self {
+            g,
+            owner: None,
+            cursors: 0,
+        }
+    }
+}
+
+impl<G> Deref for SharedGenerator<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.g
+    }
+}
+
+impl<G> DerefMut for SharedGenerator<G> {
+    fn deref_mut(&mut self) -> &mut G {
+        &mut self.g
+    }
+}
+
+/// Position of a single function in the input sequence of a [`SharedGenerator`]
+///
+/// Haystack `k` is generated after reseeding with `(seed, k)` and the needles drawn after `n` needles of the
+/// haystack continue from `(seed, k, n)` if another cursor used the generator in between. Reseeding happens
+/// outside of the timed region.
+///
+/// Generator not shared with other functions is synced the same way as before the cursors were introduced and is
+/// never reseeded between the syncs. Its input sequence matches the one of the executables built with earlier
+/// versions and the state of the generator (eg. the haystacks prefetched by
+/// [`generators::PrefetchingGenerator`]) is kept.
+struct InputCursor<G> {
+    g: Shared<SharedGenerator<G>>,
+    id: usize,
+    seed: u64,
+
+    /// Number of the haystacks generated since the last sync
+    haystacks: u64,
+
+    /// Number of the needles drawn from the current haystack
+    needles: u64,
+
+    /// Number of the mutations of the current haystack (see [`Generator::mutate_haystack()`])
+    mutations: u64,
+}
+
+impl<G: Generator> InputCursor<G> {
+    fn new(g: &Shared<SharedGenerator<G>>) -> Self {
+        let id = {
+            let mut g = g.lock();
+            g.cursors += 1;
+            g.cursors - 1
+        };
+        Self {
+            g: g.clone(),
+            id,
+            seed: 0,
+            haystacks: 0,
+            needles: 0,
+            mutations: 0,
+        }
+    }
+
+    /// Cursor of a generator not shared with any other function
+    fn exclusive(g: G) -> Self {
+        Self::new(&Shared::new(SharedGenerator::new(g)))
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.seed = seed;
+        self.haystacks = 0;
+        self.needles = 0;
+        self.mutations = 0;
+        let mut g = self.g.lock();
+        if g.cursors == 1 {
+            g.g.sync_with(SmallRng::seed_from_u64(seed));
+            g.owner = Some(self.id);
+        } else {
+            g.owner = None;
+        }
+    }
+
+    fn next_haystack(&mut self) -> G::Haystack {
+        let mut g = self.g.lock();
+        if g.cursors > 1 || g.owner != Some(self.id) {
+            g.g.sync_with(derive_rng(self.seed, [self.haystacks, u64::MAX]));
+            g.owner = None;
+        }
+        self.haystacks += 1;
+        self.needles = 0;
+        self.mutations = 0;
+        g.next_haystack()
+    }
+
+    fn haystack_size(&self, haystack: &G::Haystack) -> Option<HaystackSize> {
+        self.g.lock().g.haystack_size(haystack)
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.g.lock().g.take_error()
+    }
+
+    fn recording(&self) -> Option<PathBuf> {
+        self.g.lock().recording().map(Path::to_path_buf)
+    }
+
+    /// Generator opted in to mutating the haystacks (see [`Generator::mutates_haystack()`])
+    fn mutates(&self) -> bool {
+        self.g.lock().mutates_haystack()
+    }
+
+    /// Applies the next mutation to the current haystack, does nothing if the generator doesn't mutate haystacks
+    ///
+    /// Mutation `m` of haystack `k` is applied after reseeding with `(seed ^ MUTATION_STREAM, k, m)`, so the
+    /// mutations don't depend on the needles drawn in between.
+    fn mutate(&mut self, haystack: &mut G::Haystack) {
+        let mut g = self.g.lock();
+        if !g.mutates_haystack() {
+            return;
+        }
+        self.mutations += 1;
+        g.g.sync_with(derive_rng(
+            self.seed ^ MUTATION_STREAM,
+            [self.haystacks, self.mutations],
+        ));
+        g.owner = None;
+        g.mutate_haystack(haystack, self.mutations as usize);
+    }
+
+    /// Locks the generator for drawing `count` needles of the current haystack
+    fn needles(&mut self, count: usize) -> MutexGuard<'_, SharedGenerator<G>> {
+        let mut g = self.g.lock();
+        if g.owner != Some(self.id) {
+            g.g.sync_with(derive_rng(self.seed, [self.haystacks, self.needles]));
+            g.owner = Some(self.id);
+        }
+        self.needles += count as u64;
+        g
+    }
+
+    /// Locks the generator for using it outside of the input sequence (eg. for output verification)
+    ///
+    /// The generator is reseeded with the seed of the cursor, so the inputs depend only on the last sync.
+    fn detached(&self) -> MutexGuard<'_, SharedGenerator<G>> {
+        let mut g = self.g.lock();
+        g.g.sync_with(derive_rng(self.seed, [u64::MAX, u64::MAX]));
+        g.owner = None;
+        g
+    }
+}
+
+/// Mixed into the seed of a cursor when the haystack is mutated, so mutations and needles are drawn from different
+/// sequences
+const MUTATION_STREAM: u64 = 0x6d75_7461_7465_0000;
+
+/// RNG seeded with a given seed mixed with a position in an input sequence
+fn derive_rng(seed: u64, position: [u64; 2]) -> SmallRng {
+    let mut hash = seed;
+    for value in position {
+        hash = (hash ^ value).wrapping_mul(0x9e3779b97f4a7c15);
+        hash ^= hash >> 32;
+    }
+    SmallRng::seed_from_u64(hash)
+}
+
+/// Implementation of a [`MeasureTarget`] which uses [`Generator`] to generates a new payload for a function
+/// each new sample.
+pub struct GenFunc<F, G: Generator> {
+    f: Shared<F>,
+    g: InputCursor<G>,
+    haystack: Option<G::Haystack>,
+    name: String,
+    generator: String,
+    sizes: HaystackSizes,
+
+    /// Clones the haystack before each sample if [`HaystackIsolation::ClonedPerFunction`] is used
+    clone_haystack: Option<CloneFn<G::Haystack>>,
+
+    /// Produces verification samples if the function is registered with output verification
+    verify: Option<VerifyFn<F, G>>,
+
+    /// Cost of the generator measured during the last iterations estimation
+    cost_hints: Option<CostHints>,
+}
+
+impl<F, O, G> GenFunc<F, G>
+where
+    G: Generator,
+    F: Fn(&G::Haystack, &G::Needle) -> O,
+{
+    pub fn new(name: &str, f: F, g: G) -> Self {
+        Self::from_shared(name, Shared::new(f), InputCursor::exclusive(g))
+    }
+
+    fn from_shared(name: &str, f: Shared<F>, g: InputCursor<G>) -> Self {
+        let generator = g.g.lock().name().to_string();
+        Self {
+            name: format!("{}/{}", name, generator),
+            generator,
+            sizes: HaystackSizes::default(),
+            haystack: None,
+            f,
+            g,
+            clone_haystack: None,
+            verify: None,
+            cost_hints: None,
+        }
+    }
+
+    /// Sets the [`HaystackIsolation`] mode for this function
+    pub fn with_isolation(mut self, isolation: HaystackIsolation) -> Self
+    where
+        G::Haystack: Clone,
+    {
+        self.clone_haystack = match isolation {
+            HaystackIsolation::Shared => None,
+            HaystackIsolation::ClonedPerFunction => Some(G::Haystack::clone),
+        };
+        self
+    }
+}
+
+impl<F, O, G> MeasureTarget for GenFunc<F, G>
+where
+    G: Generator,
+    F: Fn(&G::Haystack, &G::Needle) -> O,
+{
+    fn measure(&mut self, iterations: usize) -> u64 {
+        let haystack = &*self.haystack.get_or_insert_with(|| self.g.next_haystack());
+        let mut g = self.g.needles(iterations);
+        let cloned = self.clone_haystack.map(|clone| clone(haystack));
+        let haystack = cloned.as_ref().unwrap_or(haystack);
+        let f = self.f.lock();
+
+        if mem::needs_drop::<O>() {
+            measure_retaining(iterations, || {
+                let needle = g.next_needle(haystack);
+                black_box((f)(haystack, &needle))
+            })
+        } else {
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                let needle = g.next_needle(haystack);
+                black_box((f)(haystack, &needle));
+            }
+            ActiveTimer::stop(start)
+        }
+    }
+
+    fn measure_detailed(&mut self, iterations: usize) -> Option<Vec<u64>> {
+        let haystack = &*self.haystack.get_or_insert_with(|| self.g.next_haystack());
+        let mut g = self.g.needles(iterations);
+        let cloned = self.clone_haystack.map(|clone| clone(haystack));
+        let haystack = cloned.as_ref().unwrap_or(haystack);
+        let f = self.f.lock();
+        Some(measure_calls(
+            iterations,
+            || g.next_needle(haystack),
+            |needle| (f)(haystack, &needle),
+        ))
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        // Generators are synced again after the estimation, so consuming inputs here doesn't shift the samples
+        let start = ActiveTimer::start();
+        self.next_haystack();
+        let haystack_ns = ActiveTimer::stop(start);
+        let needle_ns = {
+            let mut g = self.g.needles(10);
+            let haystack = self.haystack.as_ref().expect("haystack is generated");
+            let mut needles = SmallBuffer::<u64, 16>::with_capacity(10);
+            for _ in 0..10 {
+                let start = ActiveTimer::start();
+                black_box(g.next_needle(haystack));
+                needles.push(ActiveTimer::stop(start));
+            }
+            median(&mut needles)
+        };
+        self.cost_hints = Some(CostHints {
+            haystack_ns_estimate: haystack_ns,
+            needle_ns_estimate: needle_ns,
+        });
+
+        // Here we relying on the fact that measure() is not generating a new haystack
+        // without a call to next_haystack()
+        let median = median_execution_time(self, 10);
+        (time_ms as usize * NS_TO_MS) / median as usize
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        let haystack = self.g.next_haystack();
+        self.sizes.add(self.g.haystack_size(&haystack));
+        self.haystack = Some(haystack);
+        true
+    }
+
+    fn mutate_haystack(&mut self) {
+        if let Some(haystack) = &mut self.haystack {
+            self.g.mutate(haystack);
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.g.mutates()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generator(&self) -> Option<&str> {
+        Some(&self.generator)
+    }
+
+    fn throughput(&self) -> Option<Throughput> {
+        self.sizes.mean()
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.g.sync(seed)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.g.take_error()
+    }
+
+    fn recording(&self) -> Option<PathBuf> {
+        self.g.recording()
+    }
+
+    fn cost_hints(&self) -> Option<CostHints> {
+        self.cost_hints
+    }
+
+    fn verify(&mut self, samples: usize) -> Option<Vec<VerificationSample>> {
+        let verify = self.verify?;
+        let f = self.f.lock();
+        Some(verify(&f, &mut self.g.detached(), samples))
+    }
+
+    fn code_fingerprint(&self) -> Option<u64> {
+        fn call<F: Fn(&H, &N) -> O, H, N, O>(f: &F, haystack: &H, needle: &N) -> O {
+            f(haystack, needle)
+        }
+        let call = call::<F, G::Haystack, G::Needle, O> as fn(&F, &G::Haystack, &G::Needle) -> O;
+        code_fingerprint::<F>(call as usize)
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+/// Implementation of a [`MeasureTarget`] which calls a function with a batch of needles (see
+/// [`BenchmarkMatrix::add_batch_function()`])
+struct BatchGenFunc<F, G: Generator> {
+    f: Shared<F>,
+    g: InputCursor<G>,
+    haystack: Option<G::Haystack>,
+    name: String,
+    generator: String,
+    batch: usize,
+
+    /// Needles of the current call. Reused between the calls, so the buffer is not allocated during measurement
+    needles: Vec<G::Needle>,
+
+    clone_haystack: Option<CloneFn<G::Haystack>>,
+}
+
+impl<F, O, G> BatchGenFunc<F, G>
+where
+    G: Generator,
+    F: Fn(&G::Haystack, &[G::Needle]) -> O,
+{
+    fn new(name: &str, f: Shared<F>, g: InputCursor<G>, batch: usize) -> Self {
+        assert!(batch > 0, "Batch should contain at least one needle");
+        let generator = g.g.lock().name().to_string();
+        Self {
+            name: format!("{}/{}", name, generator),
+            generator,
+            haystack: None,
+            f,
+            g,
+            batch,
+            needles: Vec::with_capacity(batch),
+            clone_haystack: None,
+        }
+    }
+}
+
+impl<F, O, G> MeasureTarget for BatchGenFunc<F, G>
+where
+    G: Generator,
+    F: Fn(&G::Haystack, &[G::Needle]) -> O,
+{
+    fn measure(&mut self, iterations: usize) -> u64 {
+        let haystack = &*self.haystack.get_or_insert_with(|| self.g.next_haystack());
+        let mut g = self.g.needles(iterations * self.batch);
+        let cloned = self.clone_haystack.map(|clone| clone(haystack));
+        let haystack = cloned.as_ref().unwrap_or(haystack);
+        let f = self.f.lock();
+        let (batch, needles) = (self.batch, &mut self.needles);
+
+        let mut call = || {
+            needles.clear();
+            needles.extend((0..batch).map(|_| g.next_needle(haystack)));
+            black_box(f(haystack, needles))
+        };
+        if mem::needs_drop::<O>() {
+            measure_retaining(iterations, call)
+        } else {
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                call();
+            }
+            ActiveTimer::stop(start)
+        }
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        self.next_haystack();
+        let median = median_execution_time(self, 10);
+        (time_ms as usize * NS_TO_MS) / median as usize
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        self.haystack = Some(self.g.next_haystack());
+        true
+    }
+
+    fn mutate_haystack(&mut self) {
+        if let Some(haystack) = &mut self.haystack {
+            self.g.mutate(haystack);
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.g.mutates()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generator(&self) -> Option<&str> {
+        Some(&self.generator)
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.g.sync(seed)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.g.take_error()
+    }
+
+    fn recording(&self) -> Option<PathBuf> {
+        self.g.recording()
+    }
+
+    fn batch_len(&self) -> usize {
+        self.batch
+    }
+
+    fn code_fingerprint(&self) -> Option<u64> {
+        fn call<F: Fn(&H, &[N]) -> O, H, N, O>(f: &F, haystack: &H, needles: &[N]) -> O {
+            f(haystack, needles)
+        }
+        let call = call::<F, G::Haystack, G::Needle, O> as fn(&F, &G::Haystack, &[G::Needle]) -> O;
+        code_fingerprint::<F>(call as usize)
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+/// Implementation of a [`MeasureTarget`] which calls a function writing its output into a scratch buffer owned
+/// by the target (see [`BenchmarkMatrix::add_inout_function()`])
+struct InOutGenFunc<F, G: Generator, S> {
+    f: Shared<F>,
+    g: InputCursor<G>,
+    haystack: Option<G::Haystack>,
+    name: String,
+    generator: String,
+    sizes: HaystackSizes,
+    scratch: S,
+    reset: ScratchReset,
+    clone_haystack: Option<CloneFn<G::Haystack>>,
+}
+
+impl<F, G, S> InOutGenFunc<F, G, S>
+where
+    G: Generator,
+    S: Default + Resettable,
+    F: Fn(&G::Haystack, &G::Needle, &mut S),
+{
+    fn new(name: &str, f: Shared<F>, g: InputCursor<G>, reset: ScratchReset) -> Self {
+        let generator = g.g.lock().name().to_string();
+        Self {
+            name: format!("{}/{}", name, generator),
+            generator,
+            sizes: HaystackSizes::default(),
+            haystack: None,
+            f,
+            g,
+            scratch: S::default(),
+            reset,
+            clone_haystack: None,
+        }
+    }
+}
+
+impl<F, G, S> MeasureTarget for InOutGenFunc<F, G, S>
+where
+    G: Generator,
+    S: Default + Resettable,
+    F: Fn(&G::Haystack, &G::Needle, &mut S),
+{
+    fn measure(&mut self, iterations: usize) -> u64 {
+        let haystack = &*self.haystack.get_or_insert_with(|| self.g.next_haystack());
+        let mut g = self.g.needles(iterations);
+        let cloned = self.clone_haystack.map(|clone| clone(haystack));
+        let haystack = cloned.as_ref().unwrap_or(haystack);
+        let f = self.f.lock();
+        let scratch = &mut self.scratch;
+
+        let time = if self.reset == ScratchReset::Iteration {
+            (0..iterations)
+                .map(|_| {
+                    scratch.reset();
+                    let start = ActiveTimer::start();
+                    let needle = g.next_needle(haystack);
+                    f(haystack, &needle, scratch);
+                    ActiveTimer::stop(start)
+                })
+                .sum()
+        } else {
+            if self.reset == ScratchReset::Sample {
+                scratch.reset();
+            }
+            let start = ActiveTimer::start();
+            for _ in 0..iterations {
+                let needle = g.next_needle(haystack);
+                f(haystack, &needle, scratch);
+            }
+            ActiveTimer::stop(start)
+        };
+        // Contents of the buffer escape, so the writes can't be optimized away
+        black_box(&*scratch);
+        time
+    }
+
+    fn estimate_iterations(&mut self, time_ms: u32) -> usize {
+        self.next_haystack();
+        let median = median_execution_time(self, 10);
+        (time_ms as usize * NS_TO_MS) / median as usize
+    }
+
+    fn next_haystack(&mut self) -> bool {
+        let haystack = self.g.next_haystack();
+        self.sizes.add(self.g.haystack_size(&haystack));
+        self.haystack = Some(haystack);
+        true
+    }
+
+    fn mutate_haystack(&mut self) {
+        if let Some(haystack) = &mut self.haystack {
+            self.g.mutate(haystack);
+        }
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.g.mutates()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generator(&self) -> Option<&str> {
+        Some(&self.generator)
+    }
+
+    fn throughput(&self) -> Option<Throughput> {
+        self.sizes.mean()
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.g.sync(seed)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.g.take_error()
+    }
+
+    fn recording(&self) -> Option<PathBuf> {
+        self.g.recording()
+    }
+
+    fn reset(&mut self) {
+        self.next_haystack();
+        self.scratch = S::default();
+    }
+
+    fn code_fingerprint(&self) -> Option<u64> {
+        fn call<F: Fn(&H, &N, &mut S), H, N, S>(f: &F, haystack: &H, needle: &N, scratch: &mut S) {
+            f(haystack, needle, scratch)
+        }
+        let call = call::<F, G::Haystack, G::Needle, S> as fn(&F, &G::Haystack, &G::Needle, &mut S);
+        code_fingerprint::<F>(call as usize)
+    }
+
+    fn entry_point(&self) -> Option<usize> {
+        Some(entry_point::<Self>())
+    }
+}
+
+/// Matrix of functions is used to perform benchmark with different generator strategies.
+///
+/// It is a common task to benchmark function with different payload size and/or different structure of the payload.
+/// `BenchmarkMatrix` creates a new [`MeasureTarget`] for each unique combination of [`Generator`]
+/// and tested function.
+///
+/// All the functions registered against the same generator share a single instance of it. Each function keeps
+/// its own position in the input sequence and the generator is reseeded from the synced seed and that position,
+/// so any two functions of the matrix compared in the same process (see [`cli::run_pairs()`]) observe identical
+/// haystacks and needles. Baseline and candidate of `compare` command are built separately and rely on syncing
+/// their generators with the same seed instead.
+///
+/// # Example
+/// ```rust
+/// use tango_bench::{generators::RandomVec, BenchmarkMatrix, IntoBenchmarks};
+///
+/// fn sum_positive(haystack: &Vec<u32>, _: &()) -> u32 {
+///     haystack.iter().copied().filter(|v| *v > 0).sum()
+/// }
+///
+/// fn sum_benchmarks() -> impl IntoBenchmarks {
+///     BenchmarkMatrix::with_params([100, 1_000, 10_000], RandomVec::new)
+///         .add_function("sum_positive", sum_positive)
+/// }
+/// ```
+///
+/// Matrices of `Send` benchmarks are created by `BenchmarkMatrix::new_send()` (see `send` module, enabled by `send`
+/// feature).
+pub struct BenchmarkMatrix<G: Generator, T: ?Sized = dyn MeasureTarget> {
+    generators: Vec<Shared<SharedGenerator<G>>>,
+    functions: Vec<Box<T>>,
+    clone_haystack: Option<CloneFn<G::Haystack>>,
+}
+
+impl<G: Generator> BenchmarkMatrix<G> {
+    pub fn new(generator: G) -> Self {
+        Self::from_generators(iter::once(generator))
+    }
+
+    /// New matrix with generator created for a given set of parameters
+    pub fn with_params<P>(params: impl IntoIterator<Item = P>, generator: impl Fn(P) -> G) -> Self {
+        Self::from_generators(params.into_iter().map(generator))
+    }
+
+    pub fn add_function<F, O>(mut self, name: &str, f: F) -> Self
+    where
+        G: 'static,
+        F: Fn(&G::Haystack, &G::Needle) -> O + 'static,
+    {
+        let targets = self.gen_functions(name, f, None);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// Adds a function which outputs are verified to be the same in baseline and candidate before measurement
+    ///
+    /// Before taking samples the harness calls both functions on [`MeasurementSettings::verification_samples`]
+    /// inputs and compares the outputs. Mismatched tests are reported as errors and not measured. The number of
+    /// inputs is small and haystacks are generated for each of them, so verification doesn't affect measurements.
+    ///
+    /// ```rust
+    /// use tango_bench::{generators::RandomVec, BenchmarkMatrix, IntoBenchmarks};
+    ///
+    /// fn max(haystack: &Vec<u32>, _: &()) -> Option<u32> {
+    ///     haystack.iter().copied().max()
+    /// }
+    ///
+    /// fn max_benchmarks() -> impl IntoBenchmarks {
+    ///     BenchmarkMatrix::with_params([100, 1_000], RandomVec::new).add_function_verified("max", max)
+    /// }
+    /// ```
+    pub fn add_function_verified<F, O>(mut self, name: &str, f: F) -> Self
+    where
+        G: 'static,
+        G::Haystack: Debug,
+        G::Needle: Debug,
+        F: Fn(&G::Haystack, &G::Needle) -> O + 'static,
+        O: Debug,
+    {
+        let targets = self.gen_functions(name, f, Some(verify_outputs::<F, O, G>));
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// Adds a function which processes `batch` needles in a single call
+    ///
+    /// Some APIs have a bulk counterpart (eg. `get_many()` next to `get()`) which is only fair to compare per
+    /// element. Each iteration generates `batch` needles and calls the function once, and the harness divides
+    /// the measured time by `batch`, so the function is reported per element. The time of generating the needles
+    /// is measured as well, the same as for [`Self::add_function()`]. Batch functions are only compared with the
+    /// functions of the same batch size, unless `--mixed-batches` is given.
+    ///
+    /// ```rust
+    /// use tango_bench::{generators::RandomVec, BenchmarkMatrix, IntoBenchmarks};
+    ///
+    /// fn sum_all(haystack: &Vec<u32>, needles: &[()]) -> u32 {
+    ///     needles.iter().map(|_| haystack.iter().sum::<u32>()).sum()
+    /// }
+    ///
+    /// fn sum_benchmarks() -> impl IntoBenchmarks {
+    ///     BenchmarkMatrix::with_params([100, 1_000], RandomVec::new).add_batch_function("sum", 16, sum_all)
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `batch` is zero.
+    pub fn add_batch_function<F, O>(mut self, name: &str, batch: usize, f: F) -> Self
+    where
+        G: 'static,
+        F: Fn(&G::Haystack, &[G::Needle]) -> O + 'static,
+    {
+        let targets = self.batch_functions(name, batch, f);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// Adds a function which writes its output into a scratch buffer instead of returning it
+    ///
+    /// Outputs of the regular functions are retained until the end of a sample, so the work producing them is
+    /// not optimized away and dropping them is not measured. Functions which observable work is a side effect
+    /// (eg. filling a provided buffer) don't need that. The buffer is owned by the target, created with
+    /// `S::default()` and reset outside of the measurement according to `reset` (see [`ScratchReset`]). The
+    /// contents of the buffer are passed through [`black_box()`] after each sample, so the writes are kept.
+    ///
+    /// ```rust
+    /// use tango_bench::{generators::RandomVec, BenchmarkMatrix, IntoBenchmarks, ScratchReset};
+    ///
+    /// fn copy(haystack: &Vec<u32>, _: &(), scratch: &mut Vec<u32>) {
+    ///     scratch.extend_from_slice(haystack)
+    /// }
+    ///
+    /// fn copy_benchmarks() -> impl IntoBenchmarks {
+    ///     BenchmarkMatrix::with_params([100, 1_000], RandomVec::new)
+    ///         .add_inout_function("copy", ScratchReset::Iteration, copy)
+    /// }
+    /// ```
+    pub fn add_inout_function<F, S>(mut self, name: &str, reset: ScratchReset, f: F) -> Self
+    where
+        G: 'static,
+        S: Default + Resettable + 'static,
+        F: Fn(&G::Haystack, &G::Needle, &mut S) + 'static,
+    {
+        let targets = self.inout_functions(name, reset, f);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+}
+
+impl<G: Generator, T: ?Sized> BenchmarkMatrix<G, T> {
+    fn from_generators(generators: impl Iterator<Item = G>) -> Self {
+        Self {
+            generators: generators
+                .map(SharedGenerator::new)
+                .map(Shared::new)
+                .collect(),
+            functions: vec![],
+            clone_haystack: None,
+        }
+    }
+
+    /// Sets the [`HaystackIsolation`] mode for all the functions added after this call
+    pub fn with_isolation(mut self, isolation: HaystackIsolation) -> Self
+    where
+        G::Haystack: Clone,
+    {
+        self.clone_haystack = match isolation {
+            HaystackIsolation::Shared => None,
+            HaystackIsolation::ClonedPerFunction => Some(G::Haystack::clone),
+        };
+        self
+    }
+
+    /// Add a new generator to the matrix for each parameter in the given iterator.
+    pub fn add_generators_with_params<P>(
+        mut self,
+        params: impl IntoIterator<Item = P>,
+        generator: impl Fn(P) -> G,
+    ) -> Self {
+        let generators = params
+            .into_iter()
+            .map(generator)
+            .map(SharedGenerator::new)
+            .map(Shared::new);
+        self.generators.extend(generators);
+        self
+    }
+
+    fn gen_functions<F, O>(
+        &self,
+        name: &str,
+        f: F,
+        verify: Option<VerifyFn<F, G>>,
+    ) -> Vec<GenFunc<F, G>>
+    where
+        F: Fn(&G::Haystack, &G::Needle) -> O,
+    {
+        let f = Shared::new(f);
+        self.generators
+            .iter()
+            .map(|g| GenFunc::from_shared(name, f.clone(), InputCursor::new(g)))
+            .map(|mut f| {
+                f.clone_haystack = self.clone_haystack;
+                f.verify = verify;
+                f
+            })
+            .collect()
+    }
+
+    fn batch_functions<F, O>(&self, name: &str, batch: usize, f: F) -> Vec<BatchGenFunc<F, G>>
+    where
+        F: Fn(&G::Haystack, &[G::Needle]) -> O,
+    {
+        let f = Shared::new(f);
+        self.generators
+            .iter()
+            .map(|g| BatchGenFunc::new(name, f.clone(), InputCursor::new(g), batch))
+            .map(|mut f| {
+                f.clone_haystack = self.clone_haystack;
+                f
+            })
+            .collect()
+    }
+
+    fn inout_functions<F, S>(
+        &self,
+        name: &str,
+        reset: ScratchReset,
+        f: F,
+    ) -> Vec<InOutGenFunc<F, G, S>>
+    where
+        S: Default + Resettable,
+        F: Fn(&G::Haystack, &G::Needle, &mut S),
+    {
+        let f = Shared::new(f);
+        self.generators
+            .iter()
+            .map(|g| InOutGenFunc::new(name, f.clone(), InputCursor::new(g), reset))
+            .map(|mut f| {
+                f.clone_haystack = self.clone_haystack;
+                f
+            })
+            .collect()
+    }
+}
+
+impl<G: Generator> IntoBenchmarks for BenchmarkMatrix<G> {
+    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
+        assert!(!self.functions.is_empty(), "No functions was given");
+        self.functions
+    }
+}
+
+pub trait IntoBenchmarks {
+    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>>;
+}
+
+impl<const N: usize> IntoBenchmarks for [Box<dyn MeasureTarget>; N] {
+    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
+        self.into_iter().collect()
+    }
+}
+
+impl IntoBenchmarks for Vec<Box<dyn MeasureTarget>> {
+    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
+        self
+    }
+}
+
+/// Two targets compared against each other in the same process (see [`cli::run_pairs()`])
+///
+/// Unlike `compare` command doesn't require baseline and candidate to be built as separate executables, which is
+/// convenient for quick A/B experiments. Both targets are synced with the same seed, so generators of the targets
+/// produce the same inputs.
+///
+/// ```rust,no_run
+/// use tango_bench::{benchmark_fn, cli, MeasurementSettings, PairedTargets};
+///
+/// let pair = PairedTargets::new(
+///     benchmark_fn("iter_sum", || (0..100u64).sum::<u64>()),
+///     benchmark_fn("fold_sum", || (0..100u64).fold(0, |a, b| a + b)),
+/// );
+/// cli::run_pairs(MeasurementSettings::default(), vec![pair]).unwrap();
+/// ```
+///
+/// Pairs created with [`Self::from_fn()`] can also instantiate second copies of the targets, which `--aa-test`
+/// compares against the first ones.
+#[cfg(feature = "cli")]
+pub struct PairedTargets {
+    name: String,
+    baseline: Box<dyn MeasureTarget>,
+    candidate: Box<dyn MeasureTarget>,
+    factories: Option<[TargetFactory; 2]>,
+}
+
+#[cfg(feature = "cli")]
+type TargetFactory = Box<dyn Fn() -> Box<dyn MeasureTarget>>;
+
+#[cfg(feature = "cli")]
+impl PairedTargets {
+    /// Creates a pair named `<baseline> vs <candidate>` (or after the targets if their names are the same)
+    pub fn new(baseline: Box<dyn MeasureTarget>, candidate: Box<dyn MeasureTarget>) -> Self {
+        let name = PairId::new(baseline.name(), candidate.name()).to_string();
+        Self {
+            name,
+            baseline,
+            candidate,
+            factories: None,
+        }
+    }
+
+    /// Same as [`Self::new()`], but the targets are created by given functions
+    ///
+    /// The functions are called again for the second instances of the targets compared by `--aa-test`, so each
+    /// side of an A/A comparison has its own state and generator like the sides of the pair do.
+    pub fn from_fn(
+        baseline: impl Fn() -> Box<dyn MeasureTarget> + 'static,
+        candidate: impl Fn() -> Box<dyn MeasureTarget> + 'static,
+    ) -> Self {
+        let pair = Self::new(baseline(), candidate());
+        Self {
+            factories: Some([Box::new(baseline), Box::new(candidate)]),
+            ..pair
+        }
+    }
+
+    /// Renames the pair
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is empty.
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        assert!(!name.is_empty());
+        Self { name, ..self }
+    }
+
+    /// Name the results of the pair are reported under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of the compared targets
+    pub fn id(&self) -> PairId {
+        PairId::new(self.baseline.name(), self.candidate.name())
+    }
+
+    /// Second instances of the baseline and the candidate, `None` if the pair is not created by [`Self::from_fn()`]
+    pub(crate) fn replicas(&self) -> Option<[Box<dyn MeasureTarget>; 2]> {
+        self.factories
+            .as_ref()
+            .map(|factories| factories.each_ref().map(|f| f()))
+    }
+}
+
+/// Generates the payload for the benchmarking functions
+///
+/// One of the most important parts of the benchmarking process is generating the payload to test the algorithm. This /// is what this trait is doing. Test function registered in the system can accepts two arguments:
+/// - *haystack* - usually the data structure we're testing the algorithm on
+/// - *needle* - the supplementary used to test the algorithm.
+///
+/// ## Haystack
+/// Haystack is typically some sort of a collection that is used in benchmarking. It can be quite large and
+/// expensive to generate, because it is generated once per sample or less. The frequency of haystack generation
+/// is controlled by [`MeasurementSettings::samples_per_haystack`].
+///
+/// ## Needle
+/// Needle is usually some type of query that is presented to the algorithm. In case of searching algorithm it
+/// can be value we search in the collection.
+///
+/// Important distinction between haystack and needle is that haystack generation is not included in timing while
+/// needle generation is a part of measurement loop. Therefore needle generation should be relativley lightweight.
+///
+/// Sometimes haystack generation might be so expensive that it makes sense to leave haystack fixed and provide
+/// randomness by generating different needles. For example, instead of generating new random `Vec<T>` for each sample
+/// it might be more practical to generate a single `Vec` and a new `Range<usize>` as a haystack at each iteration.
+///
+/// It might be the case that the algorithm being tested is not using both type of values.
+/// In this case corresponding value type should unit type – `()`.
+/// Depending on the type of algorithm you might not need to generate both of them. Here are some examples:
+///
+/// | Algorithm | Haystack | Needle |
+/// |----------|----------|--------|
+/// | Searching in a string | String | substrung to search for and/or range to search over |
+/// | Searching in a collection | Collection | Value to search for and/or range to search over |
+/// | Soring | Collection | – |
+/// | Numerical computation: factorial, DP problems, etc. | – | Input parameters |
+///
+/// Tango orchestrates the generating of haystack and needle and guarantees that both benchmarking
+/// functions are called with the same input parameters. Therefore performance difference is predictable.
+///
+/// ## Contract
+/// Exactly one needle is generated for each iteration of the benchmarked function, so the number of needles
+/// always equals the number of iterations in a sample. Two generators synced with the same seed must produce
+/// the same sequence of haystacks and needles. [`testing::check_generator()`] can be used in tests to
+/// verify that a generator complies with this contract.
+pub trait Generator {
+    type Haystack;
+    type Needle;
+
+    /// Generates next random haystack for the benchmark
+    ///
+    /// All iterations within sample are using the same haystack. Haystack are changed only between samples
+    /// (see. [`MeasureTarget::next_haystack()`]).
+    fn next_haystack(&mut self) -> Self::Haystack;
+
+    /// Generates next random needle for the benchmark
+    ///
+    /// This method should be relatively lightweight, because the execution time of this method is included
+    /// in reported by the benchmark time. Implementation are given haystack generated by
+    /// [`Self::next_haystack()`] which will be used for benchmark execution.
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle;
+
+    /// Applies incremental changes to the haystack between the samples
+    ///
+    /// Models the workloads operating on evolving state (eg. an index growing over the run), which are neither
+    /// "fresh haystack per sample" nor "static haystack". Called outside of the timed region before each sample
+    /// but the first one on a haystack, `sample_idx` is the index of that sample on the haystack (starting from 1).
+    /// The generator is reseeded before each call, so both functions of a pair observe the same sequence of changes.
+    /// Called only if [`Self::mutates_haystack()`] returns `true`. By default does nothing.
+    fn mutate_haystack(&mut self, _haystack: &mut Self::Haystack, _sample_idx: usize) {}
+
+    /// Returns `true` if the generator changes haystacks between the samples (see [`Self::mutate_haystack()`])
+    ///
+    /// Generators overriding [`Self::mutate_haystack()`] should return `true`, otherwise the haystacks are not
+    /// mutated. Both functions of a paired test should agree on it. By default returns `false`.
+    fn mutates_haystack(&self) -> bool {
+        false
+    }
+
+    /// Size of the input processed by a single call of the function on a given haystack
+    ///
+    /// Allows the harness to report the throughput of the functions (eg. MiB/s) along with the time. Usually it is
+    /// the size of the haystack itself, but generators producing needles selecting a part of the haystack (eg. a
+    /// substring) should return the size of that part. Called outside of the timed region once per haystack.
+    /// Returns `None` by default, so the throughput is not reported.
+    fn haystack_size(&self, _haystack: &Self::Haystack) -> Option<HaystackSize> {
+        None
+    }
+
+    /// Syncs internal RNG-state of this generator with given seed
+    ///
+    /// For benchmarks to be predictable the harness periodically synchronize the RNG state of all the generators.
+    /// If applicable, implementations should set internal RNG state with the value derived from given `seed`.
+    /// Implementation are free to transform seed value in any meaningfull way (like taking only lower 32 bits)
+    /// as long as this transformation is deterministic.
+    fn sync(&mut self, seed: u64);
+
+    /// Syncs internal RNG-state of this generator with given RNG
+    ///
+    /// The harness derives RNG for each test from the master seed (see [`RngFactory`]), so generators using
+    /// [`SmallRng`] internally can take it as is. By default delegates to [`Self::sync()`] with the first value
+    /// produced by the RNG.
+    fn sync_with(&mut self, mut rng: SmallRng) {
+        self.sync(rng.gen())
+    }
+
+    /// File the inputs are recorded to or replayed from (see [`generators::Recorder`])
+    ///
+    /// Reported along with the results, so the inputs of a suspicious result can be replayed. Returns `None` by
+    /// default.
+    fn recording(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Takes the error encountered while producing the inputs since the last call
+    ///
+    /// Methods producing the inputs can't fail, so generators backed by external resources (eg.
+    /// [`generators::Replayer`]) produce the closest valid input and report the failure here. The harness checks it
+    /// after each sample and fails the test with the error. Returns `None` by default.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Name of generator
+    fn name(&self) -> &str {
+        let name = type_name::<Self>();
+        if let Some(idx) = name.rfind("::") {
+            // it's safe to operate on byte offsets here because ':' symbols is 1-byte ascii
+            &name[idx + 2..]
+        } else {
+            name
+        }
+    }
+}
+
+pub(crate) trait Reporter {
+    /// Called before the first test with the effective configuration of the run
+    fn on_session_start(&mut self, _session: &SessionInfo) {}
+
+    /// Called after [`Self::on_session_start()`] with the names of the tests planned to run in the order of execution
+    ///
+    /// Tests may still be skipped or fail, so the number of the completed tests may be smaller.
+    fn on_suite_start(&mut self, _names: &[&str]) {}
+
+    /// Called when the harness gets to a test planned in [`SessionInfo::tests`], before it is verified and measured
+    ///
+    /// Along with the number of the planned tests it allows to show the progress of the run. The outcome of the test
+    /// is reported by the other callbacks. Under `--schedule round-robin` the tests measured in turns are started
+    /// along with their first slices, before any of them is verified or completed.
+    fn on_test_start(&mut self, _name: &str) {}
+
+    fn on_complete(&mut self, results: &RunResult);
+
+    /// Reporters returning `true` receive all the samples of the measured tests in [`RunResult::pairs`]
+    ///
+    /// Samples are not retained otherwise. Results replayed from the cache and measured in streaming mode have no
+    /// samples either way.
+    fn wants_samples(&self) -> bool {
+        false
+    }
+
+    /// Called after all the tests are completed with the time spent in each phase across all the tests
+    fn on_finish(&mut self, _totals: &PhaseTimings) {}
+
+    /// Called after [`Self::on_finish()`] with all the results reported by [`Self::on_complete()`] in the order they
+    /// were reported in
+    fn on_suite_finish(&mut self, _results: &[RunResult]) {}
+
+    /// Called after [`Self::on_suite_finish()`] with the aggregate of all the completed tests
+    fn on_summary(&mut self, _summary: &SuiteSummary) {}
+
+    /// Called when the harness detects a condition which may affect the results of a test
+    fn on_warning(&mut self, name: &str, message: &str) {
+        eprintln!("[WARN] {}: {}", name, message);
+    }
+
+    /// Called when a test fails and its performance is not reported (eg. outputs verification failed)
+    fn on_error(&mut self, name: &str, message: &str) {
+        eprintln!("[ERROR] {}: {}", name, message);
+    }
+
+    /// Called after [`Self::on_complete()`] for each condition the result fails the run with (eg. `--fail-threshold`)
+    ///
+    /// The test is reported with this callback even if its result is not (eg. with `--significant-only`).
+    fn on_failure(&mut self, name: &str, message: &str) {
+        eprintln!("[ERROR] {}  -  test: {}", message, name);
+    }
+
+    /// Called when a test is not run (see [`SlowTestPolicy::Skip`])
+    fn on_skipped(&mut self, name: &str, reason: &str) {
+        eprintln!("[SKIP] {}: {}", name, reason);
+    }
+
+    /// Called after [`Self::on_summary()`] as the last callback of the run
+    fn on_session_end(&mut self, _summary: &SessionSummary) {}
+}
+
+/// Effective configuration of a benchmarking run
+pub(crate) struct SessionInfo<'a> {
+    /// Measurement settings after all the command line overrides
+    pub(crate) settings: MeasurementSettings,
+
+    /// Filter the tests are selected with (empty if all the tests are run)
+    pub(crate) filter: &'a str,
+
+    pub(crate) mode: SessionMode<'a>,
+
+    /// Seed all the test seeds are derived from (see [`RngFactory`])
+    pub(crate) master_seed: u64,
+
+    pub(crate) environment: Environment,
+
+    /// Names of the tests planned to run in the order of execution
+    pub(crate) tests: Vec<&'a str>,
+}
+
+/// Where baseline and candidate functions of a run come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SessionMode<'a> {
+    /// Functions of this executable are compared with the functions of the same name in a given executable
+    Compare { baseline: &'a Path },
+
+    /// Pairs of functions of this executable are compared (see [`cli::run_pairs()`])
+    Pairs,
+}
+
+/// Totals of a benchmarking run
+pub(crate) struct SessionSummary {
+    /// Time spent in each phase across all the tests
+    pub(crate) phases: PhaseTimings,
+
+    /// Wall time of the run including the time between tests (eg. outputs verification)
+    pub(crate) wall_time: Duration,
+
+    /// Measurement dumps successfully written during the run
+    pub(crate) dumps: Vec<PathBuf>,
+
+    /// Differences of the sentinel pairs (`None` if sentinels are disabled or not supported by the run)
+    pub(crate) sentinels: Option<noise::SentinelSummary>,
+}
+
+/// Function of a paired test
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Function of the baseline executable
+    Baseline,
+
+    /// Function of the candidate executable
+    Candidate,
+}
+
+/// Order the functions of a paired sample are called in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleOrder {
+    #[default]
+    CandidateFirst = 0,
+    BaselineFirst = 1,
+}
+
+impl SampleOrder {
+    /// Order of a given sample (0-based) of a test starting with a given order (the harness alternates it)
+    pub(crate) fn of_sample(idx: usize, first: SampleOrder) -> Self {
+        if idx.is_multiple_of(2) {
+            first
+        } else {
+            first.opposite()
+        }
+    }
+
+    /// Order of the first sample of a test with a given seed
+    ///
+    /// Chosen randomly per test, so the advantage of the first sample averages out across the suite.
+    pub(crate) fn first_for_seed(seed: u64) -> Self {
+        // Stream is independent of the sampler seeded with the same seed
+        if SmallRng::seed_from_u64(seed ^ FIRST_ORDER_SALT).gen() {
+            SampleOrder::BaselineFirst
+        } else {
+            SampleOrder::CandidateFirst
+        }
+    }
+
+    pub(crate) fn opposite(self) -> Self {
+        match self {
+            SampleOrder::CandidateFirst => SampleOrder::BaselineFirst,
+            SampleOrder::BaselineFirst => SampleOrder::CandidateFirst,
+        }
+    }
+}
+
+/// Salt of the seed the order of the first sample is derived from (see [`SampleOrder::first_for_seed()`])
+const FIRST_ORDER_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Hook called at the boundaries of each sample
+///
+/// Allows to integrate external measurement systems (eg. polling an energy meter). Both methods are called outside
+/// of the timed region, but the time spent in them is included in the test wall time. Warm-up samples are not
+/// observed. Observers are registered using [`cli::run_with_observers()`] or `tango_main!(settings, observers = [...])`.
+///
+/// See [`observers`] for the bundled implementations.
+pub trait SampleObserver {
+    /// Called before the sample is taken with the number of iterations it consists of
+    fn before_sample(&mut self, test: &str, side: Side, iterations: usize);
+
+    /// Called after the sample is taken with the value measured for it (nanoseconds for time-based tests)
+    fn after_sample(&mut self, test: &str, side: Side, value: u64);
+
+    /// Additional values of the last observed sample
+    ///
+    /// Values are summarized per test and side and reported along with the measured ones.
+    fn sample_values(&mut self) -> Vec<ObservedValue> {
+        vec![]
+    }
+}
+
+/// Name of the observed value of the energy consumed per iteration (in microjoules)
+pub(crate) const ENERGY: &str = "energy";
+
+/// Value attached to a sample by a [`SampleObserver`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObservedValue {
+    /// Name of the value (eg. `user_time`) shown in the reports
+    pub name: &'static str,
+
+    pub unit: Unit,
+
+    /// Value observed for the sample
+    pub value: f64,
+}
+
+/// Summary of the values attached to the samples of a test by observers
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ObservedSummary {
+    name: &'static str,
+    unit: Unit,
+    baseline: Option<Summary<f64>>,
+    candidate: Option<Summary<f64>>,
+
+    /// Paired differences (candidate - baseline) of the values observed in the same samples
+    diff: Option<Summary<f64>>,
+
+    /// Significance of the difference decided the same way as for the measured value
+    diff_estimate: Option<DiffEstimate>,
+}
+
+impl ObservedSummary {
+    /// Summarizes the values per name and side
+    ///
+    /// `values` are tagged with the number of the sample they are observed in. Each sample has the values observed
+    /// for both functions, so the value of the baseline is paired with the value of the same name observed for the
+    /// candidate in the same sample. Values observed for one of the sides only are left unpaired.
+    pub(crate) fn summarize(
+        values: &[(usize, Side, ObservedValue)],
+        settings: &MeasurementSettings,
+    ) -> Vec<Self> {
+        let mut names = vec![];
+        for (_, _, v) in values {
+            if !names.contains(&(v.name, v.unit)) {
+                names.push((v.name, v.unit));
+            }
+        }
+        let side_values = |name, side| {
+            values
+                .iter()
+                .filter(move |(_, s, v)| *s == side && v.name == name)
+                .map(|(sample, _, v)| (*sample, &v.value))
+        };
+        names
+            .into_iter()
+            .map(|(name, unit)| {
+                let baseline = Summary::from(side_values(name, Side::Baseline).map(|(_, v)| v));
+                let candidate_values =
+                    side_values(name, Side::Candidate).collect::<HashMap<_, _>>();
+                let diffs = side_values(name, Side::Baseline)
+                    .filter_map(|(sample, b)| Some(*candidate_values.get(&sample)? - b))
+                    .collect::<Vec<_>>();
+                let diff = Summary::from(&diffs);
+                let diff_estimate = baseline
+                    .zip(diff)
+                    .filter(|(baseline, _)| baseline.mean != 0.)
+                    .map(|(baseline, diff)| {
+                        let significance =
+                            stats::summary_significance(&diff, baseline.mean, settings);
+                        DiffEstimate::build(&significance)
+                    });
+                ObservedSummary {
+                    name,
+                    unit,
+                    baseline,
+                    candidate: Summary::from(side_values(name, Side::Candidate).map(|(_, v)| v)),
+                    diff,
+                    diff_estimate,
+                }
+            })
+            .collect()
+    }
+
+    /// Time per unit of energy of baseline and candidate in nanoseconds per microjoule (see
+    /// [`observers::RaplObserver`])
+    ///
+    /// `time` is the mean time of an iteration of baseline and candidate. Returns `None` if energy is not observed.
+    pub(crate) fn time_per_energy(observed: &[Self], time: [f64; 2]) -> Option<[Option<f64>; 2]> {
+        let energy = observed
+            .iter()
+            .find(|o| o.name == ENERGY && o.unit == Unit::Microjoules)?;
+        let ratio = |time: f64, energy: Option<Summary<f64>>| {
+            let energy = energy?.mean;
+            (energy > 0.).then(|| time / energy)
+        };
+        Some([
+            ratio(time[0], energy.baseline),
+            ratio(time[1], energy.candidate),
+        ])
+    }
+
+    /// Share of the system time in the CPU time of baseline and candidate in percent (see
+    /// [`observers::RusageObserver`])
+    ///
+    /// Returns `None` if user and system time are not observed.
+    pub(crate) fn system_share(observed: &[Self]) -> Option<[Option<f64>; 2]> {
+        let find = |name| observed.iter().find(|o| o.name == name);
+        let (user, sys) = (find("user_time")?, find("sys_time")?);
+        let share = |user: Option<Summary<f64>>, sys: Option<Summary<f64>>| {
+            let (user, sys) = (user?.mean, sys?.mean);
+            (user + sys > 0.).then(|| 100. * sys / (user + sys))
+        };
+        Some([
+            share(user.baseline, sys.baseline),
+            share(user.candidate, sys.candidate),
+        ])
+    }
+}
+
+/// Describes basic settings for the benchmarking process
+///
+/// This structure is passed to [`cli::run()`].
+///
+/// Should be created only with overriding needed properties, like so:
+/// ```rust
+/// use tango_bench::MeasurementSettings;
+///
+/// let settings = MeasurementSettings {
+///     min_iterations_per_sample: 1000,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeasurementSettings {
+    /// Strategy of removing outliers from the measurements before computing statistics
+    ///
+    /// Executables built before the strategies were introduced report it as `filter_outliers` flag.
+    #[serde(
+        alias = "filter_outliers",
+        deserialize_with = "outlier_detection_or_flag"
+    )]
+    pub outlier_detection: OutlierDetection,
+
+    /// Part of the samples outliers are detected in
+    ///
+    /// If not set, the outliers are detected in windows (see [`OutlierScope::Windowed`]) only if the differences
+    /// drift during the test (see [`stats::drift_detected()`]) and always globally with statistics version 1. Tests
+    /// with too few samples for several windows are always filtered globally.
+    pub outlier_scope: Option<OutlierScope>,
+
+    /// The number of samples per one generated haystack
+    pub samples_per_haystack: usize,
+
+    /// Minimum number of iterations in a sample for each of 2 tested functions
+    pub min_iterations_per_sample: usize,
+
+    /// The number of iterations in a sample for each of 2 tested functions
+    pub max_iterations_per_sample: usize,
+
+    pub sampler_type: SamplerType,
+
+    /// Strategy of deciding the number of samples of a test with a time limit
+    pub sample_planner: SamplePlanner,
+
+    /// Size of a CPU cache firewall in KBytes
+    ///
+    /// If set, the scheduler will perform a dummy data read between samples generation to spoil the CPU cache
+    ///
+    /// Cache firewall is a way to reduce the impact of the CPU cache on the benchmarking process. It tries
+    /// to minimize discrepancies in performance between two algorithms due to the CPU cache state.
+    pub cache_firewall: Option<usize>,
+
+    /// If true, scheduler will perform a yield of control back to the OS before taking each sample
+    ///
+    /// Yielding control to the OS is a way to reduce the impact of OS scheduler on the benchmarking process.
+    pub yield_before_sample: bool,
+
+    /// If true, the first sample after each haystack change is used as a warm-up and is not included in statistics
+    ///
+    /// Removes the cold-cache effects of a fresh haystack. Doesn't affect functions without haystack and cold-start
+    /// measurements.
+    pub discard_first_sample_after_haystack_change: bool,
+
+    /// Fraction of the test wall time spent in [`Generator::next_haystack()`] above which a warning is issued
+    pub generator_overhead_threshold: f64,
+
+    /// If true, the time spent generating haystacks is not counted towards the test duration
+    ///
+    /// For expensive generators this allows the configured duration to refer to measurement time only.
+    pub exclude_generator_time: bool,
+
+    /// If true, haystacks are reused for more than [`Self::samples_per_haystack`] samples when generating them
+    /// would take more than [`Self::generator_overhead_threshold`] of the sampling time
+    ///
+    /// Generation time is taken from [`MeasureTarget::cost_hints()`].
+    pub amortize_haystacks: bool,
+
+    /// Minimal relative difference (in percents) between functions considered meaningful
+    ///
+    /// Differences smaller than this are reported as equivalent when the confidence interval is narrow enough.
+    pub min_effect: f64,
+
+    /// Significance level of the test of the difference between functions (eg. `0.05`)
+    ///
+    /// The confidence intervals are reported at the same level. `None` is the 1% level.
+    pub significance: Option<f64>,
+
+    /// Version of the statistics the verdicts are computed with (see [`stats::STATS_VERSION`])
+    ///
+    /// Verdicts depend only on this version, so the gates relying on them don't flip with an upgrade of the harness
+    /// until the version is changed explicitly. Version 1 is the z-test with the critical z-score of 2.6 (at the
+    /// default significance level) used by the releases up to 0.4. Version 2 is Student's t-test, which is more
+    /// conservative for the tests with few samples and the same as the z-test for the tests with many samples. It
+    /// also detects the outliers in windows when the differences drift (see [`Self::outlier_scope`]) and makes the
+    /// differences explained by the order of the calls inconclusive. Settings and results written before the
+    /// statistics were versioned are read as version 1, versions newer than [`stats::STATS_VERSION`] are refused.
+    #[serde(default = "stats::legacy_stats_version")]
+    pub stats_version: u32,
+
+    /// Maximum time a test may run without completing a sample
+    ///
+    /// If set, the process is aborted with a message naming the test when it makes no progress for this long
+    /// (eg. a function entered an infinite loop on some haystack). Should be considerably larger than
+    /// the longest sample.
+    pub call_timeout: Option<Duration>,
+
+    /// Number of inputs outputs of baseline and candidate are compared on before measurement
+    ///
+    /// Applies only to functions registered with [`BenchmarkMatrix::add_function_verified()`]. `0` disables
+    /// verification.
+    pub verification_samples: usize,
+
+    /// Exact number of iterations in each sample
+    ///
+    /// If set, estimation of the number of iterations is skipped and the sampler is not used, so the schedule of
+    /// a test is the same for baseline and candidate and doesn't depend on the machine. Cold-start tests still run
+    /// a single iteration per sample.
+    pub fixed_iterations: Option<usize>,
+
+    /// Maximum wall time of estimating the number of iterations of both functions of a test
+    ///
+    /// If the estimation of baseline and candidate together takes longer, the test is sampled with a single
+    /// iteration per sample and a warning is issued. The candidate is not estimated at all if the baseline alone
+    /// exceeds the budget.
+    pub estimate_budget: Option<Duration>,
+
+    /// Exact number of samples of each test. Overrides the time limit of the run
+    pub exact_samples: Option<usize>,
+
+    /// If true, samples are not retained and results are computed from streaming statistics in constant memory
+    ///
+    /// Allows to run tests for tens of millions of samples. Outliers are not filtered and raw samples can't be
+    /// dumped in this mode.
+    pub streaming: bool,
+
+    /// Source of the time measurements, `None` to choose the finest available one (see [`probe_clocks()`])
+    ///
+    /// The host makes the library it compares against use the same source.
+    pub clock: Option<ClockSource>,
+
+    /// Memory the retained samples of a test may take (in bytes) before it is switched to a reservoir of samples
+    ///
+    /// Samples of such a test are summarized the same way as in [`Self::streaming`] mode, so the means and the
+    /// variances are still exact, while quantiles and outliers are taken from a random subset of the samples fitting
+    /// in this memory (see [`stats::Reservoir`]). Tests with a fixed number of samples are switched before sampling
+    /// if the planned samples don't fit, tests with a time limit once the samples exceed the limit.
+    pub max_sample_memory: usize,
+
+    /// Maximum length of a test name in bytes
+    ///
+    /// Names of the registered tests and of the tests of the baseline are checked when they are loaded (see
+    /// [`registry::normalize_name()`]), so a huge autogenerated name fails with an error naming the test instead of
+    /// breaking the reports.
+    pub max_name_len: usize,
+
+    /// Maximum wall time of a test with a fixed number of samples
+    ///
+    /// The test fails as soon as its projected duration exceeds this limit.
+    pub max_total_time: Option<Duration>,
+
+    /// Pause between tests letting the machine to cool down (not included in the time budget of the tests)
+    ///
+    /// With [`Self::adaptive_cooldown`] it is the maximum duration of the pause.
+    pub cooldown: Option<Duration>,
+
+    /// Pause until CPU temperature (or frequency if temperature is not available) returns close to the value
+    /// observed at the start of the run instead of sleeping for a fixed time
+    ///
+    /// Sensors are only read on Linux. On other platforms or if no sensors are found cooldown is disabled.
+    pub adaptive_cooldown: bool,
+
+    /// What to do with a test whose single call takes more than [`Self::slow_call_fraction`] of its time limit
+    pub slow_test_policy: SlowTestPolicy,
+
+    /// Fraction of the time limit of a test a single call may take before [`Self::slow_test_policy`] applies
+    pub slow_call_fraction: f64,
+
+    /// Number of samples the time limit of a slow test is extended to fit (see [`SlowTestPolicy::Extend`])
+    pub min_samples: usize,
+
+    /// Maximum time limit a slow test may be extended to (see [`SlowTestPolicy::Extend`])
+    pub max_auto_extend: Duration,
+
+    /// Measure per-iteration value of both functions at several batch sizes before sampling (see
+    /// [`stats::BatchCurve`])
+    pub batch_curve: bool,
+
+    /// Change of per-iteration value between the smallest and the largest batch (in percents) reported as a
+    /// batching effect
+    pub batch_curve_threshold: f64,
+
+    /// Keep sampling a test until the half-width of the confidence interval of the difference drops below a given
+    /// value (in percents) or [`Self::max_precision_time`] elapses
+    ///
+    /// Replaces the time limit of the tests. Not applied to the tests with a fixed number of samples.
+    pub target_precision: Option<f64>,
+
+    /// Hard time limit of a test sampled until [`Self::target_precision`] is reached
+    pub max_precision_time: Duration,
+
+    /// Number of samples between the checks of [`Self::target_precision`]. Outliers are filtered anew at each
+    /// check, so the precision is computed the same way as the final result
+    pub precision_check_interval: usize,
+
+    /// Measure each call of both functions separately after sampling and report tail latencies (see
+    /// [`stats::TailLatencies`])
+    pub per_call: bool,
+
+    /// Number of calls of each function measured in per-call mode
+    pub per_call_samples: usize,
+
+    /// Take a pair of samples of an empty function every given number of samples and split the variance of the
+    /// differences into the measurement overhead and the workload (see [`stats::OverheadVariance`])
+    ///
+    /// Empty function is sampled with the same number of iterations and through the same code path as the tested
+    /// functions. Only applied to the tests measuring time.
+    pub null_interval: Option<usize>,
+
+    /// Fraction of the variance of the differences explained by the measurement overhead above which the result is
+    /// reported as dominated by the overhead
+    pub overhead_variance_threshold: f64,
+
+    /// Allow comparing functions processing batches of different sizes (see
+    /// [`BenchmarkMatrix::add_batch_function()`])
+    ///
+    /// Both functions are reported per element, which is only meaningful if the cost of a batch is proportional to
+    /// its size. Without this setting such tests fail.
+    pub mixed_batches: bool,
+}
+
+/// Strategy of removing outliers from the paired differences
+///
+/// Baseline and candidate measurements of a sample are always removed together to keep pairing intact.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierDetection {
+    None,
+
+    /// Observations outside of Tukey's fences are removed (see [`iqr_variance_thresholds()`])
+    Iqr,
+
+    /// Given percentage of the smallest and the largest differences is removed (see [`trim_mask()`])
+    ///
+    /// Unlike [`OutlierDetection::Iqr`] doesn't depend on the shape of the distribution, so it behaves
+    /// predictably on bimodal distributions.
+    Trim {
+        lower_pct: f64,
+        upper_pct: f64,
+    },
+}
+
+/// Reads [`OutlierDetection`] given either as is or as the former `filter_outliers` flag (`true` is IQR)
+fn outlier_detection_or_flag<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<OutlierDetection, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Compat {
+        Flag(bool),
+        Detection(OutlierDetection),
+    }
+
+    Ok(match Compat::deserialize(deserializer)? {
+        Compat::Flag(true) => OutlierDetection::Iqr,
+        Compat::Flag(false) => OutlierDetection::None,
+        Compat::Detection(detection) => detection,
+    })
+}
+
+/// Part of the samples of a test the outlier thresholds are computed over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierScope {
+    /// Thresholds are computed over all the samples of a test
+    Global,
+
+    /// Samples are split into consecutive windows and the outliers are detected within each window, so a slow
+    /// drift of the measurements (eg. the machine heating up) is not mistaken for outliers on one of the tails
+    Windowed,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplerType {
+    Flat,
+    Linear,
+    Random,
+}
+
+/// Strategy of deciding the number of samples of a test with a time limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplePlanner {
+    /// Samples are taken until the time limit is reached
+    ///
+    /// The time limit is met precisely, but the number of samples depends on the state of the machine during
+    /// the test: if it slows down towards the end of the test, the late samples are underrepresented.
+    Deadline,
+
+    /// The number of samples is planned up front from the estimated cost of both functions
+    ///
+    /// The number of samples is even, so each function is measured first the same number of times, and is not
+    /// affected by the state of the machine during the test. The time limit is met only as precisely as
+    /// the estimate is: the test fails if it runs longer than twice the limit. Tests which are not estimated
+    /// (eg. cold-start or fixed iterations) are planned by the deadline.
+    Fixed,
+}
+
+/// Handling of the tests too slow for their time limit (see [`MeasurementSettings::slow_test_policy`])
+///
+/// A test with only a few samples produces a result which is mostly noise, but is reported like any other one.
+/// Single call time is known from the estimation of iterations, so only the tests measuring time within a time
+/// limit are checked (not cold-start tests or tests with fixed iterations or samples).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlowTestPolicy {
+    /// The time limit of the test is extended to fit [`MeasurementSettings::min_samples`] samples, but not above
+    /// [`MeasurementSettings::max_auto_extend`]
+    Extend,
+
+    /// The test is skipped and the reason is reported
+    Skip,
+
+    /// The test is run within its time limit anyway
+    Force,
+}
+
+/// Performs a dummy reads from memory to spoil given amount of CPU cache
+///
+/// Uses cache aligned data arrays to perform minimum amount of reads possible to spoil the cache
+struct CacheFirewall {
+    cache_lines: Vec<CacheLine>,
+}
+
+impl CacheFirewall {
+    fn new(bytes: usize) -> Self {
+        let n = bytes / mem::size_of::<CacheLine>();
         let cache_lines = vec![CacheLine::default(); n];
         Self { cache_lines }
     }
 
-    fn issue_read(&self) {
-        for line in &self.cache_lines {
-            // Because CacheLine is aligned on 64 bytes it is enough to read single element from the array
-            // to spoil the whole cache line
-            unsafe { ptr::read_volatile(&line.0[0]) };
+    fn issue_read(&self) {
+        for line in &self.cache_lines {
+            // Because CacheLine is aligned on 64 bytes it is enough to read single element from the array
+            // to spoil the whole cache line
+            unsafe { ptr::read_volatile(&line.0[0]) };
+        }
+    }
+}
+
+#[repr(C)]
+#[repr(align(64))]
+#[derive(Default, Clone, Copy)]
+struct CacheLine([u16; 32]);
+
+pub const DEFAULT_SETTINGS: MeasurementSettings = MeasurementSettings {
+    outlier_detection: OutlierDetection::None,
+    outlier_scope: None,
+    samples_per_haystack: 1,
+    min_iterations_per_sample: 1,
+    max_iterations_per_sample: 5000,
+    sampler_type: SamplerType::Random,
+    sample_planner: SamplePlanner::Deadline,
+    cache_firewall: None,
+    yield_before_sample: false,
+    discard_first_sample_after_haystack_change: true,
+    generator_overhead_threshold: 0.5,
+    exclude_generator_time: false,
+    amortize_haystacks: false,
+    min_effect: 0.5,
+    significance: None,
+    stats_version: stats::STATS_VERSION,
+    call_timeout: None,
+    verification_samples: 10,
+    fixed_iterations: None,
+    estimate_budget: Some(Duration::from_secs(1)),
+    exact_samples: None,
+    streaming: false,
+    clock: None,
+    max_sample_memory: 64 << 20,
+    max_name_len: 256,
+    max_total_time: None,
+    cooldown: None,
+    adaptive_cooldown: false,
+    slow_test_policy: SlowTestPolicy::Extend,
+    slow_call_fraction: 0.25,
+    min_samples: 10,
+    max_auto_extend: Duration::from_secs(10),
+    batch_curve: false,
+    batch_curve_threshold: 10.,
+    target_precision: None,
+    max_precision_time: Duration::from_secs(10),
+    precision_check_interval: 100,
+    per_call: false,
+    per_call_samples: 10_000,
+    null_interval: None,
+    overhead_variance_threshold: 0.5,
+    mixed_batches: false,
+};
+
+impl Default for MeasurementSettings {
+    fn default() -> Self {
+        DEFAULT_SETTINGS
+    }
+}
+
+/// Approximate memory taken by a retained sample during sampling and analysis (in bytes)
+///
+/// Raw values and the number of iterations are copied several times during analysis (per-iteration values,
+/// differences, orders and the sorted copy for outlier detection).
+const SAMPLE_MEMORY: usize = 96;
+
+impl MeasurementSettings {
+    /// Number of samples of a test fitting in [`Self::max_sample_memory`]
+    pub(crate) fn sample_capacity(&self) -> usize {
+        (self.max_sample_memory / SAMPLE_MEMORY).max(1)
+    }
+}
+
+/// Source of reproducible seeds for the tests of a benchmarking run
+///
+/// Each test gets its own seed derived from the master seed and the test name, so the seed of a test doesn't depend
+/// on the set of tests being run or their order. Knowing the master seed is sufficient to reproduce the whole run.
+#[derive(Clone, Copy, Debug)]
+pub struct RngFactory {
+    seed: u64,
+}
+
+impl RngFactory {
+    /// Seeds derived from a given master seed
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Master seed of the run
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seed derived for a given test
+    pub fn seed_for(&self, test_name: &str) -> u64 {
+        // FNV-1a is used instead of std hashers, because they are not guaranteed to be stable between Rust versions
+        let mut hash = 0xcbf29ce484222325_u64 ^ self.seed;
+        for byte in test_name.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        SmallRng::seed_from_u64(hash).gen()
+    }
+
+    /// RNG for a given test
+    pub fn rng_for(&self, test_name: &str) -> SmallRng {
+        SmallRng::seed_from_u64(self.seed_for(test_name))
+    }
+}
+
+/// Sampler is responsible for determining the number of iterations to run for each sample
+///
+/// Different sampler strategies can influence the results heavily. For example, if function is dependent heavily
+/// on a memory subsystem, then it should be tested with different number of iterations to be representative
+/// for different memory access patterns and cache states.
+trait Sampler {
+    /// Returns the number of iterations to run for the next sample
+    ///
+    /// Accepts the number of iteration being run starting from 0.
+    fn next_sample_iterations(&mut self, iteration_no: usize) -> usize;
+}
+
+/// Runs the same number of iterations for each sample
+///
+/// Estimates the number of iterations based on the number of iterations achieved in 1 ms and uses
+/// this number as a base for the number of iterations for each sample. This is the default sampler which is
+/// suitable for most cases.
+struct FlatSampler {
+    iterations: usize,
+}
+
+impl FlatSampler {
+    /// Creates a new sampler
+    ///
+    /// estimate_1ms is the number of iterations to run to estimate the number of iterations to run in 1 ms
+    fn new(settings: &MeasurementSettings, estimate: usize) -> Self {
+        FlatSampler {
+            iterations: estimate.clamp(
+                settings.min_iterations_per_sample.max(1),
+                settings.max_iterations_per_sample,
+            ),
+        }
+    }
+}
+
+impl Sampler for FlatSampler {
+    fn next_sample_iterations(&mut self, _iteration_no: usize) -> usize {
+        self.iterations
+    }
+}
+
+struct LinearSampler {
+    max_iterations: usize,
+}
+
+impl LinearSampler {
+    fn new(settings: &MeasurementSettings, estimate: usize) -> Self {
+        Self {
+            max_iterations: estimate.clamp(
+                settings.min_iterations_per_sample.max(1),
+                settings.max_iterations_per_sample,
+            ),
+        }
+    }
+}
+
+impl Sampler for LinearSampler {
+    fn next_sample_iterations(&mut self, iteration_no: usize) -> usize {
+        (iteration_no % self.max_iterations) + 1
+    }
+}
+
+/// Sampler that randomly determines the number of iterations to run for each sample
+///
+/// This sampler uses a random number generator to decide the number of iterations for each sample.
+struct RandomSampler {
+    rng: SmallRng,
+    max_iterations: usize,
+}
+
+impl RandomSampler {
+    pub fn new(settings: &MeasurementSettings, estimate: usize, seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            max_iterations: estimate.clamp(
+                settings.min_iterations_per_sample.max(1),
+                settings.max_iterations_per_sample,
+            ),
+        }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn next_sample_iterations(&mut self, _iteration_no: usize) -> usize {
+        self.rng.gen_range(1..=self.max_iterations)
+    }
+}
+
+/// Calculates the result of the benchmarking run from the raw integer values reported by the functions
+///
+/// Thin wrapper over [`calculate_run_result()`]. Values are converted to `f64` before any arithmetic, so nothing
+/// is truncated when values are normalized to the number of iterations.
+///
+/// Functions processing batches (see [`BenchmarkMatrix::add_batch_function()`]) are measured per element: values are
+/// divided by `batch` sizes of baseline and candidate respectively.
+pub(crate) fn calculate_run_result_raw<N: Into<Arc<str>>>(
+    name: N,
+    baseline: &[u64],
+    candidate: &[u64],
+    iterations_per_sample: &[usize],
+    batch: [usize; 2],
+    first: SampleOrder,
+    settings: &MeasurementSettings,
+) -> Option<RunResult> {
+    let to_f64 = |values: &[u64], batch: usize| {
+        values
+            .iter()
+            .map(|&v| v as f64 / batch as f64)
+            .collect::<Vec<_>>()
+    };
+    let mut result = calculate_run_result(
+        name,
+        &to_f64(baseline, batch[0]),
+        &to_f64(candidate, batch[1]),
+        iterations_per_sample,
+        first,
+        settings,
+    )?;
+    result.per_element = batch != [1, 1];
+    Some(result)
+}
+
+/// Calculates the result of the benchmarking run
+///
+/// `baseline` and `candidate` are the cumulative values of all the iterations of each sample, `first` is the order
+/// of the first sample. Return None if no measurements were made
+pub(crate) fn calculate_run_result<N: Into<Arc<str>>>(
+    name: N,
+    baseline: &[f64],
+    candidate: &[f64],
+    iterations_per_sample: &[usize],
+    first: SampleOrder,
+    settings: &MeasurementSettings,
+) -> Option<RunResult> {
+    assert!(baseline.len() == candidate.len());
+    assert!(baseline.len() == iterations_per_sample.len());
+
+    let mut iterations_per_sample = iterations_per_sample.to_vec();
+    let mut orders = (0..baseline.len())
+        .map(|i| SampleOrder::of_sample(i, first))
+        .collect::<Vec<_>>();
+
+    let mut diff = candidate
+        .iter()
+        .zip(baseline.iter())
+        // Calculating difference between candidate and baseline
+        .map(|(&c, &b)| c - b)
+        .zip(iterations_per_sample.iter())
+        // Normalizing difference to iterations count
+        .map(|(diff, &iters)| diff / iters as f64)
+        .collect::<Vec<_>>();
+
+    // need to save number of original samples to calculate number of outliers correctly
+    let n = diff.len();
+
+    // Normalizing measurements to iterations count
+    let mut baseline = baseline
+        .iter()
+        .zip(iterations_per_sample.iter())
+        .map(|(&v, &iters)| v / iters as f64)
+        .collect::<Vec<_>>();
+    let mut candidate = candidate
+        .iter()
+        .zip(iterations_per_sample.iter())
+        .map(|(&v, &iters)| v / iters as f64)
+        .collect::<Vec<_>>();
+
+    // Outliers are detected on the differences, but removed from all the paired arrays by index
+    let keep = outlier_mask(&diff, settings);
+    let attribution = keep
+        .as_ref()
+        .map(|keep| OutlierAttribution::new(&baseline, &candidate, keep))
+        .unwrap_or_default();
+    if let Some(keep) = &keep {
+        retain_by_mask(&mut diff, keep);
+        retain_by_mask(&mut iterations_per_sample, keep);
+        retain_by_mask(&mut orders, keep);
+        retain_by_mask(&mut baseline, keep);
+        retain_by_mask(&mut candidate, keep);
+    }
+
+    let diff_summary = Summary::from(&diff)?;
+    let baseline_summary = Summary::from(&baseline)?;
+    let candidate_summary = Summary::from(&candidate)?;
+    let mut diff_by_ordering = [RunningSummary::default(); 2];
+    for (&d, &order) in diff.iter().zip(&orders) {
+        diff_by_ordering[order as usize].add(d);
+    }
+
+    let mut result = summarized_run_result(
+        name,
+        baseline_summary,
+        candidate_summary,
+        diff_summary,
+        by_ordering(&diff_by_ordering),
+        n - diff_summary.n,
+        settings,
+    );
+    result.outliers_baseline = attribution.baseline;
+    result.outliers_candidate = attribution.candidate;
+    result.outliers_unattributed = attribution.unattributed;
+    result.percentiles = SamplePercentiles::of(&baseline, &candidate, &diff);
+    result.samples = Some([baseline, candidate]);
+    Some(result)
+}
+
+/// Splits the variance of the differences of a test using the samples of the empty function (see
+/// [`MeasurementSettings::null_interval`])
+///
+/// `null` are the pairs of cumulative values of the empty function along with the number of iterations and the
+/// order of the calls. Values are normalized by the size of the batch and filtered the same way as the differences
+/// of the test, so both variances are comparable. Variances are taken within each order of the calls: the order
+/// bias is reported on its own (see [`stats::order_bias()`]) and is not noise.
+pub(crate) fn overhead_variance(
+    result: &RunResult,
+    null: &[(u64, u64, usize, SampleOrder)],
+    batch: usize,
+    settings: &MeasurementSettings,
+) -> Option<OverheadVariance> {
+    let per_element = |v: u64, iterations: usize| v as f64 / (iterations * batch) as f64;
+    let mut diff = null
+        .iter()
+        .map(|&(a, b, iterations, _)| per_element(b, iterations) - per_element(a, iterations))
+        .collect::<Vec<_>>();
+    let mut orders = null.iter().map(|p| p.3).collect::<Vec<_>>();
+    if let Some(keep) = outlier_mask(&diff, settings) {
+        retain_by_mask(&mut diff, &keep);
+        retain_by_mask(&mut orders, &keep);
+    }
+    let mut by_order = [RunningSummary::default(); 2];
+    for (&d, &order) in diff.iter().zip(&orders) {
+        by_order[order as usize].add(d);
+    }
+    let summary = Summary::from(&diff)?;
+    let null_summary = match by_ordering(&by_order) {
+        Some(by_order) => Summary {
+            variance: within_order_variance(&by_order),
+            ..summary
+        },
+        None => summary,
+    };
+    let diff_summary = match result.diff_by_ordering {
+        Some(by_order) => Summary {
+            variance: within_order_variance(&by_order),
+            ..result.diff
+        },
+        None => result.diff,
+    };
+    let overhead = null
+        .iter()
+        .flat_map(|&(a, b, iterations, _)| [per_element(a, iterations), per_element(b, iterations)])
+        .sum::<f64>()
+        / (null.len() * 2) as f64;
+    Some(OverheadVariance::new(
+        &diff_summary,
+        &null_summary,
+        overhead,
+    ))
+}
+
+/// Variance of the values around the mean of their own order of the calls
+fn within_order_variance(by_order: &[Summary<f64>; 2]) -> f64 {
+    let n = by_order.iter().map(|s| s.n).sum::<usize>();
+    let squares = by_order
+        .iter()
+        .map(|s| s.variance * (s.n as f64 - 1.).max(0.))
+        .sum::<f64>();
+    if n > 2 {
+        squares / (n - 2) as f64
+    } else {
+        0.
+    }
+}
+
+/// Maximum number of windows the outliers are detected in (see [`OutlierScope::Windowed`])
+const OUTLIER_WINDOWS: usize = 10;
+
+/// Minimum number of samples in a window the outliers are detected in
+const MIN_OUTLIER_WINDOW: usize = 20;
+
+/// Returns a mask where `true` marks the pairs left after filtering outliers of given per-iteration differences
+///
+/// `None` if outliers are not filtered.
+fn outlier_mask(diff: &[f64], settings: &MeasurementSettings) -> Option<Vec<bool>> {
+    let detection = settings.outlier_detection;
+    if detection == OutlierDetection::None {
+        return None;
+    }
+    let windows = (diff.len() / MIN_OUTLIER_WINDOW).min(OUTLIER_WINDOWS);
+    let windowed = match settings.outlier_scope {
+        Some(scope) => scope == OutlierScope::Windowed,
+        None if settings.stats_version <= stats::LEGACY_STATS_VERSION => false,
+        None => stats::drift_detected(diff, windows),
+    };
+    if !windowed || windows < 2 {
+        return detection_mask(diff, detection);
+    }
+    let mut keep = Vec::with_capacity(diff.len());
+    for range in stats::windows(diff.len(), windows) {
+        let len = range.len();
+        keep.extend(detection_mask(&diff[range], detection).unwrap_or_else(|| vec![true; len]));
+    }
+    Some(keep)
+}
+
+/// Same as [`outlier_mask()`], but the thresholds are always computed over all the given differences
+fn detection_mask(diff: &[f64], detection: OutlierDetection) -> Option<Vec<bool>> {
+    match detection {
+        OutlierDetection::None => None,
+        // Calculating measurements range. All measurements outside this interval concidered outliers
+        OutlierDetection::Iqr => iqr_variance_thresholds(diff.to_vec())
+            .map(|range| diff.iter().map(|d| range.contains(d)).collect::<Vec<_>>()),
+        OutlierDetection::Trim {
+            lower_pct,
+            upper_pct,
+        } => Some(trim_mask(diff, lower_pct, upper_pct)),
+    }
+}
+
+/// Per-iteration values of all the samples of a test paired in the order they were taken
+///
+/// Unlike [`RunResult::samples`], outliers are not removed but marked, so reporters are able to plot them (see
+/// [`Reporter::wants_samples()`]).
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PairedSamples {
+    pub(crate) baseline: Vec<f64>,
+    pub(crate) candidate: Vec<f64>,
+
+    /// `true` marks the pairs filtered as outliers
+    pub(crate) outliers: Vec<bool>,
+}
+
+impl PairedSamples {
+    /// Pairs the raw measurements the same way [`calculate_run_result_raw()`] does
+    pub(crate) fn new(
+        baseline: &[u64],
+        candidate: &[u64],
+        iterations_per_sample: &[usize],
+        batch: [usize; 2],
+        settings: &MeasurementSettings,
+    ) -> Self {
+        let per_element = |v: u64, batch: usize| v as f64 / batch as f64;
+        let mut diff = Vec::with_capacity(baseline.len());
+        let mut pairs = (
+            Vec::with_capacity(baseline.len()),
+            Vec::with_capacity(baseline.len()),
+        );
+        for ((&b, &c), &iters) in baseline.iter().zip(candidate).zip(iterations_per_sample) {
+            let (b, c) = (per_element(b, batch[0]), per_element(c, batch[1]));
+            diff.push((c - b) / iters as f64);
+            pairs.0.push(b / iters as f64);
+            pairs.1.push(c / iters as f64);
+        }
+        let outliers = match outlier_mask(&diff, settings) {
+            Some(keep) => keep.iter().map(|keep| !keep).collect(),
+            None => vec![false; diff.len()],
+        };
+        Self {
+            baseline: pairs.0,
+            candidate: pairs.1,
+            outliers,
+        }
+    }
+}
+
+/// Why sampling of a test was stopped (see [`MeasurementSettings::target_precision`])
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PrecisionStop {
+    /// Target precision was reached
+    Reached,
+
+    /// [`MeasurementSettings::max_precision_time`] elapsed before reaching the target precision
+    TimeCap,
+}
+
+/// Precision of a test sampled until the target precision is reached
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AchievedPrecision {
+    /// Half-width of the confidence interval of the difference (in percents)
+    pub(crate) half_width: f64,
+
+    pub(crate) stop: PrecisionStop,
+}
+
+/// Amount of data processed per second by the functions of a test (see [`Generator::haystack_size()`])
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ThroughputRates {
+    pub(crate) unit: ThroughputUnit,
+    pub(crate) baseline: f64,
+    pub(crate) candidate: f64,
+}
+
+impl ThroughputRates {
+    /// Rates of the functions processing a given amount of data per iteration in a given mean time per iteration
+    /// (in nanoseconds) of baseline and candidate
+    pub(crate) fn new(throughput: Throughput, time: [f64; 2]) -> Option<Self> {
+        if time.iter().any(|&ns| ns <= 0.) {
+            return None;
+        }
+        let [baseline, candidate] = time.map(|ns| throughput.per_iteration / ns * 1e9);
+        Some(Self {
+            unit: throughput.unit,
+            baseline,
+            candidate,
+        })
+    }
+}
+
+/// Side of the pair each rejected sample is attributed to
+///
+/// A rejected pair is attributed to a side if its value is 1.5 IQR away from the corresponding quartile of all the
+/// values of that side. Unlike [`iqr_variance_thresholds()`], fences of each side are not symmetrical, so spikes
+/// on one side only are attributed as well. A pair with both values being outliers is attributed to both sides.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct OutlierAttribution {
+    baseline: usize,
+    candidate: usize,
+
+    /// Pairs rejected only because of their difference, while neither of the values is an outlier
+    unattributed: usize,
+}
+
+impl OutlierAttribution {
+    /// `keep` marks the pairs left after filtering
+    fn new(baseline: &[f64], candidate: &[f64], keep: &[bool]) -> Self {
+        let is_outlier = |values: &[f64]| {
+            let range = tukey_fences(values.to_vec());
+            move |v: &f64| range.as_ref().is_some_and(|r| !r.contains(v))
+        };
+        let (baseline_outlier, candidate_outlier) = (is_outlier(baseline), is_outlier(candidate));
+
+        let mut attribution = Self::default();
+        let rejected = keep.iter().enumerate().filter(|(_, &keep)| !keep);
+        for (i, _) in rejected {
+            let b = baseline_outlier(&baseline[i]);
+            let c = candidate_outlier(&candidate[i]);
+            attribution.baseline += b as usize;
+            attribution.candidate += c as usize;
+            attribution.unattributed += (!b && !c) as usize;
+        }
+        attribution
+    }
+}
+
+/// Cumulative values of baseline and candidate in a sample along with the number of iterations
+pub(crate) type RawSample = (u64, u64, usize);
+
+/// Paired samples of a test accumulated without retaining them (see [`MeasurementSettings::streaming`])
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StreamingSamples {
+    baseline: StreamingStats,
+    candidate: StreamingStats,
+    diff: StreamingStats,
+
+    /// Differences of the samples taken in each [`SampleOrder`]
+    diff_by_ordering: [RunningSummary; 2],
+    samples: usize,
+
+    /// Order of the first sample
+    first: SampleOrder,
+
+    /// Number of elements processed by each iteration of baseline and candidate (see
+    /// [`calculate_run_result_raw()`])
+    batch: [usize; 2],
+
+    /// Random subset of the raw samples `(index, baseline, candidate, iterations)` if the samples don't fit in
+    /// [`MeasurementSettings::max_sample_memory`]
+    reservoir: Option<Reservoir<(usize, u64, u64, usize)>>,
+}
+
+impl StreamingSamples {
+    pub(crate) fn new(first: SampleOrder, batch: [usize; 2]) -> Self {
+        Self {
+            first,
+            batch,
+            ..Self::default()
+        }
+    }
+
+    /// Accumulates the samples retaining a random subset of a given size of them
+    pub(crate) fn with_reservoir(
+        first: SampleOrder,
+        batch: [usize; 2],
+        capacity: usize,
+        seed: u64,
+    ) -> Self {
+        Self {
+            reservoir: Some(Reservoir::new(capacity, seed)),
+            ..Self::new(first, batch)
+        }
+    }
+
+    /// Raw samples `(baseline, candidate, iterations)` retained in the reservoir in the order they were taken along
+    /// with the number of all the samples
+    ///
+    /// Reservoir replaces the samples in random positions, so the samples are sorted back in time order for the
+    /// outliers to be detected within temporal windows (see [`OutlierScope::Windowed`]).
+    pub(crate) fn reservoir_samples(&self) -> Option<(Vec<RawSample>, usize)> {
+        let reservoir = self.reservoir.as_ref()?;
+        let mut items = reservoir.items().to_vec();
+        items.sort_unstable_by_key(|&(idx, ..)| idx);
+        let samples = items.into_iter().map(|(_, b, c, i)| (b, c, i)).collect();
+        Some((samples, reservoir.seen()))
+    }
+
+    /// Adds cumulative values of all the iterations of a sample
+    pub(crate) fn add(&mut self, baseline: u64, candidate: u64, iterations: usize) {
+        if let Some(reservoir) = &mut self.reservoir {
+            reservoir.add((self.samples, baseline, candidate, iterations));
+        }
+        let baseline = baseline as f64 / (iterations * self.batch[0]) as f64;
+        let candidate = candidate as f64 / (iterations * self.batch[1]) as f64;
+        self.baseline.add(baseline);
+        self.candidate.add(candidate);
+        self.diff.add(candidate - baseline);
+        let order = SampleOrder::of_sample(self.samples, self.first);
+        self.diff_by_ordering[order as usize].add(candidate - baseline);
+        self.samples += 1;
+    }
+}
+
+/// Same as [`calculate_run_result()`], but from the samples accumulated in streaming mode
+///
+/// Outliers are not filtered, because it requires all the samples. Returns None if no measurements were made
+pub(crate) fn calculate_streaming_run_result<N: Into<Arc<str>>>(
+    name: N,
+    samples: &StreamingSamples,
+    settings: &MeasurementSettings,
+) -> Option<RunResult> {
+    let baseline = samples.baseline.summary()?;
+    let candidate = samples.candidate.summary()?;
+    let diff = samples.diff.summary()?;
+    let mut result = summarized_run_result(
+        name,
+        baseline,
+        candidate,
+        diff,
+        by_ordering(&samples.diff_by_ordering),
+        0,
+        settings,
+    );
+    result.per_element = samples.batch != [1, 1];
+    result.percentiles = Some(SamplePercentiles {
+        baseline: Percentiles::estimated(&samples.baseline)?,
+        candidate: Percentiles::estimated(&samples.candidate)?,
+        diff: Percentiles::estimated(&samples.diff)?,
+    });
+    Some(result)
+}
+
+/// Same as [`calculate_streaming_run_result()`], but quantiles and outliers are taken from the reservoir of the
+/// samples (see [`MeasurementSettings::max_sample_memory`])
+///
+/// Outliers are detected in the reservoir, but not removed from the summaries, which are computed from all the
+/// samples. Numbers of outliers are scaled to all the samples. Returns None if no measurements were made
+pub(crate) fn calculate_downsampled_run_result<N: Into<Arc<str>>>(
+    name: N,
+    samples: &StreamingSamples,
+    settings: &MeasurementSettings,
+) -> Option<RunResult> {
+    let mut result = calculate_streaming_run_result(name, samples, settings)?;
+    let Some((subset, seen)) = samples.reservoir_samples() else {
+        return Some(result);
+    };
+    let mut baseline = Vec::with_capacity(subset.len());
+    let mut candidate = Vec::with_capacity(subset.len());
+    let mut iterations = Vec::with_capacity(subset.len());
+    for &(b, c, i) in &subset {
+        baseline.push(b);
+        candidate.push(c);
+        iterations.push(i);
+    }
+    let retained = calculate_run_result_raw(
+        Arc::clone(&result.name),
+        &baseline,
+        &candidate,
+        &iterations,
+        samples.batch,
+        samples.first,
+        settings,
+    )?;
+    let scale = |n: usize| (n as f64 * seen as f64 / subset.len() as f64) as usize;
+    result.outliers = scale(retained.outliers);
+    result.outliers_baseline = scale(retained.outliers_baseline);
+    result.outliers_candidate = scale(retained.outliers_candidate);
+    result.outliers_unattributed = scale(retained.outliers_unattributed);
+    result.percentiles = retained.percentiles;
+    result.samples = retained.samples;
+    result.reservoir = Some(subset.len());
+    Some(result)
+}
+
+/// Summaries of the differences in each [`SampleOrder`] or `None` if there are no samples in one of them
+fn by_ordering(summaries: &[RunningSummary; 2]) -> Option<[Summary<f64>; 2]> {
+    Some([summaries[0].summary()?, summaries[1].summary()?])
+}
+
+/// Builds the result from the summaries of the samples making the same decision as [`stats::significance()`]
+fn summarized_run_result<N: Into<Arc<str>>>(
+    name: N,
+    baseline: Summary<f64>,
+    candidate: Summary<f64>,
+    diff: Summary<f64>,
+    diff_by_ordering: Option<[Summary<f64>; 2]>,
+    outliers: usize,
+    settings: &MeasurementSettings,
+) -> RunResult {
+    let significance = stats::summary_significance(&diff, baseline.mean, settings);
+    let mut diff_estimate = DiffEstimate::build(&significance);
+    let order_bias = diff_by_ordering.map(|[candidate_first, baseline_first]| {
+        stats::order_bias(&candidate_first, &baseline_first, baseline.mean)
+    });
+    if let Some(bias) = order_bias.filter(|_| settings.stats_version > stats::LEGACY_STATS_VERSION)
+    {
+        diff_estimate.account_order_bias(&bias, &diff, settings.min_effect);
+    }
+    // Functions with the same name until the harness tells otherwise
+    let name = name.into();
+    RunResult {
+        baseline,
+        candidate,
+        diff,
+        diff_by_ordering,
+        order_bias,
+        id: PairId::same(Arc::clone(&name)),
+        name,
+        diff_estimate,
+        stats_version: settings.stats_version,
+        outliers,
+        outliers_baseline: 0,
+        outliers_candidate: 0,
+        outliers_unattributed: 0,
+        cold: false,
+        e2e: false,
+        per_element: false,
+        weight: 1.,
+        generator: None,
+        recording: None,
+        throughput: None,
+        discarded: 0,
+        unit: Unit::Nanoseconds,
+        phases: PhaseTimings::default(),
+        estimate_overrun: None,
+        precision: None,
+        tail: None,
+        percentiles: None,
+        overhead_variance: None,
+        null_skipped: None,
+        dump: None,
+        schedule: TestSchedule::default(),
+        observed: vec![],
+        samples: None,
+        pairs: None,
+        reservoir: None,
+        confirmation: None,
+        aa_of: None,
+        shard: None,
+        profile: None,
+        cached: false,
+    }
+}
+
+/// Result of a test measured twice (eg. by `compare --confirm-top`) computed from the samples of both runs
+///
+/// Summaries of the runs are pooled, so each run is weighted by its number of samples, and the verdict is made
+/// from the pooled summaries the same way it's made in streaming mode. Quantiles of the samples are not retained.
+pub(crate) fn pooled_run_result(
+    first: &RunResult,
+    second: &RunResult,
+    settings: &MeasurementSettings,
+) -> RunResult {
+    let diff_by_ordering = first
+        .diff_by_ordering
+        .zip(second.diff_by_ordering)
+        .map(|([a1, a2], [b1, b2])| [a1.pooled(&b1), a2.pooled(&b2)]);
+    let pooled = summarized_run_result(
+        Arc::clone(&first.name),
+        first.baseline.pooled(&second.baseline),
+        first.candidate.pooled(&second.candidate),
+        first.diff.pooled(&second.diff),
+        diff_by_ordering,
+        first.outliers + second.outliers,
+        settings,
+    );
+    let mut phases = first.phases;
+    phases += second.phases;
+    RunResult {
+        name: pooled.name,
+        id: first.id.clone(),
+        baseline: pooled.baseline,
+        candidate: pooled.candidate,
+        diff: pooled.diff,
+        diff_by_ordering: pooled.diff_by_ordering,
+        order_bias: pooled.order_bias,
+        diff_estimate: pooled.diff_estimate,
+        outliers: pooled.outliers,
+        outliers_baseline: first.outliers_baseline + second.outliers_baseline,
+        outliers_candidate: first.outliers_candidate + second.outliers_candidate,
+        outliers_unattributed: first.outliers_unattributed + second.outliers_unattributed,
+        discarded: first.discarded + second.discarded,
+        phases,
+        samples: None,
+        pairs: None,
+        precision: None,
+        tail: None,
+        percentiles: None,
+        overhead_variance: None,
+        cached: false,
+        ..first.clone()
+    }
+}
+
+/// Outcome of re-measuring a test with a fresh seed and a doubled budget (see `compare --confirm-top`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Confirmation {
+    /// Second run found a significant difference in the same direction
+    Confirmed,
+
+    /// Second run found no significant difference or a difference in the opposite direction
+    Unconfirmed,
+}
+
+/// Contains the estimation of how much faster or slower is candidate function compared to baseline
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct DiffEstimate {
+    // Percentage of difference between candidate and baseline
+    //
+    // Negative value means that candidate is faster than baseline, positive - slower.
+    pct: f64,
+
+    // Is the difference statistically significant
+    significant: bool,
+
+    // Confidence interval of the difference (in percents)
+    ci: ConfidenceInterval,
+
+    verdict: Verdict,
+
+    // Verdict was downgraded to inconclusive because of the order bias
+    order_biased: bool,
+}
+
+impl DiffEstimate {
+    /// Builds [`DiffEstimate`] from the decision made on differences normalized by iterations count
+    fn build(significance: &SignificanceOutcome) -> Self {
+        Self {
+            pct: significance.pct,
+            significant: significance.verdict == Verdict::Different,
+            ci: significance.ci,
+            verdict: significance.verdict,
+            order_biased: false,
+        }
+    }
+
+    /// Downgrades the verdict to inconclusive if the order of calls affects the differences more than the
+    /// measured effect
+    ///
+    /// Each sample is shifted by a half of the bias in the direction depending on the order. The bias is ignored if
+    /// it is smaller than `min_effect` (in percents), because it can't change the conclusion about effects of
+    /// interest.
+    fn account_order_bias(&mut self, bias: &OrderBias, diff: &Summary<f64>, min_effect: f64) {
+        let shift = bias.bias.abs() / 2.;
+        if self.verdict != Verdict::Inconclusive
+            && bias.significant
+            && bias.pct.abs() / 2. > min_effect
+            && shift > diff.mean.abs()
+        {
+            self.verdict = Verdict::Inconclusive;
+            self.significant = false;
+            self.order_biased = true;
         }
     }
 }
 
-#[repr(C)]
-#[repr(align(64))]
-#[derive(Default, Clone, Copy)]
-struct CacheLine([u16; 32]);
+/// Describes the results of a single benchmark run
+///
+/// Results are serialized to be replayed from the cache (see [`cache::ResultCache`]).
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RunResult {
+    /// Display name of the test: the name of the compared functions or a custom name of the pair
+    name: Arc<str>,
 
-pub const DEFAULT_SETTINGS: MeasurementSettings = MeasurementSettings {
-    filter_outliers: false,
-    samples_per_haystack: 1,
-    min_iterations_per_sample: 1,
-    max_iterations_per_sample: 5000,
-    sampler_type: SamplerType::Random,
-    cache_firewall: None,
-    yield_before_sample: false,
-};
+    /// Functions compared by the test
+    id: PairId,
 
-impl Default for MeasurementSettings {
-    fn default() -> Self {
-        DEFAULT_SETTINGS
+    /// statistical summary of baseline function measurements
+    baseline: Summary<f64>,
+
+    /// statistical summary of candidate function measurements
+    candidate: Summary<f64>,
+
+    /// individual measurements of a benchmark (candidate - baseline)
+    diff: Summary<f64>,
+
+    /// Differences of the samples taken in each [`SampleOrder`] (`None` if one of the orders has no samples)
+    diff_by_ordering: Option<[Summary<f64>; 2]>,
+
+    /// Effect of the order the functions are called in
+    order_bias: Option<OrderBias>,
+
+    diff_estimate: DiffEstimate,
+
+    /// Version of the statistics the verdict is computed with (see [`MeasurementSettings::stats_version`])
+    #[serde(default = "stats::legacy_stats_version")]
+    stats_version: u32,
+
+    /// Numbers of detected and filtered outliers
+    outliers: usize,
+
+    /// Number of outliers with the baseline value being an outlier among baseline samples
+    #[serde(default)]
+    outliers_baseline: usize,
+
+    /// Number of outliers with the candidate value being an outlier among candidate samples
+    #[serde(default)]
+    outliers_candidate: usize,
+
+    /// Number of outliers with neither of the values being an outlier on its own side
+    #[serde(default)]
+    outliers_unattributed: usize,
+
+    /// Test was measured in cold-start mode (single iteration per sample)
+    cold: bool,
+
+    /// Setup of the state was included in the measurement (see [`benchmark_fn_including_setup()`])
+    e2e: bool,
+
+    /// Values are per element of a batch (see [`BenchmarkMatrix::add_batch_function()`])
+    #[serde(default)]
+    per_element: bool,
+
+    /// Importance of the test in the suite summary (see [`MeasureTargetExt::with_weight()`])
+    weight: f64,
+
+    /// Name of the generator producing the inputs of the candidate (see [`MeasureTarget::generator()`])
+    #[serde(default)]
+    generator: Option<String>,
+
+    /// File the inputs of the candidate are recorded to or replayed from (see [`MeasureTarget::recording()`])
+    #[serde(default)]
+    recording: Option<PathBuf>,
+
+    /// Data processed per second (`None` if the generator doesn't report haystack sizes or the unit is not time)
+    #[serde(default)]
+    throughput: Option<ThroughputRates>,
+
+    /// Number of warm-up samples taken after haystack change and discarded
+    discarded: usize,
+
+    /// Unit of all the measured values
+    unit: Unit,
+
+    /// Wall time of the test broken down by phases
+    phases: PhaseTimings,
+
+    /// Time spent estimating the number of iterations if it exceeded [`MeasurementSettings::estimate_budget`]
+    ///
+    /// Such a test is sampled with a single iteration per sample.
+    estimate_overrun: Option<Duration>,
+
+    /// Precision the test was sampled to if [`MeasurementSettings::target_precision`] is given
+    #[serde(default)]
+    precision: Option<AchievedPrecision>,
+
+    /// Latencies of the slowest calls if [`MeasurementSettings::per_call`] is enabled
+    #[serde(default)]
+    tail: Option<TailLatencies>,
+
+    /// p50, p95 and p99 of the per-iteration values (`None` for the results pooled from several runs)
+    ///
+    /// Computed from the values left after filtering outliers. In streaming mode outliers are not filtered and the
+    /// percentiles are estimated from all the values, unless [`MeasurementSettings::max_sample_memory`] is given and
+    /// they are computed from the filtered reservoir.
+    #[serde(default)]
+    percentiles: Option<SamplePercentiles>,
+
+    /// Variance of the differences split into the measurement overhead and the workload if
+    /// [`MeasurementSettings::null_interval`] is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overhead_variance: Option<OverheadVariance>,
+
+    /// Reason the empty function wasn't sampled although [`MeasurementSettings::null_interval`] is given
+    #[serde(skip)]
+    null_skipped: Option<&'static str>,
+
+    /// Path of the measurements dump or the reason it wasn't written (`None` if dumping is not requested)
+    #[serde(skip)]
+    dump: Option<Result<PathBuf, String>>,
+
+    /// Schedule the test was executed with (recorded in run manifests)
+    schedule: TestSchedule,
+
+    /// Values attached to the samples by [`SampleObserver`]s (results of runs with observers are not cached, so they
+    /// are only written to `--json` and `--ndjson`)
+    #[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    observed: Vec<ObservedSummary>,
+
+    /// Per-iteration values of baseline and candidate samples left after filtering outliers (`None` in streaming
+    /// mode and for cached results)
+    #[serde(skip)]
+    samples: Option<[Vec<f64>; 2]>,
+
+    /// All the samples including outliers, only retained if the reporter asks for them (see
+    /// [`Reporter::wants_samples()`])
+    #[serde(skip)]
+    pairs: Option<PairedSamples>,
+
+    /// Number of the samples quantiles and outliers are taken from if the samples didn't fit in
+    /// [`MeasurementSettings::max_sample_memory`]
+    #[serde(default)]
+    reservoir: Option<usize>,
+
+    /// Outcome of the confirmation run if the result was among the most significant ones (see
+    /// [`pooled_run_result()`])
+    #[serde(default)]
+    confirmation: Option<Confirmation>,
+
+    /// Name of the test the result is an A/A comparison of (`--aa-test`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aa_of: Option<Arc<str>>,
+
+    /// Shard of the suite the test was run in (`compare --shard`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shard: Option<shard::Shard>,
+
+    /// Tolerance profile of the test (see [`MeasureTargetExt::with_profile()`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+
+    /// Result is replayed from the cache instead of measuring the test
+    #[serde(skip)]
+    cached: bool,
+}
+
+impl RunResult {
+    /// Fraction of the sampling loop wall time spent generating haystacks
+    fn generator_overhead(&self) -> f64 {
+        let p = &self.phases;
+        let sampling = p.warmup + p.generate + p.measure_base + p.measure_candidate;
+        if sampling.is_zero() {
+            0.
+        } else {
+            p.generate.as_secs_f64() / sampling.as_secs_f64()
+        }
     }
 }
 
-/// Sampler is responsible for determining the number of iterations to run for each sample
+/// Aggregate of the results of all the tests of a suite weighted by their importance
 ///
-/// Different sampler strategies can influence the results heavily. For example, if function is dependent heavily
-/// on a memory subsystem, then it should be tested with different number of iterations to be representative
-/// for different memory access patterns and cache states.
-trait Sampler {
-    /// Returns the number of iterations to run for the next sample
+/// See [`MeasureTargetExt::with_weight()`]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SuiteSummary {
+    /// Number of the tests aggregated
+    tests: usize,
+
+    total_weight: f64,
+
+    /// Weighted sum of the logarithms of candidate/baseline ratios
+    log_ratios: f64,
+
+    /// Weighted sum of the statistically significant regressions (in percents)
+    regressions: f64,
+}
+
+impl SuiteSummary {
+    pub(crate) fn add(&mut self, result: &RunResult) {
+        let estimate = &result.diff_estimate;
+        self.add_test(result.weight, estimate.pct, estimate.significant);
+    }
+
+    fn add_test(&mut self, weight: f64, pct: f64, significant: bool) {
+        let ratio = 1. + pct / 100.;
+        // Ratio is not defined if candidate is negative, which is possible only for non-time units
+        if !(ratio > 0. && ratio.is_finite()) {
+            return;
+        }
+        self.tests += 1;
+        self.total_weight += weight;
+        self.log_ratios += weight * ratio.ln();
+        if significant && pct > 0. {
+            self.regressions += weight * pct;
+        }
+    }
+
+    pub(crate) fn tests(&self) -> usize {
+        self.tests
+    }
+
+    /// Weighted geometric mean of candidate/baseline ratios expressed as a change in percents
+    pub(crate) fn geomean_pct(&self) -> f64 {
+        if self.total_weight == 0. {
+            return 0.;
+        }
+        ((self.log_ratios / self.total_weight).exp() - 1.) * 100.
+    }
+
+    /// Weighted mean of the significant regressions in percents
     ///
-    /// Accepts the number of iteration being run starting from 0.
-    fn next_sample_iterations(&mut self, iteration_no: usize) -> usize;
+    /// Improvements and insignificant changes count as zero, so improvements of some tests are not able to
+    /// compensate regressions of the others. This is the quantity checked against the regression budget given by
+    /// `--max-weighted-regression`.
+    pub(crate) fn weighted_regression(&self) -> f64 {
+        if self.total_weight == 0. {
+            return 0.;
+        }
+        self.regressions / self.total_weight
+    }
 }
 
-/// Runs the same number of iterations for each sample
+/// Wall time spent in each phase of a paired test
 ///
-/// Estimates the number of iterations based on the number of iterations achieved in 1 ms and uses
-/// this number as a base for the number of iterations for each sample. This is the default sampler which is
-/// suitable for most cases.
-struct FlatSampler {
-    iterations: usize,
+/// Phases are contiguous: the time between two phase boundaries is attributed to the latter phase, so the sum of
+/// all the phases is the wall time of the test. Bookkeeping of the measurement loop (sampler, yielding, cache
+/// firewall) is attributed to the phase following it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PhaseTimings {
+    /// Pause before the test letting the machine to cool down
+    cooldown: Duration,
+
+    /// Syncing generators and estimating the number of iterations per sample
+    estimate: Duration,
+
+    /// Warm-up samples discarded after haystack change
+    warmup: Duration,
+
+    /// Generating haystacks for both functions
+    generate: Duration,
+
+    measure_base: Duration,
+    measure_candidate: Duration,
+
+    /// Sampling the empty function (see [`MeasurementSettings::null_interval`])
+    #[serde(default)]
+    null: Duration,
+
+    /// Computing statistics and writing dumps
+    analyze: Duration,
 }
 
-impl FlatSampler {
-    /// Creates a new sampler
-    ///
-    /// estimate_1ms is the number of iterations to run to estimate the number of iterations to run in 1 ms
-    fn new(settings: &MeasurementSettings, estimate: usize) -> Self {
-        FlatSampler {
-            iterations: estimate.clamp(
-                settings.min_iterations_per_sample.max(1),
-                settings.max_iterations_per_sample,
-            ),
+impl PhaseTimings {
+    pub(crate) fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, d)| *d).sum()
+    }
+
+    /// Fraction of the total time a given duration takes
+    pub(crate) fn fraction(&self, duration: Duration) -> f64 {
+        let total = self.total();
+        if total.is_zero() {
+            0.
+        } else {
+            duration.as_secs_f64() / total.as_secs_f64()
         }
     }
+
+    /// Phases along with their names in the order of execution
+    pub(crate) fn phases(&self) -> [(&'static str, Duration); 8] {
+        [
+            ("cooldown", self.cooldown),
+            ("estimate", self.estimate),
+            ("warmup", self.warmup),
+            ("generate", self.generate),
+            ("baseline", self.measure_base),
+            ("candidate", self.measure_candidate),
+            ("null", self.null),
+            ("analyze", self.analyze),
+        ]
+    }
 }
 
-impl Sampler for FlatSampler {
-    fn next_sample_iterations(&mut self, _iteration_no: usize) -> usize {
-        self.iterations
+impl AddAssign for PhaseTimings {
+    fn add_assign(&mut self, rhs: Self) {
+        self.cooldown += rhs.cooldown;
+        self.estimate += rhs.estimate;
+        self.warmup += rhs.warmup;
+        self.generate += rhs.generate;
+        self.measure_base += rhs.measure_base;
+        self.measure_candidate += rhs.measure_candidate;
+        self.null += rhs.null;
+        self.analyze += rhs.analyze;
     }
 }
 
-struct LinearSampler {
-    max_iterations: usize,
+/// Removes elements marked with `false` in the mask preserving the order of remaining elements
+fn retain_by_mask<T>(values: &mut Vec<T>, mask: &[bool]) {
+    assert_eq!(values.len(), mask.len());
+    let mut mask = mask.iter();
+    values.retain(|_| *mask.next().unwrap());
 }
 
-impl LinearSampler {
-    fn new(settings: &MeasurementSettings, estimate: usize) -> Self {
-        Self {
-            max_iterations: estimate.clamp(
-                settings.min_iterations_per_sample.max(1),
-                settings.max_iterations_per_sample,
-            ),
+mod timer {
+    use super::{ClockSource, TimerResolution};
+    use std::{
+        hint::black_box,
+        sync::atomic::{AtomicU8, Ordering},
+        time::Instant,
+    };
+
+    /// Number of immediate start/stop pairs the resolution is measured with
+    const RESOLUTION_SAMPLES: usize = 5000;
+
+    /// Resolution of a clock (in nanoseconds) above which a finer clock is looked for when probing
+    const COARSE_RESOLUTION: u64 = 100;
+
+    /// [`ClockSource`] measurements are taken with (in the order of the variants), [`UNSELECTED`] until the first
+    /// measurement or [`select()`]
+    static SELECTED: AtomicU8 = AtomicU8::new(UNSELECTED);
+
+    const UNSELECTED: u8 = u8::MAX;
+
+    pub(super) type ActiveTimer = RuntimeTimer;
+
+    pub(super) trait Timer<T> {
+        fn start() -> T;
+        fn stop(start_time: T) -> u64;
+    }
+
+    /// Clock source measurements are taken with, probed on the first call if none is selected
+    #[inline]
+    pub(super) fn selected() -> ClockSource {
+        match SELECTED.load(Ordering::Relaxed) {
+            0 => ClockSource::Instant,
+            1 => ClockSource::MonotonicRaw,
+            2 => ClockSource::Rdtscp,
+            3 => ClockSource::Cntvct,
+            _ => select_probed(),
+        }
+    }
+
+    /// Makes all the following measurements use a given clock source. Returns `false` if it's not available
+    pub(super) fn select(source: ClockSource) -> bool {
+        if !is_available(source) {
+            return false;
+        }
+        SELECTED.store(source as u8, Ordering::Relaxed);
+        true
+    }
+
+    #[cold]
+    fn select_probed() -> ClockSource {
+        let (source, _) = probe();
+        select(source);
+        source
+    }
+
+    pub(super) fn is_available(source: ClockSource) -> bool {
+        match source {
+            ClockSource::Instant => true,
+            ClockSource::MonotonicRaw => cfg!(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos"
+            )),
+            #[cfg(target_arch = "x86_64")]
+            ClockSource::Rdtscp => x86::has_rdtscp(),
+            ClockSource::Cntvct => cfg!(target_arch = "aarch64"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Measures the resolution of a given clock source (`None` if it's not available)
+    pub(super) fn measure_source(source: ClockSource) -> Option<TimerResolution> {
+        if !is_available(source) {
+            return None;
+        }
+        let resolution = match source {
+            #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+            ClockSource::MonotonicRaw => measure_resolution::<_, raw::MonotonicRawTimer>(),
+            #[cfg(target_arch = "x86_64")]
+            ClockSource::Rdtscp => measure_resolution::<_, x86::RdtscpTimer>(),
+            #[cfg(target_arch = "aarch64")]
+            ClockSource::Cntvct => measure_resolution::<_, aarch64::CntvctTimer>(),
+            _ => measure_resolution::<_, PlatformTimer>(),
+        };
+        Some(TimerResolution {
+            source,
+            ..resolution
+        })
+    }
+
+    /// Chooses the clock source measurements are taken with along with the resolutions of all the probed sources
+    ///
+    /// Sources reporting nanoseconds are probed in the order of preference ([`Instant`], `CLOCK_MONOTONIC_RAW`,
+    /// `cntvct`) and the first one with a resolution of at most [`COARSE_RESOLUTION`] is chosen. If all of them
+    /// are coarser, the finest one is chosen. With `hw-timer` feature `rdtscp` is preferred if available.
+    pub(super) fn probe() -> (ClockSource, Vec<TimerResolution>) {
+        if cfg!(feature = "hw-timer") && is_available(ClockSource::Rdtscp) {
+            let probed = measure_source(ClockSource::Rdtscp).into_iter().collect();
+            return (ClockSource::Rdtscp, probed);
+        }
+        let mut probed = vec![];
+        for source in [
+            ClockSource::Instant,
+            ClockSource::MonotonicRaw,
+            ClockSource::Cntvct,
+        ] {
+            let Some(resolution) = measure_source(source) else {
+                continue;
+            };
+            probed.push(resolution);
+            if resolution.resolution <= COARSE_RESOLUTION {
+                return (source, probed);
+            }
+        }
+        let finest = probed
+            .iter()
+            .min_by_key(|r| r.resolution)
+            .map_or(ClockSource::Instant, |r| r.source);
+        (finest, probed)
+    }
+
+    /// Measures the resolution and the overhead of a given timer
+    ///
+    /// Resolution is the smallest nonzero delta of immediate start/stop pairs. If the timer is too coarse to ever
+    /// advance between two reads, the resolution is the smallest time it takes the timer to advance.
+    pub(super) fn measure_resolution<T: Copy, M: Timer<T>>() -> TimerResolution {
+        let deltas = (0..RESOLUTION_SAMPLES)
+            .map(|_| M::stop(black_box(M::start())))
+            .collect::<Vec<_>>();
+        let resolution = deltas.iter().copied().filter(|d| *d > 0).min();
+        let resolution = resolution.unwrap_or_else(|| {
+            (0..10)
+                .map(|_| {
+                    let start = M::start();
+                    loop {
+                        let delta = M::stop(start);
+                        if delta > 0 {
+                            break delta;
+                        }
+                    }
+                })
+                .min()
+                .unwrap_or(1)
+        });
+        let n = deltas.len() as f64;
+        let mean = deltas.iter().map(|d| *d as f64).sum::<f64>() / n;
+        let variance = deltas
+            .iter()
+            .map(|d| (*d as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.);
+        TimerResolution {
+            source: ClockSource::Instant,
+            resolution,
+            overhead_mean: mean,
+            overhead_stddev: variance.sqrt(),
+        }
+    }
+
+    /// Start of a measurement taken by [`RuntimeTimer`]
+    ///
+    /// Carries the source it was taken with, so the measurement is stopped with the same one.
+    #[derive(Clone, Copy)]
+    pub(super) enum Stamp {
+        Instant(Instant),
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+        MonotonicRaw(u64),
+        #[cfg(target_arch = "x86_64")]
+        Rdtscp(u64),
+        #[cfg(target_arch = "aarch64")]
+        Cntvct(u64),
+    }
+
+    /// Timer dispatching to the [selected][selected()] clock source
+    ///
+    /// Dispatch is a match on an atomic load which is always predicted correctly, so the overhead over a timer of
+    /// the same source is negligible.
+    pub(super) struct RuntimeTimer;
+
+    impl Timer<Stamp> for RuntimeTimer {
+        #[inline]
+        fn start() -> Stamp {
+            match selected() {
+                #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+                ClockSource::MonotonicRaw => Stamp::MonotonicRaw(raw::MonotonicRawTimer::start()),
+                #[cfg(target_arch = "x86_64")]
+                ClockSource::Rdtscp => Stamp::Rdtscp(x86::RdtscpTimer::start()),
+                #[cfg(target_arch = "aarch64")]
+                ClockSource::Cntvct => Stamp::Cntvct(aarch64::CntvctTimer::start()),
+                _ => Stamp::Instant(PlatformTimer::start()),
+            }
+        }
+
+        #[inline]
+        fn stop(start_time: Stamp) -> u64 {
+            match start_time {
+                Stamp::Instant(start) => PlatformTimer::stop(start),
+                #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+                Stamp::MonotonicRaw(start) => raw::MonotonicRawTimer::stop(start),
+                #[cfg(target_arch = "x86_64")]
+                Stamp::Rdtscp(start) => x86::RdtscpTimer::stop(start),
+                #[cfg(target_arch = "aarch64")]
+                Stamp::Cntvct(start) => aarch64::CntvctTimer::stop(start),
+            }
+        }
+    }
+
+    pub(super) struct PlatformTimer;
+
+    impl Timer<Instant> for PlatformTimer {
+        #[inline]
+        fn start() -> Instant {
+            Instant::now()
+        }
+
+        #[inline]
+        fn stop(start_time: Instant) -> u64 {
+            start_time.elapsed().as_nanos() as u64
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    pub(super) mod raw {
+        use super::Timer;
+
+        /// `clock_gettime(CLOCK_MONOTONIC_RAW)` which is not subject to NTP adjustments
+        pub struct MonotonicRawTimer;
+
+        impl MonotonicRawTimer {
+            #[inline]
+            fn now() -> u64 {
+                let mut ts = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_RAW, &mut ts) };
+                ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+            }
+        }
+
+        impl Timer<u64> for MonotonicRawTimer {
+            #[inline]
+            fn start() -> u64 {
+                Self::now()
+            }
+
+            #[inline]
+            fn stop(start: u64) -> u64 {
+                Self::now().saturating_sub(start)
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) mod x86 {
+        use super::Timer;
+        use std::{
+            arch::x86_64::{__cpuid, __rdtscp, _mm_mfence},
+            hint::spin_loop,
+            sync::OnceLock,
+            time::{Duration, Instant},
+        };
+
+        /// Time the frequency of the time stamp counter is calibrated for
+        const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+
+        /// Checks `RDTSCP` bit of the extended processor features
+        // `__cpuid` is a safe function on newer toolchains only
+        #[allow(unused_unsafe)]
+        pub fn has_rdtscp() -> bool {
+            // SAFETY: `cpuid` is available on all x86_64 processors
+            let max_extended = unsafe { __cpuid(0x8000_0000) }.eax;
+            // SAFETY: the leaf is checked to be supported above
+            max_extended >= 0x8000_0001 && unsafe { __cpuid(0x8000_0001) }.edx & (1 << 27) != 0
+        }
+
+        /// Time stamp counter, cycles are converted to nanoseconds using the frequency of the counter calibrated
+        /// against [`Instant`] on the first use
+        pub struct RdtscpTimer;
+
+        impl RdtscpTimer {
+            #[inline]
+            fn cycles() -> u64 {
+                unsafe { __rdtscp(&mut 0) }
+            }
+
+            /// Cycles of the counter per second
+            fn frequency() -> u64 {
+                static FREQUENCY: OnceLock<u64> = OnceLock::new();
+                *FREQUENCY.get_or_init(|| {
+                    let (instant, start) = (Instant::now(), Self::cycles());
+                    while instant.elapsed() < CALIBRATION_TIME {
+                        spin_loop();
+                    }
+                    let (elapsed, cycles) = (instant.elapsed(), Self::cycles() - start);
+                    (cycles as u128 * 1_000_000_000 / elapsed.as_nanos().max(1)).max(1) as u64
+                })
+            }
+        }
+
+        impl Timer<u64> for RdtscpTimer {
+            #[inline]
+            fn start() -> u64 {
+                unsafe { _mm_mfence() };
+                Self::cycles()
+            }
+
+            #[inline]
+            fn stop(start: u64) -> u64 {
+                let end = Self::cycles();
+                unsafe { _mm_mfence() };
+                let cycles = end.saturating_sub(start);
+                (cycles as u128 * 1_000_000_000 / Self::frequency() as u128) as u64
+            }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub(super) mod aarch64 {
+        use super::Timer;
+        use std::arch::asm;
+
+        /// Virtual counter of the generic timer (`cntvct_el0`), ticks are converted to nanoseconds using the
+        /// counter frequency (`cntfrq_el0`)
+        pub struct CntvctTimer;
+
+        impl CntvctTimer {
+            #[inline]
+            fn ticks() -> u64 {
+                let ticks: u64;
+                // Barrier keeps the counter from being read ahead of the preceding instructions
+                unsafe { asm!("isb", "mrs {}, cntvct_el0", out(reg) ticks, options(nostack)) };
+                ticks
+            }
+
+            #[inline]
+            fn frequency() -> u64 {
+                let frequency: u64;
+                unsafe { asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack)) };
+                frequency.max(1)
+            }
+        }
+
+        impl Timer<u64> for CntvctTimer {
+            #[inline]
+            fn start() -> u64 {
+                Self::ticks()
+            }
+
+            #[inline]
+            fn stop(start: u64) -> u64 {
+                let ticks = Self::ticks().saturating_sub(start);
+                (ticks as u128 * 1_000_000_000 / Self::frequency() as u128) as u64
+            }
         }
     }
 }
 
-impl Sampler for LinearSampler {
-    fn next_sample_iterations(&mut self, iteration_no: usize) -> usize {
-        (iteration_no % self.max_iterations) + 1
+fn median_execution_time(target: &mut dyn MeasureTarget, iterations: u32) -> u64 {
+    assert!(iterations >= 1);
+    let mut measures = SmallBuffer::<u64, 16>::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        measures.push(target.measure(1));
     }
+    median(&mut measures).max(1)
 }
 
-/// Sampler that randomly determines the number of iterations to run for each sample
+/// Calls the function given number of times and returns the time it took
 ///
-/// This sampler uses a random number generator to decide the number of iterations for each sample.
-struct RandomSampler {
-    rng: SmallRng,
-    max_iterations: usize,
-}
-
-impl RandomSampler {
-    pub fn new(settings: &MeasurementSettings, estimate: usize, seed: u64) -> Self {
-        Self {
-            rng: SmallRng::seed_from_u64(seed),
-            max_iterations: estimate.clamp(
-                settings.min_iterations_per_sample.max(1),
-                settings.max_iterations_per_sample,
-            ),
+/// Outputs are kept alive until the timer is stopped, so their destructors are not measured. Small outputs are kept
+/// on the stack (see [`SmallBuffer`]). The function is responsible for `black_box()`-ing its output.
+#[inline(always)]
+fn measure_retaining<O>(iterations: usize, mut f: impl FnMut() -> O) -> u64 {
+    if mem::size_of::<O>() <= buffer::MAX_INLINE_SIZE {
+        let mut result = SmallBuffer::<O, { buffer::INLINE_CAPACITY }>::with_capacity(iterations);
+        let start = ActiveTimer::start();
+        for _ in 0..iterations {
+            result.push(f());
+        }
+        let time = ActiveTimer::stop(start);
+        drop(result);
+        time
+    } else {
+        let mut result = Vec::with_capacity(iterations);
+        let start = ActiveTimer::start();
+        for _ in 0..iterations {
+            result.push(f());
         }
+        let time = ActiveTimer::stop(start);
+        drop(result);
+        time
     }
 }
 
-impl Sampler for RandomSampler {
-    fn next_sample_iterations(&mut self, _iteration_no: usize) -> usize {
-        self.rng.gen_range(1..=self.max_iterations)
+/// Calls the function given number of times on the inputs prepared outside of the measurement and returns the time
+/// of each call
+///
+/// Outputs are dropped after the timer is stopped, so their destructors are not measured.
+#[inline(always)]
+fn measure_calls<I, O>(
+    iterations: usize,
+    mut input: impl FnMut() -> I,
+    mut f: impl FnMut(I) -> O,
+) -> Vec<u64> {
+    let mut times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let input = input();
+        let start = ActiveTimer::start();
+        let output = black_box(f(input));
+        times.push(ActiveTimer::stop(start));
+        drop(output);
     }
+    times
 }
 
-/// Calculates the result of the benchmarking run
-///
-/// Return None if no measurements were made
-pub(crate) fn calculate_run_result<N: Into<String>>(
-    name: N,
-    baseline: &[u64],
-    candidate: &[u64],
-    iterations_per_sample: &[usize],
-    filter_outliers: bool,
-) -> Option<RunResult> {
-    assert!(baseline.len() == candidate.len());
-    assert!(baseline.len() == iterations_per_sample.len());
-
-    let mut iterations_per_sample = iterations_per_sample.to_vec();
-
-    let mut diff = candidate
-        .iter()
-        .zip(baseline.iter())
-        // Calculating difference between candidate and baseline
-        .map(|(&c, &b)| (c as f64 - b as f64))
-        .zip(iterations_per_sample.iter())
-        // Normalizing difference to iterations count
-        .map(|(diff, &iters)| diff / iters as f64)
-        .collect::<Vec<_>>();
-
-    // need to save number of original samples to calculate number of outliers correctly
-    let n = diff.len();
+/// Number of timer reads the overhead of the timer is estimated from
+const TIMER_OVERHEAD_SAMPLES: usize = 1001;
 
-    // Normalizing measurements to iterations count
-    let mut baseline = baseline
-        .iter()
-        .zip(iterations_per_sample.iter())
-        .map(|(&v, &iters)| (v as f64) / (iters as f64))
-        .collect::<Vec<_>>();
-    let mut candidate = candidate
-        .iter()
-        .zip(iterations_per_sample.iter())
-        .map(|(&v, &iters)| (v as f64) / (iters as f64))
+/// Median time of starting and stopping the timer with nothing in between (in nanoseconds)
+///
+/// Included in each value measured by [`MeasureTarget::measure_detailed()`].
+pub fn timer_overhead() -> u64 {
+    let mut values = (0..TIMER_OVERHEAD_SAMPLES)
+        .map(|_| ActiveTimer::stop(black_box(ActiveTimer::start())))
         .collect::<Vec<_>>();
-
-    // Calculating measurements range. All measurements outside this interval concidered outliers
-    let range = if filter_outliers {
-        iqr_variance_thresholds(diff.to_vec())
-    } else {
-        None
-    };
-
-    // Cleaning measurements from outliers if needed
-    if let Some(range) = range {
-        // We filtering outliers to build statistical Summary and the order of elements in arrays
-        // doesn't matter, therefore swap_remove() is used. But we need to make sure that all arrays
-        // has the same length
-        assert_eq!(diff.len(), baseline.len());
-        assert_eq!(diff.len(), candidate.len());
-
-        let mut i = 0;
-        while i < diff.len() {
-            if range.contains(&diff[i]) {
-                i += 1;
-            } else {
-                diff.swap_remove(i);
-                iterations_per_sample.swap_remove(i);
-                baseline.swap_remove(i);
-                candidate.swap_remove(i);
-            }
-        }
-    };
-
-    let diff_summary = Summary::from(&diff)?;
-    let baseline_summary = Summary::from(&baseline)?;
-    let candidate_summary = Summary::from(&candidate)?;
-
-    let diff_estimate = DiffEstimate::build(&baseline_summary, &diff_summary);
-
-    Some(RunResult {
-        baseline: baseline_summary,
-        candidate: candidate_summary,
-        diff: diff_summary,
-        name: name.into(),
-        diff_estimate,
-        outliers: n - diff_summary.n,
-    })
+    median(&mut values)
 }
 
-/// Contains the estimation of how much faster or slower is candidate function compared to baseline
-pub(crate) struct DiffEstimate {
-    // Percentage of difference between candidate and baseline
-    //
-    // Negative value means that candidate is faster than baseline, positive - slower.
-    pct: f64,
-
-    // Is the difference statistically significant
-    significant: bool,
+/// Source of the time measurements (see [`MeasurementSettings::clock`])
+///
+/// Sources other than [`ClockSource::Instant`] are available only on some platforms (see
+/// [`ClockSource::is_available()`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClockSource {
+    /// [`std::time::Instant`]
+    #[default]
+    Instant,
+
+    /// `clock_gettime(CLOCK_MONOTONIC_RAW)` on Linux, Android and macOS
+    MonotonicRaw,
+
+    /// Time stamp counter read with `rdtscp` on x86_64 converted to nanoseconds
+    Rdtscp,
+
+    /// Virtual counter of the generic timer (`cntvct_el0`) on aarch64 converted to nanoseconds
+    Cntvct,
 }
 
-impl DiffEstimate {
-    /// Builds [`DiffEstimate`] from flat sampling
-    ///
-    /// Flat sampling is a sampling where each measurement is normalized by the number of iterations.
-    /// This is needed to make measurements comparable between each other. Linear sampling is more
-    /// robust to outliers, but it is requiring more iterations.
-    ///
-    /// It is assumed that baseline and candidate are already normalized by iterations count.
-    fn build(baseline: &Summary<f64>, diff: &Summary<f64>) -> Self {
-        let std_dev = diff.variance.sqrt();
-        let std_err = std_dev / (diff.n as f64).sqrt();
-        let z_score = diff.mean / std_err;
-
-        // significant result is far away from 0 and have more than 0.5% base/candidate difference
-        // z_score = 2.6 corresponds to 99% significance level
-        let significant = z_score.abs() >= 2.6 && (diff.mean / baseline.mean).abs() > 0.005;
-        let pct = diff.mean / baseline.mean * 100.0;
+impl ClockSource {
+    /// Returns `true` if the source can be used on this machine
+    pub fn is_available(self) -> bool {
+        timer::is_available(self)
+    }
 
-        Self { pct, significant }
+    /// Source measurements are taken with
+    ///
+    /// Unless a source is given in [`MeasurementSettings::clock`], the finest available one is chosen on the first
+    /// measurement (see [`probe_clocks()`]).
+    pub fn active() -> Self {
+        timer::selected()
     }
 }
 
-/// Describes the results of a single benchmark run
-pub(crate) struct RunResult {
-    /// name of a test
-    name: String,
-
-    /// statistical summary of baseline function measurements
-    baseline: Summary<f64>,
+impl fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ClockSource::Instant => "instant",
+            ClockSource::MonotonicRaw => "monotonic-raw",
+            ClockSource::Rdtscp => "rdtscp",
+            ClockSource::Cntvct => "cntvct",
+        })
+    }
+}
 
-    /// statistical summary of candidate function measurements
-    candidate: Summary<f64>,
+/// Probes the available clock sources returning the one chosen for the measurements and the resolutions of all
+/// the probed ones
+///
+/// Sources reporting nanoseconds are probed in the order of preference ([`ClockSource::Instant`],
+/// [`ClockSource::MonotonicRaw`], [`ClockSource::Cntvct`]) and the first one with resolution of at most 100 ns is
+/// chosen, otherwise the finest one. With `hw-timer` feature [`ClockSource::Rdtscp`] is chosen if available.
+pub fn probe_clocks() -> (ClockSource, Vec<TimerResolution>) {
+    timer::probe()
+}
 
-    /// individual measurements of a benchmark (candidate - baseline)
-    diff: Summary<f64>,
+/// Empirical resolution of the active timer (see [`timer_resolution()`]), all the values are in nanoseconds
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimerResolution {
+    /// Source the resolution is measured for
+    #[serde(default)]
+    pub source: ClockSource,
 
-    diff_estimate: DiffEstimate,
+    /// Smallest nonzero difference between two timer reads
+    pub resolution: u64,
 
-    /// Numbers of detected and filtered outliers
-    outliers: usize,
-}
+    /// Mean time of starting and stopping the timer with nothing in between
+    pub overhead_mean: f64,
 
-/// Statistical summary for a given iterator of numbers.
-///
-/// Calculates all the information using single pass over the data. Mean and variance are calculated using
-/// streaming algorithm described in _Art of Computer Programming, Vol 2, page 232_.
-#[derive(Clone, Copy)]
-pub struct Summary<T> {
-    pub n: usize,
-    pub min: T,
-    pub max: T,
-    pub mean: f64,
-    pub variance: f64,
+    /// Standard deviation of the time of starting and stopping the timer
+    pub overhead_stddev: f64,
 }
 
-impl<T: PartialOrd> Summary<T> {
-    pub fn from<'a, C>(values: C) -> Option<Self>
-    where
-        C: IntoIterator<Item = &'a T>,
-        T: ToPrimitive + Copy + Default + 'a,
-    {
-        Self::running(values.into_iter().copied()).last()
+impl fmt::Display for TimerResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, resolution {} ns, overhead {:.1} ± {:.1} ns",
+            self.source, self.resolution, self.overhead_mean, self.overhead_stddev
+        )
     }
+}
 
-    pub fn running<I>(iter: I) -> impl Iterator<Item = Summary<T>>
-    where
-        T: ToPrimitive + Copy + Default,
-        I: Iterator<Item = T>,
-    {
-        RunningSummary {
-            iter,
-            n: 0,
-            min: T::default(),
-            max: T::default(),
-            mean: 0.,
-            s: 0.,
-        }
+/// Measures the resolution of the active timer
+///
+/// Differences of per-iteration times close to the resolution are quantization rather than performance (see
+/// [`TimerResolution`]).
+pub fn timer_resolution() -> TimerResolution {
+    let resolution = timer::measure_resolution::<_, ActiveTimer>();
+    TimerResolution {
+        source: timer::selected(),
+        ..resolution
     }
 }
 
-struct RunningSummary<T, I> {
-    iter: I,
-    n: usize,
-    min: T,
-    max: T,
-    mean: f64,
-    s: f64,
+fn median<T: Copy + Ord + Add<Output = T> + Div<Output = T>>(measures: &mut [T]) -> T {
+    assert!(!measures.is_empty(), "Vec is empty");
+    measures.sort_unstable();
+    measures[measures.len() / 2]
 }
 
-impl<T, I> Iterator for RunningSummary<T, I>
-where
-    T: Copy + PartialOrd,
-    I: Iterator<Item = T>,
-    T: ToPrimitive,
-{
-    type Item = Summary<T>;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generators::RandomVec;
+    use num_traits::ToPrimitive;
+    use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::{Cell, RefCell},
+        iter::Sum,
+        num::NonZeroU64,
+        rc::Rc,
+        thread,
+        time::Duration,
+    };
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let value = self.iter.next()?;
-        let fvalue = value.to_f64().expect("f64 overflow detected");
+    /// Counts heap allocations made by each thread, so tests can check what is allocated in the measurement loops
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
 
-        if self.n == 0 {
-            self.min = value;
-            self.max = value;
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+            System.alloc(layout)
         }
 
-        if let Some(Ordering::Less) = value.partial_cmp(&self.min) {
-            self.min = value;
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
         }
-        if let Some(Ordering::Greater) = value.partial_cmp(&self.max) {
-            self.max = value;
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations(target: &mut dyn MeasureTarget, iterations: usize) -> usize {
+        // Lazily initialized state is not counted
+        target.measure(1);
+        let before = ALLOCATIONS.with(Cell::get);
+        target.measure(iterations);
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn check_retained_outputs_are_not_allocated() {
+        // Outputs are dropped after the measurement, but don't allocate themselves
+        let mut targets = [
+            benchmark_fn("simple", String::new),
+            benchmark_fn_with_setup("setup", || 0, |_| String::new()),
+            benchmark_fn_including_setup("e2e", String::new, |s| s.len()),
+        ];
+        for target in &mut targets {
+            assert_eq!(
+                allocations(target.as_mut(), buffer::INLINE_CAPACITY),
+                0,
+                "{}",
+                target.name()
+            );
+            // Outputs beyond the inline capacity are kept on the heap
+            assert!(allocations(target.as_mut(), buffer::INLINE_CAPACITY + 1) > 0);
         }
 
-        self.n += 1;
-        let mean_p = self.mean;
-        self.mean += (fvalue - self.mean) / self.n as f64;
-        self.s += (fvalue - mean_p) * (fvalue - self.mean);
-        let variance = if self.n > 1 {
-            self.s / (self.n - 1) as f64
-        } else {
-            0.
+        let generator = generators::RandomVec::<u8>::new(10);
+        let mut target = GenFunc::new("gen", |_: &Vec<u8>, _: &()| String::new(), generator);
+        assert_eq!(allocations(&mut target, buffer::INLINE_CAPACITY), 0);
+    }
+
+    #[test]
+    fn check_throughput_from_haystack_sizes() {
+        let mut target = GenFunc::new("sum", |v: &Vec<u32>, _: &()| v.len(), RandomVec::new(10));
+        assert_eq!(target.throughput(), None);
+        assert!(target.next_haystack());
+        let expected = Throughput {
+            unit: ThroughputUnit::Bytes,
+            per_iteration: 40.,
         };
+        assert_eq!(target.throughput(), Some(expected));
 
-        Some(Summary {
-            n: self.n,
-            min: self.min,
-            max: self.max,
-            mean: self.mean,
-            variance,
-        })
+        // Wrappers report the throughput of the wrapped target
+        let target = (Box::new(target) as Box<dyn MeasureTarget>).with_weight(2.);
+        assert_eq!(target.throughput(), Some(expected));
+
+        let rates = ThroughputRates::new(expected, [20., 40.]).unwrap();
+        assert_eq!(rates.baseline, 2e9);
+        assert_eq!(rates.candidate, 1e9);
+        assert_eq!(ThroughputRates::new(expected, [0., 40.]), None);
     }
-}
 
-/// Outlier detection algorithm based on interquartile range
-///
-/// Observations that are 1.5 IQR away from the corresponding quartile are consideted as outliers
-/// as described in original Tukey's paper.
-pub fn iqr_variance_thresholds(mut input: Vec<f64>) -> Option<RangeInclusive<f64>> {
-    const MINIMUM_IQR: f64 = 1.;
+    #[test]
+    fn check_verification() {
+        let sample = |input: &str, output: &str| VerificationSample {
+            input: input.to_string(),
+            output: output.to_string(),
+        };
+        let baseline = [sample("1", "a"), sample("2", "b"), sample("3", "c")];
+        let verify =
+            |candidate: &[VerificationSample]| Verification::new(Some(&baseline), Some(candidate));
+
+        assert_eq!(verify(&baseline), Verification::Passed);
+
+        let candidate = [sample("1", "a"), sample("2", "x"), sample("3", "y")];
+        let expected = OutputMismatch {
+            sample: 1,
+            mismatched: 2,
+            total: 3,
+            input: "2".to_string(),
+            baseline: "b".to_string(),
+            candidate: "x".to_string(),
+        };
+        assert_eq!(verify(&candidate), Verification::Mismatch(expected));
+
+        let candidate = [sample("1", "a"), sample("4", "x"), sample("3", "c")];
+        assert!(matches!(verify(&candidate), Verification::Skipped(_)));
+        assert!(matches!(verify(&baseline[..2]), Verification::Skipped(_)));
+        assert!(matches!(
+            Verification::new(None, Some(&baseline)),
+            Verification::Skipped(_)
+        ));
+        assert_eq!(Verification::new(None, None), Verification::NotApplicable);
+    }
 
-    input.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-    let (q1, q3) = (input.len() / 4, input.len() * 3 / 4 - 1);
-    if q1 >= q3 || q3 >= input.len() {
-        return None;
+    #[test]
+    fn check_truncated_debug() {
+        assert_eq!(debug_truncated(&(1, "a"), 10), "(1, \"a\")");
+        assert_eq!(debug_truncated(&vec![0; 1000], 10), "[0, 0, 0, ...");
+        assert_eq!(truncated("abc", 3), "abc");
+        assert_eq!(truncated("abcd", 3), "abc...");
+        // Multibyte characters are not split
+        assert_eq!(truncated("aßc", 2), "a...");
     }
-    // In case q1 and q3 are equal, we need to make sure that IQR is not 0
-    // In the future it would be nice to measure system timer precision empirically.
-    let iqr = (input[q3] - input[q1]).max(MINIMUM_IQR);
 
-    let low_threshold = input[q1] - iqr * 1.5;
-    let high_threshold = input[q3] + iqr * 1.5;
+    #[test]
+    fn check_iqr_variance_thresholds() {
+        let mut rng = SmallRng::from_entropy();
 
-    // Calculating the indicies of the thresholds in an dataset
-    let low_threshold_idx =
-        match input[0..q1].binary_search_by(|probe| probe.total_cmp(&low_threshold)) {
-            Ok(idx) => idx,
-            Err(idx) => idx,
-        };
+        // Generate 20 random values in range [-50, 50]
+        // and add 10 outliers in each of two ranges [-1000, -200] and [200, 1000]
+        // This way IQR is no more than 100 and thresholds should be withing [-50, 50] range
+        let mut values = vec![];
+        values.extend((0..20).map(|_| rng.gen_range(-50.0..=50.)));
+        values.extend((0..10).map(|_| rng.gen_range(-1000.0..=-200.0)));
+        values.extend((0..10).map(|_| rng.gen_range(200.0..=1000.0)));
 
-    let high_threshold_idx =
-        match input[q3..].binary_search_by(|probe| probe.total_cmp(&high_threshold)) {
-            Ok(idx) => idx,
-            Err(idx) => idx,
-        };
+        let thresholds = iqr_variance_thresholds(values).unwrap();
 
-    if low_threshold_idx == 0 || high_threshold_idx >= input.len() {
-        return None;
+        assert!(
+            -50. <= *thresholds.start() && *thresholds.end() <= 50.,
+            "Invalid range: {:?}",
+            thresholds
+        );
     }
 
-    // Calculating the equal number of observations which should be removed from each "side" of observations
-    let outliers_cnt = low_threshold_idx.min(input.len() - high_threshold_idx);
+    /// This tests checks that the algorithm is stable in case of zero difference between 25 and 75 percentiles
+    #[test]
+    fn check_outliers_zero_iqr() {
+        let mut rng = SmallRng::from_entropy();
 
-    Some(input[outliers_cnt]..=(input[input.len() - outliers_cnt - 1]))
-}
+        let mut values = vec![];
+        values.extend(std::iter::repeat_n(0., 20));
+        values.extend((0..10).map(|_| rng.gen_range(-1000.0..=-200.0)));
+        values.extend((0..10).map(|_| rng.gen_range(200.0..=1000.0)));
 
-mod timer {
-    use std::time::Instant;
+        let thresholds = iqr_variance_thresholds(values).unwrap();
 
-    #[cfg(all(feature = "hw-timer", target_arch = "x86_64"))]
-    pub(super) type ActiveTimer = x86::RdtscpTimer;
+        assert!(
+            0. <= *thresholds.start() && *thresholds.end() <= 0.,
+            "Invalid range: {:?}",
+            thresholds
+        );
+    }
 
-    #[cfg(not(feature = "hw-timer"))]
-    pub(super) type ActiveTimer = PlatformTimer;
+    #[test]
+    fn check_trim_never_removes_more_than_requested() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let n = rng.gen_range(0..200);
+            // small range of values to have a lot of ties
+            let values = (0..n)
+                .map(|_| rng.gen_range(0..10) as f64)
+                .collect::<Vec<_>>();
+            let lower_pct = rng.gen_range(0.0..50.);
+            let upper_pct = rng.gen_range(0.0..50.);
+
+            let keep = trim_mask(&values, lower_pct, upper_pct);
+            let kept = values
+                .iter()
+                .zip(&keep)
+                .filter(|(_, k)| **k)
+                .map(|(v, _)| *v)
+                .collect::<Vec<_>>();
+            let removed = n - kept.len();
+
+            let max_lower = (n as f64 * lower_pct / 100.).floor() as usize;
+            let max_upper = (n as f64 * upper_pct / 100.).floor() as usize;
+            assert!(removed <= max_lower + max_upper);
+
+            // Only the smallest and the largest values are removed
+            let removed = values.iter().zip(&keep).filter(|(_, k)| !**k);
+            for (v, _) in removed {
+                let below_min = kept.iter().all(|k| v <= k);
+                let above_max = kept.iter().all(|k| v >= k);
+                assert!(below_min || above_max, "{} is not an extreme value", v);
+            }
+        }
+    }
 
-    pub(super) trait Timer<T> {
-        fn start() -> T;
-        fn stop(start_time: T) -> u64;
+    #[test]
+    fn check_f64_pipeline_agrees_with_integer_path() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let n = rng.gen_range(10..200);
+            let iterations = (0..n)
+                .map(|_| rng.gen_range(1..100))
+                .collect::<Vec<usize>>();
+            // Per-iteration values are integers, so the reference statistics can be calculated exactly
+            let mut per_iteration = || {
+                (0..n)
+                    .map(|_| rng.gen_range(1_000..1_000_000))
+                    .collect::<Vec<u64>>()
+            };
+            let (baseline, candidate) = (per_iteration(), per_iteration());
+            let total = |values: &[u64]| {
+                values
+                    .iter()
+                    .zip(&iterations)
+                    .map(|(&v, &i)| v * i as u64)
+                    .collect::<Vec<_>>()
+            };
+            let to_f64 = |values: &[u64]| values.iter().map(|&v| v as f64).collect::<Vec<_>>();
+            let (baseline_raw, candidate_raw) = (total(&baseline), total(&candidate));
+            let (baseline_f64, candidate_f64) = (to_f64(&baseline_raw), to_f64(&candidate_raw));
+
+            for outlier_detection in [OutlierDetection::None, OutlierDetection::Iqr] {
+                let settings = MeasurementSettings {
+                    outlier_detection,
+                    ..Default::default()
+                };
+                let raw = calculate_run_result_raw(
+                    "test",
+                    &baseline_raw,
+                    &candidate_raw,
+                    &iterations,
+                    [1, 1],
+                    SampleOrder::CandidateFirst,
+                    &settings,
+                )
+                .unwrap();
+                let float = calculate_run_result(
+                    "test",
+                    &baseline_f64,
+                    &candidate_f64,
+                    &iterations,
+                    SampleOrder::CandidateFirst,
+                    &settings,
+                )
+                .unwrap();
+                assert_eq!(raw.outliers, float.outliers);
+                assert_eq!(raw.baseline, float.baseline);
+                assert_eq!(raw.candidate, float.candidate);
+                assert_eq!(raw.diff, float.diff);
+            }
+
+            let result = calculate_run_result(
+                "test",
+                &baseline_f64,
+                &candidate_f64,
+                &iterations,
+                SampleOrder::CandidateFirst,
+                &MeasurementSettings::default(),
+            )
+            .unwrap();
+            let diffs = candidate
+                .iter()
+                .zip(&baseline)
+                .map(|(&c, &b)| c as i64 - b as i64)
+                .collect::<Vec<_>>();
+            let exact_mean = |sum: i128| sum as f64 / n as f64;
+            let baseline_mean = exact_mean(baseline.iter().map(|&v| v as i128).sum());
+            let diff_mean = exact_mean(diffs.iter().map(|&v| v as i128).sum());
+
+            assert_eq!(result.baseline.min, *baseline.iter().min().unwrap() as f64);
+            assert_eq!(result.baseline.max, *baseline.iter().max().unwrap() as f64);
+            assert_eq!(result.diff.min, *diffs.iter().min().unwrap() as f64);
+            assert_eq!(result.diff.max, *diffs.iter().max().unwrap() as f64);
+            assert!((result.baseline.mean - baseline_mean).abs() < 1e-6);
+            assert!((result.diff.mean - diff_mean).abs() < 1e-6);
+        }
     }
 
-    pub(super) struct PlatformTimer;
+    #[test]
+    fn check_reservoir_samples_are_in_time_order() {
+        let first = SampleOrder::CandidateFirst;
+        let mut streaming = StreamingSamples::with_reservoir(first, [1, 1], 100, 1);
+        for i in 0..10_000 {
+            streaming.add(i, i + 1, 1);
+        }
+        // Windows of the retained samples follow the drift of the test
+        let (samples, seen) = streaming.reservoir_samples().unwrap();
+        assert_eq!(seen, 10_000);
+        assert_eq!(samples.len(), 100);
+        assert!(samples.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(StreamingSamples::new(first, [1, 1])
+            .reservoir_samples()
+            .is_none());
+    }
 
-    impl Timer<Instant> for PlatformTimer {
-        #[inline]
-        fn start() -> Instant {
-            Instant::now()
+    #[test]
+    fn check_streaming_result_agrees_with_samples() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let iterations = (0..1000).map(|_| rng.gen_range(1..10)).collect::<Vec<_>>();
+        let baseline = iterations
+            .iter()
+            .map(|&i| i as u64 * rng.gen_range(100..120))
+            .collect::<Vec<_>>();
+        let candidate = iterations
+            .iter()
+            .map(|&i| i as u64 * rng.gen_range(105..125))
+            .collect::<Vec<_>>();
+        let settings = MeasurementSettings::default();
+
+        let mut streaming = StreamingSamples::new(SampleOrder::CandidateFirst, [1, 1]);
+        for ((&b, &c), &i) in baseline.iter().zip(&candidate).zip(&iterations) {
+            streaming.add(b, c, i);
         }
+        let streamed = calculate_streaming_run_result("test", &streaming, &settings).unwrap();
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+
+        assert_eq!(streamed.diff.n, result.diff.n);
+        assert!((streamed.baseline.mean - result.baseline.mean).abs() < 1e-9);
+        assert!((streamed.diff.mean - result.diff.mean).abs() < 1e-9);
+        assert!((streamed.diff.variance - result.diff.variance).abs() < 1e-6);
+        assert_eq!(streamed.diff_estimate.verdict, result.diff_estimate.verdict);
+        assert!(calculate_streaming_run_result("test", &Default::default(), &settings).is_none());
+
+        // Estimated percentiles are close to the exact ones
+        let (estimated, exact) = (streamed.percentiles.unwrap(), result.percentiles.unwrap());
+        for (estimate, exact) in [
+            (estimated.baseline.p50, exact.baseline.p50),
+            (estimated.candidate.p95, exact.candidate.p95),
+            (estimated.diff.p99, exact.diff.p99),
+        ] {
+            assert!((estimate - exact).abs() < 2., "{} != {}", estimate, exact);
+        }
+    }
 
-        #[inline]
-        fn stop(start_time: Instant) -> u64 {
-            start_time.elapsed().as_nanos() as u64
+    #[test]
+    fn check_percentiles_exclude_outliers() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut baseline = (0..1000)
+            .map(|_| rng.gen_range(100..120))
+            .collect::<Vec<u64>>();
+        let mut candidate = (0..1000)
+            .map(|_| rng.gen_range(100..120))
+            .collect::<Vec<u64>>();
+        // Spikes of the candidate are filtered the same way they are filtered from the differences
+        for i in (0..1000).step_by(100) {
+            candidate[i] = 10_000;
         }
+        baseline[1] = 5_000;
+        let iterations = vec![1; 1000];
+        let settings = MeasurementSettings::default();
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+
+        let percentiles = result.percentiles.unwrap();
+        let [baseline, candidate] = result.samples.as_ref().unwrap();
+        assert_eq!(Some(percentiles.baseline), Percentiles::of(baseline));
+        assert_eq!(Some(percentiles.candidate), Percentiles::of(candidate));
+        assert!(percentiles.candidate.p99 < 120.);
+        assert!(percentiles.baseline.p99 < 120.);
+        assert!(percentiles.diff.p50.abs() < 10.);
     }
 
-    #[cfg(all(feature = "hw-timer", target_arch = "x86_64"))]
-    pub(super) mod x86 {
-        use super::Timer;
-        use std::arch::x86_64::{__rdtscp, _mm_mfence};
+    #[test]
+    fn check_first_sample_advantage_averages_out() {
+        // Function called first in the first sample of a short run pays a cold-start penalty
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::None,
+            ..Default::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(5);
+        let iterations = [1; 20];
+        let mut suite_diff = |first_of: &dyn Fn(u64) -> SampleOrder| {
+            let tests = 2000;
+            let total = (0..tests)
+                .map(|seed| {
+                    let first = first_of(seed);
+                    let mut samples = || {
+                        (0..20)
+                            .map(|_| 1000 + rng.gen_range(0..5))
+                            .collect::<Vec<u64>>()
+                    };
+                    let (mut baseline, mut candidate) = (samples(), samples());
+                    match first {
+                        SampleOrder::BaselineFirst => baseline[0] += 100,
+                        SampleOrder::CandidateFirst => candidate[0] += 100,
+                    }
+                    calculate_run_result_raw(
+                        "test",
+                        &baseline,
+                        &candidate,
+                        &iterations,
+                        [1, 1],
+                        first,
+                        &settings,
+                    )
+                    .unwrap()
+                    .diff
+                    .mean
+                })
+                .sum::<f64>();
+            total / tests as f64
+        };
 
-        pub struct RdtscpTimer;
+        // Always starting with the candidate penalizes it by 100/20 ns on average
+        let fixed = suite_diff(&|_| SampleOrder::CandidateFirst);
+        assert!((fixed - 5.).abs() < 0.5, "{}", fixed);
 
-        impl Timer<u64> for RdtscpTimer {
-            #[inline]
-            fn start() -> u64 {
-                unsafe {
-                    _mm_mfence();
-                    __rdtscp(&mut 0)
-                }
-            }
+        let randomized = suite_diff(&SampleOrder::first_for_seed);
+        assert!(randomized.abs() < 0.5, "{}", randomized);
+    }
 
-            #[inline]
-            fn stop(start: u64) -> u64 {
-                unsafe {
-                    let end = __rdtscp(&mut 0);
-                    _mm_mfence();
-                    end - start
-                }
-            }
+    #[test]
+    fn check_overhead_variance_split() {
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::None,
+            ..Default::default()
+        };
+        // Differences alternate within each order of the calls: ±4 for the test and ±2 for the empty function
+        let pattern = |i: usize, amplitude: u64| [amplitude, amplitude, 0, 0][i % 4];
+        let first = SampleOrder::CandidateFirst;
+        let baseline = vec![1000; 400];
+        let candidate = (0..400)
+            .map(|i| 996 + 2 * pattern(i, 4))
+            .collect::<Vec<_>>();
+        let iterations = vec![1; 400];
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            first,
+            &settings,
+        )
+        .unwrap();
+
+        let null = |amplitude: u64| {
+            (0..400)
+                .map(|i| {
+                    (
+                        100,
+                        100 - amplitude + 2 * pattern(i, amplitude),
+                        1,
+                        SampleOrder::of_sample(i, first),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let overhead = overhead_variance(&result, &null(2), 1, &settings).unwrap();
+        assert_eq!(overhead.samples, 400);
+        assert!(
+            (overhead.overhead_fraction - 0.25).abs() < 1e-9,
+            "{:?}",
+            overhead
+        );
+        assert!((overhead.overhead - 100.).abs() < 1e-9, "{:?}", overhead);
+
+        let overhead = overhead_variance(&result, &null(0), 1, &settings).unwrap();
+        assert_eq!(overhead.overhead_fraction, 0.);
+        assert!(overhead_variance(&result, &[], 1, &settings).is_none());
+    }
+
+    #[test]
+    fn check_order_bias_downgrades_verdict() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        // Candidate is 1% slower, but the function called first is 5% slower on top of that
+        let mut samples = |order_effect: u64| {
+            let baseline = (0..1000)
+                .map(
+                    |i| match SampleOrder::of_sample(i, SampleOrder::CandidateFirst) {
+                        SampleOrder::BaselineFirst => 1000 + order_effect,
+                        SampleOrder::CandidateFirst => 1000,
+                    },
+                )
+                .map(|v| v + rng.gen_range(0..5))
+                .collect::<Vec<u64>>();
+            let candidate = (0..1000)
+                .map(
+                    |i| match SampleOrder::of_sample(i, SampleOrder::CandidateFirst) {
+                        SampleOrder::BaselineFirst => 1010,
+                        SampleOrder::CandidateFirst => 1010 + order_effect,
+                    },
+                )
+                .map(|v| v + rng.gen_range(0..5))
+                .collect::<Vec<u64>>();
+            (baseline, candidate)
+        };
+        let iterations = vec![1; 1000];
+        let settings = MeasurementSettings::default();
+
+        let (baseline, candidate) = samples(0);
+        let unbiased = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(unbiased.diff_estimate.verdict, Verdict::Different);
+        assert!(!unbiased.order_bias.unwrap().significant);
+        assert!(!unbiased.diff_estimate.order_biased);
+
+        let (baseline, candidate) = samples(50);
+        let biased = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        let [candidate_first, baseline_first] = biased.diff_by_ordering.unwrap();
+        assert_eq!(candidate_first.n, 500);
+        assert!((candidate_first.mean - 60.).abs() < 1.);
+        assert!((baseline_first.mean + 40.).abs() < 1.);
+        let bias = biased.order_bias.unwrap();
+        assert!(bias.significant);
+        assert!((bias.bias - 100.).abs() < 1., "{}", bias.bias);
+        assert_eq!(biased.diff_estimate.verdict, Verdict::Inconclusive);
+        assert!(!biased.diff_estimate.significant);
+        assert!(biased.diff_estimate.order_biased);
+
+        // Bias is attributed to the order the samples are taken in, which depends on the order of the first one
+        let flipped = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::BaselineFirst,
+            &settings,
+        )
+        .unwrap();
+        assert!((flipped.order_bias.unwrap().bias + 100.).abs() < 1.);
+
+        // Streaming mode tracks the orders the same way
+        let mut streaming = StreamingSamples::new(SampleOrder::CandidateFirst, [1, 1]);
+        for (&b, &c) in baseline.iter().zip(&candidate) {
+            streaming.add(b, c, 1);
         }
+        let streamed = calculate_streaming_run_result("test", &streaming, &settings).unwrap();
+        assert_eq!(streamed.order_bias, biased.order_bias);
+        assert!(streamed.diff_estimate.order_biased);
     }
-}
 
-fn median_execution_time(target: &mut dyn MeasureTarget, iterations: u32) -> u64 {
-    assert!(iterations >= 1);
-    let measures: Vec<_> = (0..iterations).map(|_| target.measure(1)).collect();
-    median(measures).max(1)
-}
+    #[test]
+    fn check_fractional_values_are_not_truncated() {
+        // 3 ns per 2 iterations is reported as 1.5 ns, not as 1 ns
+        let settings = MeasurementSettings::default();
+        let result = calculate_run_result(
+            "test",
+            &[3., 3.],
+            &[4., 4.5],
+            &[2, 2],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(result.baseline.mean, 1.5);
+        assert_eq!(result.candidate.min, 2.);
+        assert_eq!(result.candidate.max, 2.25);
+        assert_eq!(result.diff.mean, 0.625);
+    }
 
-fn median<T: Copy + Ord + Add<Output = T> + Div<Output = T>>(mut measures: Vec<T>) -> T {
-    assert!(!measures.is_empty(), "Vec is empty");
-    measures.sort_unstable();
-    measures[measures.len() / 2]
-}
+    #[test]
+    fn check_zero_trim_is_no_filtering() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let baseline = (0..1000)
+            .map(|_| rng.gen_range(100..200))
+            .collect::<Vec<u64>>();
+        let candidate = (0..1000)
+            .map(|_| rng.gen_range(100..200))
+            .collect::<Vec<u64>>();
+        let iterations = (0..1000)
+            .map(|_| rng.gen_range(1..10))
+            .collect::<Vec<usize>>();
+
+        let run = |outlier_detection| {
+            let settings = MeasurementSettings {
+                outlier_detection,
+                ..Default::default()
+            };
+            calculate_run_result_raw(
+                "test",
+                &baseline,
+                &candidate,
+                &iterations,
+                [1, 1],
+                SampleOrder::CandidateFirst,
+                &settings,
+            )
+            .unwrap()
+        };
+        let unfiltered = run(OutlierDetection::None);
+        let trimmed = run(OutlierDetection::Trim {
+            lower_pct: 0.,
+            upper_pct: 0.,
+        });
+        assert_eq!(trimmed.outliers, 0);
+        assert_eq!(trimmed.diff, unfiltered.diff);
+        assert_eq!(trimmed.baseline, unfiltered.baseline);
+        assert_eq!(trimmed.candidate, unfiltered.candidate);
+
+        let trimmed = run(OutlierDetection::Trim {
+            lower_pct: 1.,
+            upper_pct: 2.5,
+        });
+        assert_eq!(trimmed.outliers, 10 + 25);
+        assert_eq!(trimmed.baseline.n, 1000 - 35);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
-    use std::{iter::Sum, thread, time::Duration};
+    #[test]
+    fn check_settings_with_filter_outliers_flag() {
+        let read = |json| {
+            serde_json::from_str::<MeasurementSettings>(json)
+                .unwrap()
+                .outlier_detection
+        };
+        assert_eq!(read(r#"{"filter_outliers":true}"#), OutlierDetection::Iqr);
+        assert_eq!(read(r#"{"filter_outliers":false}"#), OutlierDetection::None);
+        assert_eq!(
+            read(r#"{"outlier_detection":"iqr"}"#),
+            OutlierDetection::Iqr
+        );
+
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Trim {
+                lower_pct: 1.,
+                upper_pct: 2.,
+            },
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(read(&json), settings.outlier_detection);
+    }
 
     #[test]
-    fn check_iqr_variance_thresholds() {
-        let mut rng = SmallRng::from_entropy();
+    fn check_windowed_outliers_preserve_drift() {
+        // Machine heats up after 15% of the test, so the rest of the differences are uniformly larger. Each window of
+        // 100 samples has a spike on both tails
+        let diff = (0..1000)
+            .map(|i| match i % 100 {
+                50 => 1000.,
+                60 => -1000.,
+                _ => (i % 5) as f64 + if i < 150 { 0. } else { 100. },
+            })
+            .collect::<Vec<_>>();
+        let spike = |i: usize| i % 100 == 50 || i % 100 == 60;
+        let mask = |outlier_scope| {
+            let settings = MeasurementSettings {
+                outlier_detection: OutlierDetection::Iqr,
+                outlier_scope,
+                ..Default::default()
+            };
+            outlier_mask(&diff, &settings).unwrap()
+        };
 
-        // Generate 20 random values in range [-50, 50]
-        // and add 10 outliers in each of two ranges [-1000, -200] and [200, 1000]
-        // This way IQR is no more than 100 and thresholds should be withing [-50, 50] range
-        let mut values = vec![];
-        values.extend((0..20).map(|_| rng.gen_range(-50.0..=50.)));
-        values.extend((0..10).map(|_| rng.gen_range(-1000.0..=-200.0)));
-        values.extend((0..10).map(|_| rng.gen_range(200.0..=1000.0)));
+        // Global thresholds reject the early (cool) samples along with the spikes
+        let global = mask(Some(OutlierScope::Global));
+        assert!((0..150).all(|i| !global[i]));
 
-        let thresholds = iqr_variance_thresholds(values).unwrap();
+        // Windowed thresholds reject only the spikes
+        for windowed in [mask(Some(OutlierScope::Windowed)), mask(None)] {
+            let removed = (0..1000).filter(|&i| !windowed[i]).collect::<Vec<_>>();
+            assert_eq!(removed, (0..1000).filter(|&i| spike(i)).collect::<Vec<_>>());
+        }
 
-        assert!(
-            -50. <= *thresholds.start() && *thresholds.end() <= 50.,
-            "Invalid range: {:?}",
-            thresholds
-        );
+        // Without a drift the thresholds are global by default
+        let stationary = diff
+            .iter()
+            .enumerate()
+            .map(|(i, d)| if i < 150 && !spike(i) { d + 100. } else { *d })
+            .collect::<Vec<_>>();
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            ..Default::default()
+        };
+        let auto = outlier_mask(&stationary, &settings);
+        let global = detection_mask(&stationary, OutlierDetection::Iqr);
+        assert_eq!(auto, global);
     }
 
-    /// This tests checks that the algorithm is stable in case of zero difference between 25 and 75 percentiles
     #[test]
-    fn check_outliers_zero_iqr() {
-        let mut rng = SmallRng::from_entropy();
-
-        let mut values = vec![];
-        values.extend(std::iter::repeat(0.).take(20));
-        values.extend((0..10).map(|_| rng.gen_range(-1000.0..=-200.0)));
-        values.extend((0..10).map(|_| rng.gen_range(200.0..=1000.0)));
+    fn check_paired_samples_mark_outliers() {
+        let baseline = (0..100).map(|i| 200 + i % 7).collect::<Vec<u64>>();
+        let mut candidate = (0..100).map(|i| 100 + i % 5).collect::<Vec<u64>>();
+        candidate[50] += 20_000;
+        candidate[70] = 0;
+        let iterations = vec![2; 100];
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            ..Default::default()
+        };
+        let args = (&baseline, &candidate, &iterations, [1, 2]);
+        let pairs = PairedSamples::new(args.0, args.1, args.2, args.3, &settings);
+        let result = calculate_run_result_raw(
+            "test",
+            args.0,
+            args.1,
+            args.2,
+            args.3,
+            SampleOrder::BaselineFirst,
+            &settings,
+        )
+        .unwrap();
+
+        assert_eq!(pairs.baseline[0], 100.);
+        assert_eq!(pairs.candidate[0], 25.);
+        let outliers = (0..100).filter(|&i| pairs.outliers[i]).collect::<Vec<_>>();
+        assert_eq!(outliers, [50, 70]);
+        assert_eq!(result.outliers, outliers.len());
+        // Values left after filtering are the same as in the result
+        let kept = |values: &[f64]| {
+            values
+                .iter()
+                .zip(&pairs.outliers)
+                .filter(|(_, &outlier)| !outlier)
+                .map(|(v, _)| *v)
+                .collect::<Vec<_>>()
+        };
+        let [base, cand] = result.samples.unwrap();
+        assert_eq!(kept(&pairs.baseline), base);
+        assert_eq!(kept(&pairs.candidate), cand);
 
-        let thresholds = iqr_variance_thresholds(values).unwrap();
+        let pairs = PairedSamples::new(args.0, args.1, args.2, args.3, &DEFAULT_SETTINGS);
+        assert!(pairs.outliers.iter().all(|o| !o));
+    }
 
-        assert!(
-            0. <= *thresholds.start() && *thresholds.end() <= 0.,
-            "Invalid range: {:?}",
-            thresholds
-        );
+    #[test]
+    fn check_outliers_are_attributed_to_side() {
+        let mut baseline = (0..1000).map(|i| 100 + i * 7 % 11).collect::<Vec<u64>>();
+        let mut candidate = (0..1000).map(|i| 100 + i * 3 % 11).collect::<Vec<u64>>();
+        let iterations = vec![1; 1000];
+        // IQR filtering rejects the same number of the smallest and the largest differences
+        for c in &mut candidate[..5] {
+            *c += 10_000;
+        }
+        for b in &mut baseline[10..17] {
+            *b += 10_000;
+        }
+        for i in 30..32 {
+            baseline[i] += 10_000;
+            candidate[i] += 20_000;
+        }
+        // Difference is an outlier, but both of the values are within the range of their sides
+        baseline[20] = 96;
+        candidate[20] = 114;
+        baseline[21] = 114;
+        candidate[21] = 96;
+
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            ..Default::default()
+        };
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(result.outliers, 16);
+        assert_eq!(result.outliers_candidate, 5 + 2);
+        assert_eq!(result.outliers_baseline, 7 + 2);
+        assert_eq!(result.outliers_unattributed, 2);
+
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::None,
+            ..Default::default()
+        };
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(result.outliers_baseline + result.outliers_candidate, 0);
     }
 
     #[test]
@@ -1027,7 +6062,7 @@ mod tests {
             let stat = Summary::from(&values).unwrap();
 
             let sum = (i * (i + 1)) as f64 / 2.;
-            let expected_mean = sum as f64 / i as f64;
+            let expected_mean = sum / i as f64;
             let expected_variance = naive_variance(values.as_slice());
 
             assert_eq!(stat.min, 1);
@@ -1048,6 +6083,113 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_pooled_summary() {
+        let values = [3., 1., 4., 1., 5., 9., 2., 6.];
+        for split in 1..values.len() {
+            let (a, b) = values.split_at(split);
+            let pooled = Summary::from(a).unwrap().pooled(&Summary::from(b).unwrap());
+            let expected = Summary::from(&values).unwrap();
+            assert_eq!((pooled.n, pooled.min, pooled.max), (8, 1., 9.));
+            assert!((pooled.mean - expected.mean).abs() < 1e-9);
+            assert!((pooled.variance - expected.variance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn check_observed_system_share() {
+        let settings = MeasurementSettings::default();
+        let value = |name, value| ObservedValue {
+            name,
+            unit: Unit::Nanoseconds,
+            value,
+        };
+        let values = [
+            (0, Side::Baseline, value("user_time", 90.)),
+            (0, Side::Baseline, value("sys_time", 10.)),
+            (0, Side::Candidate, value("user_time", 90.)),
+            (0, Side::Candidate, value("sys_time", 30.)),
+            (1, Side::Candidate, value("user_time", 70.)),
+            (1, Side::Candidate, value("sys_time", 10.)),
+        ];
+        let observed = ObservedSummary::summarize(&values, &settings);
+        let [Some(base), Some(candidate)] = ObservedSummary::system_share(&observed).unwrap()
+        else {
+            panic!("Both sides should have a share");
+        };
+        assert!((base - 10.).abs() < 1e-9, "{}", base);
+        assert!((candidate - 20.).abs() < 1e-9, "{}", candidate);
+
+        // Only sides with observed CPU time have a share
+        let observed = ObservedSummary::summarize(&values[..2], &settings);
+        assert!(matches!(
+            ObservedSummary::system_share(&observed),
+            Some([Some(_), None])
+        ));
+        let observed = ObservedSummary::summarize(&values[..1], &settings);
+        assert!(ObservedSummary::system_share(&observed).is_none());
+
+        let json = serde_json::to_value(observed).unwrap();
+        assert_eq!(json[0]["name"], "user_time");
+        assert_eq!(json[0]["baseline"]["mean"], 90.);
+        assert!(json[0]["candidate"].is_null());
+    }
+
+    #[test]
+    fn check_observed_differences() {
+        let settings = MeasurementSettings::default();
+        let energy = |value| ObservedValue {
+            name: ENERGY,
+            unit: Unit::Microjoules,
+            value,
+        };
+        // Candidate consumes 10% more energy in each sample
+        let values = (0..100)
+            .flat_map(|i| {
+                let baseline = 100. + (i % 5) as f64;
+                [
+                    (i, Side::Baseline, energy(baseline)),
+                    (i, Side::Candidate, energy(baseline * 1.1)),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let [observed] = &ObservedSummary::summarize(&values, &settings)[..] else {
+            panic!("Single summary expected");
+        };
+        let diff = observed.diff.unwrap();
+        assert_eq!(diff.n, 100);
+        assert!((diff.mean - 10.2).abs() < 1e-9, "{}", diff.mean);
+        let estimate = observed.diff_estimate.as_ref().unwrap();
+        assert!((estimate.pct - 10.).abs() < 1e-9, "{}", estimate.pct);
+        assert!(estimate.significant);
+
+        // Time per iteration of 500 and 550 ns
+        let [Some(base), Some(candidate)] =
+            ObservedSummary::time_per_energy(std::slice::from_ref(observed), [500., 550.]).unwrap()
+        else {
+            panic!("Both sides should have a ratio");
+        };
+        assert!((base - 500. / 102.).abs() < 1e-9, "{}", base);
+        assert!((candidate - 550. / 112.2).abs() < 1e-9, "{}", candidate);
+
+        // Values are paired within the sample they are observed in, even if some samples miss them
+        let gaps = values
+            .iter()
+            .filter(|(i, side, _)| !(*side == Side::Baseline && i % 10 == 0))
+            .copied()
+            .collect::<Vec<_>>();
+        let [observed] = &ObservedSummary::summarize(&gaps, &settings)[..] else {
+            panic!("Single summary expected");
+        };
+        assert_eq!(observed.diff.unwrap().n, 90);
+        assert!((observed.diff_estimate.as_ref().unwrap().pct - 10.).abs() < 1e-9);
+
+        // Values observed for one side only are not compared
+        let observed = ObservedSummary::summarize(&values[..1], &settings);
+        assert!(observed[0].diff.is_none() && observed[0].diff_estimate.is_none());
+        assert!(ObservedSummary::time_per_energy(&[], [1., 1.]).is_none());
+    }
+
     #[test]
     fn check_summary_statistics_types() {
         Summary::from(<&[i64]>::default());
@@ -1055,6 +6197,42 @@ mod tests {
         Summary::from(&Vec::<i64>::default());
     }
 
+    /// Type without meaningful default value
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    struct Nanos(NonZeroU64);
+
+    impl ToPrimitive for Nanos {
+        fn to_i64(&self) -> Option<i64> {
+            self.0.get().to_i64()
+        }
+
+        fn to_u64(&self) -> Option<u64> {
+            Some(self.0.get())
+        }
+    }
+
+    #[test]
+    fn check_summary_of_types_without_default() {
+        let values = [5, 2, 9]
+            .map(|v| Nanos(NonZeroU64::new(v).unwrap()))
+            .to_vec();
+        let summary = Summary::from(&values).unwrap();
+        assert_eq!(summary.n, 3);
+        assert_eq!(summary.min, values[1]);
+        assert_eq!(summary.max, values[2]);
+        assert_eq!(summary.mean, 16. / 3.);
+
+        let running = Summary::running(values.iter().copied()).collect::<Vec<_>>();
+        assert_eq!(running.last(), Some(&summary));
+        // Bounds are never reported before the first value
+        assert_eq!((running[0].min, running[0].max), (values[0], values[0]));
+
+        assert_eq!(Summary::<Nanos>::from(&[]), None);
+        assert_eq!(Summary::running(iter::empty::<Nanos>()).next(), None);
+        assert_eq!(Summary::<i64>::from(&[]), None);
+        assert_eq!(Summary::running(iter::empty::<i64>()).next(), None);
+    }
+
     #[test]
     fn check_naive_variance() {
         assert_eq!(naive_variance(&[1, 2, 3]), 1.0);
@@ -1106,6 +6284,583 @@ mod tests {
         assert!(median < expected_delay * 10);
     }
 
+    #[test]
+    fn check_cold_target_resets_state() {
+        let setups = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&setups);
+        let setup = move || {
+            counter.set(counter.get() + 1);
+            None::<Vec<u8>>
+        };
+        let lazy_table = |t: &mut Option<Vec<u8>>| t.get_or_insert_with(|| vec![0; 1024]).len();
+
+        let mut warm = benchmark_fn_with_setup("warm", setup.clone(), lazy_table);
+        assert!(!warm.is_cold());
+        assert!(!warm.next_haystack());
+        warm.measure(10);
+        warm.measure(10);
+        assert_eq!(setups.get(), 1);
+
+        let mut cold = benchmark_fn_with_setup("cold", setup, lazy_table).measure_cold();
+        assert!(cold.is_cold());
+        assert_eq!(cold.name(), "cold");
+        for _ in 0..5 {
+            assert!(cold.next_haystack());
+            cold.measure(1);
+        }
+        assert_eq!(setups.get(), 6);
+    }
+
+    #[test]
+    fn check_setup_inclusion() {
+        let setups = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&setups);
+        let delay = Duration::from_millis(2);
+        let setup = move || {
+            counter.set(counter.get() + 1);
+            thread::sleep(delay);
+        };
+        let iterations = 5;
+
+        let mut excluded = benchmark_fn_with_setup("excluded", setup.clone(), |_| {});
+        assert!(!excluded.includes_setup());
+        excluded.measure(1);
+        setups.set(0);
+        let time = Duration::from_nanos(excluded.measure(iterations));
+        assert!(time < delay, "{:?}", time);
+        assert_eq!(setups.get(), 0);
+
+        let mut included = benchmark_fn_including_setup("included", setup.clone(), |_| {});
+        assert!(included.includes_setup());
+        let time = Duration::from_nanos(included.measure(iterations));
+        assert!(time >= delay * iterations as u32, "{:?}", time);
+        assert_eq!(setups.get(), iterations);
+
+        // Existing registrations can be converted and the flag survives wrappers
+        let converted = benchmark_fn_with_setup("converted", setup, |_| {})
+            .with_weight(2.)
+            .include_setup(true);
+        assert!(converted.includes_setup());
+        assert!(!converted.include_setup(false).includes_setup());
+        assert!(!benchmark_fn("plain", || 42)
+            .include_setup(true)
+            .includes_setup());
+    }
+
+    #[test]
+    fn check_weighted_target() {
+        let target = benchmark_fn("plain", || 42);
+        assert_eq!(target.weight(), 1.);
+
+        let weighted = benchmark_fn_with_setup("weighted", || 0, |s| *s)
+            .measure_cold()
+            .with_weight(5.);
+        assert_eq!(weighted.weight(), 5.);
+        assert!(weighted.is_cold());
+        assert_eq!(weighted.name(), "weighted");
+
+        // Weight is kept regardless of the order of combinators
+        let weighted = benchmark_fn("weighted", || 42)
+            .with_weight(0.5)
+            .measure_cold();
+        assert_eq!(weighted.weight(), 0.5);
+    }
+
+    #[test]
+    fn check_code_fingerprint() {
+        fn forty_two() -> usize {
+            42
+        }
+        fn forty_three() -> usize {
+            43
+        }
+        let fingerprint = |target: Box<dyn MeasureTarget>| target.code_fingerprint();
+
+        let copy_pasted = fingerprint(benchmark_fn("baseline", forty_two));
+        assert!(copy_pasted.is_some());
+        assert_eq!(
+            copy_pasted,
+            fingerprint(benchmark_fn("candidate", forty_two))
+        );
+        assert_eq!(
+            copy_pasted,
+            fingerprint(benchmark_fn("weighted", forty_two).with_weight(2.))
+        );
+        assert_ne!(copy_pasted, fingerprint(benchmark_fn("other", forty_three)));
+
+        // Behavior of capturing closures depends on the captured values
+        let value = 42;
+        assert_eq!(fingerprint(benchmark_fn("captured", move || value)), None);
+
+        fn gen_fingerprint<O>(f: impl Fn(&Vec<u32>, &()) -> O) -> Option<u64> {
+            GenFunc::new("gen", f, RandomVec::<u32>::new(10)).code_fingerprint()
+        }
+        let sum = |h: &Vec<u32>, _: &()| h.iter().sum::<u32>();
+        let max = |h: &Vec<u32>, _: &()| h.iter().copied().max();
+        assert!(gen_fingerprint(sum).is_some());
+        assert_eq!(gen_fingerprint(sum), gen_fingerprint(sum));
+        assert_ne!(gen_fingerprint(sum), gen_fingerprint(max));
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_weight_should_be_positive() {
+        benchmark_fn("weighted", || 42).with_weight(0.);
+    }
+
+    #[test]
+    fn check_suite_summary() {
+        let suite = |tests: &[(f64, f64, bool)]| {
+            let mut summary = SuiteSummary::default();
+            for &(weight, pct, significant) in tests {
+                summary.add_test(weight, pct, significant);
+            }
+            summary
+        };
+
+        let empty = suite(&[]);
+        assert_eq!(empty.tests(), 0);
+        assert_eq!(empty.geomean_pct(), 0.);
+        assert_eq!(empty.weighted_regression(), 0.);
+
+        // Unweighted geometric mean: sqrt(1.21 * 1.0) = 1.1
+        let summary = suite(&[(1., 21., true), (1., 0., false)]);
+        assert!((summary.geomean_pct() - 10.).abs() < 1e-9);
+        assert!((summary.weighted_regression() - 10.5).abs() < 1e-9);
+
+        // Hot path weighted 4 times the error path: (1.02^4 * 1.3)^(1/5)
+        let summary = suite(&[(4., 2., true), (1., 30., true)]);
+        let expected = ((1.02_f64.powi(4) * 1.3).powf(0.2) - 1.) * 100.;
+        assert!((summary.geomean_pct() - expected).abs() < 1e-9);
+        assert!((summary.weighted_regression() - (4. * 2. + 30.) / 5.).abs() < 1e-9);
+
+        // Improvements and insignificant changes are not consuming the regression budget
+        let summary = suite(&[(2., -50., true), (1., 6., false), (1., 4., true)]);
+        assert_eq!(summary.tests(), 3);
+        assert!((summary.weighted_regression() - 1.).abs() < 1e-9);
+        assert!(summary.geomean_pct() < 0.);
+
+        // Ratios are not defined for the changes of -100% and below
+        let summary = suite(&[(1., -100., true), (1., 10., true)]);
+        assert_eq!(summary.tests(), 1);
+        assert!((summary.geomean_pct() - 10.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn check_batched_run_result() {
+        let settings = MeasurementSettings {
+            outlier_detection: OutlierDetection::None,
+            ..Default::default()
+        };
+        // Baseline looks up one element per call in 100ns, candidate looks up 4 elements per call in 300ns
+        let iterations = [10, 20, 30];
+        let baseline = iterations.map(|i| 100 * i as u64);
+        let candidate = iterations.map(|i| 300 * i as u64);
+
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 4],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert!(result.per_element);
+        assert!((result.baseline.mean - 100.).abs() < 1e-9);
+        assert!((result.candidate.mean - 75.).abs() < 1e-9);
+        assert!((result.diff.mean + 25.).abs() < 1e-9);
+        assert!((result.diff_estimate.pct + 25.).abs() < 1e-9);
+
+        let mut streaming = StreamingSamples::new(SampleOrder::CandidateFirst, [1, 4]);
+        for ((b, c), i) in baseline.iter().zip(&candidate).zip(iterations) {
+            streaming.add(*b, *c, i);
+        }
+        let streamed = calculate_streaming_run_result("test", &streaming, &settings).unwrap();
+        assert!(streamed.per_element);
+        assert!((streamed.candidate.mean - 75.).abs() < 1e-9);
+        assert!((streamed.diff.mean + 25.).abs() < 1e-9);
+
+        // Results of the functions processing single elements are not labeled
+        let result = calculate_run_result_raw(
+            "test",
+            &baseline,
+            &candidate,
+            &iterations,
+            [1, 1],
+            SampleOrder::CandidateFirst,
+            &settings,
+        )
+        .unwrap();
+        assert!(!result.per_element);
+        assert!((result.candidate.mean - 300.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn check_batch_function() {
+        /// Generates consecutive numbers as needles
+        struct Counter(usize);
+
+        impl Generator for Counter {
+            type Haystack = ();
+            type Needle = usize;
+
+            fn next_haystack(&mut self) -> Self::Haystack {}
+
+            fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+                self.0 += 1;
+                self.0
+            }
+
+            fn sync(&mut self, _: u64) {
+                self.0 = 0;
+            }
+        }
+
+        let log = Rc::new(RefCell::new(vec![]));
+        let matrix_log = Rc::clone(&log);
+        let mut benchmarks = BenchmarkMatrix::new(Counter(0))
+            .add_batch_function("batch", 3, move |_, needles: &[usize]| {
+                matrix_log.borrow_mut().push(needles.to_vec())
+            })
+            .into_benchmarks();
+        let target = &mut benchmarks[0];
+        assert_eq!(target.name(), "batch/Counter");
+        assert_eq!(target.batch_len(), 3);
+
+        target.sync(0);
+        target.measure(2);
+        assert_eq!(log.take(), [[1, 2, 3], [4, 5, 6]]);
+
+        // Wrappers keep the batch size
+        let cold = benchmarks.pop().unwrap().measure_cold();
+        assert_eq!(cold.batch_len(), 3);
+        assert_eq!(benchmark_fn("single", || 1).batch_len(), 1);
+    }
+
+    #[test]
+    fn check_inout_function() {
+        /// Checksum of all the haystacks written in the order of the writes
+        #[derive(Default)]
+        struct Checksum(u64);
+
+        impl Resettable for Checksum {
+            fn reset(&mut self) {
+                self.0 = 0;
+            }
+        }
+
+        #[allow(clippy::ptr_arg)]
+        fn write(haystack: &Vec<u32>, _: &(), out: &mut Checksum) {
+            let sum = haystack.iter().map(|v| *v as u64).sum::<u64>();
+            out.0 = out.0.wrapping_mul(31).wrapping_add(sum);
+        }
+
+        let checksum = |haystack: &Vec<u32>, iterations| {
+            let mut out = Checksum::default();
+            (0..iterations).for_each(|_| write(haystack, &(), &mut out));
+            out.0
+        };
+
+        let measured = |reset| {
+            let g = InputCursor::exclusive(RandomVec::<u32>::new(10));
+            let mut target = InOutGenFunc::new("write", Shared::new(write), g, reset);
+            target.sync(0);
+            target.next_haystack();
+            target.measure(5);
+            target.measure(5);
+            let haystack = target.haystack.clone().unwrap();
+            (target, haystack)
+        };
+
+        // Scratch depends on all the iterations since the last reset
+        let (target, haystack) = measured(ScratchReset::Sample);
+        assert_eq!(target.name(), "write/u32/10");
+        assert_eq!(target.scratch.0, checksum(&haystack, 5));
+        let (target, haystack) = measured(ScratchReset::Never);
+        assert_eq!(target.scratch.0, checksum(&haystack, 10));
+        let (mut target, haystack) = measured(ScratchReset::Iteration);
+        assert_eq!(target.scratch.0, checksum(&haystack, 1));
+
+        // Cold measurements start with a fresh buffer
+        MeasureTarget::reset(&mut target);
+        assert_eq!(target.scratch.0, 0);
+
+        let benchmarks = BenchmarkMatrix::with_params([1, 2], RandomVec::<u32>::new)
+            .add_inout_function(
+                "copy",
+                ScratchReset::Iteration,
+                |v, _, out: &mut Vec<u32>| out.extend_from_slice(v),
+            )
+            .into_benchmarks();
+        assert_eq!(benchmarks.len(), 2);
+    }
+
+    #[test]
+    fn check_haystack_isolation() {
+        /// Haystack counting the calls of the benchmarked function
+        struct CallCounter;
+
+        impl Generator for CallCounter {
+            type Haystack = Cell<usize>;
+            type Needle = ();
+
+            fn next_haystack(&mut self) -> Self::Haystack {
+                Cell::new(0)
+            }
+
+            fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+            fn sync(&mut self, _: u64) {}
+        }
+
+        /// Runs baseline and candidate for several samples and returns the haystack states observed by both
+        fn observed_states(isolation: HaystackIsolation) -> (Vec<usize>, Vec<usize>) {
+            let target = |log: &Rc<RefCell<Vec<usize>>>| {
+                let log = Rc::clone(log);
+                let f =
+                    move |h: &Cell<usize>, _: &()| log.borrow_mut().push(h.replace(h.get() + 1));
+                GenFunc::new("counter", f, CallCounter).with_isolation(isolation)
+            };
+            let baseline_log = Rc::new(RefCell::new(vec![]));
+            let candidate_log = Rc::new(RefCell::new(vec![]));
+            let mut baseline = target(&baseline_log);
+            let mut candidate = target(&candidate_log);
+
+            baseline.next_haystack();
+            candidate.next_haystack();
+            for _ in 0..3 {
+                baseline.measure(1);
+                candidate.measure(1);
+            }
+            (baseline_log.take(), candidate_log.take())
+        }
+
+        let (baseline, candidate) = observed_states(HaystackIsolation::Shared);
+        assert_eq!(baseline, [0, 1, 2]);
+        assert_eq!(candidate, [0, 1, 2]);
+
+        let (baseline, candidate) = observed_states(HaystackIsolation::ClonedPerFunction);
+        assert_eq!(baseline, [0, 0, 0]);
+        assert_eq!(candidate, [0, 0, 0]);
+
+        // Isolation mode set on the matrix is propagated to all the functions
+        let log = Rc::new(RefCell::new(vec![]));
+        let matrix_log = Rc::clone(&log);
+        let mut benchmarks = BenchmarkMatrix::new(CallCounter)
+            .with_isolation(HaystackIsolation::ClonedPerFunction)
+            .add_function("counter", move |h: &Cell<usize>, _: &()| {
+                matrix_log.borrow_mut().push(h.replace(h.get() + 1))
+            })
+            .into_benchmarks();
+        benchmarks[0].measure(2);
+        benchmarks[0].measure(2);
+        assert_eq!(log.take(), [0, 1, 0, 1]);
+    }
+
+    /// Generator of a vector growing by a random element before each sample
+    struct GrowingVec(SmallRng);
+
+    impl Generator for GrowingVec {
+        type Haystack = Vec<u32>;
+        type Needle = ();
+
+        fn next_haystack(&mut self) -> Self::Haystack {
+            vec![self.0.gen()]
+        }
+
+        fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+        fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, sample_idx: usize) {
+            assert_eq!(haystack.len(), sample_idx);
+            haystack.push(self.0.gen());
+        }
+
+        fn mutates_haystack(&self) -> bool {
+            true
+        }
+
+        fn sync(&mut self, seed: u64) {
+            self.0 = SmallRng::seed_from_u64(seed);
+        }
+    }
+
+    #[test]
+    fn check_haystack_mutation() {
+        let logs = [(); 2].map(|_| Arc::new(Mutex::new(vec![])));
+        let [a_log, b_log] = logs.clone();
+        let mut benchmarks = BenchmarkMatrix::new(GrowingVec(SmallRng::seed_from_u64(0)))
+            .add_function("a", move |h: &Vec<u32>, _: &()| {
+                a_log.lock().unwrap().push(h.clone())
+            })
+            .add_function("b", move |h: &Vec<u32>, _: &()| {
+                b_log.lock().unwrap().push(h.clone())
+            })
+            .into_benchmarks();
+        let [a, b] = &mut benchmarks[..] else {
+            panic!("two functions are registered");
+        };
+
+        // Mutations are applied between the samples taken on the same haystack the way the harness does
+        a.sync(42);
+        b.sync(42);
+        for _ in 0..2 {
+            a.next_haystack();
+            b.next_haystack();
+            for sample in 0..3 {
+                if sample > 0 {
+                    a.mutate_haystack();
+                    b.mutate_haystack();
+                }
+                a.measure(2);
+                b.measure(2);
+            }
+        }
+
+        let [a_inputs, b_inputs] =
+            [&logs[0], &logs[1]].map(|log| mem::take(&mut *log.lock().unwrap()));
+        let lengths = a_inputs.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, [1, 1, 2, 2, 3, 3, 1, 1, 2, 2, 3, 3]);
+        assert_eq!(a_inputs, b_inputs);
+        // Haystack is not replaced by a mutation
+        assert_eq!(a_inputs[0][..], a_inputs[4][..1]);
+
+        // The same mutations are observed when the function is measured alone
+        b.sync(42);
+        b.next_haystack();
+        b.measure(2);
+        b.mutate_haystack();
+        b.measure(2);
+        assert_eq!(*logs[1].lock().unwrap(), a_inputs[..4]);
+
+        // Target without a haystack yet is not mutated
+        let mut fresh = GenFunc::new(
+            "f",
+            |h: &Vec<u32>, _: &()| h.len(),
+            GrowingVec(SmallRng::seed_from_u64(0)),
+        );
+        fresh.mutate_haystack();
+        fresh.measure(1);
+        assert_eq!(fresh.haystack.as_ref().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn check_matrix_functions_observe_same_inputs() {
+        /// Generator counting the haystacks generated by all the functions
+        struct Counting {
+            rng: SmallRng,
+            haystacks: Arc<Mutex<usize>>,
+        }
+
+        impl Generator for Counting {
+            type Haystack = u32;
+            type Needle = u32;
+
+            fn next_haystack(&mut self) -> Self::Haystack {
+                *self.haystacks.lock().unwrap() += 1;
+                self.rng.gen()
+            }
+
+            fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+                self.rng.gen()
+            }
+
+            fn sync(&mut self, seed: u64) {
+                self.rng = SmallRng::seed_from_u64(seed);
+            }
+        }
+
+        let haystacks = Arc::new(Mutex::new(0));
+        let generator = Counting {
+            rng: SmallRng::seed_from_u64(0),
+            haystacks: Arc::clone(&haystacks),
+        };
+        let logs = [(); 2].map(|_| Arc::new(Mutex::new(vec![])));
+        let [a_log, b_log] = logs.clone();
+        let mut benchmarks = BenchmarkMatrix::new(generator)
+            .add_function("a", move |h: &u32, n: &u32| {
+                a_log.lock().unwrap().push((*h, *n))
+            })
+            .add_function("b", move |h: &u32, n: &u32| {
+                b_log.lock().unwrap().push((*h, *n))
+            })
+            .into_benchmarks();
+        let [a, b] = &mut benchmarks[..] else {
+            panic!("two functions are registered");
+        };
+
+        // Functions are measured in lock-step the same way paired tests are
+        a.sync(42);
+        b.sync(42);
+        for _ in 0..3 {
+            a.next_haystack();
+            b.next_haystack();
+            for _ in 0..2 {
+                a.measure(3);
+                b.measure(3);
+            }
+        }
+
+        // Single generator instance produced the haystacks of both functions
+        assert_eq!(*haystacks.lock().unwrap(), 6);
+        let [a_inputs, b_inputs] =
+            [&logs[0], &logs[1]].map(|log| mem::take(&mut *log.lock().unwrap()));
+        assert_eq!(a_inputs.len(), 18);
+        assert_eq!(a_inputs, b_inputs);
+        let mut distinct = a_inputs.iter().map(|(h, _)| *h).collect::<Vec<_>>();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3, "haystacks are changed between samples");
+
+        // The same inputs are observed when the function is measured alone
+        b.sync(42);
+        for _ in 0..3 {
+            b.next_haystack();
+            b.measure(3);
+        }
+        let haystacks_alone = logs[1]
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(h, _)| *h)
+            .collect::<Vec<_>>();
+        assert!(haystacks_alone.chunks(3).map(|c| c[0]).eq(distinct));
+    }
+
+    #[test]
+    fn check_exclusive_generator_keeps_legacy_stream() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let inputs = Arc::clone(&log);
+        let mut target = GenFunc::new(
+            "f",
+            move |h: &Vec<u32>, _: &()| inputs.lock().unwrap().push(h.clone()),
+            RandomVec::<u32>::new(4),
+        );
+        target.sync(42);
+        for _ in 0..3 {
+            target.next_haystack();
+            target.measure(1);
+        }
+
+        // Generator is synced once and not reseeded between the haystacks
+        let mut g = RandomVec::<u32>::new(4);
+        g.sync_with(SmallRng::seed_from_u64(42));
+        let expected = (0..3).map(|_| g.next_haystack()).collect::<Vec<_>>();
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn check_rng_factory() {
+        let rng = RngFactory::new(42);
+        assert_eq!(rng.seed_for("a"), RngFactory::new(42).seed_for("a"));
+        assert_ne!(rng.seed_for("a"), rng.seed_for("b"));
+        assert_ne!(rng.seed_for("a"), RngFactory::new(43).seed_for("a"));
+
+        let mut a = rng.rng_for("a");
+        let mut b = RngFactory::new(42).rng_for("a");
+        assert_eq!(a.next_u64(), b.next_u64());
+    }
+
     struct RngIterator<T>(T);
 
     impl<T: RngCore> Iterator for RngIterator<T> {
@@ -1124,9 +6879,276 @@ mod tests {
         let n = values.len() as f64;
         let mean = f64::from(values.iter().copied().sum::<T>()) / n;
         let mut sum_of_squares = 0.;
-        for value in values.into_iter().copied() {
+        for value in values.iter().copied() {
             sum_of_squares += (f64::from(value) - mean).powi(2);
         }
         sum_of_squares / (n - 1.)
     }
+
+    fn check_resolution_of<T: Copy, M: timer::Timer<T>>() {
+        let first = timer::measure_resolution::<T, M>();
+        let second = timer::measure_resolution::<T, M>();
+        for r in [first, second] {
+            assert!(r.resolution > 0, "{}", r);
+            assert!(r.overhead_mean >= 0. && r.overhead_stddev >= 0., "{}", r);
+        }
+        // Minimum is stable between measurements even on a noisy machine
+        let (min, max) = (
+            first.resolution.min(second.resolution),
+            first.resolution.max(second.resolution),
+        );
+        assert!(max <= min * 10, "{} vs. {}", first, second);
+    }
+
+    #[test]
+    fn check_timer_resolution() {
+        check_resolution_of::<_, timer::PlatformTimer>();
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+        check_resolution_of::<_, timer::raw::MonotonicRawTimer>();
+        #[cfg(target_arch = "x86_64")]
+        if timer::x86::has_rdtscp() {
+            check_resolution_of::<_, timer::x86::RdtscpTimer>();
+        }
+    }
+
+    #[test]
+    fn check_clock_sources() {
+        for source in [
+            ClockSource::Instant,
+            ClockSource::MonotonicRaw,
+            ClockSource::Rdtscp,
+            ClockSource::Cntvct,
+        ] {
+            let resolution = timer::measure_source(source);
+            assert_eq!(resolution.is_some(), source.is_available(), "{}", source);
+            if let Some(resolution) = resolution {
+                assert_eq!(resolution.source, source);
+            }
+        }
+        assert!(ClockSource::Instant.is_available());
+        assert_eq!(
+            ClockSource::Cntvct.is_available(),
+            cfg!(target_arch = "aarch64")
+        );
+
+        let (chosen, probed) = probe_clocks();
+        assert!(chosen.is_available(), "{}", chosen);
+        assert!(probed.iter().any(|r| r.source == chosen));
+    }
+
+    #[cfg(feature = "send")]
+    #[test]
+    fn check_benchmarks_are_send() {
+        let benchmarks = thread::spawn(|| {
+            let mut benchmarks =
+                BenchmarkMatrix::with_params_send([10, 100], RandomVec::<u32>::new)
+                    .add_function("max", |h, _| h.iter().max().copied())
+                    .add_batch_function("sum_batch", 2, |h, n: &[()]| h.len() * n.len())
+                    .into_send_benchmarks();
+            benchmarks.push(send::benchmark_fn_with_setup("push", Vec::new, |v| {
+                v.push(1)
+            }));
+            benchmarks.push(send::benchmark_fn("cold", || 1));
+            benchmarks
+        })
+        .join()
+        .unwrap();
+
+        let mut benchmarks = send::into_benchmarks(benchmarks);
+        let cold = benchmarks.pop().unwrap().measure_cold();
+        benchmarks.push(cold);
+        for target in &mut benchmarks {
+            target.sync(0);
+            target.next_haystack();
+            target.measure(10);
+        }
+        let spi = dylib::Spi::for_benchmarks(benchmarks).unwrap();
+        assert_eq!(spi.tests().len(), 6);
+    }
+
+    /// Name of a test, per-sample totals of baseline and candidate and the number of iterations in each sample
+    type Fixture = (
+        &'static str,
+        &'static [u64],
+        &'static [u64],
+        &'static [usize],
+    );
+
+    /// Samples of the tests recorded by the releases before the statistics were versioned
+    const GOLDEN_FIXTURES: [Fixture; 6] = [
+        (
+            "equal",
+            &[
+                196101, 97057, 199005, 146395, 193881, 50809, 150081, 197047, 203596, 105403,
+                195836, 101317, 96951, 98169, 49624, 98281, 51711, 99175, 153114, 102804, 153620,
+                51117, 100329, 99051, 101615, 48938, 199466, 150010, 50964, 50678, 50085, 148458,
+            ],
+            &[
+                196814, 93885, 198043, 151098, 193400, 50400, 153528, 195465, 203948, 103297,
+                200453, 99305, 97823, 98379, 49753, 99348, 51100, 94817, 150334, 102590, 151759,
+                51530, 99536, 99468, 99934, 48889, 197220, 147317, 50189, 50198, 50745, 150938,
+            ],
+            &[
+                200, 100, 200, 150, 200, 50, 150, 200, 200, 100, 200, 100, 100, 100, 50, 100, 50,
+                100, 150, 100, 150, 50, 100, 100, 100, 50, 200, 150, 50, 50, 50, 150,
+            ],
+        ),
+        (
+            "regression",
+            &[
+                49675, 51149, 198126, 100625, 201837, 152125, 98021, 101882, 99906, 205868, 49629,
+                199268, 153982, 50348, 50648, 151737, 200497, 99817, 103302, 148154, 203230,
+                201222, 102058, 51147, 51304, 98669, 100601, 48773, 203878, 49317, 98442, 147601,
+            ],
+            &[
+                51380, 52480, 205374, 101624, 210337, 155351, 102331, 105531, 102798, 208398,
+                51397, 205382, 156377, 51520, 51928, 159015, 209027, 99190, 105735, 154103, 209688,
+                204014, 104056, 52727, 52787, 103886, 104917, 51178, 209337, 51814, 102745, 150722,
+            ],
+            &[
+                50, 50, 200, 100, 200, 150, 100, 100, 100, 200, 50, 200, 150, 50, 50, 150, 200,
+                100, 100, 150, 200, 200, 100, 50, 50, 100, 100, 50, 200, 50, 100, 150,
+            ],
+        ),
+        (
+            "spikes",
+            &[
+                24882, 99801, 74741, 49626, 99890, 25040, 101567, 50482, 25046, 25041, 24817,
+                25019, 101057, 74203, 101093, 24985, 48902, 49362, 98906, 98173, 99875, 25083,
+                24714, 25224, 75390, 48535, 73570, 24698, 73133, 24992, 74170, 75013,
+            ],
+            &[
+                24304, 99129, 73574, 49269, 293931, 25008, 99783, 49885, 24622, 24677, 24562,
+                24904, 99471, 73281, 99741, 24824, 48596, 49128, 292932, 97163, 100334, 24753,
+                24663, 24950, 74236, 48123, 72961, 24582, 217680, 24662, 73211, 73637,
+            ],
+            &[
+                50, 200, 150, 100, 200, 50, 200, 100, 50, 50, 50, 50, 200, 150, 200, 50, 100, 100,
+                200, 200, 200, 50, 50, 50, 150, 100, 150, 50, 150, 50, 150, 150,
+            ],
+        ),
+        (
+            "few",
+            &[199098, 100413, 148500, 49895, 201536, 150882],
+            &[203134, 102810, 152818, 51046, 203813, 154590],
+            &[200, 100, 150, 50, 200, 150],
+        ),
+        (
+            "drift",
+            &[
+                99878, 100099, 99867, 100048, 99878, 100071, 100153, 99990, 100257, 99922, 100010,
+                99966, 100027, 100110, 99821, 99858, 99853, 99922, 100330, 100084, 99971, 99991,
+                100010, 100056, 100187, 100069, 100095, 100100, 100126, 100014, 100077, 100164,
+                100055, 100028, 100018, 99899, 100059, 100098, 100078, 100018, 100177, 99931,
+                100024, 100087, 100243, 100018, 100013, 99989, 100112, 100006, 99924, 100111,
+                99821, 100024, 100155, 100000, 99984, 100081, 100100, 99959,
+            ],
+            &[
+                100415, 100548, 100361, 100658, 100249, 98571, 100752, 100462, 100728, 100308,
+                100524, 100459, 102527, 100631, 100391, 100341, 100353, 100444, 100825, 100452,
+                100537, 100528, 100410, 100475, 100637, 100569, 100535, 100587, 100689, 100436,
+                104124, 104126, 104106, 104049, 103958, 103859, 104210, 104203, 104148, 104095,
+                104145, 104127, 104122, 103974, 104421, 104093, 103924, 104026, 104183, 104141,
+                104003, 104082, 103730, 104076, 104054, 104065, 103747, 104312, 104009, 103981,
+            ],
+            &[
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+            ],
+        ),
+        (
+            "order",
+            &[
+                99606, 100564, 100173, 100076, 99912, 100286, 100382, 101005, 99786, 99993, 99832,
+                100919, 99438, 100199, 99784, 100141, 101215, 99723, 99887, 98636, 100504, 99967,
+                100428, 99149, 99830, 100546, 100544, 100342, 100088, 99925, 100399, 99956, 100524,
+                101373, 100457, 100066, 100111, 99236, 100307, 99487,
+            ],
+            &[
+                103918, 99986, 104254, 99360, 104053, 99284, 104693, 100158, 103584, 99451, 104025,
+                98628, 103542, 99316, 104116, 98881, 105451, 98677, 103850, 97412, 103914, 99439,
+                105224, 97984, 104146, 98205, 103809, 98597, 104713, 99021, 104488, 99715, 104392,
+                99783, 104337, 99415, 104448, 97502, 103817, 97768,
+            ],
+            &[
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+                100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+                100, 100, 100, 100, 100, 100, 100, 100,
+            ],
+        ),
+    ];
+
+    /// Pins the verdicts of statistics version 1, so refactorings of the statistics can't silently change them
+    ///
+    /// Expected values are computed by the release before the statistics were versioned.
+    #[test]
+    fn check_legacy_stats_golden() {
+        let expected = [
+            "equal: none => significant: false -0.362669% mean diff: -3.628490 outliers: 0",
+            "equal: iqr => significant: false -0.345728% mean diff: -3.462833 outliers: 2",
+            "regression: none => significant: true +3.058867% mean diff: 30.747969 outliers: 0",
+            "regression: iqr => significant: true +3.107950% mean diff: 31.267833 outliers: 2",
+            "spikes: none => significant: false +17.284236% mean diff: 86.042187 outliers: 0",
+            "spikes: iqr => significant: false +17.284236% mean diff: 86.042187 outliers: 0",
+            "few: none => significant: true +2.200632% mean diff: 22.010278 outliers: 0",
+            "few: iqr => significant: true +2.295299% mean diff: 22.972500 outliers: 2",
+            "drift: none => significant: true +2.257225% mean diff: 22.579833 outliers: 0",
+            "drift: iqr => significant: true +2.257225% mean diff: 22.579833 outliers: 0",
+            "order: none => significant: true +1.462247% mean diff: 14.640000 outliers: 0",
+            "order: iqr => significant: true +1.462247% mean diff: 14.640000 outliers: 0",
+        ];
+        let result = |name, baseline, candidate, iterations, settings: &MeasurementSettings| {
+            calculate_run_result_raw(
+                name,
+                baseline,
+                candidate,
+                iterations,
+                [1, 1],
+                SampleOrder::CandidateFirst,
+                settings,
+            )
+            .unwrap()
+        };
+        let mut actual = vec![];
+        for (name, baseline, candidate, iterations) in GOLDEN_FIXTURES {
+            for (mode, outlier_detection) in [
+                ("none", OutlierDetection::None),
+                ("iqr", OutlierDetection::Iqr),
+            ] {
+                let settings = MeasurementSettings {
+                    outlier_detection,
+                    stats_version: stats::LEGACY_STATS_VERSION,
+                    ..Default::default()
+                };
+                let result = result(name, baseline, candidate, iterations, &settings);
+                let estimate = &result.diff_estimate;
+                actual.push(format!(
+                    "{}: {} => significant: {} {:+.6}% mean diff: {:.6} outliers: {}",
+                    name,
+                    mode,
+                    estimate.significant,
+                    estimate.pct,
+                    result.diff.mean,
+                    result.outliers
+                ));
+                assert_eq!(estimate.significant, estimate.verdict == Verdict::Different);
+                assert_eq!(result.stats_version, stats::LEGACY_STATS_VERSION);
+            }
+        }
+        assert_eq!(actual, expected);
+
+        // Drift and the order of the calls change the verdicts of the current version only
+        let current = MeasurementSettings {
+            outlier_detection: OutlierDetection::Iqr,
+            ..Default::default()
+        };
+        let [_, _, _, _, drift, order] = GOLDEN_FIXTURES;
+        let drift = result(drift.0, drift.1, drift.2, drift.3, &current);
+        assert!(drift.outliers > 0);
+        let order = result(order.0, order.1, order.2, order.3, &current);
+        assert!(order.diff_estimate.order_biased);
+        assert!(!order.diff_estimate.significant);
+    }
 }
@@ -1,5 +1,9 @@
 use goblin::{
-    elf::{Dyn, Elf},
+    elf::{
+        header::{machine_to_str, Header, ET_DYN, ET_EXEC},
+        sym::STT_FUNC,
+        Dyn, Elf,
+    },
     elf64::{
         dynamic::{DF_1_PIE, DT_FLAGS_1},
         program_header::PT_DYNAMIC,
@@ -8,8 +12,14 @@ use goblin::{
 };
 use scroll::{Pread, Pwrite};
 use std::{
-    fs, mem,
+    collections::HashMap,
+    ffi::{c_void, CStr, OsStr},
+    fs::{self, File},
+    io::Read,
+    mem,
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
+    process,
 };
 use thiserror::Error;
 
@@ -34,6 +44,43 @@ pub enum Error {
     IOError(#[from] std::io::Error),
 }
 
+/// Checks that the executable can be loaded as a library by the harness
+///
+/// `dlopen()` fails with a generic message on the executables built for a different architecture or not built as
+/// position independent (`ET_EXEC`), so those are rejected with the cause named. Files which are not ELF at all are
+/// left for `dlopen()` to report.
+pub fn check_loadable(path: impl AsRef<Path>) -> Result<(), crate::Error> {
+    let path = path.as_ref();
+    let Some(header) = read_header(path)? else {
+        return Ok(());
+    };
+    if let Some(current) = read_header(std::env::current_exe()?)? {
+        if header.e_machine != current.e_machine {
+            return Err(crate::Error::ArchitectureMismatch(
+                path.to_path_buf(),
+                machine_to_str(header.e_machine),
+                machine_to_str(current.e_machine),
+            ));
+        }
+    }
+    if header.e_type == ET_EXEC {
+        return Err(crate::Error::PositionDependentExecutable(
+            path.to_path_buf(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the ELF header of a file, `None` if the file is not an ELF file
+fn read_header(path: impl AsRef<Path>) -> Result<Option<Header>, std::io::Error> {
+    let mut bytes = vec![];
+    let header_size = mem::size_of::<goblin::elf64::header::Header>() as u64;
+    File::open(path)?
+        .take(header_size)
+        .read_to_end(&mut bytes)?;
+    Ok(Elf::parse_header(&bytes).ok())
+}
+
 /// Patches executable file for new version of glibc dynamic loader
 ///
 /// After glibc 2.29 on linux `dlopen` is explicitly denying loading
@@ -102,8 +149,192 @@ pub fn patch_pie_binary_if_needed(
         .pwrite(dyn_item, dyn_offset)
         .map_err(Error::UnableToSerializeElf)?;
 
+    // Copy is replaced rather than overwritten, because another process (eg. the parent of an isolated test) may
+    // still have the previous one loaded
     let path = path.as_ref().with_extension("patched");
-    fs::write(&path, bytes)?;
+    let written = path.with_extension(format!("patched.{}", process::id()));
+    fs::write(&written, bytes)?;
+    fs::rename(&written, &path)?;
 
     Ok(Some(path))
 }
+
+/// Module and function a code address belongs to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedAddress {
+    /// Path of the executable or the shared library the address is loaded from
+    pub module: PathBuf,
+
+    /// Address the module is loaded at
+    pub base: usize,
+
+    /// Address of the function containing the given one
+    pub start: usize,
+
+    /// Size of the function in bytes (`None` if the symbol table of the module is not available)
+    pub size: Option<usize>,
+
+    /// Mangled name of the function
+    pub symbol: Option<String>,
+}
+
+/// Resolves code addresses to the modules loaded in the current process
+///
+/// Function boundaries are taken from the ELF symbol table of the module, which is read once.
+#[derive(Default)]
+pub struct AddressResolver {
+    modules: HashMap<PathBuf, Option<Vec<u8>>>,
+}
+
+impl AddressResolver {
+    /// Module and function an address belongs to, `None` if it is not inside of a loaded module
+    pub fn resolve(&mut self, addr: usize) -> Option<ResolvedAddress> {
+        let mut info = mem::MaybeUninit::<libc::Dl_info>::uninit();
+        let info = unsafe {
+            if libc::dladdr(addr as *const c_void, info.as_mut_ptr()) == 0 {
+                return None;
+            }
+            info.assume_init()
+        };
+        let module = if info.dli_fname.is_null() {
+            PathBuf::new()
+        } else {
+            let name = unsafe { CStr::from_ptr(info.dli_fname) };
+            PathBuf::from(OsStr::from_bytes(name.to_bytes()))
+        };
+        // Main executable might be reported by the name it was started with
+        let module = match module.as_os_str().is_empty() {
+            true => std::env::current_exe().ok()?,
+            false => module,
+        };
+        let base = info.dli_fbase as usize;
+        let mut resolved = ResolvedAddress {
+            module,
+            base,
+            start: addr,
+            size: None,
+            symbol: None,
+        };
+        if !info.dli_sname.is_null() {
+            let name = unsafe { CStr::from_ptr(info.dli_sname) };
+            resolved.symbol = Some(name.to_string_lossy().into_owned());
+            resolved.start = info.dli_saddr as usize;
+        }
+
+        let bytes = self
+            .modules
+            .entry(resolved.module.clone())
+            .or_insert_with_key(|path| fs::read(path).ok());
+        if let Some(elf) = bytes.as_deref().and_then(|b| Elf::parse(b).ok()) {
+            // Symbols of position independent modules are relative to the load address
+            let bias = if elf.header.e_type == ET_DYN { base } else { 0 };
+            let offset = addr.wrapping_sub(bias) as u64;
+            let symbols = [(&elf.syms, &elf.strtab), (&elf.dynsyms, &elf.dynstrtab)];
+            let found = symbols.into_iter().find_map(|(syms, strtab)| {
+                syms.iter()
+                    .find(|s| {
+                        s.st_type() == STT_FUNC
+                            && s.st_value <= offset
+                            && offset < s.st_value + s.st_size
+                    })
+                    .map(|s| (s, strtab.get_at(s.st_name)))
+            });
+            if let Some((sym, name)) = found {
+                resolved.start = (sym.st_value as usize).wrapping_add(bias);
+                resolved.size = Some(sym.st_size as usize);
+                resolved.symbol = name.map(str::to_string).or(resolved.symbol);
+            }
+        }
+        Some(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn resolved_function() -> u64 {
+        std::hint::black_box(42)
+    }
+
+    #[test]
+    fn check_address_is_resolved_to_function() {
+        let addr = resolved_function as fn() -> u64 as usize;
+        let mut resolver = AddressResolver::default();
+        let resolved = resolver.resolve(addr).unwrap();
+
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(resolved.module.file_name(), exe.file_name());
+        assert_eq!(resolved.start, addr);
+        assert!(resolved.size.unwrap() > 0);
+        assert!(resolved.symbol.unwrap().contains("resolved_function"));
+
+        // Address inside of the function is resolved to its start
+        assert_eq!(resolver.resolve(addr + 1).unwrap().start, addr);
+    }
+
+    #[test]
+    fn check_unloadable_executables_are_diagnosed() {
+        use crate::Error;
+        use goblin::elf::header::{EM_AARCH64, EM_RISCV};
+
+        let exe = std::env::current_exe().unwrap();
+        check_loadable(&exe).unwrap();
+
+        // Fixtures are copies of the ELF header of the test executable with a single field changed
+        let dir = std::env::temp_dir().join(format!("tango-loadable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let header = fs::read(&exe).unwrap()[..64].to_vec();
+        let fixture = |name: &str, offset: usize, value: u16| {
+            let mut bytes = header.clone();
+            bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+            let path = dir.join(name);
+            fs::write(&path, bytes).unwrap();
+            path
+        };
+        let foreign = match Elf::parse_header(&header).unwrap().e_machine {
+            EM_AARCH64 => EM_RISCV,
+            _ => EM_AARCH64,
+        };
+        // e_machine and e_type fields
+        let foreign_arch = fixture("foreign-arch", 18, foreign);
+        let position_dependent = fixture("position-dependent", 16, ET_EXEC);
+        let not_elf = dir.join("not-elf");
+        fs::write(&not_elf, "#!/bin/sh").unwrap();
+
+        let foreign_arch = check_loadable(&foreign_arch);
+        let position_dependent = check_loadable(&position_dependent);
+        let not_elf = check_loadable(&not_elf);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let Err(Error::ArchitectureMismatch(_, found, expected)) = foreign_arch else {
+            panic!("{:?}", foreign_arch)
+        };
+        assert_eq!(found, machine_to_str(foreign));
+        assert_ne!(found, expected);
+        assert!(
+            matches!(
+                position_dependent,
+                Err(Error::PositionDependentExecutable(_))
+            ),
+            "{:?}",
+            position_dependent
+        );
+        assert!(not_elf.is_ok(), "{:?}", not_elf);
+    }
+
+    #[test]
+    fn check_library_without_entry_points_is_diagnosed() {
+        use crate::{dylib::Spi, Error};
+
+        let library = unsafe { libloading::Library::new("libc.so.6") }.unwrap();
+        let error = Spi::for_library(&library, 256).err().unwrap();
+        assert!(
+            matches!(error, Error::MissingEntryPoint("tango_init", _)),
+            "{:?}",
+            error
+        );
+        assert!(error.to_string().contains("[profile.bench]"));
+    }
+}
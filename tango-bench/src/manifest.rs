@@ -0,0 +1,217 @@
+//! Run manifests allowing to reproduce a benchmarking run
+//!
+//! Manifest records everything the harness randomizes or estimates during a run: the master seed, seeds derived
+//! for each test, estimated number of iterations and the number of samples taken. Replaying the manifest
+//! (`--replay-manifest`) executes exactly the same schedule of samples.
+
+use crate::{
+    noise::{LoadSpec, NoiseFloor},
+    shard::Shard,
+    stats::BatchCurve,
+    timer_resolution, Error, MeasurementSettings, SampleOrder, TimerResolution,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the manifest format
+const VERSION: u32 = 1;
+
+/// Schedule of a benchmarking run written by `--manifest` and replayed by `--replay-manifest`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// Version of the manifest format
+    pub version: u32,
+
+    /// Seed all the test seeds are derived from (see [`crate::RngFactory`])
+    pub master_seed: u64,
+
+    /// Effective measurement settings of the run
+    pub settings: MeasurementSettings,
+
+    /// Schedules of the tests in the order they were run
+    pub tests: Vec<TestSchedule>,
+
+    /// Environment the run was performed in
+    pub environment: Environment,
+
+    /// Shard of the suite the run was limited to (`--shard`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
+}
+
+/// Schedule of a single test
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestSchedule {
+    /// Name of the test
+    pub name: String,
+
+    /// Seed used for generators and sampler of this test
+    pub seed: u64,
+
+    /// Estimated number of iterations per sample the sampler was built with
+    pub estimate: usize,
+
+    /// Number of samples taken (including discarded ones)
+    pub samples: usize,
+
+    /// Number of samples each haystack was used for (0 in manifests recorded before it was planned per test)
+    #[serde(default)]
+    pub samples_per_haystack: usize,
+
+    /// Order of the first sample, the following ones alternate (candidate first in manifests recorded before it
+    /// was chosen per test)
+    #[serde(default)]
+    pub first: SampleOrder,
+
+    /// Total number of iterations of each function across all samples
+    pub total_iterations: u64,
+
+    /// Time of a single call of the slower function if it exceeded the share of the time limit allowed by
+    /// [`MeasurementSettings::slow_call_fraction`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slow_call: Option<Duration>,
+
+    /// Time limit the test was extended to because of a slow call (see [`crate::SlowTestPolicy::Extend`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_duration: Option<Duration>,
+
+    /// Per-iteration values of baseline and candidate at different batch sizes if requested (see
+    /// [`MeasurementSettings::batch_curve`]). Not used for replaying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_curve: Option<[BatchCurve; 2]>,
+
+    /// Time it took to run the test (excluding the cooldown). Not used for replaying, but to balance the shards of
+    /// the following runs (`--shard-by-cost`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+}
+
+/// Environment the run was performed in
+///
+/// Not used for replaying, but helps to explain the discrepancies between the original and replayed runs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Environment {
+    /// Version of tango the harness is built with
+    pub tango_version: String,
+
+    /// Operating system (see [`std::env::consts::OS`])
+    pub os: String,
+
+    /// CPU architecture (see [`std::env::consts::ARCH`])
+    pub arch: String,
+
+    /// Command line of the harness
+    pub args: Vec<String>,
+
+    /// Unix timestamp of the run (in seconds)
+    pub timestamp: u64,
+
+    /// Background load deliberately injected during the run (`--inject-load`). Results of such runs are noisy on
+    /// purpose and should not be compared with clean ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub injected_load: Option<LoadSpec>,
+
+    /// Noise floor measured before the run (`--max-noise`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_floor: Option<NoiseFloor>,
+
+    /// Resolution of the timer measured at the start of the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timer: Option<TimerResolution>,
+}
+
+impl Environment {
+    pub(crate) fn current() -> Self {
+        Self {
+            tango_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            args: env::args().collect(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            injected_load: None,
+            noise_floor: None,
+            timer: Some(timer_resolution()),
+        }
+    }
+}
+
+impl RunManifest {
+    /// Manifest of a run without tests in the current environment
+    pub fn new(master_seed: u64, settings: MeasurementSettings) -> Self {
+        Self {
+            version: VERSION,
+            master_seed,
+            settings,
+            tests: vec![],
+            environment: Environment::current(),
+            shard: None,
+        }
+    }
+
+    /// Schedule of a test with a given name
+    pub fn test(&self, name: &str) -> Option<&TestSchedule> {
+        self.tests.iter().find(|t| t.name == name)
+    }
+
+    /// Reads the manifest written by [`Self::write()`]
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let manifest: Self = serde_json::from_reader(reader).map_err(Error::InvalidManifest)?;
+        // Fields added later are optional, so only the files of newer versions can't be read
+        if manifest.version > VERSION {
+            return Err(Error::UnsupportedManifestVersion(manifest.version));
+        }
+        Ok(manifest)
+    }
+
+    /// Writes the manifest as pretty-printed JSON
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self).map_err(Error::InvalidManifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    #[test]
+    fn check_manifest_roundtrip() {
+        let mut manifest = RunManifest::new(42, MeasurementSettings::default());
+        manifest.tests.push(TestSchedule {
+            name: "sort/u64/100".into(),
+            seed: 1,
+            estimate: 100,
+            samples: 1000,
+            samples_per_haystack: 4,
+            first: SampleOrder::BaselineFirst,
+            total_iterations: 50_000,
+            slow_call: Some(Duration::from_millis(60)),
+            extended_duration: Some(Duration::from_millis(600)),
+            batch_curve: BatchCurve::fit(vec![(1, 20.), (50, 10.)]).map(|c| [c.clone(), c]),
+            duration: Some(Duration::from_millis(120)),
+        });
+        manifest.shard = Shard::new(2, 4);
+
+        let path = env::temp_dir().join(format!("tango-manifest-{}.json", process::id()));
+        manifest.write(&path).unwrap();
+        let read = RunManifest::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.master_seed, 42);
+        assert_eq!(read.tests, manifest.tests);
+        assert_eq!(read.shard, manifest.shard);
+        assert_eq!(read.test("sort/u64/100"), manifest.tests.first());
+        assert_eq!(read.test("unknown"), None);
+    }
+}
@@ -0,0 +1,625 @@
+//! Controlled background load for checking how robust the measurements are to noise
+//!
+//! [`BackgroundLoad`] keeps a given number of threads busy for a fixed share of each period. The load is
+//! deterministic: every thread busy-waits at the beginning of each period and sleeps for the rest of it, and periods
+//! are aligned to the start of the load, so oversleeping doesn't accumulate.
+
+use crate::{benchmark_fn, MeasureTarget};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    hint::{black_box, spin_loop},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Default period of the duty cycle
+pub const DEFAULT_PERIOD: Duration = Duration::from_millis(10);
+
+/// When the load is applied
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoadScope {
+    /// Load is started before the first test and stopped after the last one
+    #[default]
+    Session,
+
+    /// Load is started and stopped around each test
+    Test,
+}
+
+/// Parameters of a [`BackgroundLoad`]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoadSpec {
+    /// Number of threads generating the load
+    pub threads: usize,
+
+    /// Share of each period a thread is busy (0-1)
+    pub duty: f64,
+
+    /// Period the busy share is taken from
+    pub period: Duration,
+
+    /// When the load is applied
+    pub scope: LoadScope,
+}
+
+impl LoadSpec {
+    /// Load of a given number of threads busy for a given share of the default period for the whole session
+    pub fn new(threads: usize, duty: f64) -> Self {
+        Self {
+            threads,
+            duty: duty.clamp(0., 1.),
+            period: DEFAULT_PERIOD,
+            scope: LoadScope::default(),
+        }
+    }
+
+    fn busy_time(&self) -> Duration {
+        self.period.mul_f64(self.duty)
+    }
+}
+
+impl fmt::Display for LoadSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} thread(s), {:.0}% of {:?}",
+            self.threads,
+            self.duty * 100.,
+            self.period
+        )?;
+        if self.scope == LoadScope::Test {
+            write!(f, " per test")?;
+        }
+        Ok(())
+    }
+}
+
+/// Threads busy-waiting for a share of each period until stopped
+///
+/// Threads are stopped and joined when the load is dropped.
+pub struct BackgroundLoad {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<Duration>>,
+    started: Instant,
+}
+
+impl BackgroundLoad {
+    /// Starts the threads generating the load
+    pub fn start(spec: LoadSpec) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let started = Instant::now();
+        let threads = (0..spec.threads)
+            .map(|_| {
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || generate_load(spec, started, &stop))
+            })
+            .collect();
+        Self {
+            stop,
+            threads,
+            started,
+        }
+    }
+
+    /// Stops the load and returns the share of the time threads were busy on average
+    pub fn stop(mut self) -> f64 {
+        let busy = self.join();
+        let elapsed = self.started.elapsed();
+        if busy.is_empty() || elapsed.is_zero() {
+            return 0.;
+        }
+        let total = busy.iter().sum::<Duration>();
+        total.as_secs_f64() / busy.len() as f64 / elapsed.as_secs_f64()
+    }
+
+    fn join(&mut self) -> Vec<Duration> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.threads
+            .drain(..)
+            .filter_map(|t| t.join().ok())
+            .collect()
+    }
+}
+
+impl Drop for BackgroundLoad {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Busy-waits for a share of each period until stopped and returns the total busy time
+fn generate_load(spec: LoadSpec, started: Instant, stop: &AtomicBool) -> Duration {
+    let busy_time = spec.busy_time();
+    let mut busy = Duration::ZERO;
+    let mut period_start = started;
+    while !stop.load(Ordering::Relaxed) {
+        let busy_until = period_start + busy_time;
+        let mut now = Instant::now();
+        let spin_start = now;
+        while now < busy_until {
+            std::hint::spin_loop();
+            now = Instant::now();
+        }
+        busy += now.saturating_duration_since(spin_start);
+
+        // Periods missed because the thread wasn't scheduled are skipped, not caught up with
+        period_start += spec.period;
+        if period_start < now {
+            let missed = (now - period_start).as_nanos() / spec.period.as_nanos().max(1) + 1;
+            period_start += spec.period * missed as u32;
+        }
+        thread::sleep(period_start.saturating_duration_since(Instant::now()));
+    }
+    busy
+}
+
+/// Name of the reference benchmark the noise floor is measured on
+pub(crate) const REFERENCE_NAME: &str = "tango/noise-reference";
+
+/// Number of elements summed by the reference benchmark
+const REFERENCE_LEN: u64 = 1000;
+
+/// Percentile of the absolute differences of the reference runs taken as the noise floor
+const NOISE_PERCENTILE: f64 = 0.95;
+
+/// Reference benchmark compared against itself to measure the noise floor
+///
+/// It doesn't depend on the code under test, so the differences it shows are due to the environment only.
+pub(crate) fn reference_benchmark() -> Box<dyn MeasureTarget> {
+    benchmark_fn(REFERENCE_NAME, || {
+        (0..black_box(REFERENCE_LEN)).fold(0u64, |sum, i| black_box(sum.wrapping_add(i)))
+    })
+}
+
+/// Name of the empty function sampled between the samples of a test (see
+/// [`crate::MeasurementSettings::null_interval`])
+pub(crate) const NULL_NAME: &str = "tango/null";
+
+/// Empty function measuring the overhead of the sampling loop and the timer
+///
+/// It returns a value, so its output is retained and passed through `black_box()` the same way the output of any
+/// other benchmark is.
+pub(crate) fn null_benchmark() -> Box<dyn MeasureTarget> {
+    benchmark_fn(NULL_NAME, || black_box(0u64))
+}
+
+/// Noise of the environment measured before the suite (recorded in [`crate::manifest::Environment`])
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoiseFloor {
+    /// 95th percentile of the absolute differences between identical functions (in percent)
+    pub pct: f64,
+
+    /// Number of times the identical pair was compared
+    pub runs: usize,
+
+    /// Maximum acceptable noise floor (in percent)
+    pub max: f64,
+}
+
+impl NoiseFloor {
+    /// Noise floor of the differences (in percent) measured by comparing identical functions
+    ///
+    /// Returns `None` if there are no differences.
+    pub(crate) fn from_diffs(diffs: &[f64], max: f64) -> Option<Self> {
+        let mut diffs = diffs.iter().map(|d| d.abs()).collect::<Vec<_>>();
+        diffs.sort_by(f64::total_cmp);
+        let idx = (diffs.len() as f64 * NOISE_PERCENTILE).ceil() as usize;
+        let pct = *diffs.get(idx.max(1) - 1)?;
+        Some(Self {
+            pct,
+            runs: diffs.len(),
+            max,
+        })
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.pct > self.max
+    }
+}
+
+impl fmt::Display for NoiseFloor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "±{:.2}% over {} reference run(s), max ±{:.2}%",
+            self.pct, self.runs, self.max
+        )
+    }
+}
+
+/// What to do when the noise floor exceeds the maximum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseAction {
+    /// Don't run the suite and exit with a distinct exit code
+    #[default]
+    Abort,
+
+    /// Run the suite, but report the verdicts as informational (they never fail the run)
+    Demote,
+}
+
+/// Outcome of the [`NoiseGate`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GateOutcome {
+    /// Noise is acceptable, verdicts are trusted
+    Trusted,
+
+    /// Suite should not be run
+    Abort,
+
+    /// Verdicts are informational only
+    Demoted,
+}
+
+impl GateOutcome {
+    /// Verdicts may fail the run (eg. `--fail-threshold`)
+    pub(crate) fn enforces_verdicts(self) -> bool {
+        self == GateOutcome::Trusted
+    }
+}
+
+/// Maximum acceptable noise floor and the action taken when it is exceeded
+///
+/// The gate refuses to trust the comparisons made in a too noisy environment. The noise floor is measured on an
+/// identical pair of reference functions before the suite (see [`NoiseFloor`]).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct NoiseGate {
+    /// Maximum acceptable noise floor (in percent)
+    pub(crate) max: f64,
+
+    /// Number of times the reference pair is compared
+    pub(crate) runs: usize,
+
+    pub(crate) action: NoiseAction,
+}
+
+impl NoiseGate {
+    pub(crate) fn check(&self, floor: &NoiseFloor) -> GateOutcome {
+        match (floor.exceeded(), self.action) {
+            (false, _) => GateOutcome::Trusted,
+            (true, NoiseAction::Abort) => GateOutcome::Abort,
+            (true, NoiseAction::Demote) => GateOutcome::Demoted,
+        }
+    }
+}
+
+/// Names of the sentinel benchmarks along with the duration of a call in multiples of [`SENTINEL_CALL_TIME`]
+///
+/// Sentinels are identical pairs of a calibrated busy-wait compared at the start, in the middle and at the end of the
+/// suite (see [`SentinelSchedule`]). Two pairs with different call durations are compared, so both the timer overhead
+/// dominated and the CPU bound tests are covered.
+pub(crate) const SENTINELS: [(&str, u32); 2] =
+    [("tango/sentinel-short", 1), ("tango/sentinel-long", 50)];
+
+/// Duration of a call of the short sentinel
+pub(crate) const SENTINEL_CALL_TIME: Duration = Duration::from_micros(1);
+
+/// Minimal time the busy-wait is run for while calibrating
+const CALIBRATION_TIME: Duration = Duration::from_millis(5);
+
+/// Busy-waits for a given number of spins
+fn spin(spins: u64) -> u64 {
+    let mut n = 0;
+    for _ in 0..black_box(spins) {
+        spin_loop();
+        n = black_box(n + 1);
+    }
+    n
+}
+
+/// Number of spins of the busy-wait taking a given time on this machine
+///
+/// Calibrated once per run, so both functions of a sentinel pair are exactly the same.
+pub(crate) fn calibrate_spins(target: Duration) -> u64 {
+    calibrate_with(target, |spins| {
+        let started = Instant::now();
+        black_box(spin(spins));
+        started.elapsed()
+    })
+}
+
+/// Number of spins taking a given time, `run` busy-waits for a number of spins and returns the time it took
+fn calibrate_with(target: Duration, mut run: impl FnMut(u64) -> Duration) -> u64 {
+    let mut spins = 1_000u64;
+    loop {
+        let elapsed = run(spins);
+        if elapsed >= CALIBRATION_TIME || spins > u64::MAX / 4 {
+            let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+            return ((spins as f64 * scale).round() as u64).max(1);
+        }
+        spins *= 2;
+    }
+}
+
+/// Sentinel benchmarks busy-waiting for a multiple of `spins` (see [`SENTINELS`])
+pub(crate) fn sentinel_benchmarks(spins: u64) -> Vec<Box<dyn MeasureTarget>> {
+    SENTINELS
+        .iter()
+        .map(|&(name, multiple)| {
+            let spins = spins * multiple as u64;
+            benchmark_fn(name, move || spin(spins))
+        })
+        .collect()
+}
+
+/// Point of the suite execution order sentinels are compared at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SentinelSlot {
+    /// Before the first test
+    Start,
+
+    /// Before the middle test
+    Middle,
+
+    /// After the last test
+    End,
+}
+
+impl fmt::Display for SentinelSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SentinelSlot::Start => "start",
+            SentinelSlot::Middle => "middle",
+            SentinelSlot::End => "end",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Positions of the sentinel slots in the execution order of the suite
+///
+/// A slot at position `n` is due before the `n`-th test (counting from 0) is run, the end slot is at the position
+/// equal to the number of tests. With less than 2 tests some of the slots fall on the same position and are run
+/// back to back.
+#[derive(Clone, Debug)]
+pub(crate) struct SentinelSchedule {
+    slots: [(SentinelSlot, usize); 3],
+
+    /// Index of the first slot which is not run yet
+    next: usize,
+}
+
+impl SentinelSchedule {
+    pub(crate) fn new(tests: usize) -> Self {
+        Self {
+            slots: [
+                (SentinelSlot::Start, 0),
+                (SentinelSlot::Middle, tests / 2),
+                (SentinelSlot::End, tests),
+            ],
+            next: 0,
+        }
+    }
+
+    /// Slots due before the test at a given position which were not returned yet
+    ///
+    /// Slots left behind by positions which were never reached (eg. tests skipped before being counted) are
+    /// returned as well, so each slot is run exactly once.
+    pub(crate) fn due(&mut self, position: usize) -> Vec<SentinelSlot> {
+        let due = self.slots[self.next..]
+            .iter()
+            .take_while(|(_, p)| *p <= position)
+            .map(|(slot, _)| *slot)
+            .collect::<Vec<_>>();
+        self.next += due.len();
+        due
+    }
+
+    /// Slots not returned yet
+    pub(crate) fn rest(&mut self) -> Vec<SentinelSlot> {
+        self.due(usize::MAX)
+    }
+}
+
+/// Difference measured on a sentinel pair
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SentinelRun {
+    pub(crate) name: &'static str,
+    pub(crate) slot: SentinelSlot,
+
+    /// Difference between identical functions (in percent)
+    pub(crate) pct: f64,
+
+    /// Difference is statistically significant, which means the environment changed during the comparison
+    pub(crate) significant: bool,
+}
+
+/// Empirical noise floor of a run measured by the sentinels
+///
+/// Unlike [`NoiseFloor`], it covers the whole run, not only the moment before the suite.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SentinelSummary {
+    pub(crate) runs: Vec<SentinelRun>,
+}
+
+impl SentinelSummary {
+    /// Largest absolute difference of the sentinels (in percent)
+    pub(crate) fn floor(&self) -> f64 {
+        self.runs.iter().map(|r| r.pct.abs()).fold(0., f64::max)
+    }
+
+    /// Sentinel runs showing a significant difference
+    pub(crate) fn flagged(&self) -> impl Iterator<Item = &SentinelRun> {
+        self.runs.iter().filter(|r| r.significant)
+    }
+
+    /// The whole session is suspicious, because some of the sentinels show a significant difference
+    pub(crate) fn is_flagged(&self) -> bool {
+        self.flagged().next().is_some()
+    }
+}
+
+impl fmt::Display for SentinelSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "±{:.2}% over {} sentinel run(s)",
+            self.floor(),
+            self.runs.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_duty_cycle_calibration() {
+        for duty in [0.2, 0.5] {
+            let load = BackgroundLoad::start(LoadSpec::new(1, duty));
+            thread::sleep(Duration::from_millis(300));
+            let measured = load.stop();
+            assert!(
+                (measured - duty).abs() < 0.15,
+                "requested: {}, measured: {}",
+                duty,
+                measured
+            );
+        }
+    }
+
+    #[test]
+    fn check_load_is_shut_down() {
+        let spec = LoadSpec {
+            period: Duration::from_millis(50),
+            ..LoadSpec::new(3, 0.1)
+        };
+        let load = BackgroundLoad::start(spec);
+        let stop = Arc::clone(&load.stop);
+        thread::sleep(Duration::from_millis(20));
+
+        let started = Instant::now();
+        drop(load);
+        // Threads finish at most one period after being stopped and release their handles
+        assert!(started.elapsed() < spec.period * 3);
+        assert_eq!(Arc::strong_count(&stop), 1);
+
+        let idle = BackgroundLoad::start(LoadSpec::new(0, 0.5));
+        assert_eq!(idle.stop(), 0.);
+    }
+
+    #[test]
+    fn check_noise_floor() {
+        // 95th percentile of 20 runs is the 19th smallest absolute difference
+        let diffs = (1..=20).map(|i| i as f64 * if i % 2 == 0 { 0.1 } else { -0.1 });
+        let floor = NoiseFloor::from_diffs(&diffs.collect::<Vec<_>>(), 1.5).unwrap();
+        assert!((floor.pct - 1.9).abs() < 1e-9);
+        assert_eq!(floor.runs, 20);
+        assert!(floor.exceeded());
+
+        // Single run is its own percentile
+        let floor = NoiseFloor::from_diffs(&[-0.5], 1.).unwrap();
+        assert_eq!(floor.pct, 0.5);
+        assert!(!floor.exceeded());
+        assert_eq!(NoiseFloor::from_diffs(&[], 1.), None);
+    }
+
+    #[test]
+    fn check_noise_gate() {
+        let floor = |pct| NoiseFloor {
+            pct,
+            runs: 5,
+            max: 1.,
+        };
+        let gate = |action| NoiseGate {
+            max: 1.,
+            runs: 5,
+            action,
+        };
+
+        for action in [NoiseAction::Abort, NoiseAction::Demote] {
+            let outcome = gate(action).check(&floor(0.5));
+            assert_eq!(outcome, GateOutcome::Trusted);
+            assert!(outcome.enforces_verdicts());
+            // Noise floor equal to the maximum is still acceptable
+            assert_eq!(gate(action).check(&floor(1.)), GateOutcome::Trusted);
+        }
+        assert_eq!(
+            gate(NoiseAction::Abort).check(&floor(2.)),
+            GateOutcome::Abort
+        );
+        let demoted = gate(NoiseAction::Demote).check(&floor(2.));
+        assert_eq!(demoted, GateOutcome::Demoted);
+        assert!(!demoted.enforces_verdicts());
+    }
+
+    #[test]
+    fn check_sentinel_calibration() {
+        // Busy-wait running 2ns per spin
+        let mut runs = vec![];
+        let spins = calibrate_with(Duration::from_micros(200), |spins| {
+            runs.push(spins);
+            Duration::from_nanos(spins * 2)
+        });
+        assert_eq!(spins, 100_000);
+        // Doubled until the run takes at least CALIBRATION_TIME
+        assert_eq!(runs.first(), Some(&1_000));
+        assert_eq!(runs.last(), Some(&4_096_000));
+
+        // Immeasurably fast busy-wait doesn't overflow
+        assert!(calibrate_with(Duration::from_micros(200), |_| Duration::ZERO) > 0);
+        assert!(calibrate_spins(Duration::from_micros(200)) > 0);
+
+        let benchmarks = sentinel_benchmarks(spins);
+        let names = benchmarks.iter().map(|b| b.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["tango/sentinel-short", "tango/sentinel-long"]);
+    }
+
+    #[test]
+    fn check_sentinel_schedule() {
+        use SentinelSlot::*;
+
+        let mut schedule = SentinelSchedule::new(5);
+        assert_eq!(schedule.due(0), [Start]);
+        assert_eq!(schedule.due(1), []);
+        assert_eq!(schedule.due(2), [Middle]);
+        // Slots are returned once
+        assert_eq!(schedule.due(2), []);
+        assert_eq!(schedule.due(4), []);
+        assert_eq!(schedule.due(5), [End]);
+        assert_eq!(schedule.rest(), []);
+
+        // Single test is surrounded by the sentinels
+        let mut schedule = SentinelSchedule::new(1);
+        assert_eq!(schedule.due(0), [Start, Middle]);
+        assert_eq!(schedule.due(1), [End]);
+
+        // Slots of the positions skipped over are not lost
+        let mut schedule = SentinelSchedule::new(10);
+        assert_eq!(schedule.due(7), [Start, Middle]);
+        assert_eq!(schedule.rest(), [End]);
+    }
+
+    #[test]
+    fn check_sentinel_summary() {
+        let run = |slot, pct, significant| SentinelRun {
+            name: SENTINELS[0].0,
+            slot,
+            pct,
+            significant,
+        };
+        let mut summary = SentinelSummary {
+            runs: vec![
+                run(SentinelSlot::Start, 0.3, false),
+                run(SentinelSlot::Middle, -0.8, false),
+                run(SentinelSlot::End, 0.1, false),
+            ],
+        };
+        assert_eq!(summary.floor(), 0.8);
+        assert!(!summary.is_flagged());
+        assert_eq!(summary.to_string(), "±0.80% over 3 sentinel run(s)");
+
+        summary.runs.push(run(SentinelSlot::End, 2.5, true));
+        assert!(summary.is_flagged());
+        let flagged = summary.flagged().map(|r| r.pct).collect::<Vec<_>>();
+        assert_eq!(flagged, [2.5]);
+
+        assert_eq!(SentinelSummary::default().floor(), 0.);
+    }
+}
@@ -0,0 +1,508 @@
+//! Bundled implementations of [`SampleObserver`]
+
+#[cfg(all(target_os = "linux", feature = "rapl"))]
+pub use rapl::RaplObserver;
+#[cfg(any(unix, windows))]
+pub use rusage::RusageObserver;
+
+#[cfg(any(unix, windows))]
+mod rusage {
+    use crate::{ObservedValue, SampleObserver, Side, Unit};
+
+    /// Reports resource usage of the thread running the samples
+    ///
+    /// Reports user and system CPU time spent during the sample. On unix platforms the numbers of minor and major
+    /// page faults and maximum resident set size of the process (shared by baseline and candidate) are reported as
+    /// well. CPU time has a granularity of a scheduler tick, so it is only meaningful for long samples or as a mean.
+    /// Values don't affect the verdict of a test.
+    #[derive(Default)]
+    pub struct RusageObserver {
+        before: Option<Usage>,
+        values: Vec<ObservedValue>,
+    }
+
+    #[derive(Clone, Copy)]
+    struct Usage {
+        user_ns: f64,
+        sys_ns: f64,
+
+        /// Page faults and max RSS (not available on Windows)
+        memory: Option<MemoryUsage>,
+    }
+
+    #[derive(Clone, Copy)]
+    struct MemoryUsage {
+        minor_faults: f64,
+        major_faults: f64,
+        max_rss: f64,
+    }
+
+    impl RusageObserver {
+        /// Observer without a sample in progress
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl SampleObserver for RusageObserver {
+        fn before_sample(&mut self, _test: &str, _side: Side, _iterations: usize) {
+            self.before = usage();
+        }
+
+        fn after_sample(&mut self, _test: &str, _side: Side, _value: u64) {
+            self.values.clear();
+            if let (Some(before), Some(after)) = (self.before.take(), usage()) {
+                let value = |name, unit, value| ObservedValue { name, unit, value };
+                self.values.extend([
+                    value(
+                        "user_time",
+                        Unit::Nanoseconds,
+                        after.user_ns - before.user_ns,
+                    ),
+                    value("sys_time", Unit::Nanoseconds, after.sys_ns - before.sys_ns),
+                ]);
+                if let (Some(before), Some(after)) = (before.memory, after.memory) {
+                    self.values.extend([
+                        value(
+                            "minor_faults",
+                            Unit::Count,
+                            after.minor_faults - before.minor_faults,
+                        ),
+                        value(
+                            "major_faults",
+                            Unit::Count,
+                            after.major_faults - before.major_faults,
+                        ),
+                        value("max_rss", Unit::Bytes, after.max_rss),
+                    ]);
+                }
+            }
+        }
+
+        fn sample_values(&mut self) -> Vec<ObservedValue> {
+            std::mem::take(&mut self.values)
+        }
+    }
+
+    #[cfg(unix)]
+    fn usage() -> Option<Usage> {
+        use std::mem::MaybeUninit;
+
+        #[cfg(target_os = "linux")]
+        const WHO: libc::c_int = libc::RUSAGE_THREAD;
+        #[cfg(not(target_os = "linux"))]
+        const WHO: libc::c_int = libc::RUSAGE_SELF;
+
+        let mut usage = MaybeUninit::<libc::rusage>::uninit();
+        // SAFETY: getrusage() fully initializes the struct if it succeeds
+        let usage = unsafe {
+            if libc::getrusage(WHO, usage.as_mut_ptr()) != 0 {
+                return None;
+            }
+            usage.assume_init()
+        };
+        let ns = |t: libc::timeval| t.tv_sec as f64 * 1e9 + t.tv_usec as f64 * 1e3;
+        // ru_maxrss is reported in kilobytes everywhere except macOS
+        let rss_scale = if cfg!(target_os = "macos") { 1. } else { 1024. };
+        Some(Usage {
+            user_ns: ns(usage.ru_utime),
+            sys_ns: ns(usage.ru_stime),
+            memory: Some(MemoryUsage {
+                minor_faults: usage.ru_minflt as f64,
+                major_faults: usage.ru_majflt as f64,
+                max_rss: usage.ru_maxrss as f64 * rss_scale,
+            }),
+        })
+    }
+
+    #[cfg(windows)]
+    fn usage() -> Option<Usage> {
+        use std::ffi::c_void;
+
+        #[repr(C)]
+        #[derive(Clone, Copy, Default)]
+        struct FileTime {
+            low: u32,
+            high: u32,
+        }
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetCurrentThread() -> *mut c_void;
+            fn GetThreadTimes(
+                thread: *mut c_void,
+                creation: *mut FileTime,
+                exit: *mut FileTime,
+                kernel: *mut FileTime,
+                user: *mut FileTime,
+            ) -> i32;
+        }
+
+        let [mut creation, mut exit, mut kernel, mut user] = [FileTime::default(); 4];
+        // SAFETY: the pseudo handle of the current thread is always valid and all the pointers are to the local values
+        let ok = unsafe {
+            GetThreadTimes(
+                GetCurrentThread(),
+                &mut creation,
+                &mut exit,
+                &mut kernel,
+                &mut user,
+            )
+        };
+        // FILETIME is a number of 100 ns intervals
+        let ns = |t: FileTime| ((t.high as u64) << 32 | t.low as u64) as f64 * 100.;
+        (ok != 0).then(|| Usage {
+            user_ns: ns(user),
+            sys_ns: ns(kernel),
+            memory: None,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::{Duration, Instant};
+
+        /// Observes a single sample running `f` repeatedly for a given time
+        fn observe(duration: Duration, mut f: impl FnMut()) -> RusageObserver {
+            let mut observer = RusageObserver::new();
+            observer.before_sample("test", Side::Baseline, 1);
+            let started = Instant::now();
+            while started.elapsed() < duration {
+                f();
+            }
+            observer.after_sample("test", Side::Baseline, 0);
+            observer
+        }
+
+        fn value(values: &[ObservedValue], name: &str) -> f64 {
+            values.iter().find(|v| v.name == name).unwrap().value
+        }
+
+        #[test]
+        fn check_rusage_observer() {
+            let mut sum = 0u64;
+            let mut observer = observe(Duration::from_millis(10), || {
+                sum = std::hint::black_box(sum.wrapping_add(1));
+            });
+
+            let values = observer.sample_values();
+            let names = values.iter().map(|v| v.name).collect::<Vec<_>>();
+            if cfg!(unix) {
+                assert_eq!(
+                    names,
+                    [
+                        "user_time",
+                        "sys_time",
+                        "minor_faults",
+                        "major_faults",
+                        "max_rss"
+                    ]
+                );
+                assert!(values[4].value > 0.);
+            } else {
+                assert_eq!(names, ["user_time", "sys_time"]);
+            }
+            assert!(values.iter().all(|v| v.value >= 0.));
+
+            // Values are reported once per sample
+            assert!(observer.sample_values().is_empty());
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        fn check_user_and_system_time() {
+            let duration = Duration::from_millis(200);
+
+            let mut sum = 0u64;
+            let computation = observe(duration, || {
+                for i in 0..10_000u64 {
+                    sum = std::hint::black_box(sum.wrapping_mul(31).wrapping_add(i));
+                }
+            })
+            .sample_values();
+            let (user, sys) = (
+                value(&computation, "user_time"),
+                value(&computation, "sys_time"),
+            );
+            assert!(user > 0., "user: {}", user);
+            assert!(sys < user / 4., "user: {}, sys: {}", user, sys);
+
+            let syscalls = observe(duration, || {
+                std::hint::black_box(std::fs::metadata("/").unwrap());
+            })
+            .sample_values();
+            let sys = value(&syscalls, "sys_time");
+            assert!(sys > 0., "sys: {}", sys);
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "rapl"))]
+mod rapl {
+    use crate::{ObservedValue, SampleObserver, Side, Unit, ENERGY};
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Once,
+    };
+
+    /// Root of the powercap sysfs tree
+    const POWERCAP_ROOT: &str = "/sys/class/powercap";
+
+    /// Prefix of the names of the RAPL zones (used for AMD CPUs as well)
+    const ZONE_PREFIX: &str = "intel-rapl:";
+
+    /// Reports energy consumed per iteration during the sample (in microjoules)
+    ///
+    /// Energy is read from the RAPL counters of the CPU packages (top-level `intel-rapl:N` zones of the powercap
+    /// sysfs tree) before and after each sample. Counters are package-wide, so the energy spent by the other
+    /// processes is counted as well, and they are updated about once a millisecond, so only the samples taking
+    /// several milliseconds are meaningful on their own. Baseline and candidate energy is compared the same way as
+    /// the measured time.
+    ///
+    /// Registered automatically by the `compare` command of the executables built with the `rapl` feature.
+    pub struct RaplObserver {
+        zones: Vec<Zone>,
+        before: Option<Vec<u64>>,
+        iterations: usize,
+        values: Vec<ObservedValue>,
+    }
+
+    struct Zone {
+        energy: PathBuf,
+
+        /// Value the counter wraps around at
+        max_range: u64,
+    }
+
+    impl RaplObserver {
+        /// Observer of the RAPL counters of this machine
+        ///
+        /// Returns `None` if there are no RAPL zones (eg. in a virtual machine) or the counters are not readable.
+        /// Counters are readable only by root on many distributions, a hint is printed once in that case.
+        pub fn new() -> Option<Self> {
+            Self::with_root(Path::new(POWERCAP_ROOT))
+        }
+
+        fn with_root(root: &Path) -> Option<Self> {
+            match discover(root) {
+                Ok(zones) if !zones.is_empty() => Some(Self {
+                    zones,
+                    before: None,
+                    iterations: 0,
+                    values: vec![],
+                }),
+                Ok(_) => None,
+                Err(e) => {
+                    static HINT: Once = Once::new();
+                    HINT.call_once(|| eprintln!("[WARN] {}", unreadable_hint(&e)));
+                    None
+                }
+            }
+        }
+
+        fn read(&self) -> io::Result<Vec<u64>> {
+            self.zones.iter().map(|z| read_uj(&z.energy)).collect()
+        }
+    }
+
+    impl SampleObserver for RaplObserver {
+        fn before_sample(&mut self, _test: &str, _side: Side, iterations: usize) {
+            self.before = self.read().ok();
+            self.iterations = iterations;
+        }
+
+        fn after_sample(&mut self, _test: &str, _side: Side, _value: u64) {
+            self.values.clear();
+            if let (Some(before), Ok(after)) = (self.before.take(), self.read()) {
+                let consumed = self
+                    .zones
+                    .iter()
+                    .zip(before.iter().zip(&after))
+                    .map(|(zone, (&before, &after))| energy_delta(before, after, zone.max_range))
+                    .sum::<u64>();
+                self.values.push(ObservedValue {
+                    name: ENERGY,
+                    unit: Unit::Microjoules,
+                    value: consumed as f64 / self.iterations.max(1) as f64,
+                });
+            }
+        }
+
+        fn sample_values(&mut self) -> Vec<ObservedValue> {
+            std::mem::take(&mut self.values)
+        }
+    }
+
+    /// Top-level RAPL zones of a powercap tree (CPU packages) in the order of their names
+    ///
+    /// Subzones (eg. `intel-rapl:0:0` for the cores) are not returned, because their energy is included in the
+    /// energy of the package. Fails if a counter of the zone can't be read.
+    fn discover(root: &Path) -> io::Result<Vec<Zone>> {
+        let entries = match fs::read_dir(root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut names = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| is_package_zone(name))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                let dir = root.join(name);
+                let energy = dir.join("energy_uj");
+                // Reading the counter once checks the permissions
+                read_uj(&energy)?;
+                // Range is not exposed by some drivers, such counters are assumed to be 32-bit wide
+                let max_range =
+                    read_uj(&dir.join("max_energy_range_uj")).unwrap_or(u32::MAX as u64);
+                Ok(Zone { energy, max_range })
+            })
+            .collect()
+    }
+
+    /// Zone is a package (`intel-rapl:N`), not a subzone or another control type
+    fn is_package_zone(name: &str) -> bool {
+        name.strip_prefix(ZONE_PREFIX)
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    }
+
+    /// Reads a counter in microjoules
+    fn read_uj(path: &Path) -> io::Result<u64> {
+        let value = fs::read_to_string(path)?;
+        value
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Energy consumed between two readings of a counter wrapping around at `max_range`
+    fn energy_delta(before: u64, after: u64, max_range: u64) -> u64 {
+        if after >= before {
+            after - before
+        } else {
+            max_range.saturating_sub(before) + after
+        }
+    }
+
+    fn unreadable_hint(error: &io::Error) -> String {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            format!(
+                "RAPL energy counters are readable only by root, energy is not reported. Allow reading them with `sudo chmod o+r {}/{}*/energy_uj`",
+                POWERCAP_ROOT, ZONE_PREFIX
+            )
+        } else {
+            format!(
+                "Unable to read RAPL energy counters ({}), energy is not reported",
+                error
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{env, process};
+
+        /// Powercap tree with given zones and their counters (`None` for a counter which can't be read)
+        fn fixture(name: &str, zones: &[(&str, Option<u64>)]) -> PathBuf {
+            let root = env::temp_dir().join(format!("tango-rapl-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for (zone, energy) in zones {
+                let dir = root.join(zone);
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("max_energy_range_uj"), "262143328850\n").unwrap();
+                match energy {
+                    Some(energy) => fs::write(dir.join("energy_uj"), format!("{}\n", energy)),
+                    // Reading a directory fails like reading a file without permissions does
+                    None => fs::create_dir(dir.join("energy_uj")),
+                }
+                .unwrap();
+            }
+            root
+        }
+
+        #[test]
+        fn check_zone_discovery() {
+            let root = fixture(
+                "discovery",
+                &[
+                    ("intel-rapl:1", Some(20)),
+                    ("intel-rapl:0", Some(10)),
+                    ("intel-rapl:0:0", Some(5)),
+                    ("intel-rapl-mmio:0", Some(1)),
+                ],
+            );
+            let zones = discover(&root).unwrap();
+            let names = zones
+                .iter()
+                .map(|z| z.energy.parent().unwrap().file_name().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["intel-rapl:0", "intel-rapl:1"]);
+            assert!(zones.iter().all(|z| z.max_range == 262143328850));
+
+            let observer = RaplObserver::with_root(&root).unwrap();
+            assert_eq!(observer.read().unwrap(), [10, 20]);
+            fs::remove_dir_all(&root).unwrap();
+
+            // Missing tree means no RAPL support
+            assert!(discover(&root).unwrap().is_empty());
+            assert!(RaplObserver::with_root(&root).is_none());
+        }
+
+        #[test]
+        fn check_unreadable_counters() {
+            let root = fixture("unreadable", &[("intel-rapl:0", None)]);
+            assert!(discover(&root).is_err());
+            assert!(RaplObserver::with_root(&root).is_none());
+            fs::remove_dir_all(&root).unwrap();
+
+            let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+            assert!(unreadable_hint(&denied).contains("chmod o+r"));
+            let malformed = read_uj(Path::new("/dev/null")).unwrap_err();
+            assert_eq!(malformed.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn check_energy_per_iteration() {
+            let root = fixture("sample", &[("intel-rapl:0", Some(1_000))]);
+            let mut observer = RaplObserver::with_root(&root).unwrap();
+            observer.before_sample("test", Side::Baseline, 4);
+            fs::write(root.join("intel-rapl:0/energy_uj"), "1200").unwrap();
+            observer.after_sample("test", Side::Baseline, 0);
+            assert_eq!(
+                observer.sample_values(),
+                [ObservedValue {
+                    name: ENERGY,
+                    unit: Unit::Microjoules,
+                    value: 50.
+                }]
+            );
+            assert!(observer.sample_values().is_empty());
+
+            // Counter wrapped around during the sample
+            observer.before_sample("test", Side::Candidate, 1);
+            fs::write(root.join("intel-rapl:0/energy_uj"), "100").unwrap();
+            observer.after_sample("test", Side::Candidate, 0);
+            let [value] = observer.sample_values()[..] else {
+                panic!("Single value expected");
+            };
+            assert_eq!(value.value, (262143328850u64 - 1200 + 100) as f64);
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        #[test]
+        fn check_energy_delta() {
+            assert_eq!(energy_delta(100, 250, 1000), 150);
+            assert_eq!(energy_delta(900, 50, 1000), 150);
+            assert_eq!(energy_delta(5, 5, 1000), 0);
+            // Reading above the advertised range doesn't underflow
+            assert_eq!(energy_delta(1200, 10, 1000), 10);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Reporters loaded from dynamic libraries (see [`tango_reporter_plugin`])
+
+use crate::{
+    cli::reporting::JsonResult, Error, PhaseTimings, Reporter, RunResult, SessionInfo, SessionMode,
+    SessionSummary, SuiteSummary,
+};
+use libloading::{Library, Symbol};
+use serde_json::{json, Map, Value};
+use std::{ffi::c_void, path::Path, ptr::null_mut};
+use tango_reporter_plugin::{symbols, AbiVersionFn, FinishFn, InitFn, OnResultFn, ABI_VERSION};
+
+/// [`Reporter`] forwarding the session and the results to a plugin
+pub(crate) struct PluginReporter {
+    init: InitFn,
+    on_result: OnResultFn,
+    finish: FinishFn,
+
+    /// Options given to the plugin in the session JSON (`--reporter-plugin-option`)
+    options: Map<String, Value>,
+
+    /// State returned by the plugin (null before the session is started or if the plugin failed to initialize)
+    state: *mut c_void,
+
+    /// Functions above point into the library, so it should outlive them
+    _library: Library,
+}
+
+impl PluginReporter {
+    pub(crate) fn load(path: &Path, options: &[(String, String)]) -> Result<Self, Error> {
+        let library = unsafe { Library::new(path) }.map_err(Error::UnableToLoadPlugin)?;
+        unsafe {
+            let abi_version: AbiVersionFn = *lookup_symbol(&library, symbols::ABI_VERSION)?;
+            check_abi_version(abi_version())?;
+            Ok(Self {
+                init: *lookup_symbol(&library, symbols::INIT)?,
+                on_result: *lookup_symbol(&library, symbols::ON_RESULT)?,
+                finish: *lookup_symbol(&library, symbols::FINISH)?,
+                options: options
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                    .collect(),
+                state: null_mut(),
+                _library: library,
+            })
+        }
+    }
+
+    fn finish(&mut self) {
+        if !self.state.is_null() {
+            unsafe { (self.finish)(self.state) };
+            self.state = null_mut();
+        }
+    }
+}
+
+fn check_abi_version(version: u32) -> Result<(), Error> {
+    if version == ABI_VERSION {
+        Ok(())
+    } else {
+        Err(Error::IncompatiblePlugin(version))
+    }
+}
+
+unsafe fn lookup_symbol<'l, T>(library: &'l Library, name: &[u8]) -> Result<Symbol<'l, T>, Error> {
+    library.get(name).map_err(Error::UnableToLoadSymbol)
+}
+
+impl Reporter for PluginReporter {
+    fn on_session_start(&mut self, session: &SessionInfo) {
+        self.finish();
+        let (mode, baseline) = match session.mode {
+            SessionMode::Compare { baseline } => ("compare", Some(baseline)),
+            SessionMode::Pairs => ("pairs", None),
+        };
+        // The layout is a part of the plugin ABI (see `tango_reporter_plugin`), so internal types are not exposed
+        let session = json!({
+            "filter": session.filter,
+            "mode": mode,
+            "baseline": baseline,
+            "master_seed": session.master_seed,
+            "environment": session.environment,
+            "tests": session.tests,
+            "options": self.options,
+        })
+        .to_string();
+        self.state = unsafe { (self.init)(session.as_ptr(), session.len()) };
+    }
+
+    fn on_complete(&mut self, results: &RunResult) {
+        if self.state.is_null() {
+            return;
+        }
+        if let Ok(json) = serde_json::to_string(&JsonResult::from(results)) {
+            unsafe { (self.on_result)(self.state, json.as_ptr(), json.len()) };
+        }
+    }
+
+    fn on_session_end(&mut self, _summary: &SessionSummary) {
+        self.finish();
+    }
+
+    // Plugins receive results only
+    fn on_warning(&mut self, _name: &str, _message: &str) {}
+    fn on_error(&mut self, _name: &str, _message: &str) {}
+    fn on_skipped(&mut self, _name: &str, _reason: &str) {}
+}
+
+impl Drop for PluginReporter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Reports to the main reporter and all the plugins
+pub(crate) struct WithPlugins {
+    reporter: Box<dyn Reporter>,
+    plugins: Vec<PluginReporter>,
+}
+
+impl WithPlugins {
+    /// Returns the reporter as is if there are no plugins to load
+    pub(crate) fn load<P: AsRef<Path>>(
+        reporter: Box<dyn Reporter>,
+        paths: &[P],
+        options: &[(String, String)],
+    ) -> Result<Box<dyn Reporter>, Error> {
+        if paths.is_empty() {
+            return Ok(reporter);
+        }
+        let plugins = paths
+            .iter()
+            .map(|path| PluginReporter::load(path.as_ref(), options))
+            .collect::<Result<_, _>>()?;
+        Ok(Box::new(Self { reporter, plugins }))
+    }
+
+    fn reporters(&mut self) -> impl Iterator<Item = &mut (dyn Reporter + 'static)> {
+        let plugins = self
+            .plugins
+            .iter_mut()
+            .map(|p| p as &mut (dyn Reporter + 'static));
+        std::iter::once(self.reporter.as_mut()).chain(plugins)
+    }
+}
+
+impl Reporter for WithPlugins {
+    fn on_session_start(&mut self, session: &SessionInfo) {
+        self.reporters().for_each(|r| r.on_session_start(session));
+    }
+
+    fn on_suite_start(&mut self, names: &[&str]) {
+        self.reporter.on_suite_start(names);
+    }
+
+    fn on_test_start(&mut self, name: &str) {
+        self.reporters().for_each(|r| r.on_test_start(name));
+    }
+
+    fn wants_samples(&self) -> bool {
+        self.reporter.wants_samples()
+    }
+
+    fn on_complete(&mut self, results: &RunResult) {
+        self.reporters().for_each(|r| r.on_complete(results));
+    }
+
+    fn on_finish(&mut self, totals: &PhaseTimings) {
+        self.reporter.on_finish(totals);
+    }
+
+    fn on_suite_finish(&mut self, results: &[RunResult]) {
+        self.reporter.on_suite_finish(results);
+    }
+
+    fn on_summary(&mut self, summary: &SuiteSummary) {
+        self.reporter.on_summary(summary);
+    }
+
+    fn on_warning(&mut self, name: &str, message: &str) {
+        self.reporter.on_warning(name, message);
+    }
+
+    fn on_error(&mut self, name: &str, message: &str) {
+        self.reporter.on_error(name, message);
+    }
+
+    fn on_failure(&mut self, name: &str, message: &str) {
+        self.reporter.on_failure(name, message);
+    }
+
+    fn on_skipped(&mut self, name: &str, reason: &str) {
+        self.reporter.on_skipped(name, reason);
+    }
+
+    fn on_session_end(&mut self, summary: &SessionSummary) {
+        self.reporters().for_each(|r| r.on_session_end(summary));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::Environment, summarized_run_result, MeasurementSettings, Summary};
+    use std::{cell::Cell, env, fs, path::PathBuf, process, rc::Rc, time::Duration};
+
+    /// Example plugin built by `cargo test` along with the tests (`examples/jsonl_reporter.rs`)
+    fn example_plugin() -> PathBuf {
+        let exe = env::current_exe().unwrap();
+        let profile_dir = exe.parent().and_then(Path::parent).unwrap();
+        let path = profile_dir
+            .join("examples")
+            .join(libloading::library_filename("jsonl_reporter"));
+        assert!(
+            path.exists(),
+            "{} is not built (run `cargo test` with examples)",
+            path.display()
+        );
+        path
+    }
+
+    struct Counting(Rc<Cell<usize>>);
+
+    impl Reporter for Counting {
+        fn on_complete(&mut self, _results: &RunResult) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn check_plugin_receives_every_result() {
+        let output = env::temp_dir().join(format!("tango-plugin-{}.jsonl", process::id()));
+        let options = [("output".to_string(), output.display().to_string())];
+        let counter = Rc::new(Cell::new(0));
+        let main = Box::new(Counting(Rc::clone(&counter)));
+        let mut reporter = WithPlugins::load(main, &[example_plugin()], &options).unwrap();
+
+        let settings = MeasurementSettings::default();
+        let names = ["sort", "search", "insert"];
+        reporter.on_session_start(&SessionInfo {
+            settings,
+            filter: "",
+            mode: SessionMode::Pairs,
+            master_seed: 42,
+            environment: Environment::current(),
+            tests: names.to_vec(),
+        });
+        for name in names {
+            let summary = Summary::from(&[10., 12., 11.]).unwrap();
+            let diff = Summary::from(&[-1., -2., 0.]).unwrap();
+            let result = summarized_run_result(name, summary, summary, diff, None, 0, &settings);
+            reporter.on_complete(&result);
+        }
+        reporter.on_session_end(&SessionSummary {
+            phases: PhaseTimings::default(),
+            wall_time: Duration::ZERO,
+            dumps: vec![],
+            sentinels: None,
+        });
+
+        assert_eq!(counter.get(), names.len());
+
+        let lines = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+        let lines = lines
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), names.len() + 2);
+        assert_eq!(lines[0]["session"]["master_seed"], 42);
+        assert_eq!(lines[0]["session"]["tests"], json!(names));
+        assert_eq!(lines[0]["session"]["mode"], "pairs");
+        assert_eq!(lines[0]["session"]["options"]["output"], json!(output));
+        for (line, name) in lines[1..].iter().zip(names) {
+            let result = serde_json::from_value::<JsonResult>(line["result"].clone()).unwrap();
+            assert_eq!(result.name, name);
+            assert_eq!(result.outliers, 0);
+        }
+        assert_eq!(lines[names.len() + 1]["finish"]["results"], names.len());
+    }
+
+    #[test]
+    fn check_abi_version_mismatch() {
+        assert!(check_abi_version(ABI_VERSION).is_ok());
+        assert!(matches!(
+            check_abi_version(ABI_VERSION + 1),
+            Err(Error::IncompatiblePlugin(v)) if v == ABI_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! Tolerance profiles of the tests
+//!
+//! A [`Profile`] is a set of settings the results of a test are judged by. Profiles are defined in
+//! [`MainOptions::profiles`](crate::cli::MainOptions::profiles) and assigned to the tests at registration with
+//! [`MeasureTargetExt::with_profile()`](crate::MeasureTargetExt::with_profile).
+//!
+//! ```rust
+//! use tango_bench::{benchmark_fn, profile::Profile, IntoBenchmarks, MeasureTargetExt};
+//!
+//! // Given to `tango_main!()` as `MainOptions { profiles, ..MainOptions::default() }`
+//! let profiles = vec![("io".to_string(), Profile { min_effect: Some(5.), ..Profile::EMPTY })];
+//!
+//! fn io_benchmarks() -> impl IntoBenchmarks {
+//!     [benchmark_fn("read_dir", || std::fs::read_dir(".").map(|d| d.count())).with_profile("io")]
+//! }
+//! ```
+//!
+//! Effective settings of a test are resolved from the lowest to the highest precedence: the global settings, the
+//! profile of the test, the overrides of the test and the overrides given on the command line
+//! (`--profile io:min_effect=2%`). The profiles of the candidate are used when comparing.
+
+use crate::{MeasurementSettings, OutlierDetection};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Settings the results of a test are judged by
+///
+/// Settings which are `None` are inherited from the lower level (see [module documentation](crate::profile)).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Significance level of the difference (see [`MeasurementSettings::significance`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub significance: Option<f64>,
+
+    /// Minimal difference of interest in percent (see [`MeasurementSettings::min_effect`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_effect: Option<f64>,
+
+    /// See [`MeasurementSettings::min_samples`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_samples: Option<usize>,
+
+    /// See [`MeasurementSettings::outlier_detection`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier: Option<OutlierDetection>,
+}
+
+impl Profile {
+    /// Profile inheriting all the settings
+    pub const EMPTY: Self = Self {
+        significance: None,
+        min_effect: None,
+        min_samples: None,
+        outlier: None,
+    };
+
+    /// Settings of this profile overridden by the ones set in `other`
+    pub fn merged(&self, other: &Profile) -> Profile {
+        Profile {
+            significance: other.significance.or(self.significance),
+            min_effect: other.min_effect.or(self.min_effect),
+            min_samples: other.min_samples.or(self.min_samples),
+            outlier: other.outlier.or(self.outlier),
+        }
+    }
+
+    /// Given settings overridden by the ones set in this profile
+    pub fn apply(&self, settings: &MeasurementSettings) -> MeasurementSettings {
+        MeasurementSettings {
+            significance: self.significance.or(settings.significance),
+            min_effect: self.min_effect.unwrap_or(settings.min_effect),
+            min_samples: self.min_samples.unwrap_or(settings.min_samples),
+            outlier_detection: self.outlier.unwrap_or(settings.outlier_detection),
+            ..*settings
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::EMPTY
+    }
+}
+
+impl fmt::Display for Profile {
+    /// Settings set in the profile (eg. `min_effect=2% min_samples=50`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = vec![];
+        if let Some(significance) = self.significance {
+            settings.push(format!("significance={}", significance));
+        }
+        if let Some(min_effect) = self.min_effect {
+            settings.push(format!("min_effect={}%", min_effect));
+        }
+        if let Some(min_samples) = self.min_samples {
+            settings.push(format!("min_samples={}", min_samples));
+        }
+        match self.outlier {
+            Some(OutlierDetection::None) => settings.push("outlier=none".into()),
+            Some(OutlierDetection::Iqr) => settings.push("outlier=iqr".into()),
+            Some(OutlierDetection::Trim {
+                lower_pct,
+                upper_pct,
+            }) => settings.push(format!("outlier=trim:{},{}", lower_pct, upper_pct)),
+            None => {}
+        }
+        write!(f, "{}", settings.join(" "))
+    }
+}
+
+/// Tolerance of a registered test
+///
+/// Reported with the overrides of the test only and merged with the definition of the profile by [`Self::resolve()`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestProfile {
+    /// Name of the profile assigned to the test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Settings of the profile overridden by the ones of the test
+    #[serde(default)]
+    pub tolerance: Profile,
+
+    /// Profile is assigned to the test, but not defined (eg. misspelled)
+    #[serde(default)]
+    pub undefined: bool,
+}
+
+impl TestProfile {
+    /// Profile assigned to a test with the overrides of the test
+    pub fn new(name: Option<&str>, overrides: &Profile) -> Self {
+        Self {
+            name: name.map(str::to_string),
+            tolerance: *overrides,
+            undefined: false,
+        }
+    }
+
+    /// Merges the definition of the profile with the overrides of the test or marks the profile as undefined
+    pub fn resolve(&mut self, definitions: &[(String, Profile)]) {
+        let Some(name) = &self.name else {
+            return;
+        };
+        match definitions.iter().find(|(n, _)| n == name) {
+            Some((_, definition)) => self.tolerance = definition.merged(&self.tolerance),
+            None => self.undefined = true,
+        }
+    }
+
+    /// Effective settings of the test given the global ones and the overrides of the profiles from the command line
+    pub fn settings(
+        &self,
+        global: &MeasurementSettings,
+        overrides: &[ProfileOverride],
+    ) -> MeasurementSettings {
+        let settings = self.tolerance.apply(global);
+        overrides
+            .iter()
+            .filter(|o| self.name.as_deref() == Some(o.profile.as_str()))
+            .fold(settings, |settings, o| o.settings.apply(&settings))
+    }
+}
+
+/// Overrides of the settings of a profile from the command line (`--profile io:min_effect=2%`)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProfileOverride {
+    pub profile: String,
+    pub settings: Profile,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn min_effect(min_effect: f64) -> Profile {
+        Profile {
+            min_effect: Some(min_effect),
+            ..Profile::EMPTY
+        }
+    }
+
+    fn resolved(name: &str, definitions: &[(String, Profile)], overrides: &Profile) -> TestProfile {
+        let mut test = TestProfile::new(Some(name), overrides);
+        test.resolve(definitions);
+        test
+    }
+
+    fn cli(profile: &str, settings: Profile) -> ProfileOverride {
+        ProfileOverride {
+            profile: profile.into(),
+            settings,
+        }
+    }
+
+    #[test]
+    fn check_merge() {
+        let base = Profile {
+            significance: Some(0.05),
+            min_effect: Some(2.),
+            ..Profile::EMPTY
+        };
+        let over = Profile {
+            min_effect: Some(5.),
+            min_samples: Some(50),
+            ..Profile::EMPTY
+        };
+        let merged = base.merged(&over);
+        assert_eq!(merged.significance, Some(0.05));
+        assert_eq!(merged.min_effect, Some(5.));
+        assert_eq!(merged.min_samples, Some(50));
+        assert_eq!(merged.outlier, None);
+
+        assert_eq!(base.merged(&Profile::EMPTY), base);
+        assert_eq!(Profile::EMPTY.merged(&base), base);
+        assert!(Profile::default().is_empty());
+        assert_eq!(
+            merged.to_string(),
+            "significance=0.05 min_effect=5% min_samples=50"
+        );
+    }
+
+    #[test]
+    fn check_apply() {
+        let global = MeasurementSettings::default();
+        assert_eq!(
+            format!("{:?}", Profile::EMPTY.apply(&global)),
+            format!("{:?}", global)
+        );
+
+        let profile = Profile {
+            significance: Some(0.05),
+            min_effect: Some(3.),
+            min_samples: Some(100),
+            outlier: Some(OutlierDetection::Iqr),
+        };
+        let settings = profile.apply(&global);
+        assert_eq!(settings.significance, Some(0.05));
+        assert_eq!(settings.min_effect, 3.);
+        assert_eq!(settings.min_samples, 100);
+        assert_eq!(settings.outlier_detection, OutlierDetection::Iqr);
+        // Settings outside of the profile are left intact
+        assert_eq!(settings.samples_per_haystack, global.samples_per_haystack);
+    }
+
+    #[test]
+    fn check_precedence() {
+        let global = MeasurementSettings {
+            min_effect: 1.,
+            min_samples: 10,
+            significance: Some(0.01),
+            ..MeasurementSettings::default()
+        };
+        let definition = Profile {
+            min_effect: Some(2.),
+            min_samples: Some(20),
+            ..Profile::EMPTY
+        };
+        let definitions = [("io".to_string(), definition)];
+        let overrides = min_effect(3.);
+
+        // global < profile
+        let test = resolved("io", &definitions, &Profile::EMPTY);
+        let settings = test.settings(&global, &[]);
+        assert_eq!(settings.min_effect, 2.);
+        assert_eq!(settings.min_samples, 20);
+        assert_eq!(settings.significance, Some(0.01));
+
+        // profile < per-test override
+        let test = resolved("io", &definitions, &overrides);
+        let settings = test.settings(&global, &[]);
+        assert_eq!(settings.min_effect, 3.);
+        assert_eq!(settings.min_samples, 20);
+
+        // per-test override < command line, later command line overrides win
+        let from_cli = [
+            cli("io", min_effect(4.)),
+            cli("cpu", min_effect(0.1)),
+            cli(
+                "io",
+                Profile {
+                    min_samples: Some(40),
+                    ..Profile::EMPTY
+                },
+            ),
+        ];
+        let settings = test.settings(&global, &from_cli);
+        assert_eq!(settings.min_effect, 4.);
+        assert_eq!(settings.min_samples, 40);
+        let settings = test.settings(
+            &global,
+            &[cli("io", min_effect(4.)), cli("io", min_effect(5.))],
+        );
+        assert_eq!(settings.min_effect, 5.);
+
+        // Undefined profile applies the overrides of the test only
+        let test = resolved("net", &definitions, &overrides);
+        assert_eq!(test.tolerance, overrides);
+        assert!(test.undefined);
+        assert_eq!(test.settings(&global, &from_cli).min_effect, 3.);
+
+        // Tests without a profile are not affected by the command line overrides
+        let test = TestProfile::default();
+        let settings = test.settings(&global, &from_cli);
+        assert_eq!(settings.min_effect, 1.);
+        assert_eq!(settings.min_samples, 10);
+    }
+
+    #[test]
+    fn check_resolve() {
+        let definitions = [
+            ("profile-test".to_string(), min_effect(3.)),
+            ("other".to_string(), min_effect(2.)),
+        ];
+        let mut test = resolved("profile-test", &definitions, &Profile::EMPTY);
+        assert_eq!(test.tolerance, min_effect(3.));
+        test.resolve(&definitions);
+        assert_eq!(test.tolerance, min_effect(3.));
+
+        let json = serde_json::to_string(&test).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"profile-test","tolerance":{"min_effect":3.0},"undefined":false}"#
+        );
+        assert_eq!(serde_json::from_str::<TestProfile>(&json).unwrap(), test);
+        assert_eq!(
+            serde_json::from_str::<TestProfile>("{}").unwrap(),
+            TestProfile::default()
+        );
+
+        let mut test = TestProfile::default();
+        test.resolve(&definitions);
+        assert_eq!(test, TestProfile::default());
+    }
+}
@@ -0,0 +1,221 @@
+//! One-shot comparison of two closures without registering benchmarks
+//!
+//! [`compare()`] answers "is B faster than A on this input?" from a unit test or an experiment: both closures are
+//! sampled in alternating order for a given time budget and the result is returned instead of being printed.
+//! [`assert_faster!`](crate::assert_faster) builds a test assertion on top of it.
+//!
+//! # Caveats
+//!
+//! Results are only as good as the machine the test runs on. CI runners are shared and noisy, tests running in
+//! parallel compete for the same cores, and debug builds measure code which is not the one shipped. Keep the
+//! asserted differences large (several times, not several percents), give the comparison a generous budget and
+//! expect an occasional inconclusive result. Whatever is compared is measured in the same process with the same
+//! state, so the closures should not depend on each other's side effects.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use tango_bench::quick;
+//!
+//! let data = (0..1000u64).collect::<Vec<_>>();
+//! let result = quick::compare(
+//!     || data.iter().sum::<u64>(),
+//!     || data.iter().fold(0, |a, b| a + b),
+//!     Duration::from_millis(50),
+//! );
+//! println!("fold vs sum: {}", result);
+//! ```
+
+use crate::{
+    calculate_run_result_raw, measure_retaining,
+    stats::{ConfidenceInterval, Verdict},
+    MeasurementSettings, SampleOrder,
+};
+use std::{
+    fmt,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Seed the order of the first sample is chosen with, so the comparisons are reproducible
+const SEED: u64 = 0;
+
+/// Minimum number of samples taken even if the budget is exceeded
+const MIN_SAMPLES: usize = 10;
+
+/// Number of samples the duration of a sample is planned for
+const PLANNED_SAMPLES: u32 = 200;
+
+/// Number of calls the time of a single call is estimated from
+const ESTIMATE_CALLS: usize = 5;
+
+/// Outcome of [`compare()`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuickResult {
+    pub verdict: Verdict,
+
+    /// Change of the second closure relative to the first one (eg. `-0.1` if it is 10% faster)
+    pub relative_change: f64,
+
+    /// Confidence interval of [`Self::relative_change`]
+    pub ci: ConfidenceInterval,
+
+    /// Number of paired samples taken
+    pub samples: usize,
+}
+
+impl QuickResult {
+    /// Returns `true` if the second closure is significantly faster than the first one
+    pub fn is_improvement(&self) -> bool {
+        self.verdict == Verdict::Different && self.relative_change < 0.
+    }
+}
+
+impl fmt::Display for QuickResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:+.2}% (CI {:+.2}% … {:+.2}%), {:?} after {} samples",
+            self.relative_change * 100.,
+            self.ci.lower * 100.,
+            self.ci.upper * 100.,
+            self.verdict,
+            self.samples
+        )
+    }
+}
+
+/// Compares the execution time of two closures within a given time budget
+///
+/// `a` is the baseline and `b` is the candidate: negative [`QuickResult::relative_change`] means `b` is faster.
+/// The number of iterations per sample is chosen to take about 200 samples within the budget, but at least 10
+/// samples are taken even if it takes longer. Statistics are computed with [`MeasurementSettings::default()`].
+/// Nothing is printed.
+pub fn compare<O1, O2>(
+    mut a: impl FnMut() -> O1,
+    mut b: impl FnMut() -> O2,
+    budget: Duration,
+) -> QuickResult {
+    let mut measure_a = |iterations| measure_retaining(iterations, || black_box(a()));
+    let mut measure_b = |iterations| measure_retaining(iterations, || black_box(b()));
+
+    let call_ns = estimate_call(&mut measure_a).max(estimate_call(&mut measure_b));
+    let sample_ns = (budget / PLANNED_SAMPLES).as_nanos() as u64;
+    let iterations = (sample_ns / call_ns).max(1) as usize;
+
+    let first = SampleOrder::first_for_seed(SEED);
+    let (mut baseline, mut candidate) = (vec![], vec![]);
+    let started = Instant::now();
+    while baseline.len() < MIN_SAMPLES || started.elapsed() < budget {
+        match SampleOrder::of_sample(baseline.len(), first) {
+            SampleOrder::BaselineFirst => {
+                baseline.push(measure_a(iterations));
+                candidate.push(measure_b(iterations));
+            }
+            SampleOrder::CandidateFirst => {
+                candidate.push(measure_b(iterations));
+                baseline.push(measure_a(iterations));
+            }
+        }
+    }
+
+    let samples = baseline.len();
+    let result = calculate_run_result_raw(
+        "quick",
+        &baseline,
+        &candidate,
+        &vec![iterations; samples],
+        [1, 1],
+        first,
+        &MeasurementSettings::default(),
+    )
+    .expect("samples are taken");
+    let estimate = &result.diff_estimate;
+    QuickResult {
+        verdict: estimate.verdict,
+        relative_change: estimate.pct / 100.,
+        ci: ConfidenceInterval {
+            lower: estimate.ci.lower / 100.,
+            upper: estimate.ci.upper / 100.,
+        },
+        samples,
+    }
+}
+
+/// Median time of a single call (in nanoseconds). The first call is not counted, so it also warms the closure up
+fn estimate_call(measure: &mut impl FnMut(usize) -> u64) -> u64 {
+    measure(1);
+    let mut times = (0..ESTIMATE_CALLS).map(|_| measure(1)).collect::<Vec<_>>();
+    times.sort_unstable();
+    times[ESTIMATE_CALLS / 2].max(1)
+}
+
+/// Asserts that the first closure is significantly faster than the second one within a given time budget
+///
+/// Panics with both closures and the result of the comparison (see [`quick::compare()`](crate::quick::compare))
+/// otherwise. Read the caveats in [`crate::quick`] before using it in CI.
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+/// use tango_bench::assert_faster;
+///
+/// assert_faster!(
+///     || thread::sleep(Duration::from_micros(10)),
+///     || thread::sleep(Duration::from_millis(1)),
+///     Duration::from_millis(50)
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_faster {
+    ($faster:expr, $slower:expr, $budget:expr $(,)?) => {{
+        let result = $crate::quick::compare($slower, $faster, $budget);
+        if !result.is_improvement() {
+            panic!(
+                "`{}` is not faster than `{}`: {}",
+                stringify!($faster),
+                stringify!($slower),
+                result
+            );
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn sleep(us: u64) {
+        thread::sleep(Duration::from_micros(us))
+    }
+
+    #[test]
+    fn check_quick_compare() {
+        let mut calls = 0;
+        let result = compare(
+            || {
+                calls += 1;
+                sleep(100)
+            },
+            || sleep(1000),
+            Duration::from_millis(100),
+        );
+        assert_eq!(result.verdict, Verdict::Different, "{}", result);
+        assert!(result.relative_change > 1., "{}", result);
+        assert!(result.ci.lower > 0.5, "{}", result);
+        assert!(!result.is_improvement());
+        assert!(result.samples >= MIN_SAMPLES);
+        // Borrowed state is updated by the calls
+        assert!(calls >= result.samples, "{}", calls);
+    }
+
+    #[test]
+    fn check_assert_faster() {
+        assert_faster!(|| sleep(100), || sleep(1000), Duration::from_millis(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not faster than")]
+    fn check_assert_faster_fails() {
+        assert_faster!(|| sleep(1000), || sleep(100), Duration::from_millis(100));
+    }
+}
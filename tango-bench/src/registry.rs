@@ -0,0 +1,589 @@
+//! Snapshots of the registered benchmarks
+//!
+//! Renaming a test or dropping its registration breaks the history of its results and the dashboards keyed by the
+//! test name. [`RegistrySnapshot`] is a checked-in description of all the registered tests (name, unit, weight and
+//! measurement mode) which is compared with the current registry by `list --verify-registry <path>` or by
+//! [`assert_registry_matches!`](crate::assert_registry_matches) in a regular test.
+//!
+//! Snapshot is a text file with a header line followed by a line per test sorted by name. Each line is the name of the
+//! test and its attributes separated by a tab:
+//!
+//! ```text
+//! # tango registry snapshot v1
+//! sort/random_vec<u32>[100]    unit=ns weight=1 cold=false e2e=false batch=1
+//! sort/random_vec<u32>[1000]   unit=ns weight=1 cold=false e2e=false batch=1
+//! ```
+//!
+//! This module also collects the registered benchmarks. Both [`tango_benchmarks!`](crate::tango_benchmarks) and
+//! the functions annotated with `#[tango_bench::benchmark]` (`macros` feature) submit a factory of their benchmarks
+//! to a distributed slice (see [`inventory`]), and the exported `tango_init()` registers all the [`collected()`]
+//! benchmarks in the harness.
+
+#[cfg(feature = "dylib")]
+use crate::{dylib::Spi, IntoBenchmarks};
+use crate::{id::TestId, Error, MeasureTarget, Unit};
+use std::{borrow::Cow, cmp::Ordering, fmt, fs, path::Path};
+
+/// First line of a snapshot file
+const HEADER: &str = "# tango registry snapshot v1";
+
+/// Function building the registered benchmarks
+pub type BenchmarkFactory = fn() -> Vec<Box<dyn MeasureTarget>>;
+
+/// Factory submitted by [`__tango_register!`](crate::__tango_register)
+#[doc(hidden)]
+pub struct Registration {
+    pub factory: BenchmarkFactory,
+
+    /// Benchmarks are listed in `tango_benchmarks!()` rather than annotated with `#[tango_bench::benchmark]`
+    pub listed: bool,
+}
+
+inventory::collect!(Registration);
+
+/// Minimal similarity of the names (see [`similarity()`]) a removed and an added test are reported as a rename with
+const RENAME_SIMILARITY: f64 = 0.6;
+
+/// Registered test as recorded in a snapshot
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistryEntry {
+    pub name: TestId,
+    pub unit: Unit,
+    pub weight: f64,
+    pub cold: bool,
+    pub e2e: bool,
+    pub batch: usize,
+}
+
+impl RegistryEntry {
+    /// Attributes of the entry in snapshot format
+    fn attributes(&self) -> String {
+        format!(
+            "unit={} weight={} cold={} e2e={} batch={}",
+            unit_name(self.unit),
+            self.weight,
+            self.cold,
+            self.e2e,
+            self.batch
+        )
+    }
+
+    fn parse(line: &str) -> Result<Self, &'static str> {
+        let (name, attributes) = line.split_once('\t').ok_or("no attributes")?;
+        let mut entry = Self {
+            name: unescape(name).into(),
+            unit: Unit::Nanoseconds,
+            weight: 1.,
+            cold: false,
+            e2e: false,
+            batch: 1,
+        };
+        for attribute in attributes.split_whitespace() {
+            let (key, value) = attribute.split_once('=').ok_or("invalid attribute")?;
+            match key {
+                "unit" => entry.unit = parse_unit(value).ok_or("invalid unit")?,
+                "weight" => entry.weight = value.parse().map_err(|_| "invalid weight")?,
+                "cold" => entry.cold = value.parse().map_err(|_| "invalid cold flag")?,
+                "e2e" => entry.e2e = value.parse().map_err(|_| "invalid e2e flag")?,
+                "batch" => entry.batch = value.parse().map_err(|_| "invalid batch size")?,
+                // Attributes added by newer versions are ignored
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+}
+
+/// Description of all the registered tests sorted by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegistrySnapshot {
+    entries: Vec<RegistryEntry>,
+}
+
+impl RegistrySnapshot {
+    pub fn new(mut entries: Vec<RegistryEntry>) -> Self {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { entries }
+    }
+
+    #[cfg(feature = "dylib")]
+    pub(crate) fn of_spi(spi: &Spi) -> Self {
+        let entries = spi
+            .tests()
+            .iter()
+            .map(|f| RegistryEntry {
+                name: f.name.clone(),
+                unit: f.unit,
+                weight: f.weight,
+                cold: f.cold,
+                e2e: f.e2e,
+                batch: f.batch,
+            })
+            .collect();
+        Self::new(entries)
+    }
+
+    /// Snapshot of given benchmarks as they would be registered by `tango_benchmarks!()`
+    #[cfg(feature = "dylib")]
+    pub fn of_benchmarks(benchmarks: impl IntoBenchmarks) -> Result<Self, Error> {
+        let spi = Spi::for_benchmarks(benchmarks.into_benchmarks())?;
+        Ok(Self::of_spi(&spi))
+    }
+
+    pub fn entries(&self) -> &[RegistryEntry] {
+        &self.entries
+    }
+
+    /// Snapshot in the file format
+    pub fn render(&self) -> String {
+        let mut out = format!("{}\n", HEADER);
+        for entry in &self.entries {
+            out.push_str(&format!(
+                "{}\t{}\n",
+                escape(entry.name.as_str()),
+                entry.attributes()
+            ));
+        }
+        out
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let mut lines = input.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim_end()) != Some(HEADER) {
+            return Err(Error::InvalidRegistrySnapshot(1, "missing header"));
+        }
+        let entries = lines
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(no, l)| {
+                RegistryEntry::parse(l).map_err(|e| Error::InvalidRegistrySnapshot(no + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(entries))
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.render())?;
+        Ok(())
+    }
+
+    /// Differences of a current registry from this snapshot
+    pub fn diff(&self, current: &Self) -> RegistryDiff {
+        let mut diff = RegistryDiff::default();
+        let mut removed = vec![];
+        let mut added = vec![];
+
+        let (mut expected, mut actual) = (
+            self.entries.iter().peekable(),
+            current.entries.iter().peekable(),
+        );
+        loop {
+            match (expected.peek(), actual.peek()) {
+                (Some(e), Some(a)) => match e.name.cmp(&a.name) {
+                    Ordering::Less => removed.push(expected.next().unwrap()),
+                    Ordering::Greater => added.push(actual.next().unwrap()),
+                    Ordering::Equal => {
+                        let (e, a) = (expected.next().unwrap(), actual.next().unwrap());
+                        if e != a {
+                            diff.changed.push((e.clone(), a.clone()));
+                        }
+                    }
+                },
+                (Some(_), None) => removed.push(expected.next().unwrap()),
+                (None, Some(_)) => added.push(actual.next().unwrap()),
+                (None, None) => break,
+            }
+        }
+
+        // The most similar pairs of removed and added tests are matched first
+        let mut candidates = vec![];
+        for (r, old) in removed.iter().enumerate() {
+            for (a, new) in added.iter().enumerate() {
+                let score = similarity(old.name.as_str(), new.name.as_str());
+                if score >= RENAME_SIMILARITY {
+                    candidates.push((score, r, a));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+        let mut renamed_from = vec![false; removed.len()];
+        let mut renamed_to = vec![false; added.len()];
+        for (_, r, a) in candidates {
+            if !renamed_from[r] && !renamed_to[a] {
+                renamed_from[r] = true;
+                renamed_to[a] = true;
+                diff.renamed
+                    .push((removed[r].name.clone(), added[a].name.clone()));
+            }
+        }
+        diff.renamed.sort();
+
+        diff.removed = (removed.iter().zip(renamed_from))
+            .filter(|(_, renamed)| !renamed)
+            .map(|(e, _)| e.name.clone())
+            .collect();
+        diff.added = (added.iter().zip(renamed_to))
+            .filter(|(_, renamed)| !renamed)
+            .map(|(e, _)| e.name.clone())
+            .collect();
+        diff
+    }
+}
+
+/// Differences between a registry snapshot and the current registry
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegistryDiff {
+    /// Tests missing from the snapshot
+    pub added: Vec<TestId>,
+
+    /// Tests missing from the current registry
+    pub removed: Vec<TestId>,
+
+    /// Old and new names of the tests which are likely renamed
+    pub renamed: Vec<(TestId, TestId)>,
+
+    /// Snapshot and current entries of the tests with the changed attributes
+    pub changed: Vec<(RegistryEntry, RegistryEntry)>,
+}
+
+impl RegistryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for RegistryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.added {
+            writeln!(f, "  added    {}", name)?;
+        }
+        for name in &self.removed {
+            writeln!(f, "  removed  {}", name)?;
+        }
+        for (old, new) in &self.renamed {
+            writeln!(f, "  renamed  {} -> {}", old, new)?;
+        }
+        for (old, new) in &self.changed {
+            writeln!(
+                f,
+                "  changed  {} ({} -> {})",
+                old.name,
+                old.attributes(),
+                new.attributes()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Similarity of two names from 0 (nothing in common) to 1 (same names)
+///
+/// Computed as one minus the edit distance of the names relative to the length of the longest one, so changing a
+/// single segment of a long name (eg. `sort/vec/100` → `sort/vec/1000`) keeps the names similar.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.;
+    }
+
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1. - previous[b.len()] as f64 / longest as f64
+}
+
+/// Reason a test name is rejected (see [`normalize_name()`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidName {
+    /// Name is empty or consists of whitespace only
+    Empty,
+
+    /// Name contains a control character (eg. a newline) which would break the line-oriented outputs
+    ControlCharacter(char),
+
+    /// Name is longer than the limit (in bytes)
+    TooLong { len: usize, max: usize },
+}
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidName::Empty => write!(f, "name is empty"),
+            InvalidName::ControlCharacter(c) => {
+                write!(f, "name contains control character {:?}", c)
+            }
+            InvalidName::TooLong { len, max } => {
+                write!(f, "name is {} bytes long, the limit is {} bytes", len, max)
+            }
+        }
+    }
+}
+
+/// Validates the name of a registered test and collapses interior runs of whitespace into single spaces
+///
+/// Names are used as keys matching the tests of baseline and candidate and are printed one per line, so names
+/// with control characters, empty names (after trimming) and names longer than `max_len` bytes are rejected.
+pub fn normalize_name(name: &str, max_len: usize) -> Result<Cow<'_, str>, InvalidName> {
+    if let Some(c) = name.chars().find(|c| c.is_control()) {
+        return Err(InvalidName::ControlCharacter(c));
+    }
+    // Names without leading, trailing, repeated or non-space whitespace are split the same way by both
+    let name = if name.split(' ').eq(name.split_whitespace()) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(name.split_whitespace().collect::<Vec<_>>().join(" "))
+    };
+    if name.is_empty() {
+        return Err(InvalidName::Empty);
+    }
+    if name.len() > max_len {
+        return Err(InvalidName::TooLong {
+            len: name.len(),
+            max: max_len,
+        });
+    }
+    Ok(name)
+}
+
+/// Benchmarks listed in `tango_benchmarks!()` followed by the ones registered by `#[tango_bench::benchmark]`
+///
+/// Each call builds the benchmarks anew. Order of the annotated functions is the order of linking, so it is not
+/// related to the order of the functions in the source code.
+pub fn collected() -> Vec<Box<dyn MeasureTarget>> {
+    let (listed, annotated): (Vec<_>, Vec<_>) = inventory::iter::<Registration>
+        .into_iter()
+        .partition(|r| r.listed);
+    (listed.into_iter().chain(annotated))
+        .flat_map(|r| (r.factory)())
+        .collect()
+}
+
+/// Prefix of a name of at most `max_len` bytes cut at a character boundary (for error messages)
+pub(crate) fn truncate_name(name: &str, max_len: usize) -> String {
+    if name.len() <= max_len {
+        return name.to_string();
+    }
+    let end = (0..=max_len)
+        .rev()
+        .find(|i| name.is_char_boundary(*i))
+        .unwrap_or(0);
+    format!("{}…", &name[..end])
+}
+
+/// Short name of a unit used in the registry snapshots and in CSV files
+pub(crate) fn unit_name(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Nanoseconds => "ns",
+        Unit::Bytes => "bytes",
+        Unit::Count => "count",
+        Unit::Microjoules => "uJ",
+    }
+}
+
+fn parse_unit(value: &str) -> Option<Unit> {
+    [
+        Unit::Nanoseconds,
+        Unit::Bytes,
+        Unit::Count,
+        Unit::Microjoules,
+    ]
+    .into_iter()
+    .find(|u| unit_name(*u) == value)
+}
+
+/// Escapes the characters which would break the line structure of a snapshot
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> RegistryEntry {
+        RegistryEntry {
+            name: name.into(),
+            unit: Unit::Nanoseconds,
+            weight: 1.,
+            cold: false,
+            e2e: false,
+            batch: 1,
+        }
+    }
+
+    fn snapshot(names: &[&str]) -> RegistrySnapshot {
+        RegistrySnapshot::new(names.iter().map(|n| entry(n)).collect())
+    }
+
+    #[tango_bench_macros::benchmark(name = "registry/answer")]
+    fn answer() -> u64 {
+        42
+    }
+
+    #[tango_bench_macros::benchmark(generator = crate::generators::RandomVec::<u64>::new(10))]
+    #[allow(clippy::ptr_arg)]
+    fn registry_sum(haystack: &Vec<u64>, _: &()) -> u64 {
+        haystack.iter().copied().fold(0, u64::wrapping_add)
+    }
+
+    #[test]
+    fn check_collected_benchmarks() {
+        let benchmarks = collected();
+        let mut names = benchmarks.iter().map(|b| b.name()).collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["registry/answer", "registry_sum/u64/10"]);
+        // Annotated functions are left callable
+        assert_eq!(answer(), 42);
+    }
+
+    #[test]
+    fn check_snapshot_format() {
+        let mut weird = entry("odd\tname\\with\nbreaks");
+        weird.unit = Unit::Bytes;
+        weird.weight = 0.5;
+        weird.cold = true;
+        let snapshot = RegistrySnapshot::new(vec![entry("sort/vec/100"), weird, entry("find/vec")]);
+        let rendered = snapshot.render();
+        assert_eq!(
+            rendered.lines().collect::<Vec<_>>(),
+            [
+                HEADER,
+                "find/vec\tunit=ns weight=1 cold=false e2e=false batch=1",
+                "odd\\tname\\\\with\\nbreaks\tunit=bytes weight=0.5 cold=true e2e=false batch=1",
+                "sort/vec/100\tunit=ns weight=1 cold=false e2e=false batch=1",
+            ]
+        );
+        assert_eq!(RegistrySnapshot::parse(&rendered).unwrap(), snapshot);
+
+        let Err(Error::InvalidRegistrySnapshot(line, _)) =
+            RegistrySnapshot::parse(&format!("{}\nsort\tunit=parsecs\n", HEADER))
+        else {
+            panic!("invalid unit is accepted");
+        };
+        assert_eq!(line, 2);
+        assert!(RegistrySnapshot::parse("sort\tunit=ns\n").is_err());
+    }
+
+    #[test]
+    fn check_registry_diff() {
+        let expected = snapshot(&["find/vec/100", "sort/vec/100", "sort/vec/1000", "sum"]);
+        assert!(expected.diff(&expected).is_empty());
+
+        let mut changed = entry("sum");
+        changed.weight = 2.;
+        let current = RegistrySnapshot::new(vec![
+            entry("find/vec/100"),
+            entry("sort/vec/10"),
+            entry("sort/vec/1000"),
+            changed.clone(),
+            entry("unrelated"),
+        ]);
+        let diff = expected.diff(&current);
+        assert_eq!(diff.added, ["unrelated"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.renamed,
+            [("sort/vec/100".into(), "sort/vec/10".into())]
+        );
+        assert_eq!(diff.changed, [(entry("sum"), changed)]);
+
+        // Dissimilar names are reported as removed and added
+        let diff = snapshot(&["sort"]).diff(&snapshot(&["hash_map_lookup"]));
+        assert_eq!(diff.removed, ["sort"]);
+        assert_eq!(diff.added, ["hash_map_lookup"]);
+        assert!(diff.renamed.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "  added    hash_map_lookup\n  removed  sort\n"
+        );
+    }
+
+    #[test]
+    fn check_similarity() {
+        assert_eq!(similarity("", ""), 1.);
+        assert_eq!(similarity("sort", "sort"), 1.);
+        assert_eq!(similarity("abc", "xyz"), 0.);
+        assert_eq!(similarity("sort/100", "sort/1000"), 1. - 1. / 9.);
+    }
+
+    #[test]
+    fn check_normalize_name() {
+        fn normalize(name: &str) -> Result<Cow<'_, str>, InvalidName> {
+            normalize_name(name, 16)
+        }
+
+        assert_eq!(normalize("sort/100").unwrap(), "sort/100");
+        assert!(matches!(normalize("sort/100"), Ok(Cow::Borrowed(_))));
+        assert_eq!(normalize("  sort   vec\u{a0}100 ").unwrap(), "sort vec 100");
+        assert_eq!(normalize("").unwrap_err(), InvalidName::Empty);
+        assert_eq!(normalize(" \u{3000} ").unwrap_err(), InvalidName::Empty);
+        assert_eq!(
+            normalize("sort\n100").unwrap_err(),
+            InvalidName::ControlCharacter('\n')
+        );
+        assert_eq!(
+            normalize("sort\t100").unwrap_err(),
+            InvalidName::ControlCharacter('\t')
+        );
+        assert_eq!(
+            normalize("\u{7f}").unwrap_err(),
+            InvalidName::ControlCharacter('\u{7f}')
+        );
+
+        // Limit is in bytes and applies after normalization
+        assert_eq!(normalize("a".repeat(16).as_str()).unwrap().len(), 16);
+        assert_eq!(
+            normalize(&format!(" {} ", "a".repeat(16))).unwrap().len(),
+            16
+        );
+        assert_eq!(
+            normalize(&"é".repeat(9)).unwrap_err(),
+            InvalidName::TooLong { len: 18, max: 16 }
+        );
+
+        // Truncation for error messages never splits a character
+        assert_eq!(truncate_name("sort", 16), "sort");
+        assert_eq!(truncate_name(&"é".repeat(9), 5), "éé…");
+        assert_eq!(truncate_name("日本語", 4), "日…");
+        assert_eq!(truncate_name("日本語", 2), "…");
+    }
+}
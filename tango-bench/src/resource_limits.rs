@@ -0,0 +1,200 @@
+//! Resource limits applied to the child processes measuring the tests (`compare --resource-limits`)
+//!
+//! Each test is measured in its own child process, which applies the limits before the executable to compare
+//! against is loaded. A runaway benchmark (eg. one allocating without bound or spinning forever) is stopped and
+//! reported as failed, while the harness goes on with the other tests. It is not an isolation: the child still has
+//! the same access to the filesystem and the network as the harness.
+//!
+//! Core dumps are disabled and the limits are applied with `setrlimit()` on Unix. Other platforms have no
+//! equivalent of the limits, so they are refused.
+
+use serde::{Deserialize, Serialize};
+use std::{io, time::Duration};
+use thiserror::Error;
+
+/// Exit code of a process terminated because it exceeded the CPU time limit
+pub(crate) const EXIT_CPU_LIMIT: i32 = 152;
+
+/// Resources the child process measuring a test may use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ResourceLimits {
+    /// Address space in bytes (`--memory-limit`)
+    pub(crate) memory: Option<u64>,
+
+    /// CPU time of all the threads (`--cpu-limit`)
+    pub(crate) cpu: Option<Duration>,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum LimitError {
+    #[cfg(not(unix))]
+    #[error("Resource limits are not supported on this platform")]
+    UnsupportedPlatform,
+
+    #[error("Unable to limit resources ({0})")]
+    Setup(&'static str, #[source] io::Error),
+}
+
+/// Limits the resources of the current process
+///
+/// Limits can't be raised back, so it should be called once before loading the executables.
+pub(crate) fn apply(limits: ResourceLimits) -> Result<(), LimitError> {
+    platform::apply(limits)
+}
+
+/// Fails if the limits can't be applied on this platform, so the run is refused before any test is measured
+pub(crate) fn check_supported() -> Result<(), LimitError> {
+    platform::check_supported()
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{LimitError, ResourceLimits, EXIT_CPU_LIMIT};
+    use std::io;
+
+    pub(super) fn check_supported() -> Result<(), LimitError> {
+        Ok(())
+    }
+
+    pub(super) fn apply(limits: ResourceLimits) -> Result<(), LimitError> {
+        set_limit(libc::RLIMIT_CORE, 0, 0).map_err(|e| LimitError::Setup("core dumps", e))?;
+        if let Some(memory) = limits.memory {
+            set_limit(libc::RLIMIT_AS, memory, memory)
+                .map_err(|e| LimitError::Setup("memory", e))?;
+        }
+        if let Some(cpu) = limits.cpu {
+            exit_on_cpu_limit();
+            // CPU time is limited in whole seconds. The soft limit raises SIGXCPU reported by the handler, the hard
+            // one kills the process if the signal is ignored
+            let seconds = cpu.as_secs().max(1);
+            set_limit(libc::RLIMIT_CPU, seconds, seconds + 1)
+                .map_err(|e| LimitError::Setup("CPU", e))?;
+        }
+        Ok(())
+    }
+
+    /// Lowers both soft and hard limits, so they can't be raised back by the loaded code
+    ///
+    /// A hard limit lower than the requested one is kept.
+    fn set_limit(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let hard = (hard as libc::rlim_t).min(limit.rlim_max);
+        let limit = libc::rlimit {
+            rlim_cur: (soft as libc::rlim_t).min(hard),
+            rlim_max: hard,
+        };
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    type Resource = libc::__rlimit_resource_t;
+
+    #[cfg(not(target_os = "linux"))]
+    type Resource = libc::c_int;
+
+    /// Reports the exceeded CPU time limit instead of dying silently from `SIGXCPU`
+    fn exit_on_cpu_limit() {
+        extern "C" fn on_sigxcpu(_: libc::c_int) {
+            const MESSAGE: &[u8] = b"[ERROR] CPU time limit is exceeded (--cpu-limit)\n";
+            // Only async-signal-safe functions may be called here
+            unsafe {
+                libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr().cast(), MESSAGE.len());
+                libc::_exit(EXIT_CPU_LIMIT);
+            }
+        }
+        unsafe {
+            libc::signal(
+                libc::SIGXCPU,
+                on_sigxcpu as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use super::{LimitError, ResourceLimits};
+
+    pub(super) fn check_supported() -> Result<(), LimitError> {
+        Err(LimitError::UnsupportedPlatform)
+    }
+
+    pub(super) fn apply(_limits: ResourceLimits) -> Result<(), LimitError> {
+        Err(LimitError::UnsupportedPlatform)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::{env, hint::black_box, process::Command};
+
+    /// Runs a given test in a child process (the test binary itself) where `body` is executed with the limits
+    ///
+    /// Limits can't be lifted, so they are never applied to the process running the tests.
+    fn run_limited(
+        test_name: &str,
+        limits: ResourceLimits,
+        body: impl FnOnce(),
+    ) -> std::process::Output {
+        const ENV_VAR: &str = "TANGO_LIMITS_CHILD";
+        if env::var_os(ENV_VAR).is_some() {
+            apply(limits).unwrap();
+            body();
+            std::process::exit(0);
+        }
+        Command::new(env::current_exe().unwrap())
+            .args(["--exact", test_name, "--nocapture"])
+            .env(ENV_VAR, "1")
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn check_memory_limit_contains_large_allocation() {
+        let limits = ResourceLimits {
+            memory: Some(1 << 30),
+            cpu: None,
+        };
+        let output = run_limited(
+            "resource_limits::tests::check_memory_limit_contains_large_allocation",
+            limits,
+            || {
+                let data = black_box(vec![1u8; 10 << 30]);
+                println!("allocated {} bytes", data.len());
+            },
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{}", stderr);
+        assert!(stderr.contains("memory allocation"), "{}", stderr);
+    }
+
+    #[test]
+    fn check_cpu_limit_contains_spinning() {
+        let limits = ResourceLimits {
+            memory: None,
+            cpu: Some(Duration::from_secs(1)),
+        };
+        let output = run_limited(
+            "resource_limits::tests::check_cpu_limit_contains_spinning",
+            limits,
+            || {
+                while black_box(true) {
+                    std::hint::spin_loop();
+                }
+            },
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(EXIT_CPU_LIMIT), "{}", stderr);
+        assert!(stderr.contains("CPU time limit"), "{}", stderr);
+    }
+}
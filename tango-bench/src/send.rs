@@ -0,0 +1,271 @@
+//! Benchmarks which can be constructed on one thread and moved to another
+//!
+//! Benchmarks are registered as `Box<dyn MeasureTarget>` which is not `Send`, so the functions and generators are
+//! free to capture `Rc`, `Cell` and alike. Constructors of this module mirror the ones of the crate root, but
+//! require the functions, the generators, their haystacks and needles to be `Send` and return
+//! `Box<dyn MeasureTarget + Send>`. The benchmarks can be built on a worker thread (eg. while reading configuration
+//! files) and moved to the thread running the harness:
+//!
+//! ```rust
+//! use std::thread;
+//! use tango_bench::{generators::RandomVec, send, BenchmarkMatrix, IntoBenchmarks};
+//!
+//! fn benchmarks() -> impl IntoBenchmarks {
+//!     let benchmarks = thread::spawn(|| {
+//!         let mut benchmarks = BenchmarkMatrix::with_params_send([100, 1_000], RandomVec::<u32>::new)
+//!             .add_function("max", |h, _| h.iter().max().copied())
+//!             .into_send_benchmarks();
+//!         benchmarks.push(send::benchmark_fn("sum", || (0..100u32).sum::<u32>()));
+//!         benchmarks
+//!     })
+//!     .join()
+//!     .unwrap();
+//!     send::into_benchmarks(benchmarks)
+//! }
+//! ```
+//!
+//! Wrappers of [`MeasureTargetExt`][crate::MeasureTargetExt] are not `Send`, so they are applied after the
+//! benchmarks are moved.
+
+use crate::{
+    BatchGenFunc, BenchmarkMatrix, FixtureFunc, Generator, InOutGenFunc, InputCursor,
+    IntoBenchmarks, MeasureTarget, MetricFunc, Resettable, ScratchReset, SetupFunc, Shared,
+    SimpleFunc, Unit,
+};
+use std::{fmt::Debug, iter};
+
+/// `Send` counterpart of [`benchmark_fn()`][crate::benchmark_fn()]
+pub fn benchmark_fn<O, F: Fn() -> O + Send + 'static>(
+    name: &'static str,
+    func: F,
+) -> Box<dyn MeasureTarget + Send> {
+    assert!(!name.is_empty());
+    Box::new(SimpleFunc { name, func })
+}
+
+/// `Send` counterpart of [`benchmark_fn_metric()`][crate::benchmark_fn_metric()]
+pub fn benchmark_fn_metric<O, F, M>(
+    name: &'static str,
+    func: F,
+    metric: M,
+    unit: Unit,
+) -> Box<dyn MeasureTarget + Send>
+where
+    F: Fn() -> O + Send + 'static,
+    M: Fn(&O) -> u64 + Send + 'static,
+{
+    assert!(!name.is_empty());
+    Box::new(MetricFunc {
+        name,
+        func,
+        metric,
+        unit,
+    })
+}
+
+/// `Send` counterpart of [`benchmark_fn_with_setup()`][crate::benchmark_fn_with_setup()]
+pub fn benchmark_fn_with_setup<S, O, I, F>(
+    name: &'static str,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget + Send>
+where
+    S: Send + 'static,
+    I: Fn() -> S + Send + 'static,
+    F: Fn(&mut S) -> O + Send + 'static,
+{
+    assert!(!name.is_empty());
+    Box::new(SetupFunc {
+        name,
+        setup,
+        func,
+        state: None,
+        include_setup: false,
+    })
+}
+
+/// `Send` counterpart of [`benchmark_fn_including_setup()`][crate::benchmark_fn_including_setup()]
+pub fn benchmark_fn_including_setup<S, O, I, F>(
+    name: &'static str,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget + Send>
+where
+    S: Send + 'static,
+    I: Fn() -> S + Send + 'static,
+    F: Fn(&mut S) -> O + Send + 'static,
+{
+    let mut target = benchmark_fn_with_setup(name, setup, func);
+    target.set_includes_setup(true);
+    target
+}
+
+/// `Send` counterpart of [`benchmark_fn_with_fixture()`][crate::benchmark_fn_with_fixture()]
+pub fn benchmark_fn_with_fixture<X, S, O, C, I, F>(
+    name: &'static str,
+    fixture: C,
+    setup: I,
+    func: F,
+) -> Box<dyn MeasureTarget + Send>
+where
+    X: Send + 'static,
+    S: Send + 'static,
+    C: Fn() -> X + Send + 'static,
+    I: Fn(&X) -> S + Send + 'static,
+    F: Fn(&X, &mut S) -> O + Send + 'static,
+{
+    assert!(!name.is_empty());
+    Box::new(FixtureFunc {
+        name,
+        fixture_fn: fixture,
+        setup,
+        func,
+        fixture: None,
+        state: None,
+        include_setup: false,
+    })
+}
+
+/// `Send` counterpart of [`benchmark_fn_batch()`][crate::benchmark_fn_batch()]
+pub fn benchmark_fn_batch<G, F, O>(
+    name: &str,
+    generator: G,
+    batch: usize,
+    f: F,
+) -> Box<dyn MeasureTarget + Send>
+where
+    G: Generator + Send + 'static,
+    G::Haystack: Send,
+    G::Needle: Send,
+    F: Fn(&G::Haystack, &[G::Needle]) -> O + Send + 'static,
+{
+    Box::new(BatchGenFunc::new(
+        name,
+        Shared::new(f),
+        InputCursor::exclusive(generator),
+        batch,
+    ))
+}
+
+/// `Send` counterpart of [`benchmark_fn_inout()`][crate::benchmark_fn_inout()]
+pub fn benchmark_fn_inout<G, F, S>(
+    name: &str,
+    generator: G,
+    reset: ScratchReset,
+    f: F,
+) -> Box<dyn MeasureTarget + Send>
+where
+    G: Generator + Send + 'static,
+    G::Haystack: Send,
+    G::Needle: Send,
+    S: Default + Resettable + Send + 'static,
+    F: Fn(&G::Haystack, &G::Needle, &mut S) + Send + 'static,
+{
+    Box::new(InOutGenFunc::new(
+        name,
+        Shared::new(f),
+        InputCursor::exclusive(generator),
+        reset,
+    ))
+}
+
+/// Matrix of `Send` benchmarks
+///
+/// Functions are added the same way as to the [`BenchmarkMatrix`] created by [`BenchmarkMatrix::new()`], but
+/// they are required to be `Send`.
+impl<G> BenchmarkMatrix<G, dyn MeasureTarget + Send>
+where
+    G: Generator + Send,
+    G::Haystack: Send,
+    G::Needle: Send,
+{
+    pub fn new_send(generator: G) -> Self {
+        Self::from_generators(iter::once(generator))
+    }
+
+    /// New matrix with generator created for a given set of parameters
+    pub fn with_params_send<P>(
+        params: impl IntoIterator<Item = P>,
+        generator: impl Fn(P) -> G,
+    ) -> Self {
+        Self::from_generators(params.into_iter().map(generator))
+    }
+
+    pub fn add_function<F, O>(mut self, name: &str, f: F) -> Self
+    where
+        G: 'static,
+        F: Fn(&G::Haystack, &G::Needle) -> O + Send + 'static,
+    {
+        let targets = self.gen_functions(name, f, None);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// See [`BenchmarkMatrix::add_function_verified()`]
+    pub fn add_function_verified<F, O>(mut self, name: &str, f: F) -> Self
+    where
+        G: 'static,
+        G::Haystack: Debug,
+        G::Needle: Debug,
+        F: Fn(&G::Haystack, &G::Needle) -> O + Send + 'static,
+        O: Debug,
+    {
+        let verify = crate::verify_outputs::<F, O, G>;
+        let targets = self.gen_functions(name, f, Some(verify));
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// See [`BenchmarkMatrix::add_batch_function()`]
+    ///
+    /// # Panics
+    /// Panics if `batch` is zero.
+    pub fn add_batch_function<F, O>(mut self, name: &str, batch: usize, f: F) -> Self
+    where
+        G: 'static,
+        F: Fn(&G::Haystack, &[G::Needle]) -> O + Send + 'static,
+    {
+        let targets = self.batch_functions(name, batch, f);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    /// See [`BenchmarkMatrix::add_inout_function()`]
+    pub fn add_inout_function<F, S>(mut self, name: &str, reset: ScratchReset, f: F) -> Self
+    where
+        G: 'static,
+        S: Default + Resettable + Send + 'static,
+        F: Fn(&G::Haystack, &G::Needle, &mut S) + Send + 'static,
+    {
+        let targets = self.inout_functions(name, reset, f);
+        self.functions
+            .extend(targets.into_iter().map(|t| Box::new(t) as _));
+        self
+    }
+
+    pub fn into_send_benchmarks(self) -> Vec<Box<dyn MeasureTarget + Send>> {
+        assert!(!self.functions.is_empty(), "No functions was given");
+        self.functions
+    }
+}
+
+impl<G> IntoBenchmarks for BenchmarkMatrix<G, dyn MeasureTarget + Send>
+where
+    G: Generator + Send,
+    G::Haystack: Send,
+    G::Needle: Send,
+{
+    fn into_benchmarks(self) -> Vec<Box<dyn MeasureTarget>> {
+        into_benchmarks(self.into_send_benchmarks())
+    }
+}
+
+/// Converts the `Send` benchmarks moved to the thread running the harness into the registered ones
+pub fn into_benchmarks(
+    benchmarks: impl IntoIterator<Item = Box<dyn MeasureTarget + Send>>,
+) -> Vec<Box<dyn MeasureTarget>> {
+    benchmarks.into_iter().map(|t| t as _).collect()
+}
@@ -0,0 +1,201 @@
+//! Splitting a suite between parallel executors
+//!
+//! `compare --shard 2/4` runs only the tests assigned to the second of four shards. By default a test is assigned by
+//! the hash of its name, so each executor selects its tests independently and adding or removing a test doesn't move
+//! the other ones between shards. `--shard-by-cost` balances the shards by the durations of the tests recorded in a
+//! run manifest instead: tests are packed greedily, the longest first, into the shard with the smallest total.
+//! Packing depends on the whole list of tests, so all the executors should be given the same manifest and filter.
+
+use crate::manifest::RunManifest;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/// Shard of a suite (eg. `2/4`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// Number of the shard starting from 1
+    pub index: usize,
+
+    /// Total number of shards
+    pub count: usize,
+}
+
+impl Shard {
+    /// Returns `None` unless `1 <= index <= count`
+    pub fn new(index: usize, count: usize) -> Option<Self> {
+        (1..=count)
+            .contains(&index)
+            .then_some(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Shard a test is assigned to by the hash of its name (starting from 1)
+pub fn hashed_shard(name: &str, count: usize) -> usize {
+    (stable_hash(name) % count as u64) as usize + 1
+}
+
+/// Assigns the tests to `count` shards balancing the total cost of each shard
+///
+/// Returns the shard of each test (starting from 1). Tests are assigned the most expensive first to the shard with
+/// the smallest total cost so far. Ties are broken by the name of the test and the number of the shard, so the
+/// assignment doesn't depend on the order of the tests.
+pub fn pack_by_cost(tests: &[(&str, Duration)], count: usize) -> Vec<usize> {
+    let mut order = (0..tests.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (tests[a], tests[b]);
+        b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))
+    });
+    let mut totals = vec![Duration::ZERO; count];
+    let mut shards = vec![0; tests.len()];
+    for i in order {
+        let (lightest, total) = totals
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, total)| **total)
+            .expect("at least one shard");
+        *total += tests[i].1;
+        shards[i] = lightest + 1;
+    }
+    shards
+}
+
+/// Tests of a given shard
+///
+/// With `costs` given the shards are balanced by the durations of the tests recorded in the manifest (see
+/// [`pack_by_cost()`]). Tests missing in the manifest are assumed to take the median duration of the recorded ones.
+pub fn select<'a>(names: &[&'a str], shard: Shard, costs: Option<&RunManifest>) -> Vec<&'a str> {
+    let Some(manifest) = costs else {
+        return names
+            .iter()
+            .copied()
+            .filter(|name| hashed_shard(name, shard.count) == shard.index)
+            .collect();
+    };
+    let recorded = |name: &str| manifest.test(name).and_then(|t| t.duration);
+    let mut known = names
+        .iter()
+        .filter_map(|name| recorded(name))
+        .collect::<Vec<_>>();
+    known.sort_unstable();
+    let median = known
+        .get(known.len() / 2)
+        .copied()
+        .unwrap_or(Duration::from_secs(1));
+    let tests = names
+        .iter()
+        .map(|&name| (name, recorded(name).unwrap_or(median)))
+        .collect::<Vec<_>>();
+    let shards = pack_by_cost(&tests, shard.count);
+    names
+        .iter()
+        .zip(shards)
+        .filter(|(_, s)| *s == shard.index)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// Hash of a test name which is the same on all platforms and versions of Rust (FNV-1a followed by the finalizer of
+/// SplitMix64, so all the bits depend on the whole name)
+fn stable_hash(name: &str) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::TestSchedule, MeasurementSettings};
+
+    fn registry(size: usize) -> Vec<String> {
+        (0..size).map(|i| format!("sort/u64/{}", i)).collect()
+    }
+
+    #[test]
+    fn check_hashed_shards() {
+        let names = registry(400);
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let shards = (1..=4)
+            .map(|i| select(&names, Shard::new(i, 4).unwrap(), None))
+            .collect::<Vec<_>>();
+
+        // Each test is selected by exactly one shard and shards are of comparable size
+        let mut all = shards.concat();
+        all.sort_unstable();
+        let mut expected = names.clone();
+        expected.sort_unstable();
+        assert_eq!(all, expected);
+        for shard in &shards {
+            assert!((70..130).contains(&shard.len()), "{}", shard.len());
+        }
+
+        // Adding a test doesn't move the other ones
+        let mut extended = names.clone();
+        extended.insert(0, "sort/u64/new");
+        let second = select(&extended, Shard::new(2, 4).unwrap(), None);
+        let moved = second.iter().filter(|name| !shards[1].contains(name));
+        assert!(moved.copied().all(|name| name == "sort/u64/new"));
+        assert!(shards[1].iter().all(|name| second.contains(name)));
+
+        assert_eq!(hashed_shard("sort/u64/7", 1), 1);
+        // Assignment is stable between platforms and releases
+        assert_eq!(stable_hash(""), 0xf52a15e9a9b5e89b);
+    }
+
+    #[test]
+    fn check_pack_by_cost() {
+        let ms = Duration::from_millis;
+        let tests = [
+            ("a", ms(70)),
+            ("b", ms(50)),
+            ("c", ms(40)),
+            ("d", ms(30)),
+            ("e", ms(10)),
+            ("f", ms(10)),
+        ];
+        assert_eq!(pack_by_cost(&tests, 2), [1, 2, 2, 1, 2, 1]);
+        assert_eq!(pack_by_cost(&tests, 1), [1; 6]);
+        // Shards beyond the number of tests are left empty
+        assert_eq!(pack_by_cost(&tests[..2], 3), [1, 2]);
+
+        // Assignment doesn't depend on the order of the tests
+        let mut reversed = tests;
+        reversed.reverse();
+        let mut shards = pack_by_cost(&reversed, 2);
+        shards.reverse();
+        assert_eq!(shards, [1, 2, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn check_select_by_cost() {
+        let mut manifest = RunManifest::new(0, MeasurementSettings::default());
+        for (name, ms) in [("slow", 300), ("fast/1", 100), ("fast/2", 100)] {
+            manifest.tests.push(TestSchedule {
+                name: name.into(),
+                duration: Some(Duration::from_millis(ms)),
+                ..TestSchedule::default()
+            });
+        }
+        // Unknown test takes the median duration
+        let names = ["fast/1", "fast/2", "slow", "unknown"];
+        let first = select(&names, Shard::new(1, 2).unwrap(), Some(&manifest));
+        let second = select(&names, Shard::new(2, 2).unwrap(), Some(&manifest));
+        assert_eq!(first, ["slow"]);
+        assert_eq!(second, ["fast/1", "fast/2", "unknown"]);
+
+        assert_eq!(Shard::new(0, 2), None);
+        assert_eq!(Shard::new(3, 2), None);
+        assert_eq!(Shard::new(2, 2).unwrap().to_string(), "2/2");
+    }
+}
@@ -0,0 +1,892 @@
+//! Statistical tests used to decide whether the difference between two functions is meaningful
+
+use crate::{MeasurementSettings, Summary};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use tango_core::{
+    running::nearest_rank,
+    significance::{critical_t_score, decide, p_value, t_p_value, z_score},
+};
+
+pub use tango_core::{
+    running::{StreamingQuantile, StreamingStats},
+    significance::{
+        confidence_interval, critical_z_score, verdict, ConfidenceInterval, Verdict, Z_SCORE_99,
+    },
+};
+
+/// Latest version of the statistics, the default of [`MeasurementSettings::stats_version`]
+///
+/// Changes of the statistics able to flip verdicts are introduced under a new version, while the previous versions
+/// are kept reproducing the verdicts they used to make.
+pub const STATS_VERSION: u32 = 2;
+
+/// Version of the statistics of the releases before the statistics were versioned (z-test)
+pub const LEGACY_STATS_VERSION: u32 = 1;
+
+/// Version the settings and the results written before the statistics were versioned are read as
+pub(crate) fn legacy_stats_version() -> u32 {
+    LEGACY_STATS_VERSION
+}
+
+/// Paired differences of a test along with the settings they are analyzed with
+#[derive(Clone, Copy, Debug)]
+pub struct SignificanceInput<'a> {
+    /// Differences between candidate and baseline normalized by the number of iterations (outliers already removed)
+    pub diffs: &'a [f64],
+
+    /// Mean of the baseline measurements the relative difference is calculated against
+    pub baseline_mean: f64,
+
+    /// Settings the decision depends on (significance level, minimal effect and statistics version)
+    pub settings: &'a MeasurementSettings,
+}
+
+/// Statistical method the decision was made with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Estimator {
+    /// z-test of the mean of paired differences
+    ZTest,
+
+    /// Student's t-test of the mean of paired differences (statistics version 2)
+    TTest,
+
+    /// All the differences are the same (eg. deterministic metrics), so the difference is known exactly
+    Exact,
+}
+
+/// Decision on the difference between candidate and baseline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignificanceOutcome {
+    /// Relative difference between candidate and baseline (in percents)
+    pub pct: f64,
+
+    /// Two-sided p-value of the difference being 0
+    pub p_value: f64,
+
+    /// Confidence interval of the relative difference
+    pub ci: ConfidenceInterval,
+
+    pub verdict: Verdict,
+
+    /// Method the p-value and the confidence interval are calculated with
+    pub estimator_used: Estimator,
+}
+
+/// Decides whether the difference between candidate and baseline is significant
+///
+/// This is the same decision `compare` command makes after filtering outliers, so it can be used to analyze dumped
+/// data under different settings. Returns `None` if there are no differences.
+pub fn significance(input: &SignificanceInput) -> Option<SignificanceOutcome> {
+    let diff = Summary::from(input.diffs)?;
+    Some(summary_significance(
+        &diff,
+        input.baseline_mean,
+        input.settings,
+    ))
+}
+
+/// Same as [`significance()`], but for the differences already summarized (eg. by [`StreamingStats`])
+pub fn summary_significance(
+    diff: &Summary<f64>,
+    baseline_mean: f64,
+    settings: &MeasurementSettings,
+) -> SignificanceOutcome {
+    // Both tests divide the mean difference by its standard error, only the distribution of the score differs
+    let (critical, p_value, estimator) = match settings.stats_version {
+        0..=LEGACY_STATS_VERSION => {
+            let critical_z = settings.significance.map_or(Z_SCORE_99, critical_z_score);
+            (critical_z, p_value(z_score(diff)), Estimator::ZTest)
+        }
+        _ => {
+            let df = diff.n.saturating_sub(1).max(1) as f64;
+            let critical_t = critical_t_score(settings.significance.unwrap_or(0.01), df);
+            (critical_t, t_p_value(z_score(diff), df), Estimator::TTest)
+        }
+    };
+    let (verdict, ci) = decide(baseline_mean, diff, settings.min_effect, critical);
+    let (p_value, estimator_used) = if diff.variance > 0. {
+        (p_value, estimator)
+    } else {
+        let p_value = if diff.mean == 0. { 1. } else { 0. };
+        (p_value, Estimator::Exact)
+    };
+    SignificanceOutcome {
+        pct: diff.mean / baseline_mean * 100.,
+        p_value,
+        ci,
+        verdict,
+        estimator_used,
+    }
+}
+
+/// Difference between the mean paired differences of the samples taken in each order (see [`order_bias()`])
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderBias {
+    /// Mean difference of candidate-first samples minus the one of baseline-first samples
+    pub bias: f64,
+
+    /// Bias relative to the baseline mean (in percents)
+    pub pct: f64,
+
+    /// Bias is statistically significant (z-test at 99% significance level)
+    pub significant: bool,
+}
+
+/// Quantifies the effect of the order functions are called in
+///
+/// `candidate_first` and `baseline_first` are summaries of paired differences (candidate - baseline) of the samples
+/// taken in each order. Systematic advantage of the function called first (eg. warm caches) adds to the differences
+/// of one order and subtracts from the other, so without order effects both have the same mean. Means are compared
+/// using two-sample z-test.
+pub fn order_bias(
+    candidate_first: &Summary<f64>,
+    baseline_first: &Summary<f64>,
+    baseline_mean: f64,
+) -> OrderBias {
+    let bias = candidate_first.mean - baseline_first.mean;
+    let std_err = (candidate_first.variance / candidate_first.n as f64
+        + baseline_first.variance / baseline_first.n as f64)
+        .sqrt();
+    let significant = if std_err > 0. {
+        (bias / std_err).abs() >= Z_SCORE_99
+    } else {
+        bias != 0.
+    };
+    OrderBias {
+        bias,
+        pct: bias / baseline_mean * 100.,
+        significant,
+    }
+}
+
+/// Batch sizes (iterations per sample) per-iteration values are measured at by [`batch_curve()`]
+pub const BATCH_SIZES: [usize; 6] = [1, 2, 5, 10, 20, 50];
+
+/// Per-iteration value of a function at different batch sizes along with the fitted trend
+///
+/// Shows whether the per-iteration value depends on the number of iterations in a sample (eg. a warm-up amortized
+/// by larger batches). The trend is a least squares fit of the per-iteration value over the logarithm of the batch
+/// size.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchCurve {
+    /// Batch sizes and median per-iteration values measured at them
+    pub points: Vec<(usize, f64)>,
+
+    /// Change of the fitted per-iteration value per unit of the natural logarithm of the batch size
+    pub slope: f64,
+
+    /// Fitted per-iteration value at the batch of a single iteration
+    pub intercept: f64,
+}
+
+impl BatchCurve {
+    /// Returns `None` if there are less than two different batch sizes
+    pub fn fit(points: Vec<(usize, f64)>) -> Option<Self> {
+        let n = points.len() as f64;
+        let xs = points.iter().map(|&(size, _)| (size as f64).ln());
+        let x_mean = xs.clone().sum::<f64>() / n;
+        let y_mean = points.iter().map(|&(_, v)| v).sum::<f64>() / n;
+        let (mut covariance, mut variance) = (0., 0.);
+        for (x, &(_, y)) in xs.zip(&points) {
+            covariance += (x - x_mean) * (y - y_mean);
+            variance += (x - x_mean) * (x - x_mean);
+        }
+        if variance == 0. {
+            return None;
+        }
+        let slope = covariance / variance;
+        Some(Self {
+            intercept: y_mean - slope * x_mean,
+            slope,
+            points,
+        })
+    }
+
+    /// Fitted per-iteration value at a given batch size
+    pub fn fitted(&self, batch_size: usize) -> f64 {
+        self.intercept + self.slope * (batch_size as f64).ln()
+    }
+
+    /// Relative change of the fitted value from the smallest to the largest batch size (in percents)
+    pub fn change_pct(&self) -> f64 {
+        let sizes = self.points.iter().map(|&(size, _)| size);
+        let (smallest, largest) = (sizes.clone().min(), sizes.max());
+        match smallest.zip(largest) {
+            Some((smallest, largest)) => {
+                let first = self.fitted(smallest);
+                (self.fitted(largest) - first) / first * 100.
+            }
+            None => 0.,
+        }
+    }
+}
+
+/// Ranges of a given number of consecutive windows of (nearly) equal size covering `n` samples
+pub fn windows(n: usize, count: usize) -> impl Iterator<Item = Range<usize>> {
+    (0..count).map(move |i| i * n / count..(i + 1) * n / count)
+}
+
+/// Returns `true` if the values shift during the test more than they vary at any moment
+///
+/// Values are split into a given number of consecutive windows (see [`windows()`]). Drift is detected if the
+/// medians of the windows are spread wider than the interquartile range of a typical window (the median of the
+/// interquartile ranges of all the windows). Heating of the machine during a test is a typical cause of it.
+pub fn drift_detected(values: &[f64], count: usize) -> bool {
+    if count < 2 || values.len() < count {
+        return false;
+    }
+    let mut medians = Vec::with_capacity(count);
+    let mut ranges = Vec::with_capacity(count);
+    for range in windows(values.len(), count) {
+        let mut window = values[range].to_vec();
+        window.sort_unstable_by(f64::total_cmp);
+        let n = window.len();
+        medians.push(window[n / 2]);
+        ranges.push(window[n * 3 / 4] - window[n / 4]);
+    }
+    ranges.sort_unstable_by(f64::total_cmp);
+    let typical = ranges[count / 2];
+    // Values quantized to a few levels (eg. by a coarse clock) have no spread within most of the windows, so any
+    // difference of the medians would be reported as a drift
+    if typical.is_nan() || typical <= 0. {
+        return false;
+    }
+    let lowest = medians.iter().copied().fold(f64::INFINITY, f64::min);
+    let highest = medians.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    highest - lowest > typical
+}
+
+/// Measures [`BatchCurve`] of a function
+///
+/// `measure(n)` returns the total value of `n` iterations. It is called `repeats` times with each of
+/// [`BATCH_SIZES`] in round-robin, so a drift of the machine state doesn't correlate with the batch size.
+pub fn batch_curve(mut measure: impl FnMut(usize) -> u64, repeats: usize) -> Option<BatchCurve> {
+    let mut values = vec![Vec::with_capacity(repeats); BATCH_SIZES.len()];
+    for _ in 0..repeats {
+        for (size, values) in BATCH_SIZES.iter().zip(&mut values) {
+            values.push(measure(*size) as f64 / *size as f64);
+        }
+    }
+    let points = BATCH_SIZES
+        .iter()
+        .zip(values)
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(&size, mut values)| {
+            values.sort_by(f64::total_cmp);
+            (size, values[values.len() / 2])
+        })
+        .collect();
+    BatchCurve::fit(points)
+}
+
+/// Percentiles of the slowest calls of a function (in nanoseconds)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tail {
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+impl Tail {
+    /// Returns `None` if there are no calls
+    pub fn of(calls: &mut [u64]) -> Option<Self> {
+        calls.sort_unstable();
+        Some(Self {
+            p99: nearest_rank(calls, 0.99)? as f64,
+            p999: nearest_rank(calls, 0.999)? as f64,
+            max: *calls.last()? as f64,
+        })
+    }
+}
+
+/// Tail latencies of both functions measured call by call (see [`crate::MeasurementSettings::per_call`])
+///
+/// Mean comparison hides rare slow calls (eg. hash collisions or reallocations), tail latencies reveal them.
+/// Each call includes the overhead of reading the timer, so the percentiles close to it are meaningless.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TailLatencies {
+    pub baseline: Tail,
+    pub candidate: Tail,
+
+    /// Number of calls of each function
+    pub calls: usize,
+
+    /// Time of reading the timer included in each call (see [`crate::timer_overhead()`])
+    pub timer_overhead: u64,
+}
+
+impl TailLatencies {
+    /// Returns `None` if there are no calls of one of the functions
+    pub fn new(baseline: &mut [u64], candidate: &mut [u64], timer_overhead: u64) -> Option<Self> {
+        Some(Self {
+            calls: baseline.len().min(candidate.len()),
+            baseline: Tail::of(baseline)?,
+            candidate: Tail::of(candidate)?,
+            timer_overhead,
+        })
+    }
+
+    /// Relative differences of p99, p99.9 and max between candidate and baseline (in percents)
+    pub fn deltas(&self) -> [f64; 3] {
+        let (b, c) = (self.baseline, self.candidate);
+        let pct = |b: f64, c: f64| if b > 0. { (c - b) / b * 100. } else { 0. };
+        [pct(b.p99, c.p99), pct(b.p999, c.p999), pct(b.max, c.max)]
+    }
+}
+
+/// Median and the upper percentiles of the per-iteration values of a sample series
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of the values, returns `None` if there are no values
+    pub fn of(values: &[f64]) -> Option<Self> {
+        let mut values = values.to_vec();
+        values.sort_unstable_by(f64::total_cmp);
+        Some(Self {
+            p50: nearest_rank(&values, 0.5)?,
+            p95: nearest_rank(&values, 0.95)?,
+            p99: nearest_rank(&values, 0.99)?,
+        })
+    }
+
+    /// Estimates of the percentiles of the values accumulated without retaining them
+    pub fn estimated(stats: &StreamingStats) -> Option<Self> {
+        let [p50, p95, p99] = stats.percentiles()?;
+        Some(Self { p50, p95, p99 })
+    }
+}
+
+/// Percentiles of baseline, candidate and their paired differences
+///
+/// Means hide the tails, so a candidate improving the average while regressing the worst samples is revealed by its
+/// percentiles. Percentiles of the differences are the percentiles of the paired differences themselves, not the
+/// differences of the percentiles of the functions.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SamplePercentiles {
+    pub baseline: Percentiles,
+    pub candidate: Percentiles,
+    pub diff: Percentiles,
+}
+
+impl SamplePercentiles {
+    /// Returns `None` if one of the series is empty
+    pub fn of(baseline: &[f64], candidate: &[f64], diff: &[f64]) -> Option<Self> {
+        Some(Self {
+            baseline: Percentiles::of(baseline)?,
+            candidate: Percentiles::of(candidate)?,
+            diff: Percentiles::of(diff)?,
+        })
+    }
+}
+
+/// Variance of the differences of a test split into the measurement overhead and the workload (see
+/// [`crate::MeasurementSettings::null_interval`])
+///
+/// Pairs of samples of an empty function taken the same way as the samples of the test make a null distribution
+/// of the differences. Its variance is the noise of the timer and the sampling loop, the rest of the variance of
+/// the differences is attributed to the workload. If the overhead dominates, the comparison says more about the
+/// harness than about the functions.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OverheadVariance {
+    /// Number of pairs of samples of the empty function
+    pub samples: usize,
+
+    /// Per-iteration value of the empty function, the overhead included in every iteration of the test
+    pub overhead: f64,
+
+    /// Variance of the per-iteration differences of the empty function
+    pub null_variance: f64,
+
+    /// Variance of the differences of the test in excess of the null variance
+    pub excess_variance: f64,
+
+    /// Fraction of the variance of the differences explained by the null variance (from 0 to 1)
+    pub overhead_fraction: f64,
+}
+
+impl OverheadVariance {
+    pub fn new(diff: &Summary<f64>, null: &Summary<f64>, overhead: f64) -> Self {
+        let overhead_fraction = if diff.variance > 0. {
+            (null.variance / diff.variance).min(1.)
+        } else if null.variance > 0. {
+            1.
+        } else {
+            0.
+        };
+        Self {
+            samples: null.n,
+            overhead,
+            null_variance: null.variance,
+            excess_variance: (diff.variance - null.variance).max(0.),
+            overhead_fraction,
+        }
+    }
+}
+
+/// Uniform random subset of a fixed size of a series of unknown length (reservoir sampling)
+///
+/// Every value of the series ends up in the subset with the same probability, so quantiles of the subset estimate
+/// the quantiles of the whole series. Uses Algorithm R: once the reservoir is full, `i`-th value replaces a random
+/// item with probability `capacity / i`. The subset depends only on the series and the seed.
+#[derive(Clone, Debug)]
+pub struct Reservoir<T> {
+    capacity: usize,
+    items: Vec<T>,
+
+    /// Number of values added so far
+    seen: usize,
+    rng: SmallRng,
+}
+
+impl<T> Reservoir<T> {
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        assert!(capacity > 0, "reservoir capacity should be positive");
+        Self {
+            capacity,
+            items: Vec::with_capacity(capacity),
+            seen: 0,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn add(&mut self, value: T) {
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(value);
+        } else {
+            let idx = self.rng.gen_range(0..self.seen);
+            if idx < self.capacity {
+                self.items[idx] = value;
+            }
+        }
+    }
+
+    /// Values retained in the reservoir (all the values if there were no more than the capacity)
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Number of values added to the reservoir
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(n: usize, mean: f64, std_dev: f64) -> Summary<f64> {
+        Summary {
+            n,
+            min: mean - std_dev,
+            max: mean + std_dev,
+            mean,
+            variance: std_dev * std_dev,
+        }
+    }
+
+    #[test]
+    fn check_tail_latencies() {
+        let mut baseline = (1..=1000).collect::<Vec<u64>>();
+        let mut candidate = baseline.iter().map(|v| v * 2).rev().collect::<Vec<u64>>();
+        let tail = TailLatencies::new(&mut baseline, &mut candidate, 5).unwrap();
+        assert_eq!(tail.calls, 1000);
+        assert_eq!(
+            tail.baseline,
+            Tail {
+                p99: 990.,
+                p999: 999.,
+                max: 1000.
+            }
+        );
+        assert_eq!(tail.candidate.p99, 1980.);
+        assert_eq!(tail.deltas(), [100., 100., 100.]);
+        assert_eq!(TailLatencies::new(&mut [], &mut candidate, 5), None);
+    }
+
+    #[test]
+    fn check_percentiles() {
+        // Uniform distribution of 1..=1000 in shuffled order
+        let values = (0..1000)
+            .map(|i| f64::from((i * 7919) % 1000 + 1))
+            .collect::<Vec<_>>();
+        let expected = Percentiles {
+            p50: 500.,
+            p95: 950.,
+            p99: 990.,
+        };
+        assert_eq!(Percentiles::of(&values), Some(expected));
+        assert_eq!(Percentiles::of(&[]), None);
+
+        let single = Percentiles::of(&[3.]).unwrap();
+        assert_eq!([single.p50, single.p95, single.p99], [3.; 3]);
+
+        let diff = values.iter().map(|v| v - 500.).collect::<Vec<_>>();
+        let percentiles = SamplePercentiles::of(&values, &values, &diff).unwrap();
+        assert_eq!(percentiles.diff.p99, 490.);
+        assert_eq!(SamplePercentiles::of(&values, &[], &diff), None);
+    }
+
+    #[test]
+    fn check_overhead_variance() {
+        let overhead = OverheadVariance::new(&summary(100, 0., 10.), &summary(50, 0., 5.), 2.);
+        assert_eq!(overhead.samples, 50);
+        assert_eq!(overhead.null_variance, 25.);
+        assert_eq!(overhead.excess_variance, 75.);
+        assert_eq!(overhead.overhead_fraction, 0.25);
+
+        // Null distribution may be wider than the differences by chance
+        let overhead = OverheadVariance::new(&summary(100, 0., 4.), &summary(50, 0., 5.), 2.);
+        assert_eq!(overhead.excess_variance, 0.);
+        assert_eq!(overhead.overhead_fraction, 1.);
+
+        let overhead = OverheadVariance::new(&summary(100, 0., 0.), &summary(50, 0., 0.), 2.);
+        assert_eq!(overhead.overhead_fraction, 0.);
+    }
+
+    #[test]
+    fn check_drift_detection() {
+        let ranges = windows(25, 4).collect::<Vec<_>>();
+        assert_eq!(ranges, [0..6, 6..12, 12..18, 18..25]);
+
+        // Noise without a trend
+        let noise = (0..1000).map(|i| (i * 37 % 101) as f64).collect::<Vec<_>>();
+        assert!(!drift_detected(&noise, 10));
+        // Measurements become 20% slower after a third of the test
+        let step = (0..1000)
+            .map(|i| (i % 5) as f64 + if i < 300 { 100. } else { 120. })
+            .collect::<Vec<_>>();
+        assert!(drift_detected(&step, 10));
+        // Slow linear warming
+        let ramp = noise
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v + i as f64 / 5.)
+            .collect::<Vec<_>>();
+        assert!(drift_detected(&ramp, 10));
+
+        assert!(!drift_detected(&step, 1));
+        assert!(!drift_detected(&step[..5], 10));
+
+        // Windows without spread don't turn a difference of a clock tick into a drift
+        let ticks = (0..1000)
+            .map(|i| if i < 500 { 100. } else { 101. })
+            .collect::<Vec<_>>();
+        assert!(!drift_detected(&ticks, 10));
+        assert!(!drift_detected(&[f64::NAN; 100], 10));
+    }
+
+    #[test]
+    fn check_verdicts() {
+        let baseline = summary(100, 1000., 10.);
+
+        // CI: [-0.26%, +0.26%]
+        let (verdict, ci) = verdict(&baseline, &summary(100, 0., 10.), 0.5);
+        assert_eq!(verdict, Verdict::Equivalent);
+        assert!((ci.lower + 0.26).abs() < 1e-9 && (ci.upper - 0.26).abs() < 1e-9);
+
+        // CI: [-8.1%, +9.1%]
+        let (verdict, _) = super::verdict(&baseline, &summary(100, 5., 330.), 0.5);
+        assert_eq!(verdict, Verdict::Inconclusive);
+
+        // CI: [+1.74%, +2.26%]
+        let (verdict, _) = super::verdict(&baseline, &summary(100, 20., 10.), 0.5);
+        assert_eq!(verdict, Verdict::Different);
+
+        // Significant, but smaller than minimal effect
+        let (verdict, _) = super::verdict(&baseline, &summary(100, 2., 1.), 0.5);
+        assert_eq!(verdict, Verdict::Equivalent);
+
+        // Significant and crossing the equivalence bound, but still smaller than minimal effect
+        let (verdict, _) = super::verdict(&baseline, &summary(100, 4., 5.), 0.5);
+        assert_eq!(verdict, Verdict::Inconclusive);
+    }
+
+    #[test]
+    fn check_significance() {
+        let settings = MeasurementSettings {
+            stats_version: LEGACY_STATS_VERSION,
+            ..Default::default()
+        };
+        let significance = |diffs: &[f64]| {
+            let input = SignificanceInput {
+                diffs,
+                baseline_mean: 1000.,
+                settings: &settings,
+            };
+            significance(&input).unwrap()
+        };
+
+        let outcome = significance(&[10., 30., 20., 10., 30., 20.]);
+        assert_eq!(outcome.verdict, Verdict::Different);
+        assert_eq!(outcome.estimator_used, Estimator::ZTest);
+        assert!((outcome.pct - 2.).abs() < 1e-9);
+        assert!(outcome.p_value < 0.01);
+
+        let outcome = significance(&[-10., 10., -20., 20., 0.]);
+        assert_eq!(outcome.verdict, Verdict::Inconclusive);
+        assert!((outcome.p_value - 1.).abs() < 1e-6);
+
+        let outcome = significance(&[3., 3., 3.]);
+        assert_eq!(outcome.verdict, Verdict::Equivalent);
+        assert_eq!(outcome.estimator_used, Estimator::Exact);
+        assert_eq!(outcome.p_value, 0.);
+
+        // z = 2.5 is significant at 5% level, but not at the default 1% one
+        let outcome = significance(&[10., 30., 0., 20., 40., 0.]);
+        assert_eq!(outcome.verdict, Verdict::Inconclusive);
+        let lenient = MeasurementSettings {
+            significance: Some(0.05),
+            ..settings
+        };
+        let input = SignificanceInput {
+            diffs: &[10., 30., 0., 20., 40., 0.],
+            baseline_mean: 1000.,
+            settings: &lenient,
+        };
+        let outcome = super::significance(&input).unwrap();
+        assert_eq!(outcome.verdict, Verdict::Different);
+        assert!(outcome.ci.lower > 0., "{:?}", outcome.ci);
+
+        assert!(super::significance(&SignificanceInput {
+            diffs: &[],
+            baseline_mean: 1000.,
+            settings: &settings,
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn check_significance_versions() {
+        let outcome = |diffs: &[f64], stats_version| {
+            let settings = MeasurementSettings {
+                stats_version,
+                ..Default::default()
+            };
+            let input = SignificanceInput {
+                diffs,
+                baseline_mean: 1000.,
+                settings: &settings,
+            };
+            significance(&input).unwrap()
+        };
+
+        // t = 3.55 with 5 degrees of freedom: significant for the z-test, but not for the t-test (critical t is 4.03)
+        let few = [10., 30., 5., 25., 40., 10.];
+        let legacy = outcome(&few, LEGACY_STATS_VERSION);
+        assert_eq!(legacy.verdict, Verdict::Different);
+        assert_eq!(legacy.estimator_used, Estimator::ZTest);
+        let current = outcome(&few, STATS_VERSION);
+        assert_eq!(current.verdict, Verdict::Inconclusive);
+        assert_eq!(current.estimator_used, Estimator::TTest);
+        assert!(current.p_value > legacy.p_value);
+        assert!(current.ci.lower < legacy.ci.lower);
+        assert_eq!(current.pct, legacy.pct);
+
+        // Both tests agree when there are many samples
+        let many = few.repeat(20);
+        for version in [LEGACY_STATS_VERSION, STATS_VERSION] {
+            assert_eq!(outcome(&many, version).verdict, Verdict::Different);
+        }
+        assert_eq!(MeasurementSettings::default().stats_version, STATS_VERSION);
+    }
+
+    #[test]
+    fn check_critical_z_score() {
+        assert!((critical_z_score(0.01) - 2.576).abs() < 1e-3);
+        assert!((critical_z_score(0.05) - 1.960).abs() < 1e-3);
+        assert!((critical_z_score(0.001) - 3.291).abs() < 1e-3);
+        assert!(critical_z_score(1.) < 1e-6);
+    }
+
+    fn exact_quantile(values: &[f64], q: f64) -> f64 {
+        let mut values = values.to_vec();
+        values.sort_unstable_by(f64::total_cmp);
+        nearest_rank(&values, q).unwrap()
+    }
+
+    #[test]
+    fn check_streaming_quantile_accuracy() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let uniform = (0..100_000).map(|_| rng.gen::<f64>()).collect::<Vec<_>>();
+        let normal = (0..100_000)
+            .map(|_| (0..12).map(|_| rng.gen::<f64>()).sum::<f64>() - 6.)
+            .collect::<Vec<_>>();
+        let exponential = (0..100_000)
+            .map(|_| -(1. - rng.gen::<f64>()).ln())
+            .collect::<Vec<_>>();
+        // Sorted input is the worst case for marker adjustments
+        let ascending = (0..100_000).map(f64::from).collect::<Vec<_>>();
+
+        for (name, values) in [
+            ("uniform", uniform),
+            ("normal", normal),
+            ("exponential", exponential),
+            ("ascending", ascending),
+        ] {
+            let exact_range = exact_quantile(&values, 0.999) - exact_quantile(&values, 0.001);
+            for q in [0.5, 0.95, 0.99] {
+                let mut estimator = StreamingQuantile::new(q);
+                for &value in &values {
+                    estimator.add(value);
+                }
+                let estimate = estimator.quantile().unwrap();
+                let exact = exact_quantile(&values, q);
+                let error = (estimate - exact).abs() / exact_range;
+                assert!(
+                    error < 0.01,
+                    "{} p{}: {} (exact: {})",
+                    name,
+                    q * 100.,
+                    estimate,
+                    exact
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn check_reservoir_quantile_accuracy() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let exponential = (0..200_000)
+            .map(|_| -(1. - rng.gen::<f64>()).ln())
+            .collect::<Vec<_>>();
+        let ascending = (0..200_000).map(f64::from).collect::<Vec<_>>();
+
+        for (name, values) in [("exponential", exponential), ("ascending", ascending)] {
+            let mut reservoir = Reservoir::new(10_000, 42);
+            for &value in &values {
+                reservoir.add(value);
+            }
+            assert_eq!(reservoir.seen(), values.len());
+            assert_eq!(reservoir.items().len(), 10_000);
+
+            let exact_range = exact_quantile(&values, 0.999) - exact_quantile(&values, 0.001);
+            for q in [0.25, 0.5, 0.75, 0.95] {
+                let estimate = exact_quantile(reservoir.items(), q);
+                let exact = exact_quantile(&values, q);
+                let error = (estimate - exact).abs() / exact_range;
+                assert!(
+                    error < 0.02,
+                    "{} p{}: {} (exact: {})",
+                    name,
+                    q * 100.,
+                    estimate,
+                    exact
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn check_reservoir_is_deterministic() {
+        let fill = |seed| {
+            let mut reservoir = Reservoir::new(100, seed);
+            (0..10_000).for_each(|i| reservoir.add(i));
+            reservoir.into_items()
+        };
+        assert_eq!(fill(1), fill(1));
+        assert_ne!(fill(1), fill(2));
+
+        // Short series is retained as is
+        let mut reservoir = Reservoir::new(100, 1);
+        (0..10).for_each(|i| reservoir.add(i));
+        assert_eq!(reservoir.items(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn check_streaming_quantile_of_few_values() {
+        let mut estimator = StreamingQuantile::new(0.5);
+        assert_eq!(estimator.quantile(), None);
+        for value in [5., 1., 3.] {
+            estimator.add(value);
+        }
+        assert_eq!(estimator.quantile(), Some(3.));
+    }
+
+    #[test]
+    fn check_streaming_stats() {
+        let values = (1..=1000).map(f64::from).collect::<Vec<_>>();
+        let mut stats = StreamingStats::default();
+        assert!(stats.summary().is_none());
+        for &value in &values {
+            stats.add(value);
+        }
+        assert_eq!(stats.summary(), Summary::from(&values));
+        let [p50, p95, p99] = stats.percentiles().unwrap();
+        assert!((p50 - 500.).abs() < 5., "{}", p50);
+        assert!((p95 - 950.).abs() < 5., "{}", p95);
+        assert!((p99 - 990.).abs() < 5., "{}", p99);
+    }
+
+    #[test]
+    fn check_confidence_interval_for_negative_baseline() {
+        let ci = confidence_interval(&summary(100, -1000., 10.), &summary(100, 10., 10.), 2.);
+        assert!(ci.lower <= ci.upper);
+        assert!((ci.lower + 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn check_batch_curve_fit() {
+        // Exactly logarithmic curve is fitted without errors
+        let points = BATCH_SIZES
+            .iter()
+            .map(|&n| (n, 100. - 10. * (n as f64).ln()))
+            .collect();
+        let curve = BatchCurve::fit(points).unwrap();
+        assert!((curve.slope + 10.).abs() < 1e-9);
+        assert!((curve.intercept - 100.).abs() < 1e-9);
+        assert!((curve.change_pct() + 10. * 50f64.ln()).abs() < 1e-9);
+
+        assert_eq!(BatchCurve::fit(vec![]), None);
+        assert_eq!(BatchCurve::fit(vec![(10, 1.), (10, 2.)]), None);
+    }
+
+    #[test]
+    fn check_batch_curve_of_constant_cost() {
+        let mut calls = vec![];
+        let curve = batch_curve(
+            |n| {
+                calls.push(n);
+                n as u64 * 100
+            },
+            3,
+        )
+        .unwrap();
+        // Batch sizes are measured in round-robin
+        assert_eq!(calls, BATCH_SIZES.repeat(3));
+        assert!(curve.points.iter().all(|&(_, v)| v == 100.));
+        assert_eq!(curve.change_pct(), 0.);
+    }
+
+    #[test]
+    fn check_batch_curve_of_amortized_cost() {
+        // Warm-up of 1000 is amortized by larger batches: 1100 per iteration in a batch of 1, 120 in a batch of 50
+        let curve = batch_curve(|n| 1000 + n as u64 * 100, 5).unwrap();
+        assert_eq!(curve.points.first(), Some(&(1, 1100.)));
+        assert_eq!(curve.points.last(), Some(&(50, 120.)));
+        assert!(curve.slope < 0.);
+        assert!(curve.change_pct() < -50., "{}", curve.change_pct());
+
+        // Noise doesn't hide the trend, because the medians are fitted
+        let mut rng = SmallRng::seed_from_u64(0);
+        let curve = batch_curve(|n| 1000 + n as u64 * rng.gen_range(90..110), 11).unwrap();
+        assert!(curve.change_pct() < -50., "{}", curve.change_pct());
+    }
+}
@@ -0,0 +1,213 @@
+//! Map of the code addresses of the tests for the profilers (`--emit-symbol-map`)
+//!
+//! Both executables are loaded in the same process, so the samples of a profiler can't be attributed to the
+//! baseline or the candidate by the function names alone. The map names the measurement entry point of each test
+//! after the test and the side it belongs to. It is written in the textual format `perf` reads for
+//! `/tmp/perf-<pid>.map` (`<start> <size> <name>`, hexadecimal without prefix) along with a JSON variant containing
+//! the module and the symbol each address is resolved to.
+
+use crate::dylib::Spi;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Placeholder in the path replaced with the id of the running process
+const PID_PLACEHOLDER: &str = "{pid}";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Side {
+    Baseline,
+    Candidate,
+}
+
+impl Side {
+    fn as_str(self) -> &'static str {
+        match self {
+            Side::Baseline => "baseline",
+            Side::Candidate => "candidate",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct SymbolEntry {
+    pub(crate) test: String,
+    pub(crate) side: Side,
+
+    /// Address of the measurement entry point in the current process
+    pub(crate) address: usize,
+
+    /// Number of bytes from the address to the end of the function containing it (`None` if not known)
+    pub(crate) size: Option<usize>,
+
+    /// Executable or library the address belongs to
+    pub(crate) module: Option<PathBuf>,
+
+    /// Address the module is loaded at
+    pub(crate) module_base: Option<usize>,
+
+    /// Mangled name of the function containing the address
+    pub(crate) symbol: Option<String>,
+}
+
+impl SymbolEntry {
+    /// Line of the perf map. Functions of unknown size are given a single byte
+    fn perf_map_line(&self) -> String {
+        format!(
+            "{:x} {:x} tango:{}:{}",
+            self.address,
+            self.size.unwrap_or(1).max(1),
+            self.side.as_str(),
+            self.test
+        )
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SymbolMap {
+    entries: Vec<SymbolEntry>,
+
+    #[cfg(target_os = "linux")]
+    resolver: crate::linux::AddressResolver,
+}
+
+impl SymbolMap {
+    /// Adds the entry points of the tests matching a filter. Tests not exporting the address are skipped
+    pub(crate) fn add_tests(&mut self, spi: &Spi, side: Side, filter: impl Fn(&str) -> bool) {
+        for func in spi.tests().iter().filter(|f| filter(f.name.as_str())) {
+            if let Some(address) = spi.entry_point(func) {
+                self.add(func.name.as_str(), side, address);
+            }
+        }
+    }
+
+    pub(crate) fn add(&mut self, test: &str, side: Side, address: usize) {
+        #[allow(unused_mut)]
+        let mut entry = SymbolEntry {
+            test: test.to_string(),
+            side,
+            address,
+            size: None,
+            module: None,
+            module_base: None,
+            symbol: None,
+        };
+        #[cfg(target_os = "linux")]
+        if let Some(resolved) = self.resolver.resolve(address) {
+            entry.size = resolved
+                .size
+                .map(|size| (resolved.start + size).saturating_sub(address));
+            entry.module = Some(resolved.module);
+            entry.module_base = Some(resolved.base);
+            entry.symbol = resolved.symbol;
+        }
+        self.entries.push(entry);
+    }
+
+    pub(crate) fn entries(&self) -> &[SymbolEntry] {
+        &self.entries
+    }
+
+    /// Writes the perf map to a given path and the JSON variant next to it (`<path>.json`)
+    ///
+    /// `{pid}` in the path is replaced with the id of the running process. Returns the path the perf map is
+    /// written to.
+    pub(crate) fn write(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = PathBuf::from(
+            path.to_string_lossy()
+                .replace(PID_PLACEHOLDER, &process::id().to_string()),
+        );
+        let mut perf_map = BufWriter::new(File::create(&path)?);
+        for entry in &self.entries {
+            writeln!(perf_map, "{}", entry.perf_map_line())?;
+        }
+        perf_map.flush()?;
+
+        let mut json_path = path.clone().into_os_string();
+        json_path.push(".json");
+        let mut json = BufWriter::new(File::create(json_path)?);
+        serde_json::to_writer_pretty(&mut json, &self.entries)?;
+        json.flush()?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark_fn;
+    use serde_json::Value;
+    use std::{env, fs};
+
+    /// Parses a line of the perf map the way `perf` does: start and size in hex followed by the name
+    fn parse_perf_map_line(line: &str) -> Option<(usize, usize, &str)> {
+        let (start, rest) = line.split_once(' ')?;
+        let (size, name) = rest.split_once(' ')?;
+        Some((
+            usize::from_str_radix(start, 16).ok()?,
+            usize::from_str_radix(size, 16).ok()?,
+            name,
+        ))
+    }
+
+    #[test]
+    fn check_symbol_map_format() {
+        let baseline = Spi::for_benchmarks(vec![
+            benchmark_fn("sort", || 1),
+            benchmark_fn("search", || 2),
+        ])
+        .unwrap();
+        let candidate = Spi::for_benchmarks(vec![benchmark_fn("sort", || 3)]).unwrap();
+
+        let mut map = SymbolMap::default();
+        map.add_tests(&baseline, Side::Baseline, |_| true);
+        map.add_tests(&candidate, Side::Candidate, |name| name == "sort");
+        assert_eq!(map.entries().len(), 3);
+
+        let path = env::temp_dir().join("tango-symbols-{pid}.map");
+        let written = map.write(&path).unwrap();
+        assert_eq!(
+            written,
+            env::temp_dir().join(format!("tango-symbols-{}.map", process::id()))
+        );
+        let perf_map = fs::read_to_string(&written).unwrap();
+        let json_path = written.with_extension("map.json");
+        let json: Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        fs::remove_file(&written).unwrap();
+        fs::remove_file(&json_path).unwrap();
+
+        let lines = perf_map
+            .lines()
+            .map(|l| parse_perf_map_line(l).unwrap())
+            .collect::<Vec<_>>();
+        let names = lines.iter().map(|l| l.2).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "tango:baseline:sort",
+                "tango:baseline:search",
+                "tango:candidate:sort"
+            ]
+        );
+        for ((start, size, _), entry) in lines.iter().zip(map.entries()) {
+            assert_eq!(*start, entry.address);
+            assert!(*size > 0);
+        }
+
+        assert_eq!(json[0]["test"], "sort");
+        assert_eq!(json[2]["side"], "candidate");
+        assert_eq!(json[0]["address"], lines[0].0);
+        #[cfg(target_os = "linux")]
+        {
+            let exe = env::current_exe().unwrap();
+            let module = PathBuf::from(json[0]["module"].as_str().unwrap());
+            assert_eq!(module.file_name(), exe.file_name());
+            assert!(json[0]["symbol"].as_str().unwrap().contains("measure"));
+        }
+    }
+}
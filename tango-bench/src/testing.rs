@@ -0,0 +1,435 @@
+//! Helpers for checking user-defined benchmark components in regular tests
+
+use crate::{
+    registry::RegistrySnapshot, GenFunc, Generator, HaystackSize, IntoBenchmarks, MeasureTarget,
+};
+use rand::rngs::SmallRng;
+use std::{
+    any::Any,
+    env, fmt,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Seeds used to check generator determinism
+const SEEDS: [u64; 4] = [0, 1, 42, u64::MAX];
+
+/// Number of haystacks generated for each seed
+const HAYSTACKS: usize = 3;
+
+/// Number of needles generated for each haystack
+const NEEDLES: usize = 16;
+
+/// Environment variable making [`check_registry()`] rewrite the snapshot instead of comparing with it
+pub const UPDATE_REGISTRY_VAR: &str = "TANGO_UPDATE_REGISTRY";
+
+/// Violation of the [`Generator`] contract found by [`check_generator()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContractViolation {
+    /// One of the generator methods panicked
+    Panic {
+        /// Name of the method (eg. `next_haystack`)
+        method: &'static str,
+        /// Payload of the panic
+        message: String,
+    },
+
+    /// Generator produced different haystacks after being synced with the same seed
+    NonDeterministicHaystack {
+        seed: u64,
+        /// Index of the first differing haystack
+        haystack_no: usize,
+    },
+
+    /// Generator produced different needles after being synced with the same seed
+    NonDeterministicNeedle {
+        seed: u64,
+        haystack_no: usize,
+        /// Index of the first differing needle of the haystack
+        needle_no: usize,
+    },
+
+    /// Generator is not restarting the sequence when synced with the same seed again
+    SyncIsNotResetting { seed: u64 },
+
+    /// Function measured with the generator drew a number of needles other than one per iteration
+    NeedleCount { iterations: usize, needles: usize },
+
+    /// Generator name is empty which makes test names ambiguous
+    EmptyName,
+}
+
+impl fmt::Display for ContractViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panic { method, message } => write!(f, "{}() panicked: {}", method, message),
+            Self::NonDeterministicHaystack { seed, haystack_no } => write!(
+                f,
+                "haystack #{} differs between two generators synced with seed {}",
+                haystack_no, seed
+            ),
+            Self::NonDeterministicNeedle {
+                seed,
+                haystack_no,
+                needle_no,
+            } => write!(
+                f,
+                "needle #{} of haystack #{} differs between two generators synced with seed {}",
+                needle_no, haystack_no, seed
+            ),
+            Self::SyncIsNotResetting { seed } => write!(
+                f,
+                "sync({}) doesn't restart the sequence of generated haystacks",
+                seed
+            ),
+            Self::NeedleCount {
+                iterations,
+                needles,
+            } => write!(
+                f,
+                "{} needles are drawn for {} iterations",
+                needles, iterations
+            ),
+            Self::EmptyName => write!(f, "generator name is empty"),
+        }
+    }
+}
+
+/// Checks that the generator complies with the [`Generator`] contract
+///
+/// Two copies of the generator are synced with the same seed and are required to produce the same sequence of
+/// haystacks and needles. Syncing the generator again with the same seed should restart the sequence. A function
+/// measured with the generator should draw exactly one needle per iteration. All the panics are caught and reported
+/// as violations.
+///
+/// ```rust
+/// use tango_bench::{generators::RandomVec, testing::check_generator};
+///
+/// assert_eq!(check_generator(RandomVec::<u32>::new(100)), Ok(()));
+/// ```
+pub fn check_generator<G>(generator: G) -> Result<(), Vec<ContractViolation>>
+where
+    G: Generator + Clone,
+    G::Haystack: PartialEq,
+    G::Needle: PartialEq,
+{
+    let mut violations = vec![];
+
+    match catch("name", || generator.name().is_empty()) {
+        Ok(true) => violations.push(ContractViolation::EmptyName),
+        Ok(false) => {}
+        Err(v) => violations.push(v),
+    }
+
+    for seed in SEEDS {
+        if let Err(v) = check_seed(&generator, seed) {
+            violations.push(v);
+        }
+    }
+
+    if let Err(v) = check_needle_count(&generator) {
+        violations.push(v);
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+fn check_seed<G>(generator: &G, seed: u64) -> Result<(), ContractViolation>
+where
+    G: Generator + Clone,
+    G::Haystack: PartialEq,
+    G::Needle: PartialEq,
+{
+    let mut a = generator.clone();
+    let mut b = generator.clone();
+    catch("sync", || a.sync(seed))?;
+    catch("sync", || b.sync(seed))?;
+
+    let mut first_haystack = None;
+    for haystack_no in 0..HAYSTACKS {
+        let haystack_a = catch("next_haystack", || a.next_haystack())?;
+        let haystack_b = catch("next_haystack", || b.next_haystack())?;
+        if haystack_a != haystack_b {
+            return Err(ContractViolation::NonDeterministicHaystack { seed, haystack_no });
+        }
+
+        for needle_no in 0..NEEDLES {
+            let needle_a = catch("next_needle", || a.next_needle(&haystack_a))?;
+            let needle_b = catch("next_needle", || b.next_needle(&haystack_b))?;
+            if needle_a != needle_b {
+                return Err(ContractViolation::NonDeterministicNeedle {
+                    seed,
+                    haystack_no,
+                    needle_no,
+                });
+            }
+        }
+
+        first_haystack.get_or_insert(haystack_a);
+    }
+
+    catch("sync", || a.sync(seed))?;
+    let haystack = catch("next_haystack", || a.next_haystack())?;
+    if first_haystack.as_ref() != Some(&haystack) {
+        return Err(ContractViolation::SyncIsNotResetting { seed });
+    }
+
+    Ok(())
+}
+
+/// Measures a no-op function with the generator and counts the needles it draws
+///
+/// Generators can't draw needles in batches, the harness draws one per iteration, so the count holds by construction.
+/// The check guards it, because the measured values are normalized by the number of iterations.
+fn check_needle_count<G: Generator + Clone>(generator: &G) -> Result<(), ContractViolation> {
+    let needles = Arc::new(AtomicUsize::new(0));
+    let counting = CountingNeedles {
+        g: generator.clone(),
+        needles: Arc::clone(&needles),
+    };
+    let mut target = GenFunc::new("needles", |_: &G::Haystack, _: &G::Needle| (), counting);
+    catch("sync", || target.sync(SEEDS[0]))?;
+    catch("next_haystack", || target.next_haystack())?;
+    catch("next_needle", || target.measure(NEEDLES))?;
+    catch("next_needle", || target.measure_detailed(NEEDLES))?;
+
+    let iterations = 2 * NEEDLES;
+    let needles = needles.load(Ordering::Relaxed);
+    if needles == iterations {
+        Ok(())
+    } else {
+        Err(ContractViolation::NeedleCount {
+            iterations,
+            needles,
+        })
+    }
+}
+
+/// Generator counting the needles drawn from the wrapped one
+#[derive(Clone)]
+struct CountingNeedles<G> {
+    g: G,
+    needles: Arc<AtomicUsize>,
+}
+
+impl<G: Generator> Generator for CountingNeedles<G> {
+    type Haystack = G::Haystack;
+    type Needle = G::Needle;
+
+    fn next_haystack(&mut self) -> Self::Haystack {
+        self.g.next_haystack()
+    }
+
+    fn next_needle(&mut self, haystack: &Self::Haystack) -> Self::Needle {
+        self.needles.fetch_add(1, Ordering::Relaxed);
+        self.g.next_needle(haystack)
+    }
+
+    fn mutate_haystack(&mut self, haystack: &mut Self::Haystack, sample_idx: usize) {
+        self.g.mutate_haystack(haystack, sample_idx)
+    }
+
+    fn mutates_haystack(&self) -> bool {
+        self.g.mutates_haystack()
+    }
+
+    fn haystack_size(&self, haystack: &Self::Haystack) -> Option<HaystackSize> {
+        self.g.haystack_size(haystack)
+    }
+
+    fn sync(&mut self, seed: u64) {
+        self.g.sync(seed)
+    }
+
+    fn sync_with(&mut self, rng: SmallRng) {
+        self.g.sync_with(rng)
+    }
+
+    fn recording(&self) -> Option<&Path> {
+        self.g.recording()
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.g.take_error()
+    }
+
+    fn name(&self) -> &str {
+        self.g.name()
+    }
+}
+
+/// Checks that given benchmarks match a registry snapshot file (see [`crate::registry`])
+///
+/// Returns the list of added, removed and renamed tests if they don't. The snapshot is (re)written instead if
+/// `TANGO_UPDATE_REGISTRY` environment variable is set.
+pub fn check_registry(
+    benchmarks: impl IntoBenchmarks,
+    path: impl AsRef<Path>,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let current = RegistrySnapshot::of_benchmarks(benchmarks).map_err(|e| e.to_string())?;
+    if env::var_os(UPDATE_REGISTRY_VAR).is_some() {
+        return current
+            .write(path)
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e));
+    }
+    let expected = RegistrySnapshot::read(path).map_err(|e| {
+        format!(
+            "Unable to read registry snapshot {} ({}). Run with {}=1 to create it",
+            path.display(),
+            e,
+            UPDATE_REGISTRY_VAR
+        )
+    })?;
+    let diff = expected.diff(&current);
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Registry doesn't match {} (run with {}=1 to update it):\n{}",
+            path.display(),
+            UPDATE_REGISTRY_VAR,
+            diff
+        ))
+    }
+}
+
+/// Asserts that given benchmarks match a registry snapshot file
+///
+/// The path is relative to the directory of the crate the macro is used in. Panics with the list of the added,
+/// removed and renamed tests if the registry doesn't match (see [`check_registry()`]).
+///
+/// ```rust,ignore
+/// #[test]
+/// fn registry_is_not_changed() {
+///     tango_bench::testing::assert_registry_matches!(benchmarks(), "benches/registry.snap");
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_registry_matches {
+    ($benchmarks:expr, $path:expr $(,)?) => {{
+        let path = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path);
+        if let Err(e) = $crate::testing::check_registry($benchmarks, path) {
+            panic!("{}", e);
+        }
+    }};
+}
+
+pub use crate::assert_registry_matches;
+
+fn catch<T>(method: &'static str, f: impl FnOnce() -> T) -> Result<T, ContractViolation> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|e| ContractViolation::Panic {
+        method,
+        message: panic_message(e),
+    })
+}
+
+fn panic_message(e: Box<dyn Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{benchmark_fn, generators::RandomVec};
+    use std::{
+        fs, process,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    #[test]
+    fn check_builtin_generators() {
+        assert_eq!(check_generator(RandomVec::<u8>::new(10)), Ok(()));
+        assert_eq!(check_generator(RandomVec::<u64>::new(1_000)), Ok(()));
+        assert_eq!(check_generator(RandomVec::<f64>::new(0)), Ok(()));
+    }
+
+    #[test]
+    fn check_contract_violations() {
+        /// Generator ignoring the seed and relying on a global state
+        #[derive(Clone)]
+        struct Global;
+
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        impl Generator for Global {
+            type Haystack = u64;
+            type Needle = ();
+
+            fn next_haystack(&mut self) -> Self::Haystack {
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            }
+
+            fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {}
+
+            fn sync(&mut self, _: u64) {}
+        }
+
+        let violations = check_generator(Global).unwrap_err();
+        assert_eq!(violations.len(), SEEDS.len());
+        assert!(matches!(
+            violations[0],
+            ContractViolation::NonDeterministicHaystack { haystack_no: 0, .. }
+        ));
+
+        /// Generator which panics on needle generation
+        #[derive(Clone)]
+        struct Panicking;
+
+        impl Generator for Panicking {
+            type Haystack = ();
+            type Needle = ();
+
+            fn next_haystack(&mut self) -> Self::Haystack {}
+
+            fn next_needle(&mut self, _: &Self::Haystack) -> Self::Needle {
+                panic!("no needles")
+            }
+
+            fn sync(&mut self, _: u64) {}
+        }
+
+        let violations = check_generator(Panicking).unwrap_err();
+        assert_eq!(
+            violations[0],
+            ContractViolation::Panic {
+                method: "next_needle",
+                message: "no needles".into()
+            }
+        );
+    }
+
+    #[test]
+    fn check_registry_snapshot() {
+        let path = env::temp_dir().join(format!("tango-registry-{}.snap", process::id()));
+        let benchmarks = || [benchmark_fn("sum", || 1 + 1)];
+        assert!(check_registry(benchmarks(), &path)
+            .unwrap_err()
+            .contains(UPDATE_REGISTRY_VAR));
+
+        RegistrySnapshot::of_benchmarks(benchmarks())
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        let matches = check_registry(benchmarks(), &path);
+        let renamed = check_registry([benchmark_fn("sums", || 1 + 1)], &path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(matches, Ok(()));
+        assert!(renamed.unwrap_err().contains("renamed  sum -> sums"));
+    }
+}
@@ -0,0 +1,520 @@
+//! Rolling comparison for long-lived performance monitoring (`watch` subcommand)
+//!
+//! The suite is compared in cycles separated by a fixed interval. Results of each cycle are appended to the history
+//! (JSON lines, a cycle per line) and analyzed together with the previous cycles: a test is reported as regressed
+//! only when it regressed significantly in a given number of consecutive cycles, so a single noisy cycle doesn't
+//! raise an alert. An alert is raised once per streak and rearmed when the test recovers.
+
+use crate::{id::PairId, RunResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Longest uninterrupted sleep between cycles, so shutdown is not delayed by the interval
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WatchSettings {
+    /// Pause between the end of a cycle and the start of the next one
+    pub(crate) interval: Duration,
+
+    /// Number of consecutive cycles a test should regress in to raise an alert
+    pub(crate) consecutive: usize,
+
+    /// Minimal significant difference in percent considered a regression
+    pub(crate) threshold: f64,
+
+    /// Number of cycles to run (until stopped if `None`)
+    pub(crate) cycles: Option<usize>,
+}
+
+/// Results of a single cycle as stored in the history
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CycleRecord {
+    pub(crate) cycle: usize,
+
+    /// Start of the cycle (seconds since UNIX epoch)
+    pub(crate) started: u64,
+
+    /// Termination was requested during the cycle, so the tests after it are missing
+    pub(crate) interrupted: bool,
+
+    pub(crate) results: Vec<RunResult>,
+}
+
+/// Append-only history of the cycles
+pub(crate) struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn append(&self, record: &CycleRecord) -> io::Result<()> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // The line is written at once, so a cycle is never recorded partially
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut writer = BufWriter::with_capacity(line.len(), file);
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+
+    #[cfg(test)]
+    fn read(&self) -> io::Result<Vec<CycleRecord>> {
+        fs::read_to_string(&self.path)?
+            .lines()
+            .map(|l| serde_json::from_str(l).map_err(io::Error::from))
+            .collect()
+    }
+}
+
+/// Test regressed in a number of consecutive cycles. Passed to the hook as JSON
+#[derive(Serialize)]
+pub(crate) struct Regression<'a> {
+    pub(crate) test: &'a str,
+
+    /// Functions compared by the test
+    pub(crate) id: &'a PairId,
+
+    /// Cycle the alert is raised in
+    pub(crate) cycle: usize,
+
+    /// Number of consecutive cycles the test regressed in
+    pub(crate) consecutive: usize,
+
+    /// Differences in percent observed in the last cycles of the streak (oldest first)
+    pub(crate) diffs: Vec<f64>,
+
+    /// Result of the test in the last cycle
+    pub(crate) result: &'a RunResult,
+}
+
+#[derive(Default)]
+struct Streak {
+    cycles: usize,
+    diffs: VecDeque<f64>,
+    alerted: bool,
+}
+
+/// Counts consecutive cycles each test regressed in
+///
+/// Tests missing from a cycle (eg. skipped or not reached in an interrupted cycle) keep their streak.
+pub(crate) struct RegressionTracker {
+    consecutive: usize,
+    threshold: f64,
+    streaks: HashMap<PairId, Streak>,
+}
+
+impl RegressionTracker {
+    pub(crate) fn new(consecutive: usize, threshold: f64) -> Self {
+        Self {
+            consecutive: consecutive.max(1),
+            threshold,
+            streaks: HashMap::new(),
+        }
+    }
+
+    /// Returns the tests which regressed in the required number of consecutive cycles for the first time
+    pub(crate) fn observe<'a>(
+        &mut self,
+        cycle: usize,
+        results: &'a [RunResult],
+    ) -> Vec<Regression<'a>> {
+        let mut regressions = vec![];
+        for result in results {
+            let estimate = &result.diff_estimate;
+            if !(estimate.significant && estimate.pct > self.threshold) {
+                self.streaks.remove(&result.id);
+                continue;
+            }
+            let streak = self.streaks.entry(result.id.clone()).or_default();
+            streak.cycles += 1;
+            streak.diffs.push_back(estimate.pct);
+            if streak.diffs.len() > self.consecutive {
+                streak.diffs.pop_front();
+            }
+            if streak.cycles >= self.consecutive && !streak.alerted {
+                streak.alerted = true;
+                regressions.push(Regression {
+                    test: &result.name,
+                    id: &result.id,
+                    cycle,
+                    consecutive: streak.cycles,
+                    diffs: streak.diffs.iter().copied().collect(),
+                    result,
+                });
+            }
+        }
+        regressions
+    }
+}
+
+/// Runs the cycles until the given number of them is run or `stop` is set
+///
+/// `run_cycle` is given the number of the cycle and should return early with the results measured so far once
+/// `stop` is set, so the in-progress cycle is still recorded. Returns the number of cycles run.
+pub(crate) fn watch<C, H>(
+    settings: &WatchSettings,
+    mut run_cycle: C,
+    history: Option<&History>,
+    mut on_regression: H,
+    stop: &AtomicBool,
+) -> anyhow::Result<usize>
+where
+    C: FnMut(usize, &AtomicBool) -> anyhow::Result<Vec<RunResult>>,
+    H: FnMut(&Regression) -> anyhow::Result<()>,
+{
+    let mut tracker = RegressionTracker::new(settings.consecutive, settings.threshold);
+    let max_cycles = settings.cycles.unwrap_or(usize::MAX);
+    let mut cycle = 0;
+    while cycle < max_cycles && !stop.load(Ordering::Relaxed) {
+        if cycle > 0 && !sleep_unless_stopped(settings.interval, stop) {
+            break;
+        }
+        cycle += 1;
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let results = run_cycle(cycle, stop)?;
+        let record = CycleRecord {
+            cycle,
+            started,
+            interrupted: stop.load(Ordering::Relaxed),
+            results,
+        };
+        if let Some(history) = history {
+            history.append(&record)?;
+        }
+        for regression in tracker.observe(cycle, &record.results) {
+            on_regression(&regression)?;
+        }
+    }
+    Ok(cycle)
+}
+
+/// Returns `false` if stopped before the time is up
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(SLEEP_SLICE));
+    }
+}
+
+/// Runs a shell command with a given input on stdin
+///
+/// Input not read by the command is discarded.
+pub(crate) fn run_hook(command: &str, input: &[u8]) -> io::Result<ExitStatus> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(input) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Set once the process receives SIGTERM (see [`stop_on_sigterm()`])
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the flag set on SIGTERM instead of terminating the process
+///
+/// On other platforms the flag is never set.
+pub(crate) fn stop_on_sigterm() -> &'static AtomicBool {
+    #[cfg(unix)]
+    unsafe {
+        extern "C" fn on_sigterm(_: libc::c_int) {
+            TERMINATED.store(true, Ordering::Relaxed);
+        }
+        libc::signal(
+            libc::SIGTERM,
+            on_sigterm as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    &TERMINATED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{summarized_run_result, MeasurementSettings, Summary};
+    use std::{cell::RefCell, env, process};
+
+    fn result(name: &str, pct: f64, significant: bool) -> RunResult {
+        let summary = Summary::from(&[10., 12., 11.]).unwrap();
+        let settings = MeasurementSettings::default();
+        let mut result = summarized_run_result(name, summary, summary, summary, None, 0, &settings);
+        result.diff_estimate.pct = pct;
+        result.diff_estimate.significant = significant;
+        result
+    }
+
+    fn settings(consecutive: usize, cycles: usize) -> WatchSettings {
+        WatchSettings {
+            interval: Duration::ZERO,
+            consecutive,
+            threshold: 1.,
+            cycles: Some(cycles),
+        }
+    }
+
+    fn temp_history(name: &str) -> History {
+        History::new(env::temp_dir().join(format!("tango-watch-{}-{}.jsonl", name, process::id())))
+    }
+
+    #[test]
+    fn check_regression_hysteresis() {
+        let mut tracker = RegressionTracker::new(3, 1.);
+        let mut alerts = |cycle, results: &[RunResult]| {
+            tracker
+                .observe(cycle, results)
+                .iter()
+                .map(|r| (r.test.to_string(), r.consecutive, r.diffs.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // Insignificant, improved or below the threshold results break the streak
+        assert!(alerts(1, &[result("sort", 5., true)]).is_empty());
+        assert!(alerts(2, &[result("sort", 5., false)]).is_empty());
+        assert!(alerts(3, &[result("sort", 5., true)]).is_empty());
+        assert!(alerts(4, &[result("sort", 0.5, true)]).is_empty());
+        assert!(alerts(5, &[result("sort", -5., true)]).is_empty());
+
+        assert!(alerts(6, &[result("sort", 2., true)]).is_empty());
+        // Missing tests keep their streak
+        assert!(alerts(7, &[result("search", 9., true)]).is_empty());
+        assert!(alerts(8, &[result("sort", 3., true)]).is_empty());
+        assert_eq!(
+            alerts(9, &[result("sort", 4., true)]),
+            [("sort".to_string(), 3, vec![2., 3., 4.])]
+        );
+
+        // Alert is raised once per streak and rearmed after recovery
+        assert!(alerts(10, &[result("sort", 5., true)]).is_empty());
+        assert!(alerts(11, &[result("sort", 0., false)]).is_empty());
+        for cycle in 12..14 {
+            assert!(alerts(cycle, &[result("sort", 5., true)]).is_empty());
+        }
+        assert_eq!(alerts(14, &[result("sort", 6., true)]).len(), 1);
+    }
+
+    #[test]
+    fn check_streaks_are_keyed_by_identity() {
+        let mut tracker = RegressionTracker::new(2, 1.);
+        // Pairs sharing a display name are different tests
+        let pair = |candidate: &str| {
+            let mut result = result("sum", 5., true);
+            result.id = PairId::new("sum", candidate);
+            result
+        };
+        assert!(tracker.observe(1, &[pair("fold_sum")]).is_empty());
+        assert!(tracker.observe(2, &[pair("iter_sum")]).is_empty());
+        let results = [pair("iter_sum")];
+        let alerts = tracker.observe(3, &results);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].test, "sum");
+        assert_eq!(*alerts[0].id, PairId::new("sum", "iter_sum"));
+
+        // Hook input and history records keep the structured identity next to the display name
+        let hook = serde_json::to_value(&alerts[0]).unwrap();
+        assert_eq!(hook["test"], "sum");
+        assert_eq!(hook["id"]["candidate"], "iter_sum");
+        assert_eq!(hook["result"]["name"], "sum");
+        assert_eq!(hook["result"]["id"], hook["id"]);
+
+        let history = temp_history("identity");
+        let _ = fs::remove_file(history.path());
+        let record = CycleRecord {
+            cycle: 3,
+            started: 0,
+            interrupted: false,
+            results: results.to_vec(),
+        };
+        history.append(&record).unwrap();
+        let records = history.read().unwrap();
+        fs::remove_file(history.path()).unwrap();
+        assert_eq!(records[0].results[0].id, PairId::new("sum", "iter_sum"));
+    }
+
+    #[test]
+    fn check_watch_loop() {
+        let history = temp_history("loop");
+        let _ = fs::remove_file(history.path());
+        // "sort" regresses starting from the second cycle, "search" stays the same
+        let run_cycle = |cycle: usize, _: &AtomicBool| {
+            let pct = if cycle >= 2 { 5. } else { 0. };
+            Ok(vec![
+                result("sort", pct, cycle >= 2),
+                result("search", 0., false),
+            ])
+        };
+        let alerts = RefCell::new(vec![]);
+        let on_regression = |r: &Regression| {
+            alerts.borrow_mut().push((r.test.to_string(), r.cycle));
+            Ok(())
+        };
+        let stop = AtomicBool::new(false);
+        let cycles = watch(
+            &settings(2, 5),
+            run_cycle,
+            Some(&history),
+            on_regression,
+            &stop,
+        )
+        .unwrap();
+
+        assert_eq!(cycles, 5);
+        assert_eq!(*alerts.borrow(), [("sort".to_string(), 3)]);
+        let records = history.read().unwrap();
+        fs::remove_file(history.path()).unwrap();
+        assert_eq!(records.len(), 5);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.cycle, i + 1);
+            assert!(!record.interrupted);
+            assert_eq!(record.results.len(), 2);
+        }
+    }
+
+    #[test]
+    fn check_interrupted_cycle_is_flushed() {
+        let history = temp_history("interrupted");
+        let _ = fs::remove_file(history.path());
+        let stop = AtomicBool::new(false);
+        // Termination is requested in the middle of the third cycle, after the first test
+        let run_cycle = |cycle: usize, stop: &AtomicBool| {
+            let mut results = vec![result("sort", 5., true)];
+            if cycle == 3 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            if !stop.load(Ordering::Relaxed) {
+                results.push(result("search", 0., false));
+            }
+            Ok(results)
+        };
+        let mut alerts = 0;
+        let settings = WatchSettings {
+            cycles: None,
+            ..settings(3, 0)
+        };
+        let cycles = watch(
+            &settings,
+            run_cycle,
+            Some(&history),
+            |_| {
+                alerts += 1;
+                Ok(())
+            },
+            &stop,
+        )
+        .unwrap();
+
+        assert_eq!(cycles, 3);
+        // Results of the interrupted cycle are analyzed as well
+        assert_eq!(alerts, 1);
+        let records = history.read().unwrap();
+        fs::remove_file(history.path()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records[2].interrupted);
+        assert_eq!(records[2].results.len(), 1);
+
+        // Stop requested during the interval doesn't start a new cycle
+        let stop = AtomicBool::new(true);
+        let settings = WatchSettings {
+            interval: Duration::from_secs(3600),
+            ..settings
+        };
+        assert_eq!(
+            watch(&settings, run_cycle, None, |_| Ok(()), &stop).unwrap(),
+            0
+        );
+        assert!(!sleep_unless_stopped(settings.interval, &stop));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_hook_receives_json() {
+        let output = env::temp_dir().join(format!("tango-watch-hook-{}.json", process::id()));
+        let result = result("sort", 5., true);
+        let regression = Regression {
+            test: "sort",
+            id: &result.id,
+            cycle: 3,
+            consecutive: 2,
+            diffs: vec![4., 5.],
+            result: &result,
+        };
+        let json = serde_json::to_vec(&regression).unwrap();
+        let command = format!("cat > '{}'", output.display());
+        assert!(run_hook(&command, &json).unwrap().success());
+
+        let received: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert_eq!(received["test"], "sort");
+        assert_eq!(received["consecutive"], 2);
+        assert_eq!(received["diffs"], serde_json::json!([4., 5.]));
+        assert_eq!(received["result"]["name"], "sort");
+        let id = serde_json::json!({"baseline": "sort", "candidate": "sort"});
+        assert_eq!(received["id"], id);
+        assert_eq!(received["result"]["id"], id);
+
+        // Exit status is reported and unread input is ignored
+        assert!(!run_hook("exit 3", &json).unwrap().success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_sigterm_stops_watch() {
+        let stop = stop_on_sigterm();
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert!(stop.load(Ordering::Relaxed));
+        stop.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,54 @@
+//! `compare --aa-test` comparing each side against its copy
+//!
+//! The test binary is both the harness and the executable it compares against (see `tests/resource_limits.rs`).
+
+use serde_json::Value;
+use std::{env, fs, hint::black_box, process, process::Command, process::ExitCode};
+use tango_bench::{benchmark_fn, cli, tango_benchmarks, IntoBenchmarks, MeasurementSettings};
+
+const CHILD_ENV_VAR: &str = "TANGO_AA_TEST_CHILD";
+
+fn sum_benchmarks() -> impl IntoBenchmarks {
+    [benchmark_fn("sum", || {
+        (0..black_box(1000u64)).map(black_box).sum::<u64>()
+    })]
+}
+
+tango_benchmarks!(sum_benchmarks());
+
+fn main() -> cli::Result<ExitCode> {
+    if env::var_os(CHILD_ENV_VAR).is_some() {
+        unsafe { tango_init() };
+        return cli::run(MeasurementSettings::default());
+    }
+
+    let exe = env::current_exe()?;
+    let json = env::temp_dir().join(format!("tango-aa-test-{}.json", process::id()));
+    let output = Command::new(&exe)
+        .args(["compare", "--allow-self-compare", "--aa-test", "--no-cache"])
+        .args(["-s", "200", "--json"])
+        .arg(&json)
+        .arg(&exe)
+        .env(CHILD_ENV_VAR, "1")
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let results: Vec<Value> = serde_json::from_str(&fs::read_to_string(&json)?)?;
+    fs::remove_file(&json)?;
+
+    let names = results.iter().map(|r| &r["name"]).collect::<Vec<_>>();
+    assert_eq!(names, ["sum", "sum (A/A baseline)", "sum (A/A candidate)"]);
+    for result in &results[1..] {
+        assert_eq!(result["aa_of"], "sum");
+        assert_eq!(result["id"]["baseline"], "sum");
+        assert_eq!(result["id"]["candidate"], "sum");
+    }
+    let dir = exe.parent().unwrap();
+    let copies = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().contains("-aa-"))
+        .count();
+    assert_eq!(copies, 0, "copies are left");
+    println!("test check_compare_aa_test ... ok");
+    Ok(ExitCode::SUCCESS)
+}
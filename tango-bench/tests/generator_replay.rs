@@ -0,0 +1,71 @@
+//! `compare --generator-replay` replaying a recording in the recorders of both executables
+//!
+//! The test binary is both the harness and the executable it compares against (see `tests/resource_limits.rs`).
+
+use serde_json::Value;
+use std::{env, fs, path::PathBuf, process, process::Command, process::ExitCode};
+use tango_bench::{
+    cli,
+    generators::{RandomVec, Recorder},
+    tango_benchmarks, BenchmarkMatrix, Generator, IntoBenchmarks, MeasurementSettings,
+};
+
+const CHILD_ENV_VAR: &str = "TANGO_GENERATOR_REPLAY_CHILD";
+
+/// Benchmark recording its inputs to a given directory
+fn max_benchmarks() -> impl IntoBenchmarks {
+    let dir = PathBuf::from(env::var_os(CHILD_ENV_VAR).unwrap());
+    let recorder = Recorder::wrap(RandomVec::<u32>::new(4), dir.join("live.jsonl")).unwrap();
+    BenchmarkMatrix::new(recorder)
+        .add_function("max", |h: &Vec<u32>, _: &()| h.iter().max().copied())
+}
+
+tango_benchmarks!(max_benchmarks());
+
+fn main() -> cli::Result<ExitCode> {
+    if env::var_os(CHILD_ENV_VAR).is_some() {
+        unsafe { tango_init() };
+        return cli::run(MeasurementSettings::default());
+    }
+
+    let dir = env::temp_dir().join(format!("tango-generator-replay-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    // Recording of a single haystack is exhausted by the second one. Needles are not recorded, so the replayed
+    // ones can't get out of sync with the haystacks
+    let recording = dir.join("recorded.jsonl");
+    let mut recorder = Recorder::wrap(RandomVec::<u32>::new(4), &recording)?;
+    recorder.next_haystack();
+    drop(recorder);
+
+    let exe = env::current_exe()?;
+    let json = dir.join("results.json");
+    let compare = |extra_args: &[&str]| {
+        Command::new(&exe)
+            .args(["compare", "--allow-self-compare", "--no-cache", "-s", "10"])
+            .arg("--generator-replay")
+            .arg(&recording)
+            .args(extra_args)
+            .arg("--json")
+            .arg(&json)
+            .arg(&exe)
+            .env(CHILD_ENV_VAR, &dir)
+            .output()
+    };
+
+    let output = compare(&[])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{}", stderr);
+    assert!(stderr.contains("is exhausted"), "{}", stderr);
+
+    let output = compare(&["--loop-replay"])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let results: Vec<Value> = serde_json::from_str(&fs::read_to_string(&json)?)?;
+    let live = dir.join("live.jsonl").exists();
+    fs::remove_dir_all(&dir)?;
+
+    assert_eq!(results[0]["recording"], recording.to_str().unwrap());
+    assert!(!live, "inputs are recorded while replaying");
+    println!("test check_compare_generator_replay ... ok");
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,46 @@
+//! Measurement core used as a library without the default features
+//!
+//! Run with `cargo test -p tango-bench --no-default-features --test minimal`, so the core doesn't pick up a
+//! dependency on the CLI or on loading the benchmarks from a library.
+
+use std::time::Duration;
+use tango_bench::{
+    benchmark_fn, generators::RandomVec, quick, stats::critical_z_score, BenchmarkMatrix,
+    IntoBenchmarks, Summary,
+};
+
+#[test]
+fn check_targets_measure() {
+    let mut benchmarks = BenchmarkMatrix::new(RandomVec::<u64>::new(100))
+        .add_function("sum", |h: &Vec<u64>, _: &()| {
+            h.iter().fold(0u64, |a, b| a.wrapping_add(*b))
+        })
+        .into_benchmarks();
+    benchmarks.push(benchmark_fn("noop", || 42));
+
+    let names = benchmarks.iter().map(|b| b.name()).collect::<Vec<_>>();
+    assert_eq!(names, ["sum/u64/100", "noop"]);
+
+    let samples = (0..10)
+        .map(|_| benchmarks[0].measure(10))
+        .collect::<Vec<_>>();
+    let summary = Summary::from(&samples).unwrap();
+    assert_eq!(summary.n, 10);
+    assert!(summary.min <= summary.max);
+}
+
+#[test]
+fn check_programmatic_comparison() {
+    let data = (0..1000u64).collect::<Vec<_>>();
+    let result = quick::compare(
+        || data.iter().sum::<u64>(),
+        || data.iter().copied().reduce(|a, b| a + b),
+        Duration::from_millis(20),
+    );
+    assert!(result.samples >= 10);
+    assert!(result.ci.lower <= result.relative_change);
+    assert!(result.relative_change <= result.ci.upper);
+
+    // Normal quantile doesn't depend on external crates
+    assert!((critical_z_score(0.05) - 1.96).abs() < 1e-3);
+}
@@ -0,0 +1,39 @@
+//! `compare --resource-limits` stopping a spinning benchmark
+//!
+//! The test binary is both the harness and the executable it compares against, so it is linked with `-rdynamic`
+//! (see `build.rs`) and runs `compare` in a child process. The harness measures the test in a child process of its
+//! own, which is stopped by the limits, while the harness reports the test as failed.
+
+use std::{env, hint::black_box, process::Command, process::ExitCode};
+use tango_bench::{benchmark_fn, cli, tango_benchmarks, IntoBenchmarks, MeasurementSettings};
+
+const CHILD_ENV_VAR: &str = "TANGO_RESOURCE_LIMITS_CHILD";
+
+fn spinning_benchmarks() -> impl IntoBenchmarks {
+    [benchmark_fn("spin", || {
+        (0..black_box(1_000_000u64)).fold(0u64, |a, b| black_box(a.wrapping_add(b)))
+    })]
+}
+
+tango_benchmarks!(spinning_benchmarks());
+
+fn main() -> cli::Result<ExitCode> {
+    if env::var_os(CHILD_ENV_VAR).is_some() {
+        unsafe { tango_init() };
+        return cli::run(MeasurementSettings::default());
+    }
+
+    let exe = env::current_exe()?;
+    let output = Command::new(&exe)
+        .args(["compare", "--allow-self-compare", "--resource-limits"])
+        .args(["--cpu-limit", "1", "-t", "60"])
+        .arg(&exe)
+        .env(CHILD_ENV_VAR, "1")
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("[ERROR] spin"), "{}", stderr);
+    assert!(stderr.contains("CPU time limit"), "{}", stderr);
+    println!("test check_compare_stops_at_cpu_limit ... ok");
+    Ok(ExitCode::SUCCESS)
+}
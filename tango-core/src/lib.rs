@@ -0,0 +1,34 @@
+//! Sampling and statistics core of the Tango benchmarking harness
+//!
+//! This crate has no dependency on `std`, so the same code computes the statistics on the host and compiles for the
+//! targets without an operating system. Timers, IO, the runner and the planning of the samples are provided by
+//! `tango-bench` built on top of it.
+//!
+//! Without default features only the code not allocating memory is available: [`Summary`], [`RunningSummary`],
+//! streaming statistics, the significance tests, rounding of the planned samples and the encoder of [`raw`] samples.
+//! Outlier filtering and decoding of the raw samples into vectors require `alloc` feature (enabled by default).
+//!
+//! ## Measuring on a device
+//!
+//! An external runner measuring functions on a device (eg. with a cycle counter) writes the samples of each function
+//! in the [`raw`] format and the host computes the paired statistics and verdicts with
+//! `tango analyze --raw baseline.bin candidate.bin`.
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+pub mod outliers;
+pub mod planner;
+pub mod raw;
+pub mod running;
+pub mod significance;
+mod summary;
+
+#[cfg(feature = "alloc")]
+pub use outliers::{iqr_variance_thresholds, trim_mask};
+pub use summary::{RunningSummary, Summary};
@@ -0,0 +1,77 @@
+//! Detection of the outliers among paired differences
+
+use alloc::{vec, vec::Vec};
+use core::{cmp::Ordering, ops::RangeInclusive};
+
+/// Outlier detection algorithm based on interquartile range
+///
+/// Observations that are 1.5 IQR away from the corresponding quartile are consideted as outliers
+/// as described in original Tukey's paper.
+pub fn iqr_variance_thresholds(mut input: Vec<f64>) -> Option<RangeInclusive<f64>> {
+    const MINIMUM_IQR: f64 = 1.;
+
+    input.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let (q1, q3) = (input.len() / 4, input.len() * 3 / 4 - 1);
+    if q1 >= q3 || q3 >= input.len() {
+        return None;
+    }
+    // In case q1 and q3 are equal, we need to make sure that IQR is not 0
+    // In the future it would be nice to measure system timer precision empirically.
+    let iqr = (input[q3] - input[q1]).max(MINIMUM_IQR);
+
+    let low_threshold = input[q1] - iqr * 1.5;
+    let high_threshold = input[q3] + iqr * 1.5;
+
+    // Calculating the indicies of the thresholds in an dataset
+    let low_threshold_idx =
+        match input[0..q1].binary_search_by(|probe| probe.total_cmp(&low_threshold)) {
+            Ok(idx) => idx,
+            Err(idx) => idx,
+        };
+
+    let high_threshold_idx =
+        match input[q3..].binary_search_by(|probe| probe.total_cmp(&high_threshold)) {
+            Ok(idx) => idx,
+            Err(idx) => idx,
+        };
+
+    if low_threshold_idx == 0 || high_threshold_idx >= input.len() {
+        return None;
+    }
+
+    // Calculating the equal number of observations which should be removed from each "side" of observations
+    let outliers_cnt = low_threshold_idx.min(input.len() - high_threshold_idx);
+
+    Some(input[outliers_cnt]..=(input[input.len() - outliers_cnt - 1]))
+}
+
+/// Range of values no further than 1.5 IQR from the corresponding quartile
+pub fn tukey_fences(mut input: Vec<f64>) -> Option<RangeInclusive<f64>> {
+    input.sort_unstable_by(f64::total_cmp);
+    let (q1, q3) = (input.len() / 4, (input.len() * 3 / 4).checked_sub(1)?);
+    if q1 >= q3 {
+        return None;
+    }
+    let iqr = input[q3] - input[q1];
+    Some(input[q1] - iqr * 1.5..=input[q3] + iqr * 1.5)
+}
+
+/// Outlier detection algorithm trimming given percentage of the smallest and the largest observations
+///
+/// Returns a mask where `true` marks observations to keep. Number of trimmed observations on each side is
+/// rounded down, so no more than requested percentage is ever removed. Observations with equal values are
+/// trimmed in the order they appear in the input.
+pub fn trim_mask(input: &[f64], lower_pct: f64, upper_pct: f64) -> Vec<bool> {
+    let n = input.len();
+    let lower = ((n as f64 * lower_pct / 100.) as usize).min(n);
+    let upper = ((n as f64 * upper_pct / 100.) as usize).min(n - lower);
+
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| input[a].total_cmp(&input[b]));
+
+    let mut keep = vec![true; n];
+    for &idx in order[..lower].iter().chain(&order[n - upper..]) {
+        keep[idx] = false;
+    }
+    keep
+}
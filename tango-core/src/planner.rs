@@ -0,0 +1,25 @@
+//! Rounding of the number of samples planned before sampling
+//!
+//! The plan itself is made by `tango-bench` from the estimates of the functions and the measurement settings.
+
+/// Number of samples fitting in a given time budget (in nanoseconds)
+///
+/// `pair_ns` is the estimated time of a single iteration of both functions and `iterations` is the mean number of
+/// iterations per sample. Always even and at least 2, so each function is measured first the same number of times.
+pub fn even_samples(budget_ns: f64, pair_ns: f64, iterations: f64) -> usize {
+    let samples = (budget_ns / (pair_ns * iterations)) as usize;
+    samples.div_ceil(2).max(1) * 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_even_samples() {
+        assert_eq!(even_samples(1e6, 1000., 10.), 100);
+        assert_eq!(even_samples(0.99e6, 1000., 10.), 100);
+        assert_eq!(even_samples(1.01e6, 1000., 10.), 102);
+        assert_eq!(even_samples(0., 1000., 10.), 2);
+    }
+}
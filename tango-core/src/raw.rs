@@ -0,0 +1,249 @@
+//! Raw samples measured by an external runner
+//!
+//! A runner measuring the functions outside of the harness (eg. on a device without an operating system) writes the
+//! samples of each function to a separate file, and the host analyzes both files as a paired test
+//! (`analyze --raw baseline.bin candidate.bin`). Samples of both files are paired by their position, so the runner
+//! should take the i-th samples of both functions one after another, alternating the function called first the same
+//! way the harness does.
+//!
+//! A file is a header followed by the samples. All the integers are little-endian:
+//!
+//! | Offset  | Size    | Field                                                                       |
+//! |---------|---------|-----------------------------------------------------------------------------|
+//! | 0       | 8       | magic `TANGORAW`                                                            |
+//! | 8       | 2       | version of the format ([`VERSION`])                                         |
+//! | 10      | 1       | function called first in the first pair of samples: 0 – baseline, 1 – candidate |
+//! | 11      | 1       | reserved, 0                                                                 |
+//! | 12      | 4       | iterations per sample                                                       |
+//! | 16      | 2       | length of the name of the function in bytes (`L`)                           |
+//! | 18      | `L`     | name of the function (UTF-8)                                                |
+//! | 18 + `L`| 8 × `N` | cumulative value of all the iterations of each sample (eg. cycles)          |
+//!
+//! Writing a file doesn't require an allocator: [`RawHeader::encode()`] writes the header to a buffer and the
+//! samples are written with [`u64::to_le_bytes()`].
+
+use core::{fmt, str};
+
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+/// First bytes of a file with raw samples
+pub const MAGIC: [u8; 8] = *b"TANGORAW";
+
+/// Version of the format written by this crate
+pub const VERSION: u16 = 1;
+
+/// Size of the header without the name of the function
+pub const HEADER_LEN: usize = 18;
+
+/// Header of a file with raw samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawHeader<'a> {
+    /// Name of the measured function
+    pub name: &'a str,
+
+    /// Number of iterations each sample is taken over
+    pub iterations: u32,
+
+    /// Candidate was called first in the first pair of samples (the order alternates in the following pairs)
+    pub candidate_first: bool,
+}
+
+impl RawHeader<'_> {
+    /// Size of the encoded header
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.name.len()
+    }
+
+    /// Writes the header to the beginning of a given buffer returning the number of bytes written
+    ///
+    /// Returns `None` if the buffer is too small or the name is longer than [`u16::MAX`] bytes.
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let name_len = u16::try_from(self.name.len()).ok()?;
+        let len = self.encoded_len();
+        let buf = buf.get_mut(..len)?;
+        buf[0..8].copy_from_slice(&MAGIC);
+        buf[8..10].copy_from_slice(&VERSION.to_le_bytes());
+        buf[10] = u8::from(self.candidate_first);
+        buf[11] = 0;
+        buf[12..16].copy_from_slice(&self.iterations.to_le_bytes());
+        buf[16..18].copy_from_slice(&name_len.to_le_bytes());
+        buf[HEADER_LEN..].copy_from_slice(self.name.as_bytes());
+        Some(len)
+    }
+}
+
+/// File with raw samples parsed without copying
+#[derive(Clone, Copy, Debug)]
+pub struct RawFile<'a> {
+    pub header: RawHeader<'a>,
+    samples: &'a [u8],
+}
+
+impl<'a> RawFile<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, RawError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(RawError::Truncated)?;
+        if header[0..8] != MAGIC {
+            return Err(RawError::Magic);
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != VERSION {
+            return Err(RawError::Version(version));
+        }
+        let candidate_first = match header[10] {
+            0 => false,
+            1 => true,
+            order => return Err(RawError::Order(order)),
+        };
+        let iterations = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+        if iterations == 0 {
+            return Err(RawError::ZeroIterations);
+        }
+        let name_len = u16::from_le_bytes([header[16], header[17]]) as usize;
+        let name = bytes
+            .get(HEADER_LEN..HEADER_LEN + name_len)
+            .ok_or(RawError::Truncated)?;
+        let name = str::from_utf8(name).map_err(|_| RawError::Name)?;
+        let samples = &bytes[HEADER_LEN + name_len..];
+        if !samples.len().is_multiple_of(8) {
+            return Err(RawError::Truncated);
+        }
+        Ok(Self {
+            header: RawHeader {
+                name,
+                iterations,
+                candidate_first,
+            },
+            samples,
+        })
+    }
+
+    /// Cumulative values of the samples in the order they were taken
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = u64> + 'a {
+        self.samples
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+    }
+}
+
+/// Encodes a header and the samples into a new buffer
+///
+/// # Panics
+///
+/// Panics if the name is longer than [`u16::MAX`] bytes.
+#[cfg(feature = "alloc")]
+pub fn encode(header: &RawHeader, samples: &[u64]) -> Vec<u8> {
+    let mut bytes = vec![0; header.encoded_len()];
+    header.encode(&mut bytes).expect("Name is too long");
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
+
+/// Reason a file with raw samples can't be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawError {
+    /// File doesn't start with [`MAGIC`]
+    Magic,
+
+    /// File is written in an unsupported version of the format
+    Version(u16),
+
+    /// Invalid function called first
+    Order(u8),
+
+    ZeroIterations,
+
+    /// Name of the function is not valid UTF-8
+    Name,
+
+    /// File ends in the middle of the header or a sample
+    Truncated,
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawError::Magic => write!(f, "not a file with raw samples"),
+            RawError::Version(version) => {
+                write!(f, "unsupported version of raw samples: {}", version)
+            }
+            RawError::Order(order) => write!(f, "invalid order of the first sample: {}", order),
+            RawError::ZeroIterations => write!(f, "zero iterations per sample"),
+            RawError::Name => write!(f, "name of the function is not valid UTF-8"),
+            RawError::Truncated => write!(f, "file is truncated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RawError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: RawHeader = RawHeader {
+        name: "crc32",
+        iterations: 100,
+        candidate_first: true,
+    };
+
+    #[test]
+    fn check_encoded_without_allocation() {
+        let mut buf = [0u8; 64];
+        let len = HEADER.encode(&mut buf).unwrap();
+        assert_eq!(len, HEADER_LEN + 5);
+        for (i, sample) in [10_u64, 20].iter().enumerate() {
+            buf[len + i * 8..len + (i + 1) * 8].copy_from_slice(&sample.to_le_bytes());
+        }
+
+        let file = RawFile::parse(&buf[..len + 16]).unwrap();
+        assert_eq!(file.header, HEADER);
+        assert!(file.samples().eq([10, 20]));
+        assert_eq!(HEADER.encode(&mut buf[..len - 1]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn check_roundtrip() {
+        let bytes = encode(&HEADER, &[1, u64::MAX, 3]);
+        let file = RawFile::parse(&bytes).unwrap();
+        assert_eq!(file.header, HEADER);
+        assert_eq!(file.samples().len(), 3);
+        assert!(file.samples().eq([1, u64::MAX, 3]));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn check_invalid_files() {
+        let bytes = encode(&HEADER, &[1]);
+        assert_eq!(
+            RawFile::parse(&bytes[..10]).err(),
+            Some(RawError::Truncated)
+        );
+        assert_eq!(
+            RawFile::parse(&bytes[..bytes.len() - 1]).err(),
+            Some(RawError::Truncated)
+        );
+
+        let mut invalid = bytes.clone();
+        invalid[0] = b'X';
+        assert_eq!(RawFile::parse(&invalid).err(), Some(RawError::Magic));
+
+        let mut invalid = bytes.clone();
+        invalid[8] = 2;
+        assert_eq!(RawFile::parse(&invalid).err(), Some(RawError::Version(2)));
+
+        let header = RawHeader {
+            iterations: 0,
+            ..HEADER
+        };
+        let invalid = encode(&header, &[]);
+        assert_eq!(
+            RawFile::parse(&invalid).err(),
+            Some(RawError::ZeroIterations)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Statistics of a series of values computed in constant memory
+
+use crate::{RunningSummary, Summary};
+use libm::ceil;
+
+/// Nearest-rank quantile `q` of the sorted values (`None` if there are no values)
+pub fn nearest_rank<T: Copy>(sorted: &[T], q: f64) -> Option<T> {
+    let rank = ceil(sorted.len() as f64 * q) as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+/// Streaming estimation of a quantile using P² algorithm
+///
+/// Keeps 5 markers (the minimum, the maximum, the quantile and two intermediate quantiles) whose heights are adjusted
+/// with a piecewise-parabolic prediction as values arrive, so the memory used doesn't depend on the number of values.
+/// See _Jain R., Chlamtac I. The P² algorithm for dynamic calculation of quantiles and histograms without storing
+/// observations_ (1985).
+#[derive(Clone, Debug)]
+pub struct StreamingQuantile {
+    p: f64,
+    count: usize,
+
+    /// Marker heights
+    heights: [f64; 5],
+
+    /// Actual marker positions (1-based)
+    positions: [f64; 5],
+
+    /// Desired marker positions
+    desired: [f64; 5],
+
+    /// Increments of the desired marker positions per value
+    increments: [f64; 5],
+}
+
+impl StreamingQuantile {
+    /// Creates estimator of a given quantile (`0 < q < 1`)
+    pub fn new(q: f64) -> Self {
+        assert!(q > 0. && q < 1., "quantile should be in (0, 1)");
+        Self {
+            p: q,
+            count: 0,
+            heights: [0.; 5],
+            positions: [1., 2., 3., 4., 5.],
+            desired: [1., 1. + 2. * q, 1. + 4. * q, 3. + 2. * q, 5.],
+            increments: [0., q / 2., q, (1. + q) / 2., 1.],
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_unstable_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let (q, n) = (&mut self.heights, &mut self.positions);
+        // Cell the value falls into (extreme markers are moved if the value is outside of the range)
+        let k = if value < q[0] {
+            q[0] = value;
+            0
+        } else if value >= q[4] {
+            q[4] = value;
+            3
+        } else {
+            (1..5).find(|&i| value < q[i]).unwrap_or(4) - 1
+        };
+        for position in &mut n[k + 1..] {
+            *position += 1.;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - n[i];
+            if (d >= 1. && n[i + 1] - n[i] > 1.) || (d <= -1. && n[i - 1] - n[i] < -1.) {
+                let d = d.signum();
+                let parabolic = q[i]
+                    + d / (n[i + 1] - n[i - 1])
+                        * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                            + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    let j = if d > 0. { i + 1 } else { i - 1 };
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    /// Current estimate of the quantile (`None` if no values were added)
+    ///
+    /// The estimate is exact while there are less than 5 values (see [`nearest_rank()`]).
+    pub fn quantile(&self) -> Option<f64> {
+        if self.count < 5 {
+            let mut values = self.heights;
+            let values = &mut values[..self.count];
+            values.sort_unstable_by(f64::total_cmp);
+            nearest_rank(values, self.p)
+        } else {
+            Some(self.heights[2])
+        }
+    }
+}
+
+/// Summary and p50/p95/p99 percentiles of a series of values computed in constant memory
+#[derive(Clone, Debug)]
+pub struct StreamingStats {
+    summary: RunningSummary,
+    p50: StreamingQuantile,
+    p95: StreamingQuantile,
+    p99: StreamingQuantile,
+}
+
+impl Default for StreamingStats {
+    fn default() -> Self {
+        Self {
+            summary: RunningSummary::default(),
+            p50: StreamingQuantile::new(0.5),
+            p95: StreamingQuantile::new(0.95),
+            p99: StreamingQuantile::new(0.99),
+        }
+    }
+}
+
+impl StreamingStats {
+    pub fn add(&mut self, value: f64) {
+        self.summary.add(value);
+        self.p50.add(value);
+        self.p95.add(value);
+        self.p99.add(value);
+    }
+
+    /// Summary of all the values added (`None` if there are no values)
+    pub fn summary(&self) -> Option<Summary<f64>> {
+        self.summary.summary()
+    }
+
+    /// Estimates of p50, p95 and p99 percentiles (see [`StreamingQuantile`])
+    pub fn percentiles(&self) -> Option<[f64; 3]> {
+        Some([
+            self.p50.quantile()?,
+            self.p95.quantile()?,
+            self.p99.quantile()?,
+        ])
+    }
+}
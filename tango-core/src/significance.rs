@@ -0,0 +1,262 @@
+//! Decision whether the difference between two functions is meaningful
+//!
+//! Differences are tested with z-test of the mean of paired differences and equivalence is established with two
+//! one-sided tests (see [`verdict()`]). Student's t-distribution ([`t_p_value()`], [`critical_t_score()`]) accounts
+//! for the uncertainty of the variance estimated from a small number of samples.
+
+use crate::Summary;
+use core::f64::consts::SQRT_2;
+use libm::{exp, fabs, lgamma, log, sqrt};
+use serde::{Deserialize, Serialize};
+
+/// z-score corresponding to 99% significance level
+pub const Z_SCORE_99: f64 = 2.6;
+
+/// Confidence interval of the relative difference between candidate and baseline (in percents)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ConfidenceInterval {
+    /// Returns `true` if the whole interval lies within `[-bound, bound]`
+    pub fn is_within(&self, bound: f64) -> bool {
+        -bound < self.lower && self.upper < bound
+    }
+
+    /// Half of the width of the interval (precision of the estimate)
+    pub fn half_width(&self) -> f64 {
+        (self.upper - self.lower) / 2.
+    }
+}
+
+/// Classification of the difference between candidate and baseline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// With high confidence the difference is smaller than the minimal effect of interest
+    Equivalent,
+
+    /// Data is not sufficient to conclude neither the difference nor the equivalence
+    Inconclusive,
+
+    /// Difference is statistically significant and larger than the minimal effect of interest
+    Different,
+}
+
+/// Confidence interval for the relative difference at a given z-score
+///
+/// `diff` is a summary of paired differences (candidate - baseline), the interval is expressed in percents of
+/// the baseline mean.
+pub fn confidence_interval(
+    baseline: &Summary<f64>,
+    diff: &Summary<f64>,
+    z_score: f64,
+) -> ConfidenceInterval {
+    interval(baseline.mean, diff, z_score)
+}
+
+fn interval(baseline_mean: f64, diff: &Summary<f64>, z_score: f64) -> ConfidenceInterval {
+    let std_err = sqrt(diff.variance) / sqrt(diff.n as f64);
+    let lower = (diff.mean - z_score * std_err) / baseline_mean * 100.;
+    let upper = (diff.mean + z_score * std_err) / baseline_mean * 100.;
+    ConfidenceInterval {
+        lower: lower.min(upper),
+        upper: lower.max(upper),
+    }
+}
+
+/// Classifies the difference between candidate and baseline
+///
+/// The difference is significant if it is far away from 0 (z-test at 99% significance level) and larger than
+/// `min_effect` (in percents). Equivalence is established using two one-sided tests (TOST): if the
+/// confidence interval lies entirely within `±min_effect` both one-sided hypotheses of the difference
+/// exceeding the minimal effect are rejected. All other cases are inconclusive.
+pub fn verdict(
+    baseline: &Summary<f64>,
+    diff: &Summary<f64>,
+    min_effect: f64,
+) -> (Verdict, ConfidenceInterval) {
+    decide(baseline.mean, diff, min_effect, Z_SCORE_99)
+}
+
+/// Same as [`verdict()`], but against a given baseline mean and at a given critical z-score (see
+/// [`critical_z_score()`])
+pub fn decide(
+    baseline_mean: f64,
+    diff: &Summary<f64>,
+    min_effect: f64,
+    critical_z: f64,
+) -> (Verdict, ConfidenceInterval) {
+    let z_score = z_score(diff);
+    let effect = (diff.mean / baseline_mean * 100.).abs();
+    let ci = interval(baseline_mean, diff, critical_z);
+
+    let verdict = if z_score.abs() >= critical_z && effect > min_effect {
+        Verdict::Different
+    } else if ci.is_within(min_effect) {
+        Verdict::Equivalent
+    } else {
+        Verdict::Inconclusive
+    };
+    (verdict, ci)
+}
+
+/// z-score of the mean of paired differences
+pub fn z_score(diff: &Summary<f64>) -> f64 {
+    let std_err = sqrt(diff.variance) / sqrt(diff.n as f64);
+    diff.mean / std_err
+}
+
+/// Two-sided p-value of a given z-score
+pub fn p_value(z_score: f64) -> f64 {
+    erfc(z_score.abs() / SQRT_2)
+}
+
+/// z-score a two-sided z-test rejects the hypothesis of no difference at a given significance level (eg. `0.01`)
+///
+/// Found by bisection of [`p_value()`], so it is as precise as the approximation of the latter.
+pub fn critical_z_score(significance: f64) -> f64 {
+    let significance = significance.clamp(1e-9, 1.);
+    let (mut lower, mut upper) = (0_f64, 10_f64);
+    for _ in 0..60 {
+        let z = (lower + upper) / 2.;
+        if erfc(z / SQRT_2) > significance {
+            lower = z;
+        } else {
+            upper = z;
+        }
+    }
+    (lower + upper) / 2.
+}
+
+/// Two-sided p-value of a given t-score with `df` degrees of freedom
+pub fn t_p_value(t_score: f64, df: f64) -> f64 {
+    if t_score.is_nan() {
+        return 1.;
+    }
+    if t_score.is_infinite() {
+        return 0.;
+    }
+    beta_inc(df / 2., 0.5, df / (df + t_score * t_score))
+}
+
+/// t-score a two-sided t-test with `df` degrees of freedom rejects the hypothesis of no difference at a given
+/// significance level (eg. `0.01`)
+///
+/// Found by bisection of [`t_p_value()`] the same way [`critical_z_score()`] is. Approaches the latter as `df` grows.
+pub fn critical_t_score(significance: f64, df: f64) -> f64 {
+    let significance = significance.clamp(1e-9, 1.);
+    let (mut lower, mut upper) = (0_f64, 1e3_f64);
+    for _ in 0..80 {
+        let t = (lower + upper) / 2.;
+        if t_p_value(t, df) > significance {
+            lower = t;
+        } else {
+            upper = t;
+        }
+    }
+    (lower + upper) / 2.
+}
+
+/// Regularized incomplete beta function I<sub>x</sub>(a, b) (continued fraction, Numerical Recipes 6.4)
+fn beta_inc(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    let front = exp(lgamma(a + b) - lgamma(a) - lgamma(b) + a * log(x) + b * log(1. - x));
+    // Continued fraction converges quickly only below this point, the symmetry relation is used above it
+    if x < (a + 1.) / (a + b + 2.) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1. - front * beta_fraction(b, a, 1. - x) / b
+    }
+}
+
+/// Continued fraction of the incomplete beta function evaluated with the modified Lentz's method
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    const EPSILON: f64 = 1e-14;
+    let clamp = |v: f64| if fabs(v) < TINY { TINY } else { v };
+
+    let mut c = 1.;
+    let mut d = 1. / clamp(1. - (a + b) * x / (a + 1.));
+    let mut fraction = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2. * m - 1.) * (a + 2. * m));
+        d = 1. / clamp(1. + even * d);
+        c = clamp(1. + even / c);
+        fraction *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2. * m) * (a + 2. * m + 1.));
+        d = 1. / clamp(1. + odd * d);
+        c = clamp(1. + odd / c);
+        let delta = d * c;
+        fraction *= delta;
+        if fabs(delta - 1.) < EPSILON {
+            break;
+        }
+    }
+    fraction
+}
+
+/// Complementary error function (Abramowitz and Stegun 7.1.26, absolute error is less than 1.5e-7)
+fn erfc(x: f64) -> f64 {
+    const P: f64 = 0.3275911;
+    const A: [f64; 5] = [
+        0.254829592,
+        -0.284496736,
+        1.421413741,
+        -1.453152027,
+        1.061405429,
+    ];
+
+    let t = 1. / (1. + P * x.abs());
+    let poly = A.iter().rev().fold(0., |acc, a| acc * t + a) * t;
+    let erfc = poly * exp(-x * x);
+    if x >= 0. {
+        erfc
+    } else {
+        2. - erfc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_erfc() {
+        assert!((erfc(0.) - 1.).abs() < 1e-6);
+        assert!((erfc(1.) - 0.157299).abs() < 1e-6);
+        assert!((erfc(-1.) - 1.842701).abs() < 1e-6);
+        // z = 2.576 corresponds to two-sided p-value of 0.01
+        assert!((erfc(2.576 / SQRT_2) - 0.01).abs() < 1e-4);
+        assert!((p_value(-2.576) - 0.01).abs() < 1e-4);
+    }
+
+    #[test]
+    fn check_t_distribution() {
+        // Reference values of the two-sided critical t-scores
+        let cases = [
+            (1., 0.05, 12.706),
+            (5., 0.01, 4.032),
+            (19., 0.01, 2.861),
+            (30., 0.05, 2.042),
+        ];
+        for (df, significance, expected) in cases {
+            let t = critical_t_score(significance, df);
+            assert!((t - expected).abs() < 1e-3, "df = {}: {}", df, t);
+        }
+        assert!((t_p_value(2.861, 19.) - 0.01).abs() < 1e-4);
+        assert!((t_p_value(-1., 1.) - 0.5).abs() < 1e-9);
+        assert_eq!(t_p_value(0., 10.), 1.);
+        assert_eq!(t_p_value(f64::INFINITY, 10.), 0.);
+        // Large number of degrees of freedom is the normal distribution
+        assert!((critical_t_score(0.01, 1e6) - 2.576).abs() < 1e-3);
+    }
+}
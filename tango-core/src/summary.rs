@@ -0,0 +1,132 @@
+//! Single pass statistical summaries
+
+use core::cmp::Ordering;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// Statistical summary for a given iterator of numbers.
+///
+/// Calculates all the information using single pass over the data. Mean and variance are calculated using
+/// streaming algorithm described in _Art of Computer Programming, Vol 2, page 232_.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summary<T> {
+    pub n: usize,
+    pub min: T,
+    pub max: T,
+    pub mean: f64,
+    pub variance: f64,
+}
+
+impl<T: PartialOrd + Copy> Summary<T> {
+    /// Summary of the values of both summaries
+    pub fn pooled(&self, other: &Self) -> Self {
+        let n = self.n + other.n;
+        let (n1, n2) = (self.n as f64, other.n as f64);
+        let delta = other.mean - self.mean;
+        let mean = self.mean + delta * n2 / n as f64;
+        let squares = self.variance * (n1 - 1.).max(0.)
+            + other.variance * (n2 - 1.).max(0.)
+            + delta * delta * n1 * n2 / n as f64;
+        let variance = if n > 1 { squares / (n - 1) as f64 } else { 0. };
+        let min = if other.min < self.min {
+            other.min
+        } else {
+            self.min
+        };
+        let max = if other.max > self.max {
+            other.max
+        } else {
+            self.max
+        };
+        Self {
+            n,
+            min,
+            max,
+            mean,
+            variance,
+        }
+    }
+}
+
+impl<T: PartialOrd> Summary<T> {
+    pub fn from<'a, C>(values: C) -> Option<Self>
+    where
+        C: IntoIterator<Item = &'a T>,
+        T: ToPrimitive + Copy + 'a,
+    {
+        Self::running(values.into_iter().copied()).last()
+    }
+
+    pub fn running<I>(iter: I) -> impl Iterator<Item = Summary<T>>
+    where
+        T: ToPrimitive + Copy,
+        I: Iterator<Item = T>,
+    {
+        iter.scan(RunningSummary::default(), |summary, value| {
+            Some(summary.add(value))
+        })
+    }
+}
+
+/// Summary accumulated one value at a time (see [`Summary::running()`])
+#[derive(Clone, Copy, Debug)]
+pub struct RunningSummary<T = f64> {
+    n: usize,
+
+    /// Minimum and maximum of the values (`None` until the first value is added)
+    bounds: Option<(T, T)>,
+    mean: f64,
+    s: f64,
+}
+
+impl<T> Default for RunningSummary<T> {
+    fn default() -> Self {
+        Self {
+            n: 0,
+            bounds: None,
+            mean: 0.,
+            s: 0.,
+        }
+    }
+}
+
+impl<T> RunningSummary<T>
+where
+    T: Copy + PartialOrd + ToPrimitive,
+{
+    /// Adds a value returning the summary of all the values added so far
+    pub fn add(&mut self, value: T) -> Summary<T> {
+        let fvalue = value.to_f64().expect("f64 overflow detected");
+
+        let (min, max) = self.bounds.get_or_insert((value, value));
+        if let Some(Ordering::Less) = value.partial_cmp(min) {
+            *min = value;
+        }
+        if let Some(Ordering::Greater) = value.partial_cmp(max) {
+            *max = value;
+        }
+
+        self.n += 1;
+        let mean_p = self.mean;
+        self.mean += (fvalue - self.mean) / self.n as f64;
+        self.s += (fvalue - mean_p) * (fvalue - self.mean);
+        self.summary().expect("At least one value is added")
+    }
+
+    /// Summary of all the values added so far (`None` if there are no values)
+    pub fn summary(&self) -> Option<Summary<T>> {
+        let (min, max) = self.bounds?;
+        let variance = if self.n > 1 {
+            self.s / (self.n - 1) as f64
+        } else {
+            0.
+        };
+        Some(Summary {
+            n: self.n,
+            min,
+            max,
+            mean: self.mean,
+            variance,
+        })
+    }
+}
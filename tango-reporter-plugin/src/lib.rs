@@ -0,0 +1,212 @@
+//! Interface of reporters loaded by Tango harness at runtime (`--reporter-plugin <path>`)
+//!
+//! Plugin is a dynamic library (`crate-type = ["cdylib"]`) exporting a small C ABI. The harness calls
+//! [`ReporterPlugin::init()`] with the session information before the first test, [`ReporterPlugin::on_result()`]
+//! for each reported result and [`ReporterPlugin::finish()`] after the last one. Session information and results
+//! are passed as JSON, so plugins are free to parse only the fields they need.
+//!
+//! ## JSON schema
+//! The layout of the JSON is a part of the ABI: fields may be added within an [`ABI_VERSION`], but never renamed or
+//! removed. Session is an object with the following fields:
+//! - `mode` – `"compare"` or `"pairs"`;
+//! - `baseline` – path to the baseline executable (`null` in `pairs` mode);
+//! - `filter` – filter the tests are selected with (empty if all the tests are run);
+//! - `master_seed` – seed all the test seeds are derived from;
+//! - `environment` – CPU, OS and toolchain the benchmarks are run in;
+//! - `tests` – names of the tests planned to run in the order of execution;
+//! - `options` – object of `--reporter-plugin-option KEY=VALUE` options given to the harness.
+//!
+//! Each result has the same schema as the elements of the `--json` file of the harness (`name`, `unit`,
+//! `baseline`, `candidate`, `diff`, `significance`, `outliers`, etc.).
+//!
+//! ## Example
+//! ```rust
+//! use tango_reporter_plugin::{export_reporter, ReporterPlugin};
+//!
+//! struct Counter(usize);
+//!
+//! impl ReporterPlugin for Counter {
+//!     fn init(_session: &str) -> Option<Self> {
+//!         Some(Counter(0))
+//!     }
+//!
+//!     fn on_result(&mut self, _result: &str) {
+//!         self.0 += 1;
+//!     }
+//!
+//!     fn finish(self) {
+//!         eprintln!("{} results reported", self.0);
+//!     }
+//! }
+//!
+//! export_reporter!(Counter);
+//! ```
+
+use std::ffi::c_void;
+
+/// Version of the plugin ABI. Harness refuses to load plugins built for a different version
+pub const ABI_VERSION: u32 = 2;
+
+/// Names of the symbols exported by [`export_reporter!()`]
+pub mod symbols {
+    pub const ABI_VERSION: &[u8] = b"tango_reporter_abi_version\0";
+    pub const INIT: &[u8] = b"tango_reporter_init\0";
+    pub const ON_RESULT: &[u8] = b"tango_reporter_on_result\0";
+    pub const FINISH: &[u8] = b"tango_reporter_finish\0";
+}
+
+/// Signature types of all plugin functions
+///
+/// JSON is passed as a pointer to UTF-8 bytes and the length of it. The state returned by [`InitFn`] (null if
+/// initialization failed) is given to all other functions and released by [`FinishFn`].
+pub type AbiVersionFn = unsafe extern "C" fn() -> u32;
+pub type InitFn = unsafe extern "C" fn(*const u8, usize) -> *mut c_void;
+pub type OnResultFn = unsafe extern "C" fn(*mut c_void, *const u8, usize);
+pub type FinishFn = unsafe extern "C" fn(*mut c_void);
+
+/// Reporter implemented by a plugin
+///
+/// Panics are caught at the ABI boundary. A panic in [`Self::init()`] disables the plugin, panics in other
+/// methods are ignored.
+pub trait ReporterPlugin: Sized {
+    /// Creates the reporter from the JSON describing the session (seed, environment, planned tests, options)
+    ///
+    /// Returning `None` disables the plugin for the session.
+    fn init(session: &str) -> Option<Self>;
+
+    /// Called with the JSON of each reported result
+    fn on_result(&mut self, result: &str);
+
+    /// Called after the last result
+    fn finish(self) {}
+}
+
+/// Exports the C ABI of a given [`ReporterPlugin`] implementation
+///
+/// Should be invoked exactly once in a `cdylib` crate.
+#[macro_export]
+macro_rules! export_reporter {
+    ($reporter:ty) => {
+        #[no_mangle]
+        pub extern "C" fn tango_reporter_abi_version() -> u32 {
+            $crate::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn tango_reporter_init(
+            json: *const u8,
+            length: usize,
+        ) -> *mut ::std::ffi::c_void {
+            $crate::__private::init::<$reporter>(json, length)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn tango_reporter_on_result(
+            state: *mut ::std::ffi::c_void,
+            json: *const u8,
+            length: usize,
+        ) {
+            $crate::__private::on_result::<$reporter>(state, json, length)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn tango_reporter_finish(state: *mut ::std::ffi::c_void) {
+            $crate::__private::finish::<$reporter>(state)
+        }
+
+        /// Checks that all exported functions are of valid type according to the ABI
+        #[allow(unused)]
+        const _: () = {
+            let _: $crate::AbiVersionFn = tango_reporter_abi_version;
+            let _: $crate::InitFn = tango_reporter_init;
+            let _: $crate::OnResultFn = tango_reporter_on_result;
+            let _: $crate::FinishFn = tango_reporter_finish;
+        };
+    };
+}
+
+/// Implementation of the functions exported by [`export_reporter!()`]
+#[doc(hidden)]
+pub mod __private {
+    use super::ReporterPlugin;
+    use std::{ffi::c_void, panic, ptr::null_mut, slice, str};
+
+    unsafe fn as_str<'a>(json: *const u8, length: usize) -> Option<&'a str> {
+        if json.is_null() {
+            return None;
+        }
+        str::from_utf8(slice::from_raw_parts(json, length)).ok()
+    }
+
+    pub unsafe fn init<R: ReporterPlugin>(json: *const u8, length: usize) -> *mut c_void {
+        let Some(session) = as_str(json, length) else {
+            return null_mut();
+        };
+        match panic::catch_unwind(|| R::init(session)) {
+            Ok(Some(reporter)) => Box::into_raw(Box::new(reporter)) as *mut c_void,
+            _ => null_mut(),
+        }
+    }
+
+    pub unsafe fn on_result<R: ReporterPlugin>(state: *mut c_void, json: *const u8, length: usize) {
+        let (Some(reporter), Some(result)) = ((state as *mut R).as_mut(), as_str(json, length))
+        else {
+            return;
+        };
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| reporter.on_result(result)));
+    }
+
+    pub unsafe fn finish<R: ReporterPlugin>(state: *mut c_void) {
+        if state.is_null() {
+            return;
+        }
+        let reporter = Box::from_raw(state as *mut R);
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(move || reporter.finish()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::null;
+
+    struct Recorder(Vec<String>);
+
+    impl ReporterPlugin for Recorder {
+        fn init(session: &str) -> Option<Self> {
+            (session != "null").then(|| Recorder(vec![]))
+        }
+
+        fn on_result(&mut self, result: &str) {
+            if result == "panic" {
+                panic!("reporter panicked");
+            }
+            self.0.push(result.to_string());
+        }
+    }
+
+    #[test]
+    fn check_plugin_lifecycle() {
+        let call = |state, json: &str| unsafe {
+            __private::on_result::<Recorder>(state, json.as_ptr(), json.len())
+        };
+        unsafe {
+            let state = __private::init::<Recorder>(b"{}".as_ptr(), 2);
+            assert!(!state.is_null());
+            call(state, "{\"name\":\"a\"}");
+            call(state, "panic");
+            call(state, "{\"name\":\"b\"}");
+            assert_eq!(
+                (*(state as *mut Recorder)).0,
+                ["{\"name\":\"a\"}", "{\"name\":\"b\"}"]
+            );
+            __private::finish::<Recorder>(state);
+
+            assert!(__private::init::<Recorder>(b"null".as_ptr(), 4).is_null());
+            assert!(__private::init::<Recorder>(null(), 0).is_null());
+            // Calls with the state of a disabled plugin are ignored
+            call(std::ptr::null_mut(), "{}");
+            __private::finish::<Recorder>(std::ptr::null_mut());
+        }
+    }
+}